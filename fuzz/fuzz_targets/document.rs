@@ -0,0 +1,10 @@
+//! Opens the input as a `.procreate` file without a GPU, decoding its
+//! `Document.archive` and every tile into memory.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use silica::ProcreateFile;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ProcreateFile::open_in_memory(data);
+});
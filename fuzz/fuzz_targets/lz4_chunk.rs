@@ -0,0 +1,18 @@
+//! Decodes arbitrary bytes as the LZ4 chunk of a tile, through the silica
+//! loader of a document holding nothing else.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use silica::fixture::{Document, Layer};
+use silica::ProcreateFile;
+
+fuzz_target!(|data: &[u8]| {
+    let mut document = Document::new(256, 256).layer(Layer {
+        fill: None,
+        ..Layer::new("layer", [0; 4])
+    });
+    document
+        .files
+        .push((String::from("layer/0~0.lz4"), data.to_vec()));
+    let _ = ProcreateFile::open_in_memory(&document.bytes());
+});
@@ -0,0 +1,34 @@
+//! Feeds arbitrary bytes to the keyed archive decoder and walks the keys
+//! that `ProcreateFile` reads from the document root.
+#![no_main]
+
+#[allow(dead_code)]
+#[path = "../../libs/silica/src/ns_archive.rs"]
+mod ns_archive;
+
+use libfuzzer_sys::fuzz_target;
+use ns_archive::{NsKeyedArchive, Size, WrappedArray};
+use plist::{Dictionary, Value};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(nka) = NsKeyedArchive::from_reader(Cursor::new(data)) else {
+        return;
+    };
+    let Ok(root) = nka.root() else {
+        return;
+    };
+
+    let _ = nka.fetch::<Size<u32>>(root, "size");
+    let _ = nka.fetch::<u32>(root, "tileSize");
+    let _ = nka.fetch::<u32>(root, "orientation");
+    let _ = nka.fetch::<bool>(root, "flippedHorizontally");
+    let _ = nka.fetch::<bool>(root, "flippedVertically");
+    let _ = nka.fetch::<bool>(root, "backgroundHidden");
+    let _ = nka.fetch::<usize>(root, "strokeCount");
+    let _ = nka.fetch::<Option<String>>(root, "name");
+    let _ = nka.fetch::<Option<String>>(root, "authorName");
+    let _ = nka.fetch::<&[u8]>(root, "backgroundColor");
+    let _ = nka.fetch::<&Dictionary>(root, "composite");
+    let _ = nka.fetch::<WrappedArray<&Value>>(root, "unwrappedLayers");
+});
@@ -0,0 +1,70 @@
+/// Color adjustments applied over the blended layers of a composite, to
+/// preview changes to the artwork without altering the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustments {
+    pub enabled: bool,
+    /// Offset (-1.0..=1.0) added to every channel.
+    pub brightness: f32,
+    /// Contrast (-1.0..=1.0) around middle gray, -1.0 leaving flat gray.
+    pub contrast: f32,
+    /// Rotation of the hue in degrees (-180.0..=180.0).
+    pub hue: f32,
+    /// Saturation (-1.0..=1.0), -1.0 removing all color.
+    pub saturation: f32,
+    pub grayscale: bool,
+    pub invert: bool,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            brightness: 0.0,
+            contrast: 0.0,
+            hue: 0.0,
+            saturation: 0.0,
+            grayscale: false,
+            invert: false,
+        }
+    }
+}
+
+impl Adjustments {
+    /// Whether the adjustments change any colors.
+    pub fn is_active(&self) -> bool {
+        self.enabled
+            && *self
+                != Self {
+                    enabled: true,
+                    ..Default::default()
+                }
+    }
+
+    pub(super) fn uniform(&self) -> AdjustUniform {
+        AdjustUniform {
+            enabled: u32::from(self.is_active()),
+            brightness: self.brightness,
+            contrast: self.contrast,
+            hue: self.hue.to_radians(),
+            saturation: self.saturation,
+            grayscale: u32::from(self.grayscale),
+            invert: u32::from(self.invert),
+            _padding: 0,
+        }
+    }
+}
+
+/// Adjustments as laid out in the fragment shader's uniform buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(super) struct AdjustUniform {
+    enabled: u32,
+    brightness: f32,
+    contrast: f32,
+    /// Rotation of the hue in radians.
+    hue: f32,
+    saturation: f32,
+    grayscale: u32,
+    invert: u32,
+    _padding: u32,
+}
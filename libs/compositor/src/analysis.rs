@@ -0,0 +1,176 @@
+use super::color;
+use image::RgbaImage;
+use rayon::prelude::*;
+
+/// Distribution of the colors of a composite.
+#[derive(Debug, Clone)]
+pub struct ColorStats {
+    /// Pixel counts of each value of the straight red, green, blue and
+    /// alpha channels. Fully transparent pixels only count towards alpha.
+    pub histogram: [[u32; 256]; 4],
+    /// Average straight color of the visible pixels, weighted by alpha.
+    pub average: Option<[u8; 3]>,
+    /// Most common color, averaged over the colors close to it.
+    pub dominant: Option<[u8; 3]>,
+    /// Fraction of the pixels that are not fully transparent.
+    pub coverage: f32,
+}
+
+/// Accumulated over a part of the image, then merged.
+struct Partial {
+    histogram: Box<[[u32; 256]; 4]>,
+    /// Sums of the premultiplied channels and of alpha.
+    sum: [u64; 4],
+    /// Alpha weighted sums of the straight colors falling in each bucket,
+    /// with the total alpha last.
+    buckets: Vec<[u64; 4]>,
+}
+
+impl Partial {
+    /// Colors are grouped by the top bits of each channel to find the
+    /// dominant one.
+    const BUCKET_BITS: u32 = 4;
+
+    fn new() -> Self {
+        Self {
+            histogram: Box::new([[0; 256]; 4]),
+            sum: [0; 4],
+            buckets: vec![[0; 4]; 1 << (3 * Self::BUCKET_BITS)],
+        }
+    }
+
+    fn add(mut self, premultiplied: [u8; 4]) -> Self {
+        let alpha = premultiplied[3];
+        self.histogram[3][usize::from(alpha)] += 1;
+        if alpha == 0 {
+            return self;
+        }
+        for (sum, c) in self.sum.iter_mut().zip(premultiplied) {
+            *sum += u64::from(c);
+        }
+
+        let straight = color::unpremultiply(premultiplied);
+        for (histogram, c) in self.histogram.iter_mut().zip(&straight[..3]) {
+            histogram[usize::from(*c)] += 1;
+        }
+        let shift = 8 - Self::BUCKET_BITS;
+        let index = straight[..3].iter().fold(0, |index, c| {
+            (index << Self::BUCKET_BITS) | usize::from(c >> shift)
+        });
+        let bucket = &mut self.buckets[index];
+        for c in 0..3 {
+            bucket[c] += u64::from(straight[c]) * u64::from(alpha);
+        }
+        bucket[3] += u64::from(alpha);
+        self
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for (a, b) in self.histogram.iter_mut().zip(other.histogram.iter()) {
+            for (a, b) in a.iter_mut().zip(b) {
+                *a += b;
+            }
+        }
+        for (a, b) in self.sum.iter_mut().zip(other.sum) {
+            *a += b;
+        }
+        for (a, b) in self.buckets.iter_mut().zip(&other.buckets) {
+            for (a, b) in a.iter_mut().zip(b) {
+                *a += b;
+            }
+        }
+        self
+    }
+}
+
+impl ColorStats {
+    /// Compute the statistics of a premultiplied image.
+    pub fn compute(image: &RgbaImage) -> Self {
+        let partial = image
+            .as_raw()
+            .par_chunks_exact(4)
+            .fold(Partial::new, |partial, pixel| {
+                partial.add([pixel[0], pixel[1], pixel[2], pixel[3]])
+            })
+            .reduce(Partial::new, Partial::merge);
+
+        let [r, g, b, alpha] = partial.sum;
+        // Premultiplied sums over the alpha sum give the alpha weighted
+        // average of the straight colors.
+        let average =
+            (alpha > 0).then(|| [r, g, b].map(|c| ((c * 255 + alpha / 2) / alpha).min(255) as u8));
+        let dominant = partial
+            .buckets
+            .iter()
+            .filter(|bucket| bucket[3] > 0)
+            .max_by_key(|bucket| bucket[3])
+            .map(|bucket| [0, 1, 2].map(|c| (bucket[c] / bucket[3]) as u8));
+
+        let pixels = u64::from(image.width()) * u64::from(image.height());
+        let transparent = u64::from(partial.histogram[3][0]);
+        Self {
+            histogram: *partial.histogram,
+            average,
+            dominant,
+            coverage: if pixels == 0 {
+                0.0
+            } else {
+                (pixels - transparent) as f32 / pixels as f32
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_of_premultiplied_pixels() {
+        const CLEAR: [u8; 4] = [0, 0, 0, 0];
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const BLUE: [u8; 4] = [0, 0, 255, 255];
+        const BLACK: [u8; 4] = [0, 0, 0, 255];
+        const HALF_WHITE: [u8; 4] = [128, 128, 128, 128];
+        for (pixels, average, dominant, coverage) in [
+            (vec![], None, None, 0.0),
+            (vec![CLEAR; 4], None, None, 0.0),
+            (vec![RED; 2], Some([255, 0, 0]), Some([255, 0, 0]), 1.0),
+            (vec![RED, CLEAR], Some([255, 0, 0]), Some([255, 0, 0]), 0.5),
+            (
+                vec![BLUE, BLUE, BLUE, RED],
+                Some([64, 0, 191]),
+                Some([0, 0, 255]),
+                1.0,
+            ),
+            // Weighted by alpha, so the opaque black counts twice as much.
+            (vec![HALF_WHITE, BLACK], Some([85; 3]), Some([0; 3]), 1.0),
+        ] {
+            let image = RgbaImage::from_raw(pixels.len() as u32, 1, pixels.concat()).unwrap();
+            let stats = ColorStats::compute(&image);
+            assert_eq!(stats.average, average, "{pixels:?}");
+            assert_eq!(stats.dominant, dominant, "{pixels:?}");
+            assert_eq!(stats.coverage, coverage, "{pixels:?}");
+        }
+    }
+
+    #[test]
+    fn histograms_count_straight_channels() {
+        let pixels = [[0, 0, 0, 0], [128, 64, 0, 128], [255, 0, 0, 255]];
+        let image = RgbaImage::from_raw(3, 1, pixels.concat()).unwrap();
+        let histogram = ColorStats::compute(&image).histogram;
+        let counts = |channel: usize| {
+            histogram[channel]
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(value, count)| (value, *count))
+                .collect::<Vec<_>>()
+        };
+        // Transparent pixels only count towards alpha.
+        assert_eq!(counts(0), [(255, 2)]);
+        assert_eq!(counts(1), [(0, 1), (128, 1)]);
+        assert_eq!(counts(2), [(0, 2)]);
+        assert_eq!(counts(3), [(0, 1), (128, 1), (255, 1)]);
+    }
+}
@@ -4,7 +4,7 @@ use std::sync::Arc;
 /// variables. It is configured specifically to serve the `shader.wgsl`
 /// shader module and create bindings that match the shader's inputs.
 use super::dev::GpuHandle;
-use crate::compositor::CompositeLayer;
+use crate::CompositeLayer;
 
 /// Shader buffers on the CPU side.
 #[derive(Debug)]
@@ -79,11 +79,22 @@ pub(super) struct GpuBuffers {
 }
 
 impl GpuBuffers {
+    /// Size in bytes of an element of each buffer.
+    const ELEMENT_SIZE: u64 = 4;
+
+    /// Most layers that the buffers can hold and still be bound on the
+    /// device, larger composites have to be split into several passes.
+    pub fn max_len(dev: &GpuHandle) -> usize {
+        let limits = dev.device.limits();
+        let bytes = u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size);
+        (bytes / Self::ELEMENT_SIZE).max(1) as usize
+    }
+
     /// Create the buffers on the GPU.
     pub fn new(dev: Arc<GpuHandle>, size: usize) -> Self {
         let storage_desc: wgpu::BufferDescriptor = wgpu::BufferDescriptor {
             label: None,
-            size: 4 * size as u64,
+            size: Self::ELEMENT_SIZE * size as u64,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         };
@@ -0,0 +1,80 @@
+use image::{Pixel, Rgba};
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AllocationError {
+    #[error("texture of {width}x{height} with {layers} layers exceeds the device limit of {max_dimension}x{max_dimension} with {max_layers} layers")]
+    ExceedsLimits {
+        width: u32,
+        height: u32,
+        layers: u32,
+        max_dimension: u32,
+        max_layers: u32,
+    },
+    #[error("texture needs {} MiB but only {} MiB of the GPU memory budget is free", .needed >> 20, .available >> 20)]
+    OverBudget { needed: u64, available: u64 },
+}
+
+/// Accounts for the GPU memory held by textures against a user configurable
+/// budget, so that documents can be evicted before the device runs out.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: AtomicU64,
+    used: AtomicU64,
+}
+
+impl MemoryBudget {
+    pub const DEFAULT_LIMIT: u64 = 2 << 30;
+
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit: AtomicU64::new(limit),
+            used: AtomicU64::new(0),
+        }
+    }
+
+    /// Bytes taken up by a texture of the given extent.
+    pub fn texture_bytes(size: wgpu::Extent3d) -> u64 {
+        u64::from(size.width)
+            * u64::from(size.height)
+            * u64::from(size.depth_or_array_layers)
+            * u64::from(Rgba::<u8>::CHANNEL_COUNT)
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn set_limit(&self, limit: u64) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn available(&self) -> u64 {
+        self.limit().saturating_sub(self.used())
+    }
+
+    /// Check whether an allocation fits in what is left of the budget.
+    pub fn check(&self, bytes: u64) -> Result<(), AllocationError> {
+        let available = self.available();
+        if bytes > available {
+            return Err(AllocationError::OverBudget {
+                needed: bytes,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    pub(super) fn charge(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(super) fn refund(&self, bytes: u64) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,199 @@
+//! Color conversions shared by the loaders, the compositor and export.
+//!
+//! Layer textures hold 8-bit premultiplied colors in the sRGB encoding and
+//! blending happens on the encoded values, like Procreate does.
+
+/// Luminance weights used by the `lum` function of the compositor shader.
+/// Keep the two in sync so that the luminance matte matches the Color,
+/// Luminosity, Hue and Saturation blending modes.
+pub const LUMA_WEIGHTS: [f32; 3] = [0.3, 0.59, 0.11];
+
+/// Luminance of a straight color.
+pub fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    r * LUMA_WEIGHTS[0] + g * LUMA_WEIGHTS[1] + b * LUMA_WEIGHTS[2]
+}
+
+/// Gray level of an 8-bit premultiplied color in a luminance matte. The
+/// color is not unpremultiplied first, so that partial coverage darkens the
+/// matte like the color composited over black.
+pub fn luminance_matte([r, g, b, _]: [u8; 4]) -> u8 {
+    let [r, g, b] = [r, g, b].map(|c| f32::from(c) / 255.0);
+    (luminance([r, g, b]) * 255.0).round() as u8
+}
+
+/// Premultiply an 8-bit straight color, rounding to nearest.
+pub fn premultiply([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    let scale = |c: u8| ((u16::from(c) * u16::from(a) + 127) / 255) as u8;
+    [scale(r), scale(g), scale(b), a]
+}
+
+/// Recover the straight color of an 8-bit premultiplied color, rounding to
+/// nearest. Fully transparent colors become transparent black.
+pub fn unpremultiply([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    if a == 0 {
+        return [0; 4];
+    }
+    let scale = |c: u8| ((u32::from(c) * 255 + u32::from(a) / 2) / u32::from(a)).min(255) as u8;
+    [scale(r), scale(g), scale(b), a]
+}
+
+/// Decode an sRGB encoded channel to linear light.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear light channel with the sRGB transfer function.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear Display P3 to linear sRGB, both with a D65 white point.
+const P3_TO_SRGB: [[f32; 3]; 3] = [
+    [1.224_940_2, -0.224_940_4, 0.0],
+    [-0.042_056_9, 1.042_057_1, 0.0],
+    [-0.019_637_6, -0.078_636_1, 1.098_273_5],
+];
+
+/// Linear sRGB to linear Display P3, both with a D65 white point.
+const SRGB_TO_P3: [[f32; 3]; 3] = [
+    [0.822_462_1, 0.177_538, 0.0],
+    [0.033_194_1, 0.966_805_8, 0.0],
+    [0.017_082_7, 0.072_397_4, 0.910_519_9],
+];
+
+fn transform(m: &[[f32; 3]; 3], [r, g, b]: [f32; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * r + row[1] * g + row[2] * b)
+}
+
+/// Convert an encoded Display P3 color to encoded sRGB. Colors outside of the
+/// sRGB gamut are clamped.
+#[allow(dead_code)]
+pub fn p3_to_srgb(rgb: [f32; 3]) -> [f32; 3] {
+    transform(&P3_TO_SRGB, rgb.map(srgb_to_linear)).map(|c| linear_to_srgb(c.clamp(0.0, 1.0)))
+}
+
+/// Convert an encoded sRGB color to encoded Display P3.
+pub fn srgb_to_p3(rgb: [f32; 3]) -> [f32; 3] {
+    transform(&SRGB_TO_P3, rgb.map(srgb_to_linear)).map(|c| linear_to_srgb(c.clamp(0.0, 1.0)))
+}
+
+/// RGB color spaces that share the sRGB transfer function and white point,
+/// which exports can be converted between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    DisplayP3,
+}
+
+impl ColorSpace {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Srgb => "sRGB",
+            Self::DisplayP3 => "Display P3",
+        }
+    }
+}
+
+/// Matrix from the linear colors of one color space to those of another,
+/// if they differ.
+pub fn conversion_matrix(from: ColorSpace, to: ColorSpace) -> Option<[[f32; 3]; 3]> {
+    match (from, to) {
+        (ColorSpace::Srgb, ColorSpace::DisplayP3) => Some(SRGB_TO_P3),
+        (ColorSpace::DisplayP3, ColorSpace::Srgb) => Some(P3_TO_SRGB),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-4, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn srgb_transfer_round_trips() {
+        for i in 0..=255 {
+            let c = f32::from(i as u8) / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-5);
+        }
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-5);
+    }
+
+    #[test]
+    fn p3_round_trips_in_gamut() {
+        for rgb in [[0.0; 3], [1.0; 3], [0.5, 0.25, 0.75], [0.2, 0.8, 0.4]] {
+            assert_close(p3_to_srgb(srgb_to_p3(rgb)), rgb);
+        }
+    }
+
+    #[test]
+    fn p3_white_and_primaries() {
+        assert_close(p3_to_srgb([1.0, 1.0, 1.0]), [1.0, 1.0, 1.0]);
+        // Pure P3 red lies outside of sRGB and is clamped to sRGB red.
+        assert_close(p3_to_srgb([1.0, 0.0, 0.0]), [1.0, 0.0, 0.0]);
+        // sRGB red is a less saturated red in P3.
+        let red = srgb_to_p3([1.0, 0.0, 0.0]);
+        assert!(red[0] < 1.0 && red[1] > 0.0 && red[2] > 0.0);
+    }
+
+    #[test]
+    fn premultiply_round_trips_opaque_and_transparent() {
+        assert_eq!(premultiply([200, 100, 50, 255]), [200, 100, 50, 255]);
+        assert_eq!(unpremultiply([200, 100, 50, 255]), [200, 100, 50, 255]);
+        assert_eq!(premultiply([200, 100, 50, 0]), [0, 0, 0, 0]);
+        assert_eq!(unpremultiply([10, 10, 10, 0]), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn unpremultiply_inverts_premultiply() {
+        for a in [1u8, 17, 128, 254] {
+            for c in [0u8, 1, 64, 200, 255] {
+                let [r, ..] = unpremultiply(premultiply([c, c, c, a]));
+                // Low alpha loses precision, but never by more than a step.
+                let step = 255 / i32::from(a) + 1;
+                assert!(
+                    (i32::from(r) - i32::from(c)).abs() <= step,
+                    "{c} @ {a} -> {r}"
+                );
+            }
+        }
+        assert_eq!(unpremultiply([255, 0, 0, 128]), [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn luminance_of_white_is_one() {
+        assert!((luminance([1.0; 3]) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn luminance_matte_weights_premultiplied_colors() {
+        for (color, gray) in [
+            ([255, 255, 255, 255], 255),
+            ([0, 0, 0, 255], 0),
+            ([0, 0, 0, 0], 0),
+            // Half covered white is half as bright.
+            ([128, 128, 128, 128], 128),
+            // The blending modes' weights rather than Rec. 709.
+            ([255, 0, 0, 255], 77),
+            ([0, 255, 0, 255], 150),
+            ([0, 0, 255, 255], 28),
+            ([128, 0, 0, 128], 38),
+        ] {
+            assert_eq!(luminance_matte(color), gray, "{color:?}");
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use super::budget::MemoryBudget;
+use super::timing::GpuTimer;
+use super::upload::UploadThrottle;
+use std::fmt;
+use std::sync::Arc;
+
+/// Graphics API that the GPU is driven through.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GpuBackend {
+    /// Whichever primary backend the platform has.
+    #[default]
+    Auto,
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+    /// Adapter that draws on the CPU, slow but independent of drivers.
+    Software,
+}
+
+impl GpuBackend {
+    pub const fn all() -> &'static [Self] {
+        &[
+            Self::Auto,
+            Self::Vulkan,
+            Self::Dx12,
+            Self::Metal,
+            Self::Gl,
+            Self::Software,
+        ]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Vulkan => "vulkan",
+            Self::Dx12 => "dx12",
+            Self::Metal => "metal",
+            Self::Gl => "gl",
+            Self::Software => "software",
+        }
+    }
+
+    /// Backend named as on the command line and in the settings.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|backend| backend.as_str() == name)
+    }
+
+    /// Backend that an adapter drives its device through, `Auto` for those
+    /// that can not be chosen by name.
+    pub fn of_adapter(info: &wgpu::AdapterInfo) -> Self {
+        match info.backend {
+            wgpu::Backend::Vulkan => Self::Vulkan,
+            wgpu::Backend::Dx12 => Self::Dx12,
+            wgpu::Backend::Metal => Self::Metal,
+            wgpu::Backend::Gl => Self::Gl,
+            _ => Self::Auto,
+        }
+    }
+
+    /// Whether a device asked for with this backend may end up on `other`,
+    /// like the automatic choice ending up on any primary backend.
+    fn may_use(self, other: Self) -> bool {
+        self == other
+            || (self == Self::Auto && other.backends().intersects(wgpu::Backends::PRIMARY))
+    }
+
+    fn backends(self) -> wgpu::Backends {
+        match self {
+            Self::Auto => wgpu::Backends::PRIMARY,
+            Self::Vulkan => wgpu::Backends::VULKAN,
+            Self::Dx12 => wgpu::Backends::DX12,
+            Self::Metal => wgpu::Backends::METAL,
+            Self::Gl => wgpu::Backends::GL,
+            Self::Software => wgpu::Backends::all(),
+        }
+    }
+}
+
+/// Backend and adapter to create the GPU device with, so that a driver that
+/// misbehaves can be avoided.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GpuPreference {
+    pub backend: GpuBackend,
+    /// Index into the adapters of the backend, the most powerful adapter
+    /// if `None`.
+    pub adapter: Option<usize>,
+}
+
+impl GpuPreference {
+    /// The preference followed by those to fall back to in turn if it
+    /// fails: the default adapter of its backend, then of every backend and
+    /// finally the software adapter. Backends whose device was `lost`, and
+    /// the automatic choice if it could pick one of them, are left out.
+    pub fn fallbacks(self, lost: &[GpuBackend]) -> Vec<Self> {
+        let mut chain = vec![self];
+        let defaults = std::iter::once(self.backend)
+            .chain(GpuBackend::all().iter().copied())
+            .map(|backend| Self {
+                backend,
+                adapter: None,
+            });
+        for preference in defaults {
+            if !chain.contains(&preference) {
+                chain.push(preference);
+            }
+        }
+        chain.retain(|preference| !lost.iter().any(|lost| preference.backend.may_use(*lost)));
+        chain
+    }
+}
+
+impl fmt::Display for GpuPreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.backend.as_str())?;
+        if let Some(adapter) = self.adapter {
+            write!(f, " adapter {adapter}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents a grouping of useful GPU resources.
+#[derive(Debug)]
+pub struct GpuHandle {
+    /// WGPU instance.
+    pub instance: wgpu::Instance,
+    /// Physical compute device.
+    pub adapter: wgpu::Adapter,
+    /// Logical compute device.
+    pub device: wgpu::Device,
+    /// Device command queue.
+    pub queue: wgpu::Queue,
+    /// GPU memory taken up by textures.
+    pub budget: Arc<MemoryBudget>,
+    /// Paces texture uploads from background threads.
+    pub uploads: UploadThrottle,
+    /// Backend and adapter that the device was created with.
+    pub preference: GpuPreference,
+}
+
+impl GpuHandle {
+    pub fn instance_descriptor(backend: GpuBackend) -> wgpu::InstanceDescriptor {
+        wgpu::InstanceDescriptor {
+            backends: backend.backends(),
+            dx12_shader_compiler: wgpu::Dx12Compiler::Dxc {
+                dxil_path: None,
+                dxc_path: None,
+            },
+            flags: wgpu::InstanceFlags::default(),
+            gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+        }
+    }
+
+    const ADAPTER_OPTIONS: wgpu::RequestAdapterOptions<'static, 'static> =
+        wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        };
+
+    const DOWNLEVEL_FLAGS: wgpu::DownlevelFlags = wgpu::DownlevelFlags::VIEW_FORMATS;
+
+    /// Create a bare GPU handle with no surface target, falling back from
+    /// the preference until a device is created.
+    pub async fn new(preference: GpuPreference) -> Option<Self> {
+        for preference in preference.fallbacks(&[]) {
+            let instance = wgpu::Instance::new(Self::instance_descriptor(preference.backend));
+            if let Some(adapter) = Self::select_adapter(&instance, preference, None).await {
+                if let Some(dev) = Self::from_adapter(instance, adapter, preference).await {
+                    return Some(dev);
+                }
+            }
+            tracing::warn!("No GPU device could be created with {preference}");
+        }
+        None
+    }
+
+    /// Create a GPU handle with a surface target compatible with the window,
+    /// falling back from the preference until a device is created, skipping
+    /// the backends whose device was `lost`. Whether it fell back can be
+    /// told from the handle's preference.
+    pub async fn with_window<'a, W: wgpu::WindowHandle>(
+        window: &'a W,
+        preference: GpuPreference,
+        lost: &[GpuBackend],
+    ) -> Option<(Self, wgpu::Surface<'a>)> {
+        for preference in preference.fallbacks(lost) {
+            let instance = wgpu::Instance::new(Self::instance_descriptor(preference.backend));
+            let Ok(surface) = instance.create_surface(window) else {
+                tracing::warn!("No window surface could be created with {preference}");
+                continue;
+            };
+            if let Some(adapter) = Self::select_adapter(&instance, preference, Some(&surface)).await
+            {
+                if let Some(dev) = Self::from_adapter(instance, adapter, preference).await {
+                    return Some((dev, surface));
+                }
+            }
+            tracing::warn!("No GPU device could be created with {preference}");
+        }
+        None
+    }
+
+    /// Names of the adapters of a backend, in the order that they are
+    /// chosen by index.
+    pub fn adapter_names(backend: GpuBackend) -> Vec<String> {
+        wgpu::Instance::new(Self::instance_descriptor(backend))
+            .enumerate_adapters(backend.backends())
+            .iter()
+            .map(|adapter| {
+                let info = adapter.get_info();
+                format!("{} ({:?})", info.name, info.backend)
+            })
+            .collect()
+    }
+
+    /// The preferred adapter, or the most powerful one if there is no
+    /// preference. `None` if the preferred adapter is missing or cannot
+    /// draw to the surface.
+    async fn select_adapter(
+        instance: &wgpu::Instance,
+        preference: GpuPreference,
+        surface: Option<&wgpu::Surface<'_>>,
+    ) -> Option<wgpu::Adapter> {
+        if let Some(index) = preference.adapter {
+            return instance
+                .enumerate_adapters(preference.backend.backends())
+                .into_iter()
+                .nth(index)
+                .filter(|adapter| {
+                    surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+                });
+        }
+        instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: surface,
+                force_fallback_adapter: preference.backend == GpuBackend::Software,
+                ..Self::ADAPTER_OPTIONS
+            })
+            .await
+    }
+
+    /// Request device.
+    async fn from_adapter(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        preference: GpuPreference,
+    ) -> Option<Self> {
+        tracing::debug!("Adapter: {:?}", adapter.get_info());
+        tracing::debug!("Adapter limits: {:?}", adapter.limits());
+
+        // Layer textures are viewed in more than one format, which some
+        // downlevel adapters such as OpenGL ES 3.0 cannot do.
+        let missing = Self::DOWNLEVEL_FLAGS - adapter.get_downlevel_capabilities().flags;
+        if !missing.is_empty() {
+            tracing::warn!("{} lacks {missing:?}", adapter.get_info().name);
+            return None;
+        }
+
+        // Large canvases with many layers need more than the default limits.
+        let adapter_limits = adapter.limits();
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    // Composites are only timed where timestamps are supported.
+                    required_features: wgpu::Features::PUSH_CONSTANTS
+                        | (adapter.features() & GpuTimer::FEATURES),
+                    required_limits: wgpu::Limits {
+                        max_push_constant_size: 48,
+                        max_buffer_size: 1024 << 20,
+                        max_texture_dimension_2d: adapter_limits.max_texture_dimension_2d,
+                        max_texture_array_layers: adapter_limits.max_texture_array_layers,
+                        max_storage_buffer_binding_size: adapter_limits
+                            .max_storage_buffer_binding_size,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .map_err(|err| tracing::warn!("Failed to create the GPU device: {err}"))
+            .ok()?;
+
+        Some(Self {
+            instance,
+            device,
+            adapter,
+            queue,
+            budget: Arc::new(MemoryBudget::new(MemoryBudget::DEFAULT_LIMIT)),
+            uploads: UploadThrottle::default(),
+            preference,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallbacks_end_with_the_software_adapter() {
+        let preference = GpuPreference {
+            backend: GpuBackend::Gl,
+            adapter: Some(1),
+        };
+        let chain = preference.fallbacks(&[]);
+        assert_eq!(chain[0], preference);
+        assert_eq!(
+            chain[1],
+            GpuPreference {
+                backend: GpuBackend::Gl,
+                adapter: None,
+            }
+        );
+        assert_eq!(chain.len(), GpuBackend::all().len() + 1);
+        assert_eq!(chain.last().unwrap().backend, GpuBackend::Software);
+        assert_eq!(chain[0].to_string(), "gl adapter 1");
+    }
+
+    #[test]
+    fn lost_backends_are_not_fallen_back_to() {
+        use GpuBackend::*;
+        let backends = |preference: GpuPreference, lost: &[GpuBackend]| {
+            preference
+                .fallbacks(lost)
+                .into_iter()
+                .map(|preference| preference.backend)
+                .collect::<Vec<_>>()
+        };
+        let auto = GpuPreference::default();
+        // The automatic choice could pick the lost primary backend again.
+        assert_eq!(backends(auto, &[Vulkan]), [Dx12, Metal, Gl, Software]);
+        assert_eq!(backends(auto, &[Gl]), [Auto, Vulkan, Dx12, Metal, Software]);
+        let vulkan = GpuPreference {
+            backend: Vulkan,
+            adapter: Some(0),
+        };
+        assert_eq!(
+            backends(vulkan, &[Auto, Vulkan, Dx12, Metal, Gl]),
+            [Software]
+        );
+        assert!(backends(vulkan, GpuBackend::all()).is_empty());
+    }
+}
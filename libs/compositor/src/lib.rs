@@ -0,0 +1,1225 @@
+//! GPU compositor for stacks of layers, built on wgpu.
+//!
+//! Layers are textures of a [`TiledTexture`], which splits canvases larger
+//! than the GPU allows into regions. A [`CompositorTarget`] blends a list of
+//! [`CompositeLayer`]s with their [`BlendMode`], opacity and clipping masks,
+//! and then applies the [`CompositeFinish`] passes. Isolated groups are
+//! first blended into a texture of their own with
+//! [`CompositorTarget::render_to_layer`], to be blended as a single layer.
+//! The compositor knows nothing about the file format the layers come from.
+//!
+//! Textures hold premultiplied RGBA with 8 bits per channel, with their rows
+//! stored bottom-up. The public items of this crate follow semantic
+//! versioning.
+
+pub mod adjust;
+pub mod analysis;
+mod bind;
+pub mod budget;
+pub mod color;
+pub mod dev;
+pub mod lut;
+pub mod metadata;
+pub mod post;
+pub mod profile;
+pub mod tex;
+pub mod timing;
+pub mod upload;
+
+use self::{
+    adjust::Adjustments,
+    bind::{CpuBuffers, GpuBuffers},
+    dev::GpuHandle,
+    lut::LutTexture,
+    tex::{GpuTexture, Region, TiledTexture},
+};
+use arc_swap::ArcSwap;
+use image::{Pixel, Rgba};
+use std::{num::NonZeroU32, sync::Arc};
+use wgpu::{util::DeviceExt, CommandEncoder};
+
+/// Associates the texture's actual dimensions and its buffer dimensions on the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferDimensions {
+    pub width: u32,
+    pub height: u32,
+    pub unpadded_bytes_per_row: u32,
+    pub padded_bytes_per_row: u32,
+    pub extent: wgpu::Extent3d,
+}
+
+impl BufferDimensions {
+    /// Computes the buffer dimensions between the texture's actual dimensions
+    /// and its buffer dimensions on the GPU.
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self::from_extent(wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        })
+    }
+
+    /// Computes the buffer dimensions from the GPU texture extent.
+    pub const fn from_extent(extent: wgpu::Extent3d) -> Self {
+        // It is a WebGPU requirement that
+        // ImageCopyBuffer.layout.bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT == 0
+        // So we calculate padded_bytes_per_row by rounding unpadded_bytes_per_row
+        // up to the next multiple of wgpu::COPY_BYTES_PER_ROW_ALIGNMENT.
+        // https://en.wikipedia.org/wiki/Data_structure_alignment#Computing_padding
+        debug_assert!(extent.depth_or_array_layers == 1);
+        let width = extent.width;
+        let height = extent.height;
+        let bytes_per_pixel =
+            (Rgba::<u8>::CHANNEL_COUNT as usize * std::mem::size_of::<u8>()) as u32;
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row_padding = (align - unpadded_bytes_per_row % align) % align;
+        let padded_bytes_per_row = unpadded_bytes_per_row + padded_bytes_per_row_padding;
+        Self {
+            width,
+            height,
+            unpadded_bytes_per_row,
+            padded_bytes_per_row,
+            extent,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// Vertex input to the shader.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+struct VertexInput {
+    /// Position of the vertex.
+    position: [f32; 3],
+    /// Holds the UV information of the background.
+    /// The base texture uses this, which may be the texture from a
+    /// previous pass.
+    bg_coords: [f32; 2],
+    /// Holds the UV information of the foreground.
+    /// The layers to be composited on the output texture uses this.
+    fg_coords: [f32; 2],
+}
+
+impl VertexInput {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<VertexInput>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                // position
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                // bg_coords
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                // fg_coords
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2 + 3]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+            ],
+        }
+    }
+}
+
+/// Push constants fed to the fragment shader.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+struct PushConstants {
+    /// Premultiplied RGBA background color, fully transparent if hidden.
+    background: [f32; 4],
+    /// Number of layers in this render pass.
+    layer_count: u32,
+    /// Blending mode of the paper overlay.
+    paper_blend: u32,
+    /// Strength (0.0..=1.0) of the paper overlay, 0.0 if disabled.
+    paper_strength: f32,
+    /// Whether the lookup table is applied, 0 if disabled.
+    lut_enabled: u32,
+    /// Position of the rendered tile in the layer textures, so that the
+    /// paper lines up across tiles.
+    paper_offset: [u32; 2],
+    /// How blends brighter than white are handled, see [`ToneMap`].
+    tone_map: u32,
+    /// Space that the layers are blended in, see [`BlendSpace`].
+    blend_space: u32,
+}
+
+/// Paper texture that is tiled and blended over the final composite.
+#[derive(Debug, Clone, Copy)]
+pub struct CompositePaper<'a> {
+    /// Single layer texture of the paper.
+    pub texture: &'a GpuTexture,
+    /// Blending mode of the paper over the composite.
+    pub blend: BlendMode,
+    /// Strength (0.0..=1.0) of the paper.
+    pub strength: f32,
+}
+
+/// How the colors of a layer are combined with the colors beneath it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Add,
+    Lighten,
+    Exclusion,
+    Difference,
+    Subtract,
+    LinearBurn,
+    ColorDodge,
+    ColorBurn,
+    Overlay,
+    HardLight,
+    Color,
+    Luminosity,
+    Hue,
+    Saturation,
+    SoftLight,
+    Darken,
+    HardMix,
+    VividLight,
+    LinearLight,
+    PinLight,
+    LighterColor,
+    DarkerColor,
+    Divide,
+}
+
+impl BlendMode {
+    /// Value of the mode in the shader.
+    const fn to_u32(self) -> u32 {
+        match self {
+            Self::Normal => 0,
+            Self::Multiply => 1,
+            Self::Screen => 2,
+            Self::Add => 3,
+            Self::Lighten => 4,
+            Self::Exclusion => 5,
+            Self::Difference => 6,
+            Self::Subtract => 7,
+            Self::LinearBurn => 8,
+            Self::ColorDodge => 9,
+            Self::ColorBurn => 10,
+            Self::Overlay => 11,
+            Self::HardLight => 12,
+            Self::Color => 13,
+            Self::Luminosity => 14,
+            Self::Hue => 15,
+            Self::Saturation => 16,
+            Self::SoftLight => 17,
+            Self::Darken => 19,
+            Self::HardMix => 20,
+            Self::VividLight => 21,
+            Self::LinearLight => 22,
+            Self::PinLight => 23,
+            Self::LighterColor => 24,
+            Self::DarkerColor => 25,
+            Self::Divide => 26,
+        }
+    }
+}
+
+/// How blends that come out brighter than white, such as stacked Add and
+/// Color Dodge glows, are brought back into range. Colors are only kept
+/// brighter than white within a pass, since the textures between passes and
+/// of isolated groups hold 8-bit colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMap {
+    /// Clamp every blend to white as soon as it is made.
+    #[default]
+    Off,
+    /// Keep brighter than white colors between layers and clip them once
+    /// all layers are blended.
+    Clip,
+    /// Keep brighter than white colors between layers and roll the
+    /// highlights off smoothly once all layers are blended.
+    Highlights,
+}
+
+impl ToneMap {
+    pub const fn all() -> &'static [Self] {
+        &[Self::Off, Self::Clip, Self::Highlights]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Clip => "Clip",
+            Self::Highlights => "Soft Highlights",
+        }
+    }
+
+    /// Value of the mode in the shader.
+    const fn to_u32(self) -> u32 {
+        match self {
+            Self::Off => 0,
+            Self::Clip => 1,
+            Self::Highlights => 2,
+        }
+    }
+}
+
+/// Space that the colors of layers are blended in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendSpace {
+    /// Colors as they are stored, encoded with the sRGB curve, which is how
+    /// Procreate and Photoshop blend.
+    #[default]
+    Encoded,
+    /// Colors decoded to linear light, which makes Add and Screen glows
+    /// brighter and soft edges of bright colors less dark.
+    Linear,
+}
+
+impl BlendSpace {
+    pub const fn all() -> &'static [Self] {
+        &[Self::Encoded, Self::Linear]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Encoded => "sRGB Encoded",
+            Self::Linear => "Linear Light",
+        }
+    }
+
+    /// Value of the space in the shader.
+    const fn to_u32(self) -> u32 {
+        match self {
+            Self::Encoded => 0,
+            Self::Linear => 1,
+        }
+    }
+}
+
+/// Passes applied to the final composite, in order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompositeFinish<'a> {
+    /// Tone mapping of colors brighter than white, before the other passes.
+    pub tone_map: ToneMap,
+    pub adjustments: Option<Adjustments>,
+    pub paper: Option<CompositePaper<'a>>,
+    /// Color lookup table that grades the composite.
+    pub lut: Option<&'a LutTexture>,
+}
+
+/// Compositing layer information.
+#[derive(Debug, Clone)]
+pub struct CompositeLayer {
+    /// Texture index into a `&[GpuBuffer]`.
+    pub texture: u32,
+    /// Clipping texture index into a `&[GpuBuffer]`.
+    pub clipped: Option<u32>,
+    /// Opacity (0.0..=1.0) of the layer.
+    pub opacity: f32,
+    /// Blending mode of the layer.
+    pub blend: BlendMode,
+}
+
+/// Layers of an isolated group, which are composited into a texture layer
+/// before being blended as a single layer.
+#[derive(Debug, Clone)]
+pub struct CompositeGroup {
+    /// Texture index to write the group composite into.
+    pub texture: u32,
+    /// Layers of the group.
+    pub layers: Vec<CompositeLayer>,
+}
+
+/// Rotation and flips of the layer textures in the output of a target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputTransform([[f32; 2]; 4]);
+
+pub struct CompositorData {
+    dev: Arc<GpuHandle>,
+    vertices: [VertexInput; 4],
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+}
+
+impl CompositorData {
+    /// Initial vertices
+    const SQUARE_VERTICES: [VertexInput; 4] = [
+        VertexInput {
+            position: [-1.0, 1.0, 0.0],
+            bg_coords: [0.0, 0.0],
+            fg_coords: [0.0, 1.0],
+        },
+        VertexInput {
+            position: [-1.0, -1.0, 0.0],
+            bg_coords: [0.0, 1.0],
+            fg_coords: [0.0, 0.0],
+        },
+        VertexInput {
+            position: [1.0, 1.0, 0.0],
+            bg_coords: [1.0, 0.0],
+            fg_coords: [1.0, 1.0],
+        },
+        VertexInput {
+            position: [1.0, -1.0, 0.0],
+            bg_coords: [1.0, 1.0],
+            fg_coords: [1.0, 0.0],
+        },
+    ];
+
+    /// Initial indices of the 2 triangle strips
+    const INDICES: [u16; 4] = [0, 1, 2, 3];
+
+    fn new(dev: Arc<GpuHandle>) -> Self {
+        let device = &dev.device;
+
+        // Create the vertex buffer.
+        let vertices = Self::SQUARE_VERTICES;
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("vertex_buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Index draw buffer
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("index_buffer"),
+            contents: bytemuck::cast_slice(&Self::INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            dev,
+            vertices,
+            vertex_buffer,
+            index_buffer,
+        }
+    }
+
+    /// Flip the vertex data's foreground UV of the compositor target.
+    pub fn flip_vertices(&mut self, horizontal: bool, vertical: bool) {
+        for v in &mut self.vertices {
+            v.fg_coords = [
+                if horizontal {
+                    1.0 - v.fg_coords[0]
+                } else {
+                    v.fg_coords[0]
+                },
+                if vertical {
+                    1.0 - v.fg_coords[1]
+                } else {
+                    v.fg_coords[1]
+                },
+            ];
+        }
+        self.load_vertex_buffer();
+    }
+
+    /// Current rotation and flips of the output.
+    pub fn transform(&self) -> OutputTransform {
+        OutputTransform(self.vertices.map(|v| v.fg_coords))
+    }
+
+    /// Rotate and flip the output the same way as another target's.
+    pub fn set_transform(&mut self, OutputTransform(coords): OutputTransform) {
+        for (v, coords) in self.vertices.iter_mut().zip(coords) {
+            v.fg_coords = coords;
+        }
+        self.load_vertex_buffer();
+    }
+
+    /// Rotate the vertex data's foreground UV of the compositor target.
+    pub fn rotate_vertices(&mut self, ccw: bool) {
+        let temp = self.vertices[0].fg_coords;
+        if ccw {
+            self.vertices[0].fg_coords = self.vertices[1].fg_coords;
+            self.vertices[1].fg_coords = self.vertices[3].fg_coords;
+            self.vertices[3].fg_coords = self.vertices[2].fg_coords;
+            self.vertices[2].fg_coords = temp;
+        } else {
+            self.vertices[0].fg_coords = self.vertices[2].fg_coords;
+            self.vertices[2].fg_coords = self.vertices[3].fg_coords;
+            self.vertices[3].fg_coords = self.vertices[1].fg_coords;
+            self.vertices[1].fg_coords = temp;
+        }
+        self.load_vertex_buffer();
+    }
+
+    /// Where a region of the layer textures of the given size ends up in the
+    /// output, following the rotation and flips of the vertices.
+    pub fn map_region(&self, region: Region, width: u32, height: u32) -> Region {
+        let [top_left, bottom_left, top_right, _] = self.vertices.map(|v| v.fg_coords);
+        // The output axes are unit vectors along the layer texture axes.
+        let right = [top_right[0] - top_left[0], top_right[1] - top_left[1]];
+        let down = [bottom_left[0] - top_left[0], bottom_left[1] - top_left[1]];
+        let to_output = |x: u32, y: u32| {
+            let offset = [
+                f64::from(x) - f64::from(top_left[0]) * f64::from(width),
+                f64::from(y) - f64::from(top_left[1]) * f64::from(height),
+            ];
+            let project =
+                |axis: [f32; 2]| offset[0] * f64::from(axis[0]) + offset[1] * f64::from(axis[1]);
+            (project(right), project(down))
+        };
+
+        let (x0, y0) = to_output(region.x, region.y);
+        let (x1, y1) = to_output(region.x + region.width, region.y + region.height);
+        Region {
+            x: x0.min(x1).round() as u32,
+            y: y0.min(y1).round() as u32,
+            width: (x1 - x0).abs().round() as u32,
+            height: (y1 - y0).abs().round() as u32,
+        }
+    }
+
+    /// Pixel of the layer textures of the given size that ends up at a
+    /// pixel of the output, the inverse of [`Self::map_region`].
+    pub fn layer_pixel(&self, (x, y): (u32, u32), width: u32, height: u32) -> Option<(u32, u32)> {
+        let [top_left, bottom_left, top_right, _] = self.vertices.map(|v| v.fg_coords);
+        let right = [top_right[0] - top_left[0], top_right[1] - top_left[1]];
+        let down = [bottom_left[0] - top_left[0], bottom_left[1] - top_left[1]];
+        // The axes are orthonormal, so going back is a transposed projection
+        // of the center of the output pixel.
+        let (x, y) = (f64::from(x) + 0.5, f64::from(y) + 0.5);
+        let unproject = |axis: usize, size: u32| {
+            f64::from(top_left[axis]) * f64::from(size)
+                + x * f64::from(right[axis])
+                + y * f64::from(down[axis])
+        };
+        let (lx, ly) = (unproject(0, width), unproject(1, height));
+        ((0.0..f64::from(width)).contains(&lx) && (0.0..f64::from(height)).contains(&ly))
+            .then_some((lx as u32, ly as u32))
+    }
+
+    /// Load the GPU vertex buffer with updated data.
+    fn load_vertex_buffer(&mut self) {
+        self.dev
+            .queue
+            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+    }
+}
+
+/// Output target of a compositor pipeline.
+pub struct CompositorTarget {
+    pub dev: Arc<GpuHandle>,
+    pub data: CompositorData,
+    /// Output texture dimensions, across all of its tiles.
+    pub dim: BufferDimensions,
+    /// Output pixels per side of a pixel actually rendered, above 1 to
+    /// composite at a lower resolution.
+    downscale: u32,
+    /// Space that the layers are blended in.
+    pub blend_space: BlendSpace,
+    /// Compositor output buffers and texture.
+    pub output: Option<CompositorOutput>,
+}
+
+/// Compositor stage buffers. This is so that the rendering process
+/// can reuse buffers and textures whenever possible.
+pub struct CompositorOutput {
+    dev: Arc<GpuHandle>,
+    /// Number of layers that the buffers of all passes hold together.
+    size: usize,
+    /// Most layers blended in a single pass, see [`GpuBuffers::max_len`].
+    per_pass: usize,
+    /// Shader buffers of each render pass.
+    passes: Vec<(CpuBuffers, GpuBuffers)>,
+    /// Output texture, with one tile for each tile of the layer textures.
+    pub texture: TiledTexture,
+}
+
+impl CompositorOutput {
+    /// Create a new compositor stage with output tiles covering `regions`.
+    pub fn new(target: &CompositorTarget, size: usize, regions: Vec<Region>) -> Self {
+        let per_pass = GpuBuffers::max_len(&target.dev);
+        Self {
+            dev: target.dev.clone(),
+            size,
+            per_pass,
+            passes: Self::create_passes(&target.dev, size, per_pass),
+            texture: TiledTexture::empty_layers(
+                &target.dev,
+                (
+                    target.dim.width.div_ceil(target.downscale),
+                    target.dim.height.div_ceil(target.downscale),
+                ),
+                regions,
+                1,
+                GpuTexture::OUTPUT_USAGE,
+            ),
+        }
+    }
+
+    /// Create enough buffers for `size` layers, in passes of `per_pass`.
+    fn create_passes(
+        dev: &Arc<GpuHandle>,
+        size: usize,
+        per_pass: usize,
+    ) -> Vec<(CpuBuffers, GpuBuffers)> {
+        let len = size.min(per_pass);
+        (0..size.div_ceil(per_pass).max(1))
+            .map(|_| (CpuBuffers::new(len), GpuBuffers::new(dev.clone(), len)))
+            .collect()
+    }
+
+    fn reserve_buffers(&mut self, size: usize) {
+        if size <= self.size {
+            return;
+        }
+
+        self.size = size;
+        self.passes = Self::create_passes(&self.dev, size, self.per_pass);
+    }
+}
+
+impl CompositorTarget {
+    /// Create a new compositor target.
+    pub fn new(dev: Arc<GpuHandle>) -> Self {
+        Self {
+            data: CompositorData::new(dev.clone()),
+            dev,
+            dim: BufferDimensions::new(0, 0),
+            downscale: 1,
+            blend_space: BlendSpace::default(),
+            output: None,
+        }
+    }
+
+    /// Transpose the dimensions of the compositor target's output.
+    pub fn transpose_dimensions(&mut self) -> bool {
+        self.set_dimensions(self.dim.height, self.dim.width)
+    }
+
+    /// Set the dimensions of the compositor target's output.
+    pub fn set_dimensions(&mut self, width: u32, height: u32) -> bool {
+        let buffer_dimensions = BufferDimensions::new(width, height);
+        if self.dim == buffer_dimensions {
+            return false;
+        }
+        self.dim = buffer_dimensions;
+        self.output = None;
+        true
+    }
+
+    pub fn downscale(&self) -> u32 {
+        self.downscale
+    }
+
+    /// Composite at a fraction of the resolution of the output, with the
+    /// output texture shrunk to match. Returns whether it changed.
+    pub fn set_downscale(&mut self, downscale: u32) -> bool {
+        let downscale = downscale.max(1);
+        if self.downscale == downscale {
+            return false;
+        }
+        self.downscale = downscale;
+        self.output = None;
+        true
+    }
+
+    /// Render composite layers using the compositor pipeline.
+    ///
+    /// Returns whether the output textures were recreated, in which case
+    /// any views of the previous output are stale.
+    pub fn render(
+        &mut self,
+        pipeline: &CompositorPipeline,
+        bg: Option<[f32; 4]>,
+        layers: &[CompositeLayer],
+        textures: &TiledTexture,
+        finish: CompositeFinish,
+    ) -> bool {
+        assert!(!self.dim.is_empty(), "set_dimensions required");
+
+        let replaced;
+        let command_buffers = {
+            let mut encoder = self
+                .dev
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+            replaced = self.render_command(pipeline, &mut encoder, bg, layers, textures, finish);
+
+            encoder.finish()
+        };
+        self.dev.queue.submit(Some(command_buffers));
+        replaced
+    }
+
+    /// Render composite layers and copy the result into a layer of `textures`
+    /// so that it can be composited as a layer itself.
+    ///
+    /// ### Note
+    /// The target should have the same dimensions as `textures` and must not
+    /// be rotated or flipped relative to it.
+    pub fn render_to_layer(
+        &mut self,
+        pipeline: &CompositorPipeline,
+        layers: &[CompositeLayer],
+        textures: &TiledTexture,
+        layer: u32,
+    ) {
+        self.render(pipeline, None, layers, textures, Default::default());
+
+        let output = self.output.as_ref().expect("rendered output");
+        self.dev.queue.submit(Some({
+            let mut encoder = self
+                .dev
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            for ((output_region, output), (region, texture)) in
+                output.texture.tiles.iter().zip(&textures.tiles)
+            {
+                debug_assert_eq!(output_region, region);
+                encoder.copy_texture_to_texture(
+                    output.texture.as_image_copy(),
+                    wgpu::ImageCopyTexture {
+                        texture: &texture.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    output.size,
+                );
+            }
+            encoder.finish()
+        }));
+    }
+
+    fn render_command(
+        &mut self,
+        pipeline: &CompositorPipeline,
+        encoder: &mut CommandEncoder,
+        bg: Option<[f32; 4]>,
+        composite_layers: &[CompositeLayer],
+        textures: &TiledTexture,
+        finish: CompositeFinish,
+    ) -> bool {
+        let regions = textures
+            .tiles
+            .iter()
+            .map(|(region, _)| {
+                let region = self
+                    .data
+                    .map_region(*region, textures.width, textures.height);
+                // Tiles are multiples of the downscale apart, except at the
+                // far edges, so they still line up.
+                let d = self.downscale;
+                Region {
+                    x: region.x / d,
+                    y: region.y / d,
+                    width: (region.x + region.width).div_ceil(d) - region.x / d,
+                    height: (region.y + region.height).div_ceil(d) - region.y / d,
+                }
+            })
+            .collect::<Vec<_>>();
+        // Rotating or flipping the canvas moves the tiles around the output.
+        if self
+            .output
+            .as_ref()
+            .is_some_and(|output| !output.texture.has_regions(&regions))
+        {
+            self.output = None;
+        }
+
+        let replaced = self.output.is_none();
+        let stage = if let Some(stage) = self.output.as_mut() {
+            stage.reserve_buffers(composite_layers.len());
+            stage
+        } else {
+            self.output
+                .insert(CompositorOutput::new(self, composite_layers.len(), regions))
+        };
+
+        // Documents with more layers than the shader buffers can bind at once
+        // are blended in several passes, each over the result of the last.
+        let pass_count = composite_layers.len().div_ceil(stage.per_pass).max(1);
+        for (index, (bindings, buffers)) in stage.passes.iter_mut().take(pass_count).enumerate() {
+            let start = (index * stage.per_pass).min(composite_layers.len());
+            let end = (start + stage.per_pass).min(composite_layers.len());
+            bindings.map_composite_layers(&composite_layers[start..end]);
+            buffers.load(bindings);
+        }
+
+        let paper = finish.paper;
+        let paper_view = paper
+            .map_or(&pipeline.blank_paper, |paper| paper.texture)
+            .create_view();
+        let lut_view = finish.lut.unwrap_or(&pipeline.blank_lut).create_view();
+        let create_adjustments = |adjustments: Adjustments| {
+            self.dev
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("adjustments"),
+                    contents: bytemuck::bytes_of(&adjustments.uniform()),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                })
+        };
+        let adjustments = create_adjustments(finish.adjustments.unwrap_or_default());
+        // Passes before the last only blend layers, the finish is applied once.
+        let unfinished = (pass_count > 1).then(|| create_adjustments(Adjustments::default()));
+
+        // Each tile of the layer textures is composited into its own output tile.
+        for ((region, layer_textures), (_, output)) in
+            textures.tiles.iter().zip(&stage.texture.tiles)
+        {
+            let layers_view = layer_textures.create_view();
+            let mut backdrop =
+                GpuTexture::empty_with_extent(&self.dev, output.size, GpuTexture::OUTPUT_USAGE);
+            for (index, (bindings, buffers)) in stage.passes.iter().take(pass_count).enumerate() {
+                let last = index + 1 == pass_count;
+                let push_constants = if last {
+                    PushConstants {
+                        background: bg
+                            .map(|[r, g, b, a]| {
+                                let a = a.clamp(0.0, 1.0);
+                                [r * a, g * a, b * a, a]
+                            })
+                            .unwrap_or_default(),
+                        layer_count: bindings.count,
+                        paper_blend: paper.map_or(0, |paper| paper.blend.to_u32()),
+                        paper_strength: paper.map_or(0.0, |paper| paper.strength),
+                        lut_enabled: u32::from(finish.lut.is_some()),
+                        paper_offset: [region.x, region.y],
+                        tone_map: finish.tone_map.to_u32(),
+                        blend_space: self.blend_space.to_u32(),
+                    }
+                } else {
+                    // Intermediate textures clamp to white anyway.
+                    PushConstants {
+                        layer_count: bindings.count,
+                        blend_space: self.blend_space.to_u32(),
+                        ..Default::default()
+                    }
+                };
+                let clear = bg
+                    .filter(|_| last)
+                    .map(|[r, g, b, a]| {
+                        // Output textures hold premultiplied colors.
+                        let a = f64::from(a.clamp(0.0, 1.0));
+                        wgpu::Color {
+                            r: f64::from(r) * a,
+                            g: f64::from(g) * a,
+                            b: f64::from(b) * a,
+                            a,
+                        }
+                    })
+                    .unwrap_or(wgpu::Color::TRANSPARENT);
+
+                let intermediate = (!last).then(|| {
+                    GpuTexture::empty_with_extent(&self.dev, output.size, GpuTexture::OUTPUT_USAGE)
+                });
+                let target = intermediate.as_ref().unwrap_or(output);
+                TilePass {
+                    backdrop: &backdrop.create_view(),
+                    layers: &layers_view,
+                    buffers,
+                    paper: &paper_view,
+                    lut: &lut_view,
+                    adjustments: unfinished
+                        .as_ref()
+                        .filter(|_| !last)
+                        .unwrap_or(&adjustments),
+                    target: &target.create_view(),
+                    clear,
+                    push_constants,
+                }
+                .record(&self.data, pipeline, encoder);
+
+                if let Some(intermediate) = intermediate {
+                    backdrop = intermediate;
+                }
+            }
+        }
+        replaced
+    }
+}
+
+/// A draw blending a run of layers of one tile over a backdrop.
+struct TilePass<'a> {
+    /// Premultiplied result of the previous passes.
+    backdrop: &'a wgpu::TextureView,
+    layers: &'a wgpu::TextureView,
+    buffers: &'a GpuBuffers,
+    paper: &'a wgpu::TextureView,
+    lut: &'a wgpu::TextureView,
+    adjustments: &'a wgpu::Buffer,
+    target: &'a wgpu::TextureView,
+    /// Color that the target is cleared to before blending.
+    clear: wgpu::Color,
+    push_constants: PushConstants,
+}
+
+impl TilePass<'_> {
+    fn record(
+        self,
+        data: &CompositorData,
+        pipeline: &CompositorPipeline,
+        encoder: &mut CommandEncoder,
+    ) {
+        let blending_bind_group = data
+            .dev
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &pipeline.blending_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(self.backdrop),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(self.layers),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.buffers.layers.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.buffers.masks.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: self.buffers.blends.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: self.buffers.opacities.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::TextureView(self.paper),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: wgpu::BindingResource::TextureView(self.lut),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: self.adjustments.as_entire_binding(),
+                    },
+                ],
+                label: Some("mixing_bind_group"),
+            });
+
+        // The shader can be swapped while a composite is recorded.
+        let render_pipeline = pipeline.render_pipeline.load_full();
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[
+                // background color clear pass
+                Some(wgpu::RenderPassColorAttachment {
+                    view: self.target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.clear),
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+                // compositing pass
+                Some(wgpu::RenderPassColorAttachment {
+                    view: self.target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        // Finish and set the render pass's binding groups and data
+        pass.set_pipeline(&render_pipeline);
+        // We use push constants for the binding count and final pass options.
+        pass.set_push_constants(
+            wgpu::ShaderStages::FRAGMENT,
+            0,
+            bytemuck::bytes_of(&self.push_constants),
+        );
+        pass.set_bind_group(0, &pipeline.constant_bind_group, &[]);
+        pass.set_bind_group(1, &blending_bind_group, &[]);
+        pass.set_vertex_buffer(0, data.vertex_buffer.slice(..));
+        pass.set_index_buffer(data.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..CompositorData::INDICES.len() as u32, 0, 0..1);
+    }
+}
+
+pub struct CompositorPipeline {
+    /// Bound in place of the paper texture when no paper is applied.
+    blank_paper: GpuTexture,
+    /// Bound in place of the lookup table when no table is applied.
+    blank_lut: LutTexture,
+    constant_bind_group: wgpu::BindGroup,
+    blending_bind_group_layout: wgpu::BindGroupLayout,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    /// Swapped out when the shader is reloaded.
+    render_pipeline: ArcSwap<wgpu::RenderPipeline>,
+}
+
+impl CompositorPipeline {
+    /// Create a new compositor pipeline.
+    pub fn new(dev: &GpuHandle) -> Self {
+        let device = &dev.device;
+
+        // This bind group only binds the sampler, which is a constant
+        // through out all rendering passes.
+        let (constant_bind_group_layout, constant_bind_group) = {
+            let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("texture_bind_group_layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                }],
+            });
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("constant_bind_group"),
+                layout: &layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                }],
+            });
+
+            (layout, bind_group)
+        };
+
+        // This bind group changes per composition run.
+        let blending_bind_group_layout = {
+            const fn fragment_bgl_buffer_ro_entry(
+                binding: u32,
+                count: Option<NonZeroU32>,
+            ) -> wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count,
+                }
+            }
+
+            const fn fragment_bgl_tex_entry(
+                binding: u32,
+                count: Option<NonZeroU32>,
+            ) -> wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count,
+                }
+            }
+
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("blending_group_layout"),
+                entries: &[
+                    // composite
+                    fragment_bgl_tex_entry(0, None),
+                    // textures
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                    // layers
+                    fragment_bgl_buffer_ro_entry(2, None),
+                    // masks
+                    fragment_bgl_buffer_ro_entry(3, None),
+                    // blends
+                    fragment_bgl_buffer_ro_entry(4, None),
+                    // opacities
+                    fragment_bgl_buffer_ro_entry(5, None),
+                    // paper
+                    fragment_bgl_tex_entry(6, None),
+                    // lut
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                    // adjustments
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        };
+
+        // Loads the shader and creates the render pipeline.
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("render_pipeline_layout"),
+                bind_group_layouts: &[&constant_bind_group_layout, &blending_bind_group_layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::FRAGMENT,
+                    range: 0..std::mem::size_of::<PushConstants>() as u32,
+                }],
+            });
+        let shader =
+            device.create_shader_module(shader_load("shader.wgsl", include_str!("shader.wgsl")));
+        let render_pipeline =
+            Self::create_render_pipeline(device, &render_pipeline_layout, &shader);
+
+        Self {
+            blank_paper: GpuTexture::empty_layers(dev, 1, 1, 1, GpuTexture::LAYER_USAGE),
+            blank_lut: LutTexture::blank(dev),
+            constant_bind_group,
+            blending_bind_group_layout,
+            render_pipeline_layout,
+            render_pipeline: ArcSwap::from_pointee(render_pipeline),
+        }
+    }
+
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("render_pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[VertexInput::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[
+                    // Used to clear a background color
+                    Some(wgpu::ColorTargetState {
+                        format: tex::TEX_FORMAT,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    // Used to blend the shader
+                    Some(wgpu::ColorTargetState {
+                        format: tex::TEX_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Replace the compositing shader with the given WGSL source. The last
+    /// working shader is kept if the source fails to validate.
+    pub async fn reload_shader(&self, dev: &GpuHandle, source: &str) -> Result<(), wgpu::Error> {
+        dev.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = dev
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("shader.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let pipeline =
+            Self::create_render_pipeline(&dev.device, &self.render_pipeline_layout, &shader);
+        match dev.device.pop_error_scope().await {
+            Some(err) => Err(err),
+            None => {
+                self.render_pipeline.store(Arc::new(pipeline));
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Load a shader from the `src` directory of this crate, given its embedded
+/// source.
+fn shader_load(
+    name: &'static str,
+    #[cfg_attr(debug_assertions, allow(unused_variables))] embedded: &'static str,
+) -> wgpu::ShaderModuleDescriptor<'static> {
+    // In release mode, the final binary includes the file directly so that
+    // the binary does not rely on the shader file being at a specific location.
+    #[cfg(not(debug_assertions))]
+    {
+        wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(embedded.into()),
+        }
+    }
+    // In debug mode, this reads directly from a file so that recompilation
+    // will not be necessary in the event that only the shader file changes.
+    #[cfg(debug_assertions)]
+    {
+        wgpu::ShaderModuleDescriptor {
+            label: Some("Dynamically loaded shader module"),
+            source: wgpu::ShaderSource::Wgsl({
+                use std::fs::OpenOptions;
+                use std::io::Read;
+                let mut file = OpenOptions::new()
+                    .read(true)
+                    .open(
+                        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                            .join("src")
+                            .join(name),
+                    )
+                    .unwrap();
+
+                let mut buf = String::new();
+                file.read_to_string(&mut buf).unwrap();
+                buf.into()
+            }),
+        }
+    }
+}
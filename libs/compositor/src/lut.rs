@@ -0,0 +1,232 @@
+use super::dev::GpuHandle;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LutError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("line {0} is not valid in a .cube file")]
+    Syntax(usize),
+    #[error("1D lookup tables are not supported")]
+    OneDimensional,
+    #[error("custom input domains are not supported")]
+    Domain,
+    #[error("missing LUT_3D_SIZE")]
+    MissingSize,
+    #[error("lookup table size {0} is not between 2 and {max}", max = CubeLut::MAX_SIZE)]
+    UnsupportedSize(u32),
+    #[error("expected {expected} table entries but found {found}")]
+    EntryCount { expected: usize, found: usize },
+}
+
+/// 3D color lookup table read from an Adobe/Resolve `.cube` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    /// Number of entries along each axis.
+    pub size: u32,
+    /// Output colors with red changing fastest, then green, then blue.
+    table: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    pub const MAX_SIZE: u32 = 256;
+
+    pub fn open(path: &Path) -> Result<Self, LutError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, LutError> {
+        let mut title = None;
+        let mut size = None;
+        let mut table = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let syntax = || LutError::Syntax(index + 1);
+            let (keyword, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match keyword {
+                "TITLE" => title = Some(value.trim_matches('"').to_string()),
+                "LUT_1D_SIZE" => return Err(LutError::OneDimensional),
+                "LUT_3D_SIZE" => {
+                    let n = value.parse::<u32>().map_err(|_| syntax())?;
+                    if !(2..=Self::MAX_SIZE).contains(&n) {
+                        return Err(LutError::UnsupportedSize(n));
+                    }
+                    size = Some(n);
+                }
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let expected = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    let domain = parse_triple(value).ok_or_else(syntax)?;
+                    if domain != [expected; 3] {
+                        return Err(LutError::Domain);
+                    }
+                }
+                // Other keywords, such as LUT_3D_INPUT_RANGE, do not
+                // change how the table applies to display colors.
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+                _ => table.push(parse_triple(line).ok_or_else(syntax)?),
+            }
+        }
+
+        let size = size.ok_or(LutError::MissingSize)?;
+        let expected = (size as usize).pow(3);
+        if table.len() != expected {
+            return Err(LutError::EntryCount {
+                expected,
+                found: table.len(),
+            });
+        }
+        Ok(Self { title, size, table })
+    }
+
+    /// Upload the table as a 3D texture indexed by the red, green and blue
+    /// input along its width, height and depth.
+    pub fn upload(&self, dev: &GpuHandle) -> LutTexture {
+        let data = self
+            .table
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .collect::<Vec<f32>>();
+        LutTexture::new(dev, self.size, bytemuck::cast_slice(&data))
+    }
+}
+
+/// Three whitespace separated numbers.
+fn parse_triple(text: &str) -> Option<[f32; 3]> {
+    let mut values = text.split_whitespace().map(|v| v.parse::<f32>().ok());
+    let triple = [values.next()??, values.next()??, values.next()??];
+    values.next().is_none().then_some(triple)
+}
+
+/// Lookup table on the GPU that grades the final composite.
+#[derive(Debug)]
+pub struct LutTexture {
+    texture: wgpu::Texture,
+}
+
+impl LutTexture {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+    /// Table of `size` entries along each axis, given as RGBA floats.
+    fn new(dev: &GpuHandle, size: u32, data: &[u8]) -> Self {
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
+        };
+        let texture = dev.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("lut"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        dev.queue.write_texture(
+            texture.as_image_copy(),
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * size),
+                rows_per_image: Some(size),
+            },
+            extent,
+        );
+        Self { texture }
+    }
+
+    /// Bound in place of a lookup table when none is applied.
+    pub(super) fn blank(dev: &GpuHandle) -> Self {
+        Self::new(dev, 1, bytemuck::cast_slice(&[0.0f32; 4]))
+    }
+
+    pub(super) fn create_view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CubeLut, LutError};
+
+    /// Identity table of size 2, after the given header.
+    fn identity(header: &str) -> String {
+        let mut text = header.to_string();
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    text += &format!("{r} {g} {b}\n");
+                }
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn tables_parse_with_red_changing_fastest() {
+        let lut = CubeLut::parse(&identity(
+            "# comment\nTITLE \"Identity\"\nLUT_3D_SIZE 2\n\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 1 1\nLUT_3D_INPUT_RANGE 0 1\n",
+        ))
+        .unwrap();
+        assert_eq!(lut.title.as_deref(), Some("Identity"));
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.table[1], [1.0, 0.0, 0.0]);
+        assert_eq!(lut.table[2], [0.0, 1.0, 0.0]);
+        assert_eq!(lut.table[4], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn invalid_tables_fail() {
+        let cases = [
+            (
+                identity("LUT_3D_SIZE two\n"),
+                "line 1 is not valid in a .cube file",
+            ),
+            (
+                identity("LUT_1D_SIZE 2\n"),
+                "1D lookup tables are not supported",
+            ),
+            (
+                identity("LUT_3D_SIZE 2\nDOMAIN_MAX 2 2 2\n"),
+                "custom input domains are not supported",
+            ),
+            (identity(""), "missing LUT_3D_SIZE"),
+            (
+                identity("LUT_3D_SIZE 1\n"),
+                "lookup table size 1 is not between 2 and 256",
+            ),
+            (
+                identity("LUT_3D_SIZE 257\n"),
+                "lookup table size 257 is not between 2 and 256",
+            ),
+            (
+                identity("LUT_3D_SIZE 3\n"),
+                "expected 27 table entries but found 8",
+            ),
+            (
+                "LUT_3D_SIZE 2\n0 0\n".to_string(),
+                "line 2 is not valid in a .cube file",
+            ),
+            (
+                "LUT_3D_SIZE 2\n0 0 0 0\n".to_string(),
+                "line 2 is not valid in a .cube file",
+            ),
+        ];
+        for (text, message) in cases {
+            let err = CubeLut::parse(&text).unwrap_err();
+            assert_eq!(err.to_string(), message, "{text}");
+        }
+        assert!(matches!(
+            CubeLut::parse("LUT_3D_SIZE 2\n-1 x 0\n"),
+            Err(LutError::Syntax(2))
+        ));
+    }
+}
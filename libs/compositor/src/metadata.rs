@@ -0,0 +1,669 @@
+use super::color::ColorSpace;
+use super::profile::{ColorProfile, ExportColorSpace};
+use image::error::{EncodingError, ImageFormatHint};
+use image::{ImageBuffer, ImageError, ImageFormat, ImageResult, PixelWithColorType};
+use std::path::Path;
+
+/// Document information written into exported PNG and JPEG images.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Name and version of the app that exported the image, filled in by
+    /// the app rather than the libraries it is built on.
+    pub software: Option<String>,
+    /// Print resolution in dots per inch.
+    pub dpi: Option<f32>,
+    /// ICC profile embedded in PNG, JPEG and TIFF color images.
+    pub profile: Option<ColorProfile>,
+    /// Color spaces that the colors are converted from and to, by the last
+    /// post-processing pass of color exports.
+    pub conversion: Option<(ColorSpace, ColorSpace)>,
+}
+
+impl ExportMetadata {
+    /// Convert and tag the colors as chosen, starting from the document's
+    /// profile.
+    pub fn with_color_space(mut self, space: ExportColorSpace) -> Self {
+        (self.conversion, self.profile) = space.resolve(self.profile.as_ref());
+        self
+    }
+
+    /// Profile to embed in an image with the given number of channels.
+    /// Grayscale images such as mattes are never tagged with RGB profiles.
+    fn profile_for(&self, channels: u8) -> Option<&[u8]> {
+        self.profile
+            .as_ref()
+            .filter(|_| channels >= 3)
+            .map(|profile| profile.icc.as_slice())
+    }
+}
+
+/// Save an image, embedding the metadata as PNG text chunks and XMP or as
+/// JPEG EXIF, and the ICC profile in PNG, JPEG and TIFF images. Other
+/// formats are saved without metadata.
+pub fn save<P>(
+    buffer: &ImageBuffer<P, Vec<u8>>,
+    path: &Path,
+    metadata: &ExportMetadata,
+) -> ImageResult<()>
+where
+    P: PixelWithColorType<Subpixel = u8>,
+{
+    match ImageFormat::from_path(path)? {
+        ImageFormat::Png => save_png(buffer, path, metadata),
+        ImageFormat::Jpeg => save_jpeg(buffer, path, metadata),
+        ImageFormat::Tiff => match metadata.profile_for(P::CHANNEL_COUNT) {
+            Some(profile) => save_tiff(buffer, path, profile),
+            None => buffer.save(path),
+        },
+        _ => buffer.save(path),
+    }
+}
+
+fn save_png<P>(
+    buffer: &ImageBuffer<P, Vec<u8>>,
+    path: &Path,
+    metadata: &ExportMetadata,
+) -> ImageResult<()>
+where
+    P: PixelWithColorType<Subpixel = u8>,
+{
+    let png_error = |err: png::EncodingError| match err {
+        png::EncodingError::IoError(err) => ImageError::IoError(err),
+        err => ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            err,
+        )),
+    };
+
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, buffer.width(), buffer.height());
+    encoder.set_color(match P::CHANNEL_COUNT {
+        1 => png::ColorType::Grayscale,
+        2 => png::ColorType::GrayscaleAlpha,
+        3 => png::ColorType::Rgb,
+        _ => png::ColorType::Rgba,
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(dpi) = metadata.dpi {
+        // PNG stores the resolution in pixels per meter.
+        let ppm = (dpi / 0.0254).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: ppm,
+            yppu: ppm,
+            unit: png::Unit::Meter,
+        }));
+    }
+
+    // tEXt is limited to Latin-1, so non-ASCII text only goes in iTXt chunks.
+    let software = metadata.software.as_deref();
+    let comment = software.map(|software| format!("Created with {software}"));
+    let mut text = Vec::new();
+    text.extend(software.map(|software| ("Software", software)));
+    text.extend(comment.as_deref().map(|comment| ("Comment", comment)));
+    text.extend(metadata.title.as_deref().map(|title| ("Title", title)));
+    text.extend(metadata.author.as_deref().map(|author| ("Author", author)));
+    for (keyword, value) in text {
+        if value.is_ascii() {
+            encoder.add_text_chunk(keyword.to_string(), value.to_string())
+        } else {
+            encoder.add_itxt_chunk(keyword.to_string(), value.to_string())
+        }
+        .map_err(png_error)?;
+    }
+    encoder
+        .add_itxt_chunk("XML:com.adobe.xmp".to_string(), xmp_packet(metadata))
+        .map_err(png_error)?;
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    if let Some(profile) = metadata.profile_for(P::CHANNEL_COUNT) {
+        // Profile name, compression method and the zlib compressed profile.
+        let chunk = [b"ICC Profile\0\0".as_slice(), &zlib_stored(profile)].concat();
+        writer
+            .write_chunk(png::chunk::iCCP, &chunk)
+            .map_err(png_error)?;
+    }
+    writer
+        .write_image_data(buffer.as_raw())
+        .map_err(png_error)?;
+    writer.finish().map_err(png_error)
+}
+
+fn save_jpeg<P>(
+    buffer: &ImageBuffer<P, Vec<u8>>,
+    path: &Path,
+    metadata: &ExportMetadata,
+) -> ImageResult<()>
+where
+    P: PixelWithColorType<Subpixel = u8>,
+{
+    let mut bytes = Vec::new();
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut bytes);
+    if let Some(dpi) = metadata.dpi {
+        encoder.set_pixel_density(image::codecs::jpeg::PixelDensity::dpi(dpi.round() as u16));
+    }
+    encoder.encode_image(buffer)?;
+
+    // The encoder starts with SOI and the JFIF APP0 segment, the EXIF
+    // APP1 segment and the comment go right after them.
+    let at = match bytes[2..6] {
+        [0xFF, 0xE0, hi, lo] => 4 + usize::from(u16::from_be_bytes([hi, lo])),
+        _ => 2,
+    };
+    let mut entries = vec![(0xE1, exif(metadata))];
+    if let Some(profile) = metadata.profile_for(P::CHANNEL_COUNT) {
+        // Profiles are split over numbered APP2 segments.
+        let chunks = profile.chunks(ICC_SEGMENT_LEN).collect::<Vec<_>>();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let data = [
+                b"ICC_PROFILE\0",
+                &[i as u8 + 1, chunks.len() as u8][..],
+                chunk,
+            ]
+            .concat();
+            entries.push((0xE2, data));
+        }
+    }
+    if let Some(software) = &metadata.software {
+        entries.push((0xFE, format!("Created with {software}").into_bytes()));
+    }
+    let mut segments = Vec::new();
+    for (marker, data) in entries {
+        // Segment lengths include the two length bytes.
+        let Ok(len) = u16::try_from(data.len() + 2) else {
+            tracing::warn!("Skipping JPEG segment {marker:#X} that is too long");
+            continue;
+        };
+        segments.extend_from_slice(&[0xFF, marker]);
+        segments.extend_from_slice(&len.to_be_bytes());
+        segments.extend_from_slice(&data);
+    }
+    bytes.splice(at..at, segments);
+
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Largest part of an ICC profile in a JPEG APP2 segment, after the
+/// segment length and the `ICC_PROFILE` header.
+const ICC_SEGMENT_LEN: usize = 65535 - 2 - 14;
+
+fn save_tiff<P>(buffer: &ImageBuffer<P, Vec<u8>>, path: &Path, profile: &[u8]) -> ImageResult<()>
+where
+    P: PixelWithColorType<Subpixel = u8>,
+{
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    fn write<C, W>(
+        encoder: &mut TiffEncoder<W>,
+        buffer: &[u8],
+        (width, height): (u32, u32),
+        profile: &[u8],
+    ) -> tiff::TiffResult<()>
+    where
+        C: colortype::ColorType<Inner = u8>,
+        W: std::io::Write + std::io::Seek,
+    {
+        let mut image = encoder.new_image::<C>(width, height)?;
+        // InterColorProfile
+        image
+            .encoder()
+            .write_tag(tiff::tags::Tag::Unknown(34675), profile)?;
+        image.write_data(buffer)
+    }
+
+    let tiff_error = |err: tiff::TiffError| match err {
+        tiff::TiffError::IoError(err) => ImageError::IoError(err),
+        err => ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Tiff),
+            err,
+        )),
+    };
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = TiffEncoder::new(file).map_err(tiff_error)?;
+    let dimensions = buffer.dimensions();
+    match P::CHANNEL_COUNT {
+        3 => write::<colortype::RGB8, _>(&mut encoder, buffer.as_raw(), dimensions, profile),
+        _ => write::<colortype::RGBA8, _>(&mut encoder, buffer.as_raw(), dimensions, profile),
+    }
+    .map_err(tiff_error)
+}
+
+/// Wrap data in a zlib stream of uncompressed blocks, for the PNG chunks
+/// that have to be compressed. Profiles are small enough for this.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(u8::from(blocks.peek().is_none()));
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    // Adler-32 checksum of the data.
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + u32::from(byte)) % 65521;
+        (a, (b + a) % 65521)
+    });
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    out
+}
+
+/// Big endian EXIF payload with a single IFD holding the metadata.
+fn exif(metadata: &ExportMetadata) -> Vec<u8> {
+    const ASCII: u16 = 2;
+    const SHORT: u16 = 3;
+    const RATIONAL: u16 = 5;
+
+    enum Value {
+        Ascii(Vec<u8>),
+        Short(u16),
+        Rational(u32, u32),
+    }
+
+    let ascii = |text: &str| Value::Ascii([text.as_bytes(), &[0]].concat());
+    // Entries have to be sorted by tag.
+    let mut entries = Vec::new();
+    if let Some(title) = &metadata.title {
+        entries.push((0x010E, ascii(title))); // ImageDescription
+    }
+    if let Some(dpi) = metadata.dpi {
+        let dpi = (dpi * 100.0).round() as u32;
+        entries.push((0x011A, Value::Rational(dpi, 100))); // XResolution
+        entries.push((0x011B, Value::Rational(dpi, 100))); // YResolution
+        entries.push((0x0128, Value::Short(2))); // ResolutionUnit, inches
+    }
+    if let Some(software) = &metadata.software {
+        entries.push((0x0131, ascii(software))); // Software
+    }
+    if let Some(author) = &metadata.author {
+        entries.push((0x013B, ascii(author))); // Artist
+    }
+
+    let mut out = b"Exif\0\0".to_vec();
+    out.extend_from_slice(b"MM\0\x2A");
+    out.extend_from_slice(&8u32.to_be_bytes());
+
+    // Values that do not fit in four bytes go after the IFD.
+    let ifd_len = 2 + entries.len() * 12 + 4;
+    let mut data = Vec::new();
+    let mut ifd = (entries.len() as u16).to_be_bytes().to_vec();
+    for (tag, value) in entries {
+        let offset = (8 + ifd_len + data.len()) as u32;
+        ifd.extend_from_slice(&u16::to_be_bytes(tag));
+        match value {
+            Value::Ascii(bytes) => {
+                ifd.extend_from_slice(&ASCII.to_be_bytes());
+                ifd.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+                if bytes.len() <= 4 {
+                    let mut inline = [0; 4];
+                    inline[..bytes.len()].copy_from_slice(&bytes);
+                    ifd.extend_from_slice(&inline);
+                } else {
+                    ifd.extend_from_slice(&offset.to_be_bytes());
+                    data.extend_from_slice(&bytes);
+                    // Values start on word boundaries.
+                    if data.len() % 2 == 1 {
+                        data.push(0);
+                    }
+                }
+            }
+            Value::Short(value) => {
+                ifd.extend_from_slice(&SHORT.to_be_bytes());
+                ifd.extend_from_slice(&1u32.to_be_bytes());
+                ifd.extend_from_slice(&value.to_be_bytes());
+                ifd.extend_from_slice(&[0, 0]);
+            }
+            Value::Rational(numerator, denominator) => {
+                ifd.extend_from_slice(&RATIONAL.to_be_bytes());
+                ifd.extend_from_slice(&1u32.to_be_bytes());
+                ifd.extend_from_slice(&offset.to_be_bytes());
+                data.extend_from_slice(&numerator.to_be_bytes());
+                data.extend_from_slice(&denominator.to_be_bytes());
+            }
+        }
+    }
+    // No further IFDs.
+    ifd.extend_from_slice(&0u32.to_be_bytes());
+
+    out.extend_from_slice(&ifd);
+    out.extend_from_slice(&data);
+    out
+}
+
+/// XMP packet with the Dublin Core title and creator.
+fn xmp_packet(metadata: &ExportMetadata) -> String {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    let mut properties = String::new();
+    if let Some(software) = &metadata.software {
+        properties.push_str(&format!(
+            "<xmp:CreatorTool>{}</xmp:CreatorTool>",
+            escape(software)
+        ));
+    }
+    if let Some(title) = &metadata.title {
+        properties.push_str(&format!(
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+            escape(title)
+        ));
+    }
+    if let Some(author) = &metadata.author {
+        properties.push_str(&format!(
+            "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            escape(author)
+        ));
+    }
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\"",
+            " xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+            " xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">",
+            "{}",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>",
+            "<?xpacket end=\"w\"?>"
+        ),
+        properties
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Software as the app names itself.
+    const SOFTWARE: &str = "Silicate 9.9.9";
+
+    /// Value of an EXIF entry, read back from the payload.
+    #[derive(Debug, PartialEq)]
+    enum Entry {
+        Ascii(String),
+        Short(u16),
+        Rational(u32, u32),
+    }
+
+    /// Tags and values of the single IFD of an EXIF payload.
+    fn read_exif(exif: &[u8]) -> Vec<(u16, Entry)> {
+        let tiff = exif.strip_prefix(b"Exif\0\0").unwrap();
+        assert_eq!(&tiff[..4], b"MM\0\x2A");
+        let u16_at = |at: usize| u16::from_be_bytes([tiff[at], tiff[at + 1]]);
+        let u32_at = |at: usize| u32::from_be_bytes(tiff[at..at + 4].try_into().unwrap());
+        let ifd = u32_at(4) as usize;
+        let count = u16_at(ifd) as usize;
+        assert_eq!(u32_at(ifd + 2 + count * 12), 0, "no further IFDs");
+        (0..count)
+            .map(|i| {
+                let entry = ifd + 2 + i * 12;
+                let len = u32_at(entry + 4) as usize;
+                let value = match u16_at(entry + 2) {
+                    2 => {
+                        let at = if len <= 4 {
+                            entry + 8
+                        } else {
+                            u32_at(entry + 8) as usize
+                        };
+                        let text = tiff[at..at + len].strip_suffix(&[0]).unwrap();
+                        Entry::Ascii(String::from_utf8(text.to_vec()).unwrap())
+                    }
+                    3 => Entry::Short(u16_at(entry + 8)),
+                    5 => {
+                        let at = u32_at(entry + 8) as usize;
+                        assert_eq!(at % 2, 0, "values start on word boundaries");
+                        Entry::Rational(u32_at(at), u32_at(at + 4))
+                    }
+                    kind => panic!("unexpected type {kind}"),
+                };
+                (u16_at(entry), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exif_holds_the_metadata_sorted_by_tag() {
+        let software = || (0x0131, Entry::Ascii(SOFTWARE.to_string()));
+        for (metadata, expected) in [
+            (ExportMetadata::default(), vec![]),
+            (
+                ExportMetadata {
+                    software: Some(SOFTWARE.into()),
+                    ..Default::default()
+                },
+                vec![software()],
+            ),
+            (
+                ExportMetadata {
+                    title: Some("Sunset".into()),
+                    author: Some("Avery".into()),
+                    software: Some(SOFTWARE.into()),
+                    dpi: Some(300.0),
+                    ..Default::default()
+                },
+                vec![
+                    (0x010E, Entry::Ascii("Sunset".into())),
+                    (0x011A, Entry::Rational(30000, 100)),
+                    (0x011B, Entry::Rational(30000, 100)),
+                    (0x0128, Entry::Short(2)),
+                    software(),
+                    (0x013B, Entry::Ascii("Avery".into())),
+                ],
+            ),
+            // Short text is stored inline, odd lengths are padded.
+            (
+                ExportMetadata {
+                    title: Some("Sun".into()),
+                    author: Some("Odd".into()),
+                    software: Some(SOFTWARE.into()),
+                    dpi: Some(72.5),
+                    ..Default::default()
+                },
+                vec![
+                    (0x010E, Entry::Ascii("Sun".into())),
+                    (0x011A, Entry::Rational(7250, 100)),
+                    (0x011B, Entry::Rational(7250, 100)),
+                    (0x0128, Entry::Short(2)),
+                    software(),
+                    (0x013B, Entry::Ascii("Odd".into())),
+                ],
+            ),
+            (
+                ExportMetadata {
+                    author: Some("Five!".into()),
+                    ..Default::default()
+                },
+                vec![(0x013B, Entry::Ascii("Five!".into()))],
+            ),
+        ] {
+            assert_eq!(read_exif(&exif(&metadata)), expected, "{metadata:?}");
+        }
+    }
+
+    #[test]
+    fn xmp_escapes_the_title_and_creator() {
+        for (title, author, expected, unexpected) in [
+            (
+                None,
+                None,
+                vec!["<xmp:CreatorTool>Silicate 9.9.9</xmp:CreatorTool>"],
+                vec!["dc:title", "dc:creator"],
+            ),
+            (
+                Some("Cats & <Dogs>"),
+                None,
+                vec!["<rdf:li xml:lang=\"x-default\">Cats &amp; &lt;Dogs&gt;</rdf:li>"],
+                vec!["dc:creator", "Cats & <"],
+            ),
+            (
+                None,
+                Some("\"Kit\""),
+                vec!["<dc:creator><rdf:Seq><rdf:li>&quot;Kit&quot;</rdf:li></rdf:Seq>"],
+                vec!["dc:title"],
+            ),
+        ] {
+            let packet = xmp_packet(&ExportMetadata {
+                title: title.map(String::from),
+                author: author.map(String::from),
+                software: Some(SOFTWARE.into()),
+                ..Default::default()
+            });
+            assert!(packet.starts_with("<?xpacket begin=\"\u{FEFF}\""));
+            assert!(packet.ends_with("<?xpacket end=\"w\"?>"));
+            for text in expected {
+                assert!(packet.contains(text), "{text} in {packet}");
+            }
+            for text in unexpected {
+                assert!(!packet.contains(text), "{text} in {packet}");
+            }
+        }
+    }
+
+    #[test]
+    fn software_is_only_named_when_given() {
+        let packet = xmp_packet(&ExportMetadata::default());
+        assert!(!packet.contains("CreatorTool"), "{packet}");
+    }
+
+    #[test]
+    fn zlib_streams_hold_stored_blocks_and_the_adler_checksum() {
+        let stream = zlib_stored(b"Wikipedia");
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+        assert_eq!(&stream[2..7], [1, 9, 0, 0xF6, 0xFF]);
+        assert_eq!(&stream[7..16], b"Wikipedia");
+        assert_eq!(&stream[16..], 0x11E6_0398u32.to_be_bytes());
+
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1]
+        );
+
+        // Blocks hold at most 65535 bytes, and only the last is final.
+        let data = vec![7; 70_000];
+        let stream = zlib_stored(&data);
+        assert_eq!(&stream[2..7], [0, 0xFF, 0xFF, 0, 0]);
+        let second = 7 + 65535;
+        let len = (70_000 - 65535) as u16;
+        assert_eq!(stream[second], 1);
+        assert_eq!(&stream[second + 1..second + 3], len.to_le_bytes());
+        assert_eq!(&stream[second + 3..second + 5], (!len).to_le_bytes());
+        assert_eq!(stream.len(), second + 5 + len as usize + 4);
+    }
+
+    /// Path in a temporary folder for an exported image.
+    fn export_path(name: &str) -> std::path::PathBuf {
+        let folder = std::env::temp_dir().join(format!("silicate-metadata-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        folder.join(name)
+    }
+
+    fn with_profile(icc: Vec<u8>) -> ExportMetadata {
+        ExportMetadata {
+            title: Some("Sunset".into()),
+            software: Some(SOFTWARE.into()),
+            dpi: Some(300.0),
+            profile: Some(ColorProfile { icc }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn png_embeds_the_profile_and_text() {
+        let icc = ColorProfile::of(ColorSpace::DisplayP3).icc;
+        let path = export_path("profile.png");
+        let color = image::RgbImage::from_pixel(2, 2, image::Rgb([10, 20, 30]));
+        save(&color, &path, &with_profile(icc.clone())).unwrap();
+
+        let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.icc_profile.as_deref(), Some(icc.as_slice()));
+        assert_eq!(info.pixel_dims.unwrap().xppu, 11811);
+        let text = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.as_str(), chunk.text.as_str()))
+            .collect::<Vec<_>>();
+        assert!(text.contains(&("Title", "Sunset")));
+        assert!(text.contains(&("Software", SOFTWARE)));
+        assert!(text.contains(&("Comment", "Created with Silicate 9.9.9")));
+
+        // Mattes are never tagged with the color profile.
+        let matte = image::GrayImage::from_pixel(2, 2, image::Luma([128]));
+        save(&matte, &path, &with_profile(icc)).unwrap();
+        let reader = png::Decoder::new(std::fs::File::open(&path).unwrap())
+            .read_info()
+            .unwrap();
+        assert!(reader.info().icc_profile.is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn jpeg_splits_large_profiles_over_app2_segments() {
+        use image::ImageDecoder;
+
+        // Large enough to need two segments.
+        let icc = (0..ICC_SEGMENT_LEN + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let path = export_path("profile.jpg");
+        let color = image::RgbImage::from_pixel(2, 2, image::Rgb([10, 20, 30]));
+        save(&color, &path, &with_profile(icc.clone())).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let mut at = 2;
+        let mut markers = Vec::new();
+        let mut parts = Vec::new();
+        // Walk the segments before the start of the scan.
+        while bytes[at + 1] != 0xDA {
+            let marker = bytes[at + 1];
+            let len = usize::from(u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]));
+            let data = &bytes[at + 4..at + 2 + len];
+            markers.push(marker);
+            if marker == 0xE2 {
+                let rest = data.strip_prefix(b"ICC_PROFILE\0").unwrap();
+                parts.push((rest[0], rest[1], rest[2..].to_vec()));
+            }
+            at += 2 + len;
+        }
+        assert_eq!(&markers[..5], [0xE0, 0xE1, 0xE2, 0xE2, 0xFE]);
+        assert_eq!((parts[0].0, parts[0].1), (1, 2));
+        assert_eq!((parts[1].0, parts[1].1), (2, 2));
+        assert_eq!(parts[0].2.len(), ICC_SEGMENT_LEN);
+        assert_eq!([parts[0].2.as_slice(), &parts[1].2].concat(), icc);
+
+        let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+        let mut decoder = image::codecs::jpeg::JpegDecoder::new(file).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(icc));
+        assert_eq!(decoder.dimensions(), (2, 2));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tiff_holds_the_profile_in_its_tag() {
+        let icc = ColorProfile::of(ColorSpace::Srgb).icc;
+        let path = export_path("profile.tiff");
+        let color = image::RgbaImage::from_pixel(3, 2, image::Rgba([10, 20, 30, 40]));
+        save(&color, &path, &with_profile(icc.clone())).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mut decoder = tiff::decoder::Decoder::new(file).unwrap();
+        // The decoder widens bytes to larger integers.
+        let tag = decoder
+            .get_tag_u64_vec(tiff::tags::Tag::Unknown(34675))
+            .unwrap();
+        assert_eq!(tag, icc.iter().map(|&b| u64::from(b)).collect::<Vec<_>>());
+        assert_eq!(image::open(&path).unwrap().into_rgba8(), color);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,511 @@
+use super::color::{self, ColorSpace};
+use super::dev::GpuHandle;
+use super::tex::{self, GpuTexture, Region, TiledTexture};
+use wgpu::util::DeviceExt;
+
+/// Step of the post-processing chain that color exports go through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostStep {
+    /// Unsharp mask, adding `amount` times the difference between the image
+    /// and a blur of `radius` pixels.
+    Sharpen { amount: f32, radius: u32 },
+    /// Resize so that the longer side is this many pixels long.
+    Resize { long_edge: u32 },
+    /// Overlay of the watermark image, `scale` times as wide as the image.
+    Watermark {
+        opacity: f32,
+        scale: f32,
+        placement: MarkPlacement,
+    },
+}
+
+impl PostStep {
+    pub const MAX_RADIUS: u32 = 8;
+
+    /// Each kind of step, with its default settings.
+    pub fn all() -> &'static [PostStep] {
+        &[
+            Self::Sharpen {
+                amount: 0.5,
+                radius: 2,
+            },
+            Self::Resize { long_edge: 2048 },
+            Self::Watermark {
+                opacity: 0.5,
+                scale: 0.25,
+                placement: MarkPlacement::BottomRight,
+            },
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sharpen { .. } => "Sharpen",
+            Self::Resize { .. } => "Resize",
+            Self::Watermark { .. } => "Watermark",
+        }
+    }
+}
+
+/// Where the watermark is drawn on the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkPlacement {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl MarkPlacement {
+    pub const fn all() -> &'static [Self] {
+        &[
+            Self::TopLeft,
+            Self::TopRight,
+            Self::BottomLeft,
+            Self::BottomRight,
+            Self::Center,
+        ]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::TopLeft => "Top Left",
+            Self::TopRight => "Top Right",
+            Self::BottomLeft => "Bottom Left",
+            Self::BottomRight => "Bottom Right",
+            Self::Center => "Center",
+        }
+    }
+
+    /// Position of a watermark of the given size in an image, `inset`
+    /// pixels away from the edges it is placed along.
+    fn origin(self, image: [f32; 2], mark: [f32; 2], inset: f32) -> [f32; 2] {
+        let start = inset;
+        let end = |axis: usize| image[axis] - inset - mark[axis];
+        let center = |axis: usize| (image[axis] - mark[axis]) / 2.0;
+        match self {
+            Self::TopLeft => [start, start],
+            Self::TopRight => [end(0), start],
+            Self::BottomLeft => [start, end(1)],
+            Self::BottomRight => [end(0), end(1)],
+            Self::Center => [center(0), center(1)],
+        }
+    }
+}
+
+/// Parameters of a pass as laid out in the uniform buffer of `post.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+struct PostUniform {
+    scale: [f32; 2],
+    out_origin: [f32; 2],
+    src_offset: [f32; 2],
+    mark_origin: [f32; 2],
+    mark_size: [f32; 2],
+    amount: f32,
+    radius: i32,
+    opacity: f32,
+    _padding: [u32; 3],
+    /// Columns of the conversion matrix, padded to four floats.
+    matrix: [[f32; 4]; 3],
+}
+
+/// One pass of the chain over the whole image.
+struct Pass<'a> {
+    pipeline: &'a wgpu::RenderPipeline,
+    /// Size of the output.
+    size: (u32, u32),
+    /// Source pixels read around the ones under an output pixel.
+    margin: u32,
+    uniform: PostUniform,
+    mark: Option<&'a GpuTexture>,
+}
+
+/// Render pipelines of the post-processing passes.
+pub struct PostPipeline {
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Bound in place of the watermark when it is not drawn.
+    blank_mark: GpuTexture,
+    sharpen: wgpu::RenderPipeline,
+    resize: wgpu::RenderPipeline,
+    convert: wgpu::RenderPipeline,
+    watermark: wgpu::RenderPipeline,
+}
+
+impl PostPipeline {
+    /// Distance of the watermark from the edges, relative to the shorter
+    /// side of the image.
+    const MARK_INSET: f32 = 0.02;
+
+    pub fn new(dev: &GpuHandle) -> Self {
+        let device = &dev.device;
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_bind_group_layout"),
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader =
+            device.create_shader_module(super::shader_load("post.wgsl", include_str!("post.wgsl")));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: tex::TEX_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        Self {
+            sharpen: pipeline("fs_sharpen"),
+            resize: pipeline("fs_resize"),
+            convert: pipeline("fs_convert"),
+            watermark: pipeline("fs_watermark"),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            blank_mark: GpuTexture::empty_layers(dev, 1, 1, 1, GpuTexture::LAYER_USAGE),
+            layout,
+        }
+    }
+
+    /// Run the steps over the cropped region of a composite, then convert
+    /// its colors. Returns the processed image along with the crop that is
+    /// left to apply to it, which is none once a pass has run.
+    ///
+    /// The watermark is a single layer texture of premultiplied colors.
+    pub fn apply(
+        &self,
+        dev: &GpuHandle,
+        texture: TiledTexture,
+        crop: Option<Region>,
+        steps: &[PostStep],
+        watermark: Option<&GpuTexture>,
+        conversion: Option<(ColorSpace, ColorSpace)>,
+    ) -> (TiledTexture, Option<Region>) {
+        let matrix = conversion.and_then(|(from, to)| color::conversion_matrix(from, to));
+        let mut image = texture;
+        let mut view = crop
+            .and_then(|crop| crop.intersect(&image.bounds()))
+            .unwrap_or(image.bounds());
+        let mut processed = false;
+
+        for step in steps.iter().map(Some).chain([None]) {
+            let size = (view.width, view.height);
+            let pass = match step {
+                Some(&PostStep::Sharpen { amount, radius }) => {
+                    let radius = radius.clamp(1, PostStep::MAX_RADIUS);
+                    Pass {
+                        pipeline: &self.sharpen,
+                        size,
+                        margin: radius,
+                        uniform: PostUniform {
+                            amount,
+                            radius: radius as i32,
+                            ..Default::default()
+                        },
+                        mark: None,
+                    }
+                }
+                Some(&PostStep::Resize { long_edge }) => {
+                    let factor = long_edge.max(1) as f32 / size.0.max(size.1) as f32;
+                    let scaled = |side: u32| ((side as f32 * factor).round() as u32).max(1);
+                    let resized = (scaled(size.0), scaled(size.1));
+                    if resized == size {
+                        continue;
+                    }
+                    Pass {
+                        pipeline: &self.resize,
+                        size: resized,
+                        margin: (1.0 / factor).ceil() as u32 + 1,
+                        uniform: PostUniform::default(),
+                        mark: None,
+                    }
+                }
+                Some(&PostStep::Watermark {
+                    opacity,
+                    scale,
+                    placement,
+                }) => {
+                    let Some(mark) = watermark else {
+                        continue;
+                    };
+                    let (width, height) = (size.0 as f32, size.1 as f32);
+                    let inset = width.min(height) * Self::MARK_INSET;
+                    let mark_size = [mark.size.width as f32, mark.size.height as f32];
+                    let scale = width * scale / mark_size[0];
+                    // Watermarks that would be too tall are shrunk to fit
+                    // within the insets.
+                    let fit = ((width - 2.0 * inset) / mark_size[0])
+                        .min((height - 2.0 * inset) / mark_size[1])
+                        .min(scale);
+                    let mark_size = mark_size.map(|side| side * fit);
+                    Pass {
+                        pipeline: &self.watermark,
+                        size,
+                        margin: 0,
+                        uniform: PostUniform {
+                            mark_origin: placement.origin([width, height], mark_size, inset),
+                            mark_size,
+                            opacity,
+                            ..Default::default()
+                        },
+                        mark: Some(mark),
+                    }
+                }
+                None => {
+                    let Some(matrix) = matrix else {
+                        continue;
+                    };
+                    // The shader takes the matrix by columns.
+                    let column = |i: usize| [matrix[0][i], matrix[1][i], matrix[2][i], 0.0];
+                    Pass {
+                        pipeline: &self.convert,
+                        size,
+                        margin: 0,
+                        uniform: PostUniform {
+                            matrix: [column(0), column(1), column(2)],
+                            ..Default::default()
+                        },
+                        mark: None,
+                    }
+                }
+            };
+            image = self.run(dev, &image, view, pass);
+            view = image.bounds();
+            processed = true;
+        }
+
+        let crop = if processed { None } else { crop };
+        (image, crop)
+    }
+
+    /// Shrink a composite so that its longer side is at most `long_edge`
+    /// pixels long, such as for previews.
+    pub fn thumbnail(
+        &self,
+        dev: &GpuHandle,
+        source: &TiledTexture,
+        long_edge: u32,
+    ) -> TiledTexture {
+        let view = source.bounds();
+        let factor = (long_edge.max(1) as f32 / view.width.max(view.height) as f32).min(1.0);
+        let scaled = |side: u32| ((side as f32 * factor).round() as u32).max(1);
+        self.run(
+            dev,
+            source,
+            view,
+            Pass {
+                pipeline: &self.resize,
+                size: (scaled(view.width), scaled(view.height)),
+                margin: (1.0 / factor).ceil() as u32 + 1,
+                uniform: PostUniform::default(),
+                mark: None,
+            },
+        )
+    }
+
+    /// Render a pass from a region of the source into a new image.
+    fn run(
+        &self,
+        dev: &GpuHandle,
+        source: &TiledTexture,
+        view: Region,
+        pass: Pass,
+    ) -> TiledTexture {
+        let (width, height) = pass.size;
+        let scale = [
+            view.width as f32 / width as f32,
+            view.height as f32 / height as f32,
+        ];
+        // Output tiles are kept small enough that the part of the source
+        // they read fits in a single texture.
+        let max = dev.device.limits().max_texture_dimension_2d;
+        let reach = scale[0].max(scale[1]).max(1.0);
+        let edge = ((max.saturating_sub(2 * pass.margin + 2)) as f32 / reach) as u32;
+        let output = TiledTexture::empty_layers(
+            dev,
+            pass.size,
+            Region::split(width, height, edge.max(1), 1),
+            1,
+            GpuTexture::OUTPUT_USAGE,
+        );
+
+        let mark_view = pass.mark.unwrap_or(&self.blank_mark).create_view();
+        let mut encoder = dev
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        for (region, tile) in &output.tiles {
+            // Part of the source read by the tile, within the view.
+            let span = |start: u32, len: u32, scale: f32, min: u32, size: u32| {
+                let from = (min as f32 + start as f32 * scale).floor() as u32;
+                let to = (min as f32 + (start + len) as f32 * scale).ceil() as u32;
+                let from = from.saturating_sub(pass.margin).max(min);
+                let to = (to + pass.margin).min(min + size);
+                (from, to.max(from + 1) - from)
+            };
+            let (x, width) = span(region.x, region.width, scale[0], view.x, view.width);
+            let (y, height) = span(region.y, region.height, scale[1], view.y, view.height);
+            let footprint = Region {
+                x,
+                y,
+                width,
+                height,
+            };
+
+            let copy = GpuTexture::empty_layers(
+                dev,
+                footprint.width,
+                footprint.height,
+                1,
+                GpuTexture::LAYER_USAGE,
+            );
+            for (source_region, source_texture) in &source.tiles {
+                let Some(part) = source_region.intersect(&footprint) else {
+                    continue;
+                };
+                encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &source_texture.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d {
+                            x: part.x - source_region.x,
+                            y: part.y - source_region.y,
+                            z: 0,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &copy.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d {
+                            x: part.x - footprint.x,
+                            y: part.y - footprint.y,
+                            z: 0,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width: part.width,
+                        height: part.height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+
+            let uniform = PostUniform {
+                scale,
+                out_origin: [region.x as f32, region.y as f32],
+                src_offset: [
+                    view.x as f32 - footprint.x as f32,
+                    view.y as f32 - footprint.y as f32,
+                ],
+                ..pass.uniform
+            };
+            let buffer = dev
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("post_params"),
+                    contents: bytemuck::bytes_of(&uniform),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+            let bind_group = dev.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("post_bind_group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&copy.create_view()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&mark_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+
+            let tile_view = tile.create_view();
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &tile_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(pass.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        dev.queue.submit(Some(encoder.finish()));
+        output
+    }
+}
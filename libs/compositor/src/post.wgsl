@@ -0,0 +1,130 @@
+// Post-processing passes over color exports, run after compositing and
+// before the readback. Each draw renders one output tile from a copy of the
+// part of the previous image that the tile samples, with a margin around it.
+// Colors are premultiplied, as in the composite.
+
+struct Params {
+    // Source pixels per output pixel along each axis.
+    scale: vec2<f32>,
+    // Position of the output tile in the whole output.
+    out_origin: vec2<f32>,
+    // Position of the source image in the copy.
+    src_offset: vec2<f32>,
+    // Rectangle of the watermark in the whole output.
+    mark_origin: vec2<f32>,
+    mark_size: vec2<f32>,
+    // Strength of the sharpening.
+    amount: f32,
+    // Radius of the sharpening blur, in pixels.
+    radius: i32,
+    // Opacity of the watermark.
+    opacity: f32,
+    // Conversion between the linear colors of two color spaces.
+    matrix: mat3x3<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> params: Params;
+@group(0) @binding(2)
+var mark: texture_2d<f32>;
+@group(0) @binding(3)
+var mark_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // Single triangle covering the whole tile.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Position in the copy that the center of an output pixel maps to.
+fn source_position(frag: vec2<f32>) -> vec2<f32> {
+    return (params.out_origin + frag) * params.scale + params.src_offset;
+}
+
+// Pixel of the copy, repeating the edges.
+fn load(p: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    return textureLoad(source, clamp(p, vec2<i32>(0), size - 1), 0);
+}
+
+@fragment
+fn fs_sharpen(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let center = vec2<i32>(floor(source_position(frag.xy)));
+    let r = params.radius;
+    var blur = vec4<f32>(0.0);
+    var total = 0.0;
+    // Gaussian blur with a standard deviation of half the radius.
+    for (var y = -r; y <= r; y += 1) {
+        for (var x = -r; x <= r; x += 1) {
+            let weight = exp(-2.0 * f32(x * x + y * y) / f32(r * r));
+            blur += load(center + vec2<i32>(x, y)) * weight;
+            total += weight;
+        }
+    }
+    let color = load(center);
+    let rgb = color.rgb + (color.rgb - blur.rgb / total) * params.amount;
+    // Coverage is kept, and premultiplied colors never exceed it.
+    return vec4<f32>(clamp(rgb, vec3<f32>(0.0), vec3<f32>(color.a)), color.a);
+}
+
+@fragment
+fn fs_resize(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = source_position(frag.xy);
+    if max(params.scale.x, params.scale.y) > 1.0 {
+        // Shrinking averages the source pixels under the output pixel.
+        let n = vec2<i32>(min(ceil(params.scale), vec2<f32>(16.0)));
+        let start = p - params.scale * 0.5;
+        let step = params.scale / vec2<f32>(n);
+        var sum = vec4<f32>(0.0);
+        for (var y = 0; y < n.y; y += 1) {
+            for (var x = 0; x < n.x; x += 1) {
+                let at = start + (vec2<f32>(f32(x), f32(y)) + 0.5) * step;
+                sum += load(vec2<i32>(floor(at)));
+            }
+        }
+        return sum / f32(n.x * n.y);
+    }
+
+    // Enlarging interpolates bilinearly.
+    let c = p - 0.5;
+    let i = vec2<i32>(floor(c));
+    let f = fract(c);
+    let top = mix(load(i), load(i + vec2<i32>(1, 0)), f.x);
+    let bottom = mix(load(i + vec2<i32>(0, 1)), load(i + vec2<i32>(1, 1)), f.x);
+    return mix(top, bottom, f.y);
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_convert(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = load(vec2<i32>(floor(source_position(frag.xy))));
+    if color.a == 0.0 {
+        return color;
+    }
+    let linear = params.matrix * srgb_to_linear(color.rgb / color.a);
+    // Colors outside of the target gamut are clamped.
+    let rgb = linear_to_srgb(clamp(linear, vec3<f32>(0.0), vec3<f32>(1.0)));
+    return vec4<f32>(rgb * color.a, color.a);
+}
+
+@fragment
+fn fs_watermark(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = load(vec2<i32>(floor(source_position(frag.xy))));
+    let uv = (params.out_origin + frag.xy - params.mark_origin) / params.mark_size;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return color;
+    }
+    // The watermark is premultiplied when loaded.
+    let stamp = textureSampleLevel(mark, mark_sampler, uv, 0.0) * params.opacity;
+    return stamp + color * (1.0 - stamp.a);
+}
@@ -0,0 +1,325 @@
+//! ICC profiles of documents and of exported images.
+
+use super::color::ColorSpace;
+
+/// ICC profile that the colors of a document are in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorProfile {
+    pub icc: Vec<u8>,
+}
+
+impl ColorProfile {
+    /// Offset of the tag table, right after the header.
+    const TAG_TABLE: usize = 128;
+
+    /// Profile of a color space, generated the same way as the Display P3
+    /// profiles that Procreate embeds in documents.
+    pub fn of(space: ColorSpace) -> Self {
+        // Primaries adapted to the D50 profile connection space.
+        let primaries = match space {
+            ColorSpace::Srgb => [
+                [0.436_074_7, 0.222_504_5, 0.013_932_2],
+                [0.385_064_9, 0.716_878_6, 0.097_104_5],
+                [0.143_080_4, 0.060_616_9, 0.714_173_3],
+            ],
+            ColorSpace::DisplayP3 => [
+                [0.515_121_5, 0.241_195_7, -0.001_052_9],
+                [0.291_977, 0.692_245_5, 0.041_885_4],
+                [0.157_104_5, 0.066_574_1, 0.784_072_9],
+            ],
+        };
+        Self {
+            icc: matrix_profile(space.as_str(), primaries),
+        }
+    }
+
+    /// Data of the tag with the given signature.
+    fn tag(&self, signature: &[u8; 4]) -> Option<&[u8]> {
+        let icc = &self.icc;
+        let u32_at = |at: usize| {
+            icc.get(at..at + 4)
+                .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+        };
+        let count = u32_at(Self::TAG_TABLE)?;
+        (0..count).find_map(|i| {
+            let entry = Self::TAG_TABLE + 4 + i * 12;
+            (icc.get(entry..entry + 4)? == signature).then_some(())?;
+            let offset = u32_at(entry + 4)?;
+            icc.get(offset..offset + u32_at(entry + 8)?)
+        })
+    }
+
+    /// Description of the profile, such as `Display P3`.
+    pub fn description(&self) -> Option<String> {
+        let tag = self.tag(b"desc")?;
+        match tag.get(..4)? {
+            // ICC v4 localized strings, of which the first is used.
+            b"mluc" => {
+                let len = u32::from_be_bytes(tag.get(20..24)?.try_into().ok()?) as usize;
+                let offset = u32::from_be_bytes(tag.get(24..28)?.try_into().ok()?) as usize;
+                let utf16 = tag
+                    .get(offset..offset + len)?
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect::<Vec<_>>();
+                String::from_utf16(&utf16).ok()
+            }
+            // ICC v2 ASCII description.
+            b"desc" => {
+                let len = u32::from_be_bytes(tag.get(8..12)?.try_into().ok()?) as usize;
+                let text = tag.get(12..12 + len)?;
+                Some(
+                    String::from_utf8_lossy(text)
+                        .trim_end_matches('\0')
+                        .to_string(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Color space that the profile describes, if colors can be converted
+    /// from it.
+    pub fn color_space(&self) -> Option<ColorSpace> {
+        let description = self.description()?;
+        if description.contains("P3") {
+            Some(ColorSpace::DisplayP3)
+        } else if description.contains("sRGB") {
+            Some(ColorSpace::Srgb)
+        } else {
+            None
+        }
+    }
+}
+
+/// ICC v4 display profile of an RGB color space with the sRGB transfer
+/// function and a D65 white point.
+fn matrix_profile(description: &str, primaries: [[f32; 3]; 3]) -> Vec<u8> {
+    const D50: [f32; 3] = [0.964_2, 1.0, 0.824_9];
+    // Bradford adaptation from D65 to D50.
+    const CHAD: [f32; 9] = [
+        1.047_811,
+        0.022_886_6,
+        -0.050_127,
+        0.029_542_4,
+        0.990_484_4,
+        -0.017_049_1,
+        -0.009_234_5,
+        0.015_043_6,
+        0.752_131_6,
+    ];
+
+    let fixed = |v: f32| ((v * 65536.0).round() as i32).to_be_bytes();
+    let mluc = |text: &str| {
+        let utf16 = text
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect::<Vec<_>>();
+        let mut tag = b"mluc\0\0\0\0".to_vec();
+        tag.extend_from_slice(&1u32.to_be_bytes());
+        tag.extend_from_slice(&12u32.to_be_bytes());
+        tag.extend_from_slice(b"enUS");
+        tag.extend_from_slice(&(utf16.len() as u32).to_be_bytes());
+        tag.extend_from_slice(&28u32.to_be_bytes());
+        tag.extend_from_slice(&utf16);
+        tag
+    };
+    let xyz = |values: &[f32]| {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        tag.extend(values.iter().copied().flat_map(fixed));
+        tag
+    };
+    let mut sf32 = b"sf32\0\0\0\0".to_vec();
+    sf32.extend(CHAD.iter().copied().flat_map(fixed));
+    // Parametric sRGB curve, function type 3.
+    let mut trc = b"para\0\0\0\0\0\x03\0\0".to_vec();
+    for v in [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045] {
+        trc.extend_from_slice(&fixed(v));
+    }
+
+    let tags = [
+        (b"desc", mluc(description)),
+        (b"cprt", mluc("No copyright, use freely")),
+        (b"wtpt", xyz(&D50)),
+        (b"rXYZ", xyz(&primaries[0])),
+        (b"gXYZ", xyz(&primaries[1])),
+        (b"bXYZ", xyz(&primaries[2])),
+        (b"rTRC", trc.clone()),
+        (b"gTRC", trc.clone()),
+        (b"bTRC", trc),
+        (b"chad", sf32),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let start = ColorProfile::TAG_TABLE + 4 + tags.len() * 12;
+    for (signature, tag) in &tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&((start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+        // Tags start on four byte boundaries.
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    let mut header = Vec::with_capacity(ColorProfile::TAG_TABLE);
+    header.extend_from_slice(&((start + data.len()) as u32).to_be_bytes());
+    header.extend_from_slice(&[0; 4]); // Preferred CMM
+    header.extend_from_slice(&[4, 0x40, 0, 0]); // Version 4.4
+    header.extend_from_slice(b"mntrRGB XYZ ");
+    header.extend_from_slice(&[0; 12]); // Creation date
+    header.extend_from_slice(b"acsp");
+    header.extend_from_slice(&[0; 24]); // Platform, flags, device and attributes
+    header.extend_from_slice(&0u32.to_be_bytes()); // Perceptual intent
+    header.extend(D50.iter().copied().flat_map(fixed));
+    header.resize(ColorProfile::TAG_TABLE, 0);
+
+    [header, table, data].concat()
+}
+
+/// How the colors of exported images are converted and which profile is
+/// embedded in them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportColorSpace {
+    /// Leave the colors as they are and embed the document's profile.
+    #[default]
+    KeepDocument,
+    ConvertSrgb,
+    ConvertDisplayP3,
+    /// Leave the colors as they are and embed the sRGB profile, so that
+    /// they look like they do in the viewer.
+    AssignSrgb,
+}
+
+impl ExportColorSpace {
+    pub const fn all() -> &'static [Self] {
+        &[
+            Self::KeepDocument,
+            Self::ConvertSrgb,
+            Self::ConvertDisplayP3,
+            Self::AssignSrgb,
+        ]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::KeepDocument => "Keep Document Profile",
+            Self::ConvertSrgb => "Convert to sRGB",
+            Self::ConvertDisplayP3 => "Convert to Display P3",
+            Self::AssignSrgb => "Assign sRGB",
+        }
+    }
+
+    /// Color spaces to convert the document's colors between, if they
+    /// have to be, and the profile to embed. Documents without a profile
+    /// that can be converted from are treated as sRGB.
+    pub fn resolve(
+        self,
+        document: Option<&ColorProfile>,
+    ) -> (Option<(ColorSpace, ColorSpace)>, Option<ColorProfile>) {
+        let from = document
+            .and_then(ColorProfile::color_space)
+            .unwrap_or(ColorSpace::Srgb);
+        let convert = |to: ColorSpace| (from != to).then_some((from, to));
+        match self {
+            Self::KeepDocument => (None, document.cloned()),
+            Self::ConvertSrgb => (
+                convert(ColorSpace::Srgb),
+                Some(ColorProfile::of(ColorSpace::Srgb)),
+            ),
+            Self::ConvertDisplayP3 => (
+                convert(ColorSpace::DisplayP3),
+                Some(ColorProfile::of(ColorSpace::DisplayP3)),
+            ),
+            Self::AssignSrgb => (None, Some(ColorProfile::of(ColorSpace::Srgb))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_profiles_are_valid_icc_v4() {
+        for (space, description) in [
+            (ColorSpace::Srgb, "sRGB"),
+            (ColorSpace::DisplayP3, "Display P3"),
+        ] {
+            let profile = ColorProfile::of(space);
+            let icc = &profile.icc;
+            let u32_at = |at: usize| u32::from_be_bytes(icc[at..at + 4].try_into().unwrap());
+            assert_eq!(u32_at(0) as usize, icc.len());
+            assert_eq!(&icc[8..10], [4, 0x40]);
+            assert_eq!(&icc[12..24], b"mntrRGB XYZ ");
+            assert_eq!(&icc[36..40], b"acsp");
+            // D50 illuminant as s15Fixed16 numbers.
+            assert_eq!([u32_at(68), u32_at(72), u32_at(76)], [63190, 65536, 54061]);
+
+            let count = u32_at(128) as usize;
+            assert_eq!(count, 10);
+            for i in 0..count {
+                let entry = 132 + i * 12;
+                let (offset, len) = (u32_at(entry + 4) as usize, u32_at(entry + 8) as usize);
+                assert_eq!(offset % 4, 0, "tags start on four byte boundaries");
+                assert!(offset >= 132 + count * 12 && offset + len <= icc.len());
+            }
+            let trc = profile.tag(b"rTRC").unwrap();
+            assert_eq!(&trc[..12], b"para\0\0\0\0\0\x03\0\0");
+            assert_eq!(profile.description().as_deref(), Some(description));
+            assert_eq!(profile.color_space(), Some(space));
+        }
+    }
+
+    #[test]
+    fn v2_descriptions_are_read() {
+        let mut desc = b"desc\0\0\0\0".to_vec();
+        desc.extend_from_slice(&11u32.to_be_bytes());
+        desc.extend_from_slice(b"Display P3\0");
+        let mut icc = vec![0; ColorProfile::TAG_TABLE];
+        icc.extend_from_slice(&1u32.to_be_bytes());
+        icc.extend_from_slice(b"desc");
+        icc.extend_from_slice(&144u32.to_be_bytes());
+        icc.extend_from_slice(&(desc.len() as u32).to_be_bytes());
+        icc.extend_from_slice(&desc);
+        let profile = ColorProfile { icc };
+        assert_eq!(profile.description().as_deref(), Some("Display P3"));
+        assert_eq!(profile.color_space(), Some(ColorSpace::DisplayP3));
+
+        // Truncated profiles have no description rather than panicking.
+        let truncated = ColorProfile {
+            icc: profile.icc[..150].to_vec(),
+        };
+        assert_eq!(truncated.description(), None);
+    }
+
+    #[test]
+    fn exports_convert_only_between_different_spaces() {
+        let p3 = ColorProfile::of(ColorSpace::DisplayP3);
+        let srgb = ColorProfile::of(ColorSpace::Srgb);
+        for (space, document, conversion, embedded) in [
+            (ExportColorSpace::KeepDocument, Some(&p3), None, Some(&p3)),
+            (ExportColorSpace::KeepDocument, None, None, None),
+            (
+                ExportColorSpace::ConvertSrgb,
+                Some(&p3),
+                Some((ColorSpace::DisplayP3, ColorSpace::Srgb)),
+                Some(&srgb),
+            ),
+            (ExportColorSpace::ConvertSrgb, None, None, Some(&srgb)),
+            (
+                ExportColorSpace::ConvertDisplayP3,
+                None,
+                Some((ColorSpace::Srgb, ColorSpace::DisplayP3)),
+                Some(&p3),
+            ),
+            (ExportColorSpace::AssignSrgb, Some(&p3), None, Some(&srgb)),
+        ] {
+            assert_eq!(
+                space.resolve(document),
+                (conversion, embedded.cloned()),
+                "{space:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,532 @@
+// Vertex shader ///////////////////////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////
+// Nothing special about this section. It gets fed vertices in a triangle strip
+// configuration to draw a square on the texture.
+
+alias vec2f = vec2<f32>;
+alias vec3f = vec3<f32>;
+alias vec4f = vec4<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) bg_coords: vec2f,
+    @location(2) fg_coords: vec2f,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) bg_coords: vec2f,
+    @location(1) fg_coords: vec2f,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.bg_coords = model.bg_coords;
+    out.fg_coords = model.fg_coords;
+    out.clip_position = vec4(model.position, 1.0);
+    return out;
+}
+ 
+// Blending code ///////////////////////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////
+
+// HSL Blending Modes //////////////////////////////////////////////////////////
+// [PDF Blend Modes: Addendum]
+// [KHR_blend_equation_advanced]
+// Like every other mode, these work on the colors as they are stored, encoded
+// with the sRGB or Display P3 curve, unless the document is blended in linear
+// light. Procreate and Photoshop blend encoded colors, so the luma weights
+// apply to encoded values. They are mirrored by LUMA_WEIGHTS in
+// compositor/color.rs.
+// The math is only defined for colors between black and white, so backdrops
+// kept brighter than white are clamped before blending.
+const EPSILON: f32 = 1e-6;
+
+fn lum(c: vec3f) -> f32 {
+    return dot(c, vec3(0.3, 0.59, 0.11));
+}
+
+fn clip_color(c: vec3f) -> vec3f {
+    let l = lum(c);
+    let n = min(min(c.r, c.g), c.b);
+    let x = max(max(c.r, c.g), c.b);
+    var z = c;
+    // The divisors are only zero for grays, which are never out of range,
+    // but rounding can push them just past it.
+    if (n < 0.0) {
+        z = l + (((c - l) * l) / max(l - n, EPSILON));
+    }
+    if (x > 1.0) {
+        z = l + (((z - l) * (1.0 - l)) / max(x - l, EPSILON));
+    }
+    return clamp(z, vec3(0.0), vec3(1.0));
+}
+
+fn set_lum(c: vec3f, l: f32) -> vec3f {
+    let d = l - lum(c);
+    return clip_color(c + d);
+}
+
+fn sat(c: vec3f) -> f32 {
+    let n = min(min(c.r, c.g), c.b);
+    let x = max(max(c.r, c.g), c.b);
+    return x - n;
+}
+
+fn set_sat(cb: vec3f, s: f32) -> vec3f {
+    let mb = min(min(cb.r, cb.g), cb.b);
+    let sb = sat(cb);
+    // Equivalent (modulo rounding errors) to setting the
+    // smallest (R,G,B) component to 0, the largest to <ssat>,
+    // and interpolating the "middle" component based on its
+    // original value relative to the smallest/largest.
+    return select(vec3(0.0), (cb - mb) * s / sb, sb > 0.0);
+}
+
+fn color(b: vec3f, s: vec3f) -> vec3f {
+    return set_lum(s.rgb, lum(b.rgb));
+}
+
+fn luminosity(b: vec3f, s: vec3f) -> vec3f {
+    return set_lum(b.rgb, lum(s.rgb));
+}
+
+fn hue(b: vec3f, s: vec3f) -> vec3f {
+    return set_lum(set_sat(s.rgb, sat(b.rgb)), lum(b.rgb));
+}
+
+fn saturation(b: vec3f, s: vec3f) -> vec3f {
+    return set_lum(set_sat(b.rgb, sat(s.rgb)), lum(b.rgb));
+}
+
+// Utilities ///////////////////////////////////////////////////////////////////
+fn comp(c: vec3f, a: f32) -> vec3f {
+    return c * (1.0 - a);
+}
+
+fn stdalpha(b: f32, f: f32) -> f32 {
+    return b + f - b * f;
+}
+
+// RGB Blending Modes //////////////////////////////////////////////////////////
+// [PDF Blend Modes: Addendum]
+// [https://photoblogstop.com/photoshop/photoshop-blend-modes-explained]
+
+fn normal(b: vec3f, s: vec3f) -> vec3f {
+    return s;
+}
+
+fn multiply(b: vec3f, s: vec3f) -> vec3f {
+    return s * b;
+}
+
+fn divide(b: vec3f, s: vec3f) -> vec3f {
+    return b / s;
+}
+
+fn screen(b: vec3f, s: vec3f) -> vec3f {
+    return s + b - s * b;
+}
+
+fn add(b: vec3f, s: vec3f) -> vec3f {
+    return s + b;
+}
+
+fn hard_light(b: vec3f, s: vec3f) -> vec3f {
+    return mix(
+        screen(b, 2.0 * s - 1.0),
+        multiply(b, s * 2.0),
+        step(s, vec3(0.5))
+    );
+}
+
+fn overlay(b: vec3f, s: vec3f) -> vec3f {
+    return hard_light(s, b);
+}
+
+fn darken(b: vec3f, s: vec3f) -> vec3f {
+    return min(s, b);
+}
+
+fn lighten(b: vec3f, s: vec3f) -> vec3f {
+    return max(s, b);
+}
+
+fn difference(b: vec3f, s: vec3f) -> vec3f {
+    return abs(b - s);
+}
+
+fn subtract(b: vec3f, s: vec3f) -> vec3f {
+    return b - s;
+}
+
+fn linear_burn(b: vec3f, s: vec3f) -> vec3f {
+    return max(b + s - 1.0, vec3(0.0));
+}
+
+fn linear_dodge(b: vec3f, s: vec3f) -> vec3f {
+    return min(b + s, vec3(1.0));
+}
+
+fn linear_light(b: vec3f, s: vec3f) -> vec3f {
+    return mix(
+        linear_dodge(b, 2.0 * (s - 0.5)), 
+        linear_burn(b, 2.0 * s),
+        step(s, vec3(0.5))
+    );
+}
+
+fn exclusion(b: vec3f, s: vec3f) -> vec3f {
+    return b + s - 2.0 * b * s;
+}
+
+// Dodging can be allowed to go brighter than white, up to `ceiling`.
+fn color_dodge(b: vec3f, s: vec3f, ceiling: f32) -> vec3f {
+    return mix(
+        vec3(ceiling),
+        min(vec3(ceiling), b / (1.0 - s)),
+        step(s, vec3(1.0))
+    );
+}
+
+fn color_burn(b: vec3f, s: vec3f) -> vec3f {
+    return mix(
+        1.0 - min(vec3(1.0), (1.0 - b) / s),
+        vec3(0.0),
+        step(s, vec3(0.0))
+    );
+}
+
+fn soft_light(b: vec3f, s: vec3f) -> vec3f {
+    return mix(
+        sqrt(b) * (2.0 * s - 1.0) + 2.0 * b * (1.0 - s), 
+        2.0 * b * s + b * b * (1.0 - 2.0 * s),
+        step(s, vec3(0.5))
+    );
+}
+
+fn vivid_light(b: vec3f, s: vec3f) -> vec3f {
+    return mix(
+        color_dodge(b, 2.0 * (s - 0.5), 1.0),
+        color_burn(b, 2.0 * s),
+        step(s, vec3(0.5))
+    );
+}
+
+fn hard_mix(b: vec3f, s: vec3f) -> vec3f {
+    return mix(
+        vec3(1.0), 
+        vec3(0.0), 
+        step(vivid_light(b, s), vec3(0.5))
+    );
+}
+
+fn pin_light(b: vec3f, s: vec3f) -> vec3f {
+    return mix(
+        lighten(b, 2.0 * (s - 0.5)),
+        darken(b, 2.0 * s),
+        step(s, vec3(0.5))
+    );
+}
+
+fn lighter_color(b: vec3f, s: vec3f) -> vec3f {
+    return select(b, s, lum(b) < lum(s));
+}
+
+fn darker_color(b: vec3f, s: vec3f) -> vec3f {
+    return select(b, s, lum(b) > lum(s));
+}
+
+// Fragment shader /////////////////////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////
+
+@group(0) @binding(0)
+var splr: sampler;
+@group(1) @binding(0)
+var composite: texture_2d<f32>;
+@group(1) @binding(1)
+var textures: texture_2d_array<f32>;
+@group(1) @binding(2)
+var<storage, read> layers: array<u32>;
+@group(1) @binding(3)
+var<storage, read> masks: array<u32>;
+@group(1) @binding(4)
+var<storage, read> blends: array<u32>;
+@group(1) @binding(5)
+var<storage, read> opacities: array<f32>;
+@group(1) @binding(6)
+var paper: texture_2d<f32>;
+@group(1) @binding(7)
+var lut: texture_3d<f32>;
+
+struct Adjustments {
+    // Adjustments are applied if this is nonzero.
+    enabled: u32,
+    brightness: f32,
+    contrast: f32,
+    // Rotation of the hue in radians.
+    hue: f32,
+    saturation: f32,
+    grayscale: u32,
+    invert: u32,
+};
+
+@group(1) @binding(8)
+var<uniform> adjust: Adjustments;
+
+struct PushConstants {
+    // Premultiplied background color, transparent if hidden.
+    background: vec4f,
+    layer_count: u32,
+    paper_blend: u32,
+    // Paper overlay is disabled if this is zero.
+    paper_strength: f32,
+    // Color lookup table is applied if this is nonzero.
+    lut_enabled: u32,
+    // Position of this tile in the canvas, so the paper lines up across tiles.
+    paper_offset: vec2u,
+    // Blends are clamped to white right away if this is zero, otherwise
+    // they are kept brighter than white and tone mapped with TONE_MAP_*.
+    tone_map: u32,
+    // Layers are blended in linear light if this is SPACE_LINEAR.
+    blend_space: u32,
+};
+
+var<push_constant> pc: PushConstants;
+
+// Brightest color kept between layers, as a multiple of white.
+var<private> ceiling: f32 = 1.0;
+
+// Premultiplied colors clamped to the ceiling and alpha to one.
+fn clamp_premultiplied(c: vec4f) -> vec4f {
+    return clamp(c, vec4(0.0), vec4(vec3(ceiling), 1.0));
+}
+
+// Blend alpha straight colors
+fn premultiplied_blend(bg: vec4f, fg: vec4f, cg: vec4f) -> vec4f {
+    return clamp_premultiplied(vec4(
+        cg.rgb * cg.a * bg.a + comp(fg.rgb, bg.a) + comp(bg.rgb, cg.a),
+        stdalpha(bg.a, cg.a)
+    ));
+}
+
+const MASK_NONE: u32 = 0xFFFFFFFFu;
+const BLEND_NORMAL: u32 = 0u;
+const TONE_MAP_OFF: u32 = 0u;
+const TONE_MAP_HIGHLIGHTS: u32 = 2u;
+// Ceiling of blends kept brighter than white, which also keeps dodging by
+// white finite.
+const HDR_CEILING: f32 = 16.0;
+
+const SPACE_LINEAR: u32 = 1u;
+
+// Blending space //////////////////////////////////////////////////////////////
+// Textures hold premultiplied colors encoded with the sRGB curve. The curve
+// has to be undone on straight colors, so the textures are not read through
+// sRGB views, which would decode the premultiplied values instead. Composites
+// are encoded again before they are stored, which also keeps the precision of
+// dark colors in 8 bits between passes.
+fn srgb_to_linear(c: vec3f) -> vec3f {
+    return select(pow((c + 0.055) / 1.055, vec3(2.4)), c / 12.92, c <= vec3(0.04045));
+}
+
+fn linear_to_srgb(c: vec3f) -> vec3f {
+    return select(1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3(0.0031308));
+}
+
+// Premultiplied color as it is blended.
+fn decode(c: vec4f) -> vec4f {
+    if (pc.blend_space != SPACE_LINEAR || c.a == 0.0) {
+        return c;
+    }
+    return vec4(srgb_to_linear(c.rgb / c.a) * c.a, c.a);
+}
+
+// Premultiplied color as it is stored.
+fn encode(c: vec4f) -> vec4f {
+    if (pc.blend_space != SPACE_LINEAR || c.a == 0.0) {
+        return c;
+    }
+    return vec4(linear_to_srgb(max(c.rgb / c.a, vec3(0.0))) * c.a, c.a);
+}
+
+// Blend a premultiplied layer color over the premultiplied composite.
+fn blend_layer(bga: vec4f, fga: vec4f, blend: u32, opacity: f32) -> vec4f {
+    var bg = vec4(clamp(bga.rgb / bga.a, vec3(0.0), vec3(ceiling)), bga.a);
+    var fg = vec4(clamp(fga.rgb / fga.a, vec3(0.0), vec3(1.0)), fga.a * opacity);
+
+    // Blend straight colors according to modes
+    var final_pixel = vec3(0.0);
+    switch (blend) {
+        case 1u: { final_pixel = multiply(bg.rgb, fg.rgb); }
+        case 2u: { final_pixel = screen(bg.rgb, fg.rgb); }
+        case 3u: { final_pixel = add(bg.rgb, fg.rgb); }
+        case 4u: { final_pixel = lighten(bg.rgb, fg.rgb); }
+        case 5u: { final_pixel = exclusion(bg.rgb, fg.rgb); }
+        case 6u: { final_pixel = difference(bg.rgb, fg.rgb); }
+        case 7u: { final_pixel = subtract(bg.rgb, fg.rgb); }
+        case 8u: { final_pixel = linear_burn(bg.rgb, fg.rgb); }
+        case 9u: { final_pixel = color_dodge(bg.rgb, fg.rgb, ceiling); }
+        case 10u: { final_pixel = color_burn(bg.rgb, fg.rgb); }
+        case 11u: { final_pixel = overlay(bg.rgb, fg.rgb); }
+        case 12u: { final_pixel = hard_light(bg.rgb, fg.rgb); }
+        case 13u: { final_pixel = color(min(bg.rgb, vec3(1.0)), fg.rgb); }
+        case 14u: { final_pixel = luminosity(min(bg.rgb, vec3(1.0)), fg.rgb); }
+        case 15u: { final_pixel = hue(min(bg.rgb, vec3(1.0)), fg.rgb); }
+        case 16u: { final_pixel = saturation(min(bg.rgb, vec3(1.0)), fg.rgb); }
+        case 17u: { final_pixel = soft_light(bg.rgb, fg.rgb); }
+        case 19u: { final_pixel = darken(bg.rgb, fg.rgb); }
+        case 20u: { final_pixel = hard_mix(bg.rgb, fg.rgb); }
+        case 21u: { final_pixel = vivid_light(bg.rgb, fg.rgb); }
+        case 22u: { final_pixel = linear_light(bg.rgb, fg.rgb); }
+        case 23u: { final_pixel = pin_light(bg.rgb, fg.rgb); }
+        case 24u: { final_pixel = lighter_color(bg.rgb, fg.rgb); }
+        case 25u: { final_pixel = darker_color(bg.rgb, fg.rgb); }
+        case 26u: { final_pixel = divide(bg.rgb, fg.rgb); }
+        default: { final_pixel = normal(bg.rgb, fg.rgb); }
+    }
+    // Clamp to avoid unwanted behavior down the road
+    final_pixel = clamp(final_pixel, vec3(0.0), vec3(ceiling));
+
+    // Compute final premultiplied colors
+    return premultiplied_blend(bga, fga * opacity, vec4(final_pixel, fg.a));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    if (pc.tone_map != TONE_MAP_OFF) {
+        ceiling = HDR_CEILING;
+    }
+    // Premultiplied colors
+    var bga = decode(textureSample(composite, splr, in.bg_coords));
+
+    for (var i: u32 = 0u; i < pc.layer_count; i++) {
+        var maska = select(textureSample(textures, splr, in.fg_coords, i32(masks[i])).a, 1.0, masks[i] == MASK_NONE);
+        var fga = decode(textureSample(textures, splr, in.fg_coords, i32(layers[i])) * maska);
+
+        // Fully opaque Normal layers make up most documents and reduce to
+        // a plain premultiplied "over", so skip unpremultiplying entirely.
+        // This is an if/else rather than a continue to keep the texture
+        // samples above in uniform control flow.
+        if (blends[i] == BLEND_NORMAL && opacities[i] >= 1.0) {
+            bga = clamp_premultiplied(fga + bga * (1.0 - fga.a));
+        } else {
+            bga = blend_layer(bga, fga, blends[i], opacities[i]);
+        }
+    }
+
+    bga = encode(bga);
+    if (pc.tone_map != TONE_MAP_OFF) {
+        bga = apply_tone_map(bga);
+    }
+    if (adjust.enabled != 0u) {
+        bga = apply_adjustments(bga);
+    }
+    if (pc.paper_strength > 0.0) {
+        bga = apply_paper(bga, in.fg_coords);
+    }
+    if (pc.lut_enabled != 0u) {
+        bga = apply_lut(bga);
+    }
+    return bga;
+}
+
+// Tone mapping ////////////////////////////////////////////////////////////////
+// Brings colors brighter than white back into range. Highlights above the knee
+// are rolled off by scaling the whole color, so that glows keep their hue
+// instead of washing out to white, and colors below it are left untouched.
+const TONE_KNEE: f32 = 0.8;
+
+fn apply_tone_map(bga: vec4f) -> vec4f {
+    if (bga.a == 0.0) {
+        return bga;
+    }
+    var c = bga.rgb / bga.a;
+    let peak = max(max(c.r, c.g), c.b);
+    if (pc.tone_map == TONE_MAP_HIGHLIGHTS && peak > TONE_KNEE) {
+        let t = (peak - TONE_KNEE) / (1.0 - TONE_KNEE);
+        let mapped = TONE_KNEE + (1.0 - TONE_KNEE) * t / (1.0 + t);
+        c *= mapped / peak;
+    }
+    return vec4(clamp(c, vec3(0.0), vec3(1.0)) * bga.a, bga.a);
+}
+
+// Adjustments /////////////////////////////////////////////////////////////////
+// Previews color adjustments of the composite, including the background color.
+fn apply_adjustments(bga: vec4f) -> vec4f {
+    let flat = bga + pc.background * (1.0 - bga.a);
+    if (flat.a == 0.0) {
+        return flat;
+    }
+
+    var c = clamp(flat.rgb / flat.a, vec3(0.0), vec3(1.0));
+    c = clamp((c + adjust.brightness - 0.5) * (1.0 + adjust.contrast) + 0.5, vec3(0.0), vec3(1.0));
+    // Rotate the color around the gray axis.
+    let axis = vec3(0.57735027);
+    let cos_hue = cos(adjust.hue);
+    c = c * cos_hue + cross(axis, c) * sin(adjust.hue) + axis * dot(axis, c) * (1.0 - cos_hue);
+    c = mix(vec3(lum(c)), c, 1.0 + adjust.saturation);
+    if (adjust.grayscale != 0u) {
+        c = vec3(lum(c));
+    }
+    c = clamp(c, vec3(0.0), vec3(1.0));
+    if (adjust.invert != 0u) {
+        c = 1.0 - c;
+    }
+    return vec4(c * flat.a, flat.a);
+}
+
+// Paper overlay ///////////////////////////////////////////////////////////////
+// Tiles the paper texture over the canvas at its native resolution and blends
+// it over the composite, including the background color.
+fn apply_paper(bga: vec4f, coords: vec2f) -> vec4f {
+    // Flatten onto the background so the paper also shows on empty areas.
+    let flat = bga + pc.background * (1.0 - bga.a);
+    if (flat.a == 0.0) {
+        return flat;
+    }
+
+    let canvas = vec2f(textureDimensions(textures).xy);
+    let texel = (vec2u(coords * canvas) + pc.paper_offset) % textureDimensions(paper);
+    let p = textureLoad(paper, texel, 0).rgb;
+
+    let b = clamp(flat.rgb / flat.a, vec3(0.0), vec3(1.0));
+    var blended = vec3(0.0);
+    switch (pc.paper_blend) {
+        case 11u: { blended = overlay(b, p); }
+        case 17u: { blended = soft_light(b, p); }
+        default: { blended = multiply(b, p); }
+    }
+    let mixed = mix(b, clamp(blended, vec3(0.0), vec3(1.0)), pc.paper_strength);
+    return vec4(mixed * flat.a, flat.a);
+}
+
+// Color lookup table //////////////////////////////////////////////////////////
+// Grades the straight colors of the composite, including the background color,
+// through a 3D lookup table with trilinear interpolation. The table is loaded
+// rather than sampled since float textures are not filterable everywhere.
+fn apply_lut(bga: vec4f) -> vec4f {
+    let flat = bga + pc.background * (1.0 - bga.a);
+    if (flat.a == 0.0) {
+        return flat;
+    }
+
+    let size = textureDimensions(lut);
+    let position = clamp(flat.rgb / flat.a, vec3(0.0), vec3(1.0)) * vec3f(size - 1u);
+    let i0 = min(vec3u(position), size - 1u);
+    let i1 = min(i0 + 1u, size - 1u);
+    let t = position - vec3f(i0);
+
+    let c00 = mix(lut_entry(i0.x, i1.x, i0.y, i0.z, t.x), lut_entry(i0.x, i1.x, i1.y, i0.z, t.x), t.y);
+    let c01 = mix(lut_entry(i0.x, i1.x, i0.y, i1.z, t.x), lut_entry(i0.x, i1.x, i1.y, i1.z, t.x), t.y);
+    let graded = clamp(mix(c00, c01, t.z), vec3(0.0), vec3(1.0));
+    return vec4(graded * flat.a, flat.a);
+}
+
+// Table entry interpolated along the red axis.
+fn lut_entry(r0: u32, r1: u32, g: u32, b: u32, t: f32) -> vec3f {
+    return mix(textureLoad(lut, vec3u(r0, g, b), 0).rgb, textureLoad(lut, vec3u(r1, g, b), 0).rgb, t);
+}
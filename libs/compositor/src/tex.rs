@@ -0,0 +1,801 @@
+use super::budget::{AllocationError, MemoryBudget};
+use super::metadata::{self, ExportMetadata};
+use super::{color, dev::GpuHandle, BufferDimensions};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const TEX_DIM: wgpu::TextureDimension = wgpu::TextureDimension::D2;
+pub(super) const TEX_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Which image to write out when exporting a texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    /// Full RGBA image, with straight alpha.
+    Color,
+    /// Grayscale image of the alpha channel.
+    AlphaMatte,
+    /// Grayscale image of the luminance of the premultiplied colors, with
+    /// the weights of the blending modes.
+    LuminanceMatte,
+}
+
+impl ExportKind {
+    /// Whether the export is a grayscale matte rather than a color image.
+    pub fn is_matte(self) -> bool {
+        self != Self::Color
+    }
+}
+
+/// Resampling filter used when exporting at a scale other than 100%.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleFilter {
+    Nearest,
+    Bilinear,
+    Lanczos,
+}
+
+impl ResampleFilter {
+    pub fn all() -> &'static [ResampleFilter] {
+        &[Self::Nearest, Self::Bilinear, Self::Lanczos]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Nearest => "Nearest",
+            Self::Bilinear => "Bilinear",
+            Self::Lanczos => "Lanczos",
+        }
+    }
+
+    fn filter_type(self) -> image::imageops::FilterType {
+        match self {
+            Self::Nearest => image::imageops::FilterType::Nearest,
+            Self::Bilinear => image::imageops::FilterType::Triangle,
+            Self::Lanczos => image::imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Size of an exported image relative to the canvas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportScale {
+    /// Scale factor, 1.0 exports one pixel per canvas pixel.
+    pub factor: f32,
+    pub filter: ResampleFilter,
+}
+
+impl ExportScale {
+    pub const ACTUAL_SIZE: Self = Self {
+        factor: 1.0,
+        filter: ResampleFilter::Bilinear,
+    };
+
+    /// Resample an image to the export scale, unless it is kept as is.
+    fn apply(self, image: image::RgbaImage) -> image::RgbaImage {
+        let scaled = |size: u32| ((size as f32 * self.factor).round() as u32).max(1);
+        let (width, height) = (scaled(image.width()), scaled(image.height()));
+        if (width, height) == image.dimensions() {
+            return image;
+        }
+        image::imageops::resize(&image, width, height, self.filter.filter_type())
+    }
+}
+
+/// Formats that color exports are also saved in, next to the chosen file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExtraFormats {
+    pub png: bool,
+    pub jpeg: bool,
+}
+
+impl ExtraFormats {
+    /// Each format with whether it is selected.
+    pub fn entries_mut(&mut self) -> [(image::ImageFormat, &mut bool); 2] {
+        [
+            (image::ImageFormat::Png, &mut self.png),
+            (image::ImageFormat::Jpeg, &mut self.jpeg),
+        ]
+    }
+
+    /// The chosen path followed by the paths of the selected formats, with
+    /// the same file name and the extension swapped.
+    pub fn paths(mut self, path: PathBuf) -> Vec<PathBuf> {
+        let chosen = image::ImageFormat::from_path(&path).ok();
+        let extra = self
+            .entries_mut()
+            .into_iter()
+            .filter(|(format, selected)| **selected && Some(*format) != chosen)
+            .map(|(format, _)| path.with_extension(format.extensions_str()[0]))
+            .collect::<Vec<_>>();
+        std::iter::once(path).chain(extra).collect()
+    }
+}
+
+/// GPU texture abstraction.
+#[derive(Debug)]
+pub struct GpuTexture {
+    pub size: wgpu::Extent3d,
+    pub texture: wgpu::Texture,
+    /// Budget that the texture's memory is charged to until dropped.
+    budget: Arc<MemoryBudget>,
+}
+
+impl Drop for GpuTexture {
+    fn drop(&mut self) {
+        self.budget.refund(MemoryBudget::texture_bytes(self.size));
+    }
+}
+
+impl GpuTexture {
+    pub const LAYER_USAGE: wgpu::TextureUsages =
+        wgpu::TextureUsages::COPY_DST.union(wgpu::TextureUsages::TEXTURE_BINDING);
+    pub const OUTPUT_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::COPY_SRC
+        .union(wgpu::TextureUsages::TEXTURE_BINDING)
+        .union(wgpu::TextureUsages::RENDER_ATTACHMENT);
+
+    /// Create an empty texture.
+    pub fn empty_layers(
+        dev: &GpuHandle,
+        width: u32,
+        height: u32,
+        layers: u32,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers,
+        };
+
+        Self::empty_with_extent(dev, size, usage)
+    }
+
+    /// Create an empty texture, failing instead of exceeding the device
+    /// limits or the memory budget.
+    pub fn try_empty_layers(
+        dev: &GpuHandle,
+        width: u32,
+        height: u32,
+        layers: u32,
+        usage: wgpu::TextureUsages,
+    ) -> Result<Self, AllocationError> {
+        let limits = dev.device.limits();
+        if width > limits.max_texture_dimension_2d
+            || height > limits.max_texture_dimension_2d
+            || layers > limits.max_texture_array_layers
+        {
+            return Err(AllocationError::ExceedsLimits {
+                width,
+                height,
+                layers,
+                max_dimension: limits.max_texture_dimension_2d,
+                max_layers: limits.max_texture_array_layers,
+            });
+        }
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers,
+        };
+        dev.budget.check(MemoryBudget::texture_bytes(size))?;
+        Ok(Self::empty_with_extent(dev, size, usage))
+    }
+
+    /// Create an empty texture from an extent.
+    pub fn empty_with_extent(
+        dev: &GpuHandle,
+        size: wgpu::Extent3d,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        // Canvas texture
+        let texture = dev.device.create_texture(&wgpu::TextureDescriptor {
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TEX_DIM,
+            format: TEX_FORMAT,
+            view_formats: &[
+                wgpu::TextureFormat::Rgba8Unorm,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+            ],
+            usage,
+            label: None,
+        });
+
+        dev.budget.charge(MemoryBudget::texture_bytes(size));
+        Self {
+            texture,
+            size,
+            budget: dev.budget.clone(),
+        }
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.size.depth_or_array_layers
+    }
+
+    /// Make a texture view of this GPU texture.
+    pub fn create_view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub fn create_srgb_view(&self) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+            ..Default::default()
+        })
+    }
+
+    pub fn create_view_layer(&self, layer: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    /// Clear the texture with a certain color.
+    #[allow(dead_code)]
+    pub fn clear(&self, dev: &GpuHandle, color: wgpu::Color) {
+        dev.queue.submit(Some({
+            let mut encoder = dev
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.create_view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            encoder.finish()
+        }));
+    }
+
+    /// Replace a section of the texture with raw RGBA data.
+    ///
+    /// ### Note
+    /// The position `x` and `y` and size `width` and `height` data
+    /// should strictly fit within the texture boundaries.
+    pub fn replace(
+        &self,
+        dev: &GpuHandle,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        layer: u32,
+        data: &[u8],
+    ) {
+        assert!(
+            layer < self.layers(),
+            "index {layer} must be less than {}",
+            self.layers()
+        );
+        dev.uploads.acquire(data.len() as u64);
+        dev.queue.write_texture(
+            // Tells wgpu where to copy the pixel data
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: layer },
+                aspect: wgpu::TextureAspect::All,
+            },
+            // The actual pixel data
+            data,
+            // The layout of the texture
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Clone the texture.
+    ///
+    /// ### Note
+    /// `dev` should be the same device that created this texture
+    /// in the first place.
+    pub fn clone(&self, dev: &GpuHandle) -> Self {
+        let clone = Self::empty_with_extent(
+            dev,
+            self.size,
+            Self::OUTPUT_USAGE | wgpu::TextureUsages::COPY_DST,
+        );
+        dev.queue.submit(Some({
+            let mut encoder = dev
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            // Copy the data from the texture to the buffer
+            encoder.copy_texture_to_texture(
+                self.texture.as_image_copy(),
+                clone.texture.as_image_copy(),
+                self.size,
+            );
+            encoder.finish()
+        }));
+        clone
+    }
+
+    /// Read a region of a texture layer back from the GPU as raw RGBA data.
+    pub async fn read_region(
+        &self,
+        dev: &GpuHandle,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        layer: u32,
+    ) -> Vec<u8> {
+        let dim = BufferDimensions::new(width, height);
+        let output_buffer = dev.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (dim.padded_bytes_per_row * dim.height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        dev.queue.submit(Some({
+            let mut encoder = dev
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: layer },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &output_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(dim.padded_bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                dim.extent,
+            );
+            encoder.finish()
+        }));
+
+        let buffer_slice = output_buffer.slice(..);
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        dev.device.poll(wgpu::Maintain::Wait);
+        rx.await.unwrap().expect("Buffer mapping failed");
+
+        // Strip the row padding.
+        let data = buffer_slice
+            .get_mapped_range()
+            .chunks_exact(dim.padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..dim.unpadded_bytes_per_row as usize])
+            .copied()
+            .collect();
+        output_buffer.unmap();
+        data
+    }
+}
+
+/// Rectangle of a texture, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Split a texture into regions no larger than `max` on either side.
+    /// The regions are kept to multiples of `align` where possible, so that
+    /// tiles aligned to it never straddle two regions.
+    pub fn split(width: u32, height: u32, max: u32, align: u32) -> Vec<Self> {
+        let edge = if align <= max { max - max % align } else { max };
+        (0..height)
+            .step_by(edge as usize)
+            .flat_map(|y| {
+                (0..width).step_by(edge as usize).map(move |x| Self {
+                    x,
+                    y,
+                    width: edge.min(width - x),
+                    height: edge.min(height - y),
+                })
+            })
+            .collect()
+    }
+
+    /// Overlap of two regions, if there is any.
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        (x < right && y < bottom).then_some(Self {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+}
+
+/// Pixel copied out of a texture, see [`TiledTexture::stage_pixel`].
+pub struct PixelReadback {
+    buffer: wgpu::Buffer,
+}
+
+impl PixelReadback {
+    /// Wait for the copy and read the pixel as RGBA.
+    pub async fn read(self, dev: &GpuHandle) -> [u8; 4] {
+        let slice = self.buffer.slice(..);
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        dev.device.poll(wgpu::Maintain::Wait);
+        rx.await.unwrap().expect("Buffer mapping failed");
+        let data = slice.get_mapped_range();
+        [data[0], data[1], data[2], data[3]]
+    }
+}
+
+/// Texture split across several GPU textures, since a canvas may be larger
+/// than the device allows a single texture to be.
+#[derive(Debug)]
+pub struct TiledTexture {
+    pub width: u32,
+    pub height: u32,
+    /// Textures and the region of the whole that each one covers. The
+    /// regions do not overlap and cover the whole texture.
+    pub tiles: Vec<(Region, GpuTexture)>,
+}
+
+impl TiledTexture {
+    /// Create an empty texture split into the given regions.
+    pub fn empty_layers(
+        dev: &GpuHandle,
+        (width, height): (u32, u32),
+        regions: Vec<Region>,
+        layers: u32,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        let tiles = regions
+            .into_iter()
+            .map(|region| {
+                let texture =
+                    GpuTexture::empty_layers(dev, region.width, region.height, layers, usage);
+                (region, texture)
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            tiles,
+        }
+    }
+
+    /// Create an empty texture split into the given regions, failing instead
+    /// of exceeding the device limits or the memory budget.
+    pub fn try_empty_layers(
+        dev: &GpuHandle,
+        (width, height): (u32, u32),
+        regions: Vec<Region>,
+        layers: u32,
+        usage: wgpu::TextureUsages,
+    ) -> Result<Self, AllocationError> {
+        // Check the whole texture up front rather than failing halfway through.
+        dev.budget
+            .check(MemoryBudget::texture_bytes(wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers,
+            }))?;
+        let tiles = regions
+            .into_iter()
+            .map(|region| {
+                GpuTexture::try_empty_layers(dev, region.width, region.height, layers, usage)
+                    .map(|texture| (region, texture))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            width,
+            height,
+            tiles,
+        })
+    }
+
+    /// Whether the texture is split into exactly these regions.
+    pub fn has_regions(&self, regions: &[Region]) -> bool {
+        self.tiles.len() == regions.len()
+            && self
+                .tiles
+                .iter()
+                .zip(regions)
+                .all(|((region, _), other)| region == other)
+    }
+
+    /// Replace a section of the texture with raw RGBA data, which may span
+    /// several tiles.
+    ///
+    /// ### Note
+    /// The position `x` and `y` and size `width` and `height` data
+    /// should strictly fit within the texture boundaries.
+    pub fn replace(
+        &self,
+        dev: &GpuHandle,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        layer: u32,
+        data: &[u8],
+    ) {
+        let rect = Region {
+            x,
+            y,
+            width,
+            height,
+        };
+        for (region, texture) in &self.tiles {
+            let Some(part) = region.intersect(&rect) else {
+                continue;
+            };
+            let origin = (part.x - region.x, part.y - region.y);
+            let size = (part.width, part.height);
+            if part == rect {
+                texture.replace(dev, origin, size, layer, data);
+                continue;
+            }
+
+            let row = width as usize * 4;
+            let skip = (part.x - x) as usize * 4;
+            let part_data = data
+                .chunks_exact(row)
+                .skip((part.y - y) as usize)
+                .take(part.height as usize)
+                .flat_map(|row| &row[skip..skip + part.width as usize * 4])
+                .copied()
+                .collect::<Vec<_>>();
+            texture.replace(dev, origin, size, layer, &part_data);
+        }
+    }
+
+    /// Clone the texture.
+    ///
+    /// ### Note
+    /// `dev` should be the same device that created this texture
+    /// in the first place.
+    pub fn clone(&self, dev: &GpuHandle) -> Self {
+        Self {
+            width: self.width,
+            height: self.height,
+            tiles: self
+                .tiles
+                .iter()
+                .map(|(region, texture)| (*region, texture.clone(dev)))
+                .collect(),
+        }
+    }
+
+    /// Start copying one pixel of a texture layer into a small staging
+    /// buffer. The copy is read with [`PixelReadback::read`], which does not
+    /// need the texture anymore.
+    pub fn stage_pixel(
+        &self,
+        dev: &GpuHandle,
+        (x, y): (u32, u32),
+        layer: u32,
+    ) -> Option<PixelReadback> {
+        let (region, texture) = self.tiles.iter().find(|(region, _)| {
+            (region.x..region.x + region.width).contains(&x)
+                && (region.y..region.y + region.height).contains(&y)
+        })?;
+        let dim = BufferDimensions::new(1, 1);
+        let buffer = dev.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: u64::from(dim.padded_bytes_per_row),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        dev.queue.submit(Some({
+            let mut encoder = dev
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: &texture.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: x - region.x,
+                        y: y - region.y,
+                        z: layer,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(dim.padded_bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                dim.extent,
+            );
+            encoder.finish()
+        }));
+        Some(PixelReadback { buffer })
+    }
+
+    /// Read a region of a texture layer back from the GPU as raw RGBA data.
+    pub async fn read_region(
+        &self,
+        dev: &GpuHandle,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        layer: u32,
+    ) -> Vec<u8> {
+        let rect = Region {
+            x,
+            y,
+            width,
+            height,
+        };
+        let row = width as usize * 4;
+        let mut data = vec![0; row * height as usize];
+        for (region, texture) in &self.tiles {
+            let Some(part) = region.intersect(&rect) else {
+                continue;
+            };
+            let part_data = texture
+                .read_region(
+                    dev,
+                    (part.x - region.x, part.y - region.y),
+                    (part.width, part.height),
+                    layer,
+                )
+                .await;
+            let skip = (part.x - x) as usize * 4;
+            for (dst, src) in data
+                .chunks_exact_mut(row)
+                .skip((part.y - y) as usize)
+                .zip(part_data.chunks_exact(part.width as usize * 4))
+            {
+                dst[skip..skip + src.len()].copy_from_slice(src);
+            }
+        }
+        data
+    }
+
+    /// GPU memory taken up by the tiles.
+    pub fn bytes(&self) -> u64 {
+        self.tiles
+            .iter()
+            .map(|(_, texture)| MemoryBudget::texture_bytes(texture.size))
+            .sum()
+    }
+
+    /// Region covering the whole texture.
+    pub fn bounds(&self) -> Region {
+        Region {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Read a region of the first layer back from the GPU as an RGBA image.
+    pub async fn read_rgba(&self, dev: &GpuHandle, region: Region) -> image::RgbaImage {
+        let data = self
+            .read_region(dev, (region.x, region.y), (region.width, region.height), 0)
+            .await;
+        image::RgbaImage::from_raw(region.width, region.height, data)
+            .expect("region data matches its size")
+    }
+
+    /// Export the texture, or only the cropped region of it, as a single
+    /// image to each of the given paths. The texture is only read back and
+    /// converted once, whatever the number of formats.
+    pub async fn export(
+        &self,
+        dev: &GpuHandle,
+        paths: Vec<PathBuf>,
+        kind: ExportKind,
+        scale: ExportScale,
+        crop: Option<Region>,
+        metadata: ExportMetadata,
+    ) -> image::ImageResult<()> {
+        let region = crop
+            .and_then(|crop| crop.intersect(&self.bounds()))
+            .unwrap_or(self.bounds());
+        let buffer = self.read_rgba(dev, region).await;
+
+        // Resampled while still premultiplied so that transparent pixels do
+        // not bleed their color into the edges.
+        tokio::task::spawn_blocking(move || {
+            let save_all = |save: &dyn Fn(&Path) -> image::ImageResult<()>| {
+                paths.iter().try_for_each(|path| {
+                    tracing::info!("Saving the file to {}", path.display());
+                    save(path)
+                })
+            };
+            let mut buffer = scale.apply(buffer);
+            match kind {
+                ExportKind::Color => {
+                    // Image formats expect straight alpha.
+                    for pixel in buffer.pixels_mut() {
+                        pixel.0 = color::unpremultiply(pixel.0);
+                    }
+                    save_all(&|path| metadata::save(&buffer, path, &metadata))
+                }
+                ExportKind::AlphaMatte => {
+                    let matte =
+                        image::GrayImage::from_fn(buffer.width(), buffer.height(), |x, y| {
+                            image::Luma([buffer.get_pixel(x, y).0[3]])
+                        });
+                    save_all(&|path| metadata::save(&matte, path, &metadata))
+                }
+                ExportKind::LuminanceMatte => {
+                    let matte =
+                        image::GrayImage::from_fn(buffer.width(), buffer.height(), |x, y| {
+                            image::Luma([color::luminance_matte(buffer.get_pixel(x, y).0)])
+                        });
+                    save_all(&|path| metadata::save(&matte, path, &metadata))
+                }
+            }
+        })
+        .await
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extra_formats_encode_a_frame_each() {
+        let folder = std::env::temp_dir().join(format!("silicate-extra-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let all = ExtraFormats {
+            png: true,
+            jpeg: true,
+        };
+        let paths = all.paths(folder.join("frame.tga"));
+        assert_eq!(paths.len(), 3);
+
+        let color = image::RgbaImage::from_pixel(3, 2, image::Rgba([200, 100, 50, 255]));
+        let matte = image::GrayImage::from_pixel(3, 2, image::Luma([128]));
+        for path in &paths {
+            metadata::save(&color, path, &ExportMetadata::default()).unwrap();
+            let read = image::open(path).unwrap().into_rgba8();
+            assert_eq!(read.dimensions(), (3, 2), "{}", path.display());
+            let [r, g, b, _] = read.get_pixel(1, 1).0;
+            // JPEG is lossy.
+            assert!(r.abs_diff(200) <= 4 && g.abs_diff(100) <= 4 && b.abs_diff(50) <= 4);
+
+            metadata::save(&matte, path, &ExportMetadata::default()).unwrap();
+            let read = image::open(path).unwrap().into_luma8();
+            assert_eq!(
+                read.get_pixel(2, 0).0[0].abs_diff(128),
+                0,
+                "{}",
+                path.display()
+            );
+        }
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
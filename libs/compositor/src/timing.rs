@@ -0,0 +1,134 @@
+//! GPU timestamps around composites, to measure how long the GPU spends
+//! compositing rather than how long it takes to submit the work.
+
+use super::dev::GpuHandle;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimerState {
+    /// Ready to time a composite.
+    Idle,
+    /// Timing a composite, or waiting for its timestamps to be mapped.
+    Pending,
+    /// The timestamps of the last composite can be read.
+    Mapped,
+}
+
+/// Times composites with a pair of timestamp queries. Only one composite is
+/// timed at once, those made while the last timestamps are still on their
+/// way back are not timed.
+#[derive(Debug)]
+pub struct GpuTimer {
+    queries: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    state: Arc<Mutex<TimerState>>,
+    last: Mutex<Option<Duration>>,
+}
+
+impl GpuTimer {
+    /// Features that the device needs for timestamps between passes.
+    pub const FEATURES: wgpu::Features = wgpu::Features::TIMESTAMP_QUERY;
+    const SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+
+    /// Create a timer, or `None` if the device does not support timestamps.
+    pub fn new(dev: &GpuHandle) -> Option<Self> {
+        if !dev.device.features().contains(Self::FEATURES) {
+            return None;
+        }
+        let queries = dev.device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("composite_timer"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve = dev.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("composite_timer_resolve"),
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = dev.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("composite_timer_readback"),
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            queries,
+            resolve,
+            readback,
+            period: dev.queue.get_timestamp_period(),
+            state: Arc::new(Mutex::new(TimerState::Idle)),
+            last: Mutex::new(None),
+        })
+    }
+
+    /// Write the starting timestamp of a composite, returning whether it is
+    /// timed, in which case [`Self::end`] has to follow it.
+    pub fn begin(&self, dev: &GpuHandle) -> bool {
+        self.collect();
+        let mut state = self.state.lock();
+        if *state != TimerState::Idle {
+            return false;
+        }
+        *state = TimerState::Pending;
+        drop(state);
+
+        let mut encoder = dev
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.write_timestamp(&self.queries, 0);
+        dev.queue.submit(Some(encoder.finish()));
+        true
+    }
+
+    /// Write the ending timestamp of a composite and start reading both back.
+    pub fn end(&self, dev: &GpuHandle) {
+        let mut encoder = dev
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.write_timestamp(&self.queries, 1);
+        encoder.resolve_query_set(&self.queries, 0..2, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, Self::SIZE);
+        dev.queue.submit(Some(encoder.finish()));
+
+        let state = self.state.clone();
+        self.readback
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                *state.lock() = match result {
+                    Ok(()) => TimerState::Mapped,
+                    Err(_) => TimerState::Idle,
+                };
+            });
+    }
+
+    /// Read the timestamps back once they are mapped.
+    fn collect(&self) {
+        let mut state = self.state.lock();
+        if *state != TimerState::Mapped {
+            return;
+        }
+        let [start, end] = {
+            let data = self.readback.slice(..).get_mapped_range();
+            [0, 8].map(|at| u64::from_le_bytes(data[at..at + 8].try_into().unwrap()))
+        };
+        self.readback.unmap();
+        *state = TimerState::Idle;
+        // Timestamps can wrap or be reset, such as when the GPU idles.
+        if end > start {
+            let nanos = (end - start) as f64 * f64::from(self.period);
+            *self.last.lock() = Some(Duration::from_nanos(nanos as u64));
+        }
+    }
+
+    /// GPU time of the last timed composite.
+    pub fn last(&self) -> Option<Duration> {
+        self.collect();
+        *self.last.lock()
+    }
+}
@@ -0,0 +1,72 @@
+use parking_lot::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Spreads texture uploads from background threads over several frames, so
+/// that the frame that submits them is not stalled by a whole document's
+/// worth of layer chunks at once.
+#[derive(Debug, Default)]
+pub struct UploadThrottle {
+    state: Mutex<UploadState>,
+    frame: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct UploadState {
+    /// Bytes that may be uploaded per frame, `None` when not throttled.
+    limit: Option<u64>,
+    /// Bytes uploaded since the last frame.
+    used: u64,
+    /// Whether an upload is waiting for the next frame.
+    waiting: bool,
+    /// Bytes uploaded since startup.
+    total: u64,
+}
+
+impl UploadThrottle {
+    pub const DEFAULT_LIMIT: u64 = 32 << 20;
+    /// Longest that an upload waits for a frame, in case frames stop being
+    /// drawn, such as while the window is minimized.
+    const MAX_WAIT: Duration = Duration::from_millis(100);
+
+    pub fn limit(&self) -> Option<u64> {
+        self.state.lock().limit
+    }
+
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.state.lock().limit = limit;
+        self.frame.notify_all();
+    }
+
+    /// Wait until an upload of `bytes` fits in the current frame. Uploads
+    /// larger than the limit go through alone. Never call this from the
+    /// thread that draws the frames.
+    pub fn acquire(&self, bytes: u64) {
+        let mut state = self.state.lock();
+        while let Some(limit) = state.limit {
+            if state.used == 0 || state.used + bytes <= limit {
+                break;
+            }
+            state.waiting = true;
+            if self.frame.wait_for(&mut state, Self::MAX_WAIT).timed_out() {
+                break;
+            }
+        }
+        state.used += bytes;
+        state.total += bytes;
+    }
+
+    /// Bytes uploaded since startup, to measure the upload bandwidth.
+    pub fn total(&self) -> u64 {
+        self.state.lock().total
+    }
+
+    /// Start the uploads of a new frame, returning whether any were waiting
+    /// for it, in which case another frame should follow to submit them.
+    pub fn next_frame(&self) -> bool {
+        let mut state = self.state.lock();
+        state.used = 0;
+        let waiting = std::mem::take(&mut state.waiting);
+        self.frame.notify_all();
+        waiting
+    }
+}
@@ -0,0 +1,218 @@
+use super::{BlendingMode, ProcreateFile, SilicaGroup, SilicaHierarchy, SilicaLayer};
+use std::collections::{HashMap, HashSet};
+
+/// Properties of a layer or group that can be changed in the viewer.
+/// Groups are never clipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerProperties {
+    pub hidden: bool,
+    pub clipped: bool,
+    pub opacity: f32,
+    pub blend: BlendingMode,
+}
+
+impl LayerProperties {
+    pub fn of(layer: &SilicaLayer) -> Self {
+        Self {
+            hidden: layer.hidden,
+            clipped: layer.clipped,
+            opacity: layer.opacity,
+            blend: layer.blend,
+        }
+    }
+
+    pub fn of_node(node: &SilicaHierarchy) -> Self {
+        match node {
+            SilicaHierarchy::Layer(layer) => Self::of(layer),
+            SilicaHierarchy::Group(group) => Self {
+                hidden: group.hidden,
+                clipped: false,
+                opacity: group.opacity,
+                blend: group.blend,
+            },
+        }
+    }
+
+    fn apply(self, layer: &mut SilicaLayer) {
+        layer.hidden = self.hidden;
+        layer.clipped = self.clipped;
+        layer.opacity = self.opacity;
+        layer.blend = self.blend;
+    }
+
+    pub fn apply_node(self, node: &mut SilicaHierarchy) {
+        match node {
+            SilicaHierarchy::Layer(layer) => self.apply(layer),
+            SilicaHierarchy::Group(group) => {
+                group.hidden = self.hidden;
+                group.opacity = self.opacity;
+                group.blend = self.blend;
+            }
+        }
+    }
+}
+
+/// How the layers of a file changed when it was read again from disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReloadDiff {
+    /// Layers whose changes made in the viewer were kept.
+    pub kept: usize,
+    /// Layers whose properties changed on disk, dropping any changes made
+    /// to them in the viewer.
+    pub changed: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl ReloadDiff {
+    /// Short description for a notification.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if self.kept > 0 {
+            parts.push(format!("kept changes to {} layers", self.kept));
+        }
+        for (count, what) in [
+            (self.changed, "changed"),
+            (self.added, "added"),
+            (self.removed, "removed"),
+        ] {
+            if count > 0 {
+                parts.push(format!("{count} layers {what}"));
+            }
+        }
+        if parts.is_empty() {
+            "no layer changes".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+impl SilicaGroup {
+    /// Properties of every layer beneath this group, by UUID.
+    pub fn layer_properties(&self) -> HashMap<String, LayerProperties> {
+        self.iter_layers()
+            .map(|layer| (layer.uuid.clone(), LayerProperties::of(layer)))
+            .collect()
+    }
+}
+
+impl ProcreateFile {
+    /// Carry the changes made to this file's layers in the viewer over to
+    /// a newer version of the file read from disk. Layers are matched by
+    /// UUID, and only keep their changes if their properties on disk are
+    /// the same as when this version was read.
+    pub fn carry_changes(&self, newer: &mut ProcreateFile) -> ReloadDiff {
+        let current = self.layers.layer_properties();
+        let mut diff = ReloadDiff::default();
+        // Layers can share a UUID, so count the layers found by UUID.
+        let mut found = HashSet::new();
+        newer.layers.visit_mut(|_, node| {
+            let SilicaHierarchy::Layer(layer) = node else {
+                return;
+            };
+            let (Some(stored), Some(current)) = (
+                self.stored_layers.get(&layer.uuid),
+                current.get(&layer.uuid),
+            ) else {
+                diff.added += 1;
+                return;
+            };
+            found.insert(layer.uuid.clone());
+            if LayerProperties::of(layer) != *stored {
+                diff.changed += 1;
+            } else if current != stored {
+                current.apply(layer);
+                diff.kept += 1;
+            }
+        });
+        diff.removed = current.len() - found.len();
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::{Document, Layer};
+    use super::*;
+
+    fn open(doc: &Document) -> ProcreateFile {
+        ProcreateFile::open_in_memory(&doc.bytes()).unwrap().0
+    }
+
+    fn layer<'a>(file: &'a ProcreateFile, uuid: &str) -> &'a SilicaLayer {
+        file.layers
+            .iter_layers()
+            .find(|layer| layer.uuid == uuid)
+            .unwrap()
+    }
+
+    #[test]
+    fn changes_are_kept_unless_the_layer_changed_on_disk() {
+        let doc = Document::new(2, 2)
+            .layer(Layer::new("kept", [255; 4]))
+            .layer(Layer::new("changed", [255; 4]))
+            .layer(Layer::new("removed", [255; 4]));
+        let mut current = open(&doc);
+        current.layers.visit_mut(|_, node| {
+            if let SilicaHierarchy::Layer(layer) = node {
+                layer.hidden = true;
+            }
+        });
+
+        let mut changed = Layer::new("changed", [255; 4]);
+        changed.opacity = 0.5;
+        let doc = Document::new(2, 2)
+            .layer(Layer::new("kept", [255; 4]))
+            .layer(changed)
+            .layer(Layer::new("added", [255; 4]));
+        let mut newer = open(&doc);
+        let diff = current.carry_changes(&mut newer);
+
+        assert_eq!(
+            diff,
+            ReloadDiff {
+                kept: 1,
+                changed: 1,
+                added: 1,
+                removed: 1,
+            }
+        );
+        assert!(layer(&newer, "kept").hidden);
+        assert!(!layer(&newer, "changed").hidden);
+        assert_eq!(layer(&newer, "changed").opacity, 0.5);
+        assert!(!layer(&newer, "added").hidden);
+        assert_eq!(
+            diff.summary(),
+            "kept changes to 1 layers, 1 layers changed, 1 layers added, 1 layers removed"
+        );
+    }
+
+    #[test]
+    fn layers_sharing_a_uuid_are_found_once() {
+        let doc = Document::new(2, 2)
+            .layer(Layer::new("twin", [255; 4]))
+            .layer(Layer::new("twin", [255; 4]))
+            .layer(Layer::new("gone", [255; 4]));
+        let current = open(&doc);
+        let doc = Document::new(2, 2)
+            .layer(Layer::new("twin", [255; 4]))
+            .layer(Layer::new("twin", [255; 4]));
+        let mut newer = open(&doc);
+
+        let diff = current.carry_changes(&mut newer);
+        assert_eq!(diff.removed, 1);
+        assert_eq!(diff.summary(), "1 layers removed");
+
+        let diff = open(&doc).carry_changes(&mut newer);
+        assert_eq!(diff, ReloadDiff::default());
+    }
+
+    #[test]
+    fn unchanged_files_report_no_changes() {
+        let doc = Document::new(2, 2).layer(Layer::new("layer", [255; 4]));
+        let diff = open(&doc).carry_changes(&mut open(&doc));
+        assert_eq!(diff, ReloadDiff::default());
+        assert_eq!(diff.summary(), "no layer changes");
+    }
+}
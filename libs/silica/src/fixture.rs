@@ -0,0 +1,334 @@
+//! Procreate documents put together in memory, for tests that load files
+//! without a GPU or a sample document on disk, and loaded hierarchies for
+//! tests that only need the layers.
+
+use super::{BlendingMode, SilicaGroup, SilicaHierarchy, SilicaLayer};
+use crate::ns_archive::Size;
+use plist::{Dictionary, Uid, Value};
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Layer of a [`Document`], filled with a single premultiplied color.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub uuid: String,
+    pub name: Option<String>,
+    pub blend: u32,
+    pub opacity: f32,
+    pub hidden: bool,
+    pub clipped: bool,
+    /// Color of every pixel, or `None` for a layer without any tiles.
+    pub fill: Option<[u8; 4]>,
+    /// Columns and rows of the filled tiles, every tile if `None`.
+    pub tiles: Option<Vec<(u32, u32)>>,
+}
+
+impl Layer {
+    pub fn new(uuid: &str, fill: [u8; 4]) -> Self {
+        Self {
+            uuid: uuid.to_string(),
+            name: Some(uuid.to_string()),
+            blend: 0,
+            opacity: 1.0,
+            hidden: false,
+            clipped: false,
+            fill: Some(fill),
+            tiles: None,
+        }
+    }
+
+    /// Fill only the tiles at the given columns and rows.
+    pub fn tiles(mut self, tiles: &[(u32, u32)]) -> Self {
+        self.tiles = Some(tiles.to_vec());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Layer(Layer),
+    Group {
+        name: Option<String>,
+        hidden: bool,
+        opacity: f32,
+        children: Vec<Node>,
+    },
+}
+
+/// Document archive and tiles of a Procreate file.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    pub background_color: [f32; 4],
+    pub background_hidden: bool,
+    /// Layers from the top down, as Procreate stores them.
+    pub layers: Vec<Node>,
+    /// Files stored in the archive besides the chunks of the layers.
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Document {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            tile_size: 256,
+            background_color: [1.0; 4],
+            background_hidden: false,
+            layers: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn layer(mut self, layer: Layer) -> Self {
+        self.layers.push(Node::Layer(layer));
+        self
+    }
+
+    pub fn group(mut self, name: &str, children: Vec<Node>) -> Self {
+        self.layers.push(Node::Group {
+            name: Some(name.to_string()),
+            hidden: false,
+            opacity: 1.0,
+            children,
+        });
+        self
+    }
+
+    /// Bytes of the `.procreate` file.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes_with(|_| {})
+    }
+
+    /// Bytes of the `.procreate` file, with the objects of its archive
+    /// changed by `edit` first, such as to break it. The root object is the
+    /// last one.
+    pub fn bytes_with(&self, edit: impl FnOnce(&mut Vec<Value>)) -> Vec<u8> {
+        let mut archiver = Archiver::default();
+        let root = self.root(&mut archiver);
+        let root = archiver.push(Value::Dictionary(root));
+        edit(&mut archiver.objects);
+        let archive = archiver.finish(root);
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("Document.archive", options).unwrap();
+        archive.to_writer_binary(&mut zip).unwrap();
+        for (path, data) in self.chunks().iter().chain(&self.files) {
+            zip.start_file(path, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn root(&self, archiver: &mut Archiver) -> Dictionary {
+        let layers = self
+            .layers
+            .iter()
+            .map(|node| node.encode(archiver))
+            .collect();
+        let mut composite = Layer::new("composite", [0; 4]);
+        composite.fill = None;
+
+        let mut root = Dictionary::new();
+        root.insert("$class".into(), archiver.class("SilicaDocument"));
+        root.insert(
+            "size".into(),
+            Value::String(format!("{{{}, {}}}", self.width, self.height)),
+        );
+        root.insert("tileSize".into(), Value::from(u64::from(self.tile_size)));
+        root.insert("unwrappedLayers".into(), archiver.array(layers));
+        root.insert("composite".into(), composite.encode(archiver));
+        root.insert(
+            "backgroundHidden".into(),
+            Value::Boolean(self.background_hidden),
+        );
+        root.insert(
+            "backgroundColor".into(),
+            Value::Data(
+                self.background_color
+                    .iter()
+                    .flat_map(|c| c.to_le_bytes())
+                    .collect(),
+            ),
+        );
+        root.insert("strokeCount".into(), Value::from(0u64));
+        root.insert("orientation".into(), Value::from(1u64));
+        root.insert("flippedHorizontally".into(), Value::Boolean(false));
+        root.insert("flippedVertically".into(), Value::Boolean(false));
+        root
+    }
+
+    /// Raw chunks of every filled layer, named after their tile. Layers
+    /// that share a UUID share their chunks too.
+    fn chunks(&self) -> Vec<(String, Vec<u8>)> {
+        let mut layers = Vec::<&Layer>::new();
+        let mut stack = self.layers.iter().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Layer(layer) if layers.iter().all(|other| other.uuid != layer.uuid) => {
+                    layers.push(layer)
+                }
+                Node::Layer(_) => {}
+                Node::Group { children, .. } => stack.extend(children),
+            }
+        }
+
+        let size = self.tile_size;
+        let mut chunks = Vec::new();
+        for layer in layers {
+            let Some(fill) = layer.fill else {
+                continue;
+            };
+            for col in 0..self.width.div_ceil(size) {
+                for row in 0..self.height.div_ceil(size) {
+                    if layer
+                        .tiles
+                        .as_ref()
+                        .is_some_and(|t| !t.contains(&(col, row)))
+                    {
+                        continue;
+                    }
+                    let width = size.min(self.width - col * size);
+                    let height = size.min(self.height - row * size);
+                    let path = format!("{}/{col}~{row}.chunk", layer.uuid);
+                    chunks.push((path, fill.repeat((width * height) as usize)));
+                }
+            }
+        }
+        chunks
+    }
+}
+
+impl Layer {
+    fn encode(&self, archiver: &mut Archiver) -> Value {
+        let mut coder = Dictionary::new();
+        coder.insert("$class".into(), archiver.class("SilicaLayer"));
+        coder.insert("UUID".into(), Value::String(self.uuid.clone()));
+        if let Some(name) = &self.name {
+            coder.insert("name".into(), Value::String(name.clone()));
+        }
+        coder.insert("blend".into(), Value::from(u64::from(self.blend)));
+        coder.insert("opacity".into(), Value::Real(f64::from(self.opacity)));
+        coder.insert("hidden".into(), Value::Boolean(self.hidden));
+        coder.insert("clipped".into(), Value::Boolean(self.clipped));
+        coder.insert("version".into(), Value::from(1u64));
+        archiver.push(Value::Dictionary(coder))
+    }
+}
+
+impl Node {
+    fn encode(&self, archiver: &mut Archiver) -> Value {
+        match self {
+            Node::Layer(layer) => layer.encode(archiver),
+            Node::Group {
+                name,
+                hidden,
+                opacity,
+                children,
+            } => {
+                let children = children.iter().map(|node| node.encode(archiver)).collect();
+                let mut coder = Dictionary::new();
+                coder.insert("$class".into(), archiver.class("SilicaGroup"));
+                if let Some(name) = name {
+                    coder.insert("name".into(), Value::String(name.clone()));
+                }
+                coder.insert("isHidden".into(), Value::Boolean(*hidden));
+                coder.insert("opacity".into(), Value::Real(f64::from(*opacity)));
+                coder.insert("children".into(), archiver.array(children));
+                archiver.push(Value::Dictionary(coder))
+            }
+        }
+    }
+}
+
+/// Loaded 1x1 layer with the given texture, identified after it, visible
+/// and unclipped.
+pub fn loaded_layer(image: u32) -> SilicaLayer {
+    SilicaLayer {
+        blend: BlendingMode::Normal,
+        clipped: false,
+        hidden: false,
+        mask: None,
+        name: None,
+        opacity: 1.0,
+        size: Size {
+            width: 1,
+            height: 1,
+        },
+        uuid: image.to_string(),
+        version: 0,
+        image,
+        chunks: Vec::new(),
+        damage: None,
+    }
+}
+
+/// Loaded visible group with the given texture and children, listed from
+/// the top down like documents do.
+pub fn loaded_group(image: u32, children: Vec<SilicaHierarchy>) -> SilicaGroup {
+    SilicaGroup {
+        blend: BlendingMode::Normal,
+        hidden: false,
+        children,
+        name: None,
+        opacity: 1.0,
+        image,
+    }
+}
+
+/// Objects of an `NSKeyedArchiver` archive, which refer to each other by
+/// their index.
+struct Archiver {
+    objects: Vec<Value>,
+}
+
+impl Default for Archiver {
+    fn default() -> Self {
+        Self {
+            objects: vec![Value::String("$null".to_string())],
+        }
+    }
+}
+
+impl Archiver {
+    fn push(&mut self, value: Value) -> Value {
+        self.objects.push(value);
+        Value::Uid(Uid::new(self.objects.len() as u64 - 1))
+    }
+
+    fn class(&mut self, name: &str) -> Value {
+        let mut class = Dictionary::new();
+        class.insert("$classname".into(), Value::String(name.to_string()));
+        class.insert(
+            "$classes".into(),
+            Value::Array(vec![
+                Value::String(name.to_string()),
+                Value::String("NSObject".to_string()),
+            ]),
+        );
+        self.push(Value::Dictionary(class))
+    }
+
+    fn array(&mut self, objects: Vec<Value>) -> Value {
+        let mut array = Dictionary::new();
+        array.insert("$class".into(), self.class("NSArray"));
+        array.insert("NS.objects".into(), Value::Array(objects));
+        self.push(Value::Dictionary(array))
+    }
+
+    fn finish(self, root: Value) -> Value {
+        let mut top = Dictionary::new();
+        top.insert("root".into(), root);
+
+        let mut archive = Dictionary::new();
+        archive.insert("$version".into(), Value::from(100000u64));
+        archive.insert("$archiver".into(), Value::String("NSKeyedArchiver".into()));
+        archive.insert("$top".into(), Value::Dictionary(top));
+        archive.insert("$objects".into(), Value::Array(self.objects));
+        Value::Dictionary(archive)
+    }
+}
@@ -0,0 +1,429 @@
+use super::ir::{chunk_position, ChunkCompression, SilicaIRHierarchy, SilicaIRLayer};
+use super::{ProcreateFile, SilicaError, TilingData, ZipArchiveMmap};
+use super::{IGNORED_CLASSES, IGNORED_KEYS};
+use crate::ns_archive::{NsArchiveError, NsKeyedArchive, SchemaCoverage, Size, WrappedArray};
+use plist::Dictionary;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use zip::read::ZipArchive;
+
+/// Chunks of a layer as found in the archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerReport {
+    /// Names of the groups leading to the layer and of the layer itself.
+    pub path: String,
+    pub uuid: String,
+    pub chunks: usize,
+    /// Tiles of the canvas without a chunk. Procreate leaves out tiles that
+    /// are fully transparent, so these are only missing if the layer was
+    /// painted there.
+    pub empty_tiles: usize,
+    /// Size of the chunks as stored, and of the tiles once decompressed.
+    pub compressed_size: u64,
+    pub decompressed_size: u64,
+    /// Chunks that cannot be loaded and properties that fail to decode.
+    pub problems: Vec<String>,
+}
+
+/// Structure of a file checked without decoding any of its tiles, listing
+/// what would fail to load or be ignored when it is opened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveReport {
+    pub size: Size<u32>,
+    pub tile_size: u32,
+    pub layers: Vec<LayerReport>,
+    pub groups: usize,
+    /// The flattened image of the document, which is not shown but is
+    /// loaded along with the layers.
+    pub composite: Option<LayerReport>,
+    /// Chunks whose folder is not the UUID of any layer.
+    pub orphaned_chunks: Vec<String>,
+    /// Number of chunks compressed with each scheme.
+    pub compression: BTreeMap<ChunkCompression, usize>,
+    /// Keys of the document and of groups that fail to decode.
+    pub problems: Vec<String>,
+    /// Parts of the archive that are not understood.
+    pub coverage: SchemaCoverage,
+}
+
+impl ArchiveReport {
+    /// Every layer including the composite.
+    fn all_layers(&self) -> impl Iterator<Item = &LayerReport> {
+        self.layers.iter().chain(&self.composite)
+    }
+
+    /// Whether nothing was found that fails to load or is left unused.
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+            && self.orphaned_chunks.is_empty()
+            && self.all_layers().all(|layer| layer.problems.is_empty())
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.all_layers().map(|layer| layer.chunks).sum()
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.all_layers().map(|layer| layer.compressed_size).sum()
+    }
+
+    /// Approximate memory taken by the tiles once decompressed.
+    pub fn decompressed_size(&self) -> u64 {
+        self.all_layers().map(|layer| layer.decompressed_size).sum()
+    }
+
+    /// Plain text listing of the report, as printed by `silicate inspect`,
+    /// with the app that read the file named as `software`.
+    pub fn summary(&self, software: &str) -> String {
+        let mib = |bytes: u64| format!("{:.1} MiB", bytes as f64 / f64::from(1 << 20));
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "Canvas: {} by {}, tiles of {}",
+            self.size.width, self.size.height, self.tile_size
+        );
+        let _ = writeln!(
+            text,
+            "Layers: {}, groups: {}",
+            self.layers.len(),
+            self.groups
+        );
+        let compression = self
+            .compression
+            .iter()
+            .map(|(compression, count)| format!("{count} {}", compression.as_str()))
+            .collect::<Vec<_>>();
+        let _ = writeln!(
+            text,
+            "Chunks: {} ({})",
+            self.chunk_count(),
+            if compression.is_empty() {
+                String::from("none")
+            } else {
+                compression.join(", ")
+            }
+        );
+        let _ = writeln!(
+            text,
+            "Size: {} compressed, about {} decompressed",
+            mib(self.compressed_size()),
+            mib(self.decompressed_size())
+        );
+        let _ = writeln!(
+            text,
+            "Format coverage: {} unknown classes, {} unknown keys",
+            self.coverage.unknown_classes.len(),
+            self.coverage.unknown_key_count()
+        );
+
+        text.push('\n');
+        for (layer, label) in self
+            .layers
+            .iter()
+            .map(|layer| (layer, layer.path.as_str()))
+            .chain(self.composite.iter().map(|layer| (layer, "(Composite)")))
+        {
+            let _ = writeln!(
+                text,
+                "{label} [{}]: {} chunks, {} empty tiles, {}",
+                layer.uuid,
+                layer.chunks,
+                layer.empty_tiles,
+                mib(layer.decompressed_size)
+            );
+            for problem in &layer.problems {
+                let _ = writeln!(text, "  - {problem}");
+            }
+        }
+        if !self.orphaned_chunks.is_empty() {
+            let _ = writeln!(text, "\nOrphaned chunks: {}", self.orphaned_chunks.len());
+            for chunk in &self.orphaned_chunks {
+                let _ = writeln!(text, "  - {chunk}");
+            }
+        }
+        if !self.problems.is_empty() {
+            let _ = writeln!(text, "\nInvalid keys: {}", self.problems.len());
+            for problem in &self.problems {
+                let _ = writeln!(text, "  - {problem}");
+            }
+        }
+        if !self.coverage.is_complete() {
+            let _ = writeln!(text, "\n{}", self.coverage.summary(software));
+        }
+        let _ = write!(
+            text,
+            "\n{}",
+            if self.is_intact() {
+                "The file is intact."
+            } else {
+                "Problems were found in the file."
+            }
+        );
+        text
+    }
+}
+
+impl ProcreateFile {
+    /// Check the structure of a file without decoding any of its tiles.
+    pub fn inspect(path: &Path) -> Result<ArchiveReport, SilicaError> {
+        let file = OpenOptions::new().read(true).write(false).open(path)?;
+        let mapping = unsafe { memmap2::Mmap::map(&file)? };
+        Self::inspect_bytes(&mapping)
+    }
+
+    /// Check the structure of a file held in memory.
+    pub fn inspect_bytes(bytes: &[u8]) -> Result<ArchiveReport, SilicaError> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let nka = {
+            let mut document = archive.by_name("Document.archive")?;
+            let mut buf = Vec::with_capacity(document.size() as usize);
+            document.read_to_end(&mut buf)?;
+            NsKeyedArchive::from_reader(Cursor::new(buf))?.track_reads()
+        };
+        inspect_ns(&mut archive, &nka)
+    }
+}
+
+fn inspect_ns(
+    archive: &mut ZipArchiveMmap<'_>,
+    nka: &NsKeyedArchive,
+) -> Result<ArchiveReport, SilicaError> {
+    let root = nka.root()?;
+    let size = nka.fetch::<Size<u32>>(root, "size")?;
+    let tile_size = nka.fetch::<u32>(root, "tileSize")?;
+    let tile = TilingData::new(size, tile_size)?;
+
+    // The same keys that are read when the file is opened, so that the
+    // schema coverage matches.
+    let mut problems = Vec::new();
+    let mut check = |key: &str, result: Result<(), NsArchiveError>| {
+        if let Err(err) = result {
+            problems.push(format!("{key}: {err}"));
+        }
+    };
+    check(
+        "authorName",
+        nka.fetch::<Option<String>>(root, "authorName").map(drop),
+    );
+    check(
+        "backgroundHidden",
+        nka.fetch::<bool>(root, "backgroundHidden").map(drop),
+    );
+    check(
+        "strokeCount",
+        nka.fetch::<usize>(root, "strokeCount").map(drop),
+    );
+    check(
+        "backgroundColor",
+        nka.fetch::<&[u8]>(root, "backgroundColor")
+            .and_then(|color| match color.len() {
+                16 => Ok(()),
+                _ => Err(NsArchiveError::TypeMismatch("backgroundColor".to_string())),
+            }),
+    );
+    check("name", nka.fetch::<Option<String>>(root, "name").map(drop));
+    check(
+        "orientation",
+        nka.fetch::<u32>(root, "orientation").map(drop),
+    );
+    for key in ["flippedHorizontally", "flippedVertically"] {
+        check(key, nka.fetch::<bool>(root, key).map(drop));
+    }
+    // Optional values that are skipped when they are malformed.
+    let _ = nka.fetch::<Option<f32>>(root, "SilicaDocumentArchiveDPIKey");
+    let _ = nka.fetch::<Option<u32>>(root, "SilicaDocumentArchiveUnitKey");
+    let _ = nka.fetch::<Option<f64>>(root, "SilicaDocumentTrackedTimeKey");
+    for key in ["videoEnabled", "SilicaDocumentVideoPurgedKey"] {
+        let _ = nka.fetch::<Option<bool>>(root, key);
+    }
+    for key in ["videoQualityKey", "videoResolutionKey"] {
+        let _ = nka.fetch::<Option<String>>(root, key);
+    }
+    if let Ok(Some(profile)) = nka.fetch::<Option<&Dictionary>>(root, "colorProfile") {
+        let _ = nka.fetch::<&[u8]>(profile, "SiColorProfileArchiveICCDataKey");
+    }
+    if let Ok(Some(guide)) = nka.fetch::<Option<&Dictionary>>(root, "drawingguide") {
+        let _ = nka.fetch::<f32>(guide, "gridSize");
+    }
+
+    // Chunks by the folder they are stored in.
+    let mut chunks = HashMap::<String, Vec<String>>::new();
+    for name in archive.file_names() {
+        if let Some((folder, _)) = name.split_once('/') {
+            // Chunks in schemes added later may be named differently.
+            if ChunkCompression::from_extension(name).is_some()
+                || chunk_position(folder, name).is_some()
+            {
+                chunks
+                    .entry(folder.to_string())
+                    .or_default()
+                    .push(name.to_string());
+            }
+        }
+    }
+
+    let mut report = ArchiveReport {
+        size,
+        tile_size,
+        layers: Vec::new(),
+        groups: 0,
+        composite: None,
+        orphaned_chunks: Vec::new(),
+        compression: BTreeMap::new(),
+        problems: Vec::new(),
+        coverage: SchemaCoverage::default(),
+    };
+    let mut inspector = LayerInspector {
+        archive,
+        tile: &tile,
+        size,
+        chunks: &mut chunks,
+        seen: HashSet::new(),
+        problems: Vec::new(),
+        compression: &mut report.compression,
+    };
+
+    match nka.fetch::<WrappedArray<SilicaIRHierarchy>>(root, "unwrappedLayers") {
+        Ok(hierarchy) => {
+            let mut path = String::new();
+            for ir in &hierarchy.objects {
+                inspector.visit(ir, &mut path, &mut report.layers, &mut report.groups);
+            }
+        }
+        Err(err) => check("unwrappedLayers", Err(err)),
+    }
+    match nka.fetch::<SilicaIRLayer>(root, "composite") {
+        Ok(composite) => report.composite = Some(inspector.layer(&composite, String::new())),
+        Err(err) => check("composite", Err(err)),
+    }
+
+    problems.extend(inspector.problems);
+    report.orphaned_chunks = chunks.into_values().flatten().collect();
+    report.orphaned_chunks.sort_unstable();
+    report.problems = problems;
+    // Everything that is read when the file is opened has been by now.
+    report.coverage = nka
+        .coverage(IGNORED_CLASSES, IGNORED_KEYS)
+        .unwrap_or_default();
+    Ok(report)
+}
+
+struct LayerInspector<'a, 'b> {
+    archive: &'a mut ZipArchiveMmap<'b>,
+    tile: &'a TilingData,
+    size: Size<u32>,
+    /// Chunks that no layer has claimed yet, by folder.
+    chunks: &'a mut HashMap<String, Vec<String>>,
+    /// UUIDs of the layers visited so far.
+    seen: HashSet<String>,
+    /// Groups whose properties fail to decode.
+    problems: Vec<String>,
+    compression: &'a mut BTreeMap<ChunkCompression, usize>,
+}
+
+impl LayerInspector<'_, '_> {
+    fn visit(
+        &mut self,
+        ir: &SilicaIRHierarchy,
+        path: &mut String,
+        layers: &mut Vec<LayerReport>,
+        groups: &mut usize,
+    ) {
+        let parent_len = path.len();
+        if parent_len > 0 {
+            path.push('/');
+        }
+        match ir {
+            SilicaIRHierarchy::Layer(layer) => {
+                if let Ok(properties) = layer.properties(self.size) {
+                    path.push_str(properties.name.as_deref().unwrap_or_default());
+                }
+                layers.push(self.layer(layer, path.clone()));
+            }
+            SilicaIRHierarchy::Group(group) => {
+                *groups += 1;
+                match group.properties() {
+                    Ok(properties) => path.push_str(properties.name.as_deref().unwrap_or_default()),
+                    Err(err) => self.problems.push(format!("group {path}: {err}")),
+                }
+                for child in &group.children {
+                    self.visit(child, path, layers, groups);
+                }
+            }
+        }
+        path.truncate(parent_len);
+    }
+
+    fn layer(&mut self, layer: &SilicaIRLayer, path: String) -> LayerReport {
+        let mut report = LayerReport {
+            path,
+            uuid: String::new(),
+            chunks: 0,
+            empty_tiles: 0,
+            compressed_size: 0,
+            decompressed_size: 0,
+            problems: Vec::new(),
+        };
+        if let Err(err) = layer.properties(self.size) {
+            report.problems.push(format!("properties: {err}"));
+        }
+        let uuid = match layer.uuid() {
+            Ok(uuid) => uuid,
+            Err(err) => {
+                report.problems.push(format!("UUID: {err}"));
+                return report;
+            }
+        };
+        if !self.seen.insert(uuid.clone()) {
+            report
+                .problems
+                .push(String::from("UUID is shared with another layer"));
+        }
+
+        let chunks = self.chunks.remove(&uuid).unwrap_or_default();
+        let mut positions = HashSet::new();
+        for chunk in &chunks {
+            report.chunks += 1;
+            let Some((col, row)) = chunk_position(&uuid, chunk) else {
+                report.problems.push(format!("{chunk}: unreadable name"));
+                continue;
+            };
+            let Some(tile) = self.tile.tile_size(col, row) else {
+                report.problems.push(format!("{chunk}: outside the canvas"));
+                continue;
+            };
+            if !positions.insert((col, row)) {
+                report.problems.push(format!("{chunk}: duplicate tile"));
+                continue;
+            }
+            let tile_len = u64::from(tile.width) * u64::from(tile.height) * 4;
+            match self.archive.by_name(chunk) {
+                Ok(file) if file.size() > 0 => {
+                    let len = file.size();
+                    report.compressed_size += len;
+                    // Only the magic bytes are read to tell the scheme apart.
+                    let mut head = Vec::with_capacity(4);
+                    let detected = file
+                        .take(4)
+                        .read_to_end(&mut head)
+                        .map_err(SilicaError::from)
+                        .and_then(|_| ChunkCompression::detect(chunk, &head, len, tile_len));
+                    match detected {
+                        Ok(compression) => *self.compression.entry(compression).or_default() += 1,
+                        Err(err) => report.problems.push(format!("{chunk}: {err}")),
+                    }
+                }
+                Ok(_) => report.problems.push(format!("{chunk}: empty")),
+                Err(err) => report.problems.push(format!("{chunk}: {err}")),
+            }
+            report.decompressed_size += tile_len;
+        }
+        report.empty_tiles =
+            (self.tile.columns as usize * self.tile.rows as usize).saturating_sub(positions.len());
+        report.uuid = uuid;
+        report
+    }
+}
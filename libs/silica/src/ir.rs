@@ -0,0 +1,563 @@
+use std::io::Read;
+
+use super::tiles::{DecodedTile, TileSink};
+use super::{LayerDamage, LoadProgress, TileSampler};
+use super::{SilicaError, SilicaGroup, SilicaHierarchy, SilicaLayer, TilingData, ZipArchiveMmap};
+use crate::ns_archive::{NsArchiveError, NsClass, Size, WrappedArray};
+use crate::ns_archive::{NsDecode, NsKeyedArchive};
+use crate::BlendingMode;
+use image::{Pixel, Rgba};
+use minilzo_rs::LZO;
+use once_cell::sync::OnceCell;
+use plist::{Dictionary, Value};
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use regex::Regex;
+
+pub(super) enum SilicaIRHierarchy<'a> {
+    Layer(SilicaIRLayer<'a>),
+    Group(SilicaIRGroup<'a>),
+}
+
+/// Column and row of the tile that a chunk of a layer is named after, such
+/// as `{uuid}/3~4.chunk`.
+pub(super) fn chunk_position(uuid: &str, path: &str) -> Option<(u32, u32)> {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    let index_regex = INSTANCE.get_or_init(|| Regex::new("(\\d+)~(\\d+)").unwrap());
+
+    let chunk_str = path.get(uuid.len()..path.find('.').unwrap_or(path.len()))?;
+    let captures = index_regex.captures(chunk_str)?;
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?))
+}
+
+/// How the tile in a chunk is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkCompression {
+    Lzo,
+    Lz4,
+    Zstd,
+    /// Pixels stored as they are.
+    Raw,
+}
+
+impl ChunkCompression {
+    const LZ4_MAGIC: &'static [u8] = b"bv4";
+    const ZSTD_MAGIC: &'static [u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+    #[allow(dead_code)]
+    pub fn all() -> &'static [ChunkCompression] {
+        &[Self::Lzo, Self::Lz4, Self::Zstd, Self::Raw]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lzo => "LZO",
+            Self::Lz4 => "LZ4",
+            Self::Zstd => "zstd",
+            Self::Raw => "uncompressed",
+        }
+    }
+
+    /// Scheme of a chunk from the extensions Procreate names them with.
+    pub(super) fn from_extension(path: &str) -> Option<Self> {
+        if path.ends_with(".chunk") {
+            Some(Self::Lzo)
+        } else if path.ends_with(".lz4") {
+            Some(Self::Lz4)
+        } else {
+            None
+        }
+    }
+
+    /// Scheme of a chunk of `len` bytes from its first bytes, for a tile of
+    /// `tile_len` bytes once decompressed. Chunks of exactly the tile's size
+    /// are raw whatever their first pixel is, as that can look like magic
+    /// bytes. LZO has no magic bytes, so it is only assumed for the extension
+    /// Procreate uses for it.
+    pub(super) fn detect(
+        path: &str,
+        head: &[u8],
+        len: u64,
+        tile_len: u64,
+    ) -> Result<Self, SilicaError> {
+        if len == tile_len {
+            Ok(Self::Raw)
+        } else if head.starts_with(Self::ZSTD_MAGIC) {
+            Ok(Self::Zstd)
+        } else if head.starts_with(Self::LZ4_MAGIC) {
+            Ok(Self::Lz4)
+        } else if Self::from_extension(path) == Some(Self::Lzo) {
+            Ok(Self::Lzo)
+        } else {
+            Err(SilicaError::UnknownCompression {
+                extension: path
+                    .rsplit_once('.')
+                    .map_or_else(String::new, |(_, extension)| extension.to_string()),
+                magic: head.iter().take(4).copied().collect(),
+            })
+        }
+    }
+}
+
+/// Decompress the chunk of the tile at a column and row, checking that it
+/// holds the whole tile.
+pub(super) fn decode_chunk(
+    path: &str,
+    chunk: &[u8],
+    tiling: &TilingData,
+    (col, row): (u32, u32),
+) -> Result<DecodedTile, SilicaError> {
+    static LZO_INSTANCE: OnceCell<LZO> = OnceCell::new();
+
+    let size = tiling
+        .tile_size(col, row)
+        .ok_or_else(|| SilicaError::ChunkOutsideCanvas(path.to_string()))?;
+
+    // RGBA = 4 channels of 8 bits each, lzo decompressed to lzo data
+    let data_len =
+        size.width as usize * size.height as usize * usize::from(Rgba::<u8>::CHANNEL_COUNT);
+    let compression = ChunkCompression::detect(path, chunk, chunk.len() as u64, data_len as u64)?;
+    let data = match compression {
+        ChunkCompression::Lz4 => {
+            // A byte past the tile is enough to tell that a chunk holds too
+            // much, without decoding however much a hostile chunk claims.
+            let mut data = Vec::with_capacity(data_len);
+            lz4_flex::frame::FrameDecoder::new(chunk)
+                .take(data_len as u64 + 1)
+                .read_to_end(&mut data)?;
+            data
+        }
+        ChunkCompression::Zstd => {
+            zstd::bulk::decompress(chunk, data_len).map_err(SilicaError::ZstdError)?
+        }
+        ChunkCompression::Raw => chunk.to_vec(),
+        ChunkCompression::Lzo => {
+            let lzo = LZO_INSTANCE.get_or_init(|| minilzo_rs::LZO::init().unwrap());
+            lzo.decompress_safe(chunk, data_len)?
+        }
+    };
+    // A chunk of the wrong length would otherwise fail the texture upload.
+    if data.len() != data_len {
+        return Err(SilicaError::TruncatedChunk {
+            path: path.to_string(),
+            len: data.len(),
+            expected: data_len,
+        });
+    }
+
+    Ok(DecodedTile {
+        origin: (col * tiling.size, row * tiling.size),
+        size,
+        data,
+    })
+}
+
+pub(super) struct SilicaIRLayer<'a> {
+    nka: &'a NsKeyedArchive,
+    coder: &'a Dictionary,
+    /// Index of the texture the layer is loaded into, assigned once the
+    /// whole hierarchy is known.
+    image: u32,
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct IRData<'a> {
+    pub(super) tile: &'a TilingData,
+    pub(super) archive: &'a ZipArchiveMmap<'a>,
+    pub(super) size: Size<u32>,
+    pub(super) file_names: &'a [&'a str],
+    pub(super) sink: &'a dyn TileSink,
+    pub(super) sampler: &'a TileSampler,
+    pub(super) progress: &'a LoadProgress<'a>,
+}
+
+impl<'a> NsDecode<'a> for SilicaIRLayer<'a> {
+    fn decode(
+        nka: &'a NsKeyedArchive,
+        key: &'a str,
+        val: &'a Value,
+    ) -> Result<Self, NsArchiveError> {
+        Ok(Self {
+            nka,
+            coder: <&'a Dictionary>::decode(nka, key, val)?,
+            image: u32::MAX,
+        })
+    }
+}
+
+impl SilicaIRLayer<'_> {
+    pub(super) fn uuid(&self) -> Result<String, NsArchiveError> {
+        self.nka.fetch::<String>(self.coder, "UUID")
+    }
+
+    /// Add the UUID of the layer along with its texture index, which is
+    /// assigned once the layers are sorted.
+    pub(super) fn collect_image<'s>(
+        &'s mut self,
+        images: &mut Vec<(String, &'s mut u32)>,
+    ) -> Result<(), NsArchiveError> {
+        images.push((self.uuid()?, &mut self.image));
+        Ok(())
+    }
+
+    pub(super) fn load(self, meta: &IRData<'_>) -> Result<SilicaLayer, SilicaError> {
+        let uuid = self.uuid()?;
+        let image = self.image;
+
+        let chunks = meta
+            .file_names
+            .iter()
+            .filter(|path| path.starts_with(&uuid))
+            .collect::<Vec<_>>();
+
+        // Chunks are named after the column and row of their tile.
+        let position = |path: &str| {
+            chunk_position(&uuid, path).ok_or_else(|| SilicaError::BadChunkName(path.to_string()))
+        };
+
+        // A broken chunk only costs its own tile; the rest of the layer still loads.
+        let errors = chunks
+            .par_iter()
+            .map(|path| -> Result<(), SilicaError> {
+                meta.progress.check()?;
+                let mut archive = meta.archive.clone();
+
+                let position = position(path)?;
+
+                let mut chunk = archive.by_name(path)?;
+
+                let mut buf = Vec::new();
+                chunk.read_to_end(&mut buf)?;
+
+                let tile = decode_chunk(path, &buf, meta.tile, position)?;
+                meta.sampler.offer(path, image, &tile);
+                meta.sink.write_tile(image, tile);
+                Ok(())
+            })
+            .inspect(|_| meta.progress.tick())
+            .zip(chunks.par_iter())
+            .filter_map(|(result, path)| result.err().map(|err| (path, err)))
+            .collect::<Vec<_>>();
+        // Cancelled tiles are not damage, the whole load is given up on.
+        meta.progress.check()?;
+
+        for (path, err) in &errors {
+            tracing::warn!("Failed to load chunk {path}: {err}");
+        }
+        let damage = errors.first().map(|(_, err)| LayerDamage {
+            tiles: errors.len(),
+            total: chunks.len(),
+            reason: err.to_string(),
+        });
+
+        let positions = chunks
+            .iter()
+            .filter_map(|path| position(path).ok())
+            .collect();
+
+        Ok(SilicaLayer {
+            image,
+            chunks: positions,
+            damage,
+            ..self.properties(meta.size)?
+        })
+    }
+
+    /// Decode the properties of the layer without any of its tiles.
+    pub(super) fn properties(&self, size: Size<u32>) -> Result<SilicaLayer, SilicaError> {
+        let nka = self.nka;
+        let coder = self.coder;
+        Ok(SilicaLayer {
+            blend: BlendingMode::from_u32(
+                nka.fetch::<Option<u32>>(coder, "extendedBlend")
+                    .transpose()
+                    .unwrap_or_else(|| nka.fetch::<u32>(coder, "blend"))?,
+            )?,
+            clipped: nka.fetch::<bool>(coder, "clipped")?,
+            hidden: nka.fetch::<bool>(coder, "hidden")?,
+            mask: None,
+            name: nka.fetch::<Option<String>>(coder, "name")?,
+            opacity: nka.fetch::<f32>(coder, "opacity")?,
+            size,
+            uuid: self.uuid()?,
+            version: nka.fetch::<u64>(coder, "version")?,
+            image: u32::MAX,
+            chunks: Vec::new(),
+            damage: None,
+        })
+    }
+}
+
+pub(super) struct SilicaIRGroup<'a> {
+    nka: &'a NsKeyedArchive,
+    coder: &'a Dictionary,
+    pub(super) children: Vec<SilicaIRHierarchy<'a>>,
+}
+
+/// Maximum nesting of groups, which guards against archives whose groups
+/// reference themselves.
+const MAX_GROUP_DEPTH: u32 = 64;
+
+thread_local! {
+    static GROUP_DEPTH: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+}
+
+impl<'a> NsDecode<'a> for SilicaIRGroup<'a> {
+    fn decode(
+        nka: &'a NsKeyedArchive,
+        key: &'a str,
+        val: &'a Value,
+    ) -> Result<Self, NsArchiveError> {
+        let coder = <&'a Dictionary>::decode(nka, key, val)?;
+
+        let depth = GROUP_DEPTH.get();
+        if depth >= MAX_GROUP_DEPTH {
+            return Err(NsArchiveError::TooDeep(MAX_GROUP_DEPTH));
+        }
+        GROUP_DEPTH.set(depth + 1);
+        let children = nka.fetch::<WrappedArray<SilicaIRHierarchy<'a>>>(coder, "children");
+        GROUP_DEPTH.set(depth);
+
+        Ok(Self {
+            nka,
+            coder,
+            children: children?.objects,
+        })
+    }
+}
+
+impl<'a> NsDecode<'a> for SilicaIRHierarchy<'a> {
+    fn decode(
+        nka: &'a NsKeyedArchive,
+        key: &'a str,
+        val: &'a Value,
+    ) -> Result<Self, NsArchiveError> {
+        let coder = <&'a Dictionary>::decode(nka, key, val)?;
+        let class = nka.fetch::<NsClass>(coder, "$class")?;
+
+        match class.class_name.as_str() {
+            "SilicaGroup" => Ok(SilicaIRGroup::<'a>::decode(nka, key, val).map(Self::Group)?),
+            "SilicaLayer" => Ok(SilicaIRLayer::<'a>::decode(nka, key, val).map(Self::Layer)?),
+            _ => Err(NsArchiveError::TypeMismatch("$class".to_string())),
+        }
+    }
+}
+
+impl<'a> SilicaIRGroup<'a> {
+    pub(super) fn collect_images<'s>(
+        &'s mut self,
+        images: &mut Vec<(String, &'s mut u32)>,
+    ) -> Result<(), NsArchiveError> {
+        self.children
+            .iter_mut()
+            .try_for_each(|ir| ir.collect_images(images))
+    }
+
+    pub(super) fn count_groups(&self) -> u32 {
+        1 + self
+            .children
+            .iter()
+            .map(|ir| ir.count_groups())
+            .sum::<u32>()
+    }
+
+    fn load(self, meta: &'a IRData<'a>) -> Result<SilicaGroup, SilicaError> {
+        let properties = self.properties()?;
+        Ok(SilicaGroup {
+            children: self
+                .children
+                .into_par_iter()
+                .map(|ir| ir.load(meta))
+                .collect::<Result<Vec<_>, _>>()?,
+            ..properties
+        })
+    }
+
+    /// Decode the properties of the group without any of its children.
+    pub(super) fn properties(&self) -> Result<SilicaGroup, SilicaError> {
+        let nka = self.nka;
+        let coder = self.coder;
+        Ok(SilicaGroup {
+            // Groups only carry blending information in some documents.
+            blend: BlendingMode::from_u32(
+                nka.fetch::<Option<u32>>(coder, "extendedBlend")?
+                    .or(nka.fetch::<Option<u32>>(coder, "blend")?)
+                    .unwrap_or(0),
+            )?,
+            hidden: nka.fetch::<bool>(coder, "isHidden")?,
+            name: nka.fetch::<Option<String>>(coder, "name")?,
+            children: Vec::new(),
+            opacity: nka.fetch::<Option<f32>>(coder, "opacity")?.unwrap_or(1.0),
+            // Assigned once the whole hierarchy is loaded.
+            image: u32::MAX,
+        })
+    }
+}
+
+impl<'a> SilicaIRHierarchy<'a> {
+    /// Collect the UUIDs and texture indices of the layers in the order
+    /// they are stored.
+    pub(super) fn collect_images<'s>(
+        &'s mut self,
+        images: &mut Vec<(String, &'s mut u32)>,
+    ) -> Result<(), NsArchiveError> {
+        match self {
+            SilicaIRHierarchy::Layer(layer) => layer.collect_image(images),
+            SilicaIRHierarchy::Group(group) => group.collect_images(images),
+        }
+    }
+
+    pub(super) fn count_groups(&self) -> u32 {
+        match self {
+            SilicaIRHierarchy::Layer(_) => 0,
+            SilicaIRHierarchy::Group(group) => group.count_groups(),
+        }
+    }
+
+    pub(crate) fn load(self, meta: &'a IRData<'a>) -> Result<SilicaHierarchy, SilicaError> {
+        Ok(match self {
+            SilicaIRHierarchy::Layer(layer) => SilicaHierarchy::Layer(layer.load(meta)?),
+            SilicaIRHierarchy::Group(group) => SilicaHierarchy::Group(group.load(meta)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tiles of 2 pixels over a 3 by 3 canvas.
+    fn tiling() -> TilingData {
+        TilingData {
+            columns: 2,
+            rows: 2,
+            diff: Size {
+                width: 1,
+                height: 1,
+            },
+            size: 2,
+        }
+    }
+
+    /// LZ4 chunk with the data stored in a single uncompressed block.
+    fn stored_lz4(data: &[u8]) -> Vec<u8> {
+        let len = (data.len() as u32).to_le_bytes();
+        [b"bv4-", &len[..], &len[..], data, b"bv4$"].concat()
+    }
+
+    #[test]
+    fn edge_tiles_decode_at_their_size() {
+        let tile = decode_chunk(
+            "uuid/1~1.lz4",
+            &stored_lz4(&[1, 2, 3, 4]),
+            &tiling(),
+            (1, 1),
+        );
+        assert_eq!(
+            tile.unwrap(),
+            DecodedTile {
+                origin: (2, 2),
+                size: Size {
+                    width: 1,
+                    height: 1
+                },
+                data: vec![1, 2, 3, 4],
+            }
+        );
+    }
+
+    #[test]
+    fn chunks_are_detected_by_their_first_bytes() {
+        let detect = |path, head: &[u8], len| ChunkCompression::detect(path, head, len, 16);
+        assert_eq!(
+            detect("uuid/0~0.chunk", &[0x28, 0xb5, 0x2f, 0xfd, 0], 9).unwrap(),
+            ChunkCompression::Zstd
+        );
+        assert_eq!(
+            detect("uuid/0~0.chunk", b"bv41", 12).unwrap(),
+            ChunkCompression::Lz4
+        );
+        assert_eq!(
+            detect("uuid/0~0.lz4", &[0; 4], 16).unwrap(),
+            ChunkCompression::Raw
+        );
+        assert_eq!(
+            detect("uuid/0~0.chunk", &[0x11, 0, 0, 0], 12).unwrap(),
+            ChunkCompression::Lzo
+        );
+        let err = detect("uuid/0~0.tile", &[0xab, 0xcd], 2).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown compression of `.tile` chunk starting with [ab, cd]"
+        );
+    }
+
+    #[test]
+    fn raw_chunks_are_stored_as_they_are() {
+        let tile = decode_chunk("uuid/1~1.raw", &[1, 2, 3, 4], &tiling(), (1, 1)).unwrap();
+        assert_eq!(tile.data, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn raw_tiles_that_start_like_magic_bytes_stay_raw() {
+        let mut data = [7; 16];
+        data[..4].copy_from_slice(ChunkCompression::ZSTD_MAGIC);
+        let tile = decode_chunk("uuid/0~0.chunk", &data, &tiling(), (0, 0)).unwrap();
+        assert_eq!(tile.data, data);
+        data[..4].copy_from_slice(b"bv41");
+        let tile = decode_chunk("uuid/0~0.lz4", &data, &tiling(), (0, 0)).unwrap();
+        assert_eq!(tile.data, data);
+    }
+
+    #[test]
+    fn truncated_and_outside_chunks_fail() {
+        let chunk = stored_lz4(&[0; 8]);
+        assert!(decode_chunk("uuid/0~0.lz4", &chunk, &tiling(), (0, 0)).is_err());
+        assert!(decode_chunk("uuid/2~0.lz4", &chunk, &tiling(), (2, 0)).is_err());
+        assert!(decode_chunk("uuid/0~1.lz4", &chunk, &tiling(), (0, 1)).is_ok());
+    }
+
+    #[test]
+    fn oversized_lz4_chunks_stop_decoding_past_the_tile() {
+        let chunk = stored_lz4(&[0; 1 << 16]);
+        let err = decode_chunk("uuid/0~0.lz4", &chunk, &tiling(), (0, 0)).unwrap_err();
+        assert!(matches!(
+            err,
+            SilicaError::TruncatedChunk {
+                len: 17,
+                expected: 16,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn layers_get_textures_by_sorted_uuid() {
+        use crate::fixture::{Document, Layer, Node};
+        use crate::ProcreateFile;
+
+        // Layers that share a UUID still get a texture each.
+        let bytes = Document::new(3, 3)
+            .layer(Layer::new("c", [1; 4]))
+            .group(
+                "group",
+                vec![
+                    Node::Layer(Layer::new("a", [2; 4])),
+                    Node::Layer(Layer::new("c", [1; 4])),
+                ],
+            )
+            .bytes();
+        let (file, canvas) = ProcreateFile::open_in_memory(&bytes).unwrap();
+
+        let images = file
+            .layers
+            .iter_layers()
+            .map(|layer| layer.image)
+            .collect::<Vec<_>>();
+        assert_eq!(images, [1, 0, 2]);
+        for (image, fill) in images.into_iter().zip([1, 2, 1]) {
+            assert_eq!(canvas.image(image).get_pixel(2, 2).0, [fill; 4]);
+        }
+    }
+}
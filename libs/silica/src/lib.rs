@@ -0,0 +1,1373 @@
+//! Reader for Procreate (`.procreate`) documents.
+//!
+//! [`ProcreateFile`] holds the document information and the layer tree, and
+//! the pixels of the layers are decoded tile by tile into a [`TileSink`].
+//! [`ProcreateFile::open_in_memory`] keeps them as RGBA tiles in a
+//! [`Rgba8Canvas`], and [`ProcreateFile::open_without_pixels`] only reads
+//! the document. With the `gpu` feature, which is on by default,
+//! [`ProcreateFile::open_with`] uploads them straight into the layer
+//! textures of the compositor instead; turn it off to read files without
+//! depending on wgpu:
+//!
+//! ```toml
+//! silicate-silica = { version = "0.1", default-features = false }
+//! ```
+//!
+//! Tiles are premultiplied RGBA with 8 bits per channel, and their rows are
+//! stored bottom-up like the textures of the compositor.
+//!
+//! The public items of this crate follow semantic versioning. The
+//! `fixture` feature, which builds documents in memory for tests, is
+//! exempt.
+
+mod diff;
+#[cfg(any(test, feature = "fixture"))]
+pub mod fixture;
+mod inspect;
+mod ir;
+mod ns_archive;
+mod tiles;
+mod visit;
+
+pub use self::diff::{LayerProperties, ReloadDiff};
+pub use self::inspect::ArchiveReport;
+use self::ir::{IRData, SilicaIRHierarchy, SilicaIRLayer};
+pub use self::ns_archive::{NsArchiveError, SchemaCoverage, Size};
+use self::ns_archive::{NsKeyedArchive, WrappedArray};
+#[cfg(feature = "gpu")]
+pub use self::tiles::GpuUpload;
+pub use self::tiles::{AtlasLayout, DecodedTile, DiscardTiles, Rgba8Canvas, TileSink};
+#[cfg(feature = "gpu")]
+use compositor::{
+    budget::AllocationError, dev::GpuHandle, metadata::ExportMetadata, profile::ColorProfile,
+    tex::TiledTexture,
+};
+use parking_lot::Mutex;
+use plist::Dictionary;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::hash::BuildHasher;
+use std::io::Cursor;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use zip::read::ZipArchive;
+
+#[derive(Error, Debug)]
+pub enum SilicaError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Plist error: {0}")]
+    PlistError(#[from] plist::Error),
+    #[error("Zip error: {0}")]
+    ZipError(#[from] zip::result::ZipError),
+    #[error("LZO error: {0}")]
+    LzoError(#[from] minilzo_rs::Error),
+    #[error("LZ4 error: {0}")]
+    Lz4Error(#[from] lz4_flex::block::DecompressError),
+    #[error("Zstd error: {0}")]
+    ZstdError(std::io::Error),
+    #[error("Unknown compression of `.{extension}` chunk starting with {magic:02x?}")]
+    UnknownCompression { extension: String, magic: Vec<u8> },
+    #[error("Ns archive error: {0}")]
+    NsArchiveError(#[from] NsArchiveError),
+    #[cfg(feature = "gpu")]
+    #[error("GPU allocation error: {0}")]
+    AllocationError(#[from] AllocationError),
+    #[error("Invalid values in file")]
+    InvalidValue,
+    #[error("Canvas of {width} by {height} in tiles of {tile_size} is invalid")]
+    InvalidCanvas {
+        width: u32,
+        height: u32,
+        tile_size: u32,
+    },
+    #[error("Chunk {0} is not named after a tile")]
+    BadChunkName(String),
+    #[error("Chunk {0} lies outside of the canvas")]
+    ChunkOutsideCanvas(String),
+    #[error("Chunk {path} holds {len} bytes of pixels instead of {expected}")]
+    TruncatedChunk {
+        path: String,
+        len: usize,
+        expected: usize,
+    },
+    #[error("Loading was cancelled")]
+    Cancelled,
+    #[error("Unknown decoding error")]
+    #[allow(dead_code)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendingMode {
+    Normal = 0,
+    Multiply = 1,
+    Screen = 2,
+    Add = 3,
+    Lighten = 4,
+    Exclusion = 5,
+    Difference = 6,
+    Subtract = 7,
+    LinearBurn = 8,
+    ColorDodge = 9,
+    ColorBurn = 10,
+    Overlay = 11,
+    HardLight = 12,
+    Color = 13,
+    Luminosity = 14,
+    Hue = 15,
+    Saturation = 16,
+    SoftLight = 17,
+    Darken = 19,
+    HardMix = 20,
+    VividLight = 21,
+    LinearLight = 22,
+    PinLight = 23,
+    LighterColor = 24,
+    DarkerColor = 25,
+    Divide = 26,
+}
+
+impl std::fmt::Display for BlendingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl BlendingMode {
+    pub fn all() -> &'static [BlendingMode] {
+        use BlendingMode::*;
+        &[
+            Normal,
+            Multiply,
+            Screen,
+            Add,
+            Lighten,
+            Exclusion,
+            Difference,
+            Subtract,
+            LinearBurn,
+            ColorDodge,
+            ColorBurn,
+            Overlay,
+            HardLight,
+            Color,
+            Luminosity,
+            Hue,
+            Saturation,
+            SoftLight,
+            Darken,
+            HardMix,
+            VividLight,
+            LinearLight,
+            PinLight,
+            LighterColor,
+            DarkerColor,
+            Divide,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Normal => "Normal",
+            Self::Multiply => "Multiply",
+            Self::Screen => "Screen",
+            Self::Add => "Add",
+            Self::Lighten => "Lighten",
+            Self::Exclusion => "Exclusion",
+            Self::Difference => "Difference",
+            Self::Subtract => "Subtract",
+            Self::LinearBurn => "Linear Burn",
+            Self::ColorDodge => "Color Dodge",
+            Self::ColorBurn => "Color Burn",
+            Self::Overlay => "Overlay",
+            Self::HardLight => "Hard Light",
+            Self::Color => "Color",
+            Self::Luminosity => "Luminosity",
+            Self::Hue => "Hue",
+            Self::Saturation => "Saturation",
+            Self::SoftLight => "Soft Light",
+            Self::Darken => "Darken",
+            Self::HardMix => "Hard Mix",
+            Self::VividLight => "Vivid Light",
+            Self::LinearLight => "Linear Light",
+            Self::PinLight => "Pin Light",
+            Self::LighterColor => "Lighter Color",
+            Self::DarkerColor => "Darker Color",
+            Self::Divide => "Divide",
+        }
+    }
+
+    pub fn from_u32(blend: u32) -> Result<Self, SilicaError> {
+        Ok(match blend {
+            0 => Self::Normal,
+            1 => Self::Multiply,
+            2 => Self::Screen,
+            3 => Self::Add,
+            4 => Self::Lighten,
+            5 => Self::Exclusion,
+            6 => Self::Difference,
+            7 => Self::Subtract,
+            8 => Self::LinearBurn,
+            9 => Self::ColorDodge,
+            10 => Self::ColorBurn,
+            11 => Self::Overlay,
+            12 => Self::HardLight,
+            13 => Self::Color,
+            14 => Self::Luminosity,
+            15 => Self::Hue,
+            16 => Self::Saturation,
+            17 => Self::SoftLight,
+            19 => Self::Darken,
+            20 => Self::HardMix,
+            21 => Self::VividLight,
+            22 => Self::LinearLight,
+            23 => Self::PinLight,
+            24 => Self::LighterColor,
+            25 => Self::DarkerColor,
+            26 => Self::Divide,
+            _ => Err(SilicaError::InvalidValue)?,
+        })
+    }
+
+    pub fn to_u32(self) -> u32 {
+        self as u32
+    }
+}
+
+#[cfg(feature = "gpu")]
+impl From<BlendingMode> for compositor::BlendMode {
+    fn from(blend: BlendingMode) -> Self {
+        match blend {
+            BlendingMode::Normal => Self::Normal,
+            BlendingMode::Multiply => Self::Multiply,
+            BlendingMode::Screen => Self::Screen,
+            BlendingMode::Add => Self::Add,
+            BlendingMode::Lighten => Self::Lighten,
+            BlendingMode::Exclusion => Self::Exclusion,
+            BlendingMode::Difference => Self::Difference,
+            BlendingMode::Subtract => Self::Subtract,
+            BlendingMode::LinearBurn => Self::LinearBurn,
+            BlendingMode::ColorDodge => Self::ColorDodge,
+            BlendingMode::ColorBurn => Self::ColorBurn,
+            BlendingMode::Overlay => Self::Overlay,
+            BlendingMode::HardLight => Self::HardLight,
+            BlendingMode::Color => Self::Color,
+            BlendingMode::Luminosity => Self::Luminosity,
+            BlendingMode::Hue => Self::Hue,
+            BlendingMode::Saturation => Self::Saturation,
+            BlendingMode::SoftLight => Self::SoftLight,
+            BlendingMode::Darken => Self::Darken,
+            BlendingMode::HardMix => Self::HardMix,
+            BlendingMode::VividLight => Self::VividLight,
+            BlendingMode::LinearLight => Self::LinearLight,
+            BlendingMode::PinLight => Self::PinLight,
+            BlendingMode::LighterColor => Self::LighterColor,
+            BlendingMode::DarkerColor => Self::DarkerColor,
+            BlendingMode::Divide => Self::Divide,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TilingData {
+    columns: u32,
+    rows: u32,
+    diff: Size<u32>,
+    size: u32,
+}
+
+impl TilingData {
+    /// Tiles covering a canvas, checking that the canvas and its tiles
+    /// have a size at all and that they fit in the texture coordinates.
+    fn new(size: Size<u32>, tile_size: u32) -> Result<Self, SilicaError> {
+        let invalid = || SilicaError::InvalidCanvas {
+            width: size.width,
+            height: size.height,
+            tile_size,
+        };
+        if size.width == 0 || size.height == 0 || tile_size == 0 {
+            return Err(invalid());
+        }
+        let columns = size.width.div_ceil(tile_size);
+        let rows = size.height.div_ceil(tile_size);
+        Ok(Self {
+            columns,
+            rows,
+            diff: Size {
+                width: columns.checked_mul(tile_size).ok_or_else(invalid)? - size.width,
+                height: rows.checked_mul(tile_size).ok_or_else(invalid)? - size.height,
+            },
+            size: tile_size,
+        })
+    }
+
+    /// Size of the tile at the given column and row, if it is on the canvas.
+    pub fn tile_size(&self, col: u32, row: u32) -> Option<Size<u32>> {
+        if col >= self.columns || row >= self.rows {
+            return None;
+        }
+        Some(Size {
+            width: if col != self.columns - 1 {
+                self.size
+            } else {
+                self.size - self.diff.width
+            },
+            height: if row != self.rows - 1 {
+                self.size
+            } else {
+                self.size - self.diff.height
+            },
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Flipped {
+    pub horizontally: bool,
+    pub vertically: bool,
+}
+
+/// Unit that the dimensions of a canvas are entered in, in the order that
+/// Procreate's canvas settings list them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentUnit {
+    Millimeters,
+    Centimeters,
+    Inches,
+    Pixels,
+}
+
+impl DocumentUnit {
+    #[allow(dead_code)]
+    pub fn all() -> &'static [DocumentUnit] {
+        &[
+            Self::Millimeters,
+            Self::Centimeters,
+            Self::Inches,
+            Self::Pixels,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Millimeters => "mm",
+            Self::Centimeters => "cm",
+            Self::Inches => "in",
+            Self::Pixels => "px",
+        }
+    }
+
+    pub fn from_u32(unit: u32) -> Option<Self> {
+        Self::all().get(unit as usize).copied()
+    }
+
+    /// Length of an inch in this unit, `None` for pixels whose length
+    /// depends on the resolution.
+    pub fn per_inch(self) -> Option<f32> {
+        match self {
+            Self::Millimeters => Some(25.4),
+            Self::Centimeters => Some(2.54),
+            Self::Inches => Some(1.0),
+            Self::Pixels => None,
+        }
+    }
+}
+
+/// Time-lapse recording settings of a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VideoSettings {
+    pub enabled: bool,
+    /// Quality and resolution of the recording, as named by Procreate.
+    pub quality: Option<String>,
+    pub resolution: Option<String>,
+    /// Set once the recording was deleted from the document.
+    pub purged: bool,
+}
+
+#[derive(Debug)]
+pub struct ProcreateFile {
+    pub author_name: Option<String>,
+    pub background_hidden: bool,
+    pub background_color: [f32; 4],
+    //     closedCleanlyKey:Bool?
+    /// ICC profile that the colors of the document are in, as stored in
+    /// the file.
+    pub color_profile: Option<Vec<u8>>,
+
+    /// Side of the cells of the drawing guide, in canvas pixels, if the
+    /// document has an enabled 2D grid guide.
+    pub drawing_grid: Option<f32>,
+    //     faceBackgroundHidden:Bool?
+    //     1 => BlendingMode::featureSet:Int?
+    pub flipped: Flipped,
+    pub layers: SilicaGroup,
+    //     mask:SilicaLayer?
+    pub name: Option<String>,
+    pub orientation: u32,
+    //     primaryItem:Any?
+    // //  skipping a bunch of reference window related stuff here
+    //     selectedLayer:Any?
+    //     selectedSamplerLayer:SilicaLayer?
+    /// Unit that the canvas dimensions were entered in.
+    pub unit: Option<DocumentUnit>,
+    /// Time spent drawing in the document, as tracked by Procreate.
+    pub tracked_time: Option<Duration>,
+    //     SilicaDocumentVideoSegmentInfoKey:VideoSegmentInfo? // not finished
+    //     size: CGSize?
+    //     solo: SilicaLayer?
+    pub stroke_count: usize,
+    /// Print resolution set in the canvas properties, in dots per inch.
+    pub dpi: Option<f32>,
+    pub video: VideoSettings,
+    //     videoDuration: String? = "Calculating..."
+    pub tile_size: u32,
+    pub composite: Option<SilicaLayer>,
+    /// Texture index of a background image shipped with the document.
+    pub background_image: Option<u32>,
+    pub background_image_hidden: bool,
+    pub size: Size<u32>,
+    /// Parts of the archive that were not understood, if
+    /// [`LoadOptions::coverage`] was set.
+    pub coverage: SchemaCoverage,
+    /// CRC-32 and size of the document archive, which identify the
+    /// document wherever the file is moved.
+    pub content_hash: u64,
+    /// Properties of the layers as they were read, before any changes
+    /// made in the viewer.
+    pub stored_layers: HashMap<String, LayerProperties>,
+}
+
+/// Classes that are known to appear in documents but are not decoded.
+const IGNORED_CLASSES: &[&str] = &["ValkyrieText", "VideoSegmentInfo"];
+
+/// Keys that are known to appear in documents but are not decoded, as
+/// listed on [`ProcreateFile`] and [`SilicaLayer`].
+const IGNORED_KEYS: &[&str] = &[
+    "closedCleanlyKey",
+    "SiColorProfileArchiveICCNameKey",
+    "faceBackgroundHidden",
+    "featureSet",
+    "mask",
+    "primaryItem",
+    "selectedLayer",
+    "selectedSamplerLayer",
+    "SilicaDocumentVideoSegmentInfoKey",
+    "solo",
+    "videoDuration",
+    "animationHeldLength",
+    "bundledImagePath",
+    "bundledMaskPath",
+    "bundledVideoPath",
+    "contentsRect",
+    "contentsRectValid",
+    "document",
+    "locked",
+    "perspectiveAssisted",
+    "preserve",
+    "private",
+    "text",
+    "textPDF",
+    "transform",
+    "type",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SilicaHierarchy {
+    Layer(SilicaLayer),
+    Group(SilicaGroup),
+}
+
+impl SilicaHierarchy {
+    /// Name of the layer or group.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Layer(layer) => layer.name.as_deref(),
+            Self::Group(group) => group.name.as_deref(),
+        }
+    }
+
+    /// Texture of the layer or group in the atlas.
+    pub fn image(&self) -> u32 {
+        match self {
+            Self::Layer(layer) => layer.image,
+            Self::Group(group) => group.image,
+        }
+    }
+
+    /// Hide or show the layer or group.
+    pub fn set_hidden(&mut self, hidden: bool) {
+        match self {
+            Self::Layer(layer) => layer.hidden = hidden,
+            Self::Group(group) => group.hidden = hidden,
+        }
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        match self {
+            Self::Layer(layer) => layer.opacity = opacity,
+            Self::Group(group) => group.opacity = opacity,
+        }
+    }
+
+    pub fn set_blend(&mut self, blend: BlendingMode) {
+        match self {
+            Self::Layer(layer) => layer.blend = blend,
+            Self::Group(group) => group.blend = blend,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SilicaGroup {
+    pub blend: BlendingMode,
+    pub hidden: bool,
+    pub children: Vec<SilicaHierarchy>,
+    pub name: Option<String>,
+    pub opacity: f32,
+    /// Texture index that the group is composited into when it has to be
+    /// composited in isolation. Unused for the root group.
+    pub image: u32,
+}
+
+impl SilicaGroup {
+    #[allow(dead_code)]
+    pub const fn empty() -> Self {
+        Self {
+            blend: BlendingMode::Normal,
+            hidden: true,
+            children: Vec::new(),
+            name: None,
+            opacity: 1.0,
+            image: u32::MAX,
+        }
+    }
+
+    /// Whether the group has to be composited on its own before being
+    /// blended with the layers beneath it.
+    pub fn is_isolated(&self) -> bool {
+        self.blend != BlendingMode::Normal || self.opacity < 1.0
+    }
+
+    /// Every layer beneath this group with tiles that failed to load.
+    pub fn damaged_layers(&self) -> Vec<&SilicaLayer> {
+        self.iter_layers()
+            .filter(|layer| layer.damage.is_some())
+            .collect()
+    }
+
+    /// Assign texture indices to every group beneath this group.
+    fn assign_images(&mut self, next: &mut u32) {
+        self.visit_mut(|_, node| {
+            if let SilicaHierarchy::Group(group) = node {
+                group.image = *next;
+                *next += 1;
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SilicaLayer {
+    // animationHeldLength:Int?
+    pub blend: BlendingMode,
+    // bundledImagePath:String?
+    // bundledMaskPath:String?
+    // bundledVideoPath:String?
+    pub clipped: bool,
+    // contentsRect:Data?
+    // contentsRectValid:Bool?
+    // document:SilicaDocument?
+    // extendedBlend:Int?
+    pub hidden: bool,
+    // locked:Bool?
+    pub mask: Option<usize>,
+    pub name: Option<String>,
+    pub opacity: f32,
+    // perspectiveAssisted:Bool?
+    // preserve:Bool?
+    // private:Bool?
+    // text:ValkyrieText?
+    // textPDF:Data?
+    // transform:Data?
+    // type:Int?
+    pub size: Size<u32>,
+    pub uuid: String,
+    pub version: u64,
+    pub image: u32,
+    /// Column and row of each tile stored for the layer, whether or not
+    /// it loaded.
+    pub chunks: Vec<(u32, u32)>,
+    /// Set when some of the layer's tiles could not be loaded.
+    pub damage: Option<LayerDamage>,
+}
+
+/// Tiles of a layer that failed to load and were left transparent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerDamage {
+    pub tiles: usize,
+    pub total: usize,
+    /// Error of the first tile that failed.
+    pub reason: String,
+}
+
+type ZipArchiveMmap<'a> = ZipArchive<Cursor<&'a [u8]>>;
+
+/// Decoded tile data kept around to verify the GPU upload against.
+#[derive(Debug)]
+pub struct TileSample {
+    pub layer: u32,
+    pub origin: (u32, u32),
+    pub size: Size<u32>,
+    pub data: Vec<u8>,
+}
+
+/// Randomly keeps roughly a fixed number of decoded tiles.
+struct TileSampler {
+    /// Tiles are kept if their hash falls below this threshold.
+    threshold: u64,
+    random: std::collections::hash_map::RandomState,
+    samples: Mutex<Vec<TileSample>>,
+}
+
+impl TileSampler {
+    fn new(count: usize, total: usize) -> Self {
+        let probability = (count as f64 / total.max(1) as f64).min(1.0);
+        Self {
+            threshold: (u64::MAX as f64 * probability) as u64,
+            random: std::collections::hash_map::RandomState::new(),
+            samples: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Keep a copy of the tile of `image` decoded from the chunk at `path`
+    /// if it is picked.
+    pub(crate) fn offer(&self, path: &str, image: u32, tile: &DecodedTile) {
+        if self.random.hash_one(path) < self.threshold {
+            self.samples.lock().push(TileSample {
+                layer: image,
+                origin: tile.origin,
+                size: tile.size,
+                data: tile.data.clone(),
+            });
+        }
+    }
+}
+
+/// Stops files from loading part way through, such as when their tab is
+/// closed or the app quits. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    /// Token that cancels this one along with it, as do its own parents.
+    parent: Option<Arc<CancelToken>>,
+}
+
+impl CancelToken {
+    /// Token that is cancelled by itself or whenever this one or any of its
+    /// parents is.
+    pub fn child(&self) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+}
+
+/// How a file is loaded.
+#[derive(Default, Clone, Copy)]
+pub struct LoadOptions<'a> {
+    /// Approximate number of decoded tiles to keep to verify the GPU
+    /// textures with.
+    pub samples: usize,
+    /// Called with the number of tiles decoded so far and the total number
+    /// of tiles as they load.
+    pub progress: Option<&'a (dyn Fn(usize, usize) + Sync)>,
+    /// Threads to decode tiles on, those of the global thread pool if `None`.
+    pub threads: Option<NonZeroUsize>,
+    /// Checked between tiles, failing the load with
+    /// [`SilicaError::Cancelled`] once cancelled.
+    pub cancel: Option<&'a CancelToken>,
+    /// Keep track of the keys read from the archive, to fill in
+    /// [`ProcreateFile::coverage`].
+    pub coverage: bool,
+}
+
+/// Counts the tiles decoded while a file loads.
+struct LoadProgress<'a> {
+    total: usize,
+    done: AtomicUsize,
+    report: Option<&'a (dyn Fn(usize, usize) + Sync)>,
+    cancel: Option<&'a CancelToken>,
+}
+
+impl LoadProgress<'_> {
+    pub(crate) fn tick(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(report) = self.report {
+            report(done, self.total);
+        }
+    }
+
+    /// Fail if the load was cancelled.
+    pub(crate) fn check(&self) -> Result<(), SilicaError> {
+        match self.cancel {
+            Some(cancel) if cancel.is_cancelled() => Err(SilicaError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ProcreateFile {
+    /// Printed width and height of the canvas in inches at its DPI.
+    pub fn physical_size(&self) -> Option<(f32, f32)> {
+        let dpi = self.dpi?;
+        Some((self.size.width as f32 / dpi, self.size.height as f32 / dpi))
+    }
+
+    /// Document information to embed in exported images, naming the app
+    /// that exports them as `software`.
+    #[cfg(feature = "gpu")]
+    pub fn export_metadata(&self, software: &str) -> ExportMetadata {
+        ExportMetadata {
+            title: self.name.clone(),
+            author: self.author_name.clone(),
+            software: Some(software.to_string()),
+            dpi: self.dpi,
+            profile: self.color_profile.clone().map(|icc| ColorProfile { icc }),
+            conversion: None,
+        }
+    }
+
+    /// Load a Procreate file, calling `progress` with the number of tiles
+    /// decoded so far and the total number of tiles as they load.
+    #[cfg(feature = "gpu")]
+    pub fn open_with_progress<P: AsRef<Path>>(
+        p: P,
+        dev: &GpuHandle,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> Result<(Self, TiledTexture), SilicaError> {
+        let options = LoadOptions {
+            progress: Some(progress),
+            ..LoadOptions::default()
+        };
+        Self::open_with(p, dev, &options).map(|(file, textures, _)| (file, textures))
+    }
+
+    /// Load a Procreate file held in memory, without any access to the
+    /// filesystem.
+    #[cfg(feature = "gpu")]
+    pub fn open_from_bytes(
+        bytes: &[u8],
+        dev: &GpuHandle,
+    ) -> Result<(Self, TiledTexture), SilicaError> {
+        Self::from_bytes(
+            bytes,
+            |layout| GpuUpload::new(dev, layout),
+            &LoadOptions::default(),
+        )
+        .map(|(file, upload, _)| (file, upload.textures))
+    }
+
+    /// Load a Procreate file from a reader, such as standard input, reading
+    /// it into memory first since archives are read out of order.
+    #[cfg(feature = "gpu")]
+    pub fn open_from_reader(
+        mut reader: impl Read,
+        dev: &GpuHandle,
+    ) -> Result<(Self, TiledTexture), SilicaError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::open_from_bytes(&bytes, dev)
+    }
+
+    /// Read the thumbnail that Procreate embeds in a file, without loading
+    /// the document itself.
+    pub fn read_thumbnail(path: &Path) -> Result<image::RgbaImage, SilicaError> {
+        let file = OpenOptions::new().read(true).write(false).open(path)?;
+        let mut archive = ZipArchive::new(std::io::BufReader::new(file))?;
+        let mut buf = Vec::new();
+        archive
+            .by_name("QuickLook/Thumbnail.png")?
+            .read_to_end(&mut buf)?;
+        Ok(image::load_from_memory(&buf)
+            .map_err(|_| SilicaError::InvalidValue)?
+            .into_rgba8())
+    }
+
+    /// Read a Procreate file held in memory without a GPU or keeping the
+    /// pixels of its layers, such as to print its information. The tiles are
+    /// still decoded to find the damaged ones.
+    pub fn open_without_pixels(bytes: &[u8]) -> Result<Self, SilicaError> {
+        Self::from_bytes(bytes, |_| Ok(DiscardTiles), &LoadOptions::default())
+            .map(|(file, _, _)| file)
+    }
+
+    /// Load a Procreate file held in memory into tiles in memory rather
+    /// than GPU textures, to read its pixels without a GPU.
+    pub fn open_in_memory(bytes: &[u8]) -> Result<(Self, Rgba8Canvas), SilicaError> {
+        Self::from_bytes(
+            bytes,
+            |layout| Ok(Rgba8Canvas::new(layout)),
+            &LoadOptions::default(),
+        )
+        .map(|(file, canvas, _)| (file, canvas))
+    }
+
+    /// Load a Procreate file, along with the tiles sampled to verify the GPU
+    /// textures with.
+    #[cfg(feature = "gpu")]
+    pub fn open_with<P: AsRef<Path>>(
+        p: P,
+        dev: &GpuHandle,
+        options: &LoadOptions,
+    ) -> Result<(Self, TiledTexture, Vec<TileSample>), SilicaError> {
+        let file = OpenOptions::new().read(true).write(false).open(p)?;
+        let mapping = unsafe { memmap2::Mmap::map(&file)? };
+        Self::from_bytes(&mapping, |layout| GpuUpload::new(dev, layout), options)
+            .map(|(file, upload, samples)| (file, upload.textures, samples))
+    }
+
+    /// Load a file, decoding its tiles into the sink made for the layout
+    /// of its textures.
+    fn from_bytes<S: TileSink + Send>(
+        bytes: &[u8],
+        sink: impl FnOnce(&AtlasLayout) -> Result<S, SilicaError> + Send,
+        options: &LoadOptions,
+    ) -> Result<(Self, S, Vec<TileSample>), SilicaError> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+
+        let (nka, content_hash) = {
+            let mut document = archive.by_name("Document.archive")?;
+            let content_hash = u64::from(document.crc32()) << 32 | (document.size() & 0xFFFF_FFFF);
+
+            let mut buf = Vec::with_capacity(document.size() as usize);
+            document.read_to_end(&mut buf)?;
+
+            let nka = NsKeyedArchive::from_reader(Cursor::new(buf))?;
+            let nka = if options.coverage {
+                nka.track_reads()
+            } else {
+                nka
+            };
+            (nka, content_hash)
+        };
+
+        let sampler = TileSampler::new(options.samples, archive.len());
+        let load = || Self::from_ns(archive, nka, sink, &sampler, options);
+        let (mut file, sink) = match options.threads {
+            Some(threads) => match rayon::ThreadPoolBuilder::new()
+                .num_threads(threads.get())
+                .thread_name(|i| format!("silicate-load-{i}"))
+                .build()
+            {
+                Ok(pool) => pool.install(load),
+                Err(err) => {
+                    tracing::warn!("Loading on the global thread pool instead: {err}");
+                    load()
+                }
+            },
+            None => load(),
+        }?;
+        file.content_hash = content_hash;
+        Ok((file, sink, sampler.samples.into_inner()))
+    }
+
+    fn from_ns<S: TileSink>(
+        archive: ZipArchiveMmap<'_>,
+        nka: NsKeyedArchive,
+        sink: impl FnOnce(&AtlasLayout) -> Result<S, SilicaError>,
+        sampler: &TileSampler,
+        options: &LoadOptions,
+    ) -> Result<(Self, S), SilicaError> {
+        let root = nka.root()?;
+
+        let size = nka.fetch::<Size<u32>>(root, "size")?;
+        let tile_size = nka.fetch::<u32>(root, "tileSize")?;
+        let tile = TilingData::new(size, tile_size)?;
+
+        // Sorted so that chunks are visited in a stable order between loads.
+        let mut file_names = archive.file_names().collect::<Vec<_>>();
+        file_names.sort_unstable();
+
+        let mut ir_hierachy = nka
+            .fetch::<WrappedArray<SilicaIRHierarchy>>(root, "unwrappedLayers")?
+            .objects;
+        let mut ir_composite = nka.fetch::<SilicaIRLayer>(root, "composite")?;
+
+        // Atlas indices are assigned by sorted layer UUID rather than by
+        // whichever layer finishes loading first, so that loading the same
+        // file twice always yields the same texture layout. Layers that
+        // share a UUID still get a texture each, in the order they are
+        // stored, and the tiles of every layer are placed by their chunk
+        // coordinates.
+        let mut images = Vec::new();
+        for ir in &mut ir_hierachy {
+            ir.collect_images(&mut images)?;
+        }
+        // The composite is allowed to be missing.
+        let _ = ir_composite.collect_image(&mut images);
+        images.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let layer_uuids = images
+            .into_iter()
+            .enumerate()
+            .map(|(index, (uuid, image))| {
+                *image = index as u32;
+                uuid
+            })
+            .collect::<Vec<_>>();
+
+        let background_image = Self::find_background_image(&file_names);
+        let group_count = ir_hierachy.iter().map(|ir| ir.count_groups()).sum::<u32>();
+
+        // Texture layout: layers, then the background image, then groups.
+        let group_base = layer_uuids.len() as u32 + u32::from(background_image.is_some());
+        let sink = sink(&AtlasLayout {
+            size,
+            tile_size,
+            count: group_base + group_count,
+        })?;
+
+        // The background image takes the slot right after the layers.
+        let background_image = background_image.and_then(|path| {
+            let index = layer_uuids.len() as u32;
+            Self::load_background_image(&archive, path, size, &sink, index)
+                .map_err(|err| tracing::warn!("Failed to load background image {path}: {err}"))
+                .ok()
+                .map(|_| index)
+        });
+
+        // Tiles are stored under the UUID of their layer, and are loaded
+        // once for every layer with that UUID.
+        let mut layers_by_uuid = HashMap::<&str, usize>::new();
+        for uuid in &layer_uuids {
+            *layers_by_uuid.entry(uuid).or_default() += 1;
+        }
+        let progress = LoadProgress {
+            total: file_names
+                .iter()
+                .filter_map(|name| name.split_once('/'))
+                .filter_map(|(dir, _)| layers_by_uuid.get(dir))
+                .sum(),
+            done: AtomicUsize::new(0),
+            report: options.progress,
+            cancel: options.cancel,
+        };
+
+        let ir_data = IRData {
+            tile: &tile,
+            archive: &archive,
+            size,
+            file_names: &file_names,
+            sink: &sink,
+            sampler,
+            progress: &progress,
+        };
+
+        let mut file = Self {
+            author_name: nka.fetch::<Option<String>>(root, "authorName")?,
+            background_hidden: nka.fetch::<bool>(root, "backgroundHidden")?,
+            stroke_count: nka.fetch::<usize>(root, "strokeCount")?,
+            // Only used for export metadata, so a malformed value is not fatal.
+            dpi: nka
+                .fetch::<Option<f32>>(root, "SilicaDocumentArchiveDPIKey")
+                .ok()
+                .flatten()
+                .filter(|dpi| dpi.is_finite() && *dpi > 0.0),
+            // Only shown in the Info tab, so malformed values are not fatal.
+            unit: nka
+                .fetch::<Option<u32>>(root, "SilicaDocumentArchiveUnitKey")
+                .ok()
+                .flatten()
+                .and_then(DocumentUnit::from_u32),
+            tracked_time: nka
+                .fetch::<Option<f64>>(root, "SilicaDocumentTrackedTimeKey")
+                .ok()
+                .flatten()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()),
+            video: VideoSettings {
+                enabled: nka
+                    .fetch::<Option<bool>>(root, "videoEnabled")
+                    .ok()
+                    .flatten()
+                    .unwrap_or(false),
+                quality: nka
+                    .fetch::<Option<String>>(root, "videoQualityKey")
+                    .ok()
+                    .flatten(),
+                resolution: nka
+                    .fetch::<Option<String>>(root, "videoResolutionKey")
+                    .ok()
+                    .flatten(),
+                purged: nka
+                    .fetch::<Option<bool>>(root, "SilicaDocumentVideoPurgedKey")
+                    .ok()
+                    .flatten()
+                    .unwrap_or(false),
+            },
+            background_color: <[f32; 4]>::try_from(
+                nka.fetch::<&[u8]>(root, "backgroundColor")?
+                    .chunks_exact(4)
+                    .map(|bytes| {
+                        <[u8; 4]>::try_from(bytes)
+                            .map(f32::from_le_bytes)
+                            .map_err(|_| {
+                                NsArchiveError::TypeMismatch("backgroundColor".to_string())
+                            })
+                    })
+                    .collect::<Result<Vec<f32>, _>>()?,
+            )
+            .map_err(|_| NsArchiveError::TypeMismatch("backgroundColor".to_string()))?,
+            // Exports fall back to sRGB without a readable profile.
+            color_profile: nka
+                .fetch::<Option<&Dictionary>>(root, "colorProfile")
+                .ok()
+                .flatten()
+                .and_then(|profile| {
+                    nka.fetch::<&[u8]>(profile, "SiColorProfileArchiveICCDataKey")
+                        .ok()
+                })
+                .map(<[u8]>::to_vec),
+            drawing_grid: Self::drawing_grid(&nka, root),
+            name: nka.fetch::<Option<String>>(root, "name")?,
+            orientation: nka.fetch::<u32>(root, "orientation")?,
+            flipped: Flipped {
+                horizontally: nka.fetch::<bool>(root, "flippedHorizontally")?,
+                vertically: nka.fetch::<bool>(root, "flippedVertically")?,
+            },
+            tile_size,
+            size,
+            composite: ir_composite.load(&ir_data).ok(),
+            background_image,
+            background_image_hidden: false,
+            layers: {
+                let mut root = SilicaGroup {
+                    blend: BlendingMode::Normal,
+                    hidden: false,
+                    name: Some(String::from("Root Layer")),
+                    children: ir_hierachy
+                        .into_par_iter()
+                        .map(|ir| ir.load(&ir_data))
+                        .collect::<Result<_, _>>()?,
+                    opacity: 1.0,
+                    image: u32::MAX,
+                };
+                root.assign_images(&mut { group_base });
+                root
+            },
+            coverage: SchemaCoverage::default(),
+            content_hash: 0,
+            stored_layers: HashMap::new(),
+        };
+        // The composite is allowed to fail, so it may have been cut short.
+        progress.check()?;
+        file.stored_layers = file.layers.layer_properties();
+        // Everything has been decoded by now.
+        if let Some(coverage) = nka.coverage(IGNORED_CLASSES, IGNORED_KEYS) {
+            file.coverage = coverage;
+        }
+        Ok((file, sink))
+    }
+
+    /// Side of the cells of the drawing guide, if it is an enabled 2D grid.
+    /// Isometric, perspective and symmetry guides have a grid size too, but
+    /// no cells. Guides that do not say whether they are enabled or what
+    /// type they are count as enabled 2D grids, like those of older
+    /// documents. Only used to export guide cells, so a malformed guide is
+    /// not fatal.
+    fn drawing_grid(nka: &NsKeyedArchive, root: &Dictionary) -> Option<f32> {
+        /// Type of a 2D grid guide.
+        const GRID: u32 = 0;
+        let guide = nka
+            .fetch::<Option<&Dictionary>>(root, "drawingguide")
+            .ok()
+            .flatten()?;
+        let enabled = nka.fetch::<Option<bool>>(guide, "enabled").ok().flatten();
+        let kind = nka.fetch::<Option<u32>>(guide, "type").ok().flatten();
+        if enabled == Some(false) || kind.is_some_and(|kind| kind != GRID) {
+            return None;
+        }
+        nka.fetch::<f32>(guide, "gridSize")
+            .ok()
+            .filter(|size| size.is_finite() && *size >= 1.0)
+    }
+
+    /// Find a background or paper image bundled in the document archive.
+    fn find_background_image<'a>(file_names: &[&'a str]) -> Option<&'a str> {
+        file_names.iter().copied().find(|name| {
+            let path = Path::new(name);
+            // QuickLook contains the thumbnail, which is not a background.
+            let top_level = path.parent().is_none_or(|p| p.as_os_str().is_empty());
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default();
+            top_level
+                && (stem.eq_ignore_ascii_case("background") || stem.eq_ignore_ascii_case("paper"))
+                && image::ImageFormat::from_path(path).is_ok()
+        })
+    }
+
+    /// Decode the background image and upload it as a texture layer
+    /// stretched to the canvas size.
+    fn load_background_image(
+        archive: &ZipArchiveMmap<'_>,
+        path: &str,
+        size: Size<u32>,
+        sink: &dyn TileSink,
+        index: u32,
+    ) -> Result<(), SilicaError> {
+        let mut archive = archive.clone();
+        let mut buf = Vec::new();
+        archive.by_name(path)?.read_to_end(&mut buf)?;
+
+        let image = image::load_from_memory(&buf)
+            .map_err(|_| SilicaError::InvalidValue)?
+            .into_rgba8();
+        let mut image = image::imageops::resize(
+            &image,
+            size.width,
+            size.height,
+            image::imageops::FilterType::Triangle,
+        );
+        // Layer textures are stored bottom-up and premultiplied.
+        image::imageops::flip_vertical_in_place(&mut image);
+        for pixel in image.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let scale = |c: u8| ((u16::from(c) * u16::from(a) + 127) / 255) as u8;
+            pixel.0 = [scale(r), scale(g), scale(b), a];
+        }
+
+        sink.write_tile(
+            index,
+            DecodedTile {
+                origin: (0, 0),
+                size,
+                data: image.into_raw(),
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::{Document, Layer, Node};
+    use super::*;
+    use plist::{Uid, Value};
+
+    fn open(bytes: &[u8]) -> Result<ProcreateFile, SilicaError> {
+        ProcreateFile::open_in_memory(bytes).map(|(file, _)| file)
+    }
+
+    fn edit_root(objects: &mut [Value], key: &str, value: Value) {
+        let root = objects.last_mut().unwrap().as_dictionary_mut().unwrap();
+        root.insert(key.to_string(), value);
+    }
+
+    #[test]
+    fn canvases_without_tiles_fail() {
+        let doc = Document::new(3, 3);
+        let err = open(&doc.bytes_with(|objects| {
+            edit_root(objects, "tileSize", Value::from(0u64));
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            SilicaError::InvalidCanvas { tile_size: 0, .. }
+        ));
+
+        let err = open(&doc.bytes_with(|objects| {
+            let size = Value::String(format!("{{{}, 1}}", u32::MAX));
+            edit_root(objects, "size", size);
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            SilicaError::InvalidCanvas {
+                width: u32::MAX,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn groups_containing_themselves_fail() {
+        let doc = Document::new(3, 3).group("loop", Vec::new());
+        let err = open(&doc.bytes_with(|objects| {
+            let group = objects
+                .iter()
+                .position(|object| {
+                    object
+                        .as_dictionary()
+                        .is_some_and(|coder| coder.contains_key("isHidden"))
+                })
+                .unwrap();
+            let children = objects[group].as_dictionary().unwrap()["children"]
+                .as_uid()
+                .unwrap()
+                .get() as usize;
+            let array = objects[children].as_dictionary_mut().unwrap();
+            array.insert(
+                "NS.objects".to_string(),
+                Value::Array(vec![Value::Uid(Uid::new(group as u64))]),
+            );
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            SilicaError::NsArchiveError(NsArchiveError::TooDeep(_))
+        ));
+    }
+
+    #[test]
+    fn malformed_background_color_fails() {
+        let doc = Document::new(3, 3);
+        let err = open(&doc.bytes_with(|objects| {
+            edit_root(objects, "backgroundColor", Value::Data(vec![0; 6]));
+        }))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ns archive error: Type mismatch: key backgroundColor"
+        );
+    }
+
+    #[test]
+    fn broken_chunks_only_damage_their_layer() {
+        let empty = |uuid: &str| {
+            let mut layer = Layer::new(uuid, [0; 4]);
+            layer.fill = None;
+            Node::Layer(layer)
+        };
+        // LZ4 chunk with half of a 2 by 2 tile stored uncompressed.
+        let len = 8u32.to_le_bytes();
+        let truncated = [b"bv4-", &len[..], &len[..], &[0; 8], b"bv4$"].concat();
+
+        let mut doc = Document::new(3, 3);
+        doc.tile_size = 2;
+        doc.layers = vec![
+            Node::Layer(Layer::new("intact", [1; 4])),
+            empty("name"),
+            empty("outside"),
+            empty("truncated"),
+        ];
+        doc.files = vec![
+            ("name/tile.chunk".to_string(), vec![0; 16]),
+            ("outside/2~0.chunk".to_string(), vec![0; 16]),
+            ("truncated/0~0.lz4".to_string(), truncated),
+        ];
+        let file = open(&doc.bytes()).unwrap();
+
+        let reasons = file
+            .layers
+            .iter_layers()
+            .map(|layer| {
+                (
+                    layer.uuid.as_str(),
+                    layer.damage.as_ref().map(|d| d.reason.as_str()),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [
+                ("intact", None),
+                (
+                    "name",
+                    Some("Chunk name/tile.chunk is not named after a tile")
+                ),
+                (
+                    "outside",
+                    Some("Chunk outside/2~0.chunk lies outside of the canvas")
+                ),
+                (
+                    "truncated",
+                    Some("Chunk truncated/0~0.lz4 holds 8 bytes of pixels instead of 16")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn layers_list_the_tiles_they_store() {
+        let mut doc = Document::new(4, 1);
+        doc.tile_size = 1;
+        let doc = doc.layer(Layer::new("sparse", [1; 4]).tiles(&[(3, 0), (1, 0)]));
+        let file = open(&doc.bytes()).unwrap();
+        let layer = file.layers.iter_layers().next().unwrap();
+        let mut chunks = layer.chunks.clone();
+        chunks.sort();
+        assert_eq!(chunks, [(1, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn only_enabled_grid_guides_have_cells() {
+        let guide = |entries: &[(&str, Value)]| {
+            let doc = Document::new(3, 3);
+            let entries = entries.to_vec();
+            open(&doc.bytes_with(|objects| {
+                let mut guide = plist::Dictionary::new();
+                for (key, value) in entries {
+                    guide.insert(key.to_string(), value);
+                }
+                // Added after the root, so that no reference moves.
+                let index = objects.len() as u64;
+                edit_root(objects, "drawingguide", Value::Uid(Uid::new(index)));
+                objects.push(Value::Dictionary(guide));
+            }))
+            .unwrap()
+            .drawing_grid
+        };
+        let size = || ("gridSize", Value::Real(120.0));
+        assert_eq!(guide(&[size()]), Some(120.0));
+        assert_eq!(
+            guide(&[
+                size(),
+                ("enabled", Value::Boolean(true)),
+                ("type", Value::from(0u64))
+            ]),
+            Some(120.0)
+        );
+        assert_eq!(guide(&[size(), ("enabled", Value::Boolean(false))]), None);
+        // Isometric.
+        assert_eq!(guide(&[size(), ("type", Value::from(1u64))]), None);
+        assert_eq!(guide(&[("gridSize", Value::Real(0.5))]), None);
+    }
+
+    #[test]
+    fn cancelling_reaches_every_descendant() {
+        let root = CancelToken::default();
+        let child = root.child();
+        let grandchild = child.child();
+        let sibling = root.child();
+
+        grandchild.cancel();
+        assert!(!child.is_cancelled());
+        child.cancel();
+        assert!(!root.is_cancelled() && !sibling.is_cancelled());
+
+        let grandchild = root.child().child();
+        assert!(!grandchild.is_cancelled());
+        root.cancel();
+        assert!(grandchild.is_cancelled() && sibling.is_cancelled());
+    }
+}
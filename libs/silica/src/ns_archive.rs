@@ -0,0 +1,686 @@
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use plist::{Dictionary, Uid, Value};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum NsArchiveError {
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("Plist decoding error")]
+    PlistError(#[from] plist::Error),
+    #[error("Zip decoding error")]
+    ZipError(#[from] zip::result::ZipError),
+    #[error("Type mismatch: key {0}")]
+    TypeMismatch(String),
+    #[error("Missing key {0}")]
+    MissingKey(String),
+    #[error("Bad index")]
+    BadIndex,
+    #[error("Objects nested more than {0} deep")]
+    TooDeep(u32),
+}
+
+pub struct NsKeyedArchive {
+    // version: u64,
+    // archiver: String,
+    top: Dictionary,
+    objects: Vec<Value>,
+    /// Addresses of the values fetched so far, to find the parts of the
+    /// archive that were never read, if reads are tracked.
+    read_values: Option<Mutex<HashSet<usize>>>,
+}
+
+/// Classes and keys of an archive that were never read while decoding it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchemaCoverage {
+    /// Classes that none of the keys were read from.
+    pub unknown_classes: BTreeSet<String>,
+    /// Keys that were not read from any object of a class, by class.
+    pub unknown_keys: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl SchemaCoverage {
+    pub fn is_complete(&self) -> bool {
+        self.unknown_classes.is_empty() && self.unknown_keys.is_empty()
+    }
+
+    /// Number of unknown keys across all classes.
+    pub fn unknown_key_count(&self) -> usize {
+        self.unknown_keys.values().map(BTreeSet::len).sum()
+    }
+
+    /// Plain text listing of the unknown class and key names, without any
+    /// of the values, for attaching to an issue. It is headed by the name
+    /// and version of the app that read the file, given as `software`.
+    pub fn summary(&self, software: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{software} schema summary");
+        let _ = writeln!(out, "\nUnknown classes:");
+        for class in &self.unknown_classes {
+            let _ = writeln!(out, "  {class}");
+        }
+        let _ = writeln!(out, "\nUnknown keys:");
+        for (class, keys) in &self.unknown_keys {
+            let _ = writeln!(out, "  {class}");
+            for key in keys {
+                let _ = writeln!(out, "    {key}");
+            }
+        }
+        out
+    }
+}
+
+impl<'a> NsKeyedArchive {
+    pub fn from_reader(reader: impl std::io::Read + std::io::Seek) -> Result<Self, NsArchiveError> {
+        let mut value = plist::Value::from_reader(reader)?
+            .into_dictionary()
+            .ok_or(NsArchiveError::TypeMismatch(String::new()))?;
+
+        Ok(Self {
+            // version: value
+            //     .remove("$version")
+            //     .ok_or_else(|| NsArchiveError::MissingKey("$version".to_string()))?
+            //     .as_unsigned_integer()
+            //     .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))?,
+            // archiver: value
+            //     .remove("$archiver")
+            //     .ok_or_else(|| NsArchiveError::MissingKey("$archiver".to_string()))?
+            //     .into_string()
+            //     .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))?,
+            top: value
+                .remove("$top")
+                .ok_or_else(|| NsArchiveError::MissingKey("$top".to_string()))?
+                .into_dictionary()
+                .ok_or_else(|| NsArchiveError::TypeMismatch("$top".to_string()))?,
+            objects: value
+                .remove("$objects")
+                .ok_or_else(|| NsArchiveError::MissingKey("$objects".to_string()))?
+                .into_array()
+                .ok_or_else(|| NsArchiveError::TypeMismatch("$objects".to_string()))?,
+            read_values: None,
+        })
+    }
+
+    /// Keep track of the keys fetched from the archive from now on, for
+    /// [`Self::coverage`].
+    pub fn track_reads(mut self) -> Self {
+        self.read_values = Some(Mutex::new(HashSet::new()));
+        self
+    }
+
+    fn mark_read(&self, value: &Value) {
+        if let Some(read_values) = &self.read_values {
+            read_values.lock().insert(value as *const Value as usize);
+        }
+    }
+
+    /// Compare the objects of the archive against the keys read from them
+    /// so far, or `None` if reads are not tracked. Foundation classes and
+    /// the given classes and keys, which are known but unused, are left out.
+    pub fn coverage(
+        &self,
+        ignored_classes: &[&str],
+        ignored_keys: &[&str],
+    ) -> Option<SchemaCoverage> {
+        // Resolving the classes below fetches from the archive too.
+        let read_values = self.read_values.as_ref()?.lock().clone();
+        // Keys of each class, and whether the key was read from any object.
+        let mut classes = BTreeMap::<String, BTreeMap<String, bool>>::new();
+        for coder in self.objects.iter().filter_map(Value::as_dictionary) {
+            let Ok(class) = self.fetch_value(coder, "$class").and_then(|class| {
+                NsClass::decode(self, "$class", class).map(|class| class.class_name)
+            }) else {
+                continue;
+            };
+            if class.starts_with("NS") || ignored_classes.contains(&class.as_str()) {
+                continue;
+            }
+            let keys = classes.entry(class).or_default();
+            for (key, value) in coder.iter().filter(|(key, _)| *key != "$class") {
+                let read = read_values.contains(&(value as *const Value as usize));
+                *keys.entry(key.clone()).or_default() |= read;
+            }
+        }
+
+        let mut coverage = SchemaCoverage::default();
+        for (class, keys) in classes {
+            if !keys.values().any(|read| *read) {
+                coverage.unknown_classes.insert(class);
+                continue;
+            }
+            let unknown = keys
+                .into_iter()
+                .filter(|(key, read)| !read && !ignored_keys.contains(&key.as_str()))
+                .map(|(key, _)| key)
+                .collect::<BTreeSet<_>>();
+            if !unknown.is_empty() {
+                coverage.unknown_keys.insert(class, unknown);
+            }
+        }
+        Some(coverage)
+    }
+
+    fn resolve_index_nullable(&'a self, idx: usize) -> Result<Option<&'a Value>, NsArchiveError> {
+        if idx == 0 {
+            Ok(None)
+        } else {
+            self.objects
+                .get(idx)
+                .ok_or(NsArchiveError::BadIndex)
+                .map(Some)
+        }
+    }
+
+    fn resolve_index(&'a self, idx: usize) -> Result<&'a Value, NsArchiveError> {
+        if idx == 0 {
+            Err(NsArchiveError::BadIndex)
+        } else {
+            self.objects.get(idx).ok_or(NsArchiveError::BadIndex)
+        }
+    }
+
+    pub fn fetch_value_nullable(
+        &'a self,
+        coder: &'a Dictionary,
+        key: &str,
+    ) -> Result<Option<&'a Value>, NsArchiveError> {
+        let value = coder.get(key);
+        if let Some(value) = value {
+            self.mark_read(value);
+        }
+        return match value {
+            Some(Value::Uid(uid)) => self.resolve_index_nullable(uid.get() as usize),
+            value => Ok(value),
+        };
+    }
+
+    pub fn fetch_value(
+        &'a self,
+        coder: &'a Dictionary,
+        key: &str,
+    ) -> Result<&'a Value, NsArchiveError> {
+        let value = coder.get(key);
+        if let Some(value) = value {
+            self.mark_read(value);
+        }
+        return match value {
+            Some(Value::Uid(uid)) => self.resolve_index(uid.get() as usize),
+            Some(value) => Ok(value),
+            None => Err(NsArchiveError::MissingKey(key.to_string())),
+        };
+    }
+
+    pub fn fetch<T: NsDecode<'a>>(
+        &'a self,
+        coder: &'a Dictionary,
+        key: &'a str,
+    ) -> Result<T, NsArchiveError> {
+        T::fetch(self, coder, key)
+    }
+
+    pub fn root(&self) -> Result<&'_ Dictionary, NsArchiveError> {
+        self.fetch::<&'_ Dictionary>(&self.top, "root")
+    }
+}
+
+pub trait NsDecode<'a>: Sized {
+    fn fetch(
+        nka: &'a NsKeyedArchive,
+        coder: &'a Dictionary,
+        key: &'a str,
+    ) -> Result<Self, NsArchiveError> {
+        Self::decode(nka, key, nka.fetch_value(coder, key)?)
+    }
+
+    fn decode(
+        nka: &'a NsKeyedArchive,
+        key: &'a str,
+        val: &'a Value,
+    ) -> Result<Self, NsArchiveError>;
+}
+
+impl NsDecode<'_> for bool {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        val.as_boolean()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl NsDecode<'_> for usize {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        val.as_unsigned_integer()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+            .map(|n| n as Self)
+    }
+}
+
+impl NsDecode<'_> for isize {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        val.as_signed_integer()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+            .map(|n| n as Self)
+    }
+}
+
+impl NsDecode<'_> for u64 {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        val.as_unsigned_integer()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl NsDecode<'_> for i64 {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        val.as_signed_integer()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl NsDecode<'_> for f64 {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        val.as_real()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl NsDecode<'_> for u32 {
+    fn decode(nka: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        u32::try_from(u64::decode(nka, key, val)?)
+            .map_err(|_| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl NsDecode<'_> for i32 {
+    fn decode(nka: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        i32::try_from(i64::decode(nka, key, val)?)
+            .map_err(|_| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl NsDecode<'_> for f32 {
+    fn decode(nka: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        f64::decode(nka, key, val).map(|v| v as Self)
+    }
+}
+
+impl<'a> NsDecode<'a> for &'a Dictionary {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &'a Value) -> Result<Self, NsArchiveError> {
+        val.as_dictionary()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl<'a> NsDecode<'a> for &'a Value {
+    fn decode(_: &NsKeyedArchive, _: &str, val: &'a Value) -> Result<Self, NsArchiveError> {
+        Ok(val)
+    }
+}
+
+impl<'a> NsDecode<'a> for &'a [u8] {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &'a Value) -> Result<Self, NsArchiveError> {
+        val.as_data()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl NsDecode<'_> for Uid {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        val.as_uid()
+            .copied()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl<'a> NsDecode<'a> for &'a str {
+    fn decode(_: &NsKeyedArchive, key: &str, val: &'a Value) -> Result<Self, NsArchiveError> {
+        val.as_string()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl<'a> NsDecode<'a> for String {
+    fn decode(a: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        if let Ok(s) = NsString::decode(a, key, val) {
+            return Ok(s.string);
+        }
+        Ok(<&'_ str>::decode(a, key, val)?.to_owned())
+    }
+}
+
+impl<'a, T> NsDecode<'a> for Box<T>
+where
+    T: NsDecode<'a>,
+{
+    fn fetch(
+        nka: &'a NsKeyedArchive,
+        coder: &'a Dictionary,
+        key: &'a str,
+    ) -> Result<Self, NsArchiveError> {
+        Ok(Box::new(T::fetch(nka, coder, key)?))
+    }
+
+    fn decode(a: &'a NsKeyedArchive, key: &'a str, val: &'a Value) -> Result<Self, NsArchiveError> {
+        Ok(Box::new(T::decode(a, key, val)?))
+    }
+}
+
+impl<'a, T> NsDecode<'a> for Option<T>
+where
+    T: NsDecode<'a>,
+{
+    fn fetch(
+        nka: &'a NsKeyedArchive,
+        coder: &'a Dictionary,
+        key: &'a str,
+    ) -> Result<Self, NsArchiveError> {
+        nka.fetch_value_nullable(coder, key)?
+            .map(|z| T::decode(nka, key, z))
+            .transpose()
+    }
+
+    fn decode(
+        nka: &'a NsKeyedArchive,
+        key: &'a str,
+        val: &'a Value,
+    ) -> Result<Self, NsArchiveError> {
+        Ok(Some(T::decode(nka, key, val)?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size<T> {
+    pub width: T,
+    pub height: T,
+}
+use std::str::FromStr;
+impl<T: FromStr> Size<T> {
+    /// Parse a size in the `{width, height}` format of `NSStringFromCGSize`.
+    /// Surrounding whitespace, signs and fractions are accepted, and whole
+    /// numbers written as floats (`2048.0`) also parse into integer sizes.
+    pub fn parse(string: &str) -> Option<Self> {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        let size_regex = INSTANCE.get_or_init(|| {
+            Regex::new(r"^\s*\{\s*([^,{}\s]+)\s*,\s*([^,{}\s]+)\s*\}\s*$").unwrap()
+        });
+        let captures = size_regex.captures(string)?;
+        Some(Size {
+            width: Self::parse_component(&captures[1])?,
+            height: Self::parse_component(&captures[2])?,
+        })
+    }
+
+    fn parse_component(string: &str) -> Option<T> {
+        string.parse::<T>().ok().or_else(|| {
+            let float = string.parse::<f64>().ok().filter(|f| f.fract() == 0.0)?;
+            format!("{float:.0}").parse::<T>().ok()
+        })
+    }
+}
+
+impl<T: FromStr> NsDecode<'_> for Size<T> {
+    fn decode(nka: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        let string = <&'_ str>::decode(nka, key, val)?;
+        Size::parse(string).ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))
+    }
+}
+
+impl<'a, T> NsDecode<'a> for Vec<T>
+where
+    T: NsDecode<'a>,
+{
+    fn decode(
+        nka: &'a NsKeyedArchive,
+        key: &'a str,
+        val: &'a Value,
+    ) -> Result<Self, NsArchiveError> {
+        val.as_array()
+            .ok_or_else(|| NsArchiveError::TypeMismatch(key.to_string()))?
+            .iter()
+            .map(|val| T::decode(nka, key, val))
+            .collect::<Result<Vec<_>, _>>()
+    }
+}
+
+#[derive(Debug)]
+pub struct WrappedArray<T> {
+    pub objects: Vec<T>,
+}
+
+impl<'a, T> NsDecode<'a> for WrappedArray<T>
+where
+    T: NsDecode<'a>,
+{
+    fn decode(
+        nka: &'a NsKeyedArchive,
+        key: &'a str,
+        val: &'a Value,
+    ) -> Result<Self, NsArchiveError> {
+        Ok(Self {
+            objects: WrappedRawArray::decode(nka, key, val)?
+                .inner
+                .iter()
+                .map(|uid| T::decode(nka, key, nka.resolve_index(uid.get() as usize)?))
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct WrappedRawArray {
+    pub inner: Vec<Uid>,
+}
+
+impl NsDecode<'_> for WrappedRawArray {
+    fn decode(nka: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        let coder = <&'_ Dictionary>::decode(nka, key, val)?;
+        Ok(Self {
+            inner: nka.fetch::<Vec<Uid>>(coder, "NS.objects")?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct NsClass {
+    pub class_name: String,
+    pub classes: Vec<String>,
+}
+
+impl NsDecode<'_> for NsClass {
+    fn decode(nka: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        let coder = <&'_ Dictionary>::decode(nka, key, val)?;
+        Ok(Self {
+            class_name: nka.fetch::<String>(coder, "$classname")?,
+            classes: nka.fetch::<Vec<String>>(coder, "$classes")?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct NsString {
+    pub class: NsClass,
+    pub string: String,
+}
+
+impl NsDecode<'_> for NsString {
+    fn decode(nka: &NsKeyedArchive, key: &str, val: &Value) -> Result<Self, NsArchiveError> {
+        let coder = <&'_ Dictionary>::decode(nka, key, val)?;
+        Ok(Self {
+            class: nka.fetch::<NsClass>(coder, "$class")?,
+            string: nka.fetch::<String>(coder, "NS.string")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NsKeyedArchive, Size};
+    use plist::{Dictionary, Uid, Value};
+    use proptest::prelude::*;
+    use std::collections::BTreeSet;
+    use std::io::Cursor;
+
+    /// Whitespace that appears around size components in real documents.
+    fn space() -> impl Strategy<Value = &'static str> {
+        prop::sample::select(vec!["", " ", "  ", "\t"])
+    }
+
+    #[test]
+    fn parses_common_sizes() {
+        assert_eq!(
+            Size::parse("{2048, 1536}"),
+            Some(Size {
+                width: 2048u32,
+                height: 1536
+            })
+        );
+        assert_eq!(
+            Size::parse("{2048,1536}"),
+            Some(Size {
+                width: 2048u32,
+                height: 1536
+            })
+        );
+        assert_eq!(
+            Size::parse("{2048.0, 1536}"),
+            Some(Size {
+                width: 2048u32,
+                height: 1536
+            })
+        );
+        assert_eq!(
+            Size::parse("{-1.5, 2}"),
+            Some(Size {
+                width: -1.5f32,
+                height: 2.0
+            })
+        );
+        assert_eq!(Size::<u32>::parse("{-1, 2}"), None);
+        assert_eq!(Size::<u32>::parse("{2048.5, 2}"), None);
+        assert_eq!(Size::<u32>::parse("{1, 2, 3}"), None);
+        assert_eq!(Size::<u32>::parse("1, 2"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn integers_round_trip(w: i32, h: i32, a in space(), b in space(), c in space(), d in space()) {
+            let string = format!("{{{a}{w}{b},{c}{h}{d}}}");
+            prop_assert_eq!(Size::parse(&string), Some(Size { width: w, height: h }));
+        }
+
+        #[test]
+        fn floats_round_trip(w in -1e9f32..1e9, h in -1e9f32..1e9, a in space(), b in space()) {
+            let string = format!("{a}{{{w}, {h}}}{b}");
+            prop_assert_eq!(Size::parse(&string), Some(Size { width: w, height: h }));
+        }
+
+        #[test]
+        fn whole_floats_parse_as_integers(w: u32, h: u32) {
+            let string = format!("{{{w}.0, {h}.0}}");
+            prop_assert_eq!(Size::parse(&string), Some(Size { width: w, height: h }));
+        }
+
+        #[test]
+        fn never_panics(string in "\\PC*") {
+            let _ = Size::<u32>::parse(&string);
+            let _ = Size::<f32>::parse(&string);
+        }
+    }
+
+    fn class(name: &str) -> Value {
+        let mut class = Dictionary::new();
+        class.insert("$classname".into(), Value::String(name.into()));
+        class.insert(
+            "$classes".into(),
+            Value::Array(vec![Value::String(name.into()), "NSObject".into()]),
+        );
+        Value::Dictionary(class)
+    }
+
+    /// Archive of a document with a read and an unread key, that refers
+    /// to a layer.
+    fn archive() -> NsKeyedArchive {
+        let mut root = Dictionary::new();
+        root.insert("$class".into(), Value::Uid(Uid::new(2)));
+        root.insert("read".into(), Value::from(1u64));
+        root.insert("unread".into(), Value::from(2u64));
+        root.insert("layer".into(), Value::Uid(Uid::new(3)));
+        let mut layer = Dictionary::new();
+        layer.insert("$class".into(), Value::Uid(Uid::new(4)));
+        layer.insert("name".into(), Value::String("Layer".into()));
+
+        let mut top = Dictionary::new();
+        top.insert("root".into(), Value::Uid(Uid::new(1)));
+        let mut archive = Dictionary::new();
+        archive.insert("$top".into(), Value::Dictionary(top));
+        archive.insert(
+            "$objects".into(),
+            Value::Array(vec![
+                "$null".into(),
+                Value::Dictionary(root),
+                class("SilicaDocument"),
+                Value::Dictionary(layer),
+                class("SilicaLayer"),
+            ]),
+        );
+        let mut bytes = Vec::new();
+        Value::Dictionary(archive)
+            .to_writer_binary(&mut bytes)
+            .unwrap();
+        NsKeyedArchive::from_reader(Cursor::new(bytes)).unwrap()
+    }
+
+    fn read_document(nka: &NsKeyedArchive) {
+        let root = nka.root().unwrap();
+        nka.fetch::<u64>(root, "read").unwrap();
+        nka.fetch::<&Dictionary>(root, "layer").unwrap();
+        // Missing keys are not part of the report.
+        assert!(nka.fetch::<Option<u64>>(root, "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn coverage_lists_unread_classes_and_keys() {
+        let nka = archive().track_reads();
+        read_document(&nka);
+
+        let coverage = nka.coverage(&[], &[]).unwrap();
+        assert_eq!(
+            coverage.unknown_classes,
+            BTreeSet::from(["SilicaLayer".to_string()])
+        );
+        assert_eq!(coverage.unknown_keys.len(), 1);
+        assert_eq!(
+            coverage.unknown_keys["SilicaDocument"],
+            BTreeSet::from(["unread".to_string()])
+        );
+        assert_eq!(coverage.unknown_key_count(), 1);
+        assert!(!coverage.is_complete());
+
+        let summary = coverage.summary("Silicate 9.9.9");
+        assert!(summary.starts_with("Silicate 9.9.9 schema summary\n"));
+        assert!(summary.contains("\n  SilicaLayer\n"));
+        assert!(summary.contains("\n  SilicaDocument\n    unread\n"));
+        // Values are left out of the summary.
+        assert!(!summary.contains("Layer\n    name"));
+    }
+
+    #[test]
+    fn coverage_leaves_out_ignored_classes_and_keys() {
+        let nka = archive().track_reads();
+        read_document(&nka);
+
+        let coverage = nka.coverage(&["SilicaLayer"], &["unread"]).unwrap();
+        assert!(coverage.is_complete());
+        assert_eq!(coverage.unknown_key_count(), 0);
+    }
+
+    #[test]
+    fn coverage_needs_tracked_reads() {
+        let nka = archive();
+        read_document(&nka);
+        assert_eq!(nka.coverage(&[], &[]), None);
+    }
+}
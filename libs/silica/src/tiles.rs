@@ -0,0 +1,171 @@
+use crate::ns_archive::Size;
+#[cfg(feature = "gpu")]
+use crate::SilicaError;
+#[cfg(feature = "gpu")]
+use compositor::{
+    dev::GpuHandle,
+    tex::{GpuTexture, Region, TiledTexture},
+};
+use image::RgbaImage;
+use parking_lot::Mutex;
+
+/// Pixels of a tile decoded from its chunk, in premultiplied RGBA with the
+/// rows stored bottom-up like the layer textures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedTile {
+    /// Position of the first pixel of the tile on the canvas.
+    pub origin: (u32, u32),
+    pub size: Size<u32>,
+    pub data: Vec<u8>,
+}
+
+/// Textures that the layers of a file are decoded into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasLayout {
+    pub size: Size<u32>,
+    pub tile_size: u32,
+    /// Textures for the layers, then the background image, then groups.
+    pub count: u32,
+}
+
+/// Where the tiles of a file go as they are decoded, such as the layer
+/// textures on the GPU or images in memory.
+pub trait TileSink: Sync {
+    /// Store a tile into the texture at `image`. Tiles are decoded on many
+    /// threads at once.
+    fn write_tile(&self, image: u32, tile: DecodedTile);
+}
+
+/// Uploads the tiles into layer textures on the GPU.
+#[cfg(feature = "gpu")]
+pub struct GpuUpload<'a> {
+    dev: &'a GpuHandle,
+    pub textures: TiledTexture,
+}
+
+#[cfg(feature = "gpu")]
+impl<'a> GpuUpload<'a> {
+    pub fn new(dev: &'a GpuHandle, layout: &AtlasLayout) -> Result<Self, SilicaError> {
+        // Canvases larger than a texture may be are split into regions,
+        // which line up with the tiles so that no tile straddles two.
+        let regions = Region::split(
+            layout.size.width,
+            layout.size.height,
+            dev.device.limits().max_texture_dimension_2d,
+            layout.tile_size,
+        );
+        let textures = TiledTexture::try_empty_layers(
+            dev,
+            (layout.size.width, layout.size.height),
+            regions,
+            layout.count,
+            GpuTexture::LAYER_USAGE,
+        )?;
+        Ok(Self { dev, textures })
+    }
+}
+
+#[cfg(feature = "gpu")]
+impl TileSink for GpuUpload<'_> {
+    fn write_tile(&self, image: u32, tile: DecodedTile) {
+        self.textures.replace(
+            self.dev,
+            tile.origin,
+            (tile.size.width, tile.size.height),
+            image,
+            &tile.data,
+        );
+    }
+}
+
+/// Drops the tiles once they are decoded, to read the structure of a file
+/// without keeping its pixels.
+pub struct DiscardTiles;
+
+impl TileSink for DiscardTiles {
+    fn write_tile(&self, _image: u32, _tile: DecodedTile) {}
+}
+
+/// Tiles kept in memory instead of GPU textures, to read the decoded pixels
+/// without a GPU.
+#[derive(Debug)]
+pub struct Rgba8Canvas {
+    size: Size<u32>,
+    tiles: Mutex<Vec<(u32, DecodedTile)>>,
+}
+
+impl Rgba8Canvas {
+    pub fn new(layout: &AtlasLayout) -> Self {
+        Self {
+            size: layout.size,
+            tiles: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Texture at `image` with its tiles put together, bottom-up and
+    /// premultiplied like the GPU textures. Pixels without a tile are left
+    /// transparent.
+    pub fn image(&self, image: u32) -> RgbaImage {
+        let mut out = RgbaImage::new(self.size.width, self.size.height);
+        let stride = self.size.width as usize * 4;
+        let buffer: &mut [u8] = &mut out;
+        for (_, tile) in self
+            .tiles
+            .lock()
+            .iter()
+            .filter(|(index, _)| *index == image)
+        {
+            let (x, y) = tile.origin;
+            if x >= self.size.width {
+                continue;
+            }
+            let width = tile.size.width.min(self.size.width - x) as usize * 4;
+            let rows = tile.data.chunks_exact(tile.size.width as usize * 4);
+            for (row, line) in (y..self.size.height).zip(rows) {
+                let start = row as usize * stride + x as usize * 4;
+                buffer[start..start + width].copy_from_slice(&line[..width]);
+            }
+        }
+        out
+    }
+}
+
+impl TileSink for Rgba8Canvas {
+    fn write_tile(&self, image: u32, tile: DecodedTile) {
+        self.tiles.lock().push((image, tile));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_are_put_together_and_clipped() {
+        let canvas = Rgba8Canvas::new(&AtlasLayout {
+            size: Size {
+                width: 3,
+                height: 3,
+            },
+            tile_size: 2,
+            count: 2,
+        });
+        let tile = |origin, fill| DecodedTile {
+            origin,
+            size: Size {
+                width: 2,
+                height: 2,
+            },
+            data: vec![fill; 2 * 2 * 4],
+        };
+        canvas.write_tile(0, tile((0, 0), 1));
+        canvas.write_tile(0, tile((2, 2), 2));
+        canvas.write_tile(1, tile((0, 2), 3));
+
+        let image = canvas.image(0);
+        assert_eq!(image.get_pixel(1, 1).0, [1; 4]);
+        assert_eq!(image.get_pixel(2, 2).0, [2; 4]);
+        assert_eq!(image.get_pixel(2, 0).0, [0; 4]);
+        assert_eq!(canvas.image(1).get_pixel(1, 2).0, [3; 4]);
+    }
+}
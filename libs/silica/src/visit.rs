@@ -0,0 +1,211 @@
+use super::{SilicaGroup, SilicaHierarchy, SilicaLayer};
+
+/// Depth first iterator over the nodes beneath a group, in document order.
+/// Groups are yielded before their children.
+pub struct Nodes<'a> {
+    stack: Vec<std::slice::Iter<'a, SilicaHierarchy>>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = &'a SilicaHierarchy;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(node) = self.stack.last_mut()?.next() else {
+                self.stack.pop();
+                continue;
+            };
+            if let SilicaHierarchy::Group(group) = node {
+                self.stack.push(group.children.iter());
+            }
+            return Some(node);
+        }
+    }
+}
+
+impl SilicaGroup {
+    /// Every node beneath this group, depth first in document order.
+    pub fn iter(&self) -> Nodes<'_> {
+        Nodes {
+            stack: vec![self.children.iter()],
+        }
+    }
+
+    /// Every layer beneath this group, depth first in document order.
+    pub fn iter_layers(&self) -> impl Iterator<Item = &SilicaLayer> {
+        self.iter().filter_map(|node| match node {
+            SilicaHierarchy::Layer(layer) => Some(layer),
+            SilicaHierarchy::Group(_) => None,
+        })
+    }
+
+    /// Every group beneath this group, depth first in document order.
+    pub fn iter_groups(&self) -> impl Iterator<Item = &SilicaGroup> {
+        self.iter().filter_map(|node| match node {
+            SilicaHierarchy::Layer(_) => None,
+            SilicaHierarchy::Group(group) => Some(group),
+        })
+    }
+
+    /// Call `f` on every node beneath this group, depth first in document
+    /// order, along with the child indices that lead to the node from this
+    /// group. Groups are visited before their children, so changes made to
+    /// a group's children are reflected in the rest of the walk.
+    pub fn visit_mut(&mut self, mut f: impl FnMut(&[usize], &mut SilicaHierarchy)) {
+        fn inner(
+            group: &mut SilicaGroup,
+            path: &mut Vec<usize>,
+            f: &mut impl FnMut(&[usize], &mut SilicaHierarchy),
+        ) {
+            for (i, node) in group.children.iter_mut().enumerate() {
+                path.push(i);
+                f(path, node);
+                if let SilicaHierarchy::Group(group) = node {
+                    inner(group, path, f);
+                }
+                path.pop();
+            }
+        }
+        inner(self, &mut Vec::new(), &mut f);
+    }
+
+    /// Call `f` on every node beneath this group like [`Self::visit_mut`],
+    /// along with the node's hierarchy path made of the names that lead to
+    /// it from this group, separated by `/`. Unnamed nodes have an empty name.
+    pub fn visit_paths_mut(&mut self, mut f: impl FnMut(&str, &mut SilicaHierarchy)) {
+        fn inner(
+            group: &mut SilicaGroup,
+            path: &mut String,
+            f: &mut impl FnMut(&str, &mut SilicaHierarchy),
+        ) {
+            for node in group.children.iter_mut() {
+                let parent_len = path.len();
+                if parent_len > 0 {
+                    path.push('/');
+                }
+                path.push_str(node.name().unwrap_or_default());
+                f(path, node);
+                if let SilicaHierarchy::Group(group) = node {
+                    inner(group, path, f);
+                }
+                path.truncate(parent_len);
+            }
+        }
+        inner(self, &mut String::new(), &mut f);
+    }
+
+    /// Find the layer with the given UUID beneath this group.
+    pub fn find_layer(&self, uuid: &str) -> Option<&SilicaLayer> {
+        self.iter_layers().find(|layer| layer.uuid == uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::{loaded_group, loaded_layer};
+    use super::*;
+
+    fn named(image: u32, name: &str) -> SilicaHierarchy {
+        let mut layer = loaded_layer(image);
+        layer.name = Some(name.to_string());
+        SilicaHierarchy::Layer(layer)
+    }
+
+    fn group(image: u32, name: &str, children: Vec<SilicaHierarchy>) -> SilicaHierarchy {
+        let mut group = loaded_group(image, children);
+        group.name = Some(name.to_string());
+        SilicaHierarchy::Group(group)
+    }
+
+    /// Layers 0 to 3 with groups 10 and 11, the second inside the first.
+    fn document() -> SilicaGroup {
+        loaded_group(
+            u32::MAX,
+            vec![
+                named(0, "a"),
+                group(
+                    10,
+                    "Inks",
+                    vec![
+                        named(1, "b"),
+                        group(11, "Flats", vec![SilicaHierarchy::Layer(loaded_layer(2))]),
+                    ],
+                ),
+                named(3, "d"),
+            ],
+        )
+    }
+
+    #[test]
+    fn iterators_go_depth_first_with_groups_before_children() {
+        let doc = document();
+        let images = doc.iter().map(|node| node.image()).collect::<Vec<_>>();
+        assert_eq!(images, [0, 10, 1, 11, 2, 3]);
+        let layers = doc.iter_layers().map(|layer| layer.image);
+        assert_eq!(layers.collect::<Vec<_>>(), [0, 1, 2, 3]);
+        let groups = doc.iter_groups().map(|group| group.image);
+        assert_eq!(groups.collect::<Vec<_>>(), [10, 11]);
+        assert_eq!(loaded_group(0, Vec::new()).iter().count(), 0);
+    }
+
+    #[test]
+    fn visit_mut_passes_child_indices() {
+        let mut doc = document();
+        let mut visited = Vec::new();
+        doc.visit_mut(|path, node| visited.push((path.to_vec(), node.image())));
+        assert_eq!(
+            visited,
+            [
+                (vec![0], 0),
+                (vec![1], 10),
+                (vec![1, 0], 1),
+                (vec![1, 1], 11),
+                (vec![1, 1, 0], 2),
+                (vec![2], 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn visit_mut_walks_children_added_to_groups() {
+        let mut doc = document();
+        let mut visited = Vec::new();
+        doc.visit_mut(|_, node| {
+            visited.push(node.image());
+            if let SilicaHierarchy::Group(group) = node {
+                if group.image == 11 {
+                    group.children.push(SilicaHierarchy::Layer(loaded_layer(4)));
+                }
+            }
+        });
+        assert_eq!(visited, [0, 10, 1, 11, 2, 4, 3]);
+    }
+
+    #[test]
+    fn visit_paths_mut_joins_names() {
+        let mut doc = document();
+        let mut paths = Vec::new();
+        doc.visit_paths_mut(|path, node| paths.push((path.to_string(), node.image())));
+        assert_eq!(
+            paths,
+            [
+                ("a".to_string(), 0),
+                ("Inks".to_string(), 10),
+                ("Inks/b".to_string(), 1),
+                ("Inks/Flats".to_string(), 11),
+                // Unnamed nodes still take a segment.
+                ("Inks/Flats/".to_string(), 2),
+                ("d".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_layer_searches_nested_groups() {
+        let doc = document();
+        assert_eq!(doc.find_layer("2").map(|layer| layer.image), Some(2));
+        assert_eq!(doc.find_layer("0").map(|layer| layer.image), Some(0));
+        // Groups are not layers.
+        assert!(doc.find_layer("10").is_none());
+    }
+}
@@ -0,0 +1,1186 @@
+//! Commands that run headlessly instead of opening the viewer.
+
+use crate::gui::app::{App, Instance};
+use crate::template::{self, TemplateError};
+use crate::SOFTWARE;
+use compositor::dev::{GpuBackend, GpuHandle, GpuPreference};
+use compositor::tex::{ExportKind, ExportScale};
+use compositor::CompositorPipeline;
+use parking_lot::Mutex;
+use regex::Regex;
+use silica::{BlendingMode, ProcreateFile, SilicaError, SilicaGroup, SilicaHierarchy};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::runtime::Runtime;
+
+const USAGE: &str =
+    "Usage: silicate [--verbose] [--threads <N>] [--backend <NAME>] [--adapter <INDEX|NAME>]
+                [--shader <FILE.wgsl>] [<FILE.procreate> | <URL> | -]...
+       silicate export <FILE.procreate> <OUTPUT> [--hide <PATTERN>]... [--show <PATTERN>]...
+                       [--set <PATTERN>:<KEY>=<VALUE>[,<KEY>=<VALUE>]...]...
+                       [--progress <text|json>]
+       silicate watch <DIRECTORY> --out <DIRECTORY> [--name <TEMPLATE>] [<LAYER OPTIONS>]...
+       silicate inspect <FILE.procreate>
+       silicate info [--json] <FILE.procreate>
+
+Patterns are matched against layer paths such as `Sketch/Layer 1`, where `*`
+matches within a group name, `**` matches across groups and `?` matches one
+character. `--set` changes the `opacity` (0 to 1), `blend` mode or `hidden`
+state of the matched layers, ie. `--set \"Shadows:opacity=0.5,blend=multiply\"`.
+Overrides are applied in order, so later ones take precedence.
+
+A file of `-` is read from standard input, so that files can be piped in from
+other tools. HTTP and WebDAV links are downloaded before they are opened.
+
+The output can name subdirectories, which are created, and contain `{stem}`,
+the input file name without its extension, `{name}` and `{author}` of the
+document, `{width}` and `{height}` of the image, `{date}` and `{layer}`, ie.
+`\"{author}/{stem}-{width}x{height}.png\"`. With `{layer}`, each visible layer is
+exported on its own with transparency, `{layer}` being its path so that its
+groups become subdirectories. Literal braces are written `{{` and `}}`.
+
+`--progress` writes the percentage done of the `load`, `composite` and
+`export` stages to stderr as `stage: percent%` lines or as JSON objects.
+
+`watch` exports every `.procreate` file in the directory as a PNG of the same
+name, and again whenever one is added or changed, until interrupted. `--name`
+names the exports within the output directory with the same tokens as the
+output of `export`, `{stem}.png` by default. The layer options are the same as
+for `export`, files without a matching layer are skipped.
+
+`inspect` checks the structure of a file without decoding its tiles, listing
+its layers and chunks, tiles outside the canvas or whose chunks cannot be read,
+chunks that belong to no layer and keys that fail to decode or are unknown. It
+exits with an error when any problems are found.
+
+`info` lists the document's metadata and layer tree, or with `--json` writes
+them as JSON with the blending, opacity, flags, bounds and UUID of every layer.
+
+`--threads` decodes files on at most that many threads rather than on every
+core, for the viewer and for every command.
+
+`--backend` draws with `vulkan`, `dx12`, `metal`, `gl` or on the CPU with
+`software` rather than the platform's default, and `--adapter` with the
+adapter of that index or whose name contains the text rather than the most
+powerful one, for the viewer and for every command. They take the place of
+the GPU chosen in the settings. Should the device fail to be created, the
+default adapter of each backend is tried in turn and finally `software`.
+
+`--shader`, or the `SILICATE_SHADER` environment variable, composites with a
+WGSL shader file in place of the built in one, and reloads it whenever the
+file changes. Shaders that fail to validate leave the last one in place.";
+
+/// File argument that reads the file from standard input.
+pub const STDIN: &str = "-";
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("missing {0}\n\n{USAGE}")]
+    MissingArgument(&'static str),
+    #[error("unexpected argument `{0}`\n\n{USAGE}")]
+    UnexpectedArgument(String),
+    #[error("invalid layer setting `{0}`, expected `PATTERN:opacity=0.5,blend=multiply`")]
+    InvalidSetting(String),
+    #[error("pattern `{0}` does not match any layer or group")]
+    UnmatchedPattern(String),
+    #[error("invalid thread count `{0}`, expected a number above 0")]
+    InvalidThreads(String),
+    #[error("unknown backend `{0}`, expected auto, vulkan, dx12, metal, gl or software")]
+    InvalidBackend(String),
+    #[error("no GPU adapter matches `{0}`, the adapters are:{1}")]
+    UnknownAdapter(String, String),
+    #[error("cannot watch directory: {0}")]
+    Watch(std::io::Error),
+    #[error("invalid output name: {0}")]
+    Template(#[from] TemplateError),
+    #[error("cannot create output directory: {0}")]
+    OutputDirectory(std::io::Error),
+    #[error("problems were found in the file")]
+    Damaged,
+    #[error("no compatible GPU adapter was found")]
+    NoAdapter,
+    #[error("`{STDIN}` reads a file piped to standard input, but none is piped")]
+    NoPipedFile,
+    #[error(transparent)]
+    Load(#[from] SilicaError),
+    #[error(transparent)]
+    Export(#[from] image::ImageError),
+}
+
+/// Properties to change on the layers and groups matched by an override.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct LayerChanges {
+    hidden: Option<bool>,
+    opacity: Option<f32>,
+    blend: Option<BlendingMode>,
+}
+
+impl LayerChanges {
+    /// Parse comma separated `key=value` pairs, ie. `opacity=0.5,blend=multiply`.
+    fn parse(text: &str) -> Option<Self> {
+        let mut changes = Self::default();
+        for pair in text.split(',') {
+            let (key, value) = pair.split_once('=')?;
+            let value = value.trim();
+            match key.trim() {
+                "opacity" => {
+                    changes.opacity = Some(
+                        value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|o| (0.0..=1.0).contains(o))?,
+                    )
+                }
+                "blend" => {
+                    // Accept `soft-light`, `soft_light` or `SoftLight` for `Soft Light`.
+                    let normalize = |name: &str| {
+                        name.chars()
+                            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+                            .collect::<String>()
+                            .to_lowercase()
+                    };
+                    let value = normalize(value);
+                    changes.blend = Some(
+                        *BlendingMode::all()
+                            .iter()
+                            .find(|blend| normalize(blend.as_str()) == value)?,
+                    )
+                }
+                "hidden" => changes.hidden = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+        Some(changes)
+    }
+}
+
+/// Changes the layers and groups whose hierarchy path matches a glob.
+pub struct LayerOverride {
+    pattern: String,
+    regex: Regex,
+    changes: LayerChanges,
+}
+
+impl LayerOverride {
+    fn new(pattern: String, changes: LayerChanges) -> Self {
+        let regex = glob_regex(&pattern);
+        Self {
+            pattern,
+            regex,
+            changes,
+        }
+    }
+
+    /// Hide or show the matching layers and groups.
+    pub fn visibility(pattern: String, hidden: bool) -> Self {
+        Self::new(
+            pattern,
+            LayerChanges {
+                hidden: Some(hidden),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Parse a `PATTERN:key=value,...` argument of `--set`.
+    pub fn parse_set(arg: &str) -> Option<Self> {
+        let (pattern, changes) = arg.rsplit_once(':')?;
+        Some(Self::new(
+            pattern.to_string(),
+            LayerChanges::parse(changes)?,
+        ))
+    }
+
+    /// Apply the override beneath the group, returning whether it matched
+    /// any layer or group.
+    fn apply(&self, layers: &mut SilicaGroup) -> bool {
+        let LayerChanges {
+            hidden,
+            opacity,
+            blend,
+        } = self.changes;
+        let mut matched = false;
+        layers.visit_paths_mut(|path, node| {
+            if self.regex.is_match(path) {
+                if let Some(hidden) = hidden {
+                    node.set_hidden(hidden);
+                }
+                if let Some(opacity) = opacity {
+                    node.set_opacity(opacity);
+                }
+                if let Some(blend) = blend {
+                    node.set_blend(blend);
+                }
+                matched = true;
+            }
+        });
+        matched
+    }
+}
+
+/// Translate a glob over `/` separated paths into an anchored regex.
+fn glob_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.next_if_eq(&'*').is_some() => pattern.push_str(".*"),
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).expect("escaped glob is a valid regex")
+}
+
+/// Values of the tokens of an output template for one exported image.
+struct OutputValues<'a> {
+    /// Input file name without its extension.
+    stem: &'a str,
+    name: Option<&'a str>,
+    author: Option<&'a str>,
+    width: u32,
+    height: u32,
+    /// Hierarchy path of the layer, when layers are exported on their own.
+    layer: Option<&'a str>,
+}
+
+/// Output path in which `{stem}`, `{name}`, `{author}`, `{width}`,
+/// `{height}`, `{date}` and `{layer}` are replaced, so that exports can be
+/// sorted into subdirectories such as `{author}/{stem}-{width}x{height}.png`.
+pub struct OutputTemplate {
+    /// Directory the expanded path is relative to, which is not expanded.
+    dir: PathBuf,
+    template: String,
+}
+
+impl OutputTemplate {
+    const TOKENS: [&'static str; 7] =
+        ["stem", "name", "author", "width", "height", "date", "layer"];
+
+    fn parse(template: String) -> Result<Self, CliError> {
+        template::expand(&template, |token| {
+            Self::TOKENS.contains(&token).then(String::new)
+        })?;
+        Ok(Self {
+            dir: PathBuf::new(),
+            template,
+        })
+    }
+
+    /// Place the expanded paths within a directory.
+    fn within(self, dir: PathBuf) -> Self {
+        Self { dir, ..self }
+    }
+
+    /// Whether each layer is exported to a file of its own, which is when
+    /// the template has a `{layer}`.
+    fn per_layer(&self) -> bool {
+        let per_layer = Cell::new(false);
+        let _ = template::expand(&self.template, |token| {
+            per_layer.set(per_layer.get() || token == "layer");
+            Some(String::new())
+        });
+        per_layer.get()
+    }
+
+    /// Path of an image with the given values. Values are made safe to use
+    /// as file names, except that the groups of a layer path become
+    /// subdirectories.
+    fn path(&self, values: &OutputValues) -> PathBuf {
+        let expanded = template::expand(&self.template, |token| {
+            Some(match token {
+                "stem" => template::file_name(values.stem),
+                "name" => template::file_name(values.name.unwrap_or_default()),
+                "author" => template::file_name(values.author.unwrap_or("Unknown")),
+                "width" => values.width.to_string(),
+                "height" => values.height.to_string(),
+                "date" => template::today(),
+                "layer" => values
+                    .layer
+                    .unwrap_or_default()
+                    .split('/')
+                    .map(template::file_name)
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                _ => return None,
+            })
+        });
+        self.dir
+            .join(expanded.expect("template was validated when parsed"))
+    }
+}
+
+/// How progress is written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// `load: 42%` lines.
+    Text,
+    /// `{"stage":"load","percent":42}` lines.
+    Json,
+}
+
+/// Reports the percentage done of each stage whenever it goes up.
+struct Progress {
+    format: Option<ProgressFormat>,
+    last: Mutex<Option<(&'static str, usize)>>,
+}
+
+impl Progress {
+    fn report(&self, stage: &'static str, done: usize, total: usize) {
+        let Some(format) = self.format else {
+            return;
+        };
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        let mut last = self.last.lock();
+        // Tiles finish out of order across threads, only ever report forward.
+        if last.is_some_and(|(last_stage, last_percent)| {
+            last_stage == stage && last_percent >= percent
+        }) {
+            return;
+        }
+        *last = Some((stage, percent));
+        match format {
+            ProgressFormat::Text => eprintln!("{stage}: {percent}%"),
+            ProgressFormat::Json => {
+                eprintln!("{{\"stage\":\"{stage}\",\"percent\":{percent}}}")
+            }
+        }
+    }
+}
+
+pub enum Command {
+    /// Composite a file and write it out as an image.
+    Export {
+        input: PathBuf,
+        output: OutputTemplate,
+        overrides: Vec<LayerOverride>,
+        progress: Option<ProgressFormat>,
+    },
+    /// Export the files of a directory as they are added or changed.
+    Watch {
+        input: PathBuf,
+        output: PathBuf,
+        /// Names of the exports, within the output directory.
+        name: OutputTemplate,
+        overrides: Vec<LayerOverride>,
+    },
+    /// Check the structure of a file without loading it.
+    Inspect { input: PathBuf },
+    /// Print the metadata and layer tree of a file.
+    Info { input: PathBuf, json: bool },
+}
+
+/// Take an option and its value out of the arguments.
+fn take_option(
+    args: &mut Vec<String>,
+    flag: &str,
+    value: &'static str,
+) -> Result<Option<String>, CliError> {
+    let Some(index) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    args.remove(index);
+    if index == args.len() {
+        return Err(CliError::MissingArgument(value));
+    }
+    Ok(Some(args.remove(index)))
+}
+
+/// Take the `--threads <N>` option out of the arguments, which applies to
+/// the viewer and every command alike.
+pub fn take_threads(args: &mut Vec<String>) -> Result<Option<NonZeroUsize>, CliError> {
+    let Some(count) = take_option(args, "--threads", "thread count")? else {
+        return Ok(None);
+    };
+    count
+        .parse()
+        .map(Some)
+        .map_err(|_| CliError::InvalidThreads(count))
+}
+
+/// Take the `--backend <NAME>` and `--adapter <INDEX|NAME>` options out of
+/// the arguments, which override the saved GPU preference for the viewer
+/// and every command alike.
+pub fn take_gpu(args: &mut Vec<String>, saved: GpuPreference) -> Result<GpuPreference, CliError> {
+    let backend = take_option(args, "--backend", "backend")?;
+    let adapter = take_option(args, "--adapter", "adapter")?;
+    let mut preference = saved;
+    if let Some(backend) = backend {
+        preference.backend = *GpuBackend::all()
+            .iter()
+            .find(|b| b.as_str() == backend.to_lowercase())
+            .ok_or(CliError::InvalidBackend(backend))?;
+        // A saved adapter index is only meaningful for its own backend.
+        if preference.backend != saved.backend {
+            preference.adapter = None;
+        }
+    }
+    if let Some(adapter) = adapter {
+        let names = GpuHandle::adapter_names(preference.backend);
+        let index = match adapter.parse::<usize>() {
+            Ok(index) => Some(index).filter(|index| *index < names.len()),
+            Err(_) => {
+                let adapter = adapter.to_lowercase();
+                names
+                    .iter()
+                    .position(|name| name.to_lowercase().contains(&adapter))
+            }
+        };
+        preference.adapter = Some(index.ok_or_else(|| {
+            let mut list = names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| format!("\n  {i}: {name}"))
+                .collect::<String>();
+            if list.is_empty() {
+                list.push_str(" none");
+            }
+            CliError::UnknownAdapter(adapter, list)
+        })?);
+    }
+    Ok(preference)
+}
+
+/// Standard input to read a file passed as `-` from, refused when it is a
+/// terminal rather than a pipe, which would wait for input without saying so.
+pub fn stdin() -> Result<std::io::StdinLock<'static>, CliError> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Err(CliError::NoPipedFile);
+    }
+    Ok(stdin.lock())
+}
+
+impl Command {
+    /// How often a watched directory is scanned for changes.
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Parse the arguments following the program name. Returns `None` when
+    /// the viewer should be opened instead.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, CliError> {
+        let mut args = args
+            .into_iter()
+            .filter(|arg| arg != "--verbose" && arg != "-v");
+        let watch = match args.next().as_deref() {
+            Some("export") => false,
+            Some("watch") => true,
+            Some("inspect") => {
+                let input = match args.next() {
+                    Some(arg) if arg.starts_with('-') && arg != STDIN => {
+                        return Err(CliError::UnexpectedArgument(arg))
+                    }
+                    Some(arg) => PathBuf::from(arg),
+                    None => return Err(CliError::MissingArgument("input file")),
+                };
+                if let Some(extra) = args.next() {
+                    return Err(CliError::UnexpectedArgument(extra));
+                }
+                return Ok(Some(Self::Inspect { input }));
+            }
+            Some("info") => {
+                let mut input = None;
+                let mut json = false;
+                for arg in args {
+                    match arg.as_str() {
+                        "--json" => json = true,
+                        flag if flag.starts_with('-') && flag != STDIN => {
+                            return Err(CliError::UnexpectedArgument(arg))
+                        }
+                        _ if input.is_none() => input = Some(PathBuf::from(arg)),
+                        _ => return Err(CliError::UnexpectedArgument(arg)),
+                    }
+                }
+                let input = input.ok_or(CliError::MissingArgument("input file"))?;
+                return Ok(Some(Self::Info { input, json }));
+            }
+            _ => return Ok(None),
+        };
+
+        let mut positional = Vec::new();
+        let mut overrides = Vec::new();
+        let mut progress = None;
+        let mut out = None;
+        let mut name = None;
+        while let Some(arg) = args.next() {
+            let flag = match arg.as_str() {
+                "--progress" if !watch => {
+                    let format = args
+                        .next()
+                        .ok_or(CliError::MissingArgument("progress format"))?;
+                    progress = Some(match format.as_str() {
+                        "text" => ProgressFormat::Text,
+                        "json" => ProgressFormat::Json,
+                        _ => return Err(CliError::UnexpectedArgument(format)),
+                    });
+                    continue;
+                }
+                "--out" if watch => {
+                    out = Some(PathBuf::from(
+                        args.next()
+                            .ok_or(CliError::MissingArgument("output directory"))?,
+                    ));
+                    continue;
+                }
+                "--name" if watch => {
+                    name = Some(OutputTemplate::parse(
+                        args.next()
+                            .ok_or(CliError::MissingArgument("output name"))?,
+                    )?);
+                    continue;
+                }
+                "--hide" | "--show" | "--set" => arg,
+                flag if flag.starts_with('-') && flag != STDIN => {
+                    return Err(CliError::UnexpectedArgument(arg))
+                }
+                _ => {
+                    positional.push(arg);
+                    continue;
+                }
+            };
+            let value = args
+                .next()
+                .ok_or(CliError::MissingArgument("layer pattern"))?;
+            overrides.push(match flag.as_str() {
+                "--hide" => LayerOverride::visibility(value, true),
+                "--show" => LayerOverride::visibility(value, false),
+                _ => LayerOverride::parse_set(&value).ok_or(CliError::InvalidSetting(value))?,
+            });
+        }
+
+        let mut positional = positional.into_iter();
+        let input = PathBuf::from(positional.next().ok_or(CliError::MissingArgument(
+            if watch {
+                "input directory"
+            } else {
+                "input file"
+            },
+        ))?);
+        let output = if watch {
+            None
+        } else {
+            Some(OutputTemplate::parse(
+                positional
+                    .next()
+                    .ok_or(CliError::MissingArgument("output file"))?,
+            )?)
+        };
+        if let Some(extra) = positional.next() {
+            return Err(CliError::UnexpectedArgument(extra));
+        }
+        Ok(Some(match output {
+            None => {
+                let output = out.ok_or(CliError::MissingArgument("output directory"))?;
+                let name = match name {
+                    Some(name) => name,
+                    None => OutputTemplate::parse(String::from("{stem}.png"))?,
+                };
+                Self::Watch {
+                    input,
+                    name: name.within(output.clone()),
+                    output,
+                    overrides,
+                }
+            }
+            Some(output) => Self::Export {
+                input,
+                output,
+                overrides,
+                progress,
+            },
+        }))
+    }
+
+    pub fn run(self, rt: &Runtime, gpu: GpuPreference) -> Result<(), CliError> {
+        // Inspecting and describing need no GPU, so that they work wherever
+        // files are stored.
+        match &self {
+            Self::Inspect { input } => return inspect(input),
+            Self::Info { input, json } => return info(input, *json),
+            _ => {}
+        }
+        let dev = Arc::new(
+            rt.block_on(GpuHandle::new(gpu))
+                .ok_or(CliError::NoAdapter)?,
+        );
+        let pipeline = CompositorPipeline::new(&dev);
+        match self {
+            Self::Export {
+                input,
+                output,
+                overrides,
+                progress,
+            } => {
+                let progress = Progress {
+                    format: progress,
+                    last: Mutex::new(None),
+                };
+                export(rt, &dev, &pipeline, input, &output, &overrides, &progress).map(|_| ())
+            }
+            Self::Watch {
+                input,
+                output,
+                name,
+                overrides,
+            } => {
+                std::fs::create_dir_all(&output).map_err(CliError::Watch)?;
+                let progress = Progress {
+                    format: None,
+                    last: Mutex::new(None),
+                };
+                // Modification time and size of each file when last seen.
+                let mut seen = HashMap::new();
+                let mut exported = HashMap::new();
+                // Only a directory that cannot be read from the start is an
+                // error, later failures may pass, such as a share reconnecting.
+                std::fs::read_dir(&input).map_err(CliError::Watch)?;
+                tracing::info!("Watching {}", input.display());
+                loop {
+                    let entries = match std::fs::read_dir(&input) {
+                        Ok(entries) => entries,
+                        Err(err) => {
+                            tracing::warn!("Failed to scan {}, retrying: {err}", input.display());
+                            std::thread::sleep(Self::POLL_INTERVAL);
+                            continue;
+                        }
+                    };
+                    for entry in entries {
+                        let Ok(entry) = entry else {
+                            continue;
+                        };
+                        let path = entry.path();
+                        if path.extension().and_then(|ext| ext.to_str()) != Some("procreate") {
+                            continue;
+                        }
+                        let Ok(stamp) = entry
+                            .metadata()
+                            .and_then(|meta| Ok((meta.modified()?, meta.len())))
+                        else {
+                            continue;
+                        };
+                        // Wait for the file to stay the same between two
+                        // scans, so that files still being copied are skipped.
+                        let settled = seen.insert(path.clone(), stamp) == Some(stamp);
+                        if !settled || exported.get(&path) == Some(&stamp) {
+                            continue;
+                        }
+                        exported.insert(path.clone(), stamp);
+
+                        match export(
+                            rt,
+                            &dev,
+                            &pipeline,
+                            path.clone(),
+                            &name,
+                            &overrides,
+                            &progress,
+                        ) {
+                            Ok(written) => {
+                                for target in written {
+                                    eprintln!("{} -> {}", path.display(), target.display());
+                                }
+                            }
+                            Err(err) => {
+                                tracing::error!("Failed to export {}: {err}", path.display());
+                                eprintln!("Error: {}: {err}", path.display());
+                            }
+                        }
+                    }
+                    // Forget removed files so that they export again if restored.
+                    seen.retain(|path, _| path.exists());
+                    exported.retain(|path, _| seen.contains_key(path));
+                    std::thread::sleep(Self::POLL_INTERVAL);
+                }
+            }
+            Self::Inspect { .. } | Self::Info { .. } => unreachable!("handled without a GPU"),
+        }
+    }
+}
+
+/// Print the document information and layers of a file, decoding its tiles
+/// only to check them rather than keeping them in memory.
+fn info(input: &Path, json: bool) -> Result<(), CliError> {
+    let bytes = if input.as_os_str() == STDIN {
+        let mut bytes = Vec::new();
+        stdin()?.read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        std::fs::read(input)
+    }
+    .map_err(SilicaError::from)?;
+    let file = ProcreateFile::open_without_pixels(&bytes)?;
+    let text = if json {
+        crate::json::document(&file)
+    } else {
+        describe(&file)
+    };
+    let _ = writeln!(std::io::stdout().lock(), "{text}");
+    Ok(())
+}
+
+/// Print the structure of a file, failing if any problems were found.
+fn inspect(input: &Path) -> Result<(), CliError> {
+    let report = if input.as_os_str() == STDIN {
+        let mut bytes = Vec::new();
+        stdin()?
+            .read_to_end(&mut bytes)
+            .map_err(SilicaError::from)?;
+        ProcreateFile::inspect_bytes(&bytes)?
+    } else {
+        ProcreateFile::inspect(input)?
+    };
+    // Piping the report into a pager that quits early is not an error.
+    let _ = writeln!(std::io::stdout().lock(), "{}", report.summary(SOFTWARE));
+    if report.is_intact() {
+        Ok(())
+    } else {
+        Err(CliError::Damaged)
+    }
+}
+
+/// Metadata and indented layer tree of a file as plain text.
+fn describe(file: &ProcreateFile) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "Name: {}",
+        file.name.as_deref().unwrap_or("Not Specified")
+    );
+    let _ = writeln!(
+        text,
+        "Author: {}",
+        file.author_name.as_deref().unwrap_or("Not Specified")
+    );
+    let _ = writeln!(text, "Canvas: {} by {}", file.size.width, file.size.height);
+    if let Some(dpi) = file.dpi {
+        let _ = writeln!(text, "Resolution: {dpi} DPI");
+    }
+    let _ = writeln!(text, "Strokes: {}", file.stroke_count);
+    if let Some(time) = file.tracked_time {
+        let _ = writeln!(text, "Drawing time: {} s", time.as_secs());
+    }
+    text.push_str("Layers:");
+    describe_group(&mut text, &file.layers, 1);
+    text
+}
+
+/// Append the nodes beneath a group to the text, a line each, indented by
+/// how deep they are nested.
+fn describe_group(text: &mut String, group: &SilicaGroup, depth: usize) {
+    for node in &group.children {
+        let (name, kind, hidden) = match node {
+            SilicaHierarchy::Layer(layer) => (
+                layer.name.as_deref(),
+                format!("{}, {:.0}%", layer.blend.as_str(), layer.opacity * 100.0),
+                layer.hidden,
+            ),
+            SilicaHierarchy::Group(group) => (
+                group.name.as_deref(),
+                format!(
+                    "group, {}, {:.0}%",
+                    group.blend.as_str(),
+                    group.opacity * 100.0
+                ),
+                group.hidden,
+            ),
+        };
+        let _ = write!(
+            text,
+            "\n{}{} ({kind}){}",
+            "  ".repeat(depth),
+            name.unwrap_or("Untitled"),
+            if hidden { " [hidden]" } else { "" }
+        );
+        if let SilicaHierarchy::Group(group) = node {
+            describe_group(text, group, depth + 1);
+        }
+    }
+}
+
+/// Composite a file with the overrides applied and save it as an image, or
+/// each of its visible layers as an image of their own. Returns the paths
+/// that were written.
+fn export(
+    rt: &Runtime,
+    dev: &Arc<GpuHandle>,
+    pipeline: &CompositorPipeline,
+    input: PathBuf,
+    output: &OutputTemplate,
+    overrides: &[LayerOverride],
+    progress: &Progress,
+) -> Result<Vec<PathBuf>, CliError> {
+    progress.report("load", 0, 1);
+    let (mut file, textures) = if input.as_os_str() == STDIN {
+        let loaded = ProcreateFile::open_from_reader(stdin()?, dev)?;
+        progress.report("load", 1, 1);
+        loaded
+    } else {
+        ProcreateFile::open_with_progress(&input, dev, &|done, total| {
+            progress.report("load", done, total)
+        })?
+    };
+    for o in overrides {
+        if !o.apply(&mut file.layers) {
+            return Err(CliError::UnmatchedPattern(o.pattern.clone()));
+        }
+    }
+
+    let stem = if input.as_os_str() == STDIN {
+        file.name.clone().unwrap_or_default()
+    } else {
+        input
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let (name, author) = (file.name.clone(), file.author_name.clone());
+    let metadata = file.export_metadata(SOFTWARE);
+    // Each layer on its own with transparency, or the whole composite.
+    let renders = if output.per_layer() {
+        let mut layers = Vec::new();
+        visible_layers(&file.layers, "", &mut layers);
+        layers
+            .into_iter()
+            .filter_map(|(path, texture)| {
+                let (groups, layers) = App::resolve_node(&file.layers, texture)?;
+                Some((Some(path), groups, layers, None))
+            })
+            .collect()
+    } else {
+        let (groups, layers) = App::resolve_layers(&file);
+        let background = (!file.background_hidden).then_some(file.background_color);
+        vec![(None, groups, layers, background)]
+    };
+    let instance = Instance::new(dev.clone(), input, file, textures);
+
+    let mut written = Vec::new();
+    for (i, (layer, groups, layers, background)) in renders.iter().enumerate() {
+        progress.report("composite", i, renders.len());
+        instance.render(pipeline, groups, layers, *background, Default::default());
+        let texture = instance
+            .target
+            .lock()
+            .output
+            .as_ref()
+            .map(|output| output.texture.clone(dev))
+            .expect("rendered output");
+        let path = output.path(&OutputValues {
+            stem: &stem,
+            name: name.as_deref(),
+            author: author.as_deref(),
+            width: texture.width,
+            height: texture.height,
+            layer: layer.as_deref(),
+        });
+        if written.contains(&path) {
+            tracing::warn!("{} is written more than once", path.display());
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(CliError::OutputDirectory)?;
+        }
+        progress.report("export", i, renders.len());
+        rt.block_on(texture.export(
+            dev,
+            vec![path.clone()],
+            ExportKind::Color,
+            ExportScale::ACTUAL_SIZE,
+            None,
+            metadata.clone(),
+        ))?;
+        written.push(path);
+    }
+    progress.report("composite", 1, 1);
+    progress.report("export", 1, 1);
+    Ok(written)
+}
+
+/// Collect the hierarchy path and texture of each layer beneath the group
+/// that is shown, along with the groups it is in.
+fn visible_layers(group: &SilicaGroup, parent: &str, out: &mut Vec<(String, u32)>) {
+    for node in &group.children {
+        let name = node.name().unwrap_or("Untitled");
+        let path = if parent.is_empty() {
+            name.to_string()
+        } else {
+            format!("{parent}/{name}")
+        };
+        match node {
+            SilicaHierarchy::Layer(layer) if !layer.hidden => out.push((path, layer.image)),
+            SilicaHierarchy::Group(group) if !group.hidden => visible_layers(group, &path, out),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_templates_make_subdirectories() {
+        assert!(matches!(
+            OutputTemplate::parse(String::from("{size}.png")),
+            Err(CliError::Template(TemplateError::UnknownToken(token))) if token == "size"
+        ));
+        let output =
+            OutputTemplate::parse(String::from("{author}/{stem}/{layer} {width}x{height}.png"))
+                .unwrap()
+                .within(PathBuf::from("out"));
+        assert!(output.per_layer());
+        let values = OutputValues {
+            stem: "Piece",
+            name: None,
+            author: Some("A/B"),
+            width: 20,
+            height: 10,
+            layer: Some("Inks/Line: art"),
+        };
+        assert_eq!(
+            output.path(&values),
+            Path::new("out/A_B/Piece/Inks/Line_ art 20x10.png")
+        );
+        assert!(!OutputTemplate::parse(String::from("{stem}.png"))
+            .unwrap()
+            .per_layer());
+    }
+
+    #[test]
+    fn backend_overrides_the_saved_preference() {
+        let saved = GpuPreference {
+            backend: GpuBackend::Vulkan,
+            adapter: Some(1),
+        };
+        let mut args = vec![
+            String::from("--backend"),
+            String::from("GL"),
+            String::from("a"),
+        ];
+        let preference = take_gpu(&mut args, saved).unwrap();
+        assert_eq!(preference.backend, GpuBackend::Gl);
+        assert_eq!(preference.adapter, None);
+        assert_eq!(args, [String::from("a")]);
+        assert_eq!(take_gpu(&mut args, saved).unwrap(), saved);
+        assert!(matches!(
+            take_gpu(&mut vec![String::from("--backend"), String::from("glide")], saved),
+            Err(CliError::InvalidBackend(backend)) if backend == "glide"
+        ));
+    }
+
+    #[test]
+    fn globs_match_layer_paths() {
+        for (glob, path, matches) in [
+            ("Sketch", "Sketch", true),
+            ("Sketch", "Sketch 2", false),
+            ("Sketch", "Inks/Sketch", false),
+            ("Sketch/*", "Sketch/Layer 1", true),
+            ("Sketch/*", "Sketch/Rough/Layer 1", false),
+            ("Sketch/**", "Sketch/Rough/Layer 1", true),
+            ("**/Lineart", "Inks/Clean/Lineart", true),
+            ("**/Lineart", "Lineart", false),
+            ("Layer ?", "Layer 1", true),
+            ("Layer ?", "Layer 10", false),
+            ("?", "/", false),
+            ("*", "", true),
+            // Regex syntax is literal.
+            ("Shadows (old).+", "Shadows (old).+", true),
+            ("Shadows (old).+", "Shadows old..", false),
+            ("[a]", "a", false),
+        ] {
+            assert_eq!(
+                glob_regex(glob).is_match(path),
+                matches,
+                "{glob} against {path}"
+            );
+        }
+    }
+
+    fn parse(args: &[&str]) -> Result<Option<Command>, CliError> {
+        Command::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn export_arguments_are_parsed_in_order() {
+        let Ok(Some(Command::Export {
+            input,
+            output,
+            overrides,
+            progress,
+        })) = parse(&[
+            "-v",
+            "export",
+            "in.procreate",
+            "--hide",
+            "Sketch/*",
+            "out.png",
+            "--show",
+            "Lineart",
+            "--set",
+            "a:b:opacity=0.5",
+            "--progress",
+            "json",
+        ])
+        else {
+            panic!("export command expected");
+        };
+        assert_eq!(input, Path::new("in.procreate"));
+        assert_eq!(
+            output.path(&OutputValues {
+                stem: "in",
+                name: None,
+                author: None,
+                width: 1,
+                height: 1,
+                layer: None,
+            }),
+            Path::new("out.png")
+        );
+        let overrides = overrides
+            .iter()
+            .map(|o| (o.pattern.as_str(), o.changes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            overrides,
+            [
+                (
+                    "Sketch/*",
+                    LayerChanges {
+                        hidden: Some(true),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    "Lineart",
+                    LayerChanges {
+                        hidden: Some(false),
+                        ..Default::default()
+                    }
+                ),
+                // Patterns may contain colons, the setting follows the last.
+                (
+                    "a:b",
+                    LayerChanges {
+                        opacity: Some(0.5),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        assert!(matches!(progress, Some(ProgressFormat::Json)));
+    }
+
+    #[test]
+    fn commands_parse_their_arguments() {
+        for args in [&[][..], &["file.procreate"], &["-", "--threads", "2"]] {
+            assert!(matches!(parse(args), Ok(None)), "{args:?}");
+        }
+        assert!(matches!(
+            parse(&["info", "--json", "-"]),
+            Ok(Some(Command::Info { input, json: true })) if input == Path::new(STDIN)
+        ));
+        assert!(matches!(
+            parse(&["inspect", "a.procreate"]),
+            Ok(Some(Command::Inspect { input })) if input == Path::new("a.procreate")
+        ));
+        assert!(matches!(
+            parse(&["watch", "in", "--out", "out", "--name", "{stem}.jpg"]),
+            Ok(Some(Command::Watch { input, output, .. }))
+                if input == Path::new("in") && output == Path::new("out")
+        ));
+    }
+
+    #[test]
+    fn invalid_arguments_fail() {
+        for (args, expected) in [
+            (&["export", "in.procreate"][..], "output file"),
+            (&["export"], "input file"),
+            (&["export", "in", "out", "--hide"], "layer pattern"),
+            (&["export", "in", "out", "--progress"], "progress format"),
+            (&["watch", "in"], "output directory"),
+            (&["inspect"], "input file"),
+            (&["info", "--json"], "input file"),
+        ] {
+            assert!(
+                matches!(parse(args), Err(CliError::MissingArgument(arg)) if arg == expected),
+                "{args:?}"
+            );
+        }
+        for (args, unexpected) in [
+            (&["export", "in", "out", "extra"][..], "extra"),
+            (&["export", "in", "out", "--out", "dir"], "--out"),
+            (&["export", "in", "out", "--progress", "xml"], "xml"),
+            (
+                &["watch", "in", "--out", "o", "--progress", "text"],
+                "--progress",
+            ),
+            (&["inspect", "a", "b"], "b"),
+            (&["info", "--yaml", "a"], "--yaml"),
+        ] {
+            assert!(
+                matches!(parse(args), Err(CliError::UnexpectedArgument(arg)) if arg == unexpected),
+                "{args:?}"
+            );
+        }
+        assert!(matches!(
+            parse(&["export", "in", "out", "--set", "Shadows:opacity=2"]),
+            Err(CliError::InvalidSetting(setting)) if setting == "Shadows:opacity=2"
+        ));
+    }
+
+    #[test]
+    fn layer_changes_parse_key_value_pairs() {
+        let changes = |hidden, opacity, blend| {
+            Some(LayerChanges {
+                hidden,
+                opacity,
+                blend,
+            })
+        };
+        for (text, expected) in [
+            ("opacity=0.5", changes(None, Some(0.5), None)),
+            ("opacity=0", changes(None, Some(0.0), None)),
+            ("opacity=1", changes(None, Some(1.0), None)),
+            ("hidden=true", changes(Some(true), None, None)),
+            (
+                " opacity = 0.25 , blend = multiply ",
+                changes(None, Some(0.25), Some(BlendingMode::Multiply)),
+            ),
+            (
+                "blend=soft-light",
+                changes(None, None, Some(BlendingMode::SoftLight)),
+            ),
+            (
+                "blend=soft_light",
+                changes(None, None, Some(BlendingMode::SoftLight)),
+            ),
+            (
+                "blend=SoftLight",
+                changes(None, None, Some(BlendingMode::SoftLight)),
+            ),
+            (
+                "blend=Soft Light",
+                changes(None, None, Some(BlendingMode::SoftLight)),
+            ),
+            // Later pairs take precedence.
+            ("hidden=true,hidden=false", changes(Some(false), None, None)),
+            ("opacity=1.5", None),
+            ("opacity=-0.1", None),
+            ("opacity=half", None),
+            ("hidden=yes", None),
+            ("blend=glow", None),
+            ("size=2", None),
+            ("opacity", None),
+            ("", None),
+            ("opacity=0.5,", None),
+        ] {
+            assert_eq!(LayerChanges::parse(text), expected, "{text}");
+        }
+    }
+}
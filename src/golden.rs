@@ -0,0 +1,166 @@
+//! Render regression tests.
+//!
+//! Every `tests/golden/*.procreate` file is composited headlessly and compared
+//! against the `.png` of the same name. The expected renders are worked out by
+//! hand rather than exported from Procreate, see `tests/golden/README.md`.
+//! It needs a GPU adapter, so it only runs with `--ignored`. Set `SILICATE_BLESS=1` to write the current renders
+//! as the expected outputs after an intentional change.
+
+use crate::gui::app::{App, Instance};
+use crate::SOFTWARE;
+use compositor::{dev::GpuHandle, CompositorPipeline};
+use image::{Rgba, RgbaImage};
+use silica::{LoadOptions, ProcreateFile};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+/// Perceptual distance (0.0..=255.0) under which two pixels are considered equal.
+const PIXEL_TOLERANCE: f32 = 4.0;
+/// Fraction of pixels allowed to exceed the tolerance before a render fails.
+const MAX_DIFFERING_PIXELS: f64 = 0.001;
+
+/// Distance between two pixels, weighted by how sensitive the eye is
+/// to each channel. The colors are compared premultiplied so that
+/// invisible differences in transparent pixels are ignored.
+fn perceptual_distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let premultiply = |p: &Rgba<u8>| {
+        let alpha = f32::from(p.0[3]) / 255.0;
+        [
+            f32::from(p.0[0]) * alpha,
+            f32::from(p.0[1]) * alpha,
+            f32::from(p.0[2]) * alpha,
+            f32::from(p.0[3]),
+        ]
+    };
+    let [ar, ag, ab, aa] = premultiply(a);
+    let [br, bg, bb, ba] = premultiply(b);
+    let (dr, dg, db, da) = (ar - br, ag - bg, ab - bb, aa - ba);
+    (0.299 * dr * dr + 0.587 * dg * dg + 0.114 * db * db + da * da).sqrt()
+}
+
+/// Fraction of pixels that are perceptibly different between two images.
+fn differing_pixels(actual: &RgbaImage, expected: &RgbaImage) -> f64 {
+    let differing = actual
+        .pixels()
+        .zip(expected.pixels())
+        .filter(|(a, b)| perceptual_distance(a, b) > PIXEL_TOLERANCE)
+        .count();
+    differing as f64 / (actual.width() as f64 * actual.height() as f64)
+}
+
+async fn render(dev: &Arc<GpuHandle>, pipeline: &CompositorPipeline, path: &Path) -> RgbaImage {
+    let (file, textures, _) =
+        ProcreateFile::open_with(path, dev, &LoadOptions::default()).expect("golden file loads");
+    let (groups, layers) = App::resolve_layers(&file);
+    let background = (!file.background_hidden).then_some(file.background_color);
+    let instance = Instance::new(dev.clone(), path.to_path_buf(), file, textures);
+    instance.render(pipeline, &groups, &layers, background, Default::default());
+
+    let texture = instance
+        .target
+        .lock()
+        .output
+        .as_ref()
+        .map(|output| output.texture.clone(dev))
+        .expect("rendered output");
+    texture.read_rgba(dev, texture.bounds()).await
+}
+
+fn golden_files() -> Vec<PathBuf> {
+    let Ok(dir) = std::fs::read_dir(GOLDEN_DIR) else {
+        return Vec::new();
+    };
+    let mut files = dir
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "procreate"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn golden_renders() {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let dev = rt
+        .block_on(GpuHandle::new(Default::default()))
+        .expect("GPU adapter to render the golden files with");
+    let dev = Arc::new(dev);
+    let pipeline = CompositorPipeline::new(&dev);
+    let bless = std::env::var_os("SILICATE_BLESS").is_some();
+
+    let files = golden_files();
+    assert!(!files.is_empty(), "no golden documents in {GOLDEN_DIR}");
+    let mut failures = Vec::new();
+    for path in files {
+        let actual = rt.block_on(render(&dev, &pipeline, &path));
+        let expected_path = path.with_extension("png");
+
+        if bless {
+            actual.save(&expected_path).unwrap();
+            continue;
+        }
+
+        let Ok(expected) = image::open(&expected_path).map(|image| image.into_rgba8()) else {
+            failures.push(format!(
+                "{}: missing expected render, run with SILICATE_BLESS=1",
+                path.display()
+            ));
+            continue;
+        };
+
+        let failure = if actual.dimensions() != expected.dimensions() {
+            Some(format!(
+                "{}: rendered {:?}, expected {:?}",
+                path.display(),
+                actual.dimensions(),
+                expected.dimensions()
+            ))
+        } else {
+            let differing = differing_pixels(&actual, &expected);
+            (differing > MAX_DIFFERING_PIXELS).then(|| {
+                format!(
+                    "{}: {:.3}% of pixels differ",
+                    path.display(),
+                    differing * 100.0
+                )
+            })
+        };
+
+        if let Some(failure) = failure {
+            // Keep the render around to make the regression easy to inspect.
+            actual.save(path.with_extension("actual.png")).unwrap();
+            failures.push(failure);
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn golden_files_are_intact() {
+    let files = golden_files();
+    assert!(!files.is_empty(), "no golden documents in {GOLDEN_DIR}");
+    let failures = files
+        .into_iter()
+        .filter_map(|path| match ProcreateFile::inspect(&path) {
+            Ok(report) if report.is_intact() => None,
+            Ok(report) => Some(format!("{}:\n{}", path.display(), report.summary(SOFTWARE))),
+            Err(err) => Some(format!("{}: {err}", path.display())),
+        })
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn perceptual_distance_ignores_transparent_color() {
+    let a = Rgba([255, 0, 0, 0]);
+    let b = Rgba([0, 255, 0, 0]);
+    assert_eq!(perceptual_distance(&a, &b), 0.0);
+    assert!(perceptual_distance(&Rgba([0, 0, 0, 255]), &Rgba([255, 255, 255, 255])) > 200.0);
+}
@@ -1,17 +1,55 @@
-use crate::compositor::{dev::GpuHandle, tex::GpuTexture};
-use crate::compositor::{BufferDimensions, CompositorTarget};
-use crate::compositor::{CompositeLayer, CompositorPipeline};
-use crate::silica::{ProcreateFile, SilicaError, SilicaHierarchy};
+use super::canvas::{CompareMode, Guides, ViewTransform};
+use super::command::{self, BackgroundProperties, CommandQueue, DisplayState, InstanceCommand};
+use super::download::Download;
+use super::export_preset::ExportPreset;
+use super::file_manager;
+use super::gallery::Gallery;
+use super::history::EditHistory;
+use super::kra::{self, KraDocument, KraError};
+use super::motion::{self, Notifications};
+use super::picker::{ColorFormat, PixelColor};
+use super::preset::LayerPreset;
+use super::quality::AdaptiveQuality;
+use super::report::ErrorReport;
+use super::selection;
+use super::session::{Session, SessionLayout};
+use super::settings::SettingsBundle;
+use super::sheet::ContactSheet;
+use super::views::SavedView;
+use crate::SOFTWARE;
+use arc_swap::ArcSwap;
+use compositor::adjust::Adjustments;
+use compositor::analysis::ColorStats;
+use compositor::budget::AllocationError;
+use compositor::color;
+use compositor::dev::{GpuBackend, GpuHandle, GpuPreference};
+use compositor::lut::{CubeLut, LutTexture};
+use compositor::metadata::{self, ExportMetadata};
+use compositor::post::{PostPipeline, PostStep};
+use compositor::profile::ExportColorSpace;
+use compositor::tex::{
+    ExportKind, ExportScale, ExtraFormats, GpuTexture, PixelReadback, Region, TiledTexture,
+};
+use compositor::timing::GpuTimer;
+use compositor::{
+    BlendMode, CompositeFinish, CompositeGroup, CompositeLayer, CompositePaper, CompositorPipeline,
+    CompositorTarget, OutputTransform, ToneMap,
+};
 use egui_dock::{NodeIndex, SurfaceIndex};
-use egui_notify::Toasts;
 use egui_winit::winit::event_loop::EventLoopProxy;
 use parking_lot::{Mutex, RwLock};
+use silica::{
+    ArchiveReport, BlendingMode, CancelToken, LayerProperties, LoadOptions, ProcreateFile,
+    ReloadDiff, SilicaError, SilicaGroup, SilicaHierarchy, TileSample,
+};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering::{Acquire, Release};
 use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::runtime::Runtime;
 use tokio::time::MissedTickBehavior;
 
@@ -19,40 +57,688 @@ pub struct App {
     pub dev: Arc<GpuHandle>,
     pub rt: Arc<Runtime>,
     pub compositor: CompositorHandle,
-    pub toasts: Mutex<Toasts>,
+    pub toasts: Mutex<Notifications>,
     pub added_instances: Mutex<Vec<(SurfaceIndex, NodeIndex, InstanceKey)>>,
+    /// Tabs of a restored session and their views, for the editor to
+    /// arrange as they were.
+    pub restored_layout: Mutex<Option<RestoredLayout>>,
     pub event_loop: EventLoopProxy<UserEvent>,
+    pub paper: Mutex<PaperOptions>,
+    pub lut: Mutex<LutOptions>,
+    pub post: Mutex<PostOptions>,
+    /// How blends brighter than white are brought back into range, on
+    /// screen and in color exports.
+    pub tone_map: Mutex<ToneMap>,
+    /// Size and resampling of exported images.
+    pub export_scale: Mutex<ExportScale>,
+    /// Formats that color exports are also saved in.
+    pub extra_formats: Mutex<ExtraFormats>,
+    /// Conversion and profile of color exports.
+    pub export_color: Mutex<ExportColorSpace>,
+    /// Read back a sample of tiles after loading to check the GPU upload.
+    pub verify_tiles: AtomicBool,
+    /// Threads that decode the tiles of a file, 0 for every core.
+    pub load_threads: AtomicUsize,
+    /// Backend and adapter that the GPU is created with on the next start.
+    pub gpu: Mutex<GpuPreference>,
+    /// Cancelled once the app quits, so that files still loading do not
+    /// keep it from exiting. Every load is cancelled by a child of it.
+    pub loads: CancelToken,
+    /// Errors shown in the error panel until dismissed.
+    pub reports: Mutex<Vec<ErrorReport>>,
+    /// Settings loaded from a file, applied by the GUI on its next frame.
+    pub imported_settings: Mutex<Option<SettingsBundle>>,
+    /// Layer preset loaded for a file, applied by the GUI on its next frame
+    /// so that it can be undone like any other edit.
+    pub loaded_preset: Mutex<Option<(InstanceKey, LayerPreset)>>,
+    /// Folder scanned for the gallery, shown by the GUI on its next frame.
+    pub opened_gallery: Mutex<Option<Gallery>>,
+    /// Straight color last sampled from a canvas with the color picker.
+    pub sampled_color: Mutex<Option<[u8; 4]>>,
+    /// Colors kept aside from the color picker.
+    pub swatches: Mutex<Vec<[u8; 4]>>,
+    /// Layer last picked on a canvas, for the selection to take.
+    pub picked_layer: Mutex<Option<(InstanceKey, String)>>,
+    /// Canvas pixel under the pointer and its straight color.
+    pub hovered_color: Mutex<Option<PixelColor>>,
+    /// Set while the pixel under the pointer is being read back.
+    pub reading_hover: AtomicBool,
+    /// Set once a composite is exported for dragging out of the window,
+    /// until the drag ends.
+    pub dragging_out: AtomicBool,
+    /// Text to copy to the clipboard, copied by the GUI on its next frame.
+    pub clipboard: Mutex<Option<String>>,
+    /// System clipboard that copied images are placed on, kept open since
+    /// some platforms only serve a copied image while it is.
+    system_clipboard: Mutex<Option<arboard::Clipboard>>,
+    /// Files being downloaded from links to be opened.
+    pub downloads: Mutex<Vec<Arc<Download>>>,
+    /// Whether the OS asked for animations to be turned off at startup.
+    pub system_reduced_motion: bool,
+    /// Number of composites rendered for display, shown in the statistics HUD.
+    pub recomposites: AtomicUsize,
+    /// Composite at half resolution while edits render too slowly to keep
+    /// up, and at full resolution again once they pause.
+    pub adaptive_quality: AtomicBool,
+    /// Keep the previews of the files up to date, while the tab switcher
+    /// shows them.
+    pub previews_wanted: AtomicBool,
+    /// Time taken to flatten the layers of the last composite rendered for
+    /// display, shown in the statistics HUD.
+    pub linearize_time: Mutex<Duration>,
+    /// Color statistics of an instance's composite, along with the
+    /// generation of the composite they were computed from.
+    pub analysis: Mutex<Option<(InstanceKey, usize, ColorStats)>>,
+    /// Set while color statistics are being computed.
+    pub analyzing: AtomicBool,
+    /// Structure of an instance's file, along with the content hash of the
+    /// file it was checked for.
+    pub inspection: Mutex<Option<(InstanceKey, u64, Inspection)>>,
+    /// Set while a file is being inspected.
+    pub inspecting: AtomicBool,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Report of a file's structure, or why it could not be checked.
+pub type Inspection = Result<ArchiveReport, String>;
+
+/// Tabs of a restored session with the views they were left in.
+pub type RestoredLayout = SessionLayout<(InstanceKey, Option<SavedView>)>;
+
+/// Paper texture overlay applied over every composite for presentation.
+pub struct PaperOptions {
+    pub texture: Option<GpuTexture>,
+    pub name: Option<String>,
+    pub enabled: bool,
+    pub blend: BlendingMode,
+    pub strength: f32,
+    pub include_in_export: bool,
+}
+
+impl Default for PaperOptions {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            name: None,
+            enabled: true,
+            blend: BlendingMode::Multiply,
+            strength: 0.5,
+            include_in_export: false,
+        }
+    }
+}
+
+impl PaperOptions {
+    /// Blending modes that make sense for a paper overlay.
+    pub const BLENDS: [BlendingMode; 3] = [
+        BlendingMode::Multiply,
+        BlendingMode::Overlay,
+        BlendingMode::SoftLight,
+    ];
+
+    fn composite_paper(&self) -> Option<CompositePaper<'_>> {
+        self.texture
+            .as_ref()
+            .filter(|_| self.enabled && self.strength > 0.0)
+            .map(|texture| CompositePaper {
+                texture,
+                blend: self.blend.into(),
+                strength: self.strength,
+            })
+    }
+}
+
+/// Post-processing steps that color exports go through before being saved.
+#[derive(Default)]
+pub struct PostOptions {
+    pub steps: Vec<PostStep>,
+    /// Premultiplied watermark image, drawn by watermark steps.
+    pub watermark: Option<GpuTexture>,
+    pub watermark_name: Option<String>,
+}
+
+/// Color lookup table applied as the last pass over every composite.
+pub struct LutOptions {
+    pub texture: Option<LutTexture>,
+    pub name: Option<String>,
+    /// Whether the table is applied to the composites on screen.
+    pub preview: bool,
+    pub include_in_export: bool,
+}
+
+impl Default for LutOptions {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            name: None,
+            preview: true,
+            include_in_export: true,
+        }
+    }
+}
+
+impl LutOptions {
+    fn preview_lut(&self) -> Option<&LutTexture> {
+        self.texture.as_ref().filter(|_| self.preview)
+    }
+
+    fn export_lut(&self) -> Option<&LutTexture> {
+        self.texture.as_ref().filter(|_| self.include_in_export)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum UserEvent {
     RebindTexture(InstanceKey),
     RemoveInstance(InstanceKey),
+    /// Bind a page of the layer atlas of a file for the atlas inspector.
+    BindAtlasPage(InstanceKey, u32),
+    /// Bind the preview of a file for the tab switcher.
+    BindPreview(InstanceKey),
+    /// Start dragging an exported composite out of the window, if the
+    /// pointer is still held.
+    DragOut(PathBuf),
+    /// The GPU device stopped working, with the driver's reason.
+    DeviceLost(String),
+}
+
+/// Environment variable listing the backends whose device was lost in
+/// earlier runs, separated by commas, set when the viewer restarts itself
+/// with another GPU.
+pub const LOST_BACKENDS_VAR: &str = "SILICATE_LOST_BACKENDS";
+
+/// Backends whose device was lost in earlier runs, from [`LOST_BACKENDS_VAR`].
+pub fn lost_backends() -> Vec<GpuBackend> {
+    std::env::var(LOST_BACKENDS_VAR)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(GpuBackend::from_name)
+        .collect()
 }
 
 #[derive(Hash, Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct InstanceKey(pub usize);
 
+/// Another instance shown over an instance in its canvas tab.
+#[derive(Debug, Clone, Copy)]
+pub struct CompareTarget {
+    pub with: InstanceKey,
+    pub mode: CompareMode,
+    /// Overlay opacity or wipe split, see [`Comparison`](super::canvas::Comparison).
+    pub amount: f32,
+}
+
+/// What the canvas shows the difference of instead of the composite, to
+/// spot where it diverges from the flattening Procreate stored in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifferenceSource {
+    /// The composite and the composite stored in the file.
+    StoredComposite,
+    /// Two layers, by texture.
+    Layers(u32, u32),
+}
+
+impl DifferenceSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StoredComposite => "Stored Composite",
+            Self::Layers(..) => "Selected Layers",
+        }
+    }
+}
+
+/// Everything the composite of a file is rendered from, as it was once a
+/// batch of commands was applied. Snapshots are never edited, so that the
+/// rendering thread never sees an edit half applied.
+#[derive(Clone)]
+pub struct RenderSnapshot {
+    /// Increases every time a new snapshot is published.
+    pub version: u64,
+    pub groups: Vec<CompositeGroup>,
+    pub layers: Vec<CompositeLayer>,
+    /// Color of the background, `None` if it is hidden.
+    pub background: Option<[f32; 4]>,
+    /// How long the layer hierarchy took to flatten into the lists above.
+    pub linearize_time: Duration,
+    /// How the composite is shown, which the lists above already reflect.
+    pub display: DisplayState,
+}
+
+impl RenderSnapshot {
+    fn of(file: &ProcreateFile, version: u64, display: DisplayState) -> Self {
+        let start = Instant::now();
+        let (groups, layers) = App::resolve_layers(file);
+        Self {
+            version,
+            groups,
+            layers,
+            background: (!file.background_hidden).then_some(file.background_color),
+            linearize_time: start.elapsed(),
+            display,
+        }
+    }
+
+    /// Render the composite stored in the file instead, as a reference.
+    fn show_stored(&mut self, stored: u32) {
+        self.groups.clear();
+        self.layers = vec![CompositeLayer {
+            texture: stored,
+            clipped: None,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+        }];
+    }
+
+    /// Render the absolute difference of the source instead, in which
+    /// matching pixels come out black. `stored` is the texture of the
+    /// composite stored in the file, if it has one.
+    fn highlight_difference(&mut self, source: DifferenceSource, stored: Option<u32>) {
+        let layer = |texture, blend| CompositeLayer {
+            texture,
+            clipped: None,
+            opacity: 1.0,
+            blend,
+        };
+        match source {
+            DifferenceSource::StoredComposite => {
+                if let Some(stored) = stored {
+                    self.layers.push(layer(stored, BlendMode::Difference));
+                }
+            }
+            DifferenceSource::Layers(first, second) => {
+                self.groups.clear();
+                self.layers = vec![
+                    layer(first, BlendMode::Normal),
+                    layer(second, BlendMode::Difference),
+                ];
+                self.background = Some([0.0, 0.0, 0.0, 1.0]);
+            }
+        }
+    }
+}
+
 pub struct Instance {
+    /// Path the file was opened from.
+    pub path: PathBuf,
+    /// Modification time of the file when it was last read.
+    modified: Mutex<Option<SystemTime>>,
+    /// Newer modification time of the file seen on disk, until the file is
+    /// reloaded or the change is dismissed.
+    pub changed_on_disk: Mutex<Option<SystemTime>>,
+    /// Only edited through [`Self::commands`], besides being replaced when
+    /// the file is reloaded.
     pub file: RwLock<ProcreateFile>,
-    pub textures: GpuTexture,
+    /// Layer atlas of the file, `None` while evicted to stay within the
+    /// GPU memory budget.
+    pub textures: RwLock<Option<TiledTexture>>,
+    /// When the file was last visible in a tab.
+    pub last_shown: Mutex<Instant>,
+    /// Set while the evicted atlas is being reloaded from the file.
+    pub reloading: AtomicBool,
+    /// Set when reloading the evicted atlas failed, so that it is only
+    /// retried on request rather than every time the tab is shown.
+    pub reload_failed: AtomicBool,
+    /// Stops the file from being read again once its tab is closed.
+    pub cancel_reload: Mutex<Option<CancelToken>>,
     pub target: Mutex<CompositorTarget>,
-    pub changed: AtomicBool,
+    /// Unrotated target used to composite isolated groups.
+    pub group_target: Mutex<CompositorTarget>,
+    /// Instance compared against in the canvas tab.
+    pub compare: Mutex<Option<CompareTarget>>,
+    /// Composition guides drawn over the canvas tab.
+    pub guides: Mutex<Guides>,
+    /// Rotation of the canvas tab.
+    pub view: Mutex<ViewTransform>,
+    /// Color adjustments previewed over the composite.
+    pub adjustments: Mutex<Adjustments>,
+    /// Edits made to the file in the viewer that can be undone.
+    pub history: Mutex<EditHistory>,
+    /// Changes to the file and the output for the rendering thread to make
+    /// before it composites again. Nothing else edits them.
+    pub commands: CommandQueue,
+    /// Latest state of the file to composite and how it is shown, replaced
+    /// whenever commands change them.
+    pub snapshot: ArcSwap<RenderSnapshot>,
+    /// Number of times the composite was rendered for display.
+    pub generation: AtomicUsize,
+    /// Small copy of the composite for the tab switcher, along with the
+    /// generation of the composite it was made from.
+    pub preview: Mutex<Option<(usize, TiledTexture)>>,
 }
 
 impl Instance {
-    pub fn store_change_or(&self, b: bool) {
-        self.changed.fetch_or(b, Release);
+    /// Create an instance with compositor targets matching the file.
+    pub fn new(
+        dev: Arc<GpuHandle>,
+        path: PathBuf,
+        file: ProcreateFile,
+        textures: TiledTexture,
+    ) -> Self {
+        let mut target = CompositorTarget::new(dev.clone());
+        target
+            .data
+            .flip_vertices(file.flipped.horizontally, file.flipped.vertically);
+        target.set_dimensions(file.size.width, file.size.height);
+
+        let mut group_target = CompositorTarget::new(dev);
+        // Sample the layer textures in the same orientation they are stored.
+        group_target.data.flip_vertices(false, true);
+        group_target.set_dimensions(file.size.width, file.size.height);
+
+        // Show the canvas the way it was drawn.
+        for _ in 0..Self::orientation_turns(&file) {
+            target.data.rotate_vertices(true);
+            target.set_dimensions(target.dim.height, target.dim.width);
+        }
+
+        Self {
+            modified: Mutex::new(Self::modified_on_disk(&path)),
+            changed_on_disk: Mutex::new(None),
+            path,
+            snapshot: ArcSwap::from_pointee(RenderSnapshot::of(&file, 0, DisplayState::default())),
+            file: RwLock::new(file),
+            target: Mutex::new(target),
+            group_target: Mutex::new(group_target),
+            compare: Mutex::new(None),
+            guides: Mutex::new(Guides::default()),
+            view: Mutex::new(ViewTransform::default()),
+            adjustments: Mutex::new(Adjustments::default()),
+            history: Mutex::new(EditHistory::default()),
+            textures: RwLock::new(Some(textures)),
+            last_shown: Mutex::new(Instant::now()),
+            reloading: AtomicBool::new(false),
+            reload_failed: AtomicBool::new(false),
+            cancel_reload: Mutex::new(None),
+            commands: CommandQueue::default(),
+            generation: AtomicUsize::new(0),
+            preview: Mutex::new(None),
+        }
+    }
+
+    /// Composite the resolved layers of the file into the target.
+    /// Returns whether the output textures of the target were recreated.
+    pub fn render(
+        &self,
+        pipeline: &CompositorPipeline,
+        groups: &[CompositeGroup],
+        layers: &[CompositeLayer],
+        background: Option<[f32; 4]>,
+        finish: CompositeFinish,
+    ) -> bool {
+        let textures = self.textures.read();
+        // An evicted file keeps showing its last composite until reloaded.
+        let Some(textures) = textures.as_ref() else {
+            return false;
+        };
+
+        if !groups.is_empty() {
+            let mut group_target = self.group_target.lock();
+            for group in groups {
+                group_target.render_to_layer(pipeline, &group.layers, textures, group.texture);
+            }
+        }
+
+        self.target
+            .lock()
+            .render(pipeline, background, layers, textures, finish)
+    }
+
+    /// Counterclockwise quarter turns from how a file is stored to the
+    /// orientation it was drawn in.
+    fn orientation_turns(file: &ProcreateFile) -> i32 {
+        (file.orientation % 4) as i32
+    }
+
+    /// Rotate the output by quarter turns, counterclockwise if positive.
+    pub fn rotate(&self, quarters: i32) {
+        self.commands.send(InstanceCommand::Rotate(quarters));
+    }
+
+    /// Flip the output, along the axes of the layer textures.
+    pub fn flip(&self, horizontal: bool, vertical: bool) {
+        self.commands.send(InstanceCommand::Flip {
+            horizontal,
+            vertical,
+        });
+    }
+
+    /// Show the canvas as stored, or in the orientation of the document.
+    pub fn show_stored_orientation(&self, stored: bool) {
+        self.commands
+            .send(InstanceCommand::StoredOrientation(stored));
+    }
+
+    /// How the composite is shown once the pending commands are applied.
+    pub fn display(&self) -> DisplayState {
+        // Read under the lock that the commands are applied under, so that
+        // none are missed between being taken and showing in the snapshot.
+        let _file = self.file.read_recursive();
+        self.pending_display()
+    }
+
+    /// [`Self::display`] for callers that hold the lock of the file.
+    fn pending_display(&self) -> DisplayState {
+        let mut display = self.snapshot.load().display;
+        self.commands.preview_display(&mut display);
+        display
+    }
+
+    /// Name of the document for the names of exported files, that of the
+    /// file when the document has none.
+    pub fn export_name(&self) -> String {
+        self.file
+            .read()
+            .name
+            .clone()
+            .filter(|name| !name.trim().is_empty())
+            .or_else(|| {
+                self.path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| String::from("Untitled Artwork"))
+    }
+
+    /// Switch the canvas between the composite and the composite stored in
+    /// the file.
+    pub fn toggle_stored_composite(&self) {
+        let show = !self.display().show_stored;
+        self.commands.send(InstanceCommand::ShowStored(show));
+    }
+
+    /// Apply the pending commands, and publish a new snapshot if they
+    /// changed what is composited.
+    pub fn apply_commands(&self) {
+        let mut file = self.file.write();
+        // Taken while the file is locked, so that the GUI always sees the
+        // pending commands on top of the file they were sent for.
+        let commands = self.commands.take();
+        let previous = self.snapshot.load_full();
+        let mut background = BackgroundProperties::of(&file);
+        let mut display = previous.display;
+        let changed =
+            command::apply_commands(&mut file.layers, &mut background, &mut display, &commands);
+        background.apply(&mut file);
+        if changed {
+            let version = previous.version + 1;
+            let mut snapshot = match display.blend_preview {
+                // Swapped in only while the snapshot is made, so that the
+                // previewed mode never ends up in the file or its history.
+                Some((texture, blend)) => {
+                    let original = Self::swap_blend(&mut file.layers, texture, blend);
+                    let snapshot = RenderSnapshot::of(&file, version, display);
+                    if let Some(original) = original {
+                        Self::swap_blend(&mut file.layers, texture, original);
+                    }
+                    snapshot
+                }
+                None => RenderSnapshot::of(&file, version, display),
+            };
+            let stored = file.composite.as_ref().map(|composite| composite.image);
+            match (stored, display.difference) {
+                (Some(stored), _) if display.show_stored => snapshot.show_stored(stored),
+                (stored, Some(source)) => snapshot.highlight_difference(source, stored),
+                _ => {}
+            }
+            self.snapshot.store(Arc::new(snapshot));
+        } else if display != previous.display {
+            // Shown the same, so the composite is not rendered again.
+            self.snapshot.store(Arc::new(RenderSnapshot {
+                display,
+                ..RenderSnapshot::clone(&previous)
+            }));
+        }
+        let turns = Self::orientation_turns(&file);
+        drop(file);
+        self.transform_output(&commands, previous.display.stored_orientation, turns);
+    }
+
+    /// Set the blending mode of the layer or group with the texture,
+    /// returning the mode it had.
+    fn swap_blend(
+        layers: &mut SilicaGroup,
+        texture: u32,
+        blend: BlendingMode,
+    ) -> Option<BlendingMode> {
+        let mut previous = None;
+        layers.visit_mut(|_, node| {
+            if node.image() == texture {
+                previous = Some(LayerProperties::of_node(node).blend);
+                node.set_blend(blend);
+            }
+        });
+        previous
+    }
+
+    /// Apply the flips and rotations among the commands to the output,
+    /// including the `turns` of the document's orientation whenever the
+    /// canvas stops or starts being shown as `stored`.
+    fn transform_output(&self, commands: &[InstanceCommand], mut stored: bool, turns: i32) {
+        let mut target = self.target.lock();
+        let rotate = |target: &mut CompositorTarget, quarters: i32| {
+            for _ in 0..quarters.rem_euclid(4) {
+                target.data.rotate_vertices(true);
+                target.transpose_dimensions();
+            }
+        };
+        for command in commands {
+            match *command {
+                InstanceCommand::Flip {
+                    horizontal,
+                    vertical,
+                } => target.data.flip_vertices(horizontal, vertical),
+                InstanceCommand::Rotate(quarters) => rotate(&mut target, quarters),
+                InstanceCommand::StoredOrientation(show) if show != stored => {
+                    stored = show;
+                    rotate(&mut target, if show { -turns } else { turns });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Replace the file with a newer version read from disk, keeping the
+    /// changes made to layers that did not change on disk.
+    pub fn replace_file(&self, mut file: ProcreateFile, textures: TiledTexture) -> ReloadDiff {
+        let mut current = self.file.write();
+        let diff = current.carry_changes(&mut file);
+        if file.size != current.size {
+            let mut target = self.target.lock();
+            // Keep the output rotated by a right angle if it was.
+            let transposed = target.dim.width != current.size.width;
+            if transposed {
+                target.set_dimensions(file.size.height, file.size.width);
+            } else {
+                target.set_dimensions(file.size.width, file.size.height);
+            }
+            self.group_target
+                .lock()
+                .set_dimensions(file.size.width, file.size.height);
+            self.commands.send(InstanceCommand::Crop(None));
+        }
+        let turns = if self.pending_display().stored_orientation {
+            0
+        } else {
+            Self::orientation_turns(&file) - Self::orientation_turns(&current)
+        };
+        // Edits refer to layers and groups by their place in the hierarchy.
+        if diff.added > 0
+            || diff.removed > 0
+            || file.layers.iter().count() != current.layers.iter().count()
+        {
+            self.history.lock().clear();
+            self.commands.discard_node_edits();
+        }
+        *current = file;
+        *self.textures.write() = Some(textures);
+        drop(current);
+        if turns != 0 {
+            self.rotate(turns);
+        }
+        self.commands.send(InstanceCommand::Redraw);
+        *self.modified.lock() = Self::modified_on_disk(&self.path);
+        *self.changed_on_disk.lock() = None;
+        diff
+    }
+
+    fn modified_on_disk(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Keep showing the file as it is after it changed on disk.
+    pub fn dismiss_change(&self) {
+        if let Some(modified) = self.changed_on_disk.lock().take() {
+            *self.modified.lock() = Some(modified);
+        }
+    }
+
+    pub fn is_evicted(&self) -> bool {
+        self.textures.read().is_none()
     }
 
-    pub fn change_untick(&self) -> bool {
-        self.changed.swap(false, Acquire)
+    /// Drop the atlas of the file to free its GPU memory, until it is
+    /// reloaded. Its last composite stays on screen.
+    pub fn evict(&self) {
+        *self.textures.write() = None;
+    }
+
+    /// GPU memory taken up by the layer atlas of the file, `None` while it
+    /// is evicted, and by its composites and preview.
+    pub fn memory(&self) -> (Option<u64>, u64) {
+        let atlas = self.textures.read().as_ref().map(TiledTexture::bytes);
+        let output = |target: &Mutex<CompositorTarget>| {
+            target
+                .lock()
+                .output
+                .as_ref()
+                .map_or(0, |output| output.texture.bytes())
+        };
+        let preview = self
+            .preview
+            .lock()
+            .as_ref()
+            .map_or(0, |(_, preview)| preview.bytes());
+        (
+            atlas,
+            output(&self.target) + output(&self.group_target) + preview,
+        )
     }
 }
 
 impl Drop for Instance {
     fn drop(&mut self) {
-        println!("Closing {:?}", self.file.get_mut().name);
+        tracing::info!("Closing {:?}", self.file.get_mut().name);
+    }
+}
+
+/// Clears a flag once dropped, such as when the task that set it finishes
+/// or panics.
+struct ClearOnDrop<'a>(&'a AtomicBool);
+
+impl Drop for ClearOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Release);
     }
 }
 
@@ -60,201 +746,2431 @@ pub struct CompositorHandle {
     pub instances: RwLock<HashMap<InstanceKey, Instance>>,
     pub curr_id: AtomicUsize,
     pub pipeline: CompositorPipeline,
+    pub post: PostPipeline,
+    /// Times composites on the GPU, if the device supports timestamps.
+    pub timer: Option<GpuTimer>,
 }
 
 impl App {
     pub fn new(dev: GpuHandle, rt: Arc<Runtime>, event_loop: EventLoopProxy<UserEvent>) -> Self {
+        let proxy = event_loop.clone();
+        dev.device.set_device_lost_callback(move |reason, message| {
+            // The device is also reported lost when it is dropped on exit.
+            if matches!(reason, wgpu::DeviceLostReason::Unknown) {
+                let _ = proxy.send_event(UserEvent::DeviceLost(message));
+            }
+        });
         App {
             compositor: CompositorHandle {
                 instances: RwLock::new(HashMap::new()),
                 pipeline: CompositorPipeline::new(&dev),
+                post: PostPipeline::new(&dev),
+                timer: GpuTimer::new(&dev),
                 curr_id: AtomicUsize::new(0),
             },
             rt,
             dev: Arc::new(dev),
-            toasts: Mutex::new(egui_notify::Toasts::default()),
+            toasts: Mutex::new(Notifications::default()),
             added_instances: Mutex::new(Vec::with_capacity(1)),
+            restored_layout: Mutex::new(None),
             event_loop,
+            paper: Mutex::new(PaperOptions::default()),
+            lut: Mutex::new(LutOptions::default()),
+            post: Mutex::new(PostOptions::default()),
+            tone_map: Mutex::new(ToneMap::Off),
+            export_scale: Mutex::new(ExportScale::ACTUAL_SIZE),
+            extra_formats: Mutex::new(ExtraFormats::default()),
+            export_color: Mutex::new(ExportColorSpace::default()),
+            verify_tiles: AtomicBool::new(false),
+            load_threads: AtomicUsize::new(0),
+            gpu: Mutex::new(GpuPreference::default()),
+            loads: CancelToken::default(),
+            reports: Mutex::new(Vec::new()),
+            imported_settings: Mutex::new(None),
+            loaded_preset: Mutex::new(None),
+            opened_gallery: Mutex::new(None),
+            sampled_color: Mutex::new(None),
+            swatches: Mutex::new(Vec::new()),
+            picked_layer: Mutex::new(None),
+            hovered_color: Mutex::new(None),
+            reading_hover: AtomicBool::new(false),
+            dragging_out: AtomicBool::new(false),
+            clipboard: Mutex::new(None),
+            system_clipboard: Mutex::new(None),
+            downloads: Mutex::new(Vec::new()),
+            system_reduced_motion: motion::system_prefers_reduced_motion(),
+            recomposites: AtomicUsize::new(0),
+            adaptive_quality: AtomicBool::new(true),
+            previews_wanted: AtomicBool::new(false),
+            linearize_time: Mutex::new(Duration::ZERO),
+            analysis: Mutex::new(None),
+            analyzing: AtomicBool::new(false),
+            inspection: Mutex::new(None),
+            inspecting: AtomicBool::new(false),
         }
     }
 
-    pub async fn load_file(&self, path: PathBuf) -> Result<InstanceKey, SilicaError> {
-        let (file, textures) =
-            tokio::task::block_in_place(|| ProcreateFile::open(path, &self.dev)).unwrap();
-        let mut target = CompositorTarget::new(self.dev.clone());
-        target
-            .data
-            .flip_vertices(file.flipped.horizontally, file.flipped.vertically);
-        target.set_dimensions(file.size.width, file.size.height);
-
-        for _ in 0..file.orientation {
-            target.data.rotate_vertices(true);
-            target.set_dimensions(target.dim.height, target.dim.width);
+    /// Toast an error and keep its full report for the error panel.
+    pub fn report_error(
+        &self,
+        summary: String,
+        err: &(dyn std::error::Error + 'static),
+        file: Option<&Path>,
+    ) {
+        // Loads are only cancelled when their tab is closed or the app quits.
+        if matches!(err.downcast_ref(), Some(SilicaError::Cancelled)) {
+            return;
         }
+        tracing::error!("{summary}: {err}");
+        self.toasts
+            .lock()
+            .error(format!("{summary} See the error panel for details."));
+        self.reports
+            .lock()
+            .push(ErrorReport::new(summary, err, &self.dev, file));
+    }
 
-        let id = self
-            .compositor
-            .curr_id
-            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
-        let key = InstanceKey(id);
-        self.compositor.instances.write().insert(
-            key,
-            Instance {
-                file: RwLock::new(file),
-                target: Mutex::new(target),
-                textures,
-                changed: AtomicBool::new(true),
-            },
-        );
-        self.rebind_texture(key);
-        Ok(key)
+    /// Show a file in the file manager of the system, selected in its folder
+    /// or by opening the folder.
+    pub fn show_in_file_manager(self: &Arc<Self>, path: PathBuf, select: bool) {
+        let app = self.clone();
+        self.rt.spawn_blocking(move || {
+            if let Err(err) = file_manager::show(&path, select) {
+                app.report_error(
+                    format!("{} could not be shown in the file manager.", path.display()),
+                    &err,
+                    None,
+                );
+            }
+        });
     }
 
-    pub async fn load_dialog(self: Arc<Self>, surface_index: SurfaceIndex, node_index: NodeIndex) {
-        if let Some(handle) = {
-            let mut dialog = rfd::AsyncFileDialog::new();
-            dialog = dialog.add_filter("All Files", &["*"]);
-            dialog = dialog.add_filter("Procreate Files", &["procreate"]);
-            dialog
+    pub async fn save_report(self: Arc<Self>, diagnostics: String) {
+        if let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("Text", &["txt"])
+            .set_file_name("silicate-report.txt")
+            .save_file()
+            .await
+        {
+            let path = handle.path().to_path_buf();
+            match tokio::task::spawn_blocking(move || std::fs::write(path, diagnostics))
+                .await
+                .unwrap()
+            {
+                Ok(()) => self.toasts.lock().success("Report saved."),
+                Err(err) => self
+                    .toasts
+                    .lock()
+                    .error(format!("Report failed to save. Reason: {err}.")),
+            };
         }
-        .pick_file()
-        .await
+    }
+
+    pub async fn export_settings_dialog(self: Arc<Self>, settings: SettingsBundle) {
+        if let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("TOML", &["toml"])
+            .set_file_name("silicate-settings.toml")
+            .save_file()
+            .await
         {
-            match self.clone().load_file(handle.path().to_path_buf()).await {
-                Err(err) => {
-                    self.toasts.lock().error(format!(
-                        "File {} failed to load. Reason: {err}",
-                        handle.file_name()
-                    ));
-                }
-                Ok(key) => {
-                    self.toasts
-                        .lock()
-                        .success(format!("File {} successfully opened.", handle.file_name()));
-                    self.added_instances
-                        .lock()
-                        .push((surface_index, node_index, key));
+            let path = handle.path().to_path_buf();
+            let text = settings.serialize();
+            match tokio::task::spawn_blocking(move || std::fs::write(path, text))
+                .await
+                .unwrap()
+            {
+                Ok(()) => {
+                    self.toasts.lock().success("Settings exported.");
                 }
+                Err(err) => self.report_error("Settings failed to export.".to_string(), &err, None),
             }
-        } else {
-            self.toasts.lock().info("Load cancelled.");
         }
     }
 
-    pub async fn save_dialog(self: Arc<Self>, copied_texture: GpuTexture) {
+    pub async fn import_settings_dialog(self: Arc<Self>) {
         if let Some(handle) = rfd::AsyncFileDialog::new()
-            .add_filter("png", image::ImageFormat::Png.extensions_str())
-            .add_filter("jpeg", image::ImageFormat::Jpeg.extensions_str())
-            .add_filter("tga", image::ImageFormat::Tga.extensions_str())
-            .add_filter("tiff", image::ImageFormat::Tiff.extensions_str())
-            .add_filter("webp", image::ImageFormat::WebP.extensions_str())
-            .add_filter("bmp", image::ImageFormat::Bmp.extensions_str())
-            .save_file()
+            .add_filter("TOML", &["toml"])
+            .pick_file()
             .await
         {
-            let dim = BufferDimensions::from_extent(copied_texture.size);
             let path = handle.path().to_path_buf();
-            if let Err(err) = copied_texture.export(&self.dev, dim, path).await {
-                self.toasts.lock().error(format!(
-                    "File {} failed to export. Reason: {err}.",
-                    handle.file_name()
-                ));
-            } else {
-                self.toasts.lock().success(format!(
-                    "File {} successfully exported.",
-                    handle.file_name()
-                ));
+            let text = match tokio::task::spawn_blocking(move || std::fs::read_to_string(path))
+                .await
+                .unwrap()
+            {
+                Ok(text) => text,
+                Err(err) => {
+                    self.report_error(
+                        format!("Settings {} failed to load.", handle.file_name()),
+                        &err,
+                        Some(handle.path()),
+                    );
+                    return;
+                }
+            };
+            match SettingsBundle::deserialize(&text) {
+                Ok(settings) => {
+                    *self.imported_settings.lock() = Some(settings);
+                    self.toasts
+                        .lock()
+                        .success(format!("Settings imported from {}.", handle.file_name()));
+                }
+                Err(err) => self.report_error(
+                    format!("Settings {} failed to load.", handle.file_name()),
+                    &err,
+                    Some(handle.path()),
+                ),
             }
-        } else {
-            self.toasts.lock().info("Export cancelled.");
         }
     }
 
-    pub async fn rendering_thread(self: Arc<App>) {
-        let mut limiter = tokio::time::interval(Duration::from_secs(1).div_f64(f64::from(60)));
-        limiter.set_missed_tick_behavior(MissedTickBehavior::Skip);
-        loop {
-            // Ensures that we are not generating frames faster than 60FPS
-            // to avoid putting unnecessary computational pressure on the GPU.
-            limiter.tick().await;
-
-            for instance in self.compositor.instances.read().values() {
-                // If the file is contended then it might be edited by the GUI.
-                // Might as well not render a soon to be outdated result.
-                if let Some(file) = instance.file.try_read() {
-                    // Only force a recompute if we need to.
-                    if !instance.change_untick() {
-                        continue;
-                    }
-
-                    let new_layer_config = file.layers.clone();
-                    let background = (!file.background_hidden).then_some(file.background_color);
-                    // Drop the guard here, we no longer need it.
-                    drop(file);
-
-                    let resolved_layers = Self::linearize_silica_layers(&new_layer_config);
-
-                    let mut lock = instance.target.lock();
-                    lock.render(
-                        &self.compositor.pipeline,
-                        background,
-                        &resolved_layers,
-                        &instance.textures,
-                    );
-                    // ENABLE TO DEBUG: hold the lock to make sure the GUI is responsive
-                    // std::thread::sleep(std::time::Duration::from_secs(1));
-                    // Debugging notes: if the GPU is highly contended, the main
-                    // GUI rendering can still be somewhat sluggish.
-                    drop(lock);
+    pub async fn save_preset_dialog(self: Arc<Self>, key: InstanceKey) {
+        let Some((path, preset)) = self.compositor.instances.read().get(&key).map(|instance| {
+            let file = instance.file.read();
+            let mut layers = file.layers.clone();
+            let mut background = BackgroundProperties::of(&file);
+            drop(file);
+            instance.commands.preview(&mut layers, &mut background);
+            (
+                LayerPreset::sidecar(&instance.path),
+                LayerPreset::capture(&layers),
+            )
+        }) else {
+            return;
+        };
+        let mut dialog = rfd::AsyncFileDialog::new().add_filter("Layer Preset", &["json"]);
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            dialog = dialog.set_file_name(name);
+        }
+        if let Some(folder) = path.parent() {
+            dialog = dialog.set_directory(folder);
+        }
+        if let Some(handle) = dialog.save_file().await {
+            let path = handle.path().to_path_buf();
+            let text = preset.serialize();
+            match tokio::task::spawn_blocking(move || std::fs::write(path, text))
+                .await
+                .unwrap()
+            {
+                Ok(()) => {
+                    self.toasts.lock().success("Layer preset saved.");
+                }
+                Err(err) => {
+                    self.report_error("Layer preset failed to save.".to_string(), &err, None)
                 }
             }
         }
     }
 
-    /// Transform tree structure of layers into a linear list of
-    /// layers for rendering.
-    fn linearize_silica_layers<'a>(layers: &'a crate::silica::SilicaGroup) -> Vec<CompositeLayer> {
-        fn inner<'a>(
-            layers: &'a crate::silica::SilicaGroup,
-            composite_layers: &mut Vec<CompositeLayer>,
-            mask_layer: &mut Option<(u32, &'a crate::silica::SilicaLayer)>,
-        ) {
-            for layer in layers.children.iter().rev() {
-                match layer {
-                    SilicaHierarchy::Group(group) if !group.hidden => {
-                        inner(group, composite_layers, mask_layer);
-                    }
-                    SilicaHierarchy::Layer(layer) if !layer.hidden => {
-                        if let Some((_, mask_layer)) = mask_layer {
-                            if layer.clipped && mask_layer.hidden {
-                                continue;
-                            }
-                        }
+    pub async fn load_preset_dialog(self: Arc<Self>, key: InstanceKey) {
+        let mut dialog = rfd::AsyncFileDialog::new().add_filter("Layer Preset", &["json"]);
+        if let Some(folder) = self
+            .compositor
+            .instances
+            .read()
+            .get(&key)
+            .and_then(|instance| instance.path.parent().map(Path::to_path_buf))
+        {
+            dialog = dialog.set_directory(folder);
+        }
+        let Some(handle) = dialog.pick_file().await else {
+            return;
+        };
+        let path = handle.path().to_path_buf();
+        let text = match tokio::task::spawn_blocking(move || std::fs::read_to_string(path))
+            .await
+            .unwrap()
+        {
+            Ok(text) => text,
+            Err(err) => {
+                self.report_error(
+                    format!("Layer preset {} failed to load.", handle.file_name()),
+                    &err,
+                    Some(handle.path()),
+                );
+                return;
+            }
+        };
+        match LayerPreset::deserialize(&text) {
+            Ok(preset) => *self.loaded_preset.lock() = Some((key, preset)),
+            Err(err) => self.report_error(
+                format!("Layer preset {} failed to load.", handle.file_name()),
+                &err,
+                Some(handle.path()),
+            ),
+        }
+    }
 
-                        if !layer.clipped {
-                            *mask_layer = Some((layer.image, layer));
-                        }
+    /// Force every instance to recomposite, ie. when global render options change.
+    pub fn invalidate_all(&self) {
+        for (key, instance) in self.compositor.instances.read().iter() {
+            instance.commands.send(InstanceCommand::Redraw);
+            self.rebind_texture(*key);
+        }
+    }
 
-                        composite_layers.push(CompositeLayer {
-                            texture: layer.image,
-                            clipped: layer.clipped.then(|| mask_layer.unwrap().0),
-                            opacity: layer.opacity,
-                            blend: layer.blend,
-                        });
-                    }
-                    _ => continue,
+    pub async fn paper_dialog(self: Arc<Self>) {
+        if let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter(
+                "Images",
+                &["png", "jpg", "jpeg", "tga", "tiff", "webp", "bmp"],
+            )
+            .pick_file()
+            .await
+        {
+            let path = handle.path().to_path_buf();
+            let image = match tokio::task::spawn_blocking(move || image::open(path))
+                .await
+                .unwrap()
+            {
+                Ok(image) => image.into_rgba8(),
+                Err(err) => {
+                    self.report_error(
+                        format!("Paper {} failed to load.", handle.file_name()),
+                        &err,
+                        Some(handle.path()),
+                    );
+                    return;
                 }
-            }
+            };
+
+            let texture = GpuTexture::empty_layers(
+                &self.dev,
+                image.width(),
+                image.height(),
+                1,
+                GpuTexture::LAYER_USAGE,
+            );
+            let texture = self.upload_image(texture, image).await;
+
+            let mut paper = self.paper.lock();
+            paper.texture = Some(texture);
+            paper.name = Some(handle.file_name());
+            drop(paper);
+
+            self.invalidate_all();
         }
+    }
 
-        let mut composite_layers = Vec::new();
-        inner(layers, &mut composite_layers, &mut None);
-        composite_layers
+    /// Upload a whole image to the first layer of a texture. Uploads wait
+    /// for frames to be drawn, so this is done outside of the runtime's
+    /// workers.
+    async fn upload_image(&self, texture: GpuTexture, image: image::RgbaImage) -> GpuTexture {
+        let dev = self.dev.clone();
+        tokio::task::spawn_blocking(move || {
+            texture.replace(&dev, (0, 0), image.dimensions(), 0, &image);
+            texture
+        })
+        .await
+        .unwrap()
     }
 
-    pub fn rebind_texture(&self, id: InstanceKey) {
-        self.event_loop
-            .send_event(UserEvent::RebindTexture(id))
+    pub async fn watermark_dialog(self: Arc<Self>) {
+        if let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("Images", &["png", "tga", "tiff", "webp", "bmp"])
+            .pick_file()
+            .await
+        {
+            let path = handle.path().to_path_buf();
+            let image = match tokio::task::spawn_blocking(move || image::open(path))
+                .await
+                .unwrap()
+            {
+                Ok(image) => {
+                    let mut image = image.into_rgba8();
+                    // Premultiplied so that filtering does not bleed the
+                    // color of transparent pixels.
+                    for pixel in image.pixels_mut() {
+                        pixel.0 = color::premultiply(pixel.0);
+                    }
+                    image
+                }
+                Err(err) => {
+                    self.report_error(
+                        format!("Watermark {} failed to load.", handle.file_name()),
+                        &err,
+                        Some(handle.path()),
+                    );
+                    return;
+                }
+            };
+
+            let texture = match GpuTexture::try_empty_layers(
+                &self.dev,
+                image.width(),
+                image.height(),
+                1,
+                GpuTexture::LAYER_USAGE,
+            ) {
+                Ok(texture) => texture,
+                Err(err) => {
+                    self.report_error(
+                        format!("Watermark {} failed to load.", handle.file_name()),
+                        &err,
+                        Some(handle.path()),
+                    );
+                    return;
+                }
+            };
+            let texture = self.upload_image(texture, image).await;
+
+            let mut post = self.post.lock();
+            post.watermark = Some(texture);
+            post.watermark_name = Some(handle.file_name());
+        }
+    }
+
+    pub async fn lut_dialog(self: Arc<Self>) {
+        if let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("Cube LUT", &["cube"])
+            .pick_file()
+            .await
+        {
+            let path = handle.path().to_path_buf();
+            let lut = match tokio::task::spawn_blocking(move || CubeLut::open(&path))
+                .await
+                .unwrap()
+            {
+                Ok(lut) => lut,
+                Err(err) => {
+                    self.report_error(
+                        format!("Lookup table {} failed to load.", handle.file_name()),
+                        &err,
+                        Some(handle.path()),
+                    );
+                    return;
+                }
+            };
+
+            let mut options = self.lut.lock();
+            options.texture = Some(lut.upload(&self.dev));
+            options.name = Some(handle.file_name());
+            drop(options);
+
+            self.invalidate_all();
+        }
+    }
+
+    /// Copy the composite of an instance for exporting, re-rendering it
+    /// without previewed adjustments, and if the paper overlay or the
+    /// lookup table differ from the preview.
+    /// Mattes are rendered without the background and the final passes, and
+    /// composites lowered in resolution by adaptive quality are rendered
+    /// again at full resolution.
+    pub fn export_texture(&self, instance: &Instance, kind: ExportKind) -> Option<TiledTexture> {
+        self.export_texture_with(instance, kind, true)
+    }
+
+    /// Like [`Self::export_texture`], leaving the background out of color
+    /// exports too unless `background` is set.
+    fn export_texture_with(
+        &self,
+        instance: &Instance,
+        kind: ExportKind,
+        background: bool,
+    ) -> Option<TiledTexture> {
+        let paper = self.paper.lock();
+        let lut = self.lut.lock();
+        let finish = if kind.is_matte() {
+            CompositeFinish::default()
+        } else {
+            CompositeFinish {
+                tone_map: *self.tone_map.lock(),
+                adjustments: None,
+                paper: paper.composite_paper().filter(|_| paper.include_in_export),
+                lut: lut.export_lut(),
+            }
+        };
+        if !kind.is_matte()
+            && background
+            && !instance.adjustments.lock().is_active()
+            && finish.paper.is_some() == paper.composite_paper().is_some()
+            && finish.lut.is_some() == lut.preview_lut().is_some()
+            && instance.target.lock().downscale() == 1
+        {
+            drop(lut);
+            drop(paper);
+            return instance
+                .target
+                .lock()
+                .output
+                .as_ref()
+                .map(|output| output.texture.clone(&self.dev));
+        }
+
+        // Export what is shown, even if commands are still pending.
+        let snapshot = instance.snapshot.load_full();
+        let downscale = instance.target.lock().downscale();
+        instance.target.lock().set_downscale(1);
+        instance.render(
+            &self.compositor.pipeline,
+            &snapshot.groups,
+            &snapshot.layers,
+            snapshot
+                .background
+                .filter(|_| background && !kind.is_matte()),
+            finish,
+        );
+        drop(lut);
+        drop(paper);
+        let mut target = instance.target.lock();
+        let texture = target
+            .output
+            .as_ref()
+            .map(|output| output.texture.clone(&self.dev));
+        // Leaves no output, so that the redrawn composite is bound anew.
+        target.set_downscale(downscale);
+        drop(target);
+        // Let the rendering thread restore the on-screen composite.
+        instance.commands.send(InstanceCommand::Redraw);
+        texture
+    }
+
+    /// Copy the composite of an open file for exporting, away from the GUI
+    /// thread, once an export has somewhere to go. Returns `None` if the
+    /// file was closed in the meantime.
+    async fn copy_for_export(
+        self: &Arc<Self>,
+        key: InstanceKey,
+        kind: ExportKind,
+    ) -> Option<TiledTexture> {
+        let app = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let instances = app.compositor.instances.read();
+            app.export_texture(instances.get(&key)?, kind)
+        })
+        .await
+        .unwrap()
+    }
+
+    pub async fn load_file(&self, path: PathBuf) -> Result<InstanceKey, SilicaError> {
+        let samples = if self.verify_tiles.load(Acquire) {
+            Self::TILE_SAMPLES
+        } else {
+            0
+        };
+        let cancel = self.loads.child();
+        let (file, textures, samples) =
+            self.open_within_budget(None, || self.open_file(&path, samples, &cancel))?;
+        if !samples.is_empty() {
+            self.verify_samples(&textures, &samples).await;
+        }
+        self.report_damage(&file);
+
+        let id = self
+            .compositor
+            .curr_id
+            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+        let key = InstanceKey(id);
+        self.compositor
+            .instances
+            .write()
+            .insert(key, Instance::new(self.dev.clone(), path, file, textures));
+        self.rebind_texture(key);
+        Ok(key)
+    }
+
+    /// Load a file on the configured number of threads, stopping part way
+    /// through once `cancel` is cancelled.
+    fn open_file(
+        &self,
+        path: &Path,
+        samples: usize,
+        cancel: &CancelToken,
+    ) -> Result<(ProcreateFile, TiledTexture, Vec<TileSample>), SilicaError> {
+        let options = LoadOptions {
+            samples,
+            threads: NonZeroUsize::new(self.load_threads.load(Acquire)),
+            cancel: Some(cancel),
+            coverage: true,
+            ..LoadOptions::default()
+        };
+        ProcreateFile::open_with(path, &self.dev, &options)
+    }
+
+    /// Summarize the layers that were only partially recovered.
+    fn report_damage(&self, file: &ProcreateFile) {
+        let damaged = file.layers.damaged_layers();
+        if damaged.is_empty() {
+            return;
+        }
+
+        let tiles = damaged
+            .iter()
+            .filter_map(|layer| layer.damage.as_ref())
+            .map(|damage| damage.tiles)
+            .sum::<usize>();
+        let names = damaged
+            .iter()
+            .map(|layer| layer.name.as_deref().unwrap_or("Unnamed Layer"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.toasts.lock().warning(format!(
+            "{tiles} tiles in {} layers could not be recovered and were left blank: {names}",
+            damaged.len()
+        ));
+    }
+
+    /// How long a tab has to be hidden before its atlas may be evicted.
+    const EVICTION_GRACE: Duration = Duration::from_secs(2);
+
+    /// Open a file, evicting the atlases of hidden tabs and retrying once
+    /// if it does not fit in the GPU memory budget.
+    fn open_within_budget<T>(
+        &self,
+        keep: Option<InstanceKey>,
+        open: impl Fn() -> Result<T, SilicaError>,
+    ) -> Result<T, SilicaError> {
+        match tokio::task::block_in_place(&open) {
+            Err(SilicaError::AllocationError(AllocationError::OverBudget { needed, .. }))
+                if self.evict_for(needed, keep) =>
+            {
+                tokio::task::block_in_place(open)
+            }
+            result => result,
+        }
+    }
+
+    /// Evict the atlases of the least recently shown files until `bytes`
+    /// fit in the budget. Returns whether enough memory was freed.
+    fn evict_for(&self, bytes: u64, keep: Option<InstanceKey>) -> bool {
+        let instances = self.compositor.instances.read();
+        let mut candidates = instances
+            .iter()
+            .filter(|(key, instance)| {
+                Some(**key) != keep
+                    && instance.last_shown.lock().elapsed() > Self::EVICTION_GRACE
+                    && !instance.is_evicted()
+            })
+            .map(|(key, instance)| (*instance.last_shown.lock(), *key, instance))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(last_shown, ..)| *last_shown);
+
+        for (_, key, instance) in candidates {
+            if self.dev.budget.check(bytes).is_ok() {
+                break;
+            }
+            tracing::info!("Evicting the atlas of {key:?} to stay within the GPU memory budget");
+            instance.evict();
+        }
+        self.dev.budget.check(bytes).is_ok()
+    }
+
+    /// Mark a file as visible, reloading its atlas if it was evicted.
+    pub fn show_instance(self: &Arc<Self>, key: InstanceKey, instance: &Instance) {
+        *instance.last_shown.lock() = Instant::now();
+        if !instance.reload_failed.load(Acquire) {
+            self.reload_evicted(key, instance);
+        }
+    }
+
+    /// Reload the atlas of a file from disk if it was evicted.
+    pub fn reload_evicted(self: &Arc<Self>, key: InstanceKey, instance: &Instance) {
+        if instance.is_evicted() && !instance.reloading.swap(true, Acquire) {
+            instance.reload_failed.store(false, Release);
+            self.rt.spawn(self.clone().reload_textures(key));
+        }
+    }
+
+    /// Undo edits made to a file in the viewer if `steps` is negative, or
+    /// redo undone edits if it is positive. Returns the number of edits
+    /// that were undone or redone.
+    pub fn step_history(&self, key: InstanceKey, steps: isize) -> usize {
+        let instances = self.compositor.instances.read();
+        let Some(instance) = instances.get(&key) else {
+            return 0;
+        };
+        let mut history = instance.history.lock();
+        let mut stepped = 0;
+        for _ in 0..steps.unsigned_abs() {
+            let commands = if steps < 0 {
+                history.undo()
+            } else {
+                history.redo()
+            };
+            let Some(commands) = commands else {
+                break;
+            };
+            for command in commands {
+                instance.commands.send(command);
+            }
+            stepped += 1;
+        }
+        stepped
+    }
+
+    /// Read a file again from disk, keeping the changes made to its layers
+    /// in the viewer where the layers did not change on disk.
+    pub async fn reload_file(self: Arc<Self>, key: InstanceKey) {
+        let cancel = self.loads.child();
+        let Some(path) = self.compositor.instances.read().get(&key).map(|instance| {
+            *instance.cancel_reload.lock() = Some(cancel.clone());
+            instance.path.clone()
+        }) else {
+            return;
+        };
+
+        let result = self.open_within_budget(Some(key), || {
+            self.open_file(&path, 0, &cancel)
+                .map(|(file, textures, _)| (file, textures))
+        });
+        match result {
+            Ok((file, textures)) => {
+                self.report_damage(&file);
+                let Some(diff) = self
+                    .compositor
+                    .instances
+                    .read()
+                    .get(&key)
+                    .map(|instance| instance.replace_file(file, textures))
+                else {
+                    return;
+                };
+                self.rebind_texture(key);
+                self.toasts.lock().info(format!(
+                    "Reloaded {}: {}.",
+                    path.display(),
+                    diff.summary()
+                ));
+            }
+            Err(err) => self.report_error(
+                format!("File {} failed to reload.", path.display()),
+                &err,
+                Some(&path),
+            ),
+        }
+    }
+
+    async fn reload_textures(self: Arc<Self>, key: InstanceKey) {
+        let cancel = self.loads.child();
+        let Some(path) = self.compositor.instances.read().get(&key).map(|instance| {
+            *instance.cancel_reload.lock() = Some(cancel.clone());
+            instance.path.clone()
+        }) else {
+            return;
+        };
+
+        let result = self.open_within_budget(Some(key), || {
+            self.open_file(&path, 0, &cancel)
+                .map(|(file, textures, _)| (file, textures))
+        });
+        let instances = self.compositor.instances.read();
+        let Some(instance) = instances.get(&key) else {
+            return;
+        };
+        let failed = match result {
+            Ok((file, textures)) => {
+                // The file may have changed on disk while it was evicted.
+                instance.replace_file(file, textures);
+                self.rebind_texture(key);
+                None
+            }
+            Err(err) => Some(err),
+        };
+        instance.reload_failed.store(failed.is_some(), Release);
+        instance.reloading.store(false, Release);
+        drop(instances);
+        if let Some(err) = failed {
+            self.report_error(
+                format!("File {} failed to reload.", path.display()),
+                &err,
+                Some(&path),
+            );
+        }
+    }
+
+    /// Approximate number of tiles verified per file.
+    const TILE_SAMPLES: usize = 32;
+
+    /// Compare sampled tiles with what was uploaded to the GPU.
+    async fn verify_samples(&self, textures: &TiledTexture, samples: &[TileSample]) {
+        let mut mismatches = 0;
+        for sample in samples {
+            let uploaded = textures
+                .read_region(
+                    &self.dev,
+                    sample.origin,
+                    (sample.size.width, sample.size.height),
+                    sample.layer,
+                )
+                .await;
+            if uploaded != sample.data {
+                mismatches += 1;
+                tracing::warn!(
+                    "Tile at {:?} of layer {} does not match its decoded data",
+                    sample.origin,
+                    sample.layer
+                );
+            }
+        }
+
+        if mismatches == 0 {
+            self.toasts.lock().info(format!(
+                "Tile check passed for {} sampled tiles.",
+                samples.len()
+            ));
+        } else {
+            self.toasts.lock().warning(format!(
+                "Tile check: {mismatches} of {} sampled tiles differ from the file. \
+                 Your GPU driver may be corrupting uploads.",
+                samples.len()
+            ));
+        }
+    }
+
+    /// Offer to reopen the files of a session that ended in a crash, in the
+    /// tabs and splits they were in.
+    pub async fn restore_dialog(self: Arc<Self>, session: Session) {
+        let Some(layout) = session.layout else {
+            return;
+        };
+        let restore = rfd::AsyncMessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Restore Session")
+            .set_description(format!(
+                "Silicate did not close properly last time. Reopen the {} files that were open?",
+                layout.tabs().len()
+            ))
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .show()
+            .await;
+        if restore != rfd::MessageDialogResult::Yes {
+            return;
+        }
+
+        let mut failed = 0;
+        let mut keys = Vec::new();
+        for tab in layout.tabs() {
+            let path = &tab.path;
+            match self.clone().load_file(path.clone()).await {
+                Err(SilicaError::Cancelled) => return,
+                Err(err) => {
+                    failed += 1;
+                    keys.push(None);
+                    self.reports.lock().push(ErrorReport::new(
+                        format!("File {} failed to restore.", path.display()),
+                        &err,
+                        &self.dev,
+                        Some(path),
+                    ));
+                }
+                Ok(key) => keys.push(Some(key)),
+            }
+        }
+        let mut keys = keys.into_iter();
+        *self.restored_layout.lock() =
+            layout.filter_map(&mut |tab| Some((keys.next()??, tab.view)));
+
+        if failed == 0 {
+            self.toasts.lock().success("Session restored.");
+        } else {
+            self.toasts.lock().warning(format!(
+                "Session restored, but {failed} files failed to open."
+            ));
+        }
+    }
+
+    /// Open files passed on the command line.
+    pub async fn open_files(self: Arc<Self>, paths: Vec<PathBuf>) {
+        for path in paths {
+            match self.load_file(path.clone()).await {
+                Err(err) => {
+                    self.report_error(
+                        format!("File {} failed to load.", path.display()),
+                        &err,
+                        Some(&path),
+                    );
+                }
+                Ok(key) => {
+                    self.added_instances.lock().push((
+                        SurfaceIndex::main(),
+                        NodeIndex::root(),
+                        key,
+                    ));
+                }
+            }
+        }
+    }
+
+    pub async fn load_dialog(self: Arc<Self>, surface_index: SurfaceIndex, node_index: NodeIndex) {
+        if let Some(handle) = {
+            let mut dialog = rfd::AsyncFileDialog::new();
+            dialog = dialog.add_filter("All Files", &["*"]);
+            dialog = dialog.add_filter("Procreate Files", &["procreate"]);
+            dialog
+        }
+        .pick_file()
+        .await
+        {
+            match self.clone().load_file(handle.path().to_path_buf()).await {
+                Err(err) => {
+                    self.report_error(
+                        format!("File {} failed to load.", handle.file_name()),
+                        &err,
+                        Some(handle.path()),
+                    );
+                }
+                Ok(key) => {
+                    self.toasts
+                        .lock()
+                        .success(format!("File {} successfully opened.", handle.file_name()));
+                    self.added_instances
+                        .lock()
+                        .push((surface_index, node_index, key));
+                }
+            }
+        } else {
+            self.toasts.lock().info("Load cancelled.");
+        }
+    }
+
+    /// Ask for a folder and show its files in the gallery.
+    pub async fn gallery_dialog(self: Arc<Self>) {
+        let Some(handle) = rfd::AsyncFileDialog::new().pick_folder().await else {
+            return;
+        };
+        let folder = handle.path().to_path_buf();
+        match tokio::task::spawn_blocking(move || Gallery::scan(folder))
+            .await
+            .unwrap()
+        {
+            Ok(gallery) if gallery.is_empty() => {
+                self.toasts.lock().info(format!(
+                    "There are no Procreate files in {}.",
+                    handle.file_name()
+                ));
+            }
+            Ok(gallery) => *self.opened_gallery.lock() = Some(gallery),
+            Err(err) => self.report_error(
+                format!("Folder {} could not be read.", handle.file_name()),
+                &err,
+                Some(handle.path()),
+            ),
+        }
+    }
+
+    /// Ask where to save an export of a file, then copy its composite and
+    /// save it. Nothing is copied if the dialog is cancelled.
+    pub async fn save_dialog(
+        self: Arc<Self>,
+        key: InstanceKey,
+        kind: ExportKind,
+        crop: Option<Region>,
+        metadata: ExportMetadata,
+    ) {
+        let dialog =
+            rfd::AsyncFileDialog::new().add_filter("png", image::ImageFormat::Png.extensions_str());
+        // Mattes are meant for print, keep them lossless.
+        let dialog = if kind.is_matte() {
+            dialog.add_filter("tiff", image::ImageFormat::Tiff.extensions_str())
+        } else {
+            dialog
+                .add_filter("jpeg", image::ImageFormat::Jpeg.extensions_str())
+                .add_filter("tga", image::ImageFormat::Tga.extensions_str())
+                .add_filter("tiff", image::ImageFormat::Tiff.extensions_str())
+                .add_filter("webp", image::ImageFormat::WebP.extensions_str())
+                .add_filter("bmp", image::ImageFormat::Bmp.extensions_str())
+        };
+        if let Some(handle) = dialog.save_file().await {
+            let Some(copied_texture) = self.copy_for_export(key, kind).await else {
+                self.toasts
+                    .lock()
+                    .error("The file was closed before it could be exported.");
+                return;
+            };
+            let path = handle.path().to_path_buf();
+            let scale = *self.export_scale.lock();
+            let metadata = metadata.with_color_space(*self.export_color.lock());
+            let (copied_texture, crop) = if kind.is_matte() {
+                (copied_texture, crop)
+            } else {
+                let post = self.post.lock();
+                self.compositor.post.apply(
+                    &self.dev,
+                    copied_texture,
+                    crop,
+                    &post.steps,
+                    post.watermark.as_ref(),
+                    metadata.conversion,
+                )
+            };
+            // Mattes are only saved in the chosen format.
+            let paths = if kind.is_matte() {
+                vec![path]
+            } else {
+                self.extra_formats.lock().paths(path)
+            };
+            let count = paths.len();
+            if let Err(err) = copied_texture
+                .export(&self.dev, paths, kind, scale, crop, metadata)
+                .await
+            {
+                self.report_error(
+                    format!("File {} failed to export.", handle.file_name()),
+                    &err,
+                    None,
+                );
+            } else if count > 1 {
+                self.toasts.lock().success(format!(
+                    "File {} successfully exported in {count} formats.",
+                    handle.file_name()
+                ));
+            } else {
+                self.toasts.lock().success(format!(
+                    "File {} successfully exported.",
+                    handle.file_name()
+                ));
+            }
+        } else {
+            self.toasts.lock().info("Export cancelled.");
+        }
+    }
+
+    /// Export the composite of a file with each preset, into a chosen folder.
+    pub async fn export_presets_dialog(
+        self: Arc<Self>,
+        key: InstanceKey,
+        presets: Vec<ExportPreset>,
+    ) {
+        let Some((folder, name, layers, crop, metadata)) =
+            self.compositor.instances.read().get(&key).map(|instance| {
+                let name = instance.export_name();
+                let file = instance.file.read();
+                (
+                    instance.path.parent().map(Path::to_path_buf),
+                    name,
+                    file.layers.iter_layers().count(),
+                    instance.display().crop,
+                    file.export_metadata(SOFTWARE),
+                )
+            })
+        else {
+            return;
+        };
+        let mut dialog = rfd::AsyncFileDialog::new();
+        if let Some(folder) = folder {
+            dialog = dialog.set_directory(folder);
+        }
+        let Some(handle) = dialog.pick_folder().await else {
+            self.toasts.lock().info("Export cancelled.");
+            return;
+        };
+        let folder = handle.path().to_path_buf();
+        let metadata = metadata.with_color_space(*self.export_color.lock());
+        let filter = self.export_scale.lock().filter;
+        let mut exported = 0;
+        for preset in &presets {
+            let failed = format!("Preset {} failed to export.", preset.name);
+            let file_name = match preset.file_name(&name, layers) {
+                Ok(file_name) => file_name,
+                Err(err) => {
+                    self.report_error(failed, &err, None);
+                    continue;
+                }
+            };
+            let app = self.clone();
+            let background = preset.background;
+            let Some(copied_texture) = tokio::task::spawn_blocking(move || {
+                let instances = app.compositor.instances.read();
+                app.export_texture_with(instances.get(&key)?, ExportKind::Color, background)
+            })
+            .await
+            .unwrap() else {
+                self.toasts
+                    .lock()
+                    .error("The file was closed before it could be exported.");
+                return;
+            };
+            let (copied_texture, crop) = {
+                let post = self.post.lock();
+                self.compositor.post.apply(
+                    &self.dev,
+                    copied_texture,
+                    crop.filter(|_| preset.region),
+                    &post.steps,
+                    post.watermark.as_ref(),
+                    metadata.conversion,
+                )
+            };
+            let size = crop
+                .and_then(|crop| crop.intersect(&copied_texture.bounds()))
+                .unwrap_or(copied_texture.bounds());
+            let scale = ExportScale {
+                factor: preset.size.factor(size.width, size.height),
+                filter,
+            };
+            match copied_texture
+                .export(
+                    &self.dev,
+                    vec![folder.join(file_name)],
+                    ExportKind::Color,
+                    scale,
+                    crop,
+                    metadata.clone(),
+                )
+                .await
+            {
+                Ok(()) => exported += 1,
+                Err(err) => self.report_error(failed, &err, None),
+            }
+        }
+        if exported > 0 {
+            self.toasts.lock().success(format!(
+                "Exported {exported} of {} presets into {}.",
+                presets.len(),
+                folder.display()
+            ));
+        }
+    }
+
+    /// Export the composite of a file as a PNG in the temporary folder, as
+    /// the view would be exported, and drag it out of the window.
+    pub async fn drag_out(
+        self: Arc<Self>,
+        key: InstanceKey,
+        name: String,
+        crop: Option<Region>,
+        metadata: ExportMetadata,
+    ) {
+        let Some(copied_texture) = self.copy_for_export(key, ExportKind::Color).await else {
+            return;
+        };
+        let metadata = metadata.with_color_space(*self.export_color.lock());
+        let (copied_texture, crop) = {
+            let post = self.post.lock();
+            self.compositor.post.apply(
+                &self.dev,
+                copied_texture,
+                crop,
+                &post.steps,
+                post.watermark.as_ref(),
+                metadata.conversion,
+            )
+        };
+        // Named after the file, as that is the name it is dropped with.
+        let file_name = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || " -_".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let folder = std::env::temp_dir().join("silicate-drag");
+        let path = folder.join(format!("{file_name}.png"));
+        let scale = *self.export_scale.lock();
+        let result = match std::fs::create_dir_all(&folder) {
+            Ok(()) => {
+                copied_texture
+                    .export(
+                        &self.dev,
+                        vec![path.clone()],
+                        ExportKind::Color,
+                        scale,
+                        crop,
+                        metadata,
+                    )
+                    .await
+            }
+            Err(err) => Err(err.into()),
+        };
+        match result {
+            Ok(()) => self
+                .event_loop
+                .send_event(UserEvent::DragOut(path))
+                .unwrap(),
+            Err(err) => {
+                self.report_error(format!("File {name} could not be dragged out."), &err, None)
+            }
+        }
+    }
+
+    /// Most cells of a drawing guide that are exported at once, so that a
+    /// fine grid does not fill a folder with thousands of images.
+    pub const MAX_GUIDE_CELLS: usize = 200;
+
+    /// Number of cells of a grid with the given cell size over an image.
+    pub fn guide_cell_count(width: u32, height: u32, cell: u32) -> usize {
+        width.div_ceil(cell) as usize * height.div_ceil(cell) as usize
+    }
+
+    /// Save each cell of the drawing guide grid of a composite as its own
+    /// image, numbered in reading order after the chosen file name. Cells
+    /// are laid out from the top left corner of the view, those cut off by
+    /// the right and bottom edges are saved as they are.
+    pub async fn guide_cells_dialog(
+        self: Arc<Self>,
+        key: InstanceKey,
+        cell: u32,
+        metadata: ExportMetadata,
+    ) {
+        let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("png", image::ImageFormat::Png.extensions_str())
+            .add_filter("jpeg", image::ImageFormat::Jpeg.extensions_str())
+            .add_filter("tiff", image::ImageFormat::Tiff.extensions_str())
+            .add_filter("webp", image::ImageFormat::WebP.extensions_str())
+            .save_file()
+            .await
+        else {
+            self.toasts.lock().info("Export cancelled.");
+            return;
+        };
+        let Some(copied_texture) = self.copy_for_export(key, ExportKind::Color).await else {
+            self.toasts
+                .lock()
+                .error("The file was closed before it could be exported.");
+            return;
+        };
+
+        let path = handle.path().to_path_buf();
+        let stem = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let extension = path.extension().map_or_else(
+            || String::from("png"),
+            |ext| ext.to_string_lossy().into_owned(),
+        );
+        let scale = *self.export_scale.lock();
+        let metadata = metadata.with_color_space(*self.export_color.lock());
+        let count = Self::guide_cell_count(copied_texture.width, copied_texture.height, cell);
+        if count > Self::MAX_GUIDE_CELLS {
+            self.toasts.lock().error(format!(
+                "The guide has {count} cells, more than the {} that can be exported at once.",
+                Self::MAX_GUIDE_CELLS
+            ));
+            return;
+        }
+        let cells = Region::split(copied_texture.width, copied_texture.height, cell, 1);
+        for (index, cell) in cells.into_iter().enumerate() {
+            let cell_path = path.with_file_name(format!("{stem}-{:02}.{extension}", index + 1));
+            // Each cell gets its own pass of the export steps, so that a
+            // watermark lands on every one of them.
+            let (texture, crop) = {
+                let post = self.post.lock();
+                self.compositor.post.apply(
+                    &self.dev,
+                    copied_texture.clone(&self.dev),
+                    Some(cell),
+                    &post.steps,
+                    post.watermark.as_ref(),
+                    metadata.conversion,
+                )
+            };
+            let paths = self.extra_formats.lock().paths(cell_path);
+            if let Err(err) = texture
+                .export(
+                    &self.dev,
+                    paths,
+                    ExportKind::Color,
+                    scale,
+                    crop,
+                    metadata.clone(),
+                )
+                .await
+            {
+                self.report_error(
+                    format!(
+                        "Cell {} of {} failed to export.",
+                        index + 1,
+                        handle.file_name()
+                    ),
+                    &err,
+                    None,
+                );
+                return;
+            }
+        }
+        self.toasts.lock().success(format!(
+            "{count} guide cells of {} successfully exported.",
+            handle.file_name()
+        ));
+    }
+
+    /// Save a page of the layer atlas of a file as it is stored on the GPU,
+    /// with premultiplied colors, to debug how its tiles were uploaded.
+    pub async fn dump_atlas_dialog(self: Arc<Self>, key: InstanceKey, page: u32) {
+        let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("png", image::ImageFormat::Png.extensions_str())
+            .set_file_name(format!("atlas-page-{page}.png"))
+            .save_file()
+            .await
+        else {
+            return;
+        };
+
+        let app = self.clone();
+        let image = tokio::task::spawn_blocking(move || {
+            let instances = app.compositor.instances.read();
+            let textures = instances.get(&key)?.textures.read();
+            let textures = textures.as_ref()?;
+            // The atlas stays locked until it is read back.
+            let data = futures::executor::block_on(textures.read_region(
+                &app.dev,
+                (0, 0),
+                (textures.width, textures.height),
+                page,
+            ));
+            image::RgbaImage::from_raw(textures.width, textures.height, data)
+        })
+        .await
+        .unwrap();
+        let Some(image) = image else {
+            self.toasts
+                .lock()
+                .error("The atlas is no longer loaded, nothing was dumped.");
+            return;
+        };
+
+        let path = handle.path().to_path_buf();
+        match tokio::task::spawn_blocking(move || image.save(path))
+            .await
+            .unwrap()
+        {
+            Ok(()) => {
+                self.toasts
+                    .lock()
+                    .success(format!("Atlas page dumped to {}.", handle.file_name()));
+            }
+            Err(err) => self.report_error(
+                format!("Atlas page {} failed to dump.", handle.file_name()),
+                &err,
+                None,
+            ),
+        }
+    }
+
+    /// Save a file as a Krita document, with each layer read back on its
+    /// own in the orientation of the composite.
+    pub async fn kra_dialog(self: Arc<Self>, key: InstanceKey, transform: OutputTransform) {
+        let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("kra", &["kra"])
+            .save_file()
+            .await
+        else {
+            self.toasts.lock().info("Export cancelled.");
+            return;
+        };
+        let Some(merged) = self.copy_for_export(key, ExportKind::Color).await else {
+            self.toasts
+                .lock()
+                .error("The file was closed before it could be exported.");
+            return;
+        };
+
+        let mut image = merged.read_rgba(&self.dev, merged.bounds()).await;
+        let (width, height) = image.dimensions();
+        drop(merged);
+        for pixel in image.pixels_mut() {
+            pixel.0 = color::unpremultiply(pixel.0);
+        }
+
+        let app = self.clone();
+        let path = handle.path().to_path_buf();
+        let result = tokio::task::spawn_blocking(move || -> Result<bool, KraError> {
+            let instances = app.compositor.instances.read();
+            let Some(instance) = instances.get(&key) else {
+                return Ok(false);
+            };
+            let file = instance.file.read();
+            let layers = file.layers.clone();
+            let metadata = file.export_metadata(SOFTWARE);
+            let background_image = file
+                .background_image
+                .map(|texture| (texture, file.background_image_hidden));
+            let (background_color, background_hidden) =
+                (file.background_color, file.background_hidden);
+            drop(file);
+            drop(instances);
+
+            let mut target = CompositorTarget::new(app.dev.clone());
+            target.data.set_transform(transform);
+            target.set_dimensions(width, height);
+            // Only hold the atlas while a layer renders, not while it is
+            // read back and encoded.
+            let mut pixels = |texture: u32| {
+                let output = {
+                    let instances = app.compositor.instances.read();
+                    let textures = instances.get(&key).map(|i| i.textures.read());
+                    match textures.as_ref().and_then(|t| t.as_ref()) {
+                        Some(textures) => {
+                            target.render(
+                                &app.compositor.pipeline,
+                                None,
+                                &[CompositeLayer {
+                                    texture,
+                                    clipped: None,
+                                    opacity: 1.0,
+                                    blend: BlendMode::Normal,
+                                }],
+                                textures,
+                                CompositeFinish::default(),
+                            );
+                            target.output.as_ref().map(|o| o.texture.clone(&app.dev))
+                        }
+                        None => None,
+                    }
+                };
+                let Some(output) = output else {
+                    // Evicted while exporting, leaving the layer empty.
+                    return image::RgbaImage::new(width, height);
+                };
+                let mut layer =
+                    futures::executor::block_on(output.read_rgba(&app.dev, output.bounds()));
+                for pixel in layer.pixels_mut() {
+                    pixel.0 = color::unpremultiply(pixel.0);
+                }
+                layer
+            };
+            let document = KraDocument {
+                metadata: &metadata,
+                layers: &layers,
+                background_image,
+                background_color,
+                background_hidden,
+                merged: &image,
+            };
+            let out = std::io::BufWriter::new(std::fs::File::create(path)?);
+            kra::write_kra(out, &document, &mut pixels)?.flush()?;
+            Ok(true)
+        })
+        .await
+        .unwrap();
+        match result {
+            Ok(true) => {
+                self.toasts.lock().success(format!(
+                    "File {} successfully exported.",
+                    handle.file_name()
+                ));
+            }
+            Ok(false) => {
+                self.toasts
+                    .lock()
+                    .error("The file was closed, nothing was exported.");
+            }
+            Err(err) => self.report_error(
+                format!("File {} failed to export.", handle.file_name()),
+                &err,
+                None,
+            ),
+        }
+    }
+
+    /// Save a contact sheet of the composites of the files, labelled with
+    /// their file names. Files closed in the meantime are left out.
+    pub async fn contact_sheet_dialog(self: Arc<Self>, keys: Vec<InstanceKey>) {
+        let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("png", image::ImageFormat::Png.extensions_str())
+            .add_filter("jpeg", image::ImageFormat::Jpeg.extensions_str())
+            .set_file_name("contact-sheet.png")
+            .save_file()
+            .await
+        else {
+            self.toasts.lock().info("Export cancelled.");
+            return;
+        };
+
+        let sheet = Arc::new(ContactSheet { thumbnail: 256 });
+        let mut thumbnails = Vec::with_capacity(keys.len());
+        // One composite at a time, so that only its thumbnail stays around.
+        for key in keys {
+            let entry = {
+                let instances = self.compositor.instances.read();
+                instances.get(&key).and_then(|instance| {
+                    let name = instance.path.file_name().map_or_else(
+                        || instance.path.display().to_string(),
+                        |name| name.to_string_lossy().into_owned(),
+                    );
+                    self.export_texture(instance, ExportKind::Color)
+                        .map(|texture| (name, texture))
+                })
+            };
+            let Some((name, texture)) = entry else {
+                continue;
+            };
+            let image = texture.read_rgba(&self.dev, texture.bounds()).await;
+            drop(texture);
+            let sheet = sheet.clone();
+            let thumbnail = tokio::task::spawn_blocking(move || sheet.thumbnail(&image))
+                .await
+                .unwrap();
+            thumbnails.push((name, thumbnail));
+        }
+
+        let path = handle.path().to_path_buf();
+        let result = tokio::task::spawn_blocking(move || {
+            metadata::save(
+                &sheet.compose(&thumbnails),
+                &path,
+                &ExportMetadata::default(),
+            )
+        })
+        .await
+        .unwrap();
+        match result {
+            Ok(()) => {
+                self.toasts.lock().success(format!(
+                    "Contact sheet {} successfully exported.",
+                    handle.file_name()
+                ));
+            }
+            Err(err) => self.report_error(
+                format!("Contact sheet {} failed to export.", handle.file_name()),
+                &err,
+                None,
+            ),
+        }
+    }
+
+    /// Use the system clipboard, opening it the first time.
+    fn with_clipboard<T>(
+        &self,
+        f: impl FnOnce(&mut arboard::Clipboard) -> Result<T, arboard::Error>,
+    ) -> Result<T, arboard::Error> {
+        let mut clipboard = self.system_clipboard.lock();
+        match &mut *clipboard {
+            Some(clipboard) => f(clipboard),
+            none => f(none.insert(arboard::Clipboard::new()?)),
+        }
+    }
+
+    /// Whether a command line argument is a link to download rather than a
+    /// path.
+    pub fn is_link(arg: &str) -> bool {
+        Download::new(arg).is_some()
+    }
+
+    /// Link on the system clipboard that can be opened, if it holds one.
+    pub fn clipboard_link(&self) -> Option<String> {
+        let text = self.with_clipboard(|clipboard| clipboard.get_text()).ok()?;
+        Download::new(&text).map(|_| text.trim().to_string())
+    }
+
+    /// Download a file from an HTTP or WebDAV link into the temporary folder
+    /// and open it, showing the progress of the download meanwhile.
+    pub async fn open_link(self: Arc<Self>, url: String) {
+        let Some(download) = Download::new(&url).map(Arc::new) else {
+            self.toasts
+                .lock()
+                .error(format!("{url} is not an HTTP or WebDAV link."));
+            return;
+        };
+        self.downloads.lock().push(download.clone());
+        let result = {
+            let download = download.clone();
+            tokio::task::spawn_blocking(move || download.fetch())
+                .await
+                .unwrap()
+        };
+        self.downloads
+            .lock()
+            .retain(|other| !Arc::ptr_eq(other, &download));
+        match result {
+            Ok(path) => self.open_files(vec![path]).await,
+            Err(err) => self.report_error(
+                format!("File {} failed to download.", download.name),
+                &err,
+                None,
+            ),
+        }
+    }
+
+    /// Render a layer of a file on its own at full opacity, in the size and
+    /// orientation of the composite. Returns `None` if the file was closed
+    /// or unloaded from the GPU.
+    fn render_layer(&self, key: InstanceKey, uuid: &str) -> Option<TiledTexture> {
+        let instances = self.compositor.instances.read();
+        let instance = instances.get(&key)?;
+        let texture = instance.file.read().layers.find_layer(uuid)?.image;
+        let mut target = CompositorTarget::new(self.dev.clone());
+        {
+            let composite = instance.target.lock();
+            target.data.set_transform(composite.data.transform());
+            target.set_dimensions(composite.dim.width, composite.dim.height);
+        }
+        let textures = instance.textures.read();
+        target.render(
+            &self.compositor.pipeline,
+            None,
+            &[CompositeLayer {
+                texture,
+                clipped: None,
+                opacity: 1.0,
+                blend: BlendMode::Normal,
+            }],
+            textures.as_ref()?,
+            CompositeFinish::default(),
+        );
+        target.output.map(|output| output.texture)
+    }
+
+    /// Composite a layer or group of a file on its own, in the size and
+    /// orientation of the composite and without the background. Returns
+    /// `None` if the file was closed, unloaded from the GPU or no longer has
+    /// a layer or group with the texture.
+    fn render_node(&self, key: InstanceKey, texture: u32) -> Option<TiledTexture> {
+        let instances = self.compositor.instances.read();
+        let instance = instances.get(&key)?;
+        let (groups, layers) = Self::resolve_node(&instance.file.read().layers, texture)?;
+        let mut target = CompositorTarget::new(self.dev.clone());
+        {
+            let composite = instance.target.lock();
+            target.data.set_transform(composite.data.transform());
+            target.set_dimensions(composite.dim.width, composite.dim.height);
+        }
+        let textures = instance.textures.read();
+        let textures = textures.as_ref()?;
+        // Hidden groups are not kept up to date by the composite.
+        let mut group_target = instance.group_target.lock();
+        for group in &groups {
+            group_target.render_to_layer(
+                &self.compositor.pipeline,
+                &group.layers,
+                textures,
+                group.texture,
+            );
+        }
+        drop(group_target);
+        target.render(
+            &self.compositor.pipeline,
+            None,
+            &layers,
+            textures,
+            CompositeFinish::default(),
+        );
+        target.output.map(|output| output.texture)
+    }
+
+    /// Ask where to save a layer or group of a file as a PNG, then composite
+    /// it on its own with transparency and save it.
+    pub async fn export_node_dialog(
+        self: Arc<Self>,
+        key: InstanceKey,
+        texture: u32,
+        name: String,
+        metadata: ExportMetadata,
+    ) {
+        let file_name = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || " -_".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let Some(handle) = rfd::AsyncFileDialog::new()
+            .add_filter("png", image::ImageFormat::Png.extensions_str())
+            .set_file_name(format!("{file_name}.png"))
+            .save_file()
+            .await
+        else {
+            self.toasts.lock().info("Export cancelled.");
+            return;
+        };
+        let app = self.clone();
+        let Some(rendered) = tokio::task::spawn_blocking(move || app.render_node(key, texture))
+            .await
+            .unwrap()
+        else {
+            self.toasts.lock().error(format!(
+                "{name} could not be exported, it is no longer loaded."
+            ));
+            return;
+        };
+        let scale = *self.export_scale.lock();
+        let result = rendered
+            .export(
+                &self.dev,
+                vec![handle.path().to_path_buf()],
+                ExportKind::Color,
+                scale,
+                None,
+                metadata,
+            )
+            .await;
+        match result {
+            Ok(()) => {
+                self.toasts
+                    .lock()
+                    .success(format!("{name} successfully exported."));
+            }
+            Err(err) => self.report_error(format!("{name} failed to export."), &err, None),
+        }
+    }
+
+    /// Copy the composite of a file to the clipboard as a bitmap, or only
+    /// the layer with the given UUID, cut to the crop selection.
+    pub async fn copy_image(
+        self: Arc<Self>,
+        key: InstanceKey,
+        layer: Option<String>,
+        crop: Option<Region>,
+    ) {
+        let what = if layer.is_some() {
+            "Layer"
+        } else {
+            "Composite"
+        };
+        let copied_texture = match layer {
+            Some(uuid) => {
+                let app = self.clone();
+                tokio::task::spawn_blocking(move || app.render_layer(key, &uuid))
+                    .await
+                    .unwrap()
+            }
+            None => self.copy_for_export(key, ExportKind::Color).await,
+        };
+        let Some(copied_texture) = copied_texture else {
+            self.toasts
+                .lock()
+                .error("The file was closed before it could be copied.");
+            return;
+        };
+
+        let region = crop
+            .and_then(|crop| crop.intersect(&copied_texture.bounds()))
+            .unwrap_or(copied_texture.bounds());
+        let mut image = copied_texture.read_rgba(&self.dev, region).await;
+        drop(copied_texture);
+        for pixel in image.pixels_mut() {
+            pixel.0 = color::unpremultiply(pixel.0);
+        }
+        let app = self.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            app.with_clipboard(|clipboard| {
+                clipboard.set_image(arboard::ImageData {
+                    width: image.width() as usize,
+                    height: image.height() as usize,
+                    bytes: image.into_raw().into(),
+                })
+            })
+        })
+        .await
+        .unwrap();
+        match result {
+            Ok(()) => {
+                self.toasts
+                    .lock()
+                    .success(format!("{what} copied to the clipboard."));
+            }
+            Err(err) => self.report_error(format!("{what} could not be copied."), &err, None),
+        }
+    }
+
+    /// Read back a clicked pixel of a composite as the sampled color and
+    /// copy it to the clipboard as hex.
+    pub async fn pick_color(self: Arc<Self>, readback: PixelReadback) {
+        // Composites are premultiplied.
+        let rgba = color::unpremultiply(readback.read(&self.dev).await);
+        *self.sampled_color.lock() = Some(rgba);
+        let hex = ColorFormat::Hex.format(rgba);
+        self.toasts.lock().info(format!("Copied {hex}."));
+        *self.clipboard.lock() = Some(hex);
+    }
+
+    /// Find the topmost visible layer with a pixel at a point of the
+    /// composite, and select it.
+    pub async fn pick_layer(self: Arc<Self>, key: InstanceKey, pixel: (u32, u32)) {
+        let staged = {
+            let instances = self.compositor.instances.read();
+            let Some(instance) = instances.get(&key) else {
+                return;
+            };
+            let file = instance.file.read();
+            let point =
+                instance
+                    .target
+                    .lock()
+                    .data
+                    .layer_pixel(pixel, file.size.width, file.size.height);
+            let textures = instance.textures.read();
+            let (Some(textures), Some(point)) = (textures.as_ref(), point) else {
+                return;
+            };
+            selection::pickable_layers(&file.layers)
+                .into_iter()
+                .filter_map(|layer| {
+                    let readback = textures.stage_pixel(&self.dev, point, layer.image)?;
+                    Some((layer.uuid.clone(), readback))
+                })
+                .collect::<Vec<_>>()
+        };
+        for (uuid, readback) in staged {
+            if readback.read(&self.dev).await[3] > 0 {
+                *self.picked_layer.lock() = Some((key, uuid));
+                return;
+            }
+        }
+        self.toasts.lock().info("No layer has any pixels there.");
+    }
+
+    /// Read back the pixel of a composite under the pointer.
+    pub async fn hover_color(self: Arc<Self>, pixel: (u32, u32), readback: PixelReadback) {
+        let rgba = color::unpremultiply(readback.read(&self.dev).await);
+        *self.hovered_color.lock() = Some(PixelColor { pixel, rgba });
+        self.reading_hover.store(false, Release);
+    }
+
+    /// Compute the color statistics of a copy of an instance's composite.
+    pub async fn analyze(
+        self: Arc<Self>,
+        key: InstanceKey,
+        generation: usize,
+        texture: TiledTexture,
+    ) {
+        // Allow another analysis even if this one panics.
+        let _analyzing = ClearOnDrop(&self.analyzing);
+        let image = texture.read_rgba(&self.dev, texture.bounds()).await;
+        drop(texture);
+        let stats = tokio::task::spawn_blocking(move || ColorStats::compute(&image))
+            .await
             .unwrap();
+        *self.analysis.lock() = Some((key, generation, stats));
+    }
+
+    /// Watch the open files for changes on disk, such as a file synced again
+    /// from another device, and offer to reload the files that changed.
+    pub async fn watch_files(self: Arc<App>) {
+        const POLL_INTERVAL: Duration = Duration::from_secs(1);
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            poll.tick().await;
+            let paths = self
+                .compositor
+                .instances
+                .read()
+                .iter()
+                .map(|(key, instance)| (*key, instance.path.clone()))
+                .collect::<Vec<_>>();
+            // Not read under the lock, which the GUI takes every frame.
+            let modified = paths
+                .into_iter()
+                .filter_map(|(key, path)| Some((key, Instance::modified_on_disk(&path)?)))
+                .collect::<Vec<_>>();
+            let instances = self.compositor.instances.read();
+            for (key, modified) in modified {
+                let Some(instance) = instances.get(&key) else {
+                    continue;
+                };
+                if *instance.modified.lock() == Some(modified) {
+                    continue;
+                }
+                let mut changed = instance.changed_on_disk.lock();
+                if changed.is_none() {
+                    self.toasts.lock().info(format!(
+                        "{} changed on disk and can be reloaded from its tab.",
+                        instance.path.display()
+                    ));
+                }
+                *changed = Some(modified);
+            }
+        }
+    }
+
+    /// Check the structure of an instance's file on disk, see
+    /// [`ProcreateFile::inspect`].
+    pub async fn inspect(self: Arc<Self>, key: InstanceKey, content_hash: u64, path: PathBuf) {
+        let report = tokio::task::spawn_blocking(move || ProcreateFile::inspect(&path))
+            .await
+            .unwrap()
+            .map_err(|err| err.to_string());
+        *self.inspection.lock() = Some((key, content_hash, report));
+        self.inspecting.store(false, Release);
+    }
+
+    /// Composite with the shader at the given path instead of the built in
+    /// one, and reload it whenever the file changes. Shaders that fail to
+    /// validate are reported and leave the last working shader in place.
+    pub async fn watch_shader(self: Arc<App>, path: PathBuf) {
+        const POLL_INTERVAL: Duration = Duration::from_millis(500);
+        let name = path.display().to_string();
+        let mut last_modified = None;
+        let mut missing = false;
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            poll.tick().await;
+            let modified = match std::fs::metadata(&path).and_then(|m| m.modified()) {
+                Ok(modified) => modified,
+                Err(err) => {
+                    // Only report the file going missing once.
+                    if !missing {
+                        missing = true;
+                        self.report_error(format!("Shader {name} cannot be read."), &err, None);
+                    }
+                    last_modified = None;
+                    continue;
+                }
+            };
+            missing = false;
+            if last_modified == Some(modified) {
+                continue;
+            }
+            last_modified = Some(modified);
+
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(err) => {
+                    self.report_error(format!("Shader {name} cannot be read."), &err, None);
+                    continue;
+                }
+            };
+            match self
+                .compositor
+                .pipeline
+                .reload_shader(&self.dev, &source)
+                .await
+            {
+                Ok(()) => {
+                    self.toasts.lock().success(format!("Shader {name} loaded."));
+                    self.invalidate_all();
+                }
+                Err(err) => {
+                    self.report_error(format!("Shader {name} failed to validate."), &err, None)
+                }
+            }
+        }
+    }
+
+    pub async fn rendering_thread(self: Arc<App>) {
+        // Version of the snapshot last rendered for each instance, and the
+        // resolution its composites are rendered at.
+        let mut rendered = HashMap::<_, (Option<u64>, AdaptiveQuality)>::new();
+        let mut limiter = tokio::time::interval(Duration::from_secs(1).div_f64(f64::from(60)));
+        limiter.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            // Ensures that we are not generating frames faster than 60FPS
+            // to avoid putting unnecessary computational pressure on the GPU.
+            limiter.tick().await;
+
+            let adaptive = self.adaptive_quality.load(Acquire);
+            let instances = self.compositor.instances.read();
+            rendered.retain(|key, _| instances.contains_key(key));
+            for (key, instance) in instances.iter() {
+                let (version, quality) = rendered.entry(*key).or_default();
+                // Edits made while a slider is dragged are coalesced.
+                let dragging = instance.display().dragging;
+                if !instance.commands.is_empty() && (!dragging || quality.drag(Instant::now())) {
+                    instance.apply_commands();
+                }
+                // Only force a recompute if we need to.
+                let snapshot = instance.snapshot.load_full();
+                let released = !dragging && quality.release();
+                let restore = if adaptive {
+                    released || quality.should_restore(Instant::now())
+                } else {
+                    instance.target.lock().downscale() > 1
+                };
+                if *version == Some(snapshot.version) && !restore {
+                    continue;
+                }
+                *version = Some(snapshot.version);
+                if restore {
+                    quality.restore();
+                }
+                let downscale = if adaptive { quality.downscale() } else { 1 };
+                let rescaled = instance.target.lock().set_downscale(downscale);
+
+                let paper = self.paper.lock();
+                let lut = self.lut.lock();
+                let finish = CompositeFinish {
+                    tone_map: *self.tone_map.lock(),
+                    adjustments: Some(*instance.adjustments.lock()).filter(Adjustments::is_active),
+                    paper: paper.composite_paper(),
+                    lut: lut.preview_lut(),
+                };
+                let timer = self
+                    .compositor
+                    .timer
+                    .as_ref()
+                    .filter(|timer| timer.begin(&self.dev));
+                let start = Instant::now();
+                let replaced = instance.render(
+                    &self.compositor.pipeline,
+                    &snapshot.groups,
+                    &snapshot.layers,
+                    snapshot.background,
+                    finish,
+                );
+                if let Some(timer) = timer {
+                    timer.end(&self.dev);
+                }
+                if adaptive {
+                    // Wait for the GPU, so that the time covers the
+                    // composite rather than only its submission.
+                    self.dev.device.poll(wgpu::Maintain::Wait);
+                    quality.record(start.elapsed(), downscale, Instant::now());
+                }
+                self.recomposites.fetch_add(1, Release);
+                *self.linearize_time.lock() = snapshot.linearize_time;
+                instance.generation.fetch_add(1, Release);
+                // Flipping, rotating or changing the resolution can retile
+                // the output, the GUI would otherwise keep showing the old
+                // textures.
+                if replaced || rescaled {
+                    self.rebind_texture(*key);
+                }
+                // ENABLE TO DEBUG: hold the lock to make sure the GUI is responsive
+                // std::thread::sleep(std::time::Duration::from_secs(1));
+                // Debugging notes: if the GPU is highly contended, the main
+                // GUI rendering can still be somewhat sluggish.
+                drop(lut);
+                drop(paper);
+            }
+            if self.previews_wanted.load(Acquire) {
+                for (key, instance) in instances.iter() {
+                    self.update_preview(*key, instance);
+                }
+            }
+        }
+    }
+
+    /// Longest side of the previews in the tab switcher, in pixels.
+    const PREVIEW_EDGE: u32 = 256;
+
+    /// Shrink the composite of a file into its preview, unless the preview
+    /// is already of the latest composite.
+    fn update_preview(&self, key: InstanceKey, instance: &Instance) {
+        let generation = instance.generation.load(Acquire);
+        let mut preview = instance.preview.lock();
+        if generation == 0
+            || preview
+                .as_ref()
+                .is_some_and(|(made, _)| *made == generation)
+        {
+            return;
+        }
+        let target = instance.target.lock();
+        let Some(output) = target.output.as_ref() else {
+            return;
+        };
+        let texture =
+            self.compositor
+                .post
+                .thumbnail(&self.dev, &output.texture, Self::PREVIEW_EDGE);
+        *preview = Some((generation, texture));
+        drop(preview);
+        self.event_loop
+            .send_event(UserEvent::BindPreview(key))
+            .unwrap();
+    }
+
+    /// Resolve the layers of a file to be composited, including the
+    /// document's background image if it is shown. Isolated groups are
+    /// returned in the order that they need to be composited.
+    pub(crate) fn resolve_layers(
+        file: &ProcreateFile,
+    ) -> (Vec<CompositeGroup>, Vec<CompositeLayer>) {
+        let mut groups = Vec::new();
+        let mut resolved_layers = Self::linearize_silica_layers(&file.layers, &mut groups);
+        if let Some(texture) = file
+            .background_image
+            .filter(|_| !file.background_image_hidden)
+        {
+            // Composite the document's background image beneath the layer stack.
+            resolved_layers.insert(
+                0,
+                CompositeLayer {
+                    texture,
+                    clipped: None,
+                    opacity: 1.0,
+                    blend: BlendMode::Normal,
+                },
+            );
+        }
+        (groups, resolved_layers)
+    }
+
+    /// Resolve the layers to composite a single layer or group on its own,
+    /// `None` if no layer or group has the texture. It is drawn even if
+    /// hidden, and unclipped since nothing beneath it is included.
+    pub(crate) fn resolve_node(
+        layers: &SilicaGroup,
+        texture: u32,
+    ) -> Option<(Vec<CompositeGroup>, Vec<CompositeLayer>)> {
+        let mut node = layers.iter().find(|node| node.image() == texture)?.clone();
+        node.set_hidden(false);
+        if let SilicaHierarchy::Layer(layer) = &mut node {
+            layer.clipped = false;
+        }
+        let root = SilicaGroup {
+            blend: BlendingMode::Normal,
+            hidden: false,
+            name: None,
+            children: vec![node],
+            opacity: 1.0,
+            image: u32::MAX,
+        };
+        let mut groups = Vec::new();
+        let layers = Self::linearize_silica_layers(&root, &mut groups);
+        Some((groups, layers))
+    }
+
+    /// Transform tree structure of layers into a linear list of
+    /// layers for rendering. Groups that need to be composited on their
+    /// own are pushed onto `groups` and composited as a single layer.
+    ///
+    /// Clipped layers follow Procreate: they clip to the closest unclipped
+    /// layer beneath them and are hidden along with it. Above a group, that
+    /// is the group itself, or its top layer when the group is not isolated.
+    /// A clipped layer with nothing beneath it in an isolated group has
+    /// nothing to clip to and is not drawn.
+    fn linearize_silica_layers(
+        layers: &silica::SilicaGroup,
+        groups: &mut Vec<CompositeGroup>,
+    ) -> Vec<CompositeLayer> {
+        /// `base` is the texture that clipped layers are clipped to, `None`
+        /// when they are not drawn.
+        fn inner(
+            layers: &silica::SilicaGroup,
+            composite_layers: &mut Vec<CompositeLayer>,
+            groups: &mut Vec<CompositeGroup>,
+            base: &mut Option<u32>,
+        ) {
+            for layer in layers.children.iter().rev() {
+                match layer {
+                    SilicaHierarchy::Group(group) if group.hidden => *base = None,
+                    SilicaHierarchy::Group(group) if group.is_isolated() => {
+                        let layers = App::linearize_silica_layers(group, groups);
+                        groups.push(CompositeGroup {
+                            texture: group.image,
+                            layers,
+                        });
+                        composite_layers.push(CompositeLayer {
+                            texture: group.image,
+                            clipped: None,
+                            opacity: group.opacity,
+                            blend: group.blend.into(),
+                        });
+                        *base = Some(group.image);
+                    }
+                    SilicaHierarchy::Group(group) => {
+                        inner(group, composite_layers, groups, base);
+                    }
+                    SilicaHierarchy::Layer(layer) if layer.clipped => {
+                        if let Some(base) = base.filter(|_| !layer.hidden) {
+                            composite_layers.push(CompositeLayer {
+                                texture: layer.image,
+                                clipped: Some(base),
+                                opacity: layer.opacity,
+                                blend: layer.blend.into(),
+                            });
+                        }
+                    }
+                    SilicaHierarchy::Layer(layer) if layer.hidden => *base = None,
+                    SilicaHierarchy::Layer(layer) => {
+                        composite_layers.push(CompositeLayer {
+                            texture: layer.image,
+                            clipped: None,
+                            opacity: layer.opacity,
+                            blend: layer.blend.into(),
+                        });
+                        *base = Some(layer.image);
+                    }
+                }
+            }
+        }
+
+        let mut composite_layers = Vec::new();
+        inner(layers, &mut composite_layers, groups, &mut None);
+        composite_layers
+    }
+
+    pub fn rebind_texture(&self, id: InstanceKey) {
+        self.event_loop
+            .send_event(UserEvent::RebindTexture(id))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{App, DifferenceSource, Instance, RenderSnapshot};
+    use compositor::{BlendMode, CompositeGroup, CompositeLayer};
+    use silica::fixture::{loaded_group, loaded_layer};
+    use silica::{BlendingMode, LayerProperties, SilicaGroup, SilicaHierarchy, SilicaLayer};
+
+    fn layer(image: u32, clipped: bool, hidden: bool) -> SilicaHierarchy {
+        SilicaHierarchy::Layer(SilicaLayer {
+            clipped,
+            hidden,
+            ..loaded_layer(image)
+        })
+    }
+
+    fn base(image: u32) -> SilicaHierarchy {
+        layer(image, false, false)
+    }
+
+    fn clipped(image: u32) -> SilicaHierarchy {
+        layer(image, true, false)
+    }
+
+    /// Group of layers listed from the bottom up, isolated if its opacity
+    /// is below 1.
+    fn group(
+        image: u32,
+        opacity: f32,
+        hidden: bool,
+        mut children: Vec<SilicaHierarchy>,
+    ) -> SilicaGroup {
+        // Documents list children from the top down.
+        children.reverse();
+        SilicaGroup {
+            hidden,
+            opacity,
+            ..loaded_group(image, children)
+        }
+    }
+
+    fn root(children: Vec<SilicaHierarchy>) -> SilicaGroup {
+        group(u32::MAX, 1.0, false, children)
+    }
+
+    fn nested(image: u32, opacity: f32, children: Vec<SilicaHierarchy>) -> SilicaHierarchy {
+        SilicaHierarchy::Group(group(image, opacity, false, children))
+    }
+
+    /// Texture and clipping base of each composited layer, from the bottom up.
+    type Layers = Vec<(u32, Option<u32>)>;
+
+    /// Layers of the root, and of each isolated group.
+    fn linearize(root: &SilicaGroup) -> (Layers, Vec<(u32, Layers)>) {
+        let simplify = |layers: &[compositor::CompositeLayer]| {
+            layers
+                .iter()
+                .map(|layer| (layer.texture, layer.clipped))
+                .collect::<Layers>()
+        };
+        let mut groups = Vec::new();
+        let layers = App::linearize_silica_layers(root, &mut groups);
+        let groups = groups
+            .iter()
+            .map(|group| (group.texture, simplify(&group.layers)))
+            .collect();
+        (simplify(&layers), groups)
+    }
+
+    #[test]
+    fn previewed_blend_is_swapped_in_and_back() {
+        let mut doc = root(vec![base(0), nested(10, 1.0, vec![base(1)])]);
+        let blend_of = |doc: &SilicaGroup, texture| {
+            doc.iter()
+                .find(|node| node.image() == texture)
+                .map(|node| LayerProperties::of_node(node).blend)
+        };
+        let original = Instance::swap_blend(&mut doc, 10, BlendingMode::Multiply);
+        assert_eq!(original, Some(BlendingMode::Normal));
+        assert_eq!(blend_of(&doc, 10), Some(BlendingMode::Multiply));
+        assert_eq!(blend_of(&doc, 1), Some(BlendingMode::Normal));
+        Instance::swap_blend(&mut doc, 10, original.unwrap());
+        assert_eq!(blend_of(&doc, 10), Some(BlendingMode::Normal));
+        assert_eq!(
+            Instance::swap_blend(&mut doc, 4, BlendingMode::Screen),
+            None
+        );
+    }
+
+    #[test]
+    fn differences_replace_or_cover_the_composite() {
+        let doc = root(vec![base(0), nested(10, 0.5, vec![base(1)]), base(2)]);
+        let snapshot = || {
+            let mut groups = Vec::new();
+            let layers = App::linearize_silica_layers(&doc, &mut groups);
+            RenderSnapshot {
+                version: 0,
+                groups,
+                layers,
+                background: None,
+                linearize_time: Default::default(),
+                display: Default::default(),
+            }
+        };
+        let blends = |snapshot: &RenderSnapshot| {
+            snapshot
+                .layers
+                .iter()
+                .map(|layer| (layer.texture, layer.blend))
+                .collect::<Vec<_>>()
+        };
+
+        let mut stored = snapshot();
+        stored.highlight_difference(DifferenceSource::StoredComposite, Some(3));
+        assert_eq!(stored.layers.len(), 4);
+        assert_eq!(blends(&stored)[3], (3, BlendMode::Difference));
+        let mut reference = snapshot();
+        reference.show_stored(3);
+        assert!(reference.groups.is_empty());
+        assert_eq!(blends(&reference), [(3, BlendMode::Normal)]);
+
+        let mut missing = snapshot();
+        missing.highlight_difference(DifferenceSource::StoredComposite, None);
+        assert_eq!(missing.layers.len(), 3);
+
+        let mut pair = snapshot();
+        pair.highlight_difference(DifferenceSource::Layers(1, 2), Some(3));
+        assert!(pair.groups.is_empty());
+        assert_eq!(
+            blends(&pair),
+            [(1, BlendMode::Normal), (2, BlendMode::Difference)]
+        );
+        assert_eq!(pair.background, Some([0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn exported_nodes_are_shown_and_unclipped_on_their_own() {
+        let doc = root(vec![
+            base(0),
+            layer(1, true, true),
+            SilicaHierarchy::Group(group(10, 0.5, true, vec![base(2), clipped(3)])),
+        ]);
+        let simplify = |(groups, layers): (Vec<CompositeGroup>, Vec<CompositeLayer>)| {
+            (
+                groups.iter().map(|group| group.texture).collect::<Vec<_>>(),
+                layers
+                    .iter()
+                    .map(|layer| (layer.texture, layer.clipped))
+                    .collect::<Layers>(),
+            )
+        };
+        assert_eq!(
+            simplify(App::resolve_node(&doc, 1).unwrap()),
+            (vec![], vec![(1, None)])
+        );
+        assert_eq!(
+            simplify(App::resolve_node(&doc, 10).unwrap()),
+            (vec![10], vec![(10, None)])
+        );
+        assert!(App::resolve_node(&doc, 4).is_none());
+    }
+
+    #[test]
+    fn clipped_layers_share_the_base_beneath_them() {
+        let doc = root(vec![base(0), clipped(1), clipped(2), base(3), clipped(4)]);
+        assert_eq!(
+            linearize(&doc).0,
+            [
+                (0, None),
+                (1, Some(0)),
+                (2, Some(0)),
+                (3, None),
+                (4, Some(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn hidden_clip_base_hides_its_clipped_layers() {
+        let doc = root(vec![
+            base(0),
+            layer(1, false, true),
+            clipped(2),
+            clipped(3),
+            base(4),
+        ]);
+        assert_eq!(linearize(&doc).0, [(0, None), (4, None)]);
+    }
+
+    #[test]
+    fn hidden_clipped_layer_keeps_the_chain() {
+        let doc = root(vec![base(0), layer(1, true, true), clipped(2)]);
+        assert_eq!(linearize(&doc).0, [(0, None), (2, Some(0))]);
+    }
+
+    #[test]
+    fn clipped_layer_above_a_group_clips_to_its_top_layer() {
+        let doc = root(vec![
+            base(0),
+            nested(10, 1.0, vec![base(1), base(2)]),
+            clipped(3),
+        ]);
+        assert_eq!(
+            linearize(&doc).0,
+            [(0, None), (1, None), (2, None), (3, Some(2))]
+        );
+    }
+
+    #[test]
+    fn clipped_layer_at_the_bottom_of_a_group_clips_beneath_it() {
+        let doc = root(vec![base(0), nested(10, 1.0, vec![clipped(1)])]);
+        assert_eq!(linearize(&doc).0, [(0, None), (1, Some(0))]);
+    }
+
+    #[test]
+    fn isolated_group_is_a_clip_base_and_a_boundary() {
+        let doc = root(vec![
+            base(0),
+            nested(10, 0.5, vec![clipped(1), base(2)]),
+            clipped(3),
+        ]);
+        let (layers, groups) = linearize(&doc);
+        assert_eq!(layers, [(0, None), (10, None), (3, Some(10))]);
+        assert_eq!(groups, [(10, vec![(2, None)])]);
+    }
+
+    #[test]
+    fn clipped_layer_above_an_inner_isolated_group_clips_to_it() {
+        let doc = root(vec![
+            base(0),
+            nested(
+                10,
+                0.5,
+                vec![base(1), nested(11, 0.5, vec![base(2)]), clipped(3)],
+            ),
+            clipped(4),
+        ]);
+        let (layers, groups) = linearize(&doc);
+        assert_eq!(layers, [(0, None), (10, None), (4, Some(10))]);
+        assert_eq!(
+            groups,
+            [
+                (11, vec![(2, None)]),
+                (10, vec![(1, None), (11, None), (3, Some(11))])
+            ]
+        );
+    }
+
+    #[test]
+    fn hidden_group_hides_clipped_layers_above_it() {
+        let doc = root(vec![
+            base(0),
+            SilicaHierarchy::Group(group(10, 1.0, true, vec![base(1)])),
+            clipped(2),
+        ]);
+        assert_eq!(linearize(&doc).0, [(0, None)]);
+    }
+
+    #[test]
+    fn nested_groups_resolve_clipping_at_each_level() {
+        let doc = root(vec![
+            base(0),
+            nested(
+                10,
+                1.0,
+                vec![nested(11, 0.5, vec![base(1), clipped(2)]), clipped(3)],
+            ),
+            clipped(4),
+        ]);
+        let (layers, groups) = linearize(&doc);
+        assert_eq!(
+            layers,
+            [(0, None), (11, None), (3, Some(11)), (4, Some(11))]
+        );
+        assert_eq!(groups, [(11, vec![(1, None), (2, Some(1))])]);
+    }
+
+    #[test]
+    fn guide_cells_cover_the_image() {
+        for (width, height, cell) in [(1000, 500, 300), (600, 600, 200), (1, 1, 64)] {
+            assert_eq!(
+                App::guide_cell_count(width, height, cell),
+                compositor::tex::Region::split(width, height, cell, 1).len()
+            );
+        }
+        assert_eq!(App::guide_cell_count(1000, 500, 300), 8);
     }
 }
@@ -0,0 +1,235 @@
+use super::app::{App, InstanceKey, UserEvent};
+use super::canvas::region_rect;
+use compositor::dev::GpuHandle;
+use compositor::tex::TiledTexture;
+use egui::*;
+use egui_wgpu::Renderer;
+use silica::{ProcreateFile, SilicaLayer};
+use std::sync::Arc;
+
+/// Developer view of the layer atlas of a file, the texture array holding
+/// every layer, showing one of its pages at a time.
+#[derive(Debug)]
+pub struct AtlasInspector {
+    /// Page of the atlas to show.
+    pub page: u32,
+    /// Fill the tiles stored for the layer on the page.
+    pub highlight: bool,
+    /// File and page that the bound textures show.
+    bound: Option<(InstanceKey, u32)>,
+    /// File and page that textures were last requested for.
+    requested: Option<(InstanceKey, u32)>,
+    /// Textures of the page and the part of the canvas that each one covers.
+    tiles: Vec<(Rect, TextureId)>,
+}
+
+impl Default for AtlasInspector {
+    fn default() -> Self {
+        Self {
+            page: 0,
+            highlight: true,
+            bound: None,
+            requested: None,
+            tiles: Vec::new(),
+        }
+    }
+}
+
+/// Layer whose tiles are stored on a page of the atlas.
+fn page_layer(file: &ProcreateFile, page: u32) -> Option<&SilicaLayer> {
+    file.layers
+        .iter_layers()
+        .chain(file.composite.as_ref())
+        .find(|layer| layer.image == page)
+}
+
+/// Description of what a page of the atlas holds.
+fn page_contents(file: &ProcreateFile, page: u32) -> String {
+    let name = |name: Option<&str>| name.unwrap_or("Unnamed").to_string();
+    if file.composite.as_ref().is_some_and(|c| c.image == page) {
+        "Composite".to_string()
+    } else if let Some(layer) = page_layer(file, page) {
+        format!("Layer {}", name(layer.name.as_deref()))
+    } else if file.background_image == Some(page) {
+        "Background Image".to_string()
+    } else if let Some(group) = file.layers.iter_groups().find(|g| g.image == page) {
+        format!("Group {}", name(group.name.as_deref()))
+    } else {
+        "Unused".to_string()
+    }
+}
+
+impl AtlasInspector {
+    /// Bind the textures of a page of the atlas for the GUI to draw,
+    /// replacing the ones bound before.
+    pub fn bind(
+        &mut self,
+        renderer: &mut Renderer,
+        dev: &GpuHandle,
+        textures: Option<&TiledTexture>,
+        key: InstanceKey,
+        page: u32,
+    ) {
+        for (_, id) in self.tiles.drain(..) {
+            renderer.free_texture(&id);
+        }
+        if let Some(textures) = textures {
+            self.tiles = textures
+                .tiles
+                .iter()
+                .filter(|(_, texture)| page < texture.layers())
+                .map(|(region, texture)| {
+                    let id = renderer.register_native_texture(
+                        &dev.device,
+                        &texture.create_view_layer(page),
+                        wgpu::FilterMode::Nearest,
+                    );
+                    (region_rect(*region), id)
+                })
+                .collect();
+        }
+        self.bound = Some((key, page));
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, app: &Arc<App>, key: InstanceKey) {
+        let instances = app.compositor.instances.read();
+        let Some(instance) = instances.get(&key) else {
+            ui.label("No file loaded...");
+            return;
+        };
+        let textures = instance.textures.read();
+        let Some(textures) = textures.as_ref() else {
+            ui.label("The atlas is evicted to stay within the GPU memory budget.");
+            return;
+        };
+        let file = instance.file.read();
+        let pages = textures.tiles.first().map_or(1, |(_, t)| t.layers());
+        self.page = self.page.min(pages - 1);
+        let layer = page_layer(&file, self.page);
+        let columns = file.size.width.div_ceil(file.tile_size);
+        let rows = file.size.height.div_ceil(file.tile_size);
+
+        Grid::new("Atlas Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Size");
+            ui.label(format!(
+                "{} by {}, {} pages",
+                textures.width, textures.height, pages
+            ));
+            ui.end_row();
+            ui.label("Textures").on_hover_text(
+                "Canvases larger than the device allows a texture to be are split along tiles.",
+            );
+            ui.label(textures.tiles.len().to_string());
+            ui.end_row();
+            ui.label("Tiles");
+            ui.label(format!("{columns} by {rows} of {} px", file.tile_size));
+            ui.end_row();
+            ui.label("Page");
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(&mut self.page).clamp_range(0..=pages - 1));
+                if ui.button("Reload").clicked() {
+                    self.bound = None;
+                    self.requested = None;
+                }
+                if ui.button("Dump").clicked() {
+                    app.rt.spawn(app.clone().dump_atlas_dialog(key, self.page));
+                }
+            });
+            ui.end_row();
+            ui.label("Contents");
+            ui.label(page_contents(&file, self.page));
+            ui.end_row();
+            if let Some(layer) = layer {
+                ui.label("Stored Tiles");
+                ui.label(format!("{} of {}", layer.chunks.len(), columns * rows));
+                ui.end_row();
+                ui.label("Highlight Tiles");
+                ui.checkbox(&mut self.highlight, "Enable");
+                ui.end_row();
+            }
+        });
+        ui.separator();
+
+        let wanted = Some((key, self.page));
+        if self.bound != wanted && self.requested != wanted {
+            self.requested = wanted;
+            app.event_loop
+                .send_event(UserEvent::BindAtlasPage(key, self.page))
+                .unwrap();
+        }
+
+        let size = vec2(textures.width as f32, textures.height as f32);
+        let scale = (ui.available_width() / size.x).min(1.0);
+        let (rect, response) = ui.allocate_exact_size(size * scale, Sense::hover());
+        let painter = ui.painter_at(rect);
+        let to_screen = |canvas: Rect| {
+            Rect::from_min_size(
+                rect.min + canvas.min.to_vec2() * scale,
+                canvas.size() * scale,
+            )
+        };
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        if self.bound == wanted {
+            for (tile, id) in &self.tiles {
+                let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+                painter.image(*id, to_screen(*tile), uv, Color32::WHITE);
+            }
+        }
+
+        // Tiles at the right and bottom edges are cut to the canvas.
+        let tile_rect = |(col, row): (u32, u32)| {
+            let side = file.tile_size as f32;
+            let min = pos2(col as f32 * side, row as f32 * side);
+            Rect::from_min_max(min, (min + Vec2::splat(side)).min(size.to_pos2()))
+        };
+        let accent = ui.visuals().selection.bg_fill;
+        if let Some(layer) = layer.filter(|_| self.highlight) {
+            for &chunk in &layer.chunks {
+                painter.rect_filled(to_screen(tile_rect(chunk)), 0.0, accent.gamma_multiply(0.3));
+            }
+        }
+        let grid = Stroke::new(1.0, ui.visuals().weak_text_color().gamma_multiply(0.5));
+        for col in 1..columns {
+            let x = rect.min.x + (col * file.tile_size) as f32 * scale;
+            painter.vline(x, rect.y_range(), grid);
+        }
+        for row in 1..rows {
+            let y = rect.min.y + (row * file.tile_size) as f32 * scale;
+            painter.hline(rect.x_range(), y, grid);
+        }
+        for (tile, _) in textures.tiles.iter().skip(1) {
+            painter.rect_stroke(to_screen(region_rect(*tile)), 0.0, Stroke::new(2.0, accent));
+        }
+
+        if let Some(pointer) = response.hover_pos() {
+            let canvas = (pointer - rect.min) / scale;
+            let position = (
+                (canvas.x as u32 / file.tile_size).min(columns - 1),
+                (canvas.y as u32 / file.tile_size).min(rows - 1),
+            );
+            painter.rect_stroke(
+                to_screen(tile_rect(position)),
+                0.0,
+                Stroke::new(1.0, ui.visuals().strong_text_color()),
+            );
+            response.on_hover_ui_at_pointer(|ui| {
+                ui.label(format!("Tile {}, {}", position.0, position.1));
+                let stored = file
+                    .layers
+                    .iter_layers()
+                    .filter(|layer| layer.chunks.contains(&position))
+                    .collect::<Vec<_>>();
+                if stored.is_empty() {
+                    ui.label("No layer stores this tile.");
+                }
+                for layer in stored {
+                    ui.label(format!(
+                        "Page {}: {}",
+                        layer.image,
+                        layer.name.as_deref().unwrap_or("Unnamed")
+                    ));
+                }
+            });
+        }
+    }
+}
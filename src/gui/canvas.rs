@@ -1,9 +1,384 @@
+use compositor::tex::Region;
 use egui::*;
+use std::ops::RangeInclusive;
+
+/// Composite of a canvas, which may be split across several textures.
+#[derive(Clone, Debug, Default)]
+pub struct CanvasImage {
+    /// Size of the whole canvas.
+    pub size: Vec2,
+    /// Textures and the part of the canvas that each one covers, in pixels.
+    pub tiles: Vec<(Rect, TextureId)>,
+}
+
+/// Rectangle of the canvas that a region of its textures covers.
+pub fn region_rect(region: Region) -> Rect {
+    Rect::from_min_size(
+        pos2(region.x as f32, region.y as f32),
+        vec2(region.width as f32, region.height as f32),
+    )
+}
+
+/// Region of whole pixels that a rectangle of the canvas covers.
+pub fn rect_region(rect: Rect) -> Region {
+    Region {
+        x: rect.min.x as u32,
+        y: rect.min.y as u32,
+        width: rect.width() as u32,
+        height: rect.height() as u32,
+    }
+}
+
+/// How a second canvas is shown over the first to compare them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    /// Blend the second canvas over the first.
+    Overlay,
+    /// Show the second canvas right of a split that can be dragged.
+    Wipe,
+}
+
+impl CompareMode {
+    pub fn all() -> &'static [CompareMode] {
+        &[Self::Overlay, Self::Wipe]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Overlay => "Overlay",
+            Self::Wipe => "Wipe",
+        }
+    }
+}
+
+/// Second canvas drawn centered over the first.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub image: CanvasImage,
+    pub mode: CompareMode,
+    /// Opacity of the overlay, or position of the wipe split as a fraction
+    /// of the canvas width.
+    pub amount: f32,
+}
+
+/// Unit of the safe area margin of the guides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarginUnit {
+    Pixels,
+    /// Percent of the canvas width and height.
+    Percent,
+}
+
+impl MarginUnit {
+    pub fn all() -> &'static [MarginUnit] {
+        &[Self::Pixels, Self::Percent]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pixels => "px",
+            Self::Percent => "%",
+        }
+    }
+}
+
+/// Composition guides drawn over the canvas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Guides {
+    /// Lines through the center of the canvas.
+    pub center: bool,
+    /// Lines dividing the canvas into thirds.
+    pub thirds: bool,
+    /// Inset of the safe area from each edge, no safe area when zero.
+    pub margin: f32,
+    pub margin_unit: MarginUnit,
+}
+
+impl Default for Guides {
+    fn default() -> Self {
+        Self {
+            center: false,
+            thirds: false,
+            margin: 0.0,
+            margin_unit: MarginUnit::Percent,
+        }
+    }
+}
+
+impl Guides {
+    /// Guide lines across a canvas of the given size, in canvas pixels.
+    fn lines(&self, size: Vec2) -> Vec<[Pos2; 2]> {
+        let mut fractions = Vec::new();
+        if self.center {
+            fractions.push(0.5);
+        }
+        if self.thirds {
+            fractions.extend([1.0 / 3.0, 2.0 / 3.0]);
+        }
+        fractions
+            .into_iter()
+            .flat_map(|f| {
+                [
+                    [pos2(f * size.x, 0.0), pos2(f * size.x, size.y)],
+                    [pos2(0.0, f * size.y), pos2(size.x, f * size.y)],
+                ]
+            })
+            .collect()
+    }
+
+    /// Safe area of a canvas of the given size, in canvas pixels.
+    fn safe_area(&self, size: Vec2) -> Option<Rect> {
+        let inset = match self.margin_unit {
+            MarginUnit::Pixels => Vec2::splat(self.margin),
+            MarginUnit::Percent => size * self.margin / 100.0,
+        };
+        Some(Rect::from_min_max(
+            inset.to_pos2(),
+            (size - inset).to_pos2(),
+        ))
+        .filter(|area| self.margin > 0.0 && area.is_positive())
+    }
+}
+
+/// Angles that the view rotation snaps to when set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationSnap {
+    Off,
+    Degrees15,
+    Degrees45,
+    Degrees90,
+}
+
+impl RotationSnap {
+    pub fn all() -> &'static [RotationSnap] {
+        &[Self::Off, Self::Degrees15, Self::Degrees45, Self::Degrees90]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Degrees15 => "15°",
+            Self::Degrees45 => "45°",
+            Self::Degrees90 => "90°",
+        }
+    }
+
+    /// Snapping step in degrees.
+    pub fn step(&self) -> Option<f32> {
+        match self {
+            Self::Off => None,
+            Self::Degrees15 => Some(15.0),
+            Self::Degrees45 => Some(45.0),
+            Self::Degrees90 => Some(90.0),
+        }
+    }
+}
+
+/// What is drawn behind the transparent parts of a canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransparencyStyle {
+    /// The backdrop around the canvas shows through.
+    Backdrop,
+    Checkerboard,
+    Solid,
+}
+
+impl TransparencyStyle {
+    pub fn all() -> &'static [TransparencyStyle] {
+        &[Self::Backdrop, Self::Checkerboard, Self::Solid]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Backdrop => "Backdrop",
+            Self::Checkerboard => "Checkerboard",
+            Self::Solid => "Solid Color",
+        }
+    }
+}
+
+/// Pattern drawn behind the transparent parts of a canvas, such as when its
+/// background is hidden.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transparency {
+    pub style: TransparencyStyle,
+    /// Side of the checkerboard squares in points, whatever the zoom.
+    pub size: f32,
+    /// Colors of the checkerboard squares, the first of which is also the
+    /// solid color.
+    pub colors: [[u8; 3]; 2],
+}
+
+impl Default for Transparency {
+    fn default() -> Self {
+        Self {
+            style: TransparencyStyle::Backdrop,
+            size: 8.0,
+            colors: [[204, 204, 204], [255, 255, 255]],
+        }
+    }
+}
+
+impl Transparency {
+    pub const SIZES: RangeInclusive<f32> = 2.0..=64.0;
+
+    /// Add the pattern over a screen rectangle of the canvas to a mesh,
+    /// leaving out the squares outside of `visible`. The squares start from
+    /// the corner of the canvas, so that they move along with it.
+    fn add_to_mesh(&self, mesh: &mut Mesh, rect: Rect, visible: Rect) {
+        let color = |[r, g, b]: [u8; 3]| Color32::from_rgb(r, g, b);
+        match self.style {
+            TransparencyStyle::Backdrop => {}
+            TransparencyStyle::Solid => mesh.add_colored_rect(rect, color(self.colors[0])),
+            TransparencyStyle::Checkerboard => {
+                let Some(shown) = Some(rect.intersect(visible)).filter(Rect::is_positive) else {
+                    return;
+                };
+                let size = self.size.clamp(*Self::SIZES.start(), *Self::SIZES.end());
+                let first = ((shown.min - rect.min) / size).floor();
+                let last = ((shown.max - rect.min) / size).ceil();
+                for row in first.y as i64..last.y as i64 {
+                    for col in first.x as i64..last.x as i64 {
+                        let min = rect.min + vec2(col as f32, row as f32) * size;
+                        let square = Rect::from_min_size(min, Vec2::splat(size)).intersect(rect);
+                        mesh.add_colored_rect(
+                            square,
+                            color(self.colors[((row + col) % 2) as usize]),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Transform of a file's canvas tab on top of the pan and zoom kept by the
+/// canvas view. Unlike flipping the canvas itself, mirroring the view only
+/// previews the flip and leaves the document and its exports alone.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ViewTransform {
+    /// Clockwise rotation in radians, within `0.0..TAU`.
+    pub rotation: f32,
+    pub mirror_horizontally: bool,
+    pub mirror_vertically: bool,
+}
+
+impl ViewTransform {
+    pub fn degrees(&self) -> f32 {
+        self.rotation.to_degrees()
+    }
+
+    /// Set the rotation in degrees, rounded to the snapping step.
+    pub fn set_degrees(&mut self, degrees: f32, snap: RotationSnap) {
+        let degrees = match snap.step() {
+            Some(step) => (degrees / step).round() * step,
+            None => degrees,
+        };
+        self.rotation = degrees.to_radians().rem_euclid(std::f32::consts::TAU);
+    }
+
+    /// Rotate by a number of right angles, counterclockwise if negative.
+    pub fn rotate_quarters(&mut self, quarters: i32) {
+        self.set_degrees(self.degrees() + 90.0 * quarters as f32, RotationSnap::Off);
+    }
+
+    /// Snap the rotation to the nearest right angle.
+    pub fn snap_to_quarter(&mut self) {
+        self.set_degrees(self.degrees(), RotationSnap::Degrees90);
+    }
+
+    /// Pixel of a canvas of the given size shown at the place of a pixel,
+    /// or the other way around.
+    fn mirror(&self, pixel: Pos2, size: Vec2) -> Pos2 {
+        pos2(
+            if self.mirror_horizontally {
+                size.x - pixel.x
+            } else {
+                pixel.x
+            },
+            if self.mirror_vertically {
+                size.y - pixel.y
+            } else {
+                pixel.y
+            },
+        )
+    }
+
+    /// Part of a canvas of the given size shown at the place of a part.
+    fn mirror_rect(&self, part: Rect, size: Vec2) -> Rect {
+        Rect::from_two_pos(self.mirror(part.min, size), self.mirror(part.max, size))
+    }
+
+    /// Texture coordinates of a part shown at a mirrored place.
+    fn mirror_uv(&self, uv: Rect) -> Rect {
+        let mirror = |pos: Pos2| self.mirror(pos, Vec2::splat(1.0));
+        Rect::from_min_max(mirror(uv.min), mirror(uv.max))
+    }
+}
+
+/// What double clicking the canvas with a pointer button does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoubleClickAction {
+    None,
+    /// Fit the canvas with a margin and keep following its size.
+    ResetBounds,
+    /// Fit the canvas snugly to the view.
+    Fit,
+    /// Zoom to one canvas pixel per screen pixel around the pointer.
+    ActualSize,
+    /// Snap the view rotation to the nearest right angle.
+    SnapRotation,
+}
+
+impl std::fmt::Display for DoubleClickAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl DoubleClickAction {
+    pub fn all() -> &'static [DoubleClickAction] {
+        use DoubleClickAction::*;
+        &[None, ResetBounds, Fit, ActualSize, SnapRotation]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "Nothing",
+            Self::ResetBounds => "Reset Bounds",
+            Self::Fit => "Fit",
+            Self::ActualSize => "100% Zoom",
+            Self::SnapRotation => "Snap Rotation",
+        }
+    }
+}
+
+/// Double click action of each pointer button, indexed by [`PointerButton`].
+pub type DoubleClickActions = [DoubleClickAction; NUM_POINTER_BUTTONS];
+
+/// Double clicking with the primary button resets the bounds and with the
+/// middle button snaps the rotation.
+pub const DEFAULT_DOUBLE_CLICK_ACTIONS: DoubleClickActions = [
+    DoubleClickAction::ResetBounds,
+    DoubleClickAction::None,
+    DoubleClickAction::SnapRotation,
+    DoubleClickAction::None,
+    DoubleClickAction::None,
+];
+
+/// Pointer buttons in the order of their index.
+pub const POINTER_BUTTONS: [PointerButton; NUM_POINTER_BUTTONS] = [
+    PointerButton::Primary,
+    PointerButton::Secondary,
+    PointerButton::Middle,
+    PointerButton::Extra1,
+    PointerButton::Extra2,
+];
 
 /// 2D bounding box of f64 precision.
 /// The range of data values we show.
 #[derive(Clone, Copy, PartialEq, Debug)]
-struct CanvasViewBounds {
+pub struct CanvasViewBounds {
     pub(crate) min: [f32; 2],
     pub(crate) max: [f32; 2],
 }
@@ -195,6 +570,29 @@ impl ScreenTransform {
         Pos2::new(x, y)
     }
 
+    /// Screen position of a pixel of a canvas of the given size, measured
+    /// from the top left corner of the canvas, with the canvas mirrored and
+    /// rotated about its center.
+    pub fn position_from_pixel(&self, pixel: Pos2, size: Vec2, view: ViewTransform) -> Pos2 {
+        let pixel = view.mirror(pixel, size);
+        let center = self.position_from_point(&Vec2::ZERO);
+        let value = Vec2::new(pixel.x - size.x / 2.0, size.y / 2.0 - pixel.y);
+        center
+            + emath::Rot2::from_angle(view.rotation) * (self.position_from_point(&value) - center)
+    }
+
+    /// Pixel of a canvas of the given size under a screen position, the
+    /// inverse of [`Self::position_from_pixel`].
+    pub fn pixel_from_position(&self, pos: Pos2, size: Vec2, view: ViewTransform) -> Pos2 {
+        let center = self.position_from_point(&Vec2::ZERO);
+        let pos = center + emath::Rot2::from_angle(-view.rotation) * (pos - center);
+        let value = self.value_from_position(pos);
+        view.mirror(
+            Pos2::new(value.x + size.x / 2.0, size.y / 2.0 - value.y),
+            size,
+        )
+    }
+
     /// delta position / delta value
     pub fn dpos_dvalue_x(&self) -> f32 {
         self.frame.width() / self.bounds.width()
@@ -248,14 +646,25 @@ pub struct CanvasView {
     margin_fraction: Vec2,
     allow_boxed_zoom: bool,
     boxed_zoom_pointer_button: PointerButton,
+    double_click_actions: DoubleClickActions,
+    crop: Option<Rect>,
+    crop_tool: bool,
+    color_picker: bool,
+    compare: Option<Comparison>,
+    guides: Guides,
+    initial_bounds: Option<CanvasViewBounds>,
 
     data_aspect: Option<f32>,
     show_background: bool,
+    backdrop: Color32,
+    transparency: Transparency,
 
-    image: Option<Image<'static>>,
-    image_rotation: f32,
+    image: Option<CanvasImage>,
+    view: ViewTransform,
 
     show_grid: bool,
+    show_pixel_grid: bool,
+    show_rulers: bool,
     show_extended_crosshair: bool,
     show_bottom_bar: bool,
 }
@@ -290,6 +699,31 @@ struct ViewMemory {
     last_screen_transform: ScreenTransform,
     /// Allows to remember the first click position when performing a boxed zoom
     last_click_pos_for_zoom: Option<Pos2>,
+    /// Canvas pixel where the crop selection being dragged started.
+    crop_start: Option<Pos2>,
+    /// Whether the primary button is dragging the wipe split.
+    wipe_drag: bool,
+}
+
+/// What the user did with the view this frame.
+#[derive(Debug, Default)]
+pub struct CanvasViewOutput {
+    /// Double click action that was triggered.
+    pub double_click: Option<DoubleClickAction>,
+    /// Crop selection dragged out with the crop tool, in canvas pixels.
+    pub crop: Option<Rect>,
+    /// Canvas pixel under the pointer while the color picker is on.
+    pub hovered: Option<(u32, u32)>,
+    /// Canvas pixel clicked with the color picker.
+    pub picked: Option<(u32, u32)>,
+    /// Canvas pixel Alt-clicked to select the layer there.
+    pub picked_layer: Option<(u32, u32)>,
+    /// Wipe split dragged to a new position.
+    pub compare_split: Option<f32>,
+    /// Part of the canvas in view, `None` while the canvas is fitted.
+    pub bounds: Option<CanvasViewBounds>,
+    /// The view is being panned past the edge of the window.
+    pub drag_out: bool,
 }
 
 impl ViewMemory {
@@ -304,7 +738,7 @@ impl ViewMemory {
 
 impl CanvasView {
     /// Give a unique id for each plot within the same [`Ui`].
-    pub fn new(id_source: impl std::hash::Hash, image: Option<Image<'static>>) -> Self {
+    pub fn new(id_source: impl std::hash::Hash, image: Option<CanvasImage>) -> Self {
         Self {
             id_source: Id::new(id_source),
             allow_zoom: true,
@@ -313,24 +747,59 @@ impl CanvasView {
             margin_fraction: Vec2::splat(0.05),
             allow_boxed_zoom: true,
             boxed_zoom_pointer_button: PointerButton::Secondary,
+            double_click_actions: [DoubleClickAction::None; NUM_POINTER_BUTTONS],
+            crop: None,
+            crop_tool: false,
+            color_picker: false,
+            compare: None,
+            guides: Guides::default(),
+            initial_bounds: None,
             min_auto_bounds: CanvasViewBounds::NOTHING,
 
             show_grid: false,
+            show_pixel_grid: false,
+            show_rulers: false,
             show_extended_crosshair: false,
             show_bottom_bar: true,
 
             data_aspect: None,
             show_background: true,
+            backdrop: Color32::from_gray(20),
+            transparency: Transparency::default(),
             image,
-            image_rotation: 0.0,
+            view: ViewTransform::default(),
         }
     }
 
+    /// Color drawn behind the canvas.
+    pub fn backdrop(mut self, color: Color32) -> Self {
+        self.backdrop = color;
+        self
+    }
+
+    /// Pattern drawn behind the transparent parts of the canvas.
+    pub fn transparency(mut self, transparency: Transparency) -> Self {
+        self.transparency = transparency;
+        self
+    }
+
     pub fn show_grid(mut self, enable: bool) -> Self {
         self.show_grid = enable;
         self
     }
 
+    /// Outline every canvas pixel when zoomed in far enough.
+    pub fn show_pixel_grid(mut self, enable: bool) -> Self {
+        self.show_pixel_grid = enable;
+        self
+    }
+
+    /// Rulers along the top and left edges with canvas pixel coordinates.
+    pub fn show_rulers(mut self, enable: bool) -> Self {
+        self.show_rulers = enable;
+        self
+    }
+
     pub fn show_extended_crosshair(mut self, enable: bool) -> Self {
         self.show_extended_crosshair = enable;
         self
@@ -341,13 +810,58 @@ impl CanvasView {
         self
     }
 
-    pub fn with_rotation(mut self, rotation: f32) -> Self {
-        self.image_rotation = rotation;
+    /// Rotate and mirror the canvas.
+    pub fn view_transform(mut self, view: ViewTransform) -> Self {
+        self.view = view;
+        self
+    }
+
+    pub fn double_click_actions(mut self, actions: DoubleClickActions) -> Self {
+        self.double_click_actions = actions;
+        self
+    }
+
+    /// Show a crop selection, given in canvas pixels.
+    pub fn crop(mut self, crop: Option<Rect>) -> Self {
+        self.crop = crop;
+        self
+    }
+
+    /// Drag out a crop selection with the primary button instead of panning.
+    pub fn crop_tool(mut self, enable: bool) -> Self {
+        self.crop_tool = enable;
+        self
+    }
+
+    /// Pick the color of the canvas pixel clicked with the primary button.
+    pub fn color_picker(mut self, enable: bool) -> Self {
+        self.color_picker = enable;
+        self
+    }
+
+    /// Show a second canvas over this one.
+    pub fn compare(mut self, compare: Option<Comparison>) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Draw composition guides over the canvas.
+    pub fn guides(mut self, guides: Guides) -> Self {
+        self.guides = guides;
+        self
+    }
+
+    /// Part of the canvas to show when the view is first shown, instead
+    /// of fitting the canvas.
+    pub fn initial_bounds(mut self, bounds: Option<CanvasViewBounds>) -> Self {
+        self.initial_bounds = bounds;
         self
     }
 
     /// Interact with and add items to the plot and finally draw it.
-    pub fn show(self, ui: &mut Ui) -> InnerResponse<()> {
+    /// The view rotation and the crop selection are left to the caller,
+    /// so changes to them are returned.
+    pub fn show(self, ui: &mut Ui) -> InnerResponse<CanvasViewOutput> {
         let Self {
             id_source,
             allow_zoom,
@@ -355,14 +869,25 @@ impl CanvasView {
             allow_drag,
             allow_boxed_zoom,
             boxed_zoom_pointer_button: boxed_zoom_pointer,
+            double_click_actions,
+            crop,
+            crop_tool,
+            color_picker,
+            compare,
+            guides,
+            initial_bounds,
             min_auto_bounds,
             margin_fraction,
             data_aspect,
             show_background,
+            backdrop,
+            transparency,
             image,
-            image_rotation,
+            view,
             show_extended_crosshair,
             show_grid,
+            show_pixel_grid,
+            show_rulers,
             show_bottom_bar,
             ..
         } = self;
@@ -375,11 +900,17 @@ impl CanvasView {
         // Load or initialize the memory.
         let plot_id = ui.make_persistent_id(id_source);
         ui.ctx().check_for_id_clash(plot_id, rect, "Plot");
+        let initial_bounds = initial_bounds.filter(CanvasViewBounds::is_valid);
         let mut memory = ViewMemory::load(ui.ctx(), plot_id).unwrap_or_else(|| ViewMemory {
-            auto_bounds: (!min_auto_bounds.is_valid()).into(),
+            auto_bounds: (initial_bounds.is_none() && !min_auto_bounds.is_valid()).into(),
             min_auto_bounds,
-            last_screen_transform: ScreenTransform::new(rect, min_auto_bounds),
+            last_screen_transform: ScreenTransform::new(
+                rect,
+                initial_bounds.unwrap_or(min_auto_bounds),
+            ),
             last_click_pos_for_zoom: None,
+            crop_start: None,
+            wipe_drag: false,
         });
 
         // If the min bounds changed, recalculate everything.
@@ -396,6 +927,8 @@ impl CanvasView {
             mut auto_bounds,
             last_screen_transform,
             mut last_click_pos_for_zoom,
+            mut crop_start,
+            mut wipe_drag,
             ..
         } = memory;
 
@@ -414,8 +947,12 @@ impl CanvasView {
         // --- Bound computation ---
         let mut bounds = *last_screen_transform.bounds();
 
-        // Allow double clicking to reset to automatic bounds.
-        if response.double_clicked_by(PointerButton::Primary) {
+        let double_click = POINTER_BUTTONS
+            .into_iter()
+            .find(|button| response.double_clicked_by(*button))
+            .map(|button| double_click_actions[button as usize])
+            .filter(|action| *action != DoubleClickAction::None);
+        if double_click == Some(DoubleClickAction::ResetBounds) {
             auto_bounds = true.into();
         }
 
@@ -434,7 +971,7 @@ impl CanvasView {
             }
 
             if let Some(image) = image.as_ref() {
-                let image_size = image.size().unwrap();
+                let image_size = image.size;
                 let image_bounds = {
                     let mut bounds = CanvasViewBounds::NOTHING;
                     let left_top = Vec2::new(-image_size.x / 2.0, -image_size.y / 2.0);
@@ -466,18 +1003,132 @@ impl CanvasView {
         // Enforce aspect ratio
         transform.set_aspect_by_expanding(1.0);
 
+        match (double_click, image.as_ref()) {
+            (Some(DoubleClickAction::Fit), Some(image)) => {
+                let half_size = image.size / 2.0;
+                let mut image_bounds = CanvasViewBounds::NOTHING;
+                image_bounds.extend_with(&-half_size);
+                image_bounds.extend_with(&half_size);
+                transform.set_bounds(image_bounds);
+                transform.set_aspect_by_expanding(1.0);
+                auto_bounds = false.into();
+            }
+            (Some(DoubleClickAction::ActualSize), _) => {
+                if let Some(pointer) = response.hover_pos() {
+                    // One canvas pixel per physical pixel.
+                    let scale = 1.0 / (ui.ctx().pixels_per_point() * transform.dpos_dvalue_x());
+                    transform.zoom(Vec2::splat(scale), pointer);
+                    auto_bounds = false.into();
+                }
+            }
+            _ => {}
+        }
+
+        // Wipe splitting
+        let mut compare_split = None;
+        match (compare.as_ref(), image.as_ref()) {
+            (
+                Some(Comparison {
+                    mode: CompareMode::Wipe,
+                    amount,
+                    ..
+                }),
+                Some(image),
+            ) if !crop_tool => {
+                let pixel = |pos| transform.pixel_from_position(pos, image.size, view);
+                if response.drag_started_by(PointerButton::Primary) {
+                    // Only grab the split near its line, elsewhere the view pans.
+                    wipe_drag = response.interact_pointer_pos().is_some_and(|pos| {
+                        (pixel(pos).x - amount * image.size.x).abs() * transform.dpos_dvalue_x()
+                            < 8.0
+                    });
+                }
+                if wipe_drag && response.dragged_by(PointerButton::Primary) {
+                    compare_split = response
+                        .interact_pointer_pos()
+                        .map(|pos| (pixel(pos).x / image.size.x).clamp(0.0, 1.0));
+                }
+            }
+            _ => wipe_drag = false,
+        }
+        if response.drag_released_by(PointerButton::Primary) {
+            wipe_drag = false;
+        }
+
         // Dragging
-        if allow_drag && response.dragged_by(PointerButton::Primary) {
+        let mut drag_out = false;
+        if allow_drag && !crop_tool && !wipe_drag && response.dragged_by(PointerButton::Primary) {
             response = response.on_hover_cursor(CursorIcon::Grabbing);
             transform.translate_bounds(-response.drag_delta());
             auto_bounds = false.into();
+            drag_out = ui.input(|input| {
+                input
+                    .pointer
+                    .latest_pos()
+                    .is_none_or(|pos| !input.screen_rect().contains(pos))
+            });
+        }
+
+        // Cropping
+        let mut crop_output = None;
+        if let (true, Some(image)) = (crop_tool, image.as_ref()) {
+            let pixel = |pos| transform.pixel_from_position(pos, image.size, view);
+            if response.drag_started_by(PointerButton::Primary) {
+                crop_start = response.interact_pointer_pos().map(pixel);
+            }
+            if response.dragged_by(PointerButton::Primary) {
+                if let (Some(start), Some(end)) = (crop_start, response.interact_pointer_pos()) {
+                    let selection = Rect::from_two_pos(start, pixel(end))
+                        .intersect(Rect::from_min_size(Pos2::ZERO, image.size));
+                    let selection =
+                        Rect::from_min_max(selection.min.round(), selection.max.round());
+                    if selection.is_positive() {
+                        crop_output = Some(selection);
+                    }
+                }
+            }
+            if response.drag_released_by(PointerButton::Primary) {
+                crop_start = None;
+            }
+        }
+
+        // Color and layer picking
+        let (mut hovered, mut picked, mut picked_layer) = (None, None, None);
+        if let Some(image) = image.as_ref() {
+            let pixel = |pos| {
+                let pixel = transform.pixel_from_position(pos, image.size, view);
+                ((0.0..image.size.x).contains(&pixel.x) && (0.0..image.size.y).contains(&pixel.y))
+                    .then_some((pixel.x as u32, pixel.y as u32))
+            };
+            let clicked = response
+                .clicked_by(PointerButton::Primary)
+                .then(|| response.interact_pointer_pos().and_then(pixel))
+                .flatten();
+            if ui.input(|input| input.modifiers.alt) {
+                picked_layer = clicked;
+            } else if color_picker {
+                picked = clicked;
+            }
+            if color_picker {
+                hovered = response.hover_pos().and_then(pixel);
+            }
         }
 
         let prepared = PreparedView {
             image,
-            image_rotation,
+            compare: compare.map(|compare| Comparison {
+                amount: compare_split.unwrap_or(compare.amount),
+                ..compare
+            }),
+            crop: crop_output.or(crop),
+            guides,
+            backdrop,
+            transparency,
+            view,
             show_extended_crosshair,
             show_grid,
+            show_pixel_grid,
+            show_rulers,
             show_bottom_bar,
             transform: transform.clone(),
         };
@@ -566,34 +1217,194 @@ impl CanvasView {
             }
         }
 
+        let bounds = (!auto_bounds.any()).then(|| *transform.bounds());
         let memory = ViewMemory {
             auto_bounds,
             min_auto_bounds,
             last_screen_transform: transform,
             last_click_pos_for_zoom,
+            crop_start,
+            wipe_drag,
         };
         memory.store(ui.ctx(), plot_id);
 
         let response = response.on_hover_cursor(CursorIcon::Crosshair);
 
         InnerResponse {
-            inner: (),
+            inner: CanvasViewOutput {
+                double_click,
+                crop: crop_output,
+                hovered,
+                picked,
+                picked_layer,
+                compare_split,
+                bounds,
+                drag_out,
+            },
             response,
         }
     }
 }
 
 struct PreparedView {
-    image: Option<Image<'static>>,
+    image: Option<CanvasImage>,
+    compare: Option<Comparison>,
+    crop: Option<Rect>,
+    guides: Guides,
+    backdrop: Color32,
+    transparency: Transparency,
     transform: ScreenTransform,
-    image_rotation: f32,
+    view: ViewTransform,
     show_grid: bool,
+    show_pixel_grid: bool,
+    show_rulers: bool,
     show_bottom_bar: bool,
     show_extended_crosshair: bool,
 }
 
 impl PreparedView {
-    fn ui(self, ui: &mut Ui, response: &Response) {
+    /// Zoom in physical pixels per canvas pixel, 800%, past which the pixel
+    /// grid starts to fade in.
+    const PIXEL_GRID_ZOOM: f32 = 8.0;
+    /// Thickness of the rulers in points.
+    const RULER_SIZE: f32 = 18.0;
+    /// Minimum distance between labelled ruler ticks in points.
+    const RULER_SPACING: f32 = 60.0;
+
+    /// Outline the canvas pixels in view, fading in from
+    /// [`Self::PIXEL_GRID_ZOOM`] to twice that zoom.
+    fn paint_pixel_grid(&self, painter: &Painter, image_size: Vec2) {
+        let transform = &self.transform;
+        let zoom = transform.dpos_dvalue_x() * painter.ctx().pixels_per_point();
+        let fade = (zoom / Self::PIXEL_GRID_ZOOM - 1.0).clamp(0.0, 1.0);
+        if fade == 0.0 {
+            return;
+        }
+
+        // Canvas pixels covered by the frame, whatever the rotation.
+        let frame = transform.frame();
+        let corners = [
+            frame.left_top(),
+            frame.right_top(),
+            frame.right_bottom(),
+            frame.left_bottom(),
+        ]
+        .map(|pos| transform.pixel_from_position(pos, image_size, self.view));
+        let visible =
+            Rect::from_points(&corners).intersect(Rect::from_min_size(Pos2::ZERO, image_size));
+        if !visible.is_positive() {
+            return;
+        }
+        let (min, max) = (visible.min.floor(), visible.max.ceil());
+
+        let line = |a: Pos2, b: Pos2| {
+            [a, b].map(|pixel| transform.position_from_pixel(pixel, image_size, self.view))
+        };
+        let stroke = Stroke::new(
+            1.0,
+            Color32::from_rgba_unmultiplied(128, 128, 128, (fade * 96.0) as u8),
+        );
+        for x in min.x as u32..=max.x as u32 {
+            painter.line_segment(line(pos2(x as f32, min.y), pos2(x as f32, max.y)), stroke);
+        }
+        for y in min.y as u32..=max.y as u32 {
+            painter.line_segment(line(pos2(min.x, y as f32), pos2(max.x, y as f32)), stroke);
+        }
+    }
+
+    /// Rulers along the top and left edges of the view, labelled with the
+    /// canvas pixel coordinates under them. Only drawn while the canvas is
+    /// rotated by a right angle, when the coordinates follow the edges.
+    fn paint_rulers(&self, ui: &Ui, image_size: Vec2, pointer: Option<Pos2>) {
+        let quarters = self.view.rotation / std::f32::consts::FRAC_PI_2;
+        if (quarters - quarters.round()).abs() > 1e-3 {
+            return;
+        }
+
+        let transform = &self.transform;
+        let frame = *transform.frame();
+        let painter = ui.painter().with_clip_rect(frame);
+        let visuals = ui.visuals();
+        let font = TextStyle::Small.resolve(ui.style());
+        let text_color = visuals.text_color();
+        let stroke = Stroke::new(1.0, visuals.weak_text_color());
+
+        let top = Rect::from_min_max(frame.min, pos2(frame.max.x, frame.min.y + Self::RULER_SIZE));
+        let left = Rect::from_min_max(frame.min, pos2(frame.min.x + Self::RULER_SIZE, frame.max.y));
+        for (ruler, vertical) in [(top, false), (left, true)] {
+            painter.rect_filled(ruler, Rounding::ZERO, visuals.extreme_bg_color);
+            let (start, end) = if vertical {
+                (ruler.right_top(), ruler.right_bottom())
+            } else {
+                (ruler.left_bottom(), ruler.right_bottom())
+            };
+            painter.line_segment([start, end], stroke);
+
+            // The canvas axis that changes along the ruler.
+            let [a, b] =
+                [start, end].map(|pos| transform.pixel_from_position(pos, image_size, self.view));
+            let (a, b) = if (b.x - a.x).abs() >= (b.y - a.y).abs() {
+                (a.x, b.x)
+            } else {
+                (a.y, b.y)
+            };
+            let length = (end - start).length();
+            let (step, minor) = ruler_steps((b - a).abs() / length * Self::RULER_SPACING);
+
+            let (low, high) = (a.min(b), a.max(b));
+            let mut value = (low / minor as f32).ceil() as i64 * minor as i64;
+            while value as f32 <= high {
+                let pos = start.lerp(end, (value as f32 - a) / (b - a));
+                let major = value % step as i64 == 0;
+                let tick = if major {
+                    Self::RULER_SIZE
+                } else {
+                    Self::RULER_SIZE / 3.0
+                };
+                if vertical {
+                    painter.hline(ruler.right() - tick..=ruler.right(), pos.y, stroke);
+                } else {
+                    painter.vline(pos.x, ruler.bottom() - tick..=ruler.bottom(), stroke);
+                }
+                if major {
+                    let galley =
+                        painter.layout_no_wrap(value.to_string(), font.clone(), text_color);
+                    let text = if vertical {
+                        epaint::TextShape::new(
+                            pos2(ruler.left() + 2.0, pos.y - 2.0),
+                            galley,
+                            text_color,
+                        )
+                        .with_angle(-std::f32::consts::FRAC_PI_2)
+                    } else {
+                        epaint::TextShape::new(
+                            pos2(pos.x + 2.0, ruler.top() + 2.0),
+                            galley,
+                            text_color,
+                        )
+                    };
+                    painter.add(text);
+                }
+                value += minor as i64;
+            }
+
+            if let Some(pointer) = pointer.filter(|pointer| frame.contains(*pointer)) {
+                let stroke = Stroke::new(1.0, visuals.strong_text_color());
+                if vertical {
+                    painter.hline(ruler.x_range(), pointer.y, stroke);
+                } else {
+                    painter.vline(pointer.x, ruler.y_range(), stroke);
+                }
+            }
+        }
+        painter.rect_filled(
+            Rect::from_min_size(frame.min, Vec2::splat(Self::RULER_SIZE)),
+            Rounding::ZERO,
+            visuals.extreme_bg_color,
+        );
+    }
+
+    fn ui(mut self, ui: &mut Ui, response: &Response) {
         let transform = &self.transform;
 
         let mut plot_ui = ui.child_ui(*transform.frame(), Layout::default());
@@ -601,7 +1412,7 @@ impl PreparedView {
         plot_ui.painter().rect(
             plot_ui.max_rect(),
             Rounding::default(),
-            Color32::from_gray(20),
+            self.backdrop,
             Stroke::NONE,
         );
 
@@ -624,15 +1435,25 @@ impl PreparedView {
             }
         }
 
-        if let Some(image) = self.image {
-            let image_size = image.size().unwrap();
-            let rect = {
-                let left_top = Vec2::new(-image_size.x / 2.0, -image_size.y / 2.0);
-                let right_bottom = Vec2::new(image_size.x / 2.0, image_size.y / 2.0);
-                let left_top_tf = transform.position_from_point(&left_top);
-                let right_bottom_tf = transform.position_from_point(&right_bottom);
-                Rect::from_two_pos(left_top_tf, right_bottom_tf)
+        if let Some(image) = self.image.take() {
+            let image_size = image.size;
+            // Screen rectangle of a part of the canvas, given in pixels from
+            // the top left corner of the canvas.
+            let screen_rect = |part: Rect| {
+                let left_top = Vec2::new(
+                    part.min.x - image_size.x / 2.0,
+                    image_size.y / 2.0 - part.min.y,
+                );
+                let right_bottom = Vec2::new(
+                    part.max.x - image_size.x / 2.0,
+                    image_size.y / 2.0 - part.max.y,
+                );
+                Rect::from_two_pos(
+                    transform.position_from_point(&left_top),
+                    transform.position_from_point(&right_bottom),
+                )
             };
+            let rect = screen_rect(Rect::from_min_size(Pos2::ZERO, image_size));
             let image_screen_center = ((rect.max - rect.min) / 2.0) / image_size;
 
             let painter = plot_ui.painter();
@@ -643,6 +1464,17 @@ impl PreparedView {
                     Color32::from_rgba_premultiplied(0, 0, 0, 100),
                 );
                 mesh.add_colored_rect(rect, Color32::from_rgba_premultiplied(10, 10, 10, 50));
+                // Only the squares that can end up on screen once the mesh
+                // is rotated about the canvas center are added.
+                let pivot = rect.min + image_screen_center * image_size;
+                let frame = transform.frame();
+                let unrotated =
+                    pivot + emath::Rot2::from_angle(-self.view.rotation) * (frame.center() - pivot);
+                self.transparency.add_to_mesh(
+                    &mut mesh,
+                    rect,
+                    Rect::from_center_size(unrotated, Vec2::splat(frame.size().length())),
+                );
                 if self.show_bottom_bar {
                     mesh.add_colored_rect(
                         {
@@ -654,16 +1486,113 @@ impl PreparedView {
                         Color32::DARK_GRAY,
                     );
                 }
-                mesh.rotate(
-                    emath::Rot2::from_angle(self.image_rotation),
-                    rect.min + image_screen_center * image_size,
-                );
+                mesh.rotate(emath::Rot2::from_angle(self.view.rotation), pivot);
                 mesh
             }));
 
-            image
-                .rotate(self.image_rotation, image_screen_center)
-                .paint_at(&mut plot_ui, rect);
+            // Every tile rotates about the center of the whole canvas.
+            let full_uv = self
+                .view
+                .mirror_uv(Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0)));
+            for (part, texture) in image.tiles {
+                let tile_rect = screen_rect(self.view.mirror_rect(part, image_size));
+                let origin = (rect.center() - tile_rect.min) / tile_rect.size();
+                Image::from_texture(load::SizedTexture::new(texture, part.size()))
+                    .uv(full_uv)
+                    .rotate(self.view.rotation, origin)
+                    .paint_at(&mut plot_ui, tile_rect);
+            }
+
+            if let Some(compare) = self.compare.take() {
+                // Pixels of the compared canvas, which is centered on this one.
+                let offset = (image_size - compare.image.size) / 2.0;
+                let split = compare.amount * image_size.x;
+                let (visible, tint) = match compare.mode {
+                    CompareMode::Overlay => (
+                        Rect::from_min_size(Pos2::ZERO, compare.image.size),
+                        Color32::from_white_alpha((compare.amount * 255.0).round() as u8),
+                    ),
+                    CompareMode::Wipe => (
+                        Rect::from_min_max(
+                            pos2(split - offset.x, 0.0),
+                            compare.image.size.to_pos2(),
+                        ),
+                        Color32::WHITE,
+                    ),
+                };
+                for (part, texture) in compare.image.tiles {
+                    let shown = part.intersect(visible);
+                    if !shown.is_positive() {
+                        continue;
+                    }
+                    let uv = Rect::from_min_max(
+                        ((shown.min - part.min) / part.size()).to_pos2(),
+                        ((shown.max - part.min) / part.size()).to_pos2(),
+                    );
+                    let tile_rect =
+                        screen_rect(self.view.mirror_rect(shown.translate(offset), image_size));
+                    let origin = (rect.center() - tile_rect.min) / tile_rect.size();
+                    Image::from_texture(load::SizedTexture::new(texture, shown.size()))
+                        .uv(self.view.mirror_uv(uv))
+                        .tint(tint)
+                        .rotate(self.view.rotation, origin)
+                        .paint_at(&plot_ui, tile_rect);
+                }
+
+                if compare.mode == CompareMode::Wipe {
+                    let line = [pos2(split, 0.0), pos2(split, image_size.y)]
+                        .map(|pixel| transform.position_from_pixel(pixel, image_size, self.view));
+                    let painter = plot_ui.painter();
+                    painter.line_segment(line, Stroke::new(4.0, Color32::DARK_BLUE));
+                    painter.line_segment(line, Stroke::new(2.0, Color32::WHITE));
+                }
+            }
+
+            if self.show_pixel_grid {
+                self.paint_pixel_grid(plot_ui.painter(), image_size);
+            }
+
+            let to_screen = |pixel| transform.position_from_pixel(pixel, image_size, self.view);
+            let guide = Stroke::new(1.0, Color32::from_rgb(0, 160, 255));
+            let painter = plot_ui.painter();
+            for line in self.guides.lines(image_size) {
+                painter.line_segment(line.map(to_screen), guide);
+            }
+            if let Some(area) = self.guides.safe_area(image_size) {
+                let corners = [
+                    area.left_top(),
+                    area.right_top(),
+                    area.right_bottom(),
+                    area.left_bottom(),
+                    area.left_top(),
+                ]
+                .map(to_screen)
+                .to_vec();
+                painter.extend(Shape::dashed_line(&corners, guide, 6.0, 4.0));
+            }
+
+            if let Some(crop) = self.crop {
+                let corners = [
+                    crop.left_top(),
+                    crop.right_top(),
+                    crop.right_bottom(),
+                    crop.left_bottom(),
+                ]
+                .map(|pixel| transform.position_from_pixel(pixel, image_size, self.view));
+                let painter = plot_ui.painter();
+                painter.add(Shape::closed_line(
+                    corners.to_vec(),
+                    Stroke::new(4.0, Color32::DARK_BLUE),
+                ));
+                painter.add(Shape::closed_line(
+                    corners.to_vec(),
+                    Stroke::new(2.0, Color32::WHITE),
+                ));
+            }
+
+            if self.show_rulers {
+                self.paint_rulers(&plot_ui, image_size, response.hover_pos());
+            }
         }
 
         if self.show_extended_crosshair {
@@ -693,3 +1622,53 @@ impl PreparedView {
         }
     }
 }
+
+/// Spacing of the labelled ruler ticks, the smallest of 1, 2 or 5 times a
+/// power of ten that is at least `min`, and of the ticks between them.
+fn ruler_steps(min: f32) -> (u32, u32) {
+    // Also keeps an empty view from overflowing the steps.
+    let min = if min.is_finite() {
+        min.clamp(1.0, 1e8)
+    } else {
+        1e8
+    };
+    let power = 10f32.powf(min.log10().floor()).round() as u32;
+    match [1, 2, 5]
+        .into_iter()
+        .find(|factor| (factor * power) as f32 >= min)
+    {
+        Some(1) => (power, (power / 5).max(1)),
+        Some(factor) => (factor * power, power),
+        None => (10 * power, 2 * power),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ruler_steps;
+
+    #[test]
+    fn ruler_steps_round_up_to_1_2_or_5() {
+        for (min, steps) in [
+            (1.0, (1, 1)),
+            (3.0, (5, 1)),
+            (7.0, (10, 2)),
+            (10.0, (10, 2)),
+            (12.0, (20, 10)),
+            (150.0, (200, 100)),
+            (999.0, (1000, 200)),
+            (4096.0, (5000, 1000)),
+        ] {
+            assert_eq!(ruler_steps(min), steps, "{min}");
+        }
+    }
+
+    #[test]
+    fn ruler_steps_stay_in_range() {
+        assert_eq!(ruler_steps(0.0), (1, 1));
+        assert_eq!(ruler_steps(-5.0), (1, 1));
+        for min in [f32::NAN, f32::INFINITY, 1e12] {
+            assert_eq!(ruler_steps(min), (100_000_000, 20_000_000), "{min}");
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use super::app::DifferenceSource;
+use compositor::tex::Region;
+use parking_lot::Mutex;
+use silica::{BlendingMode, LayerProperties, ProcreateFile, SilicaGroup};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackgroundProperties {
+    pub hidden: bool,
+    pub color: [f32; 4],
+    pub image_hidden: bool,
+}
+
+impl BackgroundProperties {
+    pub fn of(file: &ProcreateFile) -> Self {
+        Self {
+            hidden: file.background_hidden,
+            color: file.background_color,
+            image_hidden: file.background_image_hidden,
+        }
+    }
+
+    pub fn apply(self, file: &mut ProcreateFile) {
+        file.background_hidden = self.hidden;
+        file.background_color = self.color;
+        file.background_image_hidden = self.image_hidden;
+    }
+}
+
+/// How the composite of an instance is shown, besides its layers and
+/// background. Only changed by commands, like them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DisplayState {
+    /// Blending mode hovered in the hierarchy, shown on the layer or group
+    /// with the texture without editing it.
+    pub blend_preview: Option<(u32, BlendingMode)>,
+    /// Shown instead of the composite when set.
+    pub difference: Option<DifferenceSource>,
+    /// Whether the canvas shows the composite stored in the file instead
+    /// of compositing the layers, to check the composite against.
+    pub show_stored: bool,
+    /// Whether the canvas is shown as stored, without the orientation of
+    /// the document applied.
+    pub stored_orientation: bool,
+    /// Part of the composite to export, in pixels of the target output.
+    pub crop: Option<Region>,
+    /// Set while a slider of a layer or group is dragged, so that its edits
+    /// are composited at intervals rather than every frame.
+    pub dragging: bool,
+}
+
+impl DisplayState {
+    /// Apply a command that changes how the composite is shown, returning
+    /// whether it has to be composited again.
+    pub fn apply(&mut self, command: &InstanceCommand) -> bool {
+        fn set<T: PartialEq>(field: &mut T, value: T) -> bool {
+            std::mem::replace(field, value) != *field
+        }
+
+        match *command {
+            // The crop is in pixels of the output, which no longer match.
+            InstanceCommand::Flip { .. } | InstanceCommand::Rotate(_) => {
+                self.crop = None;
+                true
+            }
+            InstanceCommand::StoredOrientation(stored) => {
+                let changed = set(&mut self.stored_orientation, stored);
+                if changed {
+                    self.crop = None;
+                }
+                changed
+            }
+            InstanceCommand::BlendPreview(preview) => set(&mut self.blend_preview, preview),
+            InstanceCommand::Difference(source) => set(&mut self.difference, source),
+            InstanceCommand::ShowStored(show) => set(&mut self.show_stored, show),
+            InstanceCommand::Crop(crop) => {
+                self.crop = crop;
+                false
+            }
+            InstanceCommand::Dragging(dragging) => {
+                self.dragging = dragging;
+                false
+            }
+            InstanceCommand::Redraw => true,
+            InstanceCommand::Node { .. } | InstanceCommand::Background(_) => false,
+        }
+    }
+}
+
+/// Change to an instance, made by the rendering thread right before it
+/// composites, so that the GUI never waits for a composite to finish.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstanceCommand {
+    /// Set the properties of the layer or group at an index of the
+    /// hierarchy in document order.
+    Node {
+        index: usize,
+        properties: LayerProperties,
+    },
+    Background(BackgroundProperties),
+    /// Flip the output, along the axes of the layer textures.
+    Flip {
+        horizontal: bool,
+        vertical: bool,
+    },
+    /// Rotate the output by quarter turns, counterclockwise if positive.
+    Rotate(i32),
+    /// Show the canvas as stored, or in the orientation of the document.
+    StoredOrientation(bool),
+    /// Show a blending mode on the layer or group with the texture, or stop
+    /// showing one.
+    BlendPreview(Option<(u32, BlendingMode)>),
+    /// Show a difference instead of the composite, or the composite again.
+    Difference(Option<DifferenceSource>),
+    /// Show the composite stored in the file, or the composite again.
+    ShowStored(bool),
+    /// Set the part of the composite to export.
+    Crop(Option<Region>),
+    /// Start or stop coalescing edits while a slider is dragged.
+    Dragging(bool),
+    /// Composite again, such as after the paper or the adjustments changed.
+    Redraw,
+}
+
+impl InstanceCommand {
+    /// Whether the command replaces a pending one, which then does not
+    /// need to be applied.
+    fn supersedes(&self, pending: &Self) -> bool {
+        match (self, pending) {
+            (Self::Node { index, .. }, Self::Node { index: other, .. }) => index == other,
+            // Flips and rotations add up rather than set the output.
+            (Self::Flip { .. } | Self::Rotate(_), _) => false,
+            // The others set what they change outright.
+            _ => std::mem::discriminant(self) == std::mem::discriminant(pending),
+        }
+    }
+}
+
+/// Apply the commands that edit the layers, the background and how they
+/// are shown, returning whether the composite changed. Turning the output
+/// is left to the caller, but counts as a change.
+pub fn apply_commands(
+    layers: &mut SilicaGroup,
+    background: &mut BackgroundProperties,
+    display: &mut DisplayState,
+    commands: &[InstanceCommand],
+) -> bool {
+    let mut changed = false;
+    for command in commands {
+        match *command {
+            InstanceCommand::Node { index, properties } => {
+                let mut i = 0;
+                layers.visit_mut(|_, node| {
+                    if i == index && LayerProperties::of_node(node) != properties {
+                        properties.apply_node(node);
+                        changed = true;
+                    }
+                    i += 1;
+                });
+            }
+            InstanceCommand::Background(properties) => {
+                changed |= *background != properties;
+                *background = properties;
+            }
+            _ => changed |= display.apply(command),
+        }
+    }
+    changed
+}
+
+/// Commands sent to an instance that were not applied yet.
+#[derive(Debug, Default)]
+pub struct CommandQueue {
+    pending: Mutex<Vec<InstanceCommand>>,
+}
+
+impl CommandQueue {
+    /// Queue a command, dropping any pending one that it replaces, so that
+    /// dragging a slider does not pile up commands.
+    pub fn send(&self, command: InstanceCommand) {
+        let mut pending = self.pending.lock();
+        // Only the last command can be replaced without reordering others.
+        if pending.last().is_some_and(|last| command.supersedes(last)) {
+            pending.pop();
+        }
+        pending.push(command);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.lock().is_empty()
+    }
+
+    /// Take the pending commands to apply them.
+    pub fn take(&self) -> Vec<InstanceCommand> {
+        std::mem::take(&mut *self.pending.lock())
+    }
+
+    /// Drop the pending edits of layers and groups, once the indices they
+    /// refer to may point to other ones.
+    pub fn discard_node_edits(&self) {
+        self.pending
+            .lock()
+            .retain(|command| !matches!(command, InstanceCommand::Node { .. }));
+    }
+
+    /// Apply the pending commands to a copy of the layers and background,
+    /// to show them as they will be once the commands are applied.
+    pub fn preview(&self, layers: &mut SilicaGroup, background: &mut BackgroundProperties) {
+        apply_commands(
+            layers,
+            background,
+            &mut DisplayState::default(),
+            &self.pending.lock(),
+        );
+    }
+
+    /// Apply the pending commands to a copy of how the composite is shown.
+    pub fn preview_display(&self, display: &mut DisplayState) {
+        for command in self.pending.lock().iter() {
+            display.apply(command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use silica::fixture::{loaded_group, loaded_layer};
+    use silica::{BlendingMode, SilicaHierarchy};
+
+    fn layer(image: u32) -> SilicaHierarchy {
+        SilicaHierarchy::Layer(loaded_layer(image))
+    }
+
+    /// Root holding a layer, then a group of two layers.
+    fn layers() -> SilicaGroup {
+        loaded_group(
+            u32::MAX,
+            vec![
+                layer(0),
+                SilicaHierarchy::Group(loaded_group(1, vec![layer(2), layer(3)])),
+            ],
+        )
+    }
+
+    fn background() -> BackgroundProperties {
+        BackgroundProperties {
+            hidden: false,
+            color: [1.0; 4],
+            image_hidden: false,
+        }
+    }
+
+    fn hide(index: usize) -> InstanceCommand {
+        InstanceCommand::Node {
+            index,
+            properties: LayerProperties {
+                hidden: true,
+                clipped: false,
+                opacity: 1.0,
+                blend: BlendingMode::Normal,
+            },
+        }
+    }
+
+    #[test]
+    fn node_commands_address_document_order() {
+        let mut edited = layers();
+        assert!(apply_commands(
+            &mut edited,
+            &mut background(),
+            &mut DisplayState::default(),
+            &[hide(2)]
+        ));
+        let hidden = edited
+            .iter()
+            .map(|node| LayerProperties::of_node(node).hidden)
+            .collect::<Vec<_>>();
+        assert_eq!(hidden, [false, false, true, false]);
+    }
+
+    #[test]
+    fn commands_that_change_nothing_are_not_changes() {
+        let mut edited = layers();
+        let unchanged = InstanceCommand::Node {
+            index: 1,
+            properties: LayerProperties::of_node(edited.iter().nth(1).unwrap()),
+        };
+        let mut background = background();
+        let same = InstanceCommand::Background(background);
+        assert!(!apply_commands(
+            &mut edited,
+            &mut background,
+            &mut DisplayState::default(),
+            &[unchanged, same]
+        ));
+        assert_eq!(edited, layers());
+        assert!(apply_commands(
+            &mut edited,
+            &mut background,
+            &mut DisplayState::default(),
+            &[InstanceCommand::Redraw]
+        ));
+    }
+
+    #[test]
+    fn queue_replaces_the_last_command_for_the_same_target() {
+        let queue = CommandQueue::default();
+        let opacity = |opacity| InstanceCommand::Node {
+            index: 0,
+            properties: LayerProperties {
+                hidden: false,
+                clipped: false,
+                opacity,
+                blend: BlendingMode::Normal,
+            },
+        };
+        queue.send(opacity(0.2));
+        queue.send(opacity(0.4));
+        queue.send(InstanceCommand::Rotate(1));
+        queue.send(opacity(0.6));
+        assert_eq!(
+            queue.take(),
+            [opacity(0.4), InstanceCommand::Rotate(1), opacity(0.6)]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn preview_shows_pending_commands_without_taking_them() {
+        let queue = CommandQueue::default();
+        queue.send(hide(0));
+        let mut previewed = layers();
+        queue.preview(&mut previewed, &mut background());
+        assert!(LayerProperties::of_node(previewed.iter().next().unwrap()).hidden);
+        assert_eq!(queue.take(), [hide(0)]);
+    }
+
+    #[test]
+    fn display_commands_only_recomposite_what_they_change() {
+        let mut display = DisplayState::default();
+        let crop = Some(Region {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 2,
+        });
+        assert!(!display.apply(&InstanceCommand::Crop(crop)));
+        assert!(!display.apply(&InstanceCommand::Dragging(true)));
+        assert!(!display.apply(&InstanceCommand::ShowStored(false)));
+        assert!(display.apply(&InstanceCommand::ShowStored(true)));
+        let difference = Some(DifferenceSource::Layers(1, 2));
+        assert!(display.apply(&InstanceCommand::Difference(difference)));
+        assert!(!display.apply(&InstanceCommand::Difference(difference)));
+        assert_eq!(display.crop, crop);
+
+        // Turning the output moves the cropped pixels.
+        assert!(display.apply(&InstanceCommand::StoredOrientation(true)));
+        assert_eq!(display.crop, None);
+        display.crop = crop;
+        assert!(display.apply(&InstanceCommand::Rotate(1)));
+        assert_eq!(display.crop, None);
+    }
+
+    #[test]
+    fn display_previews_pending_commands() {
+        let queue = CommandQueue::default();
+        queue.send(InstanceCommand::Dragging(true));
+        queue.send(InstanceCommand::Dragging(false));
+        queue.send(InstanceCommand::Rotate(1));
+        queue.send(InstanceCommand::Rotate(1));
+        queue.send(InstanceCommand::BlendPreview(Some((
+            3,
+            BlendingMode::Multiply,
+        ))));
+        let mut display = DisplayState::default();
+        queue.preview_display(&mut display);
+        assert_eq!(display.blend_preview, Some((3, BlendingMode::Multiply)));
+        assert!(!display.dragging);
+        assert_eq!(
+            queue.take(),
+            [
+                InstanceCommand::Dragging(false),
+                InstanceCommand::Rotate(1),
+                InstanceCommand::Rotate(1),
+                InstanceCommand::BlendPreview(Some((3, BlendingMode::Multiply))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    #[error("request failed: {0}")]
+    Request(#[from] Box<ureq::Error>),
+    #[error("file is larger than {} GiB", Download::MAX_SIZE >> 30)]
+    TooLarge,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// File downloaded from a link to be opened, with its progress shown until
+/// it is done.
+pub struct Download {
+    /// Link the file is downloaded from, over HTTP.
+    link: String,
+    /// Name of the file, from the last segment of the link.
+    pub name: String,
+    received: AtomicU64,
+    /// Size of the file given by the server, 0 if it gave none.
+    total: AtomicU64,
+}
+
+impl Download {
+    /// Folder in the temporary folder that downloads are cached in.
+    const CACHE: &'static str = "silicate-downloads";
+    /// Name of files whose link does not give a usable one.
+    const UNTITLED: &'static str = "Untitled Artwork.procreate";
+    /// Largest file that is downloaded, well above any artwork, so that a
+    /// wrong link can not fill the temporary folder.
+    const MAX_SIZE: u64 = 8 << 30;
+    /// Time after which a server that stopped answering is given up on. It
+    /// applies to each read rather than the whole download, which can take
+    /// long for a large file.
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Prepare to download a link, `None` if it is not an HTTP or WebDAV
+    /// link. WebDAV links are downloaded over HTTP, and credentials in the
+    /// link are sent with basic authentication.
+    pub fn new(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let (scheme, rest) = url.split_once("://")?;
+        let scheme = match scheme.to_ascii_lowercase().as_str() {
+            "http" | "dav" | "webdav" => "http",
+            "https" | "davs" | "webdavs" => "https",
+            _ => return None,
+        };
+        let link = format!("{scheme}://{rest}");
+        let name = rest
+            .split(['?', '#'])
+            .next()
+            .and_then(|rest| rest.split_once('/'))
+            .and_then(|(_, path)| path.rsplit('/').find(|segment| !segment.is_empty()))
+            .map(percent_decode)
+            .unwrap_or_else(|| String::from(Self::UNTITLED));
+        Some(Self {
+            link,
+            name,
+            received: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+        })
+    }
+
+    /// Bytes received so far and the size of the file, if known.
+    pub fn progress(&self) -> (u64, Option<u64>) {
+        let total = self.total.load(Ordering::Relaxed);
+        (
+            self.received.load(Ordering::Relaxed),
+            (total > 0).then_some(total),
+        )
+    }
+
+    /// Download the file into the cache, blocking until it is done, and
+    /// return where it was saved. Each link is saved in its own folder, so
+    /// that files of the same name from different places stay apart.
+    pub fn fetch(&self) -> Result<PathBuf, DownloadError> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Self::TIMEOUT)
+            .timeout_read(Self::TIMEOUT)
+            .build();
+        let response = agent.get(&self.link).call().map_err(Box::new)?;
+        if let Some(total) = response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok())
+        {
+            if total > Self::MAX_SIZE {
+                return Err(DownloadError::TooLarge);
+            }
+            self.total.store(total, Ordering::Relaxed);
+        }
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.link.hash(&mut hasher);
+        let cache = std::env::temp_dir().join(Self::CACHE);
+        private_dir(&cache)?;
+        let folder = cache.join(format!("{:016x}", hasher.finish()));
+        private_dir(&folder)?;
+        let path = folder.join(self.file_name());
+        // Written aside first, so that a cut off download is never opened,
+        // under a name of its own so that fetches of the same link at once
+        // do not write into each other.
+        static FETCHES: AtomicU64 = AtomicU64::new(0);
+        let partial = folder.join(format!(
+            "{}.{}-{}.part",
+            self.file_name(),
+            std::process::id(),
+            FETCHES.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = self.receive(response.into_reader(), &partial);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        result?;
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Write the body of the response to the file, up to the largest size.
+    fn receive(&self, mut reader: impl Read, path: &Path) -> Result<(), DownloadError> {
+        // Never opens a file or link that is already there.
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut writer = io::BufWriter::new(options.open(path)?);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let received = self.received.fetch_add(read as u64, Ordering::Relaxed) + read as u64;
+            if received > Self::MAX_SIZE {
+                return Err(DownloadError::TooLarge);
+            }
+            writer.write_all(&buffer[..read])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Name the file is saved under, with the characters that are not safe
+    /// in a file name replaced. Names that would be hidden or only made of
+    /// dots fall back to a default.
+    fn file_name(&self) -> String {
+        let name = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || " -_.".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        match name.trim_start_matches('.').trim() {
+            "" => String::from(Self::UNTITLED),
+            name => name.to_string(),
+        }
+    }
+}
+
+/// Create a folder in the shared temporary folder that only the user can
+/// read and write, refusing one that is already there but open to others or
+/// a link, as it may have been put there by someone else.
+fn private_dir(path: &Path) -> io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    match builder.create(path) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+    let metadata = std::fs::symlink_metadata(path)?;
+    #[cfg(unix)]
+    let shared = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o077 != 0;
+    #[cfg(not(unix))]
+    let shared = false;
+    if !metadata.is_dir() || shared {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private folder", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Decode the `%XX` escapes of a segment of a link, keeping malformed ones.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webdav_links_are_fetched_over_http() {
+        let download = Download::new("davs://nas.local/Art/My%20Piece.procreate").unwrap();
+        assert_eq!(download.link, "https://nas.local/Art/My%20Piece.procreate");
+        assert_eq!(download.name, "My Piece.procreate");
+        let download = Download::new("webdav://nas.local/a.procreate?x=1").unwrap();
+        assert_eq!(download.link, "http://nas.local/a.procreate?x=1");
+        assert_eq!(download.name, "a.procreate");
+    }
+
+    #[test]
+    fn other_links_are_refused() {
+        assert!(Download::new("ftp://nas.local/a.procreate").is_none());
+        assert!(Download::new("/home/a.procreate").is_none());
+    }
+
+    #[test]
+    fn links_without_a_file_name_are_named() {
+        let download = Download::new("https://nas.local/").unwrap();
+        assert_eq!(download.name, "Untitled Artwork.procreate");
+    }
+
+    #[test]
+    fn names_are_made_safe_for_files() {
+        for (link, file_name) in [
+            ("https://nas.local/a%2Fb.procreate", "a_b.procreate"),
+            ("https://nas.local/.hidden.procreate", "hidden.procreate"),
+            ("https://nas.local/..", "Untitled Artwork.procreate"),
+            ("https://nas.local/%2E%2E%2E", "Untitled Artwork.procreate"),
+            ("https://nas.local/.%20", "Untitled Artwork.procreate"),
+        ] {
+            let download = Download::new(link).unwrap();
+            assert_eq!(download.file_name(), file_name, "{link}");
+        }
+    }
+
+    #[test]
+    fn partial_files_are_never_reused() {
+        let folder = std::env::temp_dir().join(format!("silicate-download-{}", std::process::id()));
+        private_dir(&folder).unwrap();
+        // Made again, as by the next fetch.
+        private_dir(&folder).unwrap();
+        let partial = folder.join("a.procreate.part");
+        let download = Download::new("https://nas.local/a.procreate").unwrap();
+        download.receive(&b"first"[..], &partial).unwrap();
+        let err = download.receive(&b"second"[..], &partial).unwrap_err();
+        assert!(
+            matches!(&err, DownloadError::Io(err) if err.kind() == io::ErrorKind::AlreadyExists),
+            "{err}"
+        );
+        assert_eq!(std::fs::read(&partial).unwrap(), b"first");
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shared_folders_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+        let folder = std::env::temp_dir().join(format!("silicate-shared-{}", std::process::id()));
+        std::fs::create_dir(&folder).unwrap();
+        std::fs::set_permissions(&folder, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(private_dir(&folder).is_err());
+        std::fs::remove_dir(&folder).unwrap();
+    }
+
+    #[test]
+    fn malformed_escapes_are_kept() {
+        assert_eq!(percent_decode("100%25%2"), "100%%2");
+    }
+}
@@ -0,0 +1,221 @@
+use super::store::StoredFile;
+use crate::json::Json;
+use crate::template::{self, TemplateError};
+use image::ImageFormat;
+use std::time::Duration;
+
+/// How large a preset exports the composite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PresetSize {
+    /// Scale factor, 1.0 exports one pixel per canvas pixel.
+    Scale(f32),
+    /// Pixels along the longest side.
+    LongEdge(u32),
+}
+
+impl PresetSize {
+    /// Scale factor for an image of the given size.
+    pub fn factor(self, width: u32, height: u32) -> f32 {
+        match self {
+            Self::Scale(factor) => factor,
+            Self::LongEdge(pixels) => pixels as f32 / width.max(height).max(1) as f32,
+        }
+    }
+}
+
+/// Export settings kept under a name, so that several exports can be made
+/// in one go, such as a full size PNG, a 2000 px JPEG and a thumbnail.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportPreset {
+    pub name: String,
+    pub format: ImageFormat,
+    pub size: PresetSize,
+    /// Whether the background is exported, or left transparent.
+    pub background: bool,
+    /// Whether only the crop region of the canvas is exported, if it has one.
+    pub region: bool,
+    /// Name of the exported file without its extension, in which `{name}`,
+    /// `{date}`, `{layers}` and `{preset}` are replaced.
+    pub template: String,
+}
+
+impl ExportPreset {
+    pub const FORMATS: [ImageFormat; 3] = [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Tiff];
+
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            format: ImageFormat::Png,
+            size: PresetSize::Scale(1.0),
+            background: true,
+            region: false,
+            template: String::from("{name} {preset}"),
+        }
+    }
+
+    /// Name of the file exported for a document with the given name and
+    /// number of layers.
+    pub fn file_name(&self, name: &str, layers: usize) -> Result<String, TemplateError> {
+        let stem = template::expand(&self.template, |token| match token {
+            "name" => Some(name.to_string()),
+            "date" => Some(template::today()),
+            "layers" => Some(layers.to_string()),
+            "preset" => Some(self.name.clone()),
+            _ => None,
+        })?;
+        Ok(format!(
+            "{}.{}",
+            template::file_name(&stem),
+            self.format.extensions_str()[0]
+        ))
+    }
+
+    fn to_json(&self) -> Json {
+        let size = match self.size {
+            PresetSize::Scale(factor) => ("scale", Json::number(factor)),
+            PresetSize::LongEdge(pixels) => ("long_edge", Json::number(pixels)),
+        };
+        Json::object(vec![
+            ("name", Json::String(self.name.clone())),
+            (
+                "format",
+                Json::String(self.format.extensions_str()[0].to_string()),
+            ),
+            size,
+            ("background", Json::Bool(self.background)),
+            ("region", Json::Bool(self.region)),
+            ("template", Json::String(self.template.clone())),
+        ])
+    }
+
+    fn from_json(preset: &Json) -> Option<Self> {
+        let text = |key| preset.get(key).and_then(Json::as_str);
+        let format = text("format")?;
+        let size = match (preset.get("scale"), preset.get("long_edge")) {
+            (Some(scale), None) => {
+                PresetSize::Scale(Some(scale.as_f64()? as f32).filter(|s| *s > 0.0)?)
+            }
+            (None, Some(pixels)) => PresetSize::LongEdge(
+                Some(pixels.as_f64()?).filter(|p| *p >= 1.0 && p.fract() == 0.0)? as u32,
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            name: text("name")?.to_string(),
+            format: *Self::FORMATS
+                .iter()
+                .find(|f| f.extensions_str()[0] == format)?,
+            size,
+            background: preset.get("background")?.as_bool()?,
+            region: preset.get("region")?.as_bool()?,
+            template: text("template")?.to_string(),
+        })
+    }
+}
+
+/// Export presets, kept between runs.
+pub struct ExportPresets {
+    file: StoredFile,
+    pub presets: Vec<ExportPreset>,
+}
+
+impl ExportPresets {
+    const FORMAT: &'static str = "silicate-export-presets";
+    const VERSION: &'static str = "1";
+
+    pub fn load() -> Self {
+        let mut file = StoredFile::new("export-presets.json", "export presets", Duration::ZERO);
+        let text = file.read();
+        let presets = text.as_deref().and_then(Self::deserialize);
+        if text.is_some() && presets.is_none() {
+            tracing::warn!("Ignoring unreadable export presets");
+        }
+        let presets = presets.unwrap_or_else(Self::defaults);
+        // Only written once they are edited.
+        file.set_written(Self::serialize(&presets));
+        Self { file, presets }
+    }
+
+    /// Presets until any are saved: the full canvas as a PNG, a JPEG for
+    /// sharing and a thumbnail.
+    fn defaults() -> Vec<ExportPreset> {
+        vec![
+            ExportPreset {
+                template: String::from("{name}"),
+                ..ExportPreset::new("Full Size")
+            },
+            ExportPreset {
+                format: ImageFormat::Jpeg,
+                size: PresetSize::LongEdge(2000),
+                ..ExportPreset::new("2000px")
+            },
+            ExportPreset {
+                size: PresetSize::LongEdge(256),
+                ..ExportPreset::new("Thumbnail")
+            },
+        ]
+    }
+
+    fn serialize(presets: &[ExportPreset]) -> String {
+        let mut out = Json::object(vec![
+            ("format", Json::String(Self::FORMAT.to_string())),
+            ("version", Json::number(Self::VERSION)),
+            (
+                "presets",
+                Json::Array(presets.iter().map(ExportPreset::to_json).collect()),
+            ),
+        ])
+        .to_pretty();
+        out.push('\n');
+        out
+    }
+
+    fn deserialize(text: &str) -> Option<Vec<ExportPreset>> {
+        let presets = Json::parse(text)?;
+        if presets.get("format")?.as_str()? != Self::FORMAT
+            || presets.get("version")? != &Json::number(Self::VERSION)
+        {
+            return None;
+        }
+        presets
+            .get("presets")?
+            .as_array()?
+            .iter()
+            .map(ExportPreset::from_json)
+            .collect()
+    }
+
+    /// Write the presets if they changed.
+    pub fn save(&mut self) {
+        self.file.write(Self::serialize(&self.presets));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip() {
+        let presets = ExportPresets::defaults();
+        assert_eq!(
+            ExportPresets::deserialize(&ExportPresets::serialize(&presets)),
+            Some(presets)
+        );
+        assert_eq!(ExportPresets::deserialize("{}"), None);
+    }
+
+    #[test]
+    fn file_names_are_made_from_the_template() {
+        let preset = ExportPreset {
+            format: ImageFormat::Jpeg,
+            template: String::from("{name}/{preset} {layers}"),
+            ..ExportPreset::new("Web")
+        };
+        assert_eq!(
+            preset.file_name("Piece", 12),
+            Ok(String::from("Piece_Web 12.jpg"))
+        );
+        assert_eq!(PresetSize::LongEdge(2000).factor(4000, 1000), 0.5);
+    }
+}
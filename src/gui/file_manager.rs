@@ -0,0 +1,95 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Show a file in the file manager of the system, selected in its folder if
+/// `select` is set and the file manager supports it, otherwise by opening
+/// the folder that contains it. Blocks until the file manager is launched.
+pub fn show(path: &Path, select: bool) -> io::Result<()> {
+    let path = path.canonicalize()?;
+    let folder = path.parent().unwrap_or(&path);
+    show_in(&path, folder, select)
+}
+
+#[cfg(windows)]
+fn show_in(path: &Path, folder: &Path, select: bool) -> io::Result<()> {
+    use std::os::windows::process::CommandExt;
+
+    let mut command = Command::new("explorer");
+    if select {
+        // Explorer parses its own arguments, the path is quoted after the
+        // comma rather than as a separate argument.
+        command.raw_arg(format!("/select,\"{}\"", path.display()));
+    } else {
+        command.arg(folder);
+    }
+    // Explorer exits with an error code even when it opened the folder.
+    command.status().map(drop)
+}
+
+#[cfg(target_os = "macos")]
+fn show_in(path: &Path, folder: &Path, select: bool) -> io::Result<()> {
+    let mut command = Command::new("open");
+    if select {
+        command.arg("-R").arg(path);
+    } else {
+        command.arg(folder);
+    }
+    run(&mut command)
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn show_in(path: &Path, folder: &Path, select: bool) -> io::Result<()> {
+    // Selecting the file needs a file manager that implements the
+    // freedesktop.org FileManager1 interface, others only open the folder.
+    if select {
+        let selected = Command::new("dbus-send")
+            .args([
+                "--session",
+                "--print-reply",
+                "--dest=org.freedesktop.FileManager1",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowItems",
+            ])
+            .arg(format!("array:string:{}", file_uri(path)))
+            .arg("string:")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if selected {
+            return Ok(());
+        }
+    }
+    run(Command::new("xdg-open").arg(folder))
+}
+
+#[cfg(not(windows))]
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} exited with {status}",
+            command.get_program().to_string_lossy()
+        )))
+    }
+}
+
+/// `file://` URI of an absolute path, with every byte other than unreserved
+/// characters and separators percent-encoded.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn file_uri(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
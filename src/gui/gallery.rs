@@ -0,0 +1,340 @@
+use super::app::App;
+use egui::*;
+use parking_lot::Mutex;
+use silica::ProcreateFile;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Thumbnail of a file in the gallery, read from the file in the background.
+enum Thumbnail {
+    Reading,
+    Read(ColorImage),
+    /// The file has no thumbnail or could not be read.
+    Missing,
+}
+
+/// Grid of the `.procreate` files in a folder, showing the thumbnails that
+/// Procreate embeds in them. Only the thumbnails in view are read, and files
+/// are loaded once they are opened from the gallery.
+pub struct Gallery {
+    folder: PathBuf,
+    files: Vec<PathBuf>,
+    /// Thumbnails requested so far, by index into `files`.
+    thumbnails: Arc<Mutex<HashMap<usize, Thumbnail>>>,
+    textures: HashMap<usize, TextureHandle>,
+    selected: BTreeSet<usize>,
+    /// File that the arrow keys move from.
+    cursor: usize,
+    /// Scroll to the cursor on the next frame.
+    reveal: bool,
+    /// Rows shown in the last frame and the height of the view.
+    shown_rows: Range<usize>,
+    view_height: f32,
+}
+
+impl Gallery {
+    /// Width and height of a thumbnail cell.
+    const CELL: f32 = 144.0;
+    /// Height of the file name under a thumbnail.
+    const LABEL: f32 = 20.0;
+    /// Longest side that thumbnails are kept at, in pixels.
+    const THUMBNAIL_SIZE: u32 = 288;
+    /// Rows beyond the view whose thumbnails are kept around.
+    const KEPT_ROWS: usize = 8;
+
+    /// List the `.procreate` files directly inside a folder by name.
+    pub fn scan(folder: PathBuf) -> std::io::Result<Self> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&folder)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("procreate"))
+            {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(Self {
+            folder,
+            files,
+            thumbnails: Arc::new(Mutex::new(HashMap::new())),
+            textures: HashMap::new(),
+            selected: BTreeSet::new(),
+            cursor: 0,
+            reveal: false,
+            shown_rows: 0..0,
+            view_height: 0.0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Show the gallery, returning whether it is still open. The arrow keys
+    /// move between the files and Enter opens the selected ones.
+    pub fn ui(&mut self, context: &Context, app: &Arc<App>) -> bool {
+        let mut open = true;
+        let mut opened = BTreeSet::new();
+        let title = self.folder.file_name().map_or_else(
+            || self.folder.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        Window::new(format!("Gallery: {title}"))
+            .id(Id::new("gallery"))
+            .open(&mut open)
+            .default_size(vec2(640.0, 480.0))
+            .show(context, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} files", self.files.len()));
+                    if ui
+                        .add_enabled(
+                            !self.selected.is_empty(),
+                            Button::new(format!("Open Selected ({})", self.selected.len())),
+                        )
+                        .clicked()
+                    {
+                        opened.extend(self.selected.iter().copied());
+                    }
+                });
+                ui.separator();
+
+                let spacing = ui.spacing().item_spacing;
+                let columns = ((ui.available_width() + spacing.x) / (Self::CELL + spacing.x))
+                    .floor()
+                    .max(1.0) as usize;
+                if self.navigate(ui, columns) {
+                    opened.extend(self.selected.iter().copied());
+                }
+
+                let row_height = Self::CELL + Self::LABEL;
+                let mut scroll = ScrollArea::vertical().auto_shrink([false, false]);
+                let row = self.cursor / columns;
+                if std::mem::take(&mut self.reveal) && !self.shown_rows.contains(&row) {
+                    let stride = row_height + spacing.y;
+                    let offset = if row < self.shown_rows.start {
+                        row as f32 * stride
+                    } else {
+                        (row + 1) as f32 * stride - self.view_height
+                    };
+                    scroll = scroll.vertical_scroll_offset(offset.max(0.0));
+                }
+                let rows = self.files.len().div_ceil(columns);
+                let output = scroll.show_rows(ui, row_height, rows, |ui, shown| {
+                    for row in shown.clone() {
+                        ui.horizontal(|ui| {
+                            let end = ((row + 1) * columns).min(self.files.len());
+                            for index in row * columns..end {
+                                let response = self.cell(ui, app, index);
+                                if response.double_clicked() {
+                                    opened.insert(index);
+                                } else if response.clicked() {
+                                    self.click(ui, index);
+                                }
+                            }
+                        });
+                    }
+                    shown
+                });
+                // Thumbnails scrolled far out of view are read again if needed.
+                let kept = output.inner.start.saturating_sub(Self::KEPT_ROWS) * columns
+                    ..(output.inner.end + Self::KEPT_ROWS) * columns;
+                self.textures.retain(|index, _| kept.contains(index));
+                self.thumbnails.lock().retain(|index, thumbnail| {
+                    kept.contains(index) || matches!(thumbnail, Thumbnail::Reading)
+                });
+                self.shown_rows = output.inner;
+                self.view_height = output.inner_rect.height();
+            });
+
+        if !opened.is_empty() {
+            let paths = opened
+                .into_iter()
+                .map(|index| self.files[index].clone())
+                .collect();
+            app.rt.spawn(app.clone().open_files(paths));
+        }
+        open
+    }
+
+    /// Move the cursor with the arrow keys, extending the selection while
+    /// Shift is held. Returns whether Enter was pressed to open the selection.
+    fn navigate(&mut self, ui: &Ui, columns: usize) -> bool {
+        if self.files.is_empty() || ui.memory(|memory| memory.focus().is_some()) {
+            return false;
+        }
+        // The keys are taken from the rest of the interface while the
+        // gallery is open, such as the layer hierarchy.
+        let (step, extend, enter) = ui.input_mut(|input| {
+            let step = if input.consume_key(Modifiers::NONE, Key::ArrowRight) {
+                1
+            } else if input.consume_key(Modifiers::NONE, Key::ArrowLeft) {
+                -1
+            } else if input.consume_key(Modifiers::NONE, Key::ArrowDown) {
+                columns as isize
+            } else if input.consume_key(Modifiers::NONE, Key::ArrowUp) {
+                -(columns as isize)
+            } else {
+                0
+            };
+            (
+                step,
+                input.modifiers.shift,
+                input.consume_key(Modifiers::NONE, Key::Enter),
+            )
+        });
+        if step != 0 {
+            self.cursor = self
+                .cursor
+                .saturating_add_signed(step)
+                .min(self.files.len() - 1);
+            if !extend {
+                self.selected.clear();
+            }
+            self.selected.insert(self.cursor);
+            self.reveal = true;
+        }
+        if enter && self.selected.is_empty() {
+            self.selected.insert(self.cursor);
+        }
+        enter
+    }
+
+    /// Select a clicked file, or toggle it with Ctrl/Cmd held.
+    fn click(&mut self, ui: &Ui, index: usize) {
+        if ui.input(|input| input.modifiers.command) {
+            if !self.selected.remove(&index) {
+                self.selected.insert(index);
+            }
+        } else {
+            self.selected = BTreeSet::from([index]);
+        }
+        self.cursor = index;
+    }
+
+    fn cell(&mut self, ui: &mut Ui, app: &App, index: usize) -> Response {
+        let (rect, response) =
+            ui.allocate_exact_size(vec2(Self::CELL, Self::CELL + Self::LABEL), Sense::click());
+        let path = &self.files[index];
+        let response = response.on_hover_text(path.display().to_string());
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+
+        let visuals = ui.visuals();
+        let fill = if self.selected.contains(&index) {
+            visuals.selection.bg_fill
+        } else if response.hovered() {
+            visuals.widgets.hovered.weak_bg_fill
+        } else {
+            Color32::TRANSPARENT
+        };
+        let stroke = if index == self.cursor {
+            visuals.selection.stroke
+        } else {
+            Stroke::NONE
+        };
+        let text_color = visuals.text_color();
+        let weak_color = visuals.weak_text_color();
+        let painter = ui.painter_at(rect);
+        painter.rect(rect, 4.0, fill, stroke);
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        painter.text(
+            pos2(rect.center().x, rect.bottom() - Self::LABEL / 2.0),
+            Align2::CENTER_CENTER,
+            name,
+            FontId::proportional(12.0),
+            text_color,
+        );
+
+        let frame = Rect::from_min_size(rect.min, Vec2::splat(Self::CELL)).shrink(8.0);
+        match self.texture(ui.ctx(), app, index) {
+            Some(texture) => {
+                let size = texture.size_vec2();
+                let scale = (frame.size() / size).min_elem();
+                painter.image(
+                    texture.id(),
+                    Rect::from_center_size(frame.center(), size * scale),
+                    Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                    Color32::WHITE,
+                );
+            }
+            None => {
+                let missing =
+                    matches!(self.thumbnails.lock().get(&index), Some(Thumbnail::Missing));
+                painter.text(
+                    frame.center(),
+                    Align2::CENTER_CENTER,
+                    if missing {
+                        "No Thumbnail"
+                    } else {
+                        "Loading…"
+                    },
+                    FontId::proportional(12.0),
+                    weak_color,
+                );
+            }
+        }
+        response
+    }
+
+    /// Texture of the thumbnail of a file, reading it in the background the
+    /// first time that it is asked for.
+    fn texture(&mut self, context: &Context, app: &App, index: usize) -> Option<&TextureHandle> {
+        if !self.textures.contains_key(&index) {
+            let mut thumbnails = self.thumbnails.lock();
+            match thumbnails.remove(&index) {
+                None => {
+                    thumbnails.insert(index, Thumbnail::Reading);
+                    let thumbnails = self.thumbnails.clone();
+                    let path = self.files[index].clone();
+                    let context = context.clone();
+                    app.rt.spawn_blocking(move || {
+                        let thumbnail = match ProcreateFile::read_thumbnail(&path) {
+                            Ok(image) => {
+                                let (width, height) = image.dimensions();
+                                let scale = (Self::THUMBNAIL_SIZE as f32
+                                    / width.max(height) as f32)
+                                    .min(1.0);
+                                let image = image::imageops::thumbnail(
+                                    &image,
+                                    ((width as f32 * scale) as u32).max(1),
+                                    ((height as f32 * scale) as u32).max(1),
+                                );
+                                Thumbnail::Read(ColorImage::from_rgba_unmultiplied(
+                                    [image.width() as usize, image.height() as usize],
+                                    image.as_raw(),
+                                ))
+                            }
+                            Err(err) => {
+                                tracing::debug!("No thumbnail in {}: {err}", path.display());
+                                Thumbnail::Missing
+                            }
+                        };
+                        thumbnails.lock().insert(index, thumbnail);
+                        context.request_repaint();
+                    });
+                }
+                Some(Thumbnail::Read(image)) => {
+                    let texture = context.load_texture(
+                        format!("gallery-{index}"),
+                        image,
+                        TextureOptions::LINEAR,
+                    );
+                    self.textures.insert(index, texture);
+                }
+                Some(thumbnail) => {
+                    thumbnails.insert(index, thumbnail);
+                }
+            }
+        }
+        self.textures.get(&index)
+    }
+}
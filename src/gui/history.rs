@@ -0,0 +1,468 @@
+use super::command::{BackgroundProperties, InstanceCommand};
+use silica::{LayerProperties, SilicaGroup, SilicaHierarchy};
+use std::time::{Duration, Instant};
+
+/// Everything about a file that can be edited in the viewer, captured
+/// before and after an edit to find what it changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditState {
+    /// Name and properties of every layer and group in document order.
+    nodes: Vec<(Option<String>, LayerProperties)>,
+    background: BackgroundProperties,
+}
+
+impl EditState {
+    pub fn new(layers: &SilicaGroup, background: BackgroundProperties) -> Self {
+        Self {
+            nodes: layers
+                .iter()
+                .map(|node| {
+                    let name = match node {
+                        SilicaHierarchy::Layer(layer) => layer.name.clone(),
+                        SilicaHierarchy::Group(group) => group.name.clone(),
+                    };
+                    (name, LayerProperties::of_node(node))
+                })
+                .collect(),
+            background,
+        }
+    }
+
+    /// Commands that change a file from this state to another.
+    pub fn commands_to(&self, after: &Self) -> Vec<InstanceCommand> {
+        Self::changes(self, after)
+            .iter()
+            .map(|change| change.command(false))
+            .collect()
+    }
+
+    fn changes(before: &Self, after: &Self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        // The hierarchy can not be restructured in the viewer.
+        if before.nodes.len() == after.nodes.len() {
+            for (index, ((name, before), (_, after))) in
+                before.nodes.iter().zip(&after.nodes).enumerate()
+            {
+                if before != after {
+                    changes.push(Change::Node {
+                        index,
+                        name: name.clone(),
+                        before: *before,
+                        after: *after,
+                    });
+                }
+            }
+        }
+        if before.background != after.background {
+            changes.push(Change::Background {
+                before: before.background,
+                after: after.background,
+            });
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Change {
+    /// Layer or group at an index of the hierarchy in document order.
+    Node {
+        index: usize,
+        name: Option<String>,
+        before: LayerProperties,
+        after: LayerProperties,
+    },
+    Background {
+        before: BackgroundProperties,
+        after: BackgroundProperties,
+    },
+}
+
+impl Change {
+    /// Whether only a continuous property changed, which is edited in many
+    /// small steps such as while dragging a slider.
+    fn is_continuous(&self) -> bool {
+        match self {
+            Self::Node { before, after, .. } => {
+                LayerProperties {
+                    opacity: after.opacity,
+                    ..*before
+                } == *after
+            }
+            Self::Background { before, after } => {
+                BackgroundProperties {
+                    color: after.color,
+                    ..*before
+                } == *after
+            }
+        }
+    }
+
+    /// Whether the next change continues editing the same continuous
+    /// property of the same target, so that both are undone together.
+    fn continues(&self, next: &Self) -> bool {
+        let same_target = match (self, next) {
+            (Self::Node { index, .. }, Self::Node { index: next, .. }) => index == next,
+            (Self::Background { .. }, Self::Background { .. }) => true,
+            _ => false,
+        };
+        same_target && self.is_continuous() && next.is_continuous()
+    }
+
+    /// Command that makes the change, or reverts it.
+    fn command(&self, undo: bool) -> InstanceCommand {
+        match *self {
+            Self::Node {
+                index,
+                before,
+                after,
+                ..
+            } => InstanceCommand::Node {
+                index,
+                properties: if undo { before } else { after },
+            },
+            Self::Background { before, after } => {
+                InstanceCommand::Background(if undo { before } else { after })
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Node {
+                name,
+                before,
+                after,
+                ..
+            } => {
+                let name = name.as_deref().unwrap_or("Unnamed");
+                let toggled = |on: &str, off: &str, value: bool| {
+                    format!("{} {name}", if value { on } else { off })
+                };
+                if *before
+                    == (LayerProperties {
+                        hidden: before.hidden,
+                        ..*after
+                    })
+                {
+                    toggled("Hide", "Show", after.hidden)
+                } else if *before
+                    == (LayerProperties {
+                        clipped: before.clipped,
+                        ..*after
+                    })
+                {
+                    toggled("Clip", "Unclip", after.clipped)
+                } else if *before
+                    == (LayerProperties {
+                        opacity: before.opacity,
+                        ..*after
+                    })
+                {
+                    format!("Opacity of {name} to {:.0}%", after.opacity * 100.0)
+                } else if *before
+                    == (LayerProperties {
+                        blend: before.blend,
+                        ..*after
+                    })
+                {
+                    format!("Blend {name} with {}", after.blend.as_str())
+                } else {
+                    format!("Change {name}")
+                }
+            }
+            Self::Background { before, after } => {
+                let toggle = |value: bool| if value { "Hide" } else { "Show" };
+                if before.hidden != after.hidden {
+                    format!("{} background", toggle(after.hidden))
+                } else if before.image_hidden != after.image_hidden {
+                    format!("{} background image", toggle(after.image_hidden))
+                } else {
+                    "Change background color".to_string()
+                }
+            }
+        }
+    }
+}
+
+/// Changes made by one interaction with the viewer.
+#[derive(Debug)]
+pub struct Edit {
+    changes: Vec<Change>,
+    /// When the edit was last extended.
+    at: Instant,
+}
+
+impl Edit {
+    pub fn describe(&self) -> String {
+        match self.changes.as_slice() {
+            [change] => change.describe(),
+            changes => format!("Change {} properties", changes.len()),
+        }
+    }
+}
+
+/// Undo and redo stacks of the edits made to a file in the viewer.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// Whether the last edit may be extended by the next one, which is not
+    /// the case after undoing or redoing.
+    extendable: bool,
+}
+
+impl EditHistory {
+    /// Number of edits that can be undone.
+    const CAPACITY: usize = 200;
+    /// Edits to the same continuous property within this time of each
+    /// other are undone together.
+    const COALESCE: Duration = Duration::from_millis(750);
+
+    /// Record the edit that changed the file from one state to another.
+    pub fn record(&mut self, before: &EditState, after: &EditState) {
+        let changes = EditState::changes(before, after);
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+
+        if let (Some(last), [next], true) =
+            (self.undo.last_mut(), changes.as_slice(), self.extendable)
+        {
+            if let [change] = last.changes.as_mut_slice() {
+                if last.at.elapsed() < Self::COALESCE && change.continues(next) {
+                    match (change, next) {
+                        (Change::Node { after, .. }, Change::Node { after: next, .. }) => {
+                            *after = *next;
+                        }
+                        (
+                            Change::Background { after, .. },
+                            Change::Background { after: next, .. },
+                        ) => *after = *next,
+                        _ => unreachable!(),
+                    }
+                    last.at = Instant::now();
+                    return;
+                }
+            }
+        }
+
+        self.undo.push(Edit {
+            changes,
+            at: Instant::now(),
+        });
+        self.extendable = true;
+        if self.undo.len() > Self::CAPACITY {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Revert the last edit, returning the commands that do so if there
+    /// was one.
+    pub fn undo(&mut self) -> Option<Vec<InstanceCommand>> {
+        let edit = self.undo.pop()?;
+        let commands = edit
+            .changes
+            .iter()
+            .rev()
+            .map(|change| change.command(true))
+            .collect();
+        self.redo.push(edit);
+        self.extendable = false;
+        Some(commands)
+    }
+
+    /// Apply the last undone edit again, returning the commands that do so
+    /// if there was one.
+    pub fn redo(&mut self) -> Option<Vec<InstanceCommand>> {
+        let edit = self.redo.pop()?;
+        let commands = edit
+            .changes
+            .iter()
+            .map(|change| change.command(false))
+            .collect();
+        self.undo.push(edit);
+        self.extendable = false;
+        Some(commands)
+    }
+
+    /// Edits that can be undone, oldest first.
+    pub fn done(&self) -> &[Edit] {
+        &self.undo
+    }
+
+    /// Edits that can be redone, the next one last.
+    pub fn undone(&self) -> &[Edit] {
+        &self.redo
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use silica::fixture::{loaded_group, loaded_layer};
+    use silica::{BlendingMode, SilicaLayer};
+
+    /// Root holding a layer named "Sky", then a group of an unnamed layer.
+    fn layers() -> SilicaGroup {
+        let sky = SilicaLayer {
+            name: Some("Sky".to_string()),
+            ..loaded_layer(0)
+        };
+        loaded_group(
+            u32::MAX,
+            vec![
+                SilicaHierarchy::Layer(sky),
+                SilicaHierarchy::Group(loaded_group(
+                    1,
+                    vec![SilicaHierarchy::Layer(loaded_layer(2))],
+                )),
+            ],
+        )
+    }
+
+    fn background() -> BackgroundProperties {
+        BackgroundProperties {
+            hidden: false,
+            color: [1.0; 4],
+            image_hidden: false,
+        }
+    }
+
+    /// State after changing the properties of the node at an index.
+    fn edited(index: usize, edit: impl FnOnce(&mut LayerProperties)) -> EditState {
+        let mut state = EditState::new(&layers(), background());
+        edit(&mut state.nodes[index].1);
+        state
+    }
+
+    fn original() -> EditState {
+        EditState::new(&layers(), background())
+    }
+
+    #[test]
+    fn edits_are_undone_and_redone_with_commands() {
+        let before = original();
+        let after = edited(0, |node| node.hidden = true);
+        let mut history = EditHistory::default();
+        history.record(&before, &after);
+        history.record(&after, &after);
+        assert_eq!(history.done().len(), 1);
+        assert!(history.redo().is_none());
+        assert_eq!(
+            before.commands_to(&after),
+            [InstanceCommand::Node {
+                index: 0,
+                properties: after.nodes[0].1,
+            }]
+        );
+
+        let undo = history.undo().unwrap();
+        assert_eq!(
+            undo,
+            [InstanceCommand::Node {
+                index: 0,
+                properties: before.nodes[0].1,
+            }]
+        );
+        assert!(history.undo().is_none());
+        assert_eq!(history.undone().len(), 1);
+        assert_eq!(history.redo().unwrap(), before.commands_to(&after));
+        assert!(history.redo().is_none());
+
+        history.undo();
+        history.record(&before, &edited(2, |node| node.clipped = true));
+        assert!(history.undone().is_empty());
+    }
+
+    #[test]
+    fn dragging_a_slider_is_one_edit() {
+        let mut history = EditHistory::default();
+        let steps = [1.0, 0.8, 0.6, 0.4].map(|opacity| edited(0, |node| node.opacity = opacity));
+        for pair in steps.windows(2) {
+            history.record(&pair[0], &pair[1]);
+        }
+        assert_eq!(history.done().len(), 1);
+        assert_eq!(history.done()[0].describe(), "Opacity of Sky to 40%");
+
+        // Another target, or a toggle, starts a new edit.
+        history.record(
+            &steps[3],
+            &edited(0, |node| {
+                node.opacity = 0.4;
+                node.hidden = true;
+            }),
+        );
+        assert_eq!(history.done().len(), 2);
+
+        // So does an edit after undoing.
+        history.undo();
+        history.redo();
+        history.record(&steps[3], &edited(0, |node| node.opacity = 0.2));
+        assert_eq!(history.done().len(), 3);
+    }
+
+    #[test]
+    fn edits_describe_what_they_changed() {
+        let node = |index, edit: fn(&mut LayerProperties)| (original(), edited(index, edit));
+        let background = |edit: fn(&mut BackgroundProperties)| {
+            let mut after = original();
+            edit(&mut after.background);
+            (original(), after)
+        };
+        let cases = [
+            (node(0, |node| node.hidden = true), "Hide Sky"),
+            (node(2, |node| node.clipped = true), "Clip Unnamed"),
+            (node(0, |node| node.opacity = 0.25), "Opacity of Sky to 25%"),
+            (
+                node(1, |node| node.blend = BlendingMode::Multiply),
+                "Blend Unnamed with Multiply",
+            ),
+            (
+                node(0, |node| {
+                    node.hidden = true;
+                    node.opacity = 0.5;
+                }),
+                "Change Sky",
+            ),
+            (background(|bg| bg.hidden = true), "Hide background"),
+            (
+                background(|bg| bg.image_hidden = true),
+                "Hide background image",
+            ),
+            (
+                background(|bg| bg.color[0] = 0.5),
+                "Change background color",
+            ),
+        ];
+        for ((before, after), description) in cases {
+            let mut history = EditHistory::default();
+            history.record(&before, &after);
+            assert_eq!(history.done()[0].describe(), description);
+        }
+
+        let mut history = EditHistory::default();
+        let mut after = edited(0, |node| node.hidden = true);
+        after.background.hidden = true;
+        history.record(&original(), &after);
+        assert_eq!(history.done()[0].describe(), "Change 2 properties");
+    }
+
+    #[test]
+    fn history_keeps_the_latest_edits() {
+        let mut history = EditHistory::default();
+        let hidden = edited(0, |node| node.hidden = true);
+        for _ in 0..EditHistory::CAPACITY + 5 {
+            history.record(&original(), &hidden);
+            history.record(&hidden, &original());
+        }
+        assert_eq!(history.done().len(), EditHistory::CAPACITY);
+        history.clear();
+        assert!(history.done().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+use egui::Key;
+
+/// Canvas shortcut that is a single key press without modifiers, and can
+/// be bound to another key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shortcut {
+    RotateLeft,
+    RotateRight,
+    /// Switch to the composite stored in the file and back.
+    StoredComposite,
+    /// Show the active canvas alone in fullscreen.
+    Present,
+}
+
+impl Shortcut {
+    pub const fn all() -> &'static [Self] {
+        &[
+            Self::RotateLeft,
+            Self::RotateRight,
+            Self::StoredComposite,
+            Self::Present,
+        ]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::RotateLeft => "Rotate Left",
+            Self::RotateRight => "Rotate Right",
+            Self::StoredComposite => "Stored Composite",
+            Self::Present => "Present",
+        }
+    }
+
+    /// Key of the shortcut in the `[keys]` section of the settings.
+    pub const fn setting(&self) -> &'static str {
+        match self {
+            Self::RotateLeft => "rotate_left",
+            Self::RotateRight => "rotate_right",
+            Self::StoredComposite => "stored_composite",
+            Self::Present => "present",
+        }
+    }
+}
+
+/// Key bound to each shortcut, in the order of [`Shortcut::all`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap([Key; 4]);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self([Key::OpenBracket, Key::CloseBracket, Key::R, Key::P])
+    }
+}
+
+impl Keymap {
+    pub fn key(&self, shortcut: Shortcut) -> Key {
+        self.0[shortcut as usize]
+    }
+
+    pub fn key_mut(&mut self, shortcut: Shortcut) -> &mut Key {
+        &mut self.0[shortcut as usize]
+    }
+
+    /// Whether the key can be bound to a shortcut. Keys that the viewer
+    /// uses otherwise, such as Escape, F3 and the arrows, or that type into
+    /// text fields are left out.
+    pub fn is_bindable(key: Key) -> bool {
+        !matches!(
+            key,
+            Key::Escape
+                | Key::Tab
+                | Key::Enter
+                | Key::Space
+                | Key::Backspace
+                | Key::Delete
+                | Key::ArrowLeft
+                | Key::ArrowRight
+                | Key::ArrowUp
+                | Key::ArrowDown
+                | Key::Copy
+                | Key::Cut
+                | Key::Paste
+                | Key::F3
+                | Key::F11
+        )
+    }
+
+    /// Keys that can be bound to a shortcut.
+    pub fn bindable_keys() -> impl Iterator<Item = Key> {
+        Key::ALL
+            .iter()
+            .copied()
+            .filter(|key| Self::is_bindable(*key))
+    }
+}
@@ -0,0 +1,363 @@
+//! Krita documents keeping the layer hierarchy, for handing files over to
+//! Krita without flattening them.
+//!
+//! Clipped layers are written with Krita's inherit alpha, which clips them
+//! to everything beneath them in their group rather than to the closest
+//! unclipped layer. Both agree when the layers a clipped layer sits above
+//! are all clipped to the bottom layer of the group.
+
+use compositor::metadata::ExportMetadata;
+use image::{imageops, ImageOutputFormat, RgbaImage};
+use silica::{BlendingMode, SilicaGroup, SilicaHierarchy};
+use std::fmt::Write as _;
+use std::io::{Cursor, Seek, Write};
+use thiserror::Error;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+#[derive(Error, Debug)]
+pub enum KraError {
+    #[error("zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("image error: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+/// Everything about a file that goes into a Krita document, besides the
+/// pixels of its layers.
+pub struct KraDocument<'a> {
+    pub metadata: &'a ExportMetadata,
+    pub layers: &'a SilicaGroup,
+    /// Texture of the background image and whether it is hidden.
+    pub background_image: Option<(u32, bool)>,
+    pub background_color: [f32; 4],
+    pub background_hidden: bool,
+    /// Flattened image with straight alpha, which also sets the size of the
+    /// document.
+    pub merged: &'a RgbaImage,
+}
+
+/// Krita's name of the closest blending mode.
+fn composite_op(blend: BlendingMode) -> &'static str {
+    match blend {
+        BlendingMode::Normal => "normal",
+        BlendingMode::Multiply => "multiply",
+        BlendingMode::Screen => "screen",
+        BlendingMode::Add => "add",
+        BlendingMode::Lighten => "lighten",
+        BlendingMode::Exclusion => "exclusion",
+        BlendingMode::Difference => "diff",
+        BlendingMode::Subtract => "subtract",
+        BlendingMode::LinearBurn => "linear_burn",
+        BlendingMode::ColorDodge => "dodge",
+        BlendingMode::ColorBurn => "burn",
+        BlendingMode::Overlay => "overlay",
+        BlendingMode::HardLight => "hard_light",
+        BlendingMode::Color => "color",
+        BlendingMode::Luminosity => "luminize",
+        BlendingMode::Hue => "hue",
+        BlendingMode::Saturation => "saturation",
+        BlendingMode::SoftLight => "soft_light",
+        BlendingMode::Darken => "darken",
+        BlendingMode::HardMix => "hard_mix_photoshop",
+        BlendingMode::VividLight => "vivid_light",
+        BlendingMode::LinearLight => "linear light",
+        BlendingMode::PinLight => "pin_light",
+        BlendingMode::LighterColor => "lighter color",
+        BlendingMode::DarkerColor => "darker color",
+        BlendingMode::Divide => "divide",
+    }
+}
+
+/// Escape text for an XML attribute.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Pixel data of a layer in Krita's tiled format, version 2. Tiles are
+/// stored uncompressed, with each byte of a BGRA pixel in its own plane,
+/// and fully transparent tiles are left out.
+fn encode_tiles(image: &RgbaImage) -> Vec<u8> {
+    const TILE: u32 = 64;
+    const PLANE: usize = (TILE * TILE) as usize;
+
+    let mut tiles = Vec::new();
+    let mut count = 0;
+    for y in (0..image.height()).step_by(TILE as usize) {
+        for x in (0..image.width()).step_by(TILE as usize) {
+            // Raw data flag, then the blue, green, red and alpha planes.
+            let mut data = vec![0; 1 + 4 * PLANE];
+            let mut empty = true;
+            for ty in 0..TILE.min(image.height() - y) {
+                for tx in 0..TILE.min(image.width() - x) {
+                    let [r, g, b, a] = image.get_pixel(x + tx, y + ty).0;
+                    let i = 1 + (ty * TILE + tx) as usize;
+                    data[i] = b;
+                    data[i + PLANE] = g;
+                    data[i + 2 * PLANE] = r;
+                    data[i + 3 * PLANE] = a;
+                    empty &= a == 0;
+                }
+            }
+            if !empty {
+                tiles.extend_from_slice(format!("{x},{y},LZF,{}\n", data.len()).as_bytes());
+                tiles.extend_from_slice(&data);
+                count += 1;
+            }
+        }
+    }
+
+    let mut out =
+        format!("VERSION 2\nTILEWIDTH {TILE}\nTILEHEIGHT {TILE}\nPIXELSIZE 4\nDATA {count}\n")
+            .into_bytes();
+    out.extend_from_slice(&tiles);
+    out
+}
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, KraError> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+struct KraWriter<'a, W: Write + Seek> {
+    zip: ZipWriter<W>,
+    /// Layer files are numbered in the order they are written.
+    next: usize,
+    /// Straight RGBA pixels of a layer texture, the size of the document.
+    pixels: &'a mut dyn FnMut(u32) -> RgbaImage,
+}
+
+impl<W: Write + Seek> KraWriter<'_, W> {
+    /// Directory of the document inside the archive.
+    const IMAGE: &'static str = "Image";
+
+    fn file(&mut self, name: &str, data: &[u8]) -> Result<(), KraError> {
+        self.zip.start_file(name, FileOptions::default())?;
+        self.zip.write_all(data)?;
+        Ok(())
+    }
+
+    /// Name of the next layer file.
+    fn filename(&mut self) -> String {
+        self.next += 1;
+        format!("layer{}", self.next)
+    }
+
+    /// Write the pixels of a paint layer and return its attributes.
+    fn paint_layer(&mut self, name: &str, texture: u32, hidden: bool) -> Result<String, KraError> {
+        let filename = self.filename();
+        let tiles = encode_tiles(&(self.pixels)(texture));
+        self.file(&format!("{}/layers/{filename}", Self::IMAGE), &tiles)?;
+        let attributes = format!(
+            r#"name="{}" filename="{filename}" visible="{}" x="0" y="0" locked="0" collapsed="0" colorspacename="RGBA" channelflags="""#,
+            escape(name),
+            u8::from(!hidden),
+        );
+        Ok(attributes)
+    }
+
+    /// Write the layers of a group, from the top down like Krita lists them.
+    fn group(&mut self, xml: &mut String, group: &SilicaGroup) -> Result<(), KraError> {
+        for child in &group.children {
+            match child {
+                SilicaHierarchy::Layer(layer) => {
+                    let attributes = self.paint_layer(
+                        layer.name.as_deref().unwrap_or("Layer"),
+                        layer.image,
+                        layer.hidden,
+                    )?;
+                    let _ = writeln!(
+                        xml,
+                        r#"<layer nodetype="paintlayer" {attributes} opacity="{}" compositeop="{}" inheritalpha="{}"/>"#,
+                        (layer.opacity.clamp(0.0, 1.0) * 255.0).round() as u8,
+                        composite_op(layer.blend),
+                        u8::from(layer.clipped),
+                    );
+                }
+                SilicaHierarchy::Group(group) => {
+                    let filename = self.filename();
+                    let _ = writeln!(
+                        xml,
+                        r#"<layer nodetype="grouplayer" name="{}" filename="{filename}" visible="{}" x="0" y="0" locked="0" collapsed="0" opacity="{}" compositeop="{}" passthrough="{}">"#,
+                        escape(group.name.as_deref().unwrap_or("Group")),
+                        u8::from(!group.hidden),
+                        (group.opacity.clamp(0.0, 1.0) * 255.0).round() as u8,
+                        composite_op(group.blend),
+                        // Procreate only isolates groups that need it.
+                        u8::from(!group.is_isolated()),
+                    );
+                    xml.push_str("<layers>\n");
+                    self.group(xml, group)?;
+                    xml.push_str("</layers>\n</layer>\n");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a document as a Krita archive. `pixels` returns the straight RGBA
+/// pixels of a layer texture, in the orientation of the merged image.
+pub fn write_kra<W: Write + Seek>(
+    out: W,
+    document: &KraDocument<'_>,
+    pixels: &mut dyn FnMut(u32) -> RgbaImage,
+) -> Result<W, KraError> {
+    let (width, height) = document.merged.dimensions();
+    let mut writer = KraWriter {
+        zip: ZipWriter::new(out),
+        next: 0,
+        pixels,
+    };
+    // The mime type comes first and uncompressed, so that it can be sniffed.
+    writer.zip.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    writer.zip.write_all(b"application/x-krita")?;
+
+    let mut layers = String::from("<layers>\n");
+    writer.group(&mut layers, document.layers)?;
+    if let Some((texture, hidden)) = document.background_image {
+        let attributes = writer.paint_layer("Background Image", texture, hidden)?;
+        let _ = writeln!(
+            layers,
+            r#"<layer nodetype="paintlayer" {attributes} opacity="255" compositeop="normal"/>"#
+        );
+    }
+    // The background color fills a layer through its default pixel alone.
+    let filename = writer.filename();
+    let path = format!("{}/layers/{filename}", KraWriter::<W>::IMAGE);
+    writer.file(&path, &encode_tiles(&RgbaImage::new(0, 0)))?;
+    let [r, g, b, a] = document
+        .background_color
+        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    writer.file(&format!("{path}.defaultpixel"), &[b, g, r, a])?;
+    let _ = writeln!(
+        layers,
+        r#"<layer nodetype="paintlayer" name="Background" filename="{filename}" visible="{}" x="0" y="0" locked="1" collapsed="0" colorspacename="RGBA" channelflags="" opacity="255" compositeop="normal"/>"#,
+        u8::from(!document.background_hidden),
+    );
+    layers.push_str("</layers>\n");
+
+    let metadata = document.metadata;
+    let dpi = metadata.dpi.unwrap_or(72.0);
+    // Krita reads the profile itself from the annotation when there is one.
+    let profile = metadata
+        .profile
+        .as_ref()
+        .and_then(|profile| profile.description())
+        .unwrap_or_else(|| "sRGB-elle-V2-srgbtrc.icc".to_string());
+    let maindoc = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE DOC PUBLIC '-//KDE//DTD krita 2.0//EN' 'http://www.calligra.org/DTD/krita-2.0.dtd'>
+<DOC xmlns="http://www.calligra.org/DTD/krita" syntaxVersion="2" kritaVersion="4.4.2" editor="Silicate">
+<IMAGE name="{}" mime="application/x-kra" width="{width}" height="{height}" colorspacename="RGBA" profile="{}" x-res="{dpi}" y-res="{dpi}" description="">
+{layers}</IMAGE>
+</DOC>
+"#,
+        KraWriter::<W>::IMAGE,
+        escape(&profile),
+    );
+    writer.file("maindoc.xml", maindoc.as_bytes())?;
+
+    let info = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE document-info PUBLIC '-//KDE//DTD document-info 1.1//EN' 'http://www.calligra.org/DTD/document-info-1.1.dtd'>
+<document-info xmlns="http://www.calligra.org/DTD/document-info">
+<about><title>{}</title></about>
+<author><full-name>{}</full-name></author>
+</document-info>
+"#,
+        escape(metadata.title.as_deref().unwrap_or_default()),
+        escape(metadata.author.as_deref().unwrap_or_default()),
+    );
+    writer.file("documentinfo.xml", info.as_bytes())?;
+
+    if let Some(profile) = &metadata.profile {
+        let path = format!("{}/annotations/icc", KraWriter::<W>::IMAGE);
+        writer.file(&path, &profile.icc)?;
+    }
+    writer.file("mergedimage.png", &encode_png(document.merged)?)?;
+    let preview = imageops::thumbnail(
+        document.merged,
+        width.min(256 * width / width.max(height)).max(1),
+        height.min(256 * height / width.max(height)).max(1),
+    );
+    writer.file("preview.png", &encode_png(&preview)?)?;
+
+    Ok(writer.zip.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Tile entries of encoded layer data, as their headers and data.
+    fn tiles(data: &[u8]) -> Vec<(String, &[u8])> {
+        let text_end = |data: &[u8]| data.iter().position(|&b| b == b'\n').unwrap() + 1;
+        let mut rest = data;
+        let mut header = Vec::new();
+        for _ in 0..5 {
+            let end = text_end(rest);
+            header.push(
+                std::str::from_utf8(&rest[..end])
+                    .unwrap()
+                    .trim()
+                    .to_string(),
+            );
+            rest = &rest[end..];
+        }
+        let count = header[4].strip_prefix("DATA ").unwrap().parse().unwrap();
+        let mut tiles = Vec::new();
+        for _ in 0..count {
+            let end = text_end(rest);
+            let entry = std::str::from_utf8(&rest[..end])
+                .unwrap()
+                .trim()
+                .to_string();
+            let len = entry.rsplit(',').next().unwrap().parse::<usize>().unwrap();
+            tiles.push((entry, &rest[end..end + len]));
+            rest = &rest[end + len..];
+        }
+        assert!(rest.is_empty());
+        tiles
+    }
+
+    #[test]
+    fn transparent_tiles_are_left_out() {
+        let mut image = RgbaImage::new(100, 70);
+        image.put_pixel(70, 65, Rgba([10, 20, 30, 40]));
+        let data = encode_tiles(&image);
+        let tiles = tiles(&data);
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(tiles[0].0, "64,64,LZF,16385");
+    }
+
+    #[test]
+    fn tile_channels_are_planar_bgra() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(1, 0, Rgba([10, 20, 30, 40]));
+        let data = encode_tiles(&image);
+        let (_, tile) = tiles(&data)[0];
+        let plane = 64 * 64;
+        assert_eq!(tile[0], 0, "stored uncompressed");
+        let pixel = [0, 1, 2, 3].map(|channel| tile[1 + channel * plane + 1]);
+        assert_eq!(pixel, [30, 20, 10, 40]);
+    }
+}
@@ -1,31 +1,76 @@
-use crate::silica::{BlendingMode, SilicaHierarchy};
-use crate::silica::{SilicaGroup, SilicaLayer};
-use egui::load::SizedTexture;
+use compositor::adjust::Adjustments;
+use compositor::dev::{GpuBackend, GpuHandle, GpuPreference};
+use compositor::post::{MarkPlacement, PostStep};
+use compositor::profile::{ColorProfile, ExportColorSpace};
+use compositor::tex::{ExportKind, ResampleFilter};
+use compositor::timing::GpuTimer;
+use compositor::upload::UploadThrottle;
+use compositor::{BlendSpace, ToneMap};
 use egui::*;
 use egui_dock::{NodeIndex, SurfaceIndex};
-use std::collections::HashMap;
+use silica::{BlendingMode, DocumentUnit, SilicaHierarchy};
+use silica::{SilicaGroup, SilicaLayer};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use super::app::{App, Instance, InstanceKey, UserEvent};
-use super::canvas;
+use super::app::{
+    lost_backends, App, CompareTarget, DifferenceSource, Instance, InstanceKey, PaperOptions,
+    RestoredLayout, UserEvent, LOST_BACKENDS_VAR,
+};
+use super::atlas::AtlasInspector;
+use super::canvas::{
+    self, CompareMode, DoubleClickAction, DoubleClickActions, MarginUnit, RotationSnap,
+    Transparency, TransparencyStyle,
+};
+use super::command::{BackgroundProperties, InstanceCommand};
+use super::export_preset::{ExportPreset, ExportPresets, PresetSize};
+use super::gallery::Gallery;
+use super::history::EditState;
+use super::keymap::{Keymap, Shortcut};
+use super::motion::MotionPreference;
+use super::picker;
+use super::report;
+use super::selection::{self, LayerSelection};
+use super::session::{Session, SessionJournal, SessionLayout, SessionTab};
+use super::settings::{Settings, SettingsBundle, SettingsStore};
+use super::stats::FrameStats;
+use super::switcher::TabSwitcher;
+use super::theme::{self, Theme};
+use super::views::{SavedView, ViewHistory};
+use crate::SOFTWARE;
 
 struct ControlsGui<'a> {
     app: &'a Arc<App>,
     active_canvas: InstanceKey,
     view_options: &'a mut ViewOptions,
+    selection: &'a mut LayerSelection,
+    layer_search: &'a mut String,
+    atlas: &'a mut AtlasInspector,
+    export_presets: &'a mut ExportPresets,
+    adapter_names: &'a mut Option<(GpuBackend, Vec<String>)>,
 }
 
 impl ControlsGui<'_> {
     fn layout_info(&self, ui: &mut Ui) {
         Grid::new("File Grid").show(ui, |ui| {
-            if let Some(Instance { file, .. }) = self
+            if let Some(instance) = self
                 .app
                 .compositor
                 .instances
                 .read()
                 .get(&self.active_canvas)
             {
-                let file = file.read();
+                ui.label("Location");
+                ui.vertical(|ui| {
+                    ui.label(instance.path.display().to_string());
+                    ui.horizontal(|ui| {
+                        CanvasGui::file_actions(ui, self.app, self.active_canvas, instance);
+                    });
+                });
+                ui.end_row();
+                let file = instance.file.read();
                 ui.label("Name");
                 ui.label(file.name.as_deref().unwrap_or("Not Specified"));
                 ui.end_row();
@@ -37,10 +82,183 @@ impl ControlsGui<'_> {
                 ui.end_row();
                 ui.label("Canvas Size");
                 ui.label(format!("{} by {}", file.size.width, file.size.height));
+                ui.end_row();
+                ui.label("Physical Size");
+                match file.physical_size() {
+                    Some((width, height)) => {
+                        // In the unit the canvas was set up in, and in inches
+                        // or millimeters alongside.
+                        let unit = file
+                            .unit
+                            .filter(|unit| unit.per_inch().is_some())
+                            .unwrap_or(DocumentUnit::Millimeters);
+                        let other = if unit == DocumentUnit::Inches {
+                            DocumentUnit::Millimeters
+                        } else {
+                            DocumentUnit::Inches
+                        };
+                        let format = |unit: DocumentUnit| {
+                            let per_inch = unit.per_inch().unwrap_or(1.0);
+                            format!(
+                                "{:.2} by {:.2} {}",
+                                width * per_inch,
+                                height * per_inch,
+                                unit.as_str()
+                            )
+                        };
+                        ui.label(format!("{} ({})", format(unit), format(other)));
+                    }
+                    None => {
+                        ui.label("Not Specified");
+                    }
+                }
+                ui.end_row();
+                ui.label("Resolution");
+                ui.label(
+                    file.dpi
+                        .map_or_else(|| String::from("Not Specified"), |dpi| format!("{dpi} DPI")),
+                );
+                ui.end_row();
+                ui.label("Drawing Time");
+                ui.label(file.tracked_time.map_or_else(
+                    || String::from("Not Tracked"),
+                    |time| {
+                        let seconds = time.as_secs();
+                        format!(
+                            "{}h {:02}m {:02}s",
+                            seconds / 3600,
+                            seconds / 60 % 60,
+                            seconds % 60
+                        )
+                    },
+                ));
+                ui.end_row();
+                ui.label("Time-lapse");
+                let video = &file.video;
+                ui.label(if video.purged {
+                    String::from("Deleted")
+                } else if video.enabled {
+                    [video.resolution.as_deref(), video.quality.as_deref()]
+                        .into_iter()
+                        .flatten()
+                        .fold(String::from("Recording"), |text, setting| {
+                            format!("{text}, {setting}")
+                        })
+                } else {
+                    String::from("Off")
+                });
+                ui.end_row();
+                ui.label("Layers");
+                ui.label(file.layers.iter_layers().count().to_string());
+                ui.end_row();
+                ui.label("Format Coverage");
+                let coverage = &file.coverage;
+                if coverage.is_complete() {
+                    ui.label("Complete");
+                } else {
+                    ui.vertical(|ui| {
+                        ui.label(format!(
+                            "{} unknown classes, {} unknown keys",
+                            coverage.unknown_classes.len(),
+                            coverage.unknown_key_count()
+                        ));
+                        if ui
+                            .button("Copy Schema Summary")
+                            .on_hover_text(
+                                "Copies the unknown class and key names, without any of \
+                                 the document's content, to attach to an issue.",
+                            )
+                            .clicked()
+                        {
+                            ui.output_mut(|o| o.copied_text = coverage.summary(SOFTWARE));
+                        }
+                    });
+                }
+                ui.end_row();
+                ui.label("Metadata");
+                if ui
+                    .button("Copy as JSON")
+                    .on_hover_text(
+                        "Copies the document information and layer tree, with the \
+                         changes made in the viewer, for cataloging tools.",
+                    )
+                    .clicked()
+                {
+                    ui.output_mut(|o| o.copied_text = crate::json::document(&file));
+                }
             } else {
                 ui.label("No file loaded...");
             }
         });
+        self.layout_memory(ui);
+        ui.separator();
+        if ui.button("Open Folder as Gallery").clicked() {
+            self.app.rt.spawn(self.app.clone().gallery_dialog());
+        }
+    }
+
+    /// GPU memory taken up by each open file, with its atlas unloaded or
+    /// reloaded on demand.
+    fn layout_memory(&self, ui: &mut Ui) {
+        let instances = self.app.compositor.instances.read();
+        if instances.is_empty() {
+            return;
+        }
+        let mib = |bytes: u64| format!("{:.1} MiB", bytes as f64 / f64::from(1 << 20));
+        let mut instances = instances.iter().collect::<Vec<_>>();
+        instances.sort_by_key(|(key, _)| key.0);
+
+        ui.separator();
+        CollapsingHeader::new("GPU Memory").show(ui, |ui| {
+            Grid::new("Memory Grid")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("File");
+                    ui.strong("Atlas").on_hover_text(
+                        "Layer textures, unloaded from hidden tabs to stay within the budget.",
+                    );
+                    ui.strong("Output").on_hover_text(
+                        "Composites shown on screen, kept while the atlas is unloaded.",
+                    );
+                    ui.end_row();
+
+                    let (mut atlas_total, mut output_total) = (0, 0);
+                    for (key, instance) in instances {
+                        let (atlas, output) = instance.memory();
+                        atlas_total += atlas.unwrap_or(0);
+                        output_total += output;
+                        let name = instance.file.read().name.clone();
+                        ui.label(name.as_deref().unwrap_or("Untitled Artwork"));
+                        ui.label(atlas.map_or("Unloaded".to_string(), mib));
+                        ui.label(mib(output));
+                        match atlas {
+                            Some(_) => {
+                                if ui
+                                    .button("Unload")
+                                    .on_hover_text("Shown files are reloaded right away.")
+                                    .clicked()
+                                {
+                                    instance.evict();
+                                }
+                            }
+                            None => {
+                                let reloading = instance.reloading.load(Ordering::Acquire);
+                                if ui.add_enabled(!reloading, Button::new("Reload")).clicked() {
+                                    self.app.reload_evicted(*key, instance);
+                                }
+                            }
+                        }
+                        ui.end_row();
+                    }
+
+                    ui.strong("Total");
+                    ui.label(mib(atlas_total));
+                    ui.label(mib(output_total));
+                    ui.label(mib(atlas_total + output_total));
+                    ui.end_row();
+                });
+        });
     }
 
     fn layout_view_control(&mut self, ui: &mut Ui) {
@@ -48,12 +266,110 @@ impl ControlsGui<'_> {
             ui.label("Grid View");
             ui.checkbox(&mut self.view_options.grid, "Enable");
             ui.end_row();
+            ui.label("Pixel Grid");
+            ui.checkbox(&mut self.view_options.pixel_grid, "Enable")
+                .on_hover_text("Outlines every pixel when zoomed in past 800%.");
+            ui.end_row();
+            ui.label("Rulers");
+            ui.checkbox(&mut self.view_options.rulers, "Enable");
+            ui.end_row();
             ui.label("Extended Crosshair");
             ui.checkbox(&mut self.view_options.extended_crosshair, "Enable");
             ui.end_row();
             ui.label("Bottom Bar");
             ui.checkbox(&mut self.view_options.bottom_bar, "Enable");
             ui.end_row();
+            ui.label("HDR Blending").on_hover_text(
+                "Keep Add and Color Dodge glows brighter than white between layers instead of clamping them early. Soft Highlights also rolls off colors near white. Glows are still clamped where isolated groups are flattened, and between passes of documents with very many layers.",
+            );
+            {
+                let mut tone_map = self.app.tone_map.lock();
+                let before = *tone_map;
+                ComboBox::from_id_source("tone_map")
+                    .selected_text(tone_map.as_str())
+                    .show_ui(ui, |ui| {
+                        for t in ToneMap::all() {
+                            ui.selectable_value(&mut *tone_map, *t, t.as_str());
+                        }
+                    });
+                if *tone_map != before {
+                    drop(tone_map);
+                    self.app.invalidate_all();
+                }
+            }
+            ui.end_row();
+            ui.label("Verify Tiles on Load");
+            {
+                let mut verify = self.app.verify_tiles.load(Ordering::Acquire);
+                if ui.checkbox(&mut verify, "Enable").changed() {
+                    self.app.verify_tiles.store(verify, Ordering::Release);
+                }
+            }
+            ui.end_row();
+            ui.label("Loading Threads");
+            {
+                let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+                let mut threads = self.app.load_threads.load(Ordering::Acquire);
+                if ui
+                    .add(
+                        Slider::new(&mut threads, 0..=cores)
+                            .custom_formatter(|threads, _| {
+                                if threads == 0.0 {
+                                    String::from("All")
+                                } else {
+                                    threads.to_string()
+                                }
+                            })
+                            .custom_parser(|text| match text.trim() {
+                                "All" | "all" => Some(0.0),
+                                text => text.parse().ok(),
+                            }),
+                    )
+                    .on_hover_text("Fewer threads leave cores free for other apps while files load.")
+                    .changed()
+                {
+                    self.app.load_threads.store(threads, Ordering::Release);
+                }
+            }
+            ui.end_row();
+            ui.label("Uploads per Frame");
+            {
+                let uploads = &self.app.dev.uploads;
+                let mut mib = uploads.limit().unwrap_or(UploadThrottle::DEFAULT_LIMIT) >> 20;
+                if ui
+                    .add(
+                        Slider::new(&mut mib, 1..=512)
+                            .logarithmic(true)
+                            .suffix(" MiB"),
+                    )
+                    .on_hover_text("Lower values keep the view smooth while layers load.")
+                    .changed()
+                {
+                    uploads.set_limit(Some(mib << 20));
+                }
+            }
+            ui.end_row();
+            ui.label("GPU Memory Budget");
+            {
+                let budget = &self.app.dev.budget;
+                let mut gib = budget.limit() as f64 / f64::from(1 << 30);
+                if ui
+                    .add(
+                        Slider::new(&mut gib, 0.25..=16.0)
+                            .logarithmic(true)
+                            .fixed_decimals(2)
+                            .suffix(" GiB"),
+                    )
+                    .changed()
+                {
+                    budget.set_limit((gib * f64::from(1 << 30)) as u64);
+                }
+                ui.end_row();
+                ui.label("GPU Memory Used");
+                ui.label(format!("{} MiB", budget.used() >> 20));
+            }
+            ui.end_row();
+            self.layout_gpu_preference(ui);
             ui.label("Smooth Sampling");
             if ui
                 .checkbox(&mut self.view_options.smooth, "Enable")
@@ -62,13 +378,383 @@ impl ControlsGui<'_> {
                 self.app.rebind_texture(self.active_canvas);
             }
             ui.end_row();
-            ui.label("Rotation");
+            if let Some(instance) = self
+                .app
+                .compositor
+                .instances
+                .read()
+                .get(&self.active_canvas)
+            {
+                let snap = self.view_options.rotation_snap;
+                let mut view = instance.view.lock();
+                ui.label("Rotation");
+                ui.horizontal(|ui| {
+                    let mut degrees = view.degrees();
+                    let slider = Slider::new(&mut degrees, 0.0..=360.0)
+                        .suffix(" deg")
+                        .step_by(snap.step().map_or(0.0, f64::from));
+                    if ui.add(slider).changed() {
+                        view.set_degrees(degrees, snap);
+                    }
+                    if ui
+                        .add_enabled(view.rotation != 0.0, Button::new("Reset"))
+                        .clicked()
+                    {
+                        view.rotation = 0.0;
+                    }
+                })
+                .response
+                .on_hover_text("Rotate by 90 degrees with [ and ].");
+                ui.end_row();
+                ui.label("Mirror View");
+                ui.horizontal(|ui| {
+                    ui.toggle_value(&mut view.mirror_horizontally, "Horizontal");
+                    ui.toggle_value(&mut view.mirror_vertically, "Vertical");
+                })
+                .response
+                .on_hover_text(
+                    "Previews the canvas flipped, without changing the file or its exports.",
+                );
+                ui.end_row();
+            }
+            ui.label("Rotation Snapping");
+            ComboBox::from_id_source("rotation_snap")
+                .selected_text(self.view_options.rotation_snap.as_str())
+                .show_ui(ui, |ui| {
+                    for snap in RotationSnap::all() {
+                        ui.selectable_value(
+                            &mut self.view_options.rotation_snap,
+                            *snap,
+                            snap.as_str(),
+                        );
+                    }
+                });
+            ui.end_row();
+            for (button, action) in canvas::POINTER_BUTTONS
+                .into_iter()
+                .zip(&mut self.view_options.double_click)
             {
-                let mut degree = self.view_options.rotation.to_degrees();
-                ui.add(Slider::new(&mut degree, 0.0..=360.0).suffix(" deg"));
-                self.view_options.rotation = degree.to_radians();
+                ui.label(format!("Double Click {button:?}"));
+                ComboBox::from_id_source(("double_click", button as usize))
+                    .selected_text(action.as_str())
+                    .show_ui(ui, |ui| {
+                        for a in DoubleClickAction::all() {
+                            ui.selectable_value(action, *a, a.as_str());
+                        }
+                    });
+                ui.end_row();
             }
+            for shortcut in Shortcut::all() {
+                ui.label(format!("{} Key", shortcut.as_str()));
+                let key = self.view_options.keymap.key_mut(*shortcut);
+                ComboBox::from_id_source(("keymap", *shortcut as usize))
+                    .selected_text(key.name())
+                    .show_ui(ui, |ui| {
+                        for k in Keymap::bindable_keys() {
+                            ui.selectable_value(key, k, k.name());
+                        }
+                    });
+                ui.end_row();
+            }
+            ui.label("Color Under Cursor");
+            match *self.app.hovered_color.lock() {
+                Some(picker::PixelColor {
+                    pixel: (x, y),
+                    rgba,
+                }) if self.view_options.color_picker => {
+                    ui.horizontal(|ui| {
+                        let [r, g, b, a] = rgba;
+                        color_picker::show_color(
+                            ui,
+                            Color32::from_rgba_unmultiplied(r, g, b, a),
+                            vec2(16.0, 16.0),
+                        );
+                        ui.monospace(format!(
+                            "{} {}",
+                            picker::ColorFormat::Hex.format(rgba),
+                            picker::ColorFormat::Rgb.format(rgba)
+                        ));
+                        ui.weak(format!("at {x}, {y}"));
+                    });
+                }
+                _ => {
+                    ui.weak("Turn on the color picker in the Canvas tab");
+                }
+            }
+            ui.end_row();
+            ui.label("Statistics HUD (F3)");
+            ui.checkbox(&mut self.view_options.stats_hud, "Enable");
+            ui.end_row();
+            ui.label("Motion");
+            ComboBox::from_id_source("motion")
+                .selected_text(self.view_options.motion.as_str())
+                .show_ui(ui, |ui| {
+                    for m in MotionPreference::all() {
+                        ui.selectable_value(&mut self.view_options.motion, *m, m.as_str());
+                    }
+                });
+            ui.end_row();
         });
+        ui.separator();
+        self.layout_paper_control(ui);
+        ui.separator();
+        self.layout_lut_control(ui);
+    }
+
+    /// Rows choosing the backend and adapter used from the next start.
+    fn layout_gpu_preference(&mut self, ui: &mut Ui) {
+        const RESTART: &str = "Takes effect the next time Silicate starts.";
+        let info = self.app.dev.adapter.get_info();
+        ui.label("GPU in Use");
+        ui.label(format!("{} ({:?})", info.name, info.backend));
+        ui.end_row();
+
+        let mut gpu = self.app.gpu.lock();
+        let before = gpu.backend;
+        ui.label("Graphics Backend").on_hover_text(RESTART);
+        ComboBox::from_id_source("gpu_backend")
+            .selected_text(gpu.backend.as_str())
+            .show_ui(ui, |ui| {
+                for backend in GpuBackend::all() {
+                    ui.selectable_value(&mut gpu.backend, *backend, backend.as_str());
+                }
+            });
+        ui.end_row();
+        if gpu.backend != before {
+            // Adapters are numbered per backend.
+            gpu.adapter = None;
+        }
+
+        let backend = gpu.backend;
+        if self.adapter_names.as_ref().map(|(b, _)| *b) != Some(backend) {
+            *self.adapter_names = Some((backend, GpuHandle::adapter_names(backend)));
+        }
+        let names = &self.adapter_names.as_ref().expect("listed above").1;
+        let name = |adapter: Option<usize>| match adapter {
+            None => String::from("Most Powerful"),
+            Some(index) => names
+                .get(index)
+                .cloned()
+                .unwrap_or_else(|| format!("Adapter {index}")),
+        };
+        ui.label("GPU Adapter").on_hover_text(RESTART);
+        ComboBox::from_id_source("gpu_adapter")
+            .selected_text(name(gpu.adapter))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut gpu.adapter, None, name(None));
+                for index in 0..names.len() {
+                    ui.selectable_value(&mut gpu.adapter, Some(index), name(Some(index)));
+                }
+            });
+        ui.end_row();
+    }
+
+    fn layout_settings(&mut self, ui: &mut Ui) {
+        Grid::new("Settings Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Theme");
+            ComboBox::from_id_source("theme")
+                .selected_text(self.view_options.theme.as_str())
+                .show_ui(ui, |ui| {
+                    for t in Theme::all() {
+                        ui.selectable_value(&mut self.view_options.theme, *t, t.as_str());
+                    }
+                });
+            ui.end_row();
+            ui.label("Accent Color");
+            ui.horizontal(|ui| {
+                let mut custom = self.view_options.accent.is_some();
+                if ui.checkbox(&mut custom, "Custom").changed() {
+                    self.view_options.accent = custom.then(|| {
+                        let accent = ui.visuals().selection.bg_fill;
+                        [accent.r(), accent.g(), accent.b()]
+                    });
+                }
+                if let Some(accent) = self.view_options.accent.as_mut() {
+                    ui.color_edit_button_srgb(accent);
+                }
+            });
+            ui.end_row();
+            ui.label("Canvas Backdrop");
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut self.view_options.backdrop);
+                if ui
+                    .add_enabled(
+                        self.view_options.backdrop != theme::DEFAULT_BACKDROP,
+                        Button::new("Reset"),
+                    )
+                    .clicked()
+                {
+                    self.view_options.backdrop = theme::DEFAULT_BACKDROP;
+                }
+            });
+            ui.end_row();
+            ui.label("Transparency")
+                .on_hover_text("Drawn behind the transparent parts of the canvas, such as when its background is hidden.");
+            ui.horizontal(|ui| {
+                let transparency = &mut self.view_options.transparency;
+                ComboBox::from_id_source("transparency")
+                    .selected_text(transparency.style.as_str())
+                    .show_ui(ui, |ui| {
+                        for style in TransparencyStyle::all() {
+                            ui.selectable_value(&mut transparency.style, *style, style.as_str());
+                        }
+                    });
+                match transparency.style {
+                    TransparencyStyle::Backdrop => {}
+                    TransparencyStyle::Solid => {
+                        ui.color_edit_button_srgb(&mut transparency.colors[0]);
+                    }
+                    TransparencyStyle::Checkerboard => {
+                        for color in &mut transparency.colors {
+                            ui.color_edit_button_srgb(color);
+                        }
+                        ui.add(
+                            DragValue::new(&mut transparency.size)
+                                .clamp_range(Transparency::SIZES)
+                                .suffix(" pt"),
+                        );
+                    }
+                }
+                if ui
+                    .add_enabled(*transparency != Transparency::default(), Button::new("Reset"))
+                    .clicked()
+                {
+                    *transparency = Transparency::default();
+                }
+            });
+            ui.end_row();
+            ui.label("On Startup")
+                .on_hover_text("When no files are passed on the command line.");
+            ui.checkbox(&mut self.view_options.open_on_startup, "Open File Picker");
+            ui.end_row();
+            ui.label("Adaptive Quality").on_hover_text(
+                "Composite at half resolution while edits render slower than 60 fps, and at full resolution once they pause. Exports are always at full resolution.",
+            );
+            ui.checkbox(&mut self.view_options.adaptive_quality, "Enable");
+            ui.end_row();
+            ui.label("Presentation").on_hover_text(
+                "F11 or P shows the active canvas alone in fullscreen. Escape leaves it and the arrow keys move between tabs.",
+            );
+            ui.horizontal(|ui| {
+                let mut cycle = self.view_options.presentation_cycle.is_some();
+                if ui.checkbox(&mut cycle, "Cycle Tabs").changed() {
+                    self.view_options.presentation_cycle = cycle.then_some(10);
+                }
+                if let Some(seconds) = self.view_options.presentation_cycle.as_mut() {
+                    ui.add(
+                        DragValue::new(seconds)
+                            .clamp_range(ViewOptions::PRESENTATION_CYCLE)
+                            .prefix("every ")
+                            .suffix(" s"),
+                    );
+                }
+            });
+            ui.end_row();
+            ui.label("Settings");
+            ui.horizontal(|ui| {
+                if ui.button("Export…").clicked() {
+                    let settings = SettingsBundle {
+                        settings: Settings::capture(self.app, self.view_options),
+                        export_presets: Some(self.export_presets.presets.clone()),
+                    };
+                    self.app
+                        .rt
+                        .spawn(self.app.clone().export_settings_dialog(settings));
+                }
+                if ui.button("Import…").clicked() {
+                    self.app.rt.spawn(self.app.clone().import_settings_dialog());
+                }
+            });
+            ui.end_row();
+        });
+    }
+
+    fn layout_paper_control(&mut self, ui: &mut Ui) {
+        let mut paper = self.app.paper.lock();
+        let mut changed = false;
+        Grid::new("Paper Grid").show(ui, |ui| {
+            ui.label("Paper Texture");
+            ui.horizontal(|ui| {
+                if ui.button("Load").clicked() {
+                    self.app.rt.spawn(self.app.clone().paper_dialog());
+                }
+                if paper.texture.is_some() && ui.button("Clear").clicked() {
+                    paper.texture = None;
+                    paper.name = None;
+                    changed = true;
+                }
+            });
+            ui.end_row();
+            if let Some(name) = paper.name.clone() {
+                ui.label("File");
+                ui.label(name);
+                ui.end_row();
+                ui.label("Paper Overlay");
+                changed |= ui.checkbox(&mut paper.enabled, "Enable").changed();
+                ui.end_row();
+                ui.label("Paper Blend");
+                ComboBox::from_id_source("paper.blend")
+                    .selected_text(paper.blend.as_str())
+                    .show_ui(ui, |ui| {
+                        for b in PaperOptions::BLENDS {
+                            changed |= ui
+                                .selectable_value(&mut paper.blend, b, b.as_str())
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+                let mut percent = paper.strength * 100.0;
+                ui.label("Paper Strength");
+                changed |= ui
+                    .add(
+                        Slider::new(&mut percent, 0.0..=100.0)
+                            .fixed_decimals(0)
+                            .suffix("%"),
+                    )
+                    .changed();
+                paper.strength = percent / 100.0;
+                ui.end_row();
+                ui.label("Paper in Export");
+                ui.checkbox(&mut paper.include_in_export, "Include");
+            }
+        });
+        drop(paper);
+        if changed {
+            self.app.invalidate_all();
+        }
+    }
+
+    fn layout_lut_control(&mut self, ui: &mut Ui) {
+        let mut lut = self.app.lut.lock();
+        let mut changed = false;
+        Grid::new("LUT Grid").show(ui, |ui| {
+            ui.label("Color LUT");
+            ui.horizontal(|ui| {
+                if ui.button("Load").clicked() {
+                    self.app.rt.spawn(self.app.clone().lut_dialog());
+                }
+                if lut.texture.is_some() && ui.button("Clear").clicked() {
+                    lut.texture = None;
+                    lut.name = None;
+                    changed = true;
+                }
+            });
+            ui.end_row();
+            if let Some(name) = lut.name.clone() {
+                ui.label("File");
+                ui.label(name);
+                ui.end_row();
+                ui.label("LUT Preview");
+                changed |= ui.checkbox(&mut lut.preview, "Enable").changed();
+                ui.end_row();
+                ui.label("LUT in Export");
+                ui.checkbox(&mut lut.include_in_export, "Include");
+            }
+        });
+        drop(lut);
+        if changed {
+            self.app.invalidate_all();
+        }
     }
 
     fn layout_canvas_control(&mut self, ui: &mut Ui) {
@@ -80,92 +766,1230 @@ impl ControlsGui<'_> {
             .get(&self.active_canvas)
         {
             Grid::new("Canvas Grid").show(ui, |ui| {
-                ui.label("Flip");
+                ui.label("Source File");
+                if ui
+                    .button("Reload from Disk")
+                    .on_hover_text("Keeps layer changes where the layers did not change on disk.")
+                    .clicked()
+                {
+                    self.app
+                        .rt
+                        .spawn(self.app.clone().reload_file(self.active_canvas));
+                }
+                ui.end_row();
+                ui.label("Flip").on_hover_text(
+                    "Flips the canvas and its exports. Mirror the view to only preview it.",
+                );
                 ui.horizontal(|ui| {
                     if ui.button("Horizontal").clicked() {
-                        instance.target.lock().data.flip_vertices(false, true);
-                        instance.store_change_or(true);
-                        self.app.rebind_texture(self.active_canvas);
+                        instance.flip(false, true);
                     }
                     if ui.button("Vertical").clicked() {
-                        instance.target.lock().data.flip_vertices(true, false);
-                        instance.store_change_or(true);
-                        self.app.rebind_texture(self.active_canvas);
+                        instance.flip(true, false);
                     }
                 });
                 ui.end_row();
                 ui.label("Rotate");
                 ui.horizontal(|ui| {
                     if ui.button("CCW").clicked() {
-                        let mut target = instance.target.lock();
-                        target.data.rotate_vertices(true);
-                        if target.transpose_dimensions() {
-                            self.app.rebind_texture(self.active_canvas);
-                        }
-                        instance.store_change_or(true);
+                        instance.rotate(1);
                     }
                     if ui.button("CW").clicked() {
-                        let mut target = instance.target.lock();
-                        target.data.rotate_vertices(false);
-                        if target.transpose_dimensions() {
-                            self.app.rebind_texture(self.active_canvas);
+                        instance.rotate(-1);
+                    }
+                });
+                ui.end_row();
+                ui.label("Orientation");
+                let turns = instance.file.read().orientation % 4;
+                let mut stored = instance.display().stored_orientation;
+                if ui
+                    .add_enabled(turns != 0, Checkbox::new(&mut stored, "Show as Stored"))
+                    .on_hover_text(
+                        "Shows the canvas as stored in the file, without turning it to the orientation it was drawn in.",
+                    )
+                    .changed()
+                {
+                    instance.show_stored_orientation(stored);
+                }
+                ui.end_row();
+                ui.label("Reference");
+                let has_stored = instance.file.read().composite.is_some();
+                let reference = instance.display().show_stored;
+                if ui
+                    .add_enabled(
+                        has_stored,
+                        SelectableLabel::new(reference, "Stored Composite"),
+                    )
+                    .on_hover_text(
+                        "Shows the composite Procreate stored in the file instead, to check the composite against. Toggle with R.",
+                    )
+                    .on_disabled_hover_text("The file has no stored composite.")
+                    .clicked()
+                {
+                    instance.toggle_stored_composite();
+                }
+                ui.end_row();
+                ui.label("Blending Space").on_hover_text(
+                    "Procreate blends the colors as they are stored. Blending in linear light makes Add and Screen glows brighter.",
+                );
+                let mut space = instance.target.lock().blend_space;
+                ComboBox::from_id_source("blend_space")
+                    .selected_text(space.as_str())
+                    .show_ui(ui, |ui| {
+                        for s in BlendSpace::all() {
+                            ui.selectable_value(&mut space, *s, s.as_str());
                         }
-                        instance.store_change_or(true);
+                    });
+                if space != instance.target.lock().blend_space {
+                    instance.target.lock().blend_space = space;
+                    instance.commands.send(InstanceCommand::Redraw);
+                }
+                ui.end_row();
+                ui.label("Crop");
+                ui.horizontal(|ui| {
+                    if ui
+                        .toggle_value(&mut self.view_options.crop_tool, "Select")
+                        .changed()
+                    {
+                        self.view_options.color_picker = false;
+                    }
+                    if ui.button("Clear").clicked() {
+                        instance.commands.send(InstanceCommand::Crop(None));
                     }
                 });
+                ui.end_row();
+                let dim = instance.target.lock().dim;
+                let shown = instance.display().crop;
+                if let Some(mut crop) = shown {
+                    ui.label("Crop Region");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(&mut crop.x)
+                                .prefix("x: ")
+                                .clamp_range(0..=dim.width - 1),
+                        );
+                        ui.add(
+                            DragValue::new(&mut crop.y)
+                                .prefix("y: ")
+                                .clamp_range(0..=dim.height - 1),
+                        );
+                        ui.add(
+                            DragValue::new(&mut crop.width)
+                                .prefix("w: ")
+                                .clamp_range(1..=dim.width - crop.x),
+                        );
+                        ui.add(
+                            DragValue::new(&mut crop.height)
+                                .prefix("h: ")
+                                .clamp_range(1..=dim.height - crop.y),
+                        );
+                    });
+                    ui.end_row();
+                    if shown != Some(crop) {
+                        instance.commands.send(InstanceCommand::Crop(Some(crop)));
+                    }
+                }
+                ui.label("Color Picker");
+                if ui
+                    .toggle_value(&mut self.view_options.color_picker, "Pick")
+                    .changed()
+                {
+                    self.view_options.crop_tool = false;
+                }
+                ui.end_row();
             });
+            self.layout_color_control(ui);
             let instances = self.app.compositor.instances.read();
             if let Some(instance) = instances.get(&self.active_canvas) {
+                if instances.len() > 1 {
+                    ui.separator();
+                    self.layout_compare_control(ui, instance, &instances);
+                }
+                ui.separator();
+                self.layout_difference_control(ui, instance);
+                ui.separator();
+                self.layout_guide_control(ui, instance);
+                ui.separator();
+                Self::layout_adjustment_control(ui, instance);
+                ui.separator();
+                self.layout_post_control(ui);
                 ui.separator();
                 Grid::new("File Grid").num_columns(2).show(ui, |ui| {
+                    let mut scale = self.app.export_scale.lock();
+                    ui.label("Export Scale");
+                    let mut percent = scale.factor * 100.0;
+                    ui.add(
+                        Slider::new(&mut percent, 10.0..=400.0)
+                            .logarithmic(true)
+                            .fixed_decimals(0)
+                            .suffix("%"),
+                    );
+                    scale.factor = percent / 100.0;
+                    ui.end_row();
+                    ui.label("Resampling");
+                    ComboBox::from_id_source("export_filter")
+                        .selected_text(scale.filter.as_str())
+                        .show_ui(ui, |ui| {
+                            for f in ResampleFilter::all() {
+                                ui.selectable_value(&mut scale.filter, *f, f.as_str());
+                            }
+                        });
+                    drop(scale);
+                    ui.end_row();
+                    ui.label("Color Profile").on_hover_text(
+                        "How the colors of color exports are converted, and the ICC profile embedded in PNG, JPEG and TIFF files.",
+                    );
+                    let mut export_color = self.app.export_color.lock();
+                    let document = instance
+                        .file
+                        .read()
+                        .color_profile
+                        .clone()
+                        .and_then(|icc| ColorProfile { icc }.description());
+                    ComboBox::from_id_source("export_color")
+                        .selected_text(export_color.as_str())
+                        .show_ui(ui, |ui| {
+                            for c in ExportColorSpace::all() {
+                                ui.selectable_value(&mut *export_color, *c, c.as_str());
+                            }
+                        })
+                        .response
+                        .on_hover_text(format!(
+                            "Document profile: {}",
+                            document.as_deref().unwrap_or("none, treated as sRGB")
+                        ));
+                    drop(export_color);
+                    ui.end_row();
+                    ui.label("Also Save As")
+                        .on_hover_text("Color exports are also saved in these formats.");
+                    ui.horizontal(|ui| {
+                        for (format, selected) in self.app.extra_formats.lock().entries_mut() {
+                            ui.checkbox(selected, format.extensions_str()[0].to_uppercase());
+                        }
+                    });
+                    ui.end_row();
                     ui.label("Actions");
                     ui.vertical(|ui| {
-                        if ui.button("Export View").clicked() {
-                            if let Some(texture) = instance.target.lock().output.as_ref() {
-                                let copied_texture = texture.texture.clone(&self.app.dev);
-                                self.app
-                                    .rt
-                                    .spawn(self.app.clone().save_dialog(copied_texture));
+                        for (label, kind) in [
+                            ("Export View", ExportKind::Color),
+                            ("Export Alpha Matte", ExportKind::AlphaMatte),
+                            ("Export Luminance Matte", ExportKind::LuminanceMatte),
+                        ] {
+                            if ui.button(label).clicked() {
+                                self.app.rt.spawn(self.app.clone().save_dialog(
+                                    self.active_canvas,
+                                    kind,
+                                    instance.display().crop,
+                                    instance.file.read().export_metadata(SOFTWARE),
+                                ));
+                            }
+                        }
+                        if ui
+                            .button("Copy Image")
+                            .on_hover_text("Copies the composite to the clipboard (Ctrl+C).")
+                            .clicked()
+                        {
+                            self.app.rt.spawn(self.app.clone().copy_image(
+                                self.active_canvas,
+                                None,
+                                instance.display().crop,
+                            ));
+                        }
+                        let (grid, size) = {
+                            let file = instance.file.read();
+                            (file.drawing_grid, file.size)
+                        };
+                        // Rotating the view swaps the sides, which can
+                        // change the count slightly, so it is checked again
+                        // once exporting.
+                        let cells = grid.map(|cell| {
+                            let cell = cell.round() as u32;
+                            (cell, App::guide_cell_count(size.width, size.height, cell))
+                        });
+                        let exportable = cells.is_some_and(|(_, count)| count <= App::MAX_GUIDE_CELLS);
+                        if ui
+                            .add_enabled(exportable, Button::new("Export Guide Cells"))
+                            .on_hover_text(match cells {
+                                Some((cell, count)) if exportable => format!(
+                                    "Saves each of the {count} cells of {cell} px of the drawing guide grid as its own image."
+                                ),
+                                Some((_, count)) => format!(
+                                    "The drawing guide grid has {count} cells, more than the {} that can be exported at once.",
+                                    App::MAX_GUIDE_CELLS
+                                ),
+                                None => String::from("The document has no enabled 2D grid drawing guide."),
+                            })
+                            .clicked()
+                        {
+                            if let Some((cell, _)) = cells {
+                                self.app.rt.spawn(self.app.clone().guide_cells_dialog(
+                                    self.active_canvas,
+                                    cell,
+                                    instance.file.read().export_metadata(SOFTWARE),
+                                ));
                             }
                         }
+                        if ui
+                            .button("Export Krita Document")
+                            .on_hover_text("Keeps the layers, groups, blending modes and opacity.")
+                            .clicked()
+                        {
+                            let transform = instance.target.lock().data.transform();
+                            self.app
+                                .rt
+                                .spawn(self.app.clone().kra_dialog(self.active_canvas, transform));
+                        }
+                        if ui.button("Export Contact Sheet").clicked() {
+                            // Open files in the order they were loaded.
+                            let mut keys = instances.keys().copied().collect::<Vec<_>>();
+                            keys.sort_by_key(|key| key.0);
+                            self.app
+                                .rt
+                                .spawn(self.app.clone().contact_sheet_dialog(keys));
+                        }
                     });
                 });
+                ui.separator();
+                self.layout_export_presets(ui, instance);
             }
         } else {
             ui.label("No canvas loaded.");
         }
     }
 
-    fn layout_layer_control(ui: &mut Ui, i: usize, l: &mut SilicaLayer, changed: &mut bool) {
+    fn layout_export_presets(&mut self, ui: &mut Ui, instance: &Instance) {
+        let presets = &mut self.export_presets.presets;
+        let before = presets.clone();
+        let name = instance.export_name();
+        let layers = instance.file.read().layers.iter_layers().count();
+        let mut removed = None;
+        ui.horizontal(|ui| {
+            ui.label("Export Presets")
+                .on_hover_text("File names can use {name}, {date}, {layers} and {preset}.");
+            if ui.button("Add").clicked() {
+                presets.push(ExportPreset::new(&format!("Preset {}", presets.len() + 1)));
+            }
+            if ui
+                .add_enabled(!presets.is_empty(), Button::new("Run All Presets"))
+                .on_hover_text("Exports the view with every preset into a chosen folder.")
+                .clicked()
+            {
+                self.app.rt.spawn(
+                    self.app
+                        .clone()
+                        .export_presets_dialog(self.active_canvas, presets.clone()),
+                );
+            }
+        });
+        for (i, preset) in presets.iter_mut().enumerate() {
+            CollapsingHeader::new(&preset.name)
+                .id_source(("export_preset", i))
+                .show(ui, |ui| {
+                    Grid::new(("export_preset_grid", i))
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("Name");
+                            ui.text_edit_singleline(&mut preset.name);
+                            ui.end_row();
+                            ui.label("Format");
+                            ComboBox::from_id_source(("export_preset_format", i))
+                                .selected_text(preset.format.extensions_str()[0].to_uppercase())
+                                .show_ui(ui, |ui| {
+                                    for format in ExportPreset::FORMATS {
+                                        ui.selectable_value(
+                                            &mut preset.format,
+                                            format,
+                                            format.extensions_str()[0].to_uppercase(),
+                                        );
+                                    }
+                                });
+                            ui.end_row();
+                            ui.label("Size");
+                            ui.horizontal(|ui| {
+                                let mut long_edge = matches!(preset.size, PresetSize::LongEdge(_));
+                                ComboBox::from_id_source(("export_preset_size", i))
+                                    .selected_text(if long_edge { "Long Edge" } else { "Scale" })
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut long_edge, false, "Scale");
+                                        ui.selectable_value(&mut long_edge, true, "Long Edge");
+                                    });
+                                preset.size = match (preset.size, long_edge) {
+                                    (PresetSize::Scale(_), true) => PresetSize::LongEdge(2000),
+                                    (PresetSize::LongEdge(_), false) => PresetSize::Scale(1.0),
+                                    (size, _) => size,
+                                };
+                                match &mut preset.size {
+                                    PresetSize::Scale(factor) => {
+                                        let mut percent = *factor * 100.0;
+                                        ui.add(
+                                            DragValue::new(&mut percent)
+                                                .clamp_range(1.0..=400.0)
+                                                .suffix("%"),
+                                        );
+                                        *factor = percent / 100.0;
+                                    }
+                                    PresetSize::LongEdge(pixels) => {
+                                        ui.add(
+                                            DragValue::new(pixels)
+                                                .clamp_range(1..=16384)
+                                                .suffix(" px"),
+                                        );
+                                    }
+                                }
+                            });
+                            ui.end_row();
+                            ui.label("Background");
+                            ui.checkbox(&mut preset.background, "Include");
+                            ui.end_row();
+                            ui.label("Region");
+                            ui.checkbox(&mut preset.region, "Crop Region Only");
+                            ui.end_row();
+                            ui.label("File Name");
+                            ui.text_edit_singleline(&mut preset.template);
+                            ui.end_row();
+                            ui.label("");
+                            match preset.file_name(&name, layers) {
+                                Ok(file_name) => ui.weak(file_name),
+                                Err(err) => {
+                                    ui.colored_label(ui.visuals().error_fg_color, err.to_string())
+                                }
+                            };
+                            ui.end_row();
+                        });
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+        }
+        if let Some(i) = removed {
+            presets.remove(i);
+        }
+        if *presets != before {
+            self.export_presets.save();
+        }
+    }
+
+    fn layout_post_control(&self, ui: &mut Ui) {
+        let mut post = self.app.post.lock();
+        let mut removed = None;
+        let mut raised = None;
+        Grid::new("Post Grid").num_columns(3).show(ui, |ui| {
+            ui.label("Export Steps").on_hover_text(
+                "Run on the GPU over color exports, in order, before their colors are converted and they are saved.",
+            );
+            ui.menu_button("Add", |ui| {
+                for step in PostStep::all() {
+                    if ui.button(step.as_str()).clicked() {
+                        post.steps.push(*step);
+                        ui.close_menu();
+                    }
+                }
+            });
+            ui.end_row();
+            for (i, step) in post.steps.iter_mut().enumerate() {
+                ui.label(format!("{}. {}", i + 1, step.as_str()));
+                match step {
+                    PostStep::Sharpen { amount, radius } => {
+                        ui.horizontal(|ui| {
+                            let mut percent = *amount * 100.0;
+                            ui.add(
+                                Slider::new(&mut percent, 0.0..=300.0)
+                                    .fixed_decimals(0)
+                                    .suffix("%"),
+                            );
+                            *amount = percent / 100.0;
+                            ui.add(
+                                DragValue::new(radius)
+                                    .prefix("radius: ")
+                                    .suffix(" px")
+                                    .clamp_range(1..=PostStep::MAX_RADIUS),
+                            );
+                        });
+                    }
+                    PostStep::Resize { long_edge } => {
+                        ui.add(
+                            DragValue::new(long_edge)
+                                .prefix("long side: ")
+                                .suffix(" px")
+                                .clamp_range(16..=16384),
+                        );
+                    }
+                    PostStep::Watermark {
+                        opacity,
+                        scale,
+                        placement,
+                    } => {
+                        ui.horizontal(|ui| {
+                            let mut percent = *opacity * 100.0;
+                            ui.add(
+                                Slider::new(&mut percent, 0.0..=100.0)
+                                    .fixed_decimals(0)
+                                    .suffix("%"),
+                            );
+                            *opacity = percent / 100.0;
+                            let mut width = *scale * 100.0;
+                            ui.add(
+                                DragValue::new(&mut width)
+                                    .prefix("width: ")
+                                    .suffix("%")
+                                    .fixed_decimals(0)
+                                    .clamp_range(1.0..=100.0),
+                            )
+                            .on_hover_text("Width of the watermark relative to the image.");
+                            *scale = width / 100.0;
+                            ComboBox::from_id_source(("watermark_placement", i))
+                                .selected_text(placement.as_str())
+                                .show_ui(ui, |ui| {
+                                    for p in MarkPlacement::all() {
+                                        ui.selectable_value(placement, *p, p.as_str());
+                                    }
+                                });
+                        });
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(i > 0, Button::new("Up")).clicked() {
+                        raised = Some(i);
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+                ui.end_row();
+            }
+            if post
+                .steps
+                .iter()
+                .any(|step| matches!(step, PostStep::Watermark { .. }))
+            {
+                ui.label("Watermark Image");
+                ui.horizontal(|ui| {
+                    if ui.button("Load").clicked() {
+                        self.app.rt.spawn(self.app.clone().watermark_dialog());
+                    }
+                    if let Some(name) = post.watermark_name.clone() {
+                        ui.label(name);
+                        if ui.button("Clear").clicked() {
+                            post.watermark = None;
+                            post.watermark_name = None;
+                        }
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        if let Some(i) = raised {
+            post.steps.swap(i - 1, i);
+        }
+        if let Some(i) = removed {
+            post.steps.remove(i);
+        }
+    }
+
+    fn layout_adjustment_control(ui: &mut Ui, instance: &Instance) {
+        let mut guard = instance.adjustments.lock();
+        let adjustments = &mut *guard;
+        let before = *adjustments;
+        Grid::new("Adjustment Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Adjustments").on_hover_text(
+                "Previews color adjustments without changing the file. Exports leave them out.",
+            );
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut adjustments.enabled, "Enable");
+                if ui.button("Reset").clicked() {
+                    *adjustments = Adjustments::default();
+                }
+            });
+            ui.end_row();
+            for (label, value) in [
+                ("Brightness", &mut adjustments.brightness),
+                ("Contrast", &mut adjustments.contrast),
+                ("Saturation", &mut adjustments.saturation),
+            ] {
+                ui.label(label);
+                let mut percent = *value * 100.0;
+                ui.add(
+                    Slider::new(&mut percent, -100.0..=100.0)
+                        .fixed_decimals(0)
+                        .suffix("%"),
+                );
+                *value = percent / 100.0;
+                ui.end_row();
+            }
+            ui.label("Hue");
+            ui.add(
+                Slider::new(&mut adjustments.hue, -180.0..=180.0)
+                    .fixed_decimals(0)
+                    .suffix("°"),
+            );
+            ui.end_row();
+            ui.label("Filters");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut adjustments.grayscale, "Grayscale");
+                ui.checkbox(&mut adjustments.invert, "Invert");
+            });
+            ui.end_row();
+        });
+        if *adjustments != before {
+            instance.commands.send(InstanceCommand::Redraw);
+        }
+    }
+
+    fn layout_compare_control(
+        &self,
+        ui: &mut Ui,
+        instance: &Instance,
+        instances: &HashMap<InstanceKey, Instance>,
+    ) {
+        let name = |key: &InstanceKey| {
+            instances[key]
+                .file
+                .read()
+                .name
+                .clone()
+                .unwrap_or("Untitled Artwork".to_string())
+        };
+        let mut compare = instance.compare.lock();
+        Grid::new("Compare Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Compare With");
+            let mut with = compare.map(|compare| compare.with);
+            ComboBox::from_id_source("compare_with")
+                .selected_text(with.as_ref().map_or("Nothing".to_string(), name))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut with, None, "Nothing");
+                    // Open files in the order they were loaded.
+                    let mut keys = instances
+                        .keys()
+                        .filter(|key| **key != self.active_canvas)
+                        .copied()
+                        .collect::<Vec<_>>();
+                    keys.sort_by_key(|key| key.0);
+                    for key in keys {
+                        ui.selectable_value(&mut with, Some(key), name(&key));
+                    }
+                });
+            ui.end_row();
+            *compare = with.map(|with| CompareTarget {
+                with,
+                ..compare.unwrap_or(CompareTarget {
+                    with,
+                    mode: CompareMode::Overlay,
+                    amount: 0.5,
+                })
+            });
+
+            if let Some(compare) = compare.as_mut() {
+                ui.label("Mode");
+                ComboBox::from_id_source("compare_mode")
+                    .selected_text(compare.mode.as_str())
+                    .show_ui(ui, |ui| {
+                        for m in CompareMode::all() {
+                            ui.selectable_value(&mut compare.mode, *m, m.as_str());
+                        }
+                    });
+                ui.end_row();
+                ui.label(match compare.mode {
+                    CompareMode::Overlay => "Opacity",
+                    CompareMode::Wipe => "Split",
+                });
+                let mut percent = compare.amount * 100.0;
+                ui.add(
+                    Slider::new(&mut percent, 0.0..=100.0)
+                        .fixed_decimals(0)
+                        .suffix("%"),
+                );
+                compare.amount = percent / 100.0;
+                ui.end_row();
+            }
+        });
+    }
+
+    fn layout_difference_control(&self, ui: &mut Ui, instance: &Instance) {
+        let file = instance.file.read();
+        let has_stored = file.composite.is_some();
+        // Textures of the selected layers, when exactly two are selected.
+        let selected = self
+            .selection
+            .selected(self.active_canvas)
+            .map(|uuid| file.layers.find_layer(uuid).map(|layer| layer.image))
+            .collect::<Option<Vec<_>>>()
+            .and_then(|textures| <[u32; 2]>::try_from(textures).ok());
+        drop(file);
+
+        let before = instance.display().difference;
+        let mut difference = before;
+        Grid::new("Difference Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Difference").on_hover_text(
+                "Shows where pixels differ, which come out brighter the more they differ.",
+            );
+            ComboBox::from_id_source("difference_source")
+                .selected_text(difference.map_or("Nothing", |source| source.as_str()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut difference, None, "Nothing");
+                    ui.add_enabled_ui(has_stored, |ui| {
+                        ui.selectable_value(
+                            &mut difference,
+                            Some(DifferenceSource::StoredComposite),
+                            DifferenceSource::StoredComposite.as_str(),
+                        )
+                        .on_disabled_hover_text("The file has no stored composite.");
+                    });
+                    let layers = selected
+                        .map_or(DifferenceSource::Layers(0, 0), |[first, second]| {
+                            DifferenceSource::Layers(first, second)
+                        });
+                    ui.add_enabled_ui(selected.is_some(), |ui| {
+                        let response = ui
+                            .selectable_label(
+                                matches!(difference, Some(DifferenceSource::Layers(..))),
+                                layers.as_str(),
+                            )
+                            .on_disabled_hover_text("Select two layers to compare.");
+                        if response.clicked() {
+                            difference = Some(layers);
+                        }
+                    });
+                });
+            ui.end_row();
+        });
+        if difference != before {
+            instance
+                .commands
+                .send(InstanceCommand::Difference(difference));
+        }
+    }
+
+    fn layout_guide_control(&self, ui: &mut Ui, instance: &Instance) {
+        let mut guides = instance.guides.lock();
+        Grid::new("Guides Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Guides");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut guides.center, "Center");
+                ui.checkbox(&mut guides.thirds, "Thirds");
+            });
+            ui.end_row();
+            ui.label("Safe Margin");
+            ui.horizontal(|ui| {
+                let max = match guides.margin_unit {
+                    MarginUnit::Pixels => {
+                        let size = instance.file.read().size;
+                        size.width.min(size.height) as f32 / 2.0
+                    }
+                    MarginUnit::Percent => 50.0,
+                };
+                ui.add(
+                    DragValue::new(&mut guides.margin)
+                        .clamp_range(0.0..=max)
+                        .max_decimals(1),
+                );
+                ComboBox::from_id_source("guide_margin_unit")
+                    .width(48.0)
+                    .selected_text(guides.margin_unit.as_str())
+                    .show_ui(ui, |ui| {
+                        for u in MarginUnit::all() {
+                            ui.selectable_value(&mut guides.margin_unit, *u, u.as_str());
+                        }
+                    });
+            });
+            ui.end_row();
+        });
+    }
+
+    fn layout_color_control(&self, ui: &mut Ui) {
+        let swatch = |ui: &mut Ui, [r, g, b, a]: [u8; 4]| {
+            color_picker::show_color(
+                ui,
+                Color32::from_rgba_unmultiplied(r, g, b, a),
+                vec2(24.0, 24.0),
+            )
+            .interact(Sense::click())
+        };
+        let mut sampled = self.app.sampled_color.lock();
+        let mut swatches = self.app.swatches.lock();
+        if sampled.is_none() && swatches.is_empty() {
+            return;
+        }
+
+        ui.separator();
+        Grid::new("Color Grid").num_columns(2).show(ui, |ui| {
+            if let Some(color) = *sampled {
+                ui.label("Color");
+                ui.horizontal(|ui| {
+                    swatch(ui, color);
+                    if ui.button("Add to Swatches").clicked() && !swatches.contains(&color) {
+                        swatches.push(color);
+                    }
+                });
+                ui.end_row();
+                for format in picker::ColorFormat::all() {
+                    ui.label(format.as_str());
+                    let text = format.format(color);
+                    if ui.button(&text).on_hover_text("Copy").clicked() {
+                        ui.output_mut(|output| output.copied_text = text);
+                    }
+                    ui.end_row();
+                }
+            }
+            if !swatches.is_empty() {
+                ui.label("Swatches");
+                let mut removed = None;
+                ui.horizontal_wrapped(|ui| {
+                    for (i, color) in swatches.iter().enumerate() {
+                        let response = swatch(ui, *color)
+                            .on_hover_text("Click to select, right click to remove");
+                        if response.clicked() {
+                            *sampled = Some(*color);
+                        }
+                        if response.secondary_clicked() {
+                            removed = Some(i);
+                        }
+                    }
+                });
+                if let Some(i) = removed {
+                    swatches.remove(i);
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    /// Edits made to the active file, oldest first. Clicking an edit undoes
+    /// or redoes the edits up to it.
+    fn layout_history(&self, ui: &mut Ui) {
+        let instances = self.app.compositor.instances.read();
+        let Some(instance) = instances.get(&self.active_canvas) else {
+            ui.label("No canvas loaded.");
+            return;
+        };
+        let history = instance.history.lock();
+        let done = history
+            .done()
+            .iter()
+            .map(|edit| edit.describe())
+            .collect::<Vec<_>>();
+        let undone = history
+            .undone()
+            .iter()
+            .rev()
+            .map(|edit| edit.describe())
+            .collect::<Vec<_>>();
+        drop(history);
+        drop(instances);
+
+        let mut steps = 0;
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!done.is_empty(), Button::new("Undo"))
+                .on_hover_text("Ctrl+Z")
+                .clicked()
+            {
+                steps = -1;
+            }
+            if ui
+                .add_enabled(!undone.is_empty(), Button::new("Redo"))
+                .on_hover_text("Ctrl+Shift+Z")
+                .clicked()
+            {
+                steps = 1;
+            }
+        });
+        ui.separator();
+        ScrollArea::vertical().show(ui, |ui| {
+            if ui.selectable_label(done.is_empty(), "Opened").clicked() {
+                steps = -(done.len() as isize);
+            }
+            for (i, edit) in done.iter().enumerate() {
+                if ui.selectable_label(i + 1 == done.len(), edit).clicked() {
+                    steps = (i + 1) as isize - done.len() as isize;
+                }
+            }
+            for (i, edit) in undone.iter().enumerate() {
+                let text = RichText::new(edit).weak();
+                if ui.selectable_label(false, text).clicked() {
+                    steps = (i + 1) as isize;
+                }
+            }
+        });
+        if steps != 0 {
+            self.app.step_history(self.active_canvas, steps);
+        }
+    }
+
+    fn layout_analysis(&self, ui: &mut Ui) {
+        let instances = self.app.compositor.instances.read();
+        let Some(instance) = instances.get(&self.active_canvas) else {
+            ui.label("No canvas loaded.");
+            return;
+        };
+        let generation = instance.generation.load(Ordering::Acquire);
+        let analysis = self.app.analysis.lock();
+        let current = analysis.as_ref().is_some_and(|(key, analyzed, _)| {
+            *key == self.active_canvas && *analyzed == generation
+        });
+        if !current {
+            if generation > 0 && !self.app.analyzing.swap(true, Ordering::AcqRel) {
+                let texture = instance
+                    .target
+                    .lock()
+                    .output
+                    .as_ref()
+                    .map(|output| output.texture.clone(&self.app.dev));
+                match texture {
+                    Some(texture) => {
+                        self.app.rt.spawn(self.app.clone().analyze(
+                            self.active_canvas,
+                            generation,
+                            texture,
+                        ));
+                    }
+                    None => self.app.analyzing.store(false, Ordering::Release),
+                }
+            }
+            // Pick up the statistics once they are done.
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
+        let Some((_, _, stats)) = analysis
+            .as_ref()
+            .filter(|(key, ..)| *key == self.active_canvas)
+        else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Analyzing composite...");
+            });
+            return;
+        };
+
+        Grid::new("Analysis Grid").num_columns(2).show(ui, |ui| {
+            for (label, color) in [("Average", stats.average), ("Dominant", stats.dominant)] {
+                ui.label(label);
+                match color {
+                    Some([r, g, b]) => {
+                        ui.horizontal(|ui| {
+                            color_picker::show_color(
+                                ui,
+                                Color32::from_rgb(r, g, b),
+                                vec2(24.0, 24.0),
+                            );
+                            let hex = picker::ColorFormat::Hex.format([r, g, b, 255]);
+                            if ui.button(&hex).on_hover_text("Copy").clicked() {
+                                ui.output_mut(|output| output.copied_text = hex);
+                            }
+                        });
+                    }
+                    None => {
+                        ui.label("Fully transparent");
+                    }
+                }
+                ui.end_row();
+            }
+            ui.label("Coverage");
+            ui.label(format!("{:.1}%", stats.coverage * 100.0));
+            ui.end_row();
+            if !current {
+                ui.label("");
+                ui.weak("Updating...");
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.label("Color");
+        let [r, g, b, alpha] = &stats.histogram;
+        Self::layout_histogram(
+            ui,
+            &[
+                (r, Color32::from_rgb(230, 60, 60)),
+                (g, Color32::from_rgb(60, 200, 60)),
+                (b, Color32::from_rgb(70, 110, 240)),
+            ],
+        );
+        ui.label("Alpha");
+        Self::layout_histogram(ui, &[(alpha, Color32::from_gray(200))]);
+    }
+
+    /// Plot histograms of channel values over each other, scaled to the
+    /// highest count among them.
+    fn layout_histogram(ui: &mut Ui, channels: &[(&[u32; 256], Color32)]) {
+        let (rect, _) = ui.allocate_exact_size(vec2(ui.available_width(), 80.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, Rounding::same(2.0), ui.visuals().extreme_bg_color);
+        let max = channels
+            .iter()
+            .flat_map(|(histogram, _)| histogram.iter())
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        for (histogram, color) in channels {
+            let points = histogram
+                .iter()
+                .enumerate()
+                .map(|(value, count)| {
+                    pos2(
+                        rect.left() + rect.width() * value as f32 / 255.0,
+                        rect.bottom() - rect.height() * *count as f32 / max as f32,
+                    )
+                })
+                .collect();
+            painter.add(Shape::line(points, Stroke::new(1.0, *color)));
+        }
+    }
+
+    /// Structure of the file on disk, checked in the background without
+    /// decoding its tiles.
+    fn layout_integrity(&self, ui: &mut Ui) {
+        let instances = self.app.compositor.instances.read();
+        let Some(instance) = instances.get(&self.active_canvas) else {
+            ui.label("No canvas loaded.");
+            return;
+        };
+        let content_hash = instance.file.read().content_hash;
+        let path = instance.path.clone();
+        drop(instances);
+
+        let mut inspection = self.app.inspection.lock();
+        let current = inspection
+            .as_ref()
+            .is_some_and(|(key, hash, _)| *key == self.active_canvas && *hash == content_hash);
+        if !current {
+            if !self.app.inspecting.swap(true, Ordering::AcqRel) {
+                self.app.rt.spawn(
+                    self.app
+                        .clone()
+                        .inspect(self.active_canvas, content_hash, path),
+                );
+            }
+            // Pick up the report once it is done.
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
+        let Some((_, _, report)) = inspection
+            .as_ref()
+            .filter(|(key, ..)| *key == self.active_canvas)
+        else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Inspecting file...");
+            });
+            return;
+        };
+        let report = match report {
+            Ok(report) => report,
+            Err(err) => {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("The file could not be inspected: {err}"),
+                );
+                return;
+            }
+        };
+
+        let mut check_again = false;
+        ui.horizontal(|ui| {
+            if report.is_intact() {
+                ui.label("No problems found.");
+            } else {
+                ui.colored_label(ui.visuals().warn_fg_color, "⚠ Problems found.");
+            }
+            if ui.button("Copy Report").clicked() {
+                ui.output_mut(|o| o.copied_text = report.summary(SOFTWARE));
+            }
+            check_again = ui
+                .add_enabled(current, Button::new("Check Again"))
+                .on_hover_text("Inspect the file on disk again, such as after it changed.")
+                .clicked();
+        });
+        ui.separator();
+
+        let mib = |bytes: u64| format!("{:.1} MiB", bytes as f64 / f64::from(1 << 20));
+        Grid::new("Integrity Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Layers");
+            ui.label(format!(
+                "{} in {} groups",
+                report.layers.len(),
+                report.groups
+            ));
+            ui.end_row();
+            ui.label("Chunks");
+            ui.label(report.chunk_count().to_string());
+            ui.end_row();
+            ui.label("Compression");
+            ui.label(
+                report
+                    .compression
+                    .iter()
+                    .map(|(compression, count)| format!("{count} {}", compression.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+            ui.end_row();
+            ui.label("Stored Size");
+            ui.label(mib(report.compressed_size()));
+            ui.end_row();
+            ui.label("Decompressed Size").on_hover_text(
+                "Approximate memory taken by the tiles once decompressed, without \
+                 the textures for groups and the canvas.",
+            );
+            ui.label(mib(report.decompressed_size()));
+            ui.end_row();
+            ui.label("Orphaned Chunks")
+                .on_hover_text("Chunks stored in the file that belong to no layer.");
+            ui.label(report.orphaned_chunks.len().to_string());
+            ui.end_row();
+            ui.label("Invalid Keys");
+            ui.label(report.problems.len().to_string());
+            ui.end_row();
+            ui.label("Unknown Keys");
+            ui.label(report.coverage.unknown_key_count().to_string());
+            ui.end_row();
+        });
+
+        ui.separator();
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("Integrity Layers")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Layer");
+                    ui.strong("Chunks");
+                    ui.strong("Empty Tiles").on_hover_text(
+                        "Tiles without a chunk, which Procreate leaves out where a \
+                         layer is fully transparent.",
+                    );
+                    ui.end_row();
+                    for (layer, name) in report
+                        .layers
+                        .iter()
+                        .map(|layer| (layer, layer.path.as_str()))
+                        .chain(report.composite.iter().map(|layer| (layer, "Composite")))
+                    {
+                        let label = if layer.problems.is_empty() {
+                            RichText::new(name)
+                        } else {
+                            RichText::new(format!("⚠ {name}")).color(ui.visuals().warn_fg_color)
+                        };
+                        ui.label(label).on_hover_text(if layer.problems.is_empty() {
+                            layer.uuid.clone()
+                        } else {
+                            layer.problems.join("\n")
+                        });
+                        ui.label(layer.chunks.to_string());
+                        ui.label(layer.empty_tiles.to_string());
+                        ui.end_row();
+                    }
+                });
+            for (title, items) in [
+                ("Orphaned Chunks", &report.orphaned_chunks),
+                ("Invalid Keys", &report.problems),
+            ] {
+                if !items.is_empty() {
+                    CollapsingHeader::new(title).show(ui, |ui| {
+                        for item in items {
+                            ui.label(item);
+                        }
+                    });
+                }
+            }
+        });
+
+        if check_again {
+            *inspection = None;
+        }
+    }
+
+    fn layout_layer_control(
+        ui: &mut Ui,
+        i: usize,
+        l: &mut SilicaLayer,
+        changed: &mut bool,
+        actions: &mut NodeActions,
+    ) {
         ui.horizontal_wrapped(|ui| {
             *changed |= ui.checkbox(&mut l.hidden, "Hidden").changed();
             *changed |= ui.checkbox(&mut l.clipped, "Clipped").changed();
         });
+        let texture = l.image;
+        Self::layout_blend_control(
+            ui,
+            i,
+            texture,
+            &mut l.blend,
+            &mut l.opacity,
+            changed,
+            actions,
+        )
+    }
+
+    fn layout_group_control(
+        ui: &mut Ui,
+        i: usize,
+        g: &mut SilicaGroup,
+        changed: &mut bool,
+        actions: &mut NodeActions,
+    ) {
+        *changed |= ui.checkbox(&mut g.hidden, "Hidden").changed();
+        let texture = g.image;
+        Self::layout_blend_control(
+            ui,
+            i,
+            texture,
+            &mut g.blend,
+            &mut g.opacity,
+            changed,
+            actions,
+        )
+    }
+
+    /// Blending mode and opacity of the layer or group with the texture.
+    fn layout_blend_control(
+        ui: &mut Ui,
+        i: usize,
+        texture: u32,
+        blend: &mut BlendingMode,
+        opacity: &mut f32,
+        changed: &mut bool,
+        actions: &mut NodeActions,
+    ) {
         Grid::new(i).show(ui, |ui| {
             ui.label("Blend");
             ComboBox::from_id_source(0)
-                .selected_text(l.blend.as_str())
+                .selected_text(blend.as_str())
                 .show_ui(ui, |ui| {
                     for b in BlendingMode::all() {
-                        *changed |= ui.selectable_value(&mut l.blend, *b, b.as_str()).changed();
+                        let response = ui.selectable_value(blend, *b, b.as_str());
+                        if response.hovered() {
+                            actions.blend_preview = Some((texture, *b));
+                        }
+                        *changed |= response.changed();
                     }
                 });
             ui.end_row();
 
-            let mut percent = l.opacity * 100.0;
+            let mut percent = *opacity * 100.0;
             ui.label("Opacity");
-            *changed |= ui
-                .add(
-                    Slider::new(&mut percent, 0.0..=100.0)
-                        .fixed_decimals(0)
-                        .suffix("%"),
-                )
-                .changed();
-            l.opacity = percent / 100.0;
+            let response = ui.add(
+                Slider::new(&mut percent, 0.0..=100.0)
+                    .fixed_decimals(0)
+                    .suffix("%"),
+            );
+            *changed |= response.changed();
+            actions.dragging |= response.dragged();
+            *opacity = percent / 100.0;
         });
     }
 
-    fn layout_layers_sub(ui: &mut Ui, layers: &mut SilicaGroup, i: &mut usize, changed: &mut bool) {
+    fn layout_layers_sub(
+        ui: &mut Ui,
+        layers: &mut SilicaGroup,
+        i: &mut usize,
+        changed: &mut bool,
+        key: InstanceKey,
+        selection: &mut LayerSelection,
+        actions: &mut NodeActions,
+    ) {
         for layer in &mut layers.children {
             *i += 1;
             match layer {
@@ -173,35 +1997,126 @@ impl ControlsGui<'_> {
                     ui.push_id(*i, |ui| {
                         *i += 1;
 
-                        ui.collapsing(
-                            l.name
-                                .to_owned()
-                                .unwrap_or_else(|| format!("Unnamed Layer [{i}]")),
-                            |ui| {
-                                Self::layout_layer_control(ui, *i, l, changed);
-                            },
-                        );
+                        let name = l
+                            .name
+                            .to_owned()
+                            .unwrap_or_else(|| format!("Unnamed Layer [{i}]"));
+                        let mut header = match &l.damage {
+                            Some(_) => {
+                                RichText::new(format!("⚠ {name}")).color(ui.visuals().warn_fg_color)
+                            }
+                            None => RichText::new(&name),
+                        };
+                        if selection.is_selected(key, &l.uuid) {
+                            let visuals = &ui.visuals().selection;
+                            header = header
+                                .background_color(visuals.bg_fill)
+                                .color(visuals.stroke.color);
+                        }
+                        let reveal = selection.take_reveal(key, &l.uuid);
+                        let response = CollapsingHeader::new(header)
+                            .open(reveal.then_some(true))
+                            .show(ui, |ui| {
+                                if let Some(damage) = &l.damage {
+                                    ui.colored_label(
+                                        ui.visuals().warn_fg_color,
+                                        format!(
+                                            "{} of {} tiles failed to load.",
+                                            damage.tiles, damage.total
+                                        ),
+                                    );
+                                }
+                                Self::layout_layer_control(ui, *i, l, changed, actions);
+                            });
+                        let header_response = response.header_response;
+                        if reveal {
+                            header_response.scroll_to_me(Some(Align::Center));
+                        }
+                        if header_response.clicked() {
+                            if ui.input(|input| input.modifiers.command) {
+                                selection.toggle(key, &l.uuid);
+                            } else {
+                                selection.select(key, &l.uuid, false);
+                            }
+                        }
+                        header_response.context_menu(|ui| {
+                            if ui.button("Export Layer as PNG").clicked() {
+                                actions.export = Some((l.image, name));
+                                ui.close_menu();
+                            }
+                        });
+                        if let Some(damage) = &l.damage {
+                            header_response.on_hover_text(&damage.reason);
+                        }
                     });
                 }
                 SilicaHierarchy::Group(h) => {
                     ui.push_id(*i, |ui| {
                         *i += 1;
-                        ui.collapsing(
-                            h.name
-                                .to_owned()
-                                .unwrap_or_else(|| format!("Unnamed Group [{i}]")),
-                            |ui| {
-                                *changed |= ui.checkbox(&mut h.hidden, "Hidden").changed();
-                                Self::layout_layers_sub(ui, h, i, changed);
-                            },
-                        )
+                        let reveal = selection.reveals_in(key, h);
+                        let name = h
+                            .name
+                            .to_owned()
+                            .unwrap_or_else(|| format!("Unnamed Group [{i}]"));
+                        let response = CollapsingHeader::new(&name)
+                            .open(reveal.then_some(true))
+                            .show(ui, |ui| {
+                                Self::layout_group_control(ui, *i, h, changed, actions);
+                                Self::layout_layers_sub(ui, h, i, changed, key, selection, actions);
+                            });
+                        response.header_response.context_menu(|ui| {
+                            if ui.button("Export Group as PNG").clicked() {
+                                actions.export = Some((h.image, name));
+                                ui.close_menu();
+                            }
+                        });
                     });
                 }
             }
         }
     }
 
-    fn layout_layers(&self, ui: &mut Ui) {
+    /// Move the selection to the previous or next layer with the arrow keys,
+    /// unless a widget has keyboard focus.
+    fn step_selection(
+        ui: &Ui,
+        layers: &SilicaGroup,
+        key: InstanceKey,
+        selection: &mut LayerSelection,
+    ) {
+        if ui.memory(|memory| memory.focus().is_some()) {
+            return;
+        }
+        let step = ui.input(|input| {
+            if input.key_pressed(Key::ArrowUp) {
+                -1
+            } else if input.key_pressed(Key::ArrowDown) {
+                1
+            } else {
+                0
+            }
+        });
+        if step == 0 {
+            return;
+        }
+        let uuids = layers
+            .iter_layers()
+            .map(|l| l.uuid.as_str())
+            .collect::<Vec<_>>();
+        let index = match selection
+            .focused(key)
+            .and_then(|focused| uuids.iter().position(|uuid| *uuid == focused))
+        {
+            Some(index) => index.saturating_add_signed(step).min(uuids.len() - 1),
+            None if step > 0 => 0,
+            None => uuids.len().saturating_sub(1),
+        };
+        if let Some(uuid) = uuids.get(index) {
+            selection.select(key, uuid, true);
+        }
+    }
+
+    fn layout_layers(&mut self, ui: &mut Ui) {
         if let Some(instance) = self
             .app
             .compositor
@@ -209,60 +2124,495 @@ impl ControlsGui<'_> {
             .read()
             .get(&self.active_canvas)
         {
-            let mut file = instance.file.write();
+            // Edit a copy showing the commands that were not applied yet, and
+            // send what changed for the rendering thread to apply.
+            let file = instance.file.read();
+            let mut layers = file.layers.clone();
+            let mut background = BackgroundProperties::of(&file);
+            let has_background_image = file.background_image.is_some();
+            instance.commands.preview(&mut layers, &mut background);
+            drop(file);
             let mut changed = false;
+            let before = EditState::new(&layers, background);
+            // Applied to the copy, so that it is sent and undone like any
+            // edit made here.
+            let preset = self
+                .app
+                .loaded_preset
+                .lock()
+                .take_if(|(key, _)| *key == self.active_canvas);
+            if let Some((_, preset)) = preset {
+                let missing = preset.apply(&mut layers);
+                changed = true;
+                let mut toasts = self.app.toasts.lock();
+                if missing > 0 {
+                    toasts.warning(format!(
+                        "Layer preset applied. {missing} of its layers and groups are not in this file."
+                    ));
+                } else {
+                    toasts.success("Layer preset applied.");
+                }
+            }
+
+            Self::step_selection(ui, &layers, self.active_canvas, self.selection);
+            ui.horizontal(|ui| {
+                let response = ui.add(
+                    TextEdit::singleline(self.layer_search)
+                        .hint_text("Find Layers")
+                        .desired_width(160.0),
+                );
+                let mut found = selection::search_layers(&layers, self.layer_search);
+                let entered =
+                    response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+                if (response.changed() || entered) && !found.is_empty() {
+                    // The topmost match is focused.
+                    found.reverse();
+                    self.selection.select_all(self.active_canvas, &found);
+                }
+                if !self.layer_search.trim().is_empty() {
+                    ui.label(match found.len() {
+                        1 => "1 layer found".to_string(),
+                        n => format!("{n} layers found"),
+                    });
+                }
+            });
+            let selected = self.selection.len(self.active_canvas);
+            ui.horizontal(|ui| {
+                let focused = self.selection.focused(self.active_canvas);
+                if ui
+                    .add_enabled(focused.is_some(), Button::new("Copy Layer"))
+                    .on_hover_text("Copies the selected layer to the clipboard (Ctrl+Shift+C).")
+                    .clicked()
+                {
+                    self.app.rt.spawn(self.app.clone().copy_image(
+                        self.active_canvas,
+                        focused.map(str::to_string),
+                        instance.display().crop,
+                    ));
+                }
+                if ui
+                    .button("Save Preset")
+                    .on_hover_text(
+                        "Saves the visibility, opacity and blending of every layer and group next to the file.",
+                    )
+                    .clicked()
+                {
+                    self.app
+                        .rt
+                        .spawn(self.app.clone().save_preset_dialog(self.active_canvas));
+                }
+                if ui.button("Load Preset").clicked() {
+                    self.app
+                        .rt
+                        .spawn(self.app.clone().load_preset_dialog(self.active_canvas));
+                }
+                if selected > 1 {
+                    ui.label(format!("{selected} layers selected"));
+                }
+            });
 
             let mut i = 0;
-            Self::layout_layers_sub(ui, &mut file.layers, &mut i, &mut changed);
+            let mut actions = NodeActions::default();
+            Self::layout_layers_sub(
+                ui,
+                &mut layers,
+                &mut i,
+                &mut changed,
+                self.active_canvas,
+                self.selection,
+                &mut actions,
+            );
+            let display = instance.display();
+            if display.blend_preview != actions.blend_preview {
+                instance
+                    .commands
+                    .send(InstanceCommand::BlendPreview(actions.blend_preview));
+            }
+            if display.dragging != actions.dragging {
+                instance
+                    .commands
+                    .send(InstanceCommand::Dragging(actions.dragging));
+            }
+            if let Some((texture, name)) = actions.export {
+                let metadata = instance.file.read().export_metadata(SOFTWARE);
+                self.app.rt.spawn(self.app.clone().export_node_dialog(
+                    self.active_canvas,
+                    texture,
+                    name,
+                    metadata,
+                ));
+            }
 
             ui.separator();
 
             // Let background controls be first since color controls are bad.
             Grid::new("layers.background").show(ui, |ui| {
                 ui.label("Background");
-                changed |= ui.checkbox(&mut file.background_hidden, "Hidden").changed();
+                changed |= ui.checkbox(&mut background.hidden, "Hidden").changed();
                 ui.end_row();
                 ui.label("Background Color");
+                changed |= ui
+                    .color_edit_button_rgba_unmultiplied(&mut background.color)
+                    .changed();
 
-                // Safety: This is trivially safe. The underlying container is of 4 elements.
-                // This does the same thing as split_array_mut except that is not stabilized yet.
-                let bg = unsafe { &mut *(file.background_color.as_mut_ptr() as *mut [f32; 3]) };
-                changed |= ui.color_edit_button_rgb(bg).changed();
+                if has_background_image {
+                    ui.end_row();
+                    ui.label("Background Image");
+                    changed |= ui
+                        .checkbox(&mut background.image_hidden, "Hidden")
+                        .changed();
+                }
             });
 
-            instance.store_change_or(changed);
+            if changed {
+                let after = EditState::new(&layers, background);
+                for command in before.commands_to(&after) {
+                    instance.commands.send(command);
+                }
+                instance.history.lock().record(&before, &after);
+            }
         } else {
             ui.label("No file hierachy.");
         }
     }
 }
 
+/// What was asked of the layers and groups in the hierarchy, handled once
+/// it is laid out.
+#[derive(Default)]
+struct NodeActions {
+    /// Texture and name of a layer or group to export on its own.
+    export: Option<(u32, String)>,
+    /// Blending mode hovered for the layer or group with the texture.
+    blend_preview: Option<(u32, BlendingMode)>,
+    /// Whether the opacity slider of a layer or group is being dragged.
+    dragging: bool,
+}
+
 pub struct ViewOptions {
     pub extended_crosshair: bool,
     pub smooth: bool,
     pub grid: bool,
-    pub rotation: f32,
+    /// Outline the canvas pixels when zoomed in far enough.
+    pub pixel_grid: bool,
+    /// Rulers with canvas pixel coordinates along the view edges.
+    pub rulers: bool,
+    /// Angles that the rotation slider snaps to.
+    pub rotation_snap: RotationSnap,
     pub bottom_bar: bool,
+    /// What double clicking the canvas does with each pointer button.
+    pub double_click: DoubleClickActions,
+    /// Keys of the single key canvas shortcuts.
+    pub keymap: Keymap,
+    /// Dragging on the canvas selects the region to export instead of panning.
+    pub crop_tool: bool,
+    /// Clicking on the canvas samples its color.
+    pub color_picker: bool,
+    /// Whether the interface animates.
+    pub motion: MotionPreference,
+    /// Show the frame statistics overlay, toggled with F3.
+    pub stats_hud: bool,
+    pub theme: Theme,
+    /// Color of the selection and links, the theme's own when `None`.
+    pub accent: Option<[u8; 3]>,
+    /// Color drawn behind the canvas.
+    pub backdrop: [u8; 3],
+    /// Pattern drawn behind the transparent parts of the canvas.
+    pub transparency: Transparency,
+    /// Open the file picker on startup when no files are passed on the
+    /// command line.
+    pub open_on_startup: bool,
+    /// Lower the resolution of composites while edits render too slowly.
+    pub adaptive_quality: bool,
+    /// Seconds that presentation mode shows each tab for before moving on
+    /// to the next, staying on the same tab if `None`.
+    pub presentation_cycle: Option<u32>,
+}
+
+impl ViewOptions {
+    /// Seconds that presentation mode can show each tab for.
+    pub const PRESENTATION_CYCLE: std::ops::RangeInclusive<u32> = 1..=3600;
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        Self {
+            extended_crosshair: false,
+            smooth: false,
+            grid: true,
+            pixel_grid: true,
+            rulers: false,
+            rotation_snap: RotationSnap::Off,
+            bottom_bar: false,
+            double_click: canvas::DEFAULT_DOUBLE_CLICK_ACTIONS,
+            keymap: Keymap::default(),
+            crop_tool: false,
+            color_picker: false,
+            motion: MotionPreference::System,
+            stats_hud: false,
+            theme: Theme::Dark,
+            accent: None,
+            backdrop: theme::DEFAULT_BACKDROP,
+            transparency: Transparency::default(),
+            open_on_startup: false,
+            adaptive_quality: true,
+            presentation_cycle: None,
+        }
+    }
+}
+
+/// Tabs closed together from the context menu of a tab.
+#[derive(Debug, Clone, Copy)]
+enum CloseTabs {
+    /// Every tab except this one.
+    Others(InstanceKey),
+    All,
 }
 
 struct CanvasGui<'a> {
     app: &'a Arc<App>,
-    canvases: &'a mut HashMap<InstanceKey, SizedTexture>,
+    canvases: &'a mut HashMap<InstanceKey, canvas::CanvasImage>,
     instances: &'a HashMap<InstanceKey, Instance>,
-    view_options: &'a ViewOptions,
+    view_options: &'a mut ViewOptions,
+    /// Files drawn this frame.
+    visible: &'a mut HashSet<InstanceKey>,
+    deferred_rebinds: &'a mut HashSet<InstanceKey>,
+    view_history: &'a mut ViewHistory,
+    selection: &'a LayerSelection,
+    /// Set when tabs are closed from a context menu, which happens once the
+    /// dock is drawn.
+    close_tabs: &'a mut Option<CloseTabs>,
+}
+
+impl CanvasGui<'_> {
+    /// Mark a file as drawn this frame, catching up on the texture rebinds
+    /// skipped while it was hidden.
+    fn show(&mut self, key: InstanceKey, instance: &Instance) {
+        self.app.show_instance(key, instance);
+        self.visible.insert(key);
+        if self.deferred_rebinds.remove(&key) {
+            self.app.rebind_texture(key);
+        }
+    }
+}
+
+impl CanvasGui<'_> {
+    /// Show a file alone and fitted to the view, without the overlays.
+    fn present(&mut self, ui: &mut Ui, key: InstanceKey) {
+        let Some(instance) = self.instances.get(&key) else {
+            return;
+        };
+        self.show(key, instance);
+        let view = *instance.view.lock();
+        canvas::CanvasView::new(("presentation", key), self.canvases.get(&key).cloned())
+            .view_transform(view)
+            .show_bottom_bar(false)
+            .backdrop({
+                let [r, g, b] = self.view_options.backdrop;
+                Color32::from_rgb(r, g, b)
+            })
+            .transparency(self.view_options.transparency)
+            .show(ui);
+    }
+}
+
+impl CanvasGui<'_> {
+    /// Buttons to show a file in the file manager and reload it from disk.
+    fn file_actions(ui: &mut Ui, app: &Arc<App>, key: InstanceKey, instance: &Instance) {
+        if ui.button("Reveal in File Manager").clicked() {
+            app.show_in_file_manager(instance.path.clone(), true);
+            ui.close_menu();
+        }
+        if ui.button("Open Containing Folder").clicked() {
+            app.show_in_file_manager(instance.path.clone(), false);
+            ui.close_menu();
+        }
+        if ui
+            .button("Reload from Disk")
+            .on_hover_text("Keeps layer changes where the layers did not change on disk.")
+            .clicked()
+        {
+            app.rt.spawn(app.clone().reload_file(key));
+            ui.close_menu();
+        }
+    }
 }
 
 impl egui_dock::TabViewer for CanvasGui<'_> {
     type Tab = InstanceKey;
 
     fn ui(&mut self, ui: &mut Ui, tab: &mut Self::Tab) {
+        let instance = self.instances.get(tab);
+        if let Some(instance) = instance {
+            self.show(*tab, instance);
+            if instance.changed_on_disk.lock().is_some() {
+                ui.horizontal(|ui| {
+                    ui.label("The file changed on disk.");
+                    if ui
+                        .button("Reload")
+                        .on_hover_text(
+                            "Keeps the view and layer changes where the layers did not change on disk.",
+                        )
+                        .clicked()
+                    {
+                        instance.dismiss_change();
+                        self.app.rt.spawn(self.app.clone().reload_file(*tab));
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        instance.dismiss_change();
+                    }
+                });
+            }
+        }
+        let crop = instance.and_then(|instance| instance.display().crop);
+        let compare = instance
+            .and_then(|instance| *instance.compare.lock())
+            .and_then(|compare| {
+                let other = self.instances.get(&compare.with)?;
+                // Keep the compared file loaded while it is on screen.
+                self.show(compare.with, other);
+                Some(canvas::Comparison {
+                    image: self.canvases.get(&compare.with)?.clone(),
+                    mode: compare.mode,
+                    amount: compare.amount,
+                })
+            });
+        let content_hash = instance.map(|instance| instance.file.read().content_hash);
+        let saved = instance
+            .zip(content_hash)
+            .and_then(|(instance, hash)| self.view_history.get(hash, &instance.path));
+        let view = instance.map_or_else(Default::default, |instance| *instance.view.lock());
+        let guides = instance.map_or_else(Default::default, |instance| *instance.guides.lock());
         let tex = self.canvases.get(tab);
-        canvas::CanvasView::new(*tab, tex.copied().map(Image::from_texture))
-            .with_rotation(self.view_options.rotation)
+        let loaded = tex.is_some();
+        let response = canvas::CanvasView::new(*tab, tex.cloned())
+            .initial_bounds(saved.as_ref().and_then(|view| view.bounds))
+            .view_transform(view)
             .show_extended_crosshair(self.view_options.extended_crosshair)
             .show_grid(self.view_options.grid)
+            .show_pixel_grid(self.view_options.pixel_grid)
+            .show_rulers(self.view_options.rulers)
             .show_bottom_bar(self.view_options.bottom_bar)
+            .backdrop({
+                let [r, g, b] = self.view_options.backdrop;
+                Color32::from_rgb(r, g, b)
+            })
+            .transparency(self.view_options.transparency)
+            .double_click_actions(self.view_options.double_click)
+            .crop(crop.map(canvas::region_rect))
+            .crop_tool(self.view_options.crop_tool)
+            .color_picker(self.view_options.color_picker)
+            .compare(compare)
+            .guides(guides)
             .show(ui);
+        if response.inner.double_click == Some(DoubleClickAction::SnapRotation) {
+            if let Some(instance) = instance {
+                instance.view.lock().snap_to_quarter();
+            }
+        }
+        if let (Some(crop), Some(instance)) = (response.inner.crop, instance) {
+            let crop = Some(canvas::rect_region(crop));
+            instance.commands.send(InstanceCommand::Crop(crop));
+        }
+        if let (true, Some(instance), Some(hash)) = (loaded, instance, content_hash) {
+            self.view_history.record(
+                hash,
+                &instance.path,
+                SavedView {
+                    bounds: response.inner.bounds,
+                    rotation: view.rotation,
+                    guides,
+                    // Only one file has a selection at a time, so the others
+                    // keep the one they had.
+                    selection: match self.selection.len(*tab) {
+                        0 => saved.map(|view| view.selection).unwrap_or_default(),
+                        _ => self.selection.saved(*tab),
+                    },
+                },
+            );
+        }
+        // Only some platforms let files be dragged out of the window.
+        if cfg!(any(windows, target_os = "macos")) {
+            if !ui.input(|input| input.pointer.primary_down()) {
+                self.app.dragging_out.store(false, Ordering::Release);
+            } else if let (true, Some(instance)) = (response.inner.drag_out, instance) {
+                if !self.app.dragging_out.swap(true, Ordering::AcqRel) {
+                    let file = instance.file.read();
+                    self.app.rt.spawn(self.app.clone().drag_out(
+                        *tab,
+                        file.name.clone().unwrap_or("Untitled Artwork".to_string()),
+                        instance.display().crop,
+                        file.export_metadata(SOFTWARE),
+                    ));
+                }
+            }
+        }
+        if let (Some(split), Some(instance)) = (response.inner.compare_split, instance) {
+            if let Some(compare) = instance.compare.lock().as_mut() {
+                compare.amount = split;
+            }
+        }
+        if let Some(instance) = instance {
+            let stage = |(x, y): (u32, u32)| {
+                let target = instance.target.lock();
+                // Sample the pixel covering it in a lower resolution composite.
+                let d = target.downscale();
+                target
+                    .output
+                    .as_ref()
+                    .and_then(|output| output.texture.stage_pixel(&self.app.dev, (x / d, y / d), 0))
+            };
+            if let Some(readback) = response.inner.picked.and_then(stage) {
+                self.app.rt.spawn(self.app.clone().pick_color(readback));
+            }
+            if let Some(pixel) = response.inner.picked_layer {
+                self.app.rt.spawn(self.app.clone().pick_layer(*tab, pixel));
+            }
+            if self.view_options.color_picker {
+                // Show the colors read back in the meantime.
+                ui.ctx()
+                    .request_repaint_after(std::time::Duration::from_millis(50));
+            }
+            // Read the pixel under the pointer when it moves, one at a time.
+            if let Some(pixel) = response.inner.hovered {
+                let known = self
+                    .app
+                    .hovered_color
+                    .lock()
+                    .is_some_and(|hovered| hovered.pixel == pixel);
+                if !known && !self.app.reading_hover.swap(true, Ordering::AcqRel) {
+                    match stage(pixel) {
+                        Some(readback) => {
+                            self.app
+                                .rt
+                                .spawn(self.app.clone().hover_color(pixel, readback));
+                        }
+                        None => self.app.reading_hover.store(false, Ordering::Release),
+                    }
+                }
+            }
+        }
+    }
+
+    fn context_menu(
+        &mut self,
+        ui: &mut Ui,
+        tab: &mut Self::Tab,
+        _surface: SurfaceIndex,
+        _node: NodeIndex,
+    ) {
+        if let Some(instance) = self.instances.get(tab) {
+            Self::file_actions(ui, self.app, *tab, instance);
+            ui.separator();
+        }
+        if ui.button("Close Others").clicked() {
+            *self.close_tabs = Some(CloseTabs::Others(*tab));
+            ui.close_menu();
+        }
+        if ui.button("Close All").clicked() {
+            *self.close_tabs = Some(CloseTabs::All);
+            ui.close_menu();
+        }
     }
 
     fn on_close(&mut self, tab: &mut Self::Tab) -> bool {
@@ -291,23 +2641,152 @@ impl egui_dock::TabViewer for CanvasGui<'_> {
 pub struct ViewerGui {
     pub app: Arc<App>,
 
-    pub canvases: HashMap<InstanceKey, SizedTexture>,
+    pub canvases: HashMap<InstanceKey, canvas::CanvasImage>,
     pub active_canvas: InstanceKey,
     pub view_options: ViewOptions,
     pub canvas_tree: egui_dock::DockState<InstanceKey>,
     pub viewer_tree: egui_dock::DockState<ViewerTab>,
+    pub journal: SessionJournal,
+    pub frame_stats: FrameStats,
+    /// Files drawn in the last frame, either in their tab or compared.
+    pub visible_tabs: HashSet<InstanceKey>,
+    /// Files whose textures changed while hidden, rebound once shown.
+    pub deferred_rebinds: HashSet<InstanceKey>,
+    pub view_history: ViewHistory,
+    /// Layers selected in the hierarchy, on the canvas or by searching.
+    pub selection: LayerSelection,
+    /// Text entered in the layer search of the hierarchy.
+    pub layer_search: String,
+    pub atlas: AtlasInspector,
+    pub export_presets: ExportPresets,
+    pub settings_store: SettingsStore,
+    /// When presentation mode last moved to another tab, `None` outside of
+    /// presentation mode.
+    pub presenting: Option<Instant>,
+    pub gallery: Option<Gallery>,
+    pub switcher: TabSwitcher,
+    /// Link entered in the Open Link window, `None` while it is closed.
+    pub open_link: Option<String>,
+    /// Adapters of the backend chosen in the settings, listed once it is
+    /// chosen.
+    pub adapter_names: Option<(GpuBackend, Vec<String>)>,
+    /// Whether the open files were passed to a viewer started in place of
+    /// this one, which then deletes the piped files once they are closed.
+    pub files_handed_over: bool,
 }
 
 impl ViewerGui {
     pub fn remove_index(&mut self, index: InstanceKey) {
         self.canvases.remove(&index);
-        self.app.compositor.instances.write().remove(&index);
+        self.visible_tabs.remove(&index);
+        self.deferred_rebinds.remove(&index);
+        self.selection.forget(index);
+        let mut instances = self.app.compositor.instances.write();
+        if let Some(removed) = instances.remove(&index) {
+            if let Some(cancel) = removed.cancel_reload.lock().take() {
+                cancel.cancel();
+            }
+            // Files piped to standard input only exist while they are open,
+            // unless a relaunched viewer took them over.
+            if crate::paths::is_piped(&removed.path)
+                && !self.files_handed_over
+                && !instances
+                    .values()
+                    .any(|instance| instance.path == removed.path)
+            {
+                if let Err(err) = std::fs::remove_file(&removed.path) {
+                    tracing::warn!("Failed to delete {}: {err}", removed.path.display());
+                }
+            }
+        }
+        for instance in instances.values() {
+            let mut compare = instance.compare.lock();
+            if compare.is_some_and(|compare| compare.with == index) {
+                *compare = None;
+            }
+        }
+    }
+
+    /// Stop loading files and save the session, view history and settings
+    /// before the window closes.
+    pub fn shut_down(&mut self) {
+        // Files still loading would keep the app from exiting.
+        self.app.loads.cancel();
+        self.save_state();
+        // Release the files of the window before the loop exits, so that the
+        // rendering thread lets go of their GPU resources first.
+        self.close_window();
+    }
+
+    /// Close the session journal and save the view history and settings.
+    fn save_state(&mut self) {
+        self.journal.close();
+        self.view_history.save(true);
+        self.settings_store
+            .save(Settings::capture(&self.app, &self.view_options), true);
+    }
+
+    /// Start the viewer again with the files open in the window and the
+    /// next GPU to fall back to, after the device was lost. Backends lost
+    /// in earlier runs are skipped. Returns the GPU it was started with,
+    /// `None` if there is none left to fall back to.
+    pub fn relaunch_with_fallback(&mut self) -> std::io::Result<Option<GpuPreference>> {
+        let current = self.app.dev.preference;
+        // Both the backend asked for and the one the device ended up on,
+        // which differ when it was chosen automatically.
+        let mut lost = lost_backends();
+        lost.push(current.backend);
+        lost.push(GpuBackend::of_adapter(&self.app.dev.adapter.get_info()));
+        let Some(next) = current.fallbacks(&lost).into_iter().next() else {
+            return Ok(None);
+        };
+        let lost = lost
+            .iter()
+            .map(|backend| backend.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut command = std::process::Command::new(std::env::current_exe()?);
+        command
+            .args(["--backend", next.backend.as_str()])
+            .env(LOST_BACKENDS_VAR, &lost);
+        let instances = self.app.compositor.instances.read();
+        command.args(
+            self.canvas_tree
+                .iter_all_tabs()
+                .filter_map(|(_, key)| instances.get(key))
+                .map(|instance| &instance.path),
+        );
+        drop(instances);
+        // The new viewer reads the settings as they are now.
+        self.save_state();
+        command.spawn()?;
+        self.files_handed_over = true;
+        Ok(Some(next))
+    }
+
+    /// Tear down the files open in the tabs of the window once it closes,
+    /// leaving the files of other windows alone.
+    pub fn close_window(&mut self) {
+        let keys = self
+            .canvas_tree
+            .iter_all_tabs()
+            .map(|(_, key)| *key)
+            .collect::<Vec<_>>();
+        for key in keys {
+            self.remove_index(key);
+        }
+        self.canvas_tree = egui_dock::DockState::new(Vec::new());
     }
 
     fn layout_view(&mut self, ui: &mut Ui) {
         ui.set_min_size(ui.available_size());
 
-        let mut instances = self.app.compositor.instances.read();
+        // Held apart from `self`, which restoring views changes.
+        let app = self.app.clone();
+        let mut instances = app.compositor.instances.read();
+        let mut visible = HashSet::new();
+        let mut close_tabs = None;
 
         if instances.is_empty() {
             ui.allocate_space(vec2(
@@ -323,15 +2802,28 @@ impl ViewerGui {
                             .load_dialog(SurfaceIndex::main(), NodeIndex::root()),
                     );
                 }
+                if ui.button("Open Folder as Gallery").clicked() {
+                    self.app.rt.spawn(self.app.clone().gallery_dialog());
+                }
+                if ui.button("Open Link").clicked() {
+                    Self::show_open_link(&self.app, &mut self.open_link, ui.ctx());
+                }
             });
         } else {
-            if let Some(mut added_instances) = self.app.added_instances.try_lock() {
+            if let Some(mut added_instances) = app.added_instances.try_lock() {
                 for (surface, node, id) in added_instances.drain(..) {
+                    if let Some(instance) = instances.get(&id) {
+                        self.apply_saved_view(id, instance);
+                    }
                     self.canvas_tree
                         .set_focused_node_and_surface((surface, node));
                     self.canvas_tree.push_to_focused_leaf(id);
                 }
             }
+            let restored = app.restored_layout.lock().take();
+            if let Some(layout) = restored {
+                self.restore_layout(layout, &instances);
+            }
 
             if let Some((_, &mut id)) = self.canvas_tree.find_active_focused() {
                 self.active_canvas = id;
@@ -344,37 +2836,602 @@ impl ViewerGui {
                     ui,
                     &mut CanvasGui {
                         app: &self.app,
-                        view_options: &self.view_options,
+                        view_options: &mut self.view_options,
                         canvases: &mut self.canvases,
                         instances: &mut instances,
+                        visible: &mut visible,
+                        deferred_rebinds: &mut self.deferred_rebinds,
+                        view_history: &mut self.view_history,
+                        selection: &self.selection,
+                        close_tabs: &mut close_tabs,
                     },
                 );
         }
+        self.visible_tabs = visible;
+
+        if let Some(close_tabs) = close_tabs {
+            // Closed the same way as the close button of a tab.
+            self.canvas_tree.retain_tabs(|key| {
+                let keep = matches!(close_tabs, CloseTabs::Others(kept) if kept == *key);
+                if !keep {
+                    self.app
+                        .event_loop
+                        .send_event(UserEvent::RemoveInstance(*key))
+                        .unwrap();
+                }
+                keep
+            });
+        }
     }
 
-    pub fn layout_gui(&mut self, context: &Context) {
-        SidePanel::new(panel::Side::Right, "Side Panel")
-            .default_width(300.0)
-            .frame(Frame::none())
+    /// Rotate the active canvas by right angles with `[` and `]`, or the
+    /// keys they are rebound to.
+    fn rotation_shortcuts(&self, context: &Context) {
+        if context.memory(|memory| memory.focus().is_some()) {
+            return;
+        }
+        let keymap = &self.view_options.keymap;
+        let quarters = context.input(|input| {
+            i32::from(input.key_pressed(keymap.key(Shortcut::RotateRight)))
+                - i32::from(input.key_pressed(keymap.key(Shortcut::RotateLeft)))
+        });
+        if quarters == 0 {
+            return;
+        }
+        if let Some(instance) = self
+            .app
+            .compositor
+            .instances
+            .read()
+            .get(&self.active_canvas)
+        {
+            instance.view.lock().rotate_quarters(quarters);
+        }
+    }
+
+    /// Switch the active canvas to the composite stored in its file and
+    /// back with R, or the key it is rebound to.
+    fn reference_shortcuts(&self, context: &Context) {
+        let key = self.view_options.keymap.key(Shortcut::StoredComposite);
+        if context.memory(|memory| memory.focus().is_some())
+            || !context.input(|input| input.key_pressed(key))
+        {
+            return;
+        }
+        if let Some(instance) = self
+            .app
+            .compositor
+            .instances
+            .read()
+            .get(&self.active_canvas)
+            .filter(|instance| instance.file.read().composite.is_some())
+        {
+            instance.toggle_stored_composite();
+        }
+    }
+
+    /// Undo edits to the active file with Ctrl+Z and redo them with
+    /// Ctrl+Shift+Z or Ctrl+Y.
+    fn history_shortcuts(&self, context: &Context) {
+        if context.memory(|memory| memory.focus().is_some()) {
+            return;
+        }
+        let steps = context.input_mut(|input| {
+            let redo = input.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z)
+                || input.consume_key(Modifiers::COMMAND, Key::Y);
+            if redo {
+                1
+            } else if input.consume_key(Modifiers::COMMAND, Key::Z) {
+                -1
+            } else {
+                0
+            }
+        });
+        if steps != 0 {
+            self.app.step_history(self.active_canvas, steps);
+        }
+    }
+
+    /// Copy the composite of the active file to the clipboard with Ctrl+C,
+    /// or its focused layer with Ctrl+Shift+C.
+    fn copy_shortcuts(&self, context: &Context) {
+        if context.memory(|memory| memory.focus().is_some()) {
+            return;
+        }
+        // Copying arrives as its own event rather than as a key press.
+        let copy = context.input_mut(|input| {
+            let events = input.events.len();
+            input.events.retain(|event| !matches!(event, Event::Copy));
+            (input.events.len() != events).then_some(input.modifiers.shift)
+        });
+        let Some(layer) = copy else {
+            return;
+        };
+        let layer = match self.selection.focused(self.active_canvas) {
+            Some(uuid) if layer => Some(uuid.to_string()),
+            None if layer => return,
+            _ => None,
+        };
+        if let Some(instance) = self
+            .app
+            .compositor
+            .instances
+            .read()
+            .get(&self.active_canvas)
+        {
+            self.app.rt.spawn(self.app.clone().copy_image(
+                self.active_canvas,
+                layer,
+                instance.display().crop,
+            ));
+        }
+    }
+
+    /// Open the Open Link window, filled in with a link copied from
+    /// elsewhere if there is one.
+    fn show_open_link(app: &App, open_link: &mut Option<String>, context: &Context) {
+        *open_link = Some(app.clipboard_link().unwrap_or_default());
+        context.memory_mut(|memory| memory.request_focus(Id::new("open_link.text")));
+    }
+
+    /// Window to download and open a file from an HTTP or WebDAV link,
+    /// opened with Ctrl+L.
+    fn layout_open_link(&mut self, context: &Context) {
+        if self.open_link.is_none()
+            && context.memory(|memory| memory.focus().is_none())
+            && context.input_mut(|input| input.consume_key(Modifiers::COMMAND, Key::L))
+        {
+            Self::show_open_link(&self.app, &mut self.open_link, context);
+        }
+        let app = &self.app;
+        let Some(link) = &mut self.open_link else {
+            return;
+        };
+        let mut open = true;
+        let mut done = false;
+        Window::new("Open Link")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_TOP, vec2(0.0, 64.0))
+            .show(context, |ui| {
+                ui.label("Link to a file on a web server or a WebDAV share, such as a NAS.");
+                let response = ui.add(
+                    TextEdit::singleline(link)
+                        .id(Id::new("open_link.text"))
+                        .hint_text("https://")
+                        .desired_width(360.0),
+                );
+                let entered =
+                    response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(App::is_link(link), Button::new("Open"))
+                        .clicked()
+                        || (entered && App::is_link(link))
+                    {
+                        app.rt.spawn(app.clone().open_link(link.clone()));
+                        done = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        done = true;
+                    }
+                });
+            });
+        if !open || done {
+            self.open_link = None;
+        }
+    }
+
+    /// Progress of the files being downloaded, over the bottom left corner.
+    fn layout_downloads(&self, context: &Context) {
+        let downloads = self.app.downloads.lock();
+        if downloads.is_empty() {
+            return;
+        }
+        Area::new("Downloads")
+            .anchor(Align2::LEFT_BOTTOM, vec2(8.0, -8.0))
+            .order(Order::Foreground)
+            .interactable(false)
+            .show(context, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_width(240.0);
+                    for download in downloads.iter() {
+                        ui.label(format!("Downloading {}", download.name));
+                        let megabytes = |bytes: u64| bytes as f32 / (1024.0 * 1024.0);
+                        let bar = match download.progress() {
+                            (received, Some(total)) => {
+                                ProgressBar::new(received as f32 / total as f32).text(format!(
+                                    "{:.1} of {:.1} MB",
+                                    megabytes(received),
+                                    megabytes(total)
+                                ))
+                            }
+                            (received, None) => ProgressBar::new(0.0)
+                                .animate(true)
+                                .text(format!("{:.1} MB", megabytes(received))),
+                        };
+                        ui.add(bar);
+                    }
+                });
+            });
+        context.request_repaint_after(Duration::from_millis(100));
+    }
+
+    /// Overlay of the frame statistics over the canvas.
+    fn layout_stats(&mut self, context: &Context) {
+        if context.input(|input| input.key_pressed(Key::F3)) {
+            self.view_options.stats_hud ^= true;
+        }
+        self.frame_stats.record(
+            self.app.recomposites.load(Ordering::Acquire),
+            self.app.dev.uploads.total(),
+            self.app.compositor.timer.as_ref().and_then(GpuTimer::last),
+            *self.app.linearize_time.lock(),
+        );
+        if !self.view_options.stats_hud {
+            return;
+        }
+
+        Area::new("Stats HUD")
+            .anchor(Align2::LEFT_TOP, vec2(10.0, 10.0))
+            .interactable(false)
             .show(context, |ui| {
-                egui_dock::DockArea::new(&mut self.viewer_tree)
-                    .style(egui_dock::Style::from_egui(ui.style()))
-                    .show_close_buttons(false)
-                    .show_inside(
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    let budget = &self.app.dev.budget;
+                    self.frame_stats.ui(
                         ui,
-                        &mut ControlsGui {
-                            app: &self.app,
-                            active_canvas: self.active_canvas,
-                            view_options: &mut self.view_options,
-                        },
+                        self.app.compositor.timer.is_some(),
+                        budget.used(),
+                        budget.limit(),
                     );
+                });
             });
+        // Keep drawing frames so that the graph shows the frame rate rather
+        // than the time between input events.
+        context.request_repaint();
+    }
+
+    /// Show the active canvas alone in fullscreen with F11 or P, or the key
+    /// P is rebound to, until either is pressed again or Escape is, and move
+    /// between the tabs with the arrow keys in the meantime.
+    fn presentation_shortcuts(&mut self, context: &Context) {
+        let typing = context.memory(|memory| memory.focus().is_some());
+        let present = self.view_options.keymap.key(Shortcut::Present);
+        let (toggle, escape, steps) = context.input(|input| {
+            (
+                input.key_pressed(Key::F11) || (!typing && input.key_pressed(present)),
+                input.key_pressed(Key::Escape),
+                i32::from(input.key_pressed(Key::ArrowRight))
+                    - i32::from(input.key_pressed(Key::ArrowLeft)),
+            )
+        });
+        if toggle || (escape && self.presenting.is_some()) {
+            self.set_presenting(context, toggle && self.presenting.is_none());
+        } else if self.presenting.is_some() && steps != 0 {
+            self.step_presentation(steps);
+        }
+    }
+
+    fn set_presenting(&mut self, context: &Context, presenting: bool) {
+        self.presenting = presenting.then(Instant::now);
+        context.send_viewport_cmd(ViewportCommand::Fullscreen(presenting));
+    }
+
+    /// Move to the next tab once the active one was presented long enough.
+    fn cycle_presentation(&mut self, context: &Context) {
+        let (Some(since), Some(seconds)) = (self.presenting, self.view_options.presentation_cycle)
+        else {
+            return;
+        };
+        let interval = Duration::from_secs(seconds.into());
+        let elapsed = since.elapsed();
+        if elapsed < interval {
+            context.request_repaint_after(interval - elapsed);
+        } else {
+            self.step_presentation(1);
+        }
+    }
+
+    /// Present the tab `steps` after the active one, wrapping around.
+    fn step_presentation(&mut self, steps: i32) {
+        let tabs = self
+            .canvas_tree
+            .iter_all_tabs()
+            .map(|(_, key)| *key)
+            .collect::<Vec<_>>();
+        let Some(current) = tabs.iter().position(|key| *key == self.active_canvas) else {
+            return;
+        };
+        let next = tabs[(current as i64 + i64::from(steps)).rem_euclid(tabs.len() as i64) as usize];
+        self.focus_tab(next);
+        self.presenting = Some(Instant::now());
+    }
+
+    /// Bring the tab of a file to the front and make it the active one.
+    fn focus_tab(&mut self, key: InstanceKey) {
+        if let Some((surface, node, tab)) = self.canvas_tree.find_tab(&key) {
+            self.canvas_tree.set_active_tab((surface, node, tab));
+            self.canvas_tree
+                .set_focused_node_and_surface((surface, node));
+            self.active_canvas = key;
+        }
+    }
+
+    /// Overview of the open tabs, shown while Ctrl+Tab is held.
+    fn layout_switcher(&mut self, context: &Context) {
+        let tabs = {
+            let instances = self.app.compositor.instances.read();
+            self.canvas_tree
+                .iter_all_tabs()
+                .filter_map(|(_, key)| {
+                    let name = instances.get(key)?.file.read().name.clone();
+                    Some((*key, name.unwrap_or("Untitled Artwork".to_string())))
+                })
+                .collect::<Vec<_>>()
+        };
+        if let Some(key) = self.switcher.ui(context, &tabs, self.active_canvas) {
+            self.focus_tab(key);
+            if self.presenting.is_some() {
+                self.presenting = Some(Instant::now());
+            }
+        }
+        self.app
+            .previews_wanted
+            .store(self.switcher.is_open(), Ordering::Release);
+    }
+
+    /// Journal the open files as arranged in the dock, with their views.
+    fn record_session(&mut self) {
+        let canvas_tree = &self.canvas_tree;
+        let view_history = &self.view_history;
+        let app = &self.app;
+        self.journal.record(|| {
+            let instances = app.compositor.instances.read();
+            Session {
+                layout: SessionLayout::from_dock(canvas_tree, |key| {
+                    let instance = instances.get(key)?;
+                    let hash = instance.file.read().content_hash;
+                    Some(SessionTab {
+                        path: instance.path.clone(),
+                        view: view_history.get(hash, &instance.path),
+                    })
+                }),
+            }
+        });
+    }
+
+    /// Restore the last view of a newly opened file.
+    fn apply_saved_view(&mut self, id: InstanceKey, instance: &Instance) {
+        let hash = instance.file.read().content_hash;
+        if let Some(view) = self.view_history.get(hash, &instance.path) {
+            instance.view.lock().rotation = view.rotation;
+            *instance.guides.lock() = view.guides;
+            let file = instance.file.read();
+            let selection = view
+                .selection
+                .into_iter()
+                .filter(|uuid| file.layers.find_layer(uuid).is_some())
+                .collect::<Vec<_>>();
+            self.selection.select_all(id, &selection);
+        }
+    }
+
+    /// Arrange the files of a restored session in the splits and tabs they
+    /// were in, each viewed as it was. Files opened in the meantime keep
+    /// their tabs, with the restored ones added to the focused leaf.
+    fn restore_layout(
+        &mut self,
+        layout: RestoredLayout,
+        instances: &HashMap<InstanceKey, Instance>,
+    ) {
+        let Some(layout) = layout.filter_map(&mut |(id, view)| {
+            let instance = instances.get(&id)?;
+            if let Some(view) = view {
+                let hash = instance.file.read().content_hash;
+                self.view_history.record(hash, &instance.path, view);
+            }
+            self.apply_saved_view(id, instance);
+            Some(id)
+        }) else {
+            return;
+        };
+        if self.canvas_tree.iter_all_tabs().next().is_none() {
+            self.canvas_tree = layout.into_dock();
+        } else {
+            for id in layout.tabs() {
+                self.canvas_tree.push_to_focused_leaf(*id);
+            }
+        }
+    }
 
-        CentralPanel::default()
-            .frame(Frame::none())
+    /// Window listing errors with their full diagnostics until dismissed.
+    fn layout_errors(&self, context: &Context) {
+        let mut reports = self.app.reports.lock();
+        if reports.is_empty() {
+            return;
+        }
+
+        let mut open = true;
+        let mut dismissed = None;
+        Window::new("Errors")
+            .open(&mut open)
+            .default_width(420.0)
+            .vscroll(true)
             .show(context, |ui| {
-                self.layout_view(ui);
+                for (i, report) in reports.iter().enumerate() {
+                    ui.push_id(i, |ui| {
+                        let summary =
+                            RichText::new(&report.summary).color(ui.visuals().error_fg_color);
+                        CollapsingHeader::new(summary).show(ui, |ui| {
+                            for (depth, cause) in report.chain.iter().enumerate() {
+                                ui.label(format!("{depth}: {cause}"));
+                            }
+                            if let Some(file) = &report.file {
+                                ui.label(format!("File: {}", file.display()));
+                            }
+                            ui.collapsing("Diagnostics", |ui| {
+                                ui.monospace(report.diagnostics());
+                            });
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Copy diagnostics").clicked() {
+                                let text = report.diagnostics().to_string();
+                                ui.output_mut(|output| output.copied_text = text);
+                            }
+                            if ui.button("Report issue").clicked() {
+                                if let Err(err) = report::open_url(&report.issue_url()) {
+                                    self.app
+                                        .toasts
+                                        .lock()
+                                        .warning(format!("Could not open the browser: {err}."));
+                                }
+                            }
+                            if ui.button("Save report").clicked() {
+                                self.app.rt.spawn(
+                                    self.app
+                                        .clone()
+                                        .save_report(report.diagnostics().to_string()),
+                                );
+                            }
+                            if ui.button("Dismiss").clicked() {
+                                dismissed = Some(i);
+                            }
+                        });
+                        ui.separator();
+                    });
+                }
             });
+
+        if !open {
+            reports.clear();
+        } else if let Some(i) = dismissed {
+            reports.remove(i);
+        }
+    }
+
+    pub fn layout_gui(&mut self, context: &Context) {
+        let reduced_motion = self
+            .view_options
+            .motion
+            .is_reduced(self.app.system_reduced_motion);
+        self.app.toasts.lock().reduced_motion = reduced_motion;
+        self.app
+            .adaptive_quality
+            .store(self.view_options.adaptive_quality, Ordering::Release);
+        let visuals = self.view_options.theme.visuals(self.view_options.accent);
+        context.style_mut(|style| {
+            style.visuals = visuals;
+            style.animation_time = if reduced_motion {
+                0.0
+            } else {
+                Style::default().animation_time
+            };
+        });
+
+        self.presentation_shortcuts(context);
+        // Presentation ends once the file on show is closed.
+        if self.presenting.is_some()
+            && !self
+                .app
+                .compositor
+                .instances
+                .read()
+                .contains_key(&self.active_canvas)
+        {
+            self.set_presenting(context, false);
+        }
+
+        if let Some(gallery) = self.app.opened_gallery.lock().take() {
+            self.gallery = Some(gallery);
+        }
+
+        if let Some((key, uuid)) = self.app.picked_layer.lock().take() {
+            self.selection.select(key, &uuid, true);
+        }
+
+        if self.presenting.is_some() {
+            self.cycle_presentation(context);
+            let instances = self.app.compositor.instances.read();
+            let mut visible = HashSet::new();
+            CentralPanel::default()
+                .frame(Frame::none())
+                .show(context, |ui| {
+                    CanvasGui {
+                        app: &self.app,
+                        view_options: &mut self.view_options,
+                        canvases: &mut self.canvases,
+                        instances: &instances,
+                        visible: &mut visible,
+                        deferred_rebinds: &mut self.deferred_rebinds,
+                        view_history: &mut self.view_history,
+                        selection: &self.selection,
+                        close_tabs: &mut None,
+                    }
+                    .present(ui, self.active_canvas);
+                });
+            self.visible_tabs = visible;
+        } else {
+            // Shown first so that it takes the arrow keys from the hierarchy.
+            if let Some(gallery) = self.gallery.as_mut() {
+                if !gallery.ui(context, &self.app) {
+                    self.gallery = None;
+                }
+            }
+
+            SidePanel::new(panel::Side::Right, "Side Panel")
+                .default_width(300.0)
+                .frame(Frame::none())
+                .show(context, |ui| {
+                    egui_dock::DockArea::new(&mut self.viewer_tree)
+                        .style(egui_dock::Style::from_egui(ui.style()))
+                        .show_close_buttons(false)
+                        .show_inside(
+                            ui,
+                            &mut ControlsGui {
+                                app: &self.app,
+                                active_canvas: self.active_canvas,
+                                view_options: &mut self.view_options,
+                                selection: &mut self.selection,
+                                layer_search: &mut self.layer_search,
+                                atlas: &mut self.atlas,
+                                export_presets: &mut self.export_presets,
+                                adapter_names: &mut self.adapter_names,
+                            },
+                        );
+                });
+
+            CentralPanel::default()
+                .frame(Frame::none())
+                .show(context, |ui| {
+                    self.layout_view(ui);
+                });
+        }
+
+        self.layout_errors(context);
+        self.layout_open_link(context);
+        self.layout_downloads(context);
+        self.layout_switcher(context);
+        self.layout_stats(context);
+        self.rotation_shortcuts(context);
+        self.reference_shortcuts(context);
+        self.history_shortcuts(context);
+        self.copy_shortcuts(context);
+        self.record_session();
+        self.view_history.save(false);
+        self.settings_store
+            .save(Settings::capture(&self.app, &self.view_options), false);
+
+        if let Some(bundle) = self.app.imported_settings.lock().take() {
+            bundle.settings.apply(&self.app, &mut self.view_options);
+            if let Some(presets) = bundle.export_presets {
+                self.export_presets.presets = presets;
+                self.export_presets.save();
+            }
+        }
+        if let Some(text) = self.app.clipboard.lock().take() {
+            context.output_mut(|output| output.copied_text = text);
+        }
     }
 }
 
@@ -384,6 +3441,11 @@ pub enum ViewerTab {
     ViewControls,
     CanvasControls,
     Hierarchy,
+    Analysis,
+    History,
+    Settings,
+    Atlas,
+    Integrity,
 }
 
 impl egui_dock::TabViewer for ControlsGui<'_> {
@@ -397,6 +3459,15 @@ impl egui_dock::TabViewer for ControlsGui<'_> {
                 ViewerTab::ViewControls => self.layout_view_control(ui),
                 ViewerTab::CanvasControls => self.layout_canvas_control(ui),
                 ViewerTab::Hierarchy => self.layout_layers(ui),
+                ViewerTab::Analysis => self.layout_analysis(ui),
+                ViewerTab::History => self.layout_history(ui),
+                ViewerTab::Settings => self.layout_settings(ui),
+                ViewerTab::Integrity => self.layout_integrity(ui),
+                ViewerTab::Atlas => {
+                    ScrollArea::vertical().show(ui, |ui| {
+                        self.atlas.ui(ui, self.app, self.active_canvas);
+                    });
+                }
             });
     }
 
@@ -406,6 +3477,11 @@ impl egui_dock::TabViewer for ControlsGui<'_> {
             ViewerTab::ViewControls => "View",
             ViewerTab::CanvasControls => "Canvas",
             ViewerTab::Hierarchy => "Hierarchy",
+            ViewerTab::Analysis => "Analysis",
+            ViewerTab::History => "History",
+            ViewerTab::Settings => "Settings",
+            ViewerTab::Atlas => "Atlas",
+            ViewerTab::Integrity => "Integrity",
         }
         .into()
     }
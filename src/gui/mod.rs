@@ -1,21 +1,53 @@
 pub mod app;
+mod atlas;
 mod canvas;
+mod command;
+mod download;
+mod export_preset;
+mod file_manager;
+mod gallery;
+mod history;
+mod keymap;
+mod kra;
 mod layout;
+mod motion;
+mod picker;
+mod preset;
+mod quality;
+mod report;
+mod selection;
+mod session;
+pub mod settings;
+mod sheet;
+mod stats;
+mod store;
+mod switcher;
+mod theme;
+mod views;
 
 use self::{
     app::{App, InstanceKey},
     layout::{ViewOptions, ViewerGui},
+    session::SessionJournal,
+    settings::SettingsStore,
 };
 use crate::gui::layout::ViewerTab;
-use egui::{load::SizedTexture, FullOutput, ViewportId};
+use compositor::upload::UploadThrottle;
+use egui::{vec2, FullOutput, Pos2, Rect, ViewportCommand, ViewportId};
 use egui_wgpu::{Renderer, ScreenDescriptor};
 
 use crate::winit;
 use std::time::Duration;
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
+};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::ControlFlow,
+    window::Fullscreen,
 };
 
 impl App {
@@ -24,6 +56,8 @@ impl App {
         window: &winit::window::Window,
         surface: wgpu::Surface,
         event_loop: egui_winit::winit::event_loop::EventLoop<app::UserEvent>,
+        files: Vec<PathBuf>,
+        links: Vec<String>,
     ) -> Result<(), winit::error::EventLoopError> {
         let surface_caps = surface.get_capabilities(&self.dev.adapter);
         let surface_format = surface_caps.formats[0];
@@ -60,13 +94,7 @@ impl App {
         let mut editor = ViewerGui {
             app: self.clone(),
             canvases: HashMap::new(),
-            view_options: ViewOptions {
-                smooth: false,
-                grid: true,
-                extended_crosshair: false,
-                rotation: 0.0,
-                bottom_bar: false,
-            },
+            view_options: ViewOptions::default(),
             active_canvas: InstanceKey(0),
             canvas_tree: egui_dock::DockState::new(Vec::new()),
             viewer_tree: {
@@ -74,23 +102,76 @@ impl App {
                     ViewerTab::Information,
                     ViewerTab::ViewControls,
                     ViewerTab::CanvasControls,
+                    ViewerTab::Settings,
                 ];
                 let mut state = egui_dock::DockState::new(tabs);
                 state.main_surface_mut().split_below(
                     egui_dock::NodeIndex::root(),
                     0.4,
-                    vec![ViewerTab::Hierarchy],
+                    vec![
+                        ViewerTab::Hierarchy,
+                        ViewerTab::History,
+                        ViewerTab::Analysis,
+                        ViewerTab::Atlas,
+                        ViewerTab::Integrity,
+                    ],
                 );
                 state
             },
+            journal: SessionJournal::new(),
+            frame_stats: Default::default(),
+            visible_tabs: HashSet::new(),
+            deferred_rebinds: HashSet::new(),
+            view_history: views::ViewHistory::load(),
+            selection: selection::LayerSelection::default(),
+            layer_search: String::new(),
+            atlas: atlas::AtlasInspector::default(),
+            export_presets: export_preset::ExportPresets::load(),
+            settings_store: SettingsStore::load(),
+            presenting: None,
+            gallery: None,
+            switcher: Default::default(),
+            open_link: None,
+            adapter_names: None,
+            files_handed_over: false,
         };
+        if let Some(settings) = editor.settings_store.saved() {
+            settings.apply(&self, &mut editor.view_options);
+        }
+
+        let crashed_session = editor.journal.crashed_session();
+        let restoring = crashed_session.is_some();
+        if let Some(session) = crashed_session {
+            self.rt.spawn(self.clone().restore_dialog(session));
+        }
+        if files.is_empty() && links.is_empty() {
+            if editor.view_options.open_on_startup && !restoring {
+                self.rt.spawn(self.clone().load_dialog(
+                    egui_dock::SurfaceIndex::main(),
+                    egui_dock::NodeIndex::root(),
+                ));
+            }
+        } else {
+            self.rt.spawn(self.clone().open_files(files));
+            for link in links {
+                self.rt.spawn(self.clone().open_link(link));
+            }
+        }
 
         self.rt.spawn(self.clone().rendering_thread());
+        self.rt.spawn(self.clone().watch_files());
+        // Layer uploads only have frames to be spread over in the viewer.
+        self.dev
+            .uploads
+            .set_limit(Some(UploadThrottle::DEFAULT_LIMIT));
 
+        // Set once the window is closed, after which the events that some
+        // platforms still deliver for it are dropped.
+        let mut closed = false;
         event_loop.run(move |event, eltarget| {
             match event {
                 // Event::MainEventsCleared => window.request_redraw(),
-                Event::WindowEvent { event, .. } => {
+                Event::WindowEvent { window_id, event } if window_id == window.id() && !closed => {
                     match event {
                         WindowEvent::RedrawRequested => {
                             let output_frame = match surface.get_current_texture() {
@@ -102,7 +183,7 @@ impl App {
                                     return;
                                 }
                                 Err(e) => {
-                                    eprintln!("Dropped frame with error: {}", e);
+                                    tracing::warn!("Dropped frame with error: {}", e);
                                     return;
                                 }
                             };
@@ -124,7 +205,15 @@ impl App {
                                 viewport_output,
                             } = integration.egui_ctx().end_frame();
 
-                            let repaint_after = viewport_output[&ViewportId::ROOT].repaint_delay;
+                            let viewport = &viewport_output[&ViewportId::ROOT];
+                            let repaint_after = viewport.repaint_delay;
+                            for command in &viewport.commands {
+                                if let ViewportCommand::Fullscreen(fullscreen) = command {
+                                    window.set_fullscreen(
+                                        fullscreen.then_some(Fullscreen::Borderless(None)),
+                                    );
+                                }
+                            }
 
                             if repaint_after.is_zero() {
                                 window.request_redraw();
@@ -197,8 +286,13 @@ impl App {
                                 encoder.finish()
                             }));
                             output_frame.present();
+                            if self.dev.uploads.next_frame() {
+                                window.request_redraw();
+                            }
                         }
                         WindowEvent::CloseRequested => {
+                            editor.shut_down();
+                            closed = true;
                             eltarget.exit();
                             return;
                         }
@@ -218,15 +312,20 @@ impl App {
                             surface.configure(&self.dev.device, &surface_config);
                         }
                         WindowEvent::DroppedFile(file) => {
-                            println!("File dropped: {:?}", file.as_path().display().to_string());
+                            tracing::info!(
+                                "File dropped: {:?}",
+                                file.as_path().display().to_string()
+                            );
                             self.rt.spawn({
                                 let app = self.clone();
                                 async move {
-                                    match app.clone().load_file(file).await {
+                                    match app.clone().load_file(file.clone()).await {
                                         Err(err) => {
-                                            app.toasts.lock().error(format!(
-                                                "File from drag/drop failed to load. Reason: {err}"
-                                            ));
+                                            app.report_error(
+                                                "File from drag/drop failed to load.".to_string(),
+                                                &err,
+                                                Some(&file),
+                                            );
                                         }
                                         Ok(key) => {
                                             app.toasts
@@ -255,9 +354,110 @@ impl App {
                         }
                     }
                 }
+                // Events of other windows, or of this one once it is closed.
+                Event::WindowEvent { .. } => {}
+                Event::UserEvent(_) if closed => {}
+                Event::UserEvent(app::UserEvent::DeviceLost(message)) => {
+                    tracing::error!("The GPU device was lost: {message}");
+                    match editor.relaunch_with_fallback() {
+                        Ok(Some(next)) => {
+                            tracing::info!("Restarting with {next}");
+                            editor.shut_down();
+                            closed = true;
+                            eltarget.exit();
+                        }
+                        Ok(None) => {
+                            self.toasts.lock().error(
+                                "The GPU device was lost and there is no other GPU to fall back to.",
+                            );
+                        }
+                        Err(err) => self.report_error(
+                            String::from("The GPU device was lost and Silicate could not restart."),
+                            &err,
+                            None,
+                        ),
+                    }
+                }
                 Event::UserEvent(app::UserEvent::RemoveInstance(idx)) => {
                     editor.remove_index(idx);
                 }
+                Event::UserEvent(app::UserEvent::BindAtlasPage(idx, page)) => {
+                    let instances = self.compositor.instances.read();
+                    let textures = instances.get(&idx).map(|instance| instance.textures.read());
+                    editor.atlas.bind(
+                        &mut renderer,
+                        &self.dev,
+                        textures.as_ref().and_then(|textures| textures.as_ref()),
+                        idx,
+                        page,
+                    );
+                }
+                Event::UserEvent(app::UserEvent::DragOut(path)) => {
+                    // The drag ended while the composite was exported.
+                    if !integration
+                        .egui_ctx()
+                        .input(|input| input.pointer.primary_down())
+                    {
+                        return;
+                    }
+                    #[cfg(any(windows, target_os = "macos"))]
+                    {
+                        if let Err(err) = drag::start_drag(
+                            window,
+                            drag::DragItem::Files(vec![path.clone()]),
+                            drag::Image::File(path),
+                            |_, _| {},
+                            drag::Options::default(),
+                        ) {
+                            tracing::warn!("Could not drag the composite out: {err}");
+                        }
+                        // The platform takes the release of the button, which
+                        // the canvas would otherwise keep panning without.
+                        let pos = integration
+                            .egui_ctx()
+                            .input(|input| input.pointer.latest_pos())
+                            .unwrap_or_default();
+                        integration
+                            .egui_input_mut()
+                            .events
+                            .push(egui::Event::PointerButton {
+                                pos,
+                                button: egui::PointerButton::Primary,
+                                pressed: false,
+                                modifiers: Default::default(),
+                            });
+                        window.request_redraw();
+                    }
+                    #[cfg(not(any(windows, target_os = "macos")))]
+                    tracing::debug!("Dragging {} out is not supported", path.display());
+                }
+                Event::UserEvent(app::UserEvent::BindPreview(idx)) => {
+                    // A preview being updated is bound once the update
+                    // sends its own event.
+                    let instances = self.compositor.instances.read();
+                    let Some(preview) = instances
+                        .get(&idx)
+                        .map_or(Some(None), |instance| instance.preview.try_lock().map(Some))
+                    else {
+                        return;
+                    };
+                    editor.switcher.bind(
+                        &mut renderer,
+                        &self.dev,
+                        idx,
+                        preview
+                            .as_deref()
+                            .and_then(Option::as_ref)
+                            .map(|(_, texture)| texture),
+                        |key| instances.contains_key(&key),
+                    );
+                }
+                Event::UserEvent(app::UserEvent::RebindTexture(idx))
+                    if !editor.visible_tabs.contains(&idx) =>
+                {
+                    // Hidden tabs are rebound once they are shown again.
+                    editor.deferred_rebinds.insert(idx);
+                }
                 Event::UserEvent(e @ app::UserEvent::RebindTexture(idx)) => {
                     // Updates textures bound for EGUI rendering
                     // Do not block on any locks/rwlocks since we do not want to block
@@ -273,30 +473,52 @@ impl App {
                     if let Some(instance) = instances.get(&idx) {
                         if let Some(target) = instance.target.try_lock() {
                             if let Some(output) = target.output.as_ref() {
-                                let texture_view = output.texture.create_srgb_view();
-
-                                if let Some(tex) = editor.canvases.get_mut(&idx) {
-                                    renderer.update_egui_texture_from_wgpu_texture(
-                                        &self.dev.device,
-                                        &texture_view,
-                                        texture_filter,
-                                        tex.id,
-                                    );
-                                    tex.size = target.dim.to_vec2();
-                                } else {
-                                    let tex = renderer.register_native_texture(
-                                        &self.dev.device,
-                                        &texture_view,
-                                        texture_filter,
-                                    );
-                                    editor.canvases.insert(
-                                        idx,
-                                        SizedTexture {
-                                            id: tex,
-                                            size: target.dim.to_vec2(),
-                                        },
-                                    );
+                                let canvas = editor.canvases.entry(idx).or_default();
+                                // Reuse the texture ids of the previous tiles.
+                                let mut ids = std::mem::take(&mut canvas.tiles)
+                                    .into_iter()
+                                    .map(|(_, id)| id);
+                                // Tiles of a lower resolution composite are
+                                // stretched over the canvas.
+                                let downscale = target.downscale() as f32;
+                                canvas.tiles = output
+                                    .texture
+                                    .tiles
+                                    .iter()
+                                    .map(|(region, texture)| {
+                                        let texture_view = texture.create_srgb_view();
+                                        let id = if let Some(id) = ids.next() {
+                                            renderer.update_egui_texture_from_wgpu_texture(
+                                                &self.dev.device,
+                                                &texture_view,
+                                                texture_filter,
+                                                id,
+                                            );
+                                            id
+                                        } else {
+                                            renderer.register_native_texture(
+                                                &self.dev.device,
+                                                &texture_view,
+                                                texture_filter,
+                                            )
+                                        };
+                                        let rect = canvas::region_rect(*region);
+                                        let rect = Rect::from_min_size(
+                                            (rect.min.to_vec2() * downscale).to_pos2(),
+                                            rect.size() * downscale,
+                                        )
+                                        .intersect(Rect::from_min_size(
+                                            Pos2::ZERO,
+                                            vec2(target.dim.width as f32, target.dim.height as f32),
+                                        ));
+                                        (rect, id)
+                                    })
+                                    .collect();
+                                for id in ids {
+                                    renderer.free_texture(&id);
                                 }
+                                canvas.size =
+                                    vec2(target.dim.width as f32, target.dim.height as f32);
                                 return;
                             }
                         }
@@ -0,0 +1,105 @@
+use egui_notify::{Toast, Toasts};
+use std::process::Command;
+
+/// Whether the interface animates, following the OS unless overridden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionPreference {
+    System,
+    Reduced,
+    Full,
+}
+
+impl MotionPreference {
+    pub const fn all() -> &'static [Self] {
+        &[Self::System, Self::Reduced, Self::Full]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::System => "System",
+            Self::Reduced => "Reduced",
+            Self::Full => "Full",
+        }
+    }
+
+    /// Whether animations should be turned off, given the OS setting.
+    pub fn is_reduced(self, system: bool) -> bool {
+        match self {
+            Self::System => system,
+            Self::Reduced => true,
+            Self::Full => false,
+        }
+    }
+}
+
+/// Ask the OS whether the user turned animations off. Assumes they did not
+/// if the setting cannot be read.
+pub fn system_prefers_reduced_motion() -> bool {
+    let query = |program: &str, args: &[&str]| {
+        Command::new(program)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    if cfg!(windows) {
+        // `MinAnimate` is 0 when "Animate controls and elements" is off.
+        query(
+            "reg",
+            &[
+                "query",
+                r"HKCU\Control Panel\Desktop\WindowMetrics",
+                "/v",
+                "MinAnimate",
+            ],
+        )
+        .is_some_and(|out| out.split_whitespace().last() == Some("0"))
+    } else if cfg!(target_os = "macos") {
+        query(
+            "defaults",
+            &["read", "com.apple.universalaccess", "reduceMotion"],
+        )
+        .is_some_and(|out| out == "1")
+    } else {
+        query(
+            "gsettings",
+            &["get", "org.gnome.desktop.interface", "enable-animations"],
+        )
+        .is_some_and(|out| out == "false")
+    }
+}
+
+/// Toasts that leave out their countdown bar when motion is reduced.
+#[derive(Default)]
+pub struct Notifications {
+    toasts: Toasts,
+    pub reduced_motion: bool,
+}
+
+impl Notifications {
+    fn add(&mut self, toast: fn(&mut Toasts, String) -> &mut Toast, text: String) -> &mut Toast {
+        let reduced_motion = self.reduced_motion;
+        toast(&mut self.toasts, text).set_show_progress_bar(!reduced_motion)
+    }
+
+    pub fn success(&mut self, text: impl Into<String>) -> &mut Toast {
+        self.add(|toasts, text| toasts.success(text), text.into())
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) -> &mut Toast {
+        self.add(|toasts, text| toasts.info(text), text.into())
+    }
+
+    pub fn warning(&mut self, text: impl Into<String>) -> &mut Toast {
+        self.add(|toasts, text| toasts.warning(text), text.into())
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) -> &mut Toast {
+        self.add(|toasts, text| toasts.error(text), text.into())
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.toasts.show(ctx);
+    }
+}
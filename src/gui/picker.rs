@@ -0,0 +1,133 @@
+use compositor::color;
+
+/// Straight color read back from a canvas pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelColor {
+    pub pixel: (u32, u32),
+    pub rgba: [u8; 4],
+}
+
+/// Text formats that a sampled color can be copied as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFormat {
+    /// `#RRGGBB`, with `AA` appended when not opaque.
+    Hex,
+    /// `rgb(r, g, b)` or `rgba(r, g, b, a)`.
+    Rgb,
+    /// `hsl(h, s%, l%)` or `hsla(h, s%, l%, a)`.
+    Hsl,
+    /// `#RRGGBB` of the color converted to Display P3.
+    P3Hex,
+}
+
+impl ColorFormat {
+    pub const fn all() -> &'static [Self] {
+        &[Self::Hex, Self::Rgb, Self::Hsl, Self::P3Hex]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hex => "Hex",
+            Self::Rgb => "RGB",
+            Self::Hsl => "HSL",
+            Self::P3Hex => "P3 Hex",
+        }
+    }
+
+    /// Format a straight sRGB color.
+    pub fn format(&self, [r, g, b, a]: [u8; 4]) -> String {
+        let alpha = f32::from(a) / 255.0;
+        match self {
+            Self::Hex if a == 255 => format!("#{r:02X}{g:02X}{b:02X}"),
+            Self::Hex => format!("#{r:02X}{g:02X}{b:02X}{a:02X}"),
+            Self::Rgb if a == 255 => format!("rgb({r}, {g}, {b})"),
+            Self::Rgb => format!("rgba({r}, {g}, {b}, {alpha:.2})"),
+            Self::Hsl => {
+                let [h, s, l] = hsl([r, g, b].map(|c| f32::from(c) / 255.0));
+                let (h, s, l) = (h.round(), (s * 100.0).round(), (l * 100.0).round());
+                if a == 255 {
+                    format!("hsl({h}, {s}%, {l}%)")
+                } else {
+                    format!("hsla({h}, {s}%, {l}%, {alpha:.2})")
+                }
+            }
+            Self::P3Hex => {
+                let [r, g, b] = color::srgb_to_p3([r, g, b].map(|c| f32::from(c) / 255.0))
+                    .map(|c| (c * 255.0).round() as u8);
+                format!("#{r:02X}{g:02X}{b:02X}")
+            }
+        }
+    }
+}
+
+/// Hue in degrees, saturation and lightness of an encoded color.
+fn hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return [0.0, 0.0, lightness];
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    [hue * 60.0, saturation, lightness]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hsl_of_primaries_and_grays() {
+        for (rgb, expected) in [
+            ([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+            ([1.0, 1.0, 1.0], [0.0, 0.0, 1.0]),
+            ([0.5, 0.5, 0.5], [0.0, 0.0, 0.5]),
+            ([1.0, 0.0, 0.0], [0.0, 1.0, 0.5]),
+            ([1.0, 1.0, 0.0], [60.0, 1.0, 0.5]),
+            ([0.0, 1.0, 0.0], [120.0, 1.0, 0.5]),
+            ([0.0, 1.0, 1.0], [180.0, 1.0, 0.5]),
+            ([0.0, 0.0, 1.0], [240.0, 1.0, 0.5]),
+            ([1.0, 0.0, 1.0], [300.0, 1.0, 0.5]),
+            ([0.75, 0.25, 0.25], [0.0, 0.5, 0.5]),
+            ([0.25, 0.5, 0.75], [210.0, 0.5, 0.5]),
+            ([1.0, 0.5, 0.5], [0.0, 1.0, 0.75]),
+        ] {
+            let hsl = hsl(rgb);
+            for (actual, expected) in hsl.iter().zip(expected) {
+                assert!((actual - expected).abs() < 1e-4, "{rgb:?}: {hsl:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn colors_are_formatted() {
+        for (format, rgba, expected) in [
+            (ColorFormat::Hex, [255, 128, 0, 255], "#FF8000"),
+            (ColorFormat::Hex, [255, 128, 0, 128], "#FF800080"),
+            (ColorFormat::Rgb, [255, 128, 0, 255], "rgb(255, 128, 0)"),
+            (
+                ColorFormat::Rgb,
+                [255, 128, 0, 128],
+                "rgba(255, 128, 0, 0.50)",
+            ),
+            (ColorFormat::Hsl, [255, 128, 0, 255], "hsl(30, 100%, 50%)"),
+            (
+                ColorFormat::Hsl,
+                [128, 128, 128, 0],
+                "hsla(0, 0%, 50%, 0.00)",
+            ),
+            (ColorFormat::P3Hex, [255, 255, 255, 255], "#FFFFFF"),
+            (ColorFormat::P3Hex, [0, 0, 0, 255], "#000000"),
+        ] {
+            assert_eq!(format.format(rgba), expected, "{format:?} {rgba:?}");
+        }
+    }
+}
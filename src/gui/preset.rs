@@ -0,0 +1,245 @@
+use crate::json::Json;
+use silica::{BlendingMode, LayerProperties, SilicaGroup, SilicaHierarchy};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PresetError {
+    #[error("not a Silicate layer preset")]
+    NotAPreset,
+    #[error("layer preset version {0} is not supported")]
+    UnsupportedVersion(String),
+    #[error("entry {0} is not a valid layer or group")]
+    InvalidNode(usize),
+}
+
+/// Where a layer or group of a preset is found in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NodeKey {
+    /// Layer by UUID, which stays the same while the file is edited.
+    Layer(String),
+    /// Group by hierarchy path, since groups have no UUID.
+    Group(String),
+}
+
+impl NodeKey {
+    fn of(path: &str, node: &SilicaHierarchy) -> Self {
+        match node {
+            SilicaHierarchy::Layer(layer) => Self::Layer(layer.uuid.clone()),
+            SilicaHierarchy::Group(_) => Self::Group(path.to_string()),
+        }
+    }
+}
+
+/// Visibility, opacity and blending of the layers and groups of a file,
+/// kept in a small JSON file next to it so that setups such as only the
+/// line art or only the flats can be applied again for later exports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerPreset {
+    /// Nodes in document order, with their hierarchy path.
+    nodes: Vec<(NodeKey, String, LayerProperties)>,
+}
+
+impl LayerPreset {
+    const FORMAT: &'static str = "silicate-layer-preset";
+    const VERSION: &'static str = "1";
+
+    pub fn capture(layers: &SilicaGroup) -> Self {
+        let mut nodes = Vec::new();
+        layers.clone().visit_paths_mut(|path, node| {
+            nodes.push((
+                NodeKey::of(path, node),
+                path.to_string(),
+                LayerProperties::of_node(node),
+            ));
+        });
+        Self { nodes }
+    }
+
+    /// Apply the preset to the layers, returning how many of its entries
+    /// matched none of them. Groups sharing a path are matched in order.
+    pub fn apply(&self, layers: &mut SilicaGroup) -> usize {
+        let mut matched = vec![false; self.nodes.len()];
+        layers.visit_paths_mut(|path, node| {
+            let key = NodeKey::of(path, node);
+            let found = self
+                .nodes
+                .iter()
+                .zip(&mut matched)
+                .find(|((other, ..), matched)| !**matched && *other == key);
+            if let Some(((_, _, properties), matched)) = found {
+                properties.apply_node(node);
+                *matched = true;
+            }
+        });
+        matched.iter().filter(|matched| !**matched).count()
+    }
+
+    /// File the preset of a file is saved to by default, next to it.
+    pub fn sidecar(path: &Path) -> PathBuf {
+        path.with_extension("layers.json")
+    }
+
+    pub fn serialize(&self) -> String {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(key, path, properties)| {
+                let mut members = match key {
+                    NodeKey::Layer(uuid) => vec![
+                        ("layer", Json::String(uuid.clone())),
+                        ("path", Json::String(path.clone())),
+                        ("clipped", Json::Bool(properties.clipped)),
+                    ],
+                    NodeKey::Group(path) => vec![("group", Json::String(path.clone()))],
+                };
+                members.extend([
+                    ("hidden", Json::Bool(properties.hidden)),
+                    ("opacity", Json::number(properties.opacity)),
+                    ("blend", Json::String(properties.blend.as_str().to_string())),
+                ]);
+                Json::object(members)
+            })
+            .collect();
+        let mut out = Json::object(vec![
+            ("format", Json::String(Self::FORMAT.to_string())),
+            ("version", Json::number(Self::VERSION)),
+            ("nodes", Json::Array(nodes)),
+        ])
+        .to_pretty();
+        out.push('\n');
+        out
+    }
+
+    pub fn deserialize(text: &str) -> Result<Self, PresetError> {
+        let preset = Json::parse(text)
+            .filter(|preset| preset.get("format").and_then(Json::as_str) == Some(Self::FORMAT))
+            .ok_or(PresetError::NotAPreset)?;
+        match preset.get("version") {
+            Some(Json::Number(version)) if version == Self::VERSION => {}
+            version => {
+                let version = match version {
+                    Some(Json::Number(version) | Json::String(version)) => version.clone(),
+                    _ => String::from("(none)"),
+                };
+                return Err(PresetError::UnsupportedVersion(version));
+            }
+        }
+        let nodes = preset
+            .get("nodes")
+            .and_then(Json::as_array)
+            .ok_or(PresetError::NotAPreset)?;
+        let nodes = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| Self::node(node).ok_or(PresetError::InvalidNode(i)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { nodes })
+    }
+
+    fn node(node: &Json) -> Option<(NodeKey, String, LayerProperties)> {
+        let text = |key| node.get(key).and_then(Json::as_str).map(str::to_string);
+        let (key, path) = match (text("layer"), text("group")) {
+            (Some(uuid), None) => (NodeKey::Layer(uuid), text("path").unwrap_or_default()),
+            (None, Some(path)) => (NodeKey::Group(path.clone()), path),
+            _ => return None,
+        };
+        let blend = text("blend")?;
+        let properties = LayerProperties {
+            hidden: node.get("hidden")?.as_bool()?,
+            clipped: match &key {
+                NodeKey::Layer(_) => node.get("clipped")?.as_bool()?,
+                NodeKey::Group(_) => false,
+            },
+            opacity: Some(node.get("opacity")?.as_f64()? as f32)
+                .filter(|opacity| (0.0..=1.0).contains(opacity))?,
+            blend: *BlendingMode::all()
+                .iter()
+                .find(|mode| mode.as_str() == blend)?,
+        };
+        Some((key, path, properties))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use silica::fixture::{loaded_group, loaded_layer};
+    use silica::SilicaLayer;
+
+    fn layer(uuid: &str) -> SilicaHierarchy {
+        SilicaHierarchy::Layer(SilicaLayer {
+            name: Some(format!("Layer {uuid}")),
+            uuid: uuid.to_string(),
+            ..loaded_layer(0)
+        })
+    }
+
+    fn group(name: &str, children: Vec<SilicaHierarchy>) -> SilicaGroup {
+        SilicaGroup {
+            name: Some(name.to_string()),
+            ..loaded_group(0, children)
+        }
+    }
+
+    #[test]
+    fn presets_round_trip_and_apply_by_uuid_and_path() {
+        let mut doc = group(
+            "Root",
+            vec![
+                SilicaHierarchy::Group(group("Inks", vec![layer("a")])),
+                layer("b"),
+            ],
+        );
+        doc.visit_mut(|_, node| {
+            if node.name() == Some("Inks") {
+                node.set_blend(BlendingMode::Multiply);
+            } else if node.name() == Some("Layer b") {
+                node.set_hidden(true);
+                node.set_opacity(0.25);
+            }
+        });
+        let preset = LayerPreset::capture(&doc);
+        let text = preset.serialize();
+        assert_eq!(LayerPreset::deserialize(&text).unwrap(), preset);
+
+        // The same layers in another order, with one layer missing.
+        let mut other = group(
+            "Root",
+            vec![
+                layer("b"),
+                layer("c"),
+                SilicaHierarchy::Group(group("Inks", Vec::new())),
+            ],
+        );
+        assert_eq!(preset.apply(&mut other), 1);
+        let properties = other
+            .iter()
+            .map(LayerProperties::of_node)
+            .collect::<Vec<_>>();
+        assert!(properties[0].hidden);
+        assert_eq!(properties[0].opacity, 0.25);
+        assert!(!properties[1].hidden);
+        assert_eq!(properties[2].blend, BlendingMode::Multiply);
+    }
+
+    #[test]
+    fn invalid_presets_are_refused() {
+        assert!(matches!(
+            LayerPreset::deserialize("{}"),
+            Err(PresetError::NotAPreset)
+        ));
+        assert!(matches!(
+            LayerPreset::deserialize(r#"{"format": "silicate-layer-preset", "version": 2}"#),
+            Err(PresetError::UnsupportedVersion(version)) if version == "2"
+        ));
+        let text = r#"{"format": "silicate-layer-preset", "version": 1, "nodes": [
+            {"group": "Inks", "hidden": false, "opacity": 1, "blend": "Normal"},
+            {"layer": "a", "hidden": false, "opacity": 2, "blend": "Normal", "clipped": false}
+        ]}"#;
+        assert!(matches!(
+            LayerPreset::deserialize(text),
+            Err(PresetError::InvalidNode(1))
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+/// Resolution of the composites of a file while its layers are being
+/// edited. Composites drop to half resolution once several in a row miss
+/// the frame budget, and go back to full resolution once edits pause.
+/// While a slider is dragged, edits are composited at half resolution and
+/// at most once per interval, then at full resolution once it is released.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdaptiveQuality {
+    /// Full resolution composites in a row that missed the frame budget.
+    slow: u32,
+    /// When the last composite at a lower resolution was rendered, if the
+    /// output is currently at a lower resolution.
+    reduced_since: Option<Instant>,
+    /// When edits were last composited during the current drag, if any.
+    dragged_at: Option<Instant>,
+}
+
+impl AdaptiveQuality {
+    /// Longest a composite may take to keep up with 60 frames per second.
+    const FRAME_BUDGET: Duration = Duration::from_millis(16);
+    /// Composites in a row over budget before the resolution is lowered.
+    const SLOW_STREAK: u32 = 3;
+    /// How long edits have to pause before full resolution is restored.
+    const IDLE: Duration = Duration::from_millis(300);
+    /// Output pixels per side of a composite pixel at lower resolution.
+    const REDUCED: u32 = 2;
+    /// Least time between composites while a slider is dragged.
+    const DRAG_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// Output pixels per side of a pixel of the next composite.
+    pub fn downscale(&self) -> u32 {
+        if self.reduced_since.is_some() || self.dragged_at.is_some() {
+            Self::REDUCED
+        } else {
+            1
+        }
+    }
+
+    /// Record how long a composite took at the given downscale.
+    pub fn record(&mut self, time: Duration, downscale: u32, now: Instant) {
+        if downscale > 1 {
+            self.reduced_since = Some(now);
+            return;
+        }
+        self.reduced_since = None;
+        if time > Self::FRAME_BUDGET {
+            self.slow += 1;
+        } else {
+            self.slow = 0;
+        }
+        if self.slow >= Self::SLOW_STREAK {
+            self.slow = 0;
+            self.reduced_since = Some(now);
+        }
+    }
+
+    /// Whether the output is at a lower resolution and edits paused long
+    /// enough to render it again at full resolution.
+    pub fn should_restore(&self, now: Instant) -> bool {
+        self.reduced_since
+            .is_some_and(|since| now.duration_since(since) >= Self::IDLE)
+    }
+
+    /// Whether edits made while a slider is dragged may be composited now,
+    /// at most once per interval. Edits are left pending otherwise.
+    pub fn drag(&mut self, now: Instant) -> bool {
+        match self.dragged_at {
+            Some(last) if now.duration_since(last) < Self::DRAG_INTERVAL => false,
+            _ => {
+                self.dragged_at = Some(now);
+                true
+            }
+        }
+    }
+
+    /// End the current drag. Returns whether edits were composited during
+    /// it, which then need to be composited again at full resolution.
+    pub fn release(&mut self) -> bool {
+        self.dragged_at.take().is_some()
+    }
+
+    /// Render at full resolution, for the next composite only until it
+    /// turns out to be slow again.
+    pub fn restore(&mut self) {
+        self.reduced_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLOW: Duration = Duration::from_millis(40);
+    const FAST: Duration = Duration::from_millis(4);
+
+    #[test]
+    fn lowers_resolution_after_a_streak_of_slow_composites() {
+        let now = Instant::now();
+        let mut quality = AdaptiveQuality::default();
+        for time in [SLOW, SLOW, FAST, SLOW, SLOW] {
+            quality.record(time, 1, now);
+        }
+        assert_eq!(quality.downscale(), 1, "a fast composite breaks the streak");
+        quality.record(SLOW, 1, now);
+        assert_eq!(quality.downscale(), AdaptiveQuality::REDUCED);
+    }
+
+    #[test]
+    fn restores_full_resolution_once_idle() {
+        let start = Instant::now();
+        let mut quality = AdaptiveQuality::default();
+        for _ in 0..AdaptiveQuality::SLOW_STREAK {
+            quality.record(SLOW, 1, start);
+        }
+        let later = start + AdaptiveQuality::IDLE / 2;
+        quality.record(FAST, quality.downscale(), later);
+        assert!(!quality.should_restore(later + AdaptiveQuality::IDLE / 2));
+        assert!(quality.should_restore(later + AdaptiveQuality::IDLE));
+        quality.restore();
+        assert_eq!(quality.downscale(), 1);
+        assert!(!quality.should_restore(later + AdaptiveQuality::IDLE * 2));
+    }
+
+    #[test]
+    fn drags_are_composited_at_intervals_until_released() {
+        let start = Instant::now();
+        let mut quality = AdaptiveQuality::default();
+        assert!(quality.drag(start));
+        assert_eq!(quality.downscale(), AdaptiveQuality::REDUCED);
+        assert!(!quality.drag(start + AdaptiveQuality::DRAG_INTERVAL / 2));
+        assert!(quality.drag(start + AdaptiveQuality::DRAG_INTERVAL));
+        assert!(quality.release());
+        assert_eq!(quality.downscale(), 1);
+        assert!(!quality.release(), "only released once");
+    }
+}
@@ -0,0 +1,219 @@
+use compositor::dev::GpuHandle;
+use std::error::Error;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An error along with the context needed to act on a bug report.
+pub struct ErrorReport {
+    /// One line description of what failed.
+    pub summary: String,
+    /// The error followed by each of its sources.
+    pub chain: Vec<String>,
+    pub file: Option<PathBuf>,
+    diagnostics: String,
+}
+
+impl ErrorReport {
+    pub fn new(
+        summary: impl Into<String>,
+        err: &(dyn Error + 'static),
+        dev: &GpuHandle,
+        file: Option<&Path>,
+    ) -> Self {
+        let summary = summary.into();
+        let chain = std::iter::successors(Some(err), |&err| err.source())
+            .map(|err| err.to_string())
+            .collect::<Vec<_>>();
+        let diagnostics = Self::collect_diagnostics(&summary, &chain, dev, file);
+        Self {
+            summary,
+            chain,
+            file: file.map(Path::to_path_buf),
+            diagnostics,
+        }
+    }
+
+    /// Plain text report suitable for pasting into an issue.
+    pub fn diagnostics(&self) -> &str {
+        &self.diagnostics
+    }
+
+    /// Link to a new GitHub issue with the report filled in. Paths are cut
+    /// down to the file name and the home directory is hidden.
+    pub fn issue_url(&self) -> String {
+        // Browsers and GitHub cut off longer links.
+        const MAX_URL: usize = 8000;
+
+        let title = self.sanitize(&self.summary);
+        let diagnostics = self.sanitize(&self.diagnostics);
+        let mut url = format!(
+            "{}/issues/new?title={}&body=",
+            env!("CARGO_PKG_REPOSITORY"),
+            percent_encode(&title)
+        );
+        let body = format!(
+            "**What happened**\n\n\n**Steps to reproduce**\n\n\n**Diagnostics**\n```\n{}```\n",
+            diagnostics
+        );
+        let mut encoded = percent_encode(&body);
+        if url.len() + encoded.len() > MAX_URL {
+            let mut body = body;
+            while url.len() + encoded.len() > MAX_URL - 64 {
+                let keep = body.chars().count() * 9 / 10;
+                body = body.chars().take(keep).collect();
+                encoded = percent_encode(&body);
+            }
+            encoded.push_str(&percent_encode("…\n```\n(truncated)"));
+        }
+        url.push_str(&encoded);
+        url
+    }
+
+    /// Hide the directories of the file and of the user's home.
+    fn sanitize(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if let Some(file) = &self.file {
+            if let Some(name) = file.file_name() {
+                text = text.replace(&*file.to_string_lossy(), &name.to_string_lossy());
+            }
+        }
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        if let Some(home) = home.filter(|home| !home.is_empty()) {
+            text = text.replace(&*home.to_string_lossy(), "~");
+        }
+        text
+    }
+
+    fn collect_diagnostics(
+        summary: &str,
+        chain: &[String],
+        dev: &GpuHandle,
+        file: Option<&Path>,
+    ) -> String {
+        // Writing into a String can not fail.
+        let mut out = String::new();
+        let _ = writeln!(out, "Silicate {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(
+            out,
+            "Platform: {} {}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        if let Ok(time) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            let _ = writeln!(out, "Time: {} (unix)", time.as_secs());
+        }
+
+        let _ = writeln!(out, "\nError: {summary}");
+        for (depth, cause) in chain.iter().enumerate() {
+            let _ = writeln!(out, "  {depth}: {cause}");
+        }
+
+        let info = dev.adapter.get_info();
+        let _ = writeln!(out, "\nAdapter: {} ({:?})", info.name, info.device_type);
+        let _ = writeln!(out, "Backend: {:?}", info.backend);
+        let _ = writeln!(
+            out,
+            "Vendor: {:#06x}, Device: {:#06x}",
+            info.vendor, info.device
+        );
+        let _ = writeln!(out, "Driver: {} {}", info.driver, info.driver_info);
+
+        if let Some(file) = file {
+            let _ = writeln!(out, "\nFile: {}", file.display());
+            match std::fs::metadata(file) {
+                Ok(metadata) => {
+                    let _ = writeln!(out, "Size: {} bytes", metadata.len());
+                    if let Ok(modified) = metadata.modified() {
+                        if let Ok(time) = modified.duration_since(UNIX_EPOCH) {
+                            let _ = writeln!(out, "Modified: {} (unix)", time.as_secs());
+                        }
+                    }
+                }
+                Err(err) => {
+                    let _ = writeln!(out, "Metadata unavailable: {err}");
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Percent-encode everything but the unreserved characters of a URL.
+fn percent_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => {
+                let _ = write!(out, "%{byte:02X}");
+            }
+        }
+    }
+    out
+}
+
+/// Open a link in the default browser.
+pub fn open_url(url: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(windows) {
+        // `start` would need the `&` of the query escaped for cmd.
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(url).spawn().map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode_keeps_unreserved_characters() {
+        for (text, expected) in [
+            ("", ""),
+            ("AZaz09-_.~", "AZaz09-_.~"),
+            ("a b", "a%20b"),
+            ("a&b=c?d#e/f", "a%26b%3Dc%3Fd%23e%2Ff"),
+            ("100%", "100%25"),
+            ("line\nbreak", "line%0Abreak"),
+            ("é", "%C3%A9"),
+            ("…", "%E2%80%A6"),
+        ] {
+            assert_eq!(percent_encode(text), expected, "{text}");
+        }
+    }
+
+    fn report(diagnostics: String) -> ErrorReport {
+        ErrorReport {
+            summary: String::from("File /art/private/Piece.procreate failed to load."),
+            chain: Vec::new(),
+            file: Some(PathBuf::from("/art/private/Piece.procreate")),
+            diagnostics,
+        }
+    }
+
+    #[test]
+    fn issue_links_hide_directories() {
+        let url = report(String::from("File: /art/private/Piece.procreate\n")).issue_url();
+        assert!(url.contains("title=File%20Piece.procreate%20failed%20to%20load."));
+        assert!(url.contains("File%3A%20Piece.procreate%0A"));
+        assert!(!url.contains("private"));
+    }
+
+    #[test]
+    fn long_issue_links_are_truncated() {
+        let url = report("é".repeat(10_000)).issue_url();
+        assert!(url.len() <= 8000, "{}", url.len());
+        assert!(url.ends_with(&percent_encode("…\n```\n(truncated)")));
+        // Cut between characters rather than within them.
+        assert!(url.contains("%C3%A9%E2%80%A6"));
+    }
+}
@@ -0,0 +1,223 @@
+use super::app::InstanceKey;
+use silica::{SilicaGroup, SilicaHierarchy, SilicaLayer};
+use std::collections::BTreeSet;
+
+/// Layers selected in a file. Every way of selecting layers goes through
+/// this, so that the hierarchy, its layer details and operations on the
+/// selected layers follow each other.
+#[derive(Debug, Default)]
+pub struct LayerSelection {
+    /// File that the selected layers belong to.
+    instance: Option<InstanceKey>,
+    /// UUIDs of the selected layers.
+    layers: BTreeSet<String>,
+    /// Layer selected last.
+    focused: Option<String>,
+    /// Set when the focused layer was selected from outside the hierarchy,
+    /// which then opens and scrolls to it.
+    reveal: bool,
+}
+
+impl LayerSelection {
+    /// Select only the given layer.
+    pub fn select(&mut self, instance: InstanceKey, uuid: &str, reveal: bool) {
+        self.instance = Some(instance);
+        self.layers = BTreeSet::from([uuid.to_string()]);
+        self.focused = Some(uuid.to_string());
+        self.reveal = reveal;
+    }
+
+    /// Select the given layers, focusing the last one, such as when the
+    /// selection of a file is restored.
+    pub fn select_all(&mut self, instance: InstanceKey, uuids: &[String]) {
+        let Some(focused) = uuids.last() else {
+            return;
+        };
+        self.instance = Some(instance);
+        self.layers = uuids.iter().cloned().collect();
+        self.focused = Some(focused.clone());
+        self.reveal = true;
+    }
+
+    /// Add the layer to the selection, or remove it if it was selected.
+    pub fn toggle(&mut self, instance: InstanceKey, uuid: &str) {
+        if self.instance != Some(instance) {
+            self.select(instance, uuid, false);
+        } else if self.layers.remove(uuid) {
+            if self.focused.as_deref() == Some(uuid) {
+                self.focused = self.layers.iter().next_back().cloned();
+            }
+        } else {
+            self.layers.insert(uuid.to_string());
+            self.focused = Some(uuid.to_string());
+        }
+    }
+
+    /// Drop the selection if it belongs to the file.
+    pub fn forget(&mut self, instance: InstanceKey) {
+        if self.instance == Some(instance) {
+            *self = Self::default();
+        }
+    }
+
+    pub fn is_selected(&self, instance: InstanceKey, uuid: &str) -> bool {
+        self.instance == Some(instance) && self.layers.contains(uuid)
+    }
+
+    /// Number of selected layers in the file.
+    pub fn len(&self, instance: InstanceKey) -> usize {
+        if self.instance == Some(instance) {
+            self.layers.len()
+        } else {
+            0
+        }
+    }
+
+    /// UUIDs of the selected layers in the file.
+    pub fn selected(&self, instance: InstanceKey) -> impl Iterator<Item = &str> {
+        self.layers
+            .iter()
+            .map(String::as_str)
+            .filter(move |_| self.instance == Some(instance))
+    }
+
+    /// UUIDs of the selected layers in the file, the focused one last, as
+    /// taken by [`Self::select_all`].
+    pub fn saved(&self, instance: InstanceKey) -> Vec<String> {
+        let focused = self.focused(instance);
+        self.selected(instance)
+            .filter(|uuid| Some(*uuid) != focused)
+            .chain(focused)
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn focused(&self, instance: InstanceKey) -> Option<&str> {
+        self.focused
+            .as_deref()
+            .filter(|_| self.instance == Some(instance))
+    }
+
+    /// Whether the group has to be opened to reveal the focused layer.
+    pub fn reveals_in(&self, instance: InstanceKey, group: &SilicaGroup) -> bool {
+        self.reveal
+            && self
+                .focused(instance)
+                .is_some_and(|focused| group.iter_layers().any(|layer| layer.uuid == focused))
+    }
+
+    /// Whether the layer has to be revealed, which is only done once.
+    pub fn take_reveal(&mut self, instance: InstanceKey, uuid: &str) -> bool {
+        let reveal = self.reveal && self.focused(instance) == Some(uuid);
+        self.reveal &= !reveal;
+        reveal
+    }
+}
+
+/// Layers shown on the canvas, from the top down, which are the ones that
+/// can be picked by clicking on it.
+pub fn pickable_layers(group: &SilicaGroup) -> Vec<&SilicaLayer> {
+    fn inner<'a>(group: &'a SilicaGroup, layers: &mut Vec<&'a SilicaLayer>) {
+        for node in &group.children {
+            match node {
+                SilicaHierarchy::Layer(layer) if !layer.hidden && layer.opacity > 0.0 => {
+                    layers.push(layer)
+                }
+                SilicaHierarchy::Group(group) if !group.hidden && group.opacity > 0.0 => {
+                    inner(group, layers)
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut layers = Vec::new();
+    inner(group, &mut layers);
+    layers
+}
+
+/// UUIDs of the layers whose name contains the query, ignoring case, from
+/// the top down.
+pub fn search_layers(group: &SilicaGroup, query: &str) -> Vec<String> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    group
+        .iter_layers()
+        .filter(|layer| {
+            layer
+                .name
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase().contains(&query))
+        })
+        .map(|layer| layer.uuid.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use silica::fixture::{loaded_group, loaded_layer};
+
+    fn named(image: u32, name: &str) -> SilicaHierarchy {
+        let mut layer = loaded_layer(image);
+        layer.name = Some(name.to_string());
+        SilicaHierarchy::Layer(layer)
+    }
+
+    fn document() -> SilicaGroup {
+        let mut hidden = loaded_layer(2);
+        hidden.hidden = true;
+        let mut hidden_group = loaded_group(5, vec![named(6, "Sketch 2")]);
+        hidden_group.hidden = true;
+        loaded_group(
+            0,
+            vec![
+                named(1, "Ink"),
+                SilicaHierarchy::Layer(hidden),
+                SilicaHierarchy::Group(loaded_group(3, vec![named(4, "Sketch")])),
+                SilicaHierarchy::Group(hidden_group),
+                named(7, "Paper"),
+            ],
+        )
+    }
+
+    #[test]
+    fn picking_skips_hidden_layers_and_groups() {
+        let document = document();
+        let uuids = pickable_layers(&document)
+            .iter()
+            .map(|layer| layer.uuid.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(uuids, ["1", "4", "7"]);
+    }
+
+    #[test]
+    fn search_matches_names_from_the_top_down() {
+        let document = document();
+        for (query, expected) in [
+            ("sketch", &["4", "6"][..]),
+            ("  INK ", &["1"]),
+            ("a", &["7"]),
+            ("missing", &[]),
+            ("", &[]),
+        ] {
+            assert_eq!(search_layers(&document, query), expected, "{query:?}");
+        }
+    }
+
+    #[test]
+    fn restored_selections_focus_their_last_layer() {
+        let key = InstanceKey(1);
+        let mut selection = LayerSelection::default();
+        selection.select_all(key, &["B".to_string(), "A".to_string()]);
+        assert_eq!(selection.focused(key), Some("A"));
+        assert_eq!(selection.len(key), 2);
+        assert_eq!(selection.saved(key), ["B", "A"]);
+        assert!(selection.saved(InstanceKey(2)).is_empty());
+        assert!(selection.take_reveal(key, "A"));
+
+        selection.select_all(key, &[]);
+        assert_eq!(selection.saved(key), ["B", "A"]);
+    }
+}
@@ -0,0 +1,652 @@
+use super::store::StoredFile;
+use super::views::SavedView;
+use egui_dock::{DockState, Node, NodeIndex, Split, SurfaceIndex, TabIndex, Tree};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// File open in a tab of a session and how it was viewed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionTab {
+    pub path: PathBuf,
+    pub view: Option<SavedView>,
+}
+
+/// Arrangement of the tabs of a session, as split in the dock.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionLayout<Tab> {
+    Tabs {
+        tabs: Vec<Tab>,
+        active: usize,
+    },
+    Split {
+        /// Whether the halves are stacked, rather than side by side.
+        vertical: bool,
+        /// Share of the first half, the left or top one.
+        fraction: f32,
+        first: Box<Self>,
+        second: Box<Self>,
+    },
+}
+
+impl<Tab> SessionLayout<Tab> {
+    /// Deepest split that is read back, as the dock stores its nodes in a
+    /// tree that doubles in size with each level.
+    const MAX_DEPTH: usize = 12;
+
+    /// Layout of the main surface of a dock, `None` if it has no tabs left
+    /// after leaving out those that `tab` returns `None` for. Tabs in
+    /// windows are put with the first tabs, as windows are not restored.
+    pub fn from_dock<T>(
+        dock: &DockState<T>,
+        mut tab: impl FnMut(&T) -> Option<Tab>,
+    ) -> Option<Self> {
+        fn node<T, Tab>(
+            tree: &Tree<T>,
+            index: NodeIndex,
+            tab: &mut impl FnMut(&T) -> Option<Tab>,
+        ) -> Option<SessionLayout<Tab>> {
+            if index.0 >= tree.len() {
+                return None;
+            }
+            let (vertical, fraction) = match &tree[index] {
+                Node::Leaf { tabs, active, .. } => {
+                    let mut kept = Vec::with_capacity(tabs.len());
+                    let mut kept_active = 0;
+                    for (i, t) in tabs.iter().enumerate() {
+                        if let Some(t) = tab(t) {
+                            if i == active.0 {
+                                kept_active = kept.len();
+                            }
+                            kept.push(t);
+                        }
+                    }
+                    return (!kept.is_empty()).then_some(SessionLayout::Tabs {
+                        tabs: kept,
+                        active: kept_active,
+                    });
+                }
+                Node::Horizontal { fraction, .. } => (false, *fraction),
+                Node::Vertical { fraction, .. } => (true, *fraction),
+                Node::Empty => return None,
+            };
+            let first = node(tree, index.left(), tab);
+            let second = node(tree, index.right(), tab);
+            match (first, second) {
+                (Some(first), Some(second)) => Some(SessionLayout::Split {
+                    vertical,
+                    fraction,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (half, None) | (None, half) => half,
+            }
+        }
+
+        let mut windowed = dock
+            .iter_all_tabs()
+            .filter(|((surface, _), _)| *surface != SurfaceIndex::main())
+            .filter_map(|(_, t)| tab(t))
+            .collect::<Vec<_>>();
+        let layout = node(dock.main_surface(), NodeIndex::root(), &mut tab);
+        if windowed.is_empty() {
+            return layout;
+        }
+        let Some(mut layout) = layout else {
+            return Some(Self::Tabs {
+                tabs: windowed,
+                active: 0,
+            });
+        };
+        let mut first = &mut layout;
+        while let Self::Split { first: half, .. } = first {
+            first = half;
+        }
+        if let Self::Tabs { tabs, .. } = first {
+            tabs.append(&mut windowed);
+        }
+        Some(layout)
+    }
+
+    /// Tabs in the order they appear in the layout, first half first.
+    pub fn tabs(&self) -> Vec<&Tab> {
+        match self {
+            Self::Tabs { tabs, .. } => tabs.iter().collect(),
+            Self::Split { first, second, .. } => {
+                let mut tabs = first.tabs();
+                tabs.extend(second.tabs());
+                tabs
+            }
+        }
+    }
+
+    /// Layout with each tab replaced in the order of [`Self::tabs`], `None`
+    /// if no tab is left after leaving out those that `f` returns `None`
+    /// for.
+    pub fn filter_map<U>(self, f: &mut impl FnMut(Tab) -> Option<U>) -> Option<SessionLayout<U>> {
+        match self {
+            Self::Tabs { tabs, active } => {
+                let mut kept = Vec::with_capacity(tabs.len());
+                let mut kept_active = 0;
+                for (i, tab) in tabs.into_iter().enumerate() {
+                    if let Some(tab) = f(tab) {
+                        if i == active {
+                            kept_active = kept.len();
+                        }
+                        kept.push(tab);
+                    }
+                }
+                (!kept.is_empty()).then_some(SessionLayout::Tabs {
+                    tabs: kept,
+                    active: kept_active,
+                })
+            }
+            Self::Split {
+                vertical,
+                fraction,
+                first,
+                second,
+            } => match (first.filter_map(f), second.filter_map(f)) {
+                (Some(first), Some(second)) => Some(SessionLayout::Split {
+                    vertical,
+                    fraction,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (half, None) | (None, half) => half,
+            },
+        }
+    }
+}
+
+impl<Tab: Clone> SessionLayout<Tab> {
+    /// Dock with the tabs split as in the layout.
+    pub fn into_dock(self) -> DockState<Tab> {
+        fn build<Tab: Clone>(tree: &mut Tree<Tab>, index: NodeIndex, layout: SessionLayout<Tab>) {
+            match layout {
+                SessionLayout::Tabs { tabs, active } => {
+                    tree[index] = Node::leaf_with(tabs);
+                    tree.set_active_tab(index, TabIndex(active));
+                }
+                SessionLayout::Split {
+                    vertical,
+                    fraction,
+                    first,
+                    second,
+                } => {
+                    // Both halves are leaves until they are built in turn.
+                    let placeholder = first.tabs()[0].clone();
+                    let split = if vertical { Split::Below } else { Split::Right };
+                    let [old, new] = tree.split(index, split, fraction, Node::leaf(placeholder));
+                    build(tree, old, *first);
+                    build(tree, new, *second);
+                }
+            }
+        }
+
+        let mut dock = DockState::new(vec![self.tabs()[0].clone()]);
+        build(dock.main_surface_mut(), NodeIndex::root(), self);
+        dock
+    }
+}
+
+/// Tabs that were open in a session, as arranged in the dock.
+#[derive(Debug, Default, PartialEq)]
+pub struct Session {
+    /// `None` when no files were open.
+    pub layout: Option<SessionLayout<SessionTab>>,
+}
+
+impl Session {
+    const HEADER: &'static str = "silicate-session 2";
+    /// Header of journals that only listed the files.
+    const HEADER_V1: &'static str = "silicate-session 1";
+
+    fn serialize(&self) -> String {
+        fn write(out: &mut String, layout: &SessionLayout<SessionTab>) {
+            match layout {
+                SessionLayout::Tabs { tabs, active } => {
+                    out.push_str(&format!("\ntabs {active}"));
+                    for tab in tabs {
+                        out.push_str("\nfile ");
+                        out.push_str(&encode_path(&tab.path));
+                        if let Some(view) = &tab.view {
+                            out.push_str("\nview ");
+                            out.push_str(&view.fields());
+                            view.write_details(out);
+                        }
+                    }
+                }
+                SessionLayout::Split {
+                    vertical,
+                    fraction,
+                    first,
+                    second,
+                } => {
+                    let direction = if *vertical { "vertical" } else { "horizontal" };
+                    out.push_str(&format!("\nsplit {direction} {fraction}"));
+                    write(out, first);
+                    write(out, second);
+                }
+            }
+        }
+
+        let mut out = String::from(Self::HEADER);
+        if let Some(layout) = &self.layout {
+            write(&mut out, layout);
+        }
+        out.push('\n');
+        out
+    }
+
+    fn deserialize(text: &str) -> Option<Self> {
+        type Lines<'a> = std::iter::Peekable<std::str::Lines<'a>>;
+
+        fn read(lines: &mut Lines, depth: usize) -> Option<SessionLayout<SessionTab>> {
+            let line = lines.next()?;
+            if let Some(split) = line.strip_prefix("split ") {
+                let (direction, fraction) = split.split_once(' ')?;
+                let vertical = match direction {
+                    "horizontal" => false,
+                    "vertical" => true,
+                    _ => return None,
+                };
+                let fraction = fraction
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|f| (0.0..=1.0).contains(f))?;
+                if depth >= SessionLayout::<SessionTab>::MAX_DEPTH {
+                    return None;
+                }
+                Some(SessionLayout::Split {
+                    vertical,
+                    fraction,
+                    first: Box::new(read(lines, depth + 1)?),
+                    second: Box::new(read(lines, depth + 1)?),
+                })
+            } else {
+                let active = line.strip_prefix("tabs ")?.parse::<usize>().ok()?;
+                let tabs = read_tabs(lines)?;
+                (active < tabs.len()).then_some(SessionLayout::Tabs { tabs, active })
+            }
+        }
+
+        fn read_tabs(lines: &mut Lines) -> Option<Vec<SessionTab>> {
+            let mut tabs = Vec::new();
+            while let Some(path) = lines.peek().and_then(|line| line.strip_prefix("file ")) {
+                let path = decode_path(path)?;
+                lines.next();
+                let mut view = None;
+                if let Some(fields) = lines.peek().and_then(|line| line.strip_prefix("view ")) {
+                    let (rotation, bounds) = fields.split_once(' ')?;
+                    view = Some(SavedView::from_fields(rotation, bounds)?);
+                    lines.next();
+                }
+                // Further lines describe the view above them.
+                while let (Some(view), Some(line)) = (&mut view, lines.peek()) {
+                    if !view.read_detail(line) {
+                        break;
+                    }
+                    lines.next();
+                }
+                tabs.push(SessionTab { path, view });
+            }
+            Some(tabs)
+        }
+
+        let mut lines = text.lines().peekable();
+        let layout = match lines.next()? {
+            Self::HEADER if lines.peek().is_none() => None,
+            Self::HEADER => Some(read(&mut lines, 0)?),
+            Self::HEADER_V1 => {
+                let tabs = lines
+                    .filter_map(|line| line.strip_prefix("file "))
+                    .map(|path| SessionTab {
+                        path: PathBuf::from(path),
+                        view: None,
+                    })
+                    .collect::<Vec<_>>();
+                return Some(Self {
+                    layout: (!tabs.is_empty()).then_some(SessionLayout::Tabs { tabs, active: 0 }),
+                });
+            }
+            _ => return None,
+        };
+        lines.next().is_none().then_some(Self { layout })
+    }
+}
+
+/// Path written on one line without loss. `%` and line breaks are escaped as
+/// `%XX`, as are bytes that are not UTF-8 on Unix and `%uXXXX` unpaired
+/// surrogates on Windows.
+fn encode_path(path: &std::path::Path) -> String {
+    fn push_char(out: &mut String, c: char) {
+        if c == '%' || c.is_ascii_control() {
+            out.push_str(&format!("%{:02X}", c as u8));
+        } else {
+            out.push(c);
+        }
+    }
+
+    let mut out = String::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+            chunk.valid().chars().for_each(|c| push_char(&mut out, c));
+            for byte in chunk.invalid() {
+                out.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        for c in char::decode_utf16(path.as_os_str().encode_wide()) {
+            match c {
+                Ok(c) => push_char(&mut out, c),
+                Err(err) => out.push_str(&format!("%u{:04X}", err.unpaired_surrogate())),
+            }
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let path = path.to_string_lossy();
+        path.chars().for_each(|c| push_char(&mut out, c));
+    }
+    out
+}
+
+/// Path written by [`encode_path`], `None` if the text is not one.
+fn decode_path(text: &str) -> Option<PathBuf> {
+    #[cfg(unix)]
+    let mut path = Vec::<u8>::new();
+    #[cfg(windows)]
+    let mut path = Vec::<u16>::new();
+    #[cfg(not(any(unix, windows)))]
+    let mut path = Vec::<u8>::new();
+
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        if c != '%' {
+            #[cfg(windows)]
+            path.extend(c.encode_utf16(&mut [0; 2]).iter());
+            #[cfg(not(windows))]
+            path.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        let hex = |digits: &str| {
+            digits
+                .bytes()
+                .all(|b| b.is_ascii_hexdigit())
+                .then(|| u16::from_str_radix(digits, 16).ok())
+                .flatten()
+        };
+        #[cfg(windows)]
+        if let Some(digits) = rest.strip_prefix('u') {
+            path.push(hex(digits.get(..4)?)?);
+            rest = &digits[4..];
+            continue;
+        }
+        let byte = hex(rest.get(..2)?)?;
+        rest = &rest[2..];
+        // Only ASCII is escaped by its byte on Windows.
+        #[cfg(windows)]
+        path.push(Some(byte).filter(|b| *b < 0x80)?);
+        #[cfg(not(windows))]
+        path.push(byte as u8);
+    }
+
+    #[cfg(unix)]
+    let path = {
+        use std::os::unix::ffi::OsStringExt;
+        OsString::from_vec(path)
+    };
+    #[cfg(windows)]
+    let path = {
+        use std::os::windows::ffi::OsStringExt;
+        OsString::from_wide(&path)
+    };
+    #[cfg(not(any(unix, windows)))]
+    let path = OsString::from(String::from_utf8(path).ok()?);
+    Some(PathBuf::from(path))
+}
+
+/// Journal of the open session that is rewritten while the app runs and
+/// removed on a clean exit. A journal left behind at launch means that the
+/// previous session crashed.
+pub struct SessionJournal {
+    file: StoredFile,
+}
+
+impl SessionJournal {
+    /// Minimum time between checks of whether the journal needs rewriting.
+    const INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new() -> Self {
+        Self {
+            file: StoredFile::new("session.journal", "session journal", Self::INTERVAL),
+        }
+    }
+
+    /// Session left behind by a previous run that did not exit cleanly.
+    pub fn crashed_session(&self) -> Option<Session> {
+        let text = self.file.read()?;
+        Session::deserialize(&text).filter(|session| session.layout.is_some())
+    }
+
+    /// Rewrite the journal if the session changed, at most once per interval.
+    pub fn record(&mut self, session: impl FnOnce() -> Session) {
+        if self.file.due(false) {
+            self.file.write(session().serialize());
+        }
+    }
+
+    /// Remove the journal on a clean exit.
+    pub fn close(&self) {
+        self.file.remove();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::canvas::{CanvasViewBounds, Guides};
+
+    fn tab(path: &str) -> SessionTab {
+        SessionTab {
+            path: PathBuf::from(path),
+            view: None,
+        }
+    }
+
+    fn split<Tab>(
+        vertical: bool,
+        first: SessionLayout<Tab>,
+        second: SessionLayout<Tab>,
+    ) -> SessionLayout<Tab> {
+        SessionLayout::Split {
+            vertical,
+            fraction: 0.25,
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let view = SavedView {
+            bounds: Some(CanvasViewBounds {
+                min: [0.0, 10.5],
+                max: [100.0, 90.0],
+            }),
+            rotation: 90.0,
+            guides: Guides {
+                thirds: true,
+                ..Guides::default()
+            },
+            selection: vec!["a".to_string(), "b".to_string()],
+        };
+        let session = Session {
+            layout: Some(split(
+                false,
+                SessionLayout::Tabs {
+                    tabs: vec![
+                        tab("/art/first.procreate"),
+                        SessionTab {
+                            view: Some(view),
+                            ..tab("C:\\Users\\Kit\\file with spaces.procreate")
+                        },
+                    ],
+                    active: 1,
+                },
+                split(
+                    true,
+                    SessionLayout::Tabs {
+                        tabs: vec![tab("top.procreate")],
+                        active: 0,
+                    },
+                    SessionLayout::Tabs {
+                        tabs: vec![tab("bottom.procreate")],
+                        active: 0,
+                    },
+                ),
+            )),
+        };
+        let text = session.serialize();
+        assert_eq!(Session::deserialize(&text), Some(session));
+        assert_eq!(
+            Session::deserialize("silicate-session 2\n"),
+            Some(Session::default())
+        );
+        assert_eq!(
+            Session::deserialize("silicate-session 2\ntabs 1\nfile a"),
+            None
+        );
+        assert_eq!(
+            Session::deserialize("silicate-session 3\ntabs 0\nfile a"),
+            None
+        );
+        assert_eq!(Session::deserialize(""), None);
+    }
+
+    #[test]
+    fn file_lists_of_the_first_version_are_read() {
+        assert_eq!(
+            Session::deserialize("silicate-session 1\nfile a\nfile b\n"),
+            Some(Session {
+                layout: Some(SessionLayout::Tabs {
+                    tabs: vec![tab("a"), tab("b")],
+                    active: 0,
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn paths_are_kept_whole() {
+        let mut paths = vec![
+            PathBuf::from("two\nlines"),
+            PathBuf::from("100%\r\t.procreate"),
+            PathBuf::from("%41"),
+            PathBuf::from("ünïcödé 🎨"),
+        ];
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStringExt;
+            paths.push(PathBuf::from(OsString::from_vec(
+                b"not \xff utf-8".to_vec(),
+            )));
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStringExt;
+            paths.push(PathBuf::from(OsString::from_wide(&[0x61, 0xd800, 0x62])));
+        }
+        for path in paths {
+            let text = encode_path(&path);
+            assert!(!text.contains(['\n', '\r']), "{text:?}");
+            assert_eq!(decode_path(&text), Some(path));
+        }
+        assert_eq!(decode_path("%4"), None);
+        assert_eq!(decode_path("%+1"), None);
+    }
+
+    #[test]
+    fn layouts_round_trip_through_the_dock() {
+        let layout = split(
+            true,
+            SessionLayout::Tabs {
+                tabs: vec![1, 2, 3],
+                active: 2,
+            },
+            split(
+                false,
+                SessionLayout::Tabs {
+                    tabs: vec![4],
+                    active: 0,
+                },
+                SessionLayout::Tabs {
+                    tabs: vec![5, 6],
+                    active: 1,
+                },
+            ),
+        );
+        let dock = layout.clone().into_dock();
+        assert_eq!(
+            dock.iter_all_tabs()
+                .map(|(_, tab)| *tab)
+                .collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(
+            SessionLayout::from_dock(&dock, |tab| Some(*tab)),
+            Some(layout)
+        );
+
+        // Splits left with one half are left out.
+        assert_eq!(
+            SessionLayout::from_dock(&dock, |tab| (*tab >= 4).then_some(*tab)),
+            Some(split(
+                false,
+                SessionLayout::Tabs {
+                    tabs: vec![4],
+                    active: 0,
+                },
+                SessionLayout::Tabs {
+                    tabs: vec![5, 6],
+                    active: 1,
+                },
+            ))
+        );
+        assert_eq!(SessionLayout::from_dock(&dock, |_| None::<i32>), None);
+    }
+
+    #[test]
+    fn journals_are_left_behind_until_closed() {
+        let path = std::env::temp_dir().join(format!(
+            "silicate-session-{}/session.journal",
+            std::process::id()
+        ));
+        let file = || StoredFile::at(Some(path.clone()), "session journal", Duration::ZERO);
+        let session = || Session {
+            layout: Some(SessionLayout::Tabs {
+                tabs: vec![tab("open.procreate")],
+                active: 0,
+            }),
+        };
+
+        let mut journal = SessionJournal { file: file() };
+        journal.record(Session::default);
+        assert_eq!(journal.crashed_session(), None);
+        journal.record(session);
+
+        // As read by the next run after a crash.
+        let next = SessionJournal { file: file() };
+        assert_eq!(next.crashed_session(), Some(session()));
+        journal.close();
+        assert_eq!(next.crashed_session(), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,751 @@
+use super::app::{App, PaperOptions};
+use super::canvas::{
+    self, DoubleClickAction, DoubleClickActions, RotationSnap, Transparency, TransparencyStyle,
+};
+use super::export_preset::{ExportPreset, PresetSize};
+use super::keymap::{Keymap, Shortcut};
+use super::layout::ViewOptions;
+use super::motion::MotionPreference;
+use super::store::StoredFile;
+use super::theme::Theme;
+use compositor::budget::MemoryBudget;
+use compositor::dev::{GpuBackend, GpuPreference};
+use compositor::tex::{ExportScale, ExtraFormats, ResampleFilter};
+use compositor::upload::UploadThrottle;
+use silica::BlendingMode;
+use std::fmt::Write;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("not a Silicate settings file")]
+    MissingVersion,
+    #[error("settings version {0} is not supported")]
+    UnsupportedVersion(String),
+    #[error("line {0} is not a `key = value` pair or a `[section]`")]
+    Syntax(usize),
+    #[error("line {line} has an invalid value for {key}")]
+    InvalidValue { line: usize, key: String },
+}
+
+/// Preferences that are kept between runs and can be carried between
+/// machines as a TOML file: the view options, pointer and key bindings,
+/// paper overlay and export settings. Paper textures and open files are not
+/// part of the bundle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub smooth: bool,
+    pub grid: bool,
+    pub pixel_grid: bool,
+    pub rulers: bool,
+    pub rotation_snap: RotationSnap,
+    pub extended_crosshair: bool,
+    pub bottom_bar: bool,
+    pub motion: MotionPreference,
+    pub theme: Theme,
+    pub accent: Option<[u8; 3]>,
+    pub backdrop: [u8; 3],
+    pub transparency: Transparency,
+    pub open_on_startup: bool,
+    pub adaptive_quality: bool,
+    pub presentation_cycle: Option<u32>,
+    pub verify_tiles: bool,
+    /// Threads that decode the tiles of a file, 0 for every core.
+    pub load_threads: usize,
+    pub memory_budget: u64,
+    /// GPU to use from the next start.
+    pub gpu: GpuPreference,
+    /// Bytes of layer textures uploaded per frame.
+    pub upload_limit: u64,
+    pub double_click: DoubleClickActions,
+    pub keymap: Keymap,
+    pub paper_enabled: bool,
+    pub paper_blend: BlendingMode,
+    pub paper_strength: f32,
+    pub paper_in_export: bool,
+    pub export_scale: ExportScale,
+    pub extra_formats: ExtraFormats,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::from_parts(
+            &ViewOptions::default(),
+            &PaperOptions::default(),
+            ExportScale::ACTUAL_SIZE,
+            ExtraFormats::default(),
+            MemoryBudget::DEFAULT_LIMIT,
+            UploadThrottle::DEFAULT_LIMIT,
+            false,
+        )
+    }
+}
+
+impl Settings {
+    const VERSION: &'static str = "1";
+
+    fn from_parts(
+        view: &ViewOptions,
+        paper: &PaperOptions,
+        export_scale: ExportScale,
+        extra_formats: ExtraFormats,
+        memory_budget: u64,
+        upload_limit: u64,
+        verify_tiles: bool,
+    ) -> Self {
+        Self {
+            smooth: view.smooth,
+            grid: view.grid,
+            pixel_grid: view.pixel_grid,
+            rulers: view.rulers,
+            rotation_snap: view.rotation_snap,
+            extended_crosshair: view.extended_crosshair,
+            bottom_bar: view.bottom_bar,
+            motion: view.motion,
+            theme: view.theme,
+            accent: view.accent,
+            backdrop: view.backdrop,
+            transparency: view.transparency,
+            open_on_startup: view.open_on_startup,
+            adaptive_quality: view.adaptive_quality,
+            presentation_cycle: view.presentation_cycle,
+            verify_tiles,
+            load_threads: 0,
+            memory_budget,
+            gpu: GpuPreference::default(),
+            upload_limit,
+            double_click: view.double_click,
+            keymap: view.keymap,
+            paper_enabled: paper.enabled,
+            paper_blend: paper.blend,
+            paper_strength: paper.strength,
+            paper_in_export: paper.include_in_export,
+            export_scale,
+            extra_formats,
+        }
+    }
+
+    /// Current settings of the app and the viewer.
+    pub fn capture(app: &App, view: &ViewOptions) -> Self {
+        let settings = Self::from_parts(
+            view,
+            &app.paper.lock(),
+            *app.export_scale.lock(),
+            *app.extra_formats.lock(),
+            app.dev.budget.limit(),
+            app.dev
+                .uploads
+                .limit()
+                .unwrap_or(UploadThrottle::DEFAULT_LIMIT),
+            app.verify_tiles.load(Acquire),
+        );
+        Self {
+            load_threads: app.load_threads.load(Acquire),
+            gpu: *app.gpu.lock(),
+            ..settings
+        }
+    }
+
+    /// Replace the settings of the app and the viewer.
+    pub fn apply(&self, app: &App, view: &mut ViewOptions) {
+        view.smooth = self.smooth;
+        view.grid = self.grid;
+        view.pixel_grid = self.pixel_grid;
+        view.rulers = self.rulers;
+        view.rotation_snap = self.rotation_snap;
+        view.extended_crosshair = self.extended_crosshair;
+        view.bottom_bar = self.bottom_bar;
+        view.motion = self.motion;
+        view.theme = self.theme;
+        view.accent = self.accent;
+        view.backdrop = self.backdrop;
+        view.transparency = self.transparency;
+        view.open_on_startup = self.open_on_startup;
+        view.adaptive_quality = self.adaptive_quality;
+        view.presentation_cycle = self.presentation_cycle;
+        view.double_click = self.double_click;
+        view.keymap = self.keymap;
+
+        let mut paper = app.paper.lock();
+        paper.enabled = self.paper_enabled;
+        paper.blend = self.paper_blend;
+        paper.strength = self.paper_strength;
+        paper.include_in_export = self.paper_in_export;
+        drop(paper);
+
+        *app.export_scale.lock() = self.export_scale;
+        *app.extra_formats.lock() = self.extra_formats;
+        app.dev.budget.set_limit(self.memory_budget);
+        app.dev.uploads.set_limit(Some(self.upload_limit));
+        app.verify_tiles.store(self.verify_tiles, Release);
+        app.load_threads.store(self.load_threads, Release);
+        *app.gpu.lock() = self.gpu;
+        // The paper and the sampling of the canvases may have changed.
+        app.invalidate_all();
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut out = String::from("# Silicate settings\n");
+        let _ = writeln!(out, "version = {}", Self::VERSION);
+
+        out.push_str("\n[view]\n");
+        let _ = writeln!(out, "smooth = {}", self.smooth);
+        let _ = writeln!(out, "grid = {}", self.grid);
+        let _ = writeln!(out, "pixel_grid = {}", self.pixel_grid);
+        let _ = writeln!(out, "rulers = {}", self.rulers);
+        let _ = writeln!(
+            out,
+            "rotation_snap = {}",
+            quote(self.rotation_snap.as_str())
+        );
+        let _ = writeln!(out, "extended_crosshair = {}", self.extended_crosshair);
+        let _ = writeln!(out, "bottom_bar = {}", self.bottom_bar);
+        let _ = writeln!(out, "motion = {}", quote(self.motion.as_str()));
+        let _ = writeln!(out, "theme = {}", quote(self.theme.as_str()));
+        // The theme's own accent is left out.
+        if let Some(accent) = self.accent {
+            let _ = writeln!(out, "accent = {}", format_rgb(accent));
+        }
+        let _ = writeln!(out, "backdrop = {}", format_rgb(self.backdrop));
+        let _ = writeln!(
+            out,
+            "transparency = {}",
+            quote(self.transparency.style.as_str())
+        );
+        let _ = writeln!(out, "checker_size = {}", self.transparency.size);
+        let _ = writeln!(
+            out,
+            "transparency_color = {}",
+            format_rgb(self.transparency.colors[0])
+        );
+        let _ = writeln!(
+            out,
+            "checker_color = {}",
+            format_rgb(self.transparency.colors[1])
+        );
+        let _ = writeln!(out, "open_on_startup = {}", self.open_on_startup);
+        let _ = writeln!(out, "adaptive_quality = {}", self.adaptive_quality);
+        // Presentation stays on the same tab if left out.
+        if let Some(seconds) = self.presentation_cycle {
+            let _ = writeln!(out, "presentation_cycle_seconds = {seconds}");
+        }
+        let _ = writeln!(out, "verify_tiles = {}", self.verify_tiles);
+        let _ = writeln!(out, "load_threads = {}", self.load_threads);
+        let _ = writeln!(out, "memory_budget_mib = {}", self.memory_budget >> 20);
+        let _ = writeln!(out, "upload_mib_per_frame = {}", self.upload_limit >> 20);
+
+        out.push_str("\n[gpu]\n");
+        let _ = writeln!(out, "backend = {}", quote(self.gpu.backend.as_str()));
+        // The most powerful adapter is used if left out.
+        if let Some(adapter) = self.gpu.adapter {
+            let _ = writeln!(out, "adapter = {adapter}");
+        }
+
+        out.push_str("\n[double_click]\n");
+        for (button, action) in canvas::POINTER_BUTTONS.into_iter().zip(self.double_click) {
+            let _ = writeln!(out, "{} = {}", button_key(button), quote(action.as_str()));
+        }
+
+        out.push_str("\n[keys]\n");
+        for shortcut in Shortcut::all() {
+            let key = self.keymap.key(*shortcut);
+            let _ = writeln!(out, "{} = {}", shortcut.setting(), quote(key.name()));
+        }
+
+        out.push_str("\n[paper]\n");
+        let _ = writeln!(out, "enabled = {}", self.paper_enabled);
+        let _ = writeln!(out, "blend = {}", quote(self.paper_blend.as_str()));
+        let _ = writeln!(out, "strength = {}", self.paper_strength);
+        let _ = writeln!(out, "include_in_export = {}", self.paper_in_export);
+
+        out.push_str("\n[export]\n");
+        let _ = writeln!(out, "scale = {}", self.export_scale.factor);
+        let _ = writeln!(
+            out,
+            "resampling = {}",
+            quote(self.export_scale.filter.as_str())
+        );
+        let _ = writeln!(out, "also_png = {}", self.extra_formats.png);
+        let _ = writeln!(out, "also_jpeg = {}", self.extra_formats.jpeg);
+        out
+    }
+
+    /// Parse a settings file, leaving out any export presets in it.
+    pub fn deserialize(text: &str) -> Result<Self, SettingsError> {
+        SettingsBundle::deserialize(text).map(|bundle| bundle.settings)
+    }
+
+    /// Set one setting, returning `None` if the value is invalid.
+    fn set(&mut self, section: &str, key: &str, value: &str) -> Option<()> {
+        match (section, key) {
+            ("view", "smooth") => self.smooth = value.parse().ok()?,
+            ("view", "grid") => self.grid = value.parse().ok()?,
+            ("view", "pixel_grid") => self.pixel_grid = value.parse().ok()?,
+            ("view", "rulers") => self.rulers = value.parse().ok()?,
+            ("view", "rotation_snap") => {
+                self.rotation_snap = parse_named(value, RotationSnap::all(), RotationSnap::as_str)?
+            }
+            ("view", "extended_crosshair") => self.extended_crosshair = value.parse().ok()?,
+            ("view", "bottom_bar") => self.bottom_bar = value.parse().ok()?,
+            ("view", "motion") => {
+                self.motion = parse_named(value, MotionPreference::all(), MotionPreference::as_str)?
+            }
+            ("view", "theme") => self.theme = parse_named(value, Theme::all(), Theme::as_str)?,
+            ("view", "accent") => self.accent = Some(parse_rgb(value)?),
+            ("view", "backdrop") => self.backdrop = parse_rgb(value)?,
+            ("view", "transparency") => {
+                self.transparency.style =
+                    parse_named(value, TransparencyStyle::all(), TransparencyStyle::as_str)?
+            }
+            ("view", "checker_size") => {
+                self.transparency.size = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|size| Transparency::SIZES.contains(size))?
+            }
+            ("view", "transparency_color") => self.transparency.colors[0] = parse_rgb(value)?,
+            ("view", "checker_color") => self.transparency.colors[1] = parse_rgb(value)?,
+            ("view", "open_on_startup") => self.open_on_startup = value.parse().ok()?,
+            ("view", "adaptive_quality") => self.adaptive_quality = value.parse().ok()?,
+            ("view", "presentation_cycle_seconds") => {
+                self.presentation_cycle = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|seconds| ViewOptions::PRESENTATION_CYCLE.contains(seconds))?,
+                )
+            }
+            ("view", "verify_tiles") => self.verify_tiles = value.parse().ok()?,
+            ("view", "load_threads") => self.load_threads = value.parse().ok()?,
+            ("view", "upload_mib_per_frame") => {
+                let mib = value.parse::<u64>().ok().filter(|&mib| mib > 0)?;
+                self.upload_limit = mib.checked_mul(1 << 20)?;
+            }
+            ("view", "memory_budget_mib") => {
+                let mib = value.parse::<u64>().ok().filter(|&mib| mib > 0)?;
+                self.memory_budget = mib.checked_mul(1 << 20)?;
+            }
+            ("gpu", "backend") => {
+                self.gpu.backend = parse_named(value, GpuBackend::all(), GpuBackend::as_str)?
+            }
+            ("gpu", "adapter") => self.gpu.adapter = Some(value.parse().ok()?),
+            ("keys", key) => {
+                let Some(shortcut) = Shortcut::all().iter().find(|s| s.setting() == key) else {
+                    tracing::warn!("Ignoring unknown setting {section}.{key}");
+                    return Some(());
+                };
+                *self.keymap.key_mut(*shortcut) = egui::Key::from_name(&unquote(value)?)
+                    .filter(|key| Keymap::is_bindable(*key))?;
+            }
+            ("double_click", key) => {
+                let Some(index) = canvas::POINTER_BUTTONS
+                    .iter()
+                    .position(|b| button_key(*b) == key)
+                else {
+                    tracing::warn!("Ignoring unknown setting {section}.{key}");
+                    return Some(());
+                };
+                self.double_click[index] =
+                    parse_named(value, DoubleClickAction::all(), DoubleClickAction::as_str)?;
+            }
+            ("paper", "enabled") => self.paper_enabled = value.parse().ok()?,
+            ("paper", "blend") => {
+                self.paper_blend = parse_named(value, &PaperOptions::BLENDS, BlendingMode::as_str)?
+            }
+            ("paper", "strength") => {
+                self.paper_strength = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|s| (0.0..=1.0).contains(s))?
+            }
+            ("paper", "include_in_export") => self.paper_in_export = value.parse().ok()?,
+            ("export", "scale") => {
+                self.export_scale.factor = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|f| (0.1..=4.0).contains(f))?
+            }
+            ("export", "resampling") => {
+                self.export_scale.filter =
+                    parse_named(value, ResampleFilter::all(), ResampleFilter::as_str)?
+            }
+            ("export", "also_png") => self.extra_formats.png = value.parse().ok()?,
+            ("export", "also_jpeg") => self.extra_formats.jpeg = value.parse().ok()?,
+            _ => tracing::warn!("Ignoring unknown setting {section}.{key}"),
+        }
+        Some(())
+    }
+}
+
+/// Settings file carried between machines, with the export presets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsBundle {
+    pub settings: Settings,
+    /// Presets of the file, `None` if it has none so that importing it
+    /// keeps the current ones.
+    pub export_presets: Option<Vec<ExportPreset>>,
+}
+
+impl SettingsBundle {
+    /// Section of each export preset, an array of tables.
+    const PRESETS: &'static str = "export_presets";
+
+    pub fn serialize(&self) -> String {
+        let mut out = self.settings.serialize();
+        for preset in self.export_presets.iter().flatten() {
+            let _ = writeln!(out, "\n[[{}]]", Self::PRESETS);
+            let _ = writeln!(out, "name = {}", quote(&preset.name));
+            let _ = writeln!(out, "format = {}", quote(preset.format.extensions_str()[0]));
+            let _ = match preset.size {
+                PresetSize::Scale(factor) => writeln!(out, "scale = {factor}"),
+                PresetSize::LongEdge(pixels) => writeln!(out, "long_edge = {pixels}"),
+            };
+            let _ = writeln!(out, "background = {}", preset.background);
+            let _ = writeln!(out, "region = {}", preset.region);
+            let _ = writeln!(out, "template = {}", quote(&preset.template));
+        }
+        out
+    }
+
+    /// Parse a settings file. Settings missing from the file keep their
+    /// defaults and unknown ones are skipped, so that files written by other
+    /// versions still load.
+    pub fn deserialize(text: &str) -> Result<Self, SettingsError> {
+        let mut settings = Settings::default();
+        let mut export_presets: Option<Vec<ExportPreset>> = None;
+        let mut version = None;
+        let mut section = "";
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+                section = name.trim();
+                if section == Self::PRESETS {
+                    let presets = export_presets.get_or_insert_with(Vec::new);
+                    presets.push(ExportPreset::new("Preset"));
+                }
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim();
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(SettingsError::Syntax(index + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+            let valid = if section.is_empty() && key == "version" {
+                if value != Settings::VERSION {
+                    return Err(SettingsError::UnsupportedVersion(value.to_string()));
+                }
+                version = Some(value);
+                Some(())
+            } else if let Some(preset) = export_presets
+                .as_mut()
+                .and_then(|presets| presets.last_mut())
+                .filter(|_| section == Self::PRESETS)
+            {
+                set_preset(preset, key, value)
+            } else {
+                settings.set(section, key, value)
+            };
+            if valid.is_none() {
+                return Err(SettingsError::InvalidValue {
+                    line: index + 1,
+                    key: format!("{section}.{key}"),
+                });
+            }
+        }
+        version.ok_or(SettingsError::MissingVersion)?;
+        Ok(Self {
+            settings,
+            export_presets,
+        })
+    }
+}
+
+/// Set one setting of an export preset, returning `None` if the value is
+/// invalid.
+fn set_preset(preset: &mut ExportPreset, key: &str, value: &str) -> Option<()> {
+    match key {
+        "name" => preset.name = unquote(value)?,
+        "format" => {
+            let extension = unquote(value)?;
+            preset.format = *ExportPreset::FORMATS
+                .iter()
+                .find(|f| f.extensions_str()[0] == extension)?;
+        }
+        "scale" => {
+            preset.size = PresetSize::Scale(value.parse::<f32>().ok().filter(|s| *s > 0.0)?);
+        }
+        "long_edge" => {
+            preset.size = PresetSize::LongEdge(value.parse::<u32>().ok().filter(|p| *p > 0)?);
+        }
+        "background" => preset.background = value.parse().ok()?,
+        "region" => preset.region = value.parse().ok()?,
+        "template" => preset.template = unquote(value)?,
+        _ => tracing::warn!("Ignoring unknown export preset setting {key}"),
+    }
+    Some(())
+}
+
+/// Settings kept between runs, written whenever they change.
+pub struct SettingsStore {
+    file: StoredFile,
+    /// Settings as they were last read or written.
+    saved: Option<Settings>,
+}
+
+impl SettingsStore {
+    /// Minimum time between writes of the settings.
+    const INTERVAL: Duration = Duration::from_secs(2);
+
+    pub fn load() -> Self {
+        let file = StoredFile::new("settings.toml", "settings", Self::INTERVAL);
+        let saved = file.read().and_then(|text| {
+            Settings::deserialize(&text)
+                .map_err(|err| tracing::warn!("Ignoring the saved settings: {err}"))
+                .ok()
+        });
+        Self { file, saved }
+    }
+
+    /// Settings saved by a previous run.
+    pub fn saved(&self) -> Option<Settings> {
+        self.saved
+    }
+
+    /// Write the settings if they changed, at most once per interval
+    /// unless forced.
+    pub fn save(&mut self, settings: Settings, force: bool) {
+        if self.saved != Some(settings) && self.file.due(force) {
+            self.saved = Some(settings);
+            self.file.write(settings.serialize());
+        }
+    }
+}
+
+/// Key of a pointer button in the `[double_click]` section.
+fn button_key(button: egui::PointerButton) -> String {
+    format!("{button:?}").to_lowercase()
+}
+
+/// Color written as an `[r, g, b]` array.
+fn format_rgb([r, g, b]: [u8; 3]) -> String {
+    format!("[{r}, {g}, {b}]")
+}
+
+/// Parse an `[r, g, b]` array.
+fn parse_rgb(value: &str) -> Option<[u8; 3]> {
+    let channels = value
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split(',')
+        .map(|c| c.trim().parse::<u8>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    channels.try_into().ok()
+}
+
+/// Find the variant named by a quoted string value.
+fn parse_named<T: Copy>(value: &str, all: &[T], name: fn(&T) -> &'static str) -> Option<T> {
+    let value = unquote(value)?;
+    all.iter().find(|v| name(v) == value).copied()
+}
+
+/// Line without its comment, if it has one. A `#` inside a string does not
+/// start a comment.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// String written as a TOML basic string, with quotes, backslashes and
+/// control characters escaped.
+fn quote(value: &str) -> String {
+    let mut out = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parse a TOML basic string, the whole of the value.
+fn unquote(value: &str) -> Option<String> {
+    let mut chars = value.strip_prefix('"')?.chars();
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => {
+                let c = match chars.next()? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    escape @ ('u' | 'U') => {
+                        let len = if escape == 'u' { 4 } else { 8 };
+                        let hex = chars.by_ref().take(len).collect::<String>();
+                        if hex.len() != len || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                            return None;
+                        }
+                        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    _ => return None,
+                };
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    chars.as_str().is_empty().then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+
+    #[test]
+    fn settings_round_trip() {
+        let mut settings = Settings {
+            accent: Some([1, 2, 3]),
+            presentation_cycle: Some(30),
+            load_threads: 3,
+            paper_strength: 0.25,
+            ..Settings::default()
+        };
+        settings.double_click[1] = DoubleClickAction::Fit;
+        *settings.keymap.key_mut(Shortcut::Present) = egui::Key::F5;
+        *settings.keymap.key_mut(Shortcut::RotateLeft) = egui::Key::Comma;
+        assert_eq!(
+            Settings::deserialize(&settings.serialize()).unwrap(),
+            settings
+        );
+        assert_eq!(
+            Settings::deserialize(&Settings::default().serialize()).unwrap(),
+            Settings::default()
+        );
+    }
+
+    #[test]
+    fn bundles_round_trip_presets_with_special_characters() {
+        let presets = vec![
+            ExportPreset {
+                template: String::from("#{name} \"final\" \\ {date}"),
+                ..ExportPreset::new("Print # 1")
+            },
+            ExportPreset {
+                format: ImageFormat::Jpeg,
+                size: PresetSize::LongEdge(2000),
+                background: false,
+                region: true,
+                ..ExportPreset::new("Tab\tand\nnew line\u{1}")
+            },
+        ];
+        let bundle = SettingsBundle {
+            settings: Settings::default(),
+            export_presets: Some(presets),
+        };
+        assert_eq!(
+            SettingsBundle::deserialize(&bundle.serialize()).unwrap(),
+            bundle
+        );
+
+        // Files without presets keep the current ones.
+        let settings = Settings::default().serialize();
+        let bundle = SettingsBundle::deserialize(&settings).unwrap();
+        assert_eq!(bundle.export_presets, None);
+    }
+
+    #[test]
+    fn comments_end_lines_outside_of_strings() {
+        for (line, expected) in [
+            ("theme = \"Light\"", "theme = \"Light\""),
+            ("theme = \"Light\" # dark at night", "theme = \"Light\" "),
+            ("# whole line", ""),
+            ("name = \"A # B\" # comment", "name = \"A # B\" "),
+            ("name = \"A \\\" # B\"", "name = \"A \\\" # B\""),
+            ("name = \"A \\\\\" # B", "name = \"A \\\\\" "),
+        ] {
+            assert_eq!(strip_comment(line), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn strings_are_escaped_and_unescaped() {
+        for (text, quoted) in [
+            ("plain", "\"plain\""),
+            ("a \"b\"", "\"a \\\"b\\\"\""),
+            ("C:\\Exports", "\"C:\\\\Exports\""),
+            ("line\nbreak\ttab", "\"line\\nbreak\\ttab\""),
+            ("bell\u{7}", "\"bell\\u0007\""),
+        ] {
+            assert_eq!(quote(text), quoted);
+            assert_eq!(unquote(quoted).as_deref(), Some(text));
+        }
+        assert_eq!(unquote("\"\\u00e9\\U0001F600\"").as_deref(), Some("é😀"));
+        for invalid in [
+            "plain",
+            "\"unterminated",
+            "\"trailing\" text",
+            "\"bad \\q escape\"",
+            "\"short \\u12\"",
+            "\"sign \\u+123\"",
+            "\"surrogate \\uD800\"",
+        ] {
+            assert_eq!(unquote(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn invalid_files_report_their_line() {
+        assert!(matches!(
+            Settings::deserialize("[view]\nsmooth = true\n"),
+            Err(SettingsError::MissingVersion)
+        ));
+        assert!(matches!(
+            Settings::deserialize("version = 2\n"),
+            Err(SettingsError::UnsupportedVersion(version)) if version == "2"
+        ));
+        assert!(matches!(
+            Settings::deserialize("version = 1\n[view]\nsmooth\n"),
+            Err(SettingsError::Syntax(3))
+        ));
+        assert!(matches!(
+            Settings::deserialize("version = 1\n[keys]\npresent = \"Escape\"\n"),
+            Err(SettingsError::InvalidValue { line: 3, key }) if key == "keys.present"
+        ));
+        // Unknown settings are skipped.
+        let settings = Settings::deserialize("version = 1\n[view]\nsparkles = 3\n").unwrap();
+        assert_eq!(settings, Settings::default());
+    }
+}
@@ -0,0 +1,131 @@
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::{imageops, Rgba, RgbaImage};
+
+/// Grid of labelled thumbnails giving an overview of several files.
+pub struct ContactSheet {
+    /// Largest width and height of a thumbnail.
+    pub thumbnail: u32,
+}
+
+impl ContactSheet {
+    const PADDING: u32 = 16;
+    const LABEL_HEIGHT: u32 = 24;
+    const LABEL_SIZE: f32 = 16.0;
+    const BACKGROUND: Rgba<u8> = Rgba([240, 240, 240, 255]);
+    const TEXT: [u8; 3] = [32, 32, 32];
+    /// Font of the labels, the same as the viewer's interface.
+    const FONT: &'static str = "Ubuntu-Light";
+
+    /// Downscale a premultiplied composite to fit in a thumbnail.
+    pub fn thumbnail(&self, image: &RgbaImage) -> RgbaImage {
+        let scale = (self.thumbnail as f32 / image.width() as f32)
+            .min(self.thumbnail as f32 / image.height() as f32)
+            .min(1.0);
+        let scaled = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+        imageops::resize(
+            image,
+            scaled(image.width()),
+            scaled(image.height()),
+            imageops::FilterType::Triangle,
+        )
+    }
+
+    /// Lay out premultiplied thumbnails in a square-ish grid, each centered
+    /// in its cell above its label. The sheet is opaque.
+    pub fn compose(&self, entries: &[(String, RgbaImage)]) -> RgbaImage {
+        let count = entries.len().max(1) as u32;
+        let columns = (count as f64).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+        let cell_width = self.thumbnail + Self::PADDING;
+        let cell_height = self.thumbnail + Self::LABEL_HEIGHT + Self::PADDING;
+
+        let mut sheet = RgbaImage::from_pixel(
+            columns * cell_width + Self::PADDING,
+            rows * cell_height + Self::PADDING,
+            Self::BACKGROUND,
+        );
+
+        let fonts = egui::FontDefinitions::default();
+        let font = fonts
+            .font_data
+            .get(Self::FONT)
+            .and_then(|data| FontRef::try_from_slice(&data.font).ok());
+
+        for (i, (label, thumbnail)) in entries.iter().enumerate() {
+            let x = Self::PADDING + (i as u32 % columns) * cell_width;
+            let y = Self::PADDING + (i as u32 / columns) * cell_height;
+
+            let left = x + (self.thumbnail - thumbnail.width().min(self.thumbnail)) / 2;
+            let top = y + (self.thumbnail - thumbnail.height().min(self.thumbnail)) / 2;
+            for (tx, ty, src) in thumbnail.enumerate_pixels() {
+                let dst = sheet.get_pixel_mut(left + tx, top + ty);
+                // Premultiplied source over an opaque background.
+                let alpha = 255 - u16::from(src.0[3]);
+                for c in 0..3 {
+                    dst.0[c] =
+                        (u16::from(src.0[c]) + u16::from(dst.0[c]) * alpha / 255).min(255) as u8;
+                }
+            }
+
+            if let Some(font) = &font {
+                draw_label(
+                    &mut sheet,
+                    font,
+                    label,
+                    (x, y + self.thumbnail),
+                    self.thumbnail,
+                );
+            }
+        }
+        sheet
+    }
+}
+
+/// Draw a line of text centered in a label box, shortened with an ellipsis
+/// if it does not fit.
+fn draw_label(sheet: &mut RgbaImage, font: &FontRef, text: &str, (x, y): (u32, u32), width: u32) {
+    let scale = PxScale::from(ContactSheet::LABEL_SIZE);
+    let scaled = font.as_scaled(scale);
+    let text_width = |text: &str| {
+        text.chars()
+            .map(|c| scaled.h_advance(scaled.glyph_id(c)))
+            .sum::<f32>()
+    };
+
+    let mut text = text.to_string();
+    if text_width(&text) > width as f32 {
+        while !text.is_empty() && text_width(&text) + text_width("…") > width as f32 {
+            text.pop();
+        }
+        text.push('…');
+    }
+
+    let mut caret = x as f32 + (width as f32 - text_width(&text)).max(0.0) / 2.0;
+    let baseline =
+        y as f32 + (ContactSheet::LABEL_HEIGHT as f32 + scaled.ascent() + scaled.descent()) / 2.0;
+    for c in text.chars() {
+        let glyph = scaled
+            .glyph_id(c)
+            .with_scale_and_position(scale, point(caret, baseline));
+        caret += scaled.h_advance(glyph.id);
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + i64::from(gx);
+            let py = bounds.min.y as i64 + i64::from(gy);
+            let (Ok(px), Ok(py)) = (u32::try_from(px), u32::try_from(py)) else {
+                return;
+            };
+            if px >= sheet.width() || py >= sheet.height() {
+                return;
+            }
+            let pixel = sheet.get_pixel_mut(px, py);
+            for (channel, text) in pixel.0.iter_mut().zip(ContactSheet::TEXT) {
+                *channel = (f32::from(*channel) * (1.0 - coverage) + f32::from(text) * coverage)
+                    .round() as u8;
+            }
+        });
+    }
+}
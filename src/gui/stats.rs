@@ -0,0 +1,195 @@
+use egui::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// What was measured when a frame started.
+struct FrameSample {
+    start: Instant,
+    /// Recomposites done since startup.
+    recomposites: usize,
+    /// Bytes of layer textures uploaded since startup.
+    uploaded: u64,
+    /// GPU time of the last timed composite.
+    composite: Option<Duration>,
+    /// CPU time flattening the layers of the last composite.
+    linearize: Duration,
+}
+
+/// Recent frames of the GUI, shown in the statistics HUD.
+#[derive(Default)]
+pub struct FrameStats {
+    /// Samples of recent frames, oldest first.
+    frames: VecDeque<FrameSample>,
+}
+
+impl FrameStats {
+    /// Number of frames kept for the graphs.
+    const HISTORY: usize = 120;
+    /// Frame time at the top of the graph, unless a frame took longer.
+    const GRAPH_MS: f32 = 1000.0 / 30.0;
+    const TARGET_MS: f32 = 1000.0 / 60.0;
+    const GRAPH_SIZE: Vec2 = vec2(180.0, 32.0);
+
+    pub fn record(
+        &mut self,
+        recomposites: usize,
+        uploaded: u64,
+        composite: Option<Duration>,
+        linearize: Duration,
+    ) {
+        if self.frames.len() == Self::HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(FrameSample {
+            start: Instant::now(),
+            recomposites,
+            uploaded,
+            composite,
+            linearize,
+        });
+    }
+
+    /// A measure between consecutive frames, such as the time between them,
+    /// oldest first.
+    fn deltas(&self, measure: impl Fn(&FrameSample, &FrameSample) -> f32) -> Vec<f32> {
+        self.frames
+            .iter()
+            .zip(self.frames.iter().skip(1))
+            .map(|(a, b)| measure(a, b))
+            .collect()
+    }
+
+    /// Time between consecutive frames in milliseconds, oldest first.
+    fn frame_times(&self) -> Vec<f32> {
+        self.deltas(|a, b| (b.start - a.start).as_secs_f32() * 1000.0)
+    }
+
+    /// Upload bandwidth of each frame in MiB/s, oldest first.
+    fn upload_rates(&self) -> Vec<f32> {
+        self.deltas(|a, b| {
+            let seconds = (b.start - a.start).as_secs_f32().max(f32::EPSILON);
+            (b.uploaded - a.uploaded) as f32 / (1 << 20) as f32 / seconds
+        })
+    }
+
+    /// Recomposites per second over the recent frames.
+    fn recomposite_rate(&self) -> f32 {
+        match (self.frames.front(), self.frames.back()) {
+            (Some(first), Some(last)) if last.start > first.start => {
+                (last.recomposites - first.recomposites) as f32
+                    / (last.start - first.start).as_secs_f32()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Plot recent values from right to left, scaled so that `top` or the
+    /// largest value fills the graph, with an optional target line.
+    fn graph(ui: &mut Ui, values: &[f32], top: f32, target: Option<f32>, color: Color32) {
+        let (rect, _) = ui.allocate_exact_size(Self::GRAPH_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, Rounding::same(2.0), Color32::from_black_alpha(120));
+        let top = values.iter().copied().fold(top, f32::max);
+        let y = |value: f32| rect.bottom() - rect.height() * (value / top).min(1.0);
+        if let Some(target) = target {
+            painter.hline(
+                rect.x_range(),
+                y(target),
+                Stroke::new(1.0, Color32::from_gray(80)),
+            );
+        }
+        let step = rect.width() / (Self::HISTORY - 1) as f32;
+        let points = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                pos2(
+                    rect.right() - (values.len() - 1 - i) as f32 * step,
+                    y(*value),
+                )
+            })
+            .collect::<Vec<_>>();
+        painter.add(Shape::line(points, Stroke::new(1.0, color)));
+    }
+
+    /// Compact overlay with graphs of the frame time, the GPU time of
+    /// composites, the time flattening their layers and the upload
+    /// bandwidth, along with the recomposites and the GPU memory charged
+    /// to the budget.
+    ///
+    /// `timestamps` tells whether the device can time composites at all.
+    pub fn ui(&self, ui: &mut Ui, timestamps: bool, vram: u64, vram_limit: u64) {
+        let times = self.frame_times();
+        let average = times.iter().sum::<f32>() / times.len().max(1) as f32;
+        let worst = times.iter().copied().fold(0.0, f32::max);
+        let composites = self
+            .frames
+            .iter()
+            .filter_map(|frame| frame.composite)
+            .map(|time| time.as_secs_f32() * 1000.0)
+            .collect::<Vec<_>>();
+        let linearize = self
+            .frames
+            .iter()
+            .map(|frame| frame.linearize.as_secs_f32() * 1000.0)
+            .collect::<Vec<_>>();
+        let uploads = self.upload_rates();
+
+        Grid::new("Stats Grid").num_columns(2).show(ui, |ui| {
+            ui.label("Frame");
+            ui.vertical(|ui| {
+                Self::graph(
+                    ui,
+                    &times,
+                    Self::GRAPH_MS,
+                    Some(Self::TARGET_MS),
+                    Color32::LIGHT_GREEN,
+                );
+                ui.monospace(format!(
+                    "{:.0} fps, {average:.1} ms avg, {worst:.1} ms max",
+                    1000.0 / average.max(f32::EPSILON)
+                ));
+            });
+            ui.end_row();
+            ui.label("Composite");
+            ui.vertical(|ui| match composites.last() {
+                Some(last) => {
+                    Self::graph(
+                        ui,
+                        &composites,
+                        Self::TARGET_MS,
+                        None,
+                        Color32::from_rgb(255, 170, 80),
+                    );
+                    ui.monospace(format!("{last:.2} ms on the GPU"));
+                }
+                None if timestamps => {
+                    ui.monospace("waiting for a composite");
+                }
+                None => {
+                    ui.monospace("unsupported by the GPU");
+                }
+            });
+            ui.end_row();
+            ui.label("Linearize");
+            ui.vertical(|ui| {
+                Self::graph(ui, &linearize, 1.0, None, Color32::LIGHT_BLUE);
+                ui.monospace(format!("{:.3} ms", linearize.last().unwrap_or(&0.0)));
+            });
+            ui.end_row();
+            ui.label("Uploads");
+            ui.vertical(|ui| {
+                Self::graph(ui, &uploads, 1.0, None, Color32::from_rgb(220, 130, 255));
+                ui.monospace(format!("{:.1} MiB/s", uploads.last().unwrap_or(&0.0)));
+            });
+            ui.end_row();
+            ui.label("Recomposites");
+            let recomposites = self.frames.back().map_or(0, |frame| frame.recomposites);
+            ui.monospace(format!("{recomposites} ({:.1}/s)", self.recomposite_rate()));
+            ui.end_row();
+            ui.label("VRAM");
+            ui.monospace(format!("{} / {} MiB", vram >> 20, vram_limit >> 20));
+            ui.end_row();
+        });
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// File in the data directory that is rewritten while the app runs, such as
+/// the settings or the view history. Each write goes to a temporary file
+/// that then replaces the previous version, so that a crash in the middle of
+/// a write never leaves a truncated file behind.
+#[derive(Debug)]
+pub struct StoredFile {
+    path: Option<PathBuf>,
+    /// What the file holds, for the log.
+    what: &'static str,
+    /// Minimum time between checks of whether the file needs rewriting.
+    interval: Duration,
+    last_check: Instant,
+    /// Contents as they were last written, to only write them when they
+    /// changed.
+    written: Option<String>,
+}
+
+impl StoredFile {
+    /// File of the given name in the data directory.
+    pub fn new(name: &str, what: &'static str, interval: Duration) -> Self {
+        let path = crate::paths::data_dir().map(|dir| dir.join(name));
+        Self::at(path, what, interval)
+    }
+
+    pub fn at(path: Option<PathBuf>, what: &'static str, interval: Duration) -> Self {
+        Self {
+            path,
+            what,
+            interval,
+            last_check: Instant::now(),
+            written: None,
+        }
+    }
+
+    pub fn read(&self) -> Option<String> {
+        fs::read_to_string(self.path.as_ref()?).ok()
+    }
+
+    /// Whether the file should be checked for changes now, at most once per
+    /// interval unless forced.
+    pub fn due(&mut self, force: bool) -> bool {
+        if !force && self.last_check.elapsed() < self.interval {
+            return false;
+        }
+        self.last_check = Instant::now();
+        true
+    }
+
+    /// Take the contents as already written, such as defaults that only
+    /// need to be written once they are changed.
+    pub fn set_written(&mut self, contents: String) {
+        self.written = Some(contents);
+    }
+
+    /// Replace the file if its contents changed.
+    pub fn write(&mut self, contents: String) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if self.written.as_ref() == Some(&contents) {
+            return;
+        }
+        match Self::replace(path, contents.as_bytes()) {
+            Ok(()) => self.written = Some(contents),
+            Err(err) => {
+                tracing::warn!("Failed to write {} {}: {err}", self.what, path.display());
+                // Do not keep retrying a file that can not be written.
+                self.path = None;
+            }
+        }
+    }
+
+    /// Remove the file, such as a journal on a clean exit.
+    pub fn remove(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to remove {} {}: {err}", self.what, path.display());
+            }
+        }
+    }
+
+    fn replace(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+        let result = fs::rename(&temp, path);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_replaced_only_when_they_change() {
+        let folder = std::env::temp_dir().join(format!("silicate-store-{}", std::process::id()));
+        let path = folder.join("nested/views");
+        let mut file = StoredFile::at(Some(path.clone()), "test file", Duration::from_secs(60));
+        assert_eq!(file.read(), None);
+
+        file.write("first".to_string());
+        assert_eq!(file.read().as_deref(), Some("first"));
+        assert!(!folder.join("nested/views.tmp").exists());
+
+        // Unchanged contents are not written again.
+        fs::write(&path, "edited elsewhere").unwrap();
+        file.write("first".to_string());
+        assert_eq!(file.read().as_deref(), Some("edited elsewhere"));
+        file.write("second".to_string());
+        assert_eq!(file.read().as_deref(), Some("second"));
+
+        file.set_written("third".to_string());
+        file.write("third".to_string());
+        assert_eq!(file.read().as_deref(), Some("second"));
+
+        file.remove();
+        assert!(!path.exists());
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn checks_are_throttled_unless_forced() {
+        let mut file = StoredFile::at(None, "test file", Duration::from_secs(60));
+        assert!(!file.due(false));
+        assert!(file.due(true));
+
+        let mut file = StoredFile::at(None, "test file", Duration::ZERO);
+        assert!(file.due(false));
+    }
+
+    #[test]
+    fn unwritable_files_are_given_up() {
+        let folder =
+            std::env::temp_dir().join(format!("silicate-store-bad-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        // A directory can not be replaced by a file.
+        fs::create_dir_all(folder.join("taken")).unwrap();
+        let mut file = StoredFile::at(Some(folder.join("taken")), "test file", Duration::ZERO);
+        file.write("contents".to_string());
+        assert_eq!(file.path, None);
+        assert!(!folder.join("taken.tmp").exists());
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use super::app::InstanceKey;
+use compositor::dev::GpuHandle;
+use compositor::tex::TiledTexture;
+use egui::*;
+use egui_wgpu::Renderer;
+use std::collections::HashMap;
+
+/// Overview of the open tabs with a live preview of each, shown while
+/// Ctrl+Tab is held. Tab and Shift+Tab move through the tabs, and letting
+/// go of Ctrl switches to the selected one.
+#[derive(Debug, Default)]
+pub struct TabSwitcher {
+    /// Tab selected in the overview, `None` while it is closed.
+    selected: Option<InstanceKey>,
+    /// Bound previews of the files and their size.
+    previews: HashMap<InstanceKey, (TextureId, Vec2)>,
+}
+
+impl TabSwitcher {
+    /// Size of the frame that previews are fitted in.
+    const FRAME: Vec2 = vec2(160.0, 120.0);
+    /// Height of the name under a preview.
+    const LABEL: f32 = 20.0;
+
+    pub fn is_open(&self) -> bool {
+        self.selected.is_some()
+    }
+
+    /// Bind the preview of a file for the overview, replacing the one bound
+    /// before, and release the previews of files that were closed.
+    pub fn bind(
+        &mut self,
+        renderer: &mut Renderer,
+        dev: &GpuHandle,
+        key: InstanceKey,
+        preview: Option<&TiledTexture>,
+        open: impl Fn(InstanceKey) -> bool,
+    ) {
+        self.previews.retain(|key, (id, _)| {
+            let keep = open(*key);
+            if !keep {
+                renderer.free_texture(id);
+            }
+            keep
+        });
+        let Some((_, texture)) = preview.and_then(|preview| preview.tiles.first()) else {
+            return;
+        };
+        let view = texture.create_srgb_view();
+        let size = vec2(texture.size.width as f32, texture.size.height as f32);
+        match self.previews.get_mut(&key) {
+            Some((id, bound)) => {
+                renderer.update_egui_texture_from_wgpu_texture(
+                    &dev.device,
+                    &view,
+                    wgpu::FilterMode::Linear,
+                    *id,
+                );
+                *bound = size;
+            }
+            None => {
+                let id =
+                    renderer.register_native_texture(&dev.device, &view, wgpu::FilterMode::Linear);
+                self.previews.insert(key, (id, size));
+            }
+        }
+    }
+
+    /// Show the overview of `tabs`, with their keys and titles in order.
+    /// Returns the tab to switch to once one is chosen.
+    pub fn ui(
+        &mut self,
+        context: &Context,
+        tabs: &[(InstanceKey, String)],
+        active: InstanceKey,
+    ) -> Option<InstanceKey> {
+        if tabs.is_empty() {
+            self.selected = None;
+            return None;
+        }
+        let step = context.input_mut(|input| {
+            let back = input.consume_key(Modifiers::CTRL | Modifiers::SHIFT, Key::Tab);
+            let forward = input.consume_key(Modifiers::CTRL, Key::Tab);
+            i32::from(forward) - i32::from(back)
+        });
+        if step != 0 {
+            let from = self.selected.unwrap_or(active);
+            let index = tabs
+                .iter()
+                .position(|(tab, _)| *tab == from)
+                .map_or(0, |index| {
+                    (index as i32 + step).rem_euclid(tabs.len() as i32) as usize
+                });
+            self.selected = Some(tabs[index].0);
+        }
+        let selected = self.selected?;
+        let (held, cancel) = context.input_mut(|input| {
+            (
+                input.modifiers.ctrl,
+                input.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        });
+        if cancel {
+            self.selected = None;
+            return None;
+        }
+        if !held {
+            self.selected = None;
+            return Some(selected);
+        }
+
+        let mut clicked = None;
+        Area::new("Tab Switcher")
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .order(Order::Foreground)
+            .show(context, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    let columns = ((context.screen_rect().width() * 0.8) / Self::FRAME.x)
+                        .floor()
+                        .clamp(1.0, 6.0) as usize;
+                    ui.set_max_width(
+                        columns as f32 * (Self::FRAME.x + ui.spacing().item_spacing.x),
+                    );
+                    ui.horizontal_wrapped(|ui| {
+                        for (key, title) in tabs {
+                            if self.card(ui, *key, title, *key == selected).clicked() {
+                                clicked = Some(*key);
+                            }
+                        }
+                    });
+                });
+            });
+        if clicked.is_some() {
+            self.selected = None;
+        }
+        clicked
+    }
+
+    fn card(&self, ui: &mut Ui, key: InstanceKey, title: &str, selected: bool) -> Response {
+        let (rect, response) =
+            ui.allocate_exact_size(Self::FRAME + vec2(0.0, Self::LABEL), Sense::click());
+        let visuals = ui.visuals();
+        let painter = ui.painter_at(rect);
+        if selected {
+            painter.rect(
+                rect,
+                4.0,
+                visuals.selection.bg_fill,
+                visuals.selection.stroke,
+            );
+        } else if response.hovered() {
+            painter.rect_filled(rect, 4.0, visuals.widgets.hovered.weak_bg_fill);
+        }
+
+        let frame = Rect::from_min_size(rect.min, Self::FRAME).shrink(6.0);
+        match self.previews.get(&key) {
+            Some((id, size)) => {
+                let scale = (frame.size() / *size).min_elem();
+                painter.image(
+                    *id,
+                    Rect::from_center_size(frame.center(), *size * scale),
+                    Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                    Color32::WHITE,
+                );
+            }
+            None => {
+                painter.text(
+                    frame.center(),
+                    Align2::CENTER_CENTER,
+                    "Loading…",
+                    FontId::proportional(12.0),
+                    visuals.weak_text_color(),
+                );
+            }
+        }
+        painter.text(
+            pos2(rect.center().x, rect.bottom() - Self::LABEL / 2.0),
+            Align2::CENTER_CENTER,
+            title,
+            FontId::proportional(12.0),
+            visuals.text_color(),
+        );
+        response.on_hover_text(title)
+    }
+}
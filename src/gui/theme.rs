@@ -0,0 +1,50 @@
+use egui::{Color32, Visuals};
+
+/// Backdrop drawn behind the canvas unless changed.
+pub const DEFAULT_BACKDROP: [u8; 3] = [20, 20, 20];
+
+/// Color scheme of the interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const fn all() -> &'static [Self] {
+        &[Self::Dark, Self::Light]
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Dark => "Dark",
+            Self::Light => "Light",
+        }
+    }
+
+    /// Visuals of the theme, with the selection and links in the accent
+    /// color if one is given.
+    pub fn visuals(self, accent: Option<[u8; 3]>) -> Visuals {
+        let mut visuals = match self {
+            Self::Dark => Visuals::dark(),
+            Self::Light => Visuals::light(),
+        };
+        if let Some([r, g, b]) = accent {
+            let accent = Color32::from_rgb(r, g, b);
+            visuals.selection.bg_fill = accent;
+            visuals.selection.stroke.color = contrasting(accent);
+            visuals.hyperlink_color = accent;
+        }
+        visuals
+    }
+}
+
+/// Black or white, whichever reads better on the color.
+fn contrasting(color: Color32) -> Color32 {
+    let [r, g, b, _] = color.to_array().map(f32::from);
+    if 0.299 * r + 0.587 * g + 0.114 * b > 150.0 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
@@ -0,0 +1,333 @@
+use super::canvas::{CanvasViewBounds, Guides, MarginUnit};
+use super::store::StoredFile;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How a file was last viewed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedView {
+    /// Part of the canvas in view, `None` when fitted to the view.
+    pub bounds: Option<CanvasViewBounds>,
+    pub rotation: f32,
+    pub guides: Guides,
+    /// UUIDs of the selected layers, the focused one last.
+    pub selection: Vec<String>,
+}
+
+impl SavedView {
+    /// Rotation and bounds, as written on the line that starts a view.
+    pub fn fields(&self) -> String {
+        let bounds = self.bounds.map_or("fit".to_string(), |b| {
+            format!("{},{},{},{}", b.min[0], b.min[1], b.max[0], b.max[1])
+        });
+        format!("{} {bounds}", self.rotation)
+    }
+
+    /// View from the rotation and bounds written by [`Self::fields`].
+    pub fn from_fields(rotation: &str, bounds: &str) -> Option<Self> {
+        let rotation = rotation.parse::<f32>().ok()?;
+        let bounds = match bounds {
+            "fit" => None,
+            bounds => {
+                let values = bounds
+                    .split(',')
+                    .map(|v| v.parse::<f32>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                let &[x0, y0, x1, y1] = values.as_slice() else {
+                    return None;
+                };
+                Some(CanvasViewBounds {
+                    min: [x0, y0],
+                    max: [x1, y1],
+                })
+                .filter(CanvasViewBounds::is_valid)
+            }
+        };
+        Some(Self {
+            bounds,
+            rotation,
+            guides: Guides::default(),
+            selection: Vec::new(),
+        })
+    }
+
+    /// Write the lines that further describe the view, after the line that
+    /// starts it.
+    pub fn write_details(&self, out: &mut String) {
+        let guides = self.guides;
+        if guides != Guides::default() {
+            out.push_str(&format!(
+                "\nguides {} {} {} {}",
+                guides.center,
+                guides.thirds,
+                guides.margin,
+                guides.margin_unit.as_str()
+            ));
+        }
+        // UUIDs that can not be told apart on the line are not kept.
+        let selection = self
+            .selection
+            .iter()
+            .filter(|uuid| !uuid.is_empty() && !uuid.contains(char::is_whitespace))
+            .fold(String::new(), |line, uuid| line + " " + uuid.as_str());
+        if !selection.is_empty() {
+            out.push_str("\nselection");
+            out.push_str(&selection);
+        }
+    }
+
+    /// Apply a line written by [`Self::write_details`], returning whether
+    /// it was one.
+    pub fn read_detail(&mut self, line: &str) -> bool {
+        let guides = |line: &str| {
+            let mut fields = line.strip_prefix("guides ")?.split(' ');
+            let guides = Guides {
+                center: fields.next()?.parse().ok()?,
+                thirds: fields.next()?.parse().ok()?,
+                margin: fields.next()?.parse::<f32>().ok().filter(|m| *m >= 0.0)?,
+                margin_unit: {
+                    let unit = fields.next()?;
+                    *MarginUnit::all().iter().find(|u| u.as_str() == unit)?
+                },
+            };
+            fields.next().is_none().then_some(guides)
+        };
+        if let Some(guides) = guides(line) {
+            self.guides = guides;
+        } else if let Some(selection) = line.strip_prefix("selection ") {
+            self.selection = selection.split(' ').map(str::to_string).collect();
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+/// Views of recently viewed files, kept between runs. Files are found by
+/// their content hash first, so that moved or renamed files keep their
+/// view, and by their path otherwise.
+pub struct ViewHistory {
+    file: StoredFile,
+    /// Content hash, path and view of each file, most recent first.
+    entries: Vec<(u64, PathBuf, SavedView)>,
+    changed: bool,
+}
+
+impl ViewHistory {
+    const HEADER: &'static str = "silicate-views 1";
+    /// Number of files remembered.
+    const CAPACITY: usize = 200;
+    /// Minimum time between writes of the history.
+    const INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn load() -> Self {
+        Self::open(StoredFile::new("views", "view history", Self::INTERVAL))
+    }
+
+    fn open(file: StoredFile) -> Self {
+        let entries = file
+            .read()
+            .and_then(|text| Self::deserialize(&text))
+            .unwrap_or_default();
+        Self {
+            file,
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Last view of a file.
+    pub fn get(&self, hash: u64, path: &Path) -> Option<SavedView> {
+        self.entries
+            .iter()
+            .find(|(h, ..)| *h == hash)
+            .or_else(|| self.entries.iter().find(|(_, p, _)| p == path))
+            .map(|(.., view)| view.clone())
+    }
+
+    /// Remember the view of a file.
+    pub fn record(&mut self, hash: u64, path: &Path, view: SavedView) {
+        if self
+            .entries
+            .first()
+            .is_some_and(|(h, p, v)| *h == hash && p == path && *v == view)
+        {
+            return;
+        }
+        self.entries.retain(|(h, p, _)| *h != hash && p != path);
+        self.entries.insert(0, (hash, path.to_path_buf(), view));
+        self.entries.truncate(Self::CAPACITY);
+        self.changed = true;
+    }
+
+    /// Write the history if it changed, at most once per interval unless
+    /// forced.
+    pub fn save(&mut self, force: bool) {
+        if self.changed && self.file.due(force) {
+            self.changed = false;
+            let text = self.serialize();
+            self.file.write(text);
+        }
+    }
+
+    fn serialize(&self) -> String {
+        let mut out = String::from(Self::HEADER);
+        for (hash, path, view) in &self.entries {
+            // Paths that can not be written on one line are not kept.
+            let Some(path) = path.to_str().filter(|p| !p.contains('\n')) else {
+                continue;
+            };
+            out.push_str(&format!("\nview {hash:016x} {} {path}", view.fields()));
+            view.write_details(&mut out);
+        }
+        out.push('\n');
+        out
+    }
+
+    fn deserialize(text: &str) -> Option<Vec<(u64, PathBuf, SavedView)>> {
+        let mut lines = text.lines();
+        if lines.next()? != Self::HEADER {
+            return None;
+        }
+        let entry = |line: &str| {
+            let mut fields = line.strip_prefix("view ")?.splitn(4, ' ');
+            let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+            let view = SavedView::from_fields(fields.next()?, fields.next()?)?;
+            let path = PathBuf::from(fields.next()?);
+            Some((hash, path, view))
+        };
+
+        let mut entries = Vec::<(u64, PathBuf, SavedView)>::new();
+        // Whether the last view was kept, which the lines after it describe.
+        let mut kept = false;
+        for line in lines {
+            if line.starts_with("view ") {
+                let entry = entry(line);
+                kept = entry.is_some();
+                entries.extend(entry);
+            } else if let (true, Some((.., view))) = (kept, entries.last_mut()) {
+                view.read_detail(line);
+            }
+        }
+        Some(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: Vec<(u64, PathBuf, SavedView)>) -> ViewHistory {
+        ViewHistory {
+            file: StoredFile::at(None, "view history", Duration::ZERO),
+            entries,
+            changed: false,
+        }
+    }
+
+    fn view(rotation: f32) -> SavedView {
+        SavedView {
+            bounds: None,
+            rotation,
+            guides: Guides::default(),
+            selection: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn views_keep_their_guides() {
+        let guides = Guides {
+            center: true,
+            thirds: false,
+            margin: 12.5,
+            margin_unit: MarginUnit::Pixels,
+        };
+        let entries = vec![
+            (
+                0xABCD,
+                PathBuf::from("/art/with guides.procreate"),
+                SavedView {
+                    bounds: Some(CanvasViewBounds {
+                        min: [-10.0, 0.0],
+                        max: [100.0, 50.5],
+                    }),
+                    rotation: 90.0,
+                    guides,
+                    selection: Vec::new(),
+                },
+            ),
+            (2, PathBuf::from("plain.procreate"), view(0.0)),
+        ];
+        let text = history(entries.clone()).serialize();
+        assert_eq!(text.matches("\nguides ").count(), 1);
+        assert_eq!(ViewHistory::deserialize(&text), Some(entries));
+    }
+
+    #[test]
+    fn views_keep_their_selected_layers() {
+        let mut selected = view(0.0);
+        selected.selection = vec!["B-2".to_string(), "A-1".to_string()];
+        let mut entries = vec![(1, PathBuf::from("a.procreate"), selected)];
+        let text = history(entries.clone()).serialize();
+        assert!(text.ends_with("\nselection B-2 A-1\n"));
+        assert_eq!(ViewHistory::deserialize(&text), Some(entries.clone()));
+
+        // Layers whose UUID would break the line are left out.
+        entries[0].2.selection.insert(0, "two words".to_string());
+        let text = history(entries.clone()).serialize();
+        entries[0].2.selection.remove(0);
+        assert_eq!(ViewHistory::deserialize(&text), Some(entries));
+    }
+
+    #[test]
+    fn guides_follow_the_view_they_belong_to() {
+        let text = "silicate-views 1\n\
+            view 0000000000000001 0 fit a.procreate\n\
+            view zz 0 fit broken.procreate\n\
+            guides true true 5 %\n\
+            view 0000000000000002 45 fit b.procreate\n\
+            guides true false 5 cm\n";
+        assert_eq!(
+            ViewHistory::deserialize(text),
+            Some(vec![
+                (1, PathBuf::from("a.procreate"), view(0.0)),
+                (2, PathBuf::from("b.procreate"), view(45.0)),
+            ])
+        );
+    }
+
+    #[test]
+    fn views_are_found_by_hash_then_path_after_a_restart() {
+        let path =
+            std::env::temp_dir().join(format!("silicate-views-{}/views", std::process::id()));
+        let file = || StoredFile::at(Some(path.clone()), "view history", Duration::from_secs(60));
+        let mut views = ViewHistory::open(file());
+        views.record(1, Path::new("moved.procreate"), view(90.0));
+        views.record(2, Path::new("b.procreate"), view(180.0));
+        // Throttled until forced, such as on exit.
+        views.save(false);
+        assert!(!path.exists());
+        views.save(true);
+
+        let views = ViewHistory::open(file());
+        let get = |hash, path| views.get(hash, Path::new(path)).map(|v| v.rotation);
+        assert_eq!(get(1, "renamed.procreate"), Some(90.0));
+        assert_eq!(get(3, "b.procreate"), Some(180.0));
+        assert_eq!(get(3, "c.procreate"), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn views_keep_the_most_recent_files() {
+        let mut views = history(Vec::new());
+        for hash in 0..ViewHistory::CAPACITY as u64 + 10 {
+            views.record(hash, Path::new(&format!("{hash}.procreate")), view(0.0));
+        }
+        // Viewing a file again moves it to the front.
+        views.record(50, Path::new("50.procreate"), view(45.0));
+        assert_eq!(views.entries.len(), ViewHistory::CAPACITY);
+        assert_eq!(views.entries[0].0, 50);
+        assert_eq!(views.entries.iter().filter(|(h, ..)| *h == 50).count(), 1);
+        assert!(views.get(0, Path::new("0.procreate")).is_none());
+    }
+}
@@ -0,0 +1,484 @@
+use compositor::profile::ColorProfile;
+use silica::{ProcreateFile, SilicaGroup, SilicaHierarchy, SilicaLayer, Size};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// JSON value, written and parsed by hand since nothing else needs a
+/// serializer.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    /// Number as it is written, `null` when it is not finite.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they are written.
+    Object(Vec<(Cow<'static, str>, Json)>),
+}
+
+impl Json {
+    /// Deepest nesting of arrays and objects that is parsed.
+    const MAX_DEPTH: usize = 64;
+
+    pub(crate) fn object(members: Vec<(&'static str, Json)>) -> Self {
+        Self::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (Cow::Borrowed(key), value))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn number(number: impl std::fmt::Display) -> Self {
+        let number = number.to_string();
+        if number.parse::<f64>().is_ok_and(f64::is_finite) {
+            Self::Number(number)
+        } else {
+            Self::Null
+        }
+    }
+
+    pub(crate) fn string(text: Option<&str>) -> Self {
+        text.map_or(Self::Null, |text| Self::String(text.to_string()))
+    }
+
+    fn size(size: Size<impl std::fmt::Display>) -> Self {
+        Self::object(vec![
+            ("width", Self::number(size.width)),
+            ("height", Self::number(size.height)),
+        ])
+    }
+
+    /// Member of an object.
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The value as pretty-printed JSON.
+    pub(crate) fn to_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out
+    }
+
+    /// Parse a JSON document, `None` if it is malformed.
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        (parser.pos == text.len()).then_some(value)
+    }
+
+    /// Write the value indented by two spaces per level.
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = |out: &mut String, depth: usize| {
+            out.push('\n');
+            out.extend(std::iter::repeat_n("  ", depth));
+        };
+        match self {
+            Self::Null => out.push_str("null"),
+            Self::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Self::Number(number) => out.push_str(number),
+            Self::String(text) => write_string(out, text),
+            Self::Array(items) if items.is_empty() => out.push_str("[]"),
+            Self::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    indent(out, depth + 1);
+                    item.write(out, depth + 1);
+                }
+                indent(out, depth);
+                out.push(']');
+            }
+            Self::Object(members) if members.is_empty() => out.push_str("{}"),
+            Self::Object(members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    indent(out, depth + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, depth + 1);
+                }
+                indent(out, depth);
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Write text as a quoted JSON string.
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Recursive descent over the text of a JSON document.
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of the next character.
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\n' | b'\r' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip the byte after any whitespace, returning whether it was there.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(byte);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        if depth > Json::MAX_DEPTH {
+            return None;
+        }
+        self.skip_whitespace();
+        match self.peek()? {
+            b'{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return None;
+                        }
+                        members.push((Cow::Owned(key), self.value(depth + 1)?));
+                        if self.eat(b'}') {
+                            break;
+                        } else if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Json::Object(members))
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        if self.eat(b']') {
+                            break;
+                        } else if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Json::Array(items))
+            }
+            b'"' => self.string().map(Json::String),
+            b't' => self.keyword("true", Json::Bool(true)),
+            b'f' => self.keyword("false", Json::Bool(false)),
+            b'n' => self.keyword("null", Json::Null),
+            _ => {
+                let rest = &self.text[self.pos..];
+                let len = rest
+                    .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or(rest.len());
+                let number = &rest[..len];
+                number.parse::<f64>().ok().filter(|n| n.is_finite())?;
+                self.pos += len;
+                Some(Json::Number(number.to_string()))
+            }
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Option<Json> {
+        self.text[self.pos..].starts_with(word).then(|| {
+            self.pos += word.len();
+            value
+        })
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.peek()? != b'"' {
+            return None;
+        }
+        let mut out = String::new();
+        let mut chars = self.text[self.pos + 1..].char_indices();
+        loop {
+            match chars.next()? {
+                (i, '"') => {
+                    self.pos += i + 2;
+                    return Some(out);
+                }
+                (_, '\\') => match chars.next()?.1 {
+                    c @ ('"' | '\\' | '/') => out.push(c),
+                    'n' => out.push('\n'),
+                    'r' => out.push('\r'),
+                    't' => out.push('\t'),
+                    'b' => out.push('\u{8}'),
+                    'f' => out.push('\u{c}'),
+                    'u' => {
+                        let hex = (0..4)
+                            .map(|_| chars.next().map(|(_, c)| c))
+                            .collect::<Option<String>>()?;
+                        // Surrogate pairs are not put back together.
+                        let code = u32::from_str_radix(&hex, 16).ok()?;
+                        out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    _ => return None,
+                },
+                (_, c) if c.is_control() => return None,
+                (_, c) => out.push(c),
+            }
+        }
+    }
+}
+
+/// Document information and the layer tree as pretty-printed JSON, for
+/// cataloging tools. Layers are listed in the order of the file, with any
+/// changes made to them in the viewer.
+pub fn document(file: &ProcreateFile) -> String {
+    let json = Json::object(vec![
+        ("name", Json::string(file.name.as_deref())),
+        ("author", Json::string(file.author_name.as_deref())),
+        ("size", Json::size(file.size)),
+        ("tile_size", Json::number(file.tile_size)),
+        ("dpi", file.dpi.map_or(Json::Null, Json::number)),
+        (
+            "unit",
+            Json::string(file.unit.as_ref().map(|unit| unit.as_str())),
+        ),
+        (
+            "physical_size_inches",
+            file.physical_size().map_or(Json::Null, |(width, height)| {
+                Json::size(Size { width, height })
+            }),
+        ),
+        ("stroke_count", Json::number(file.stroke_count)),
+        (
+            "tracked_time_seconds",
+            file.tracked_time
+                .map_or(Json::Null, |time| Json::number(time.as_secs_f64())),
+        ),
+        ("orientation", Json::number(file.orientation)),
+        (
+            "flipped",
+            Json::object(vec![
+                ("horizontally", Json::Bool(file.flipped.horizontally)),
+                ("vertically", Json::Bool(file.flipped.vertically)),
+            ]),
+        ),
+        (
+            "background",
+            Json::object(vec![
+                (
+                    "color",
+                    Json::Array(file.background_color.map(Json::number).into()),
+                ),
+                ("hidden", Json::Bool(file.background_hidden)),
+            ]),
+        ),
+        (
+            "color_profile",
+            Json::string(
+                file.color_profile
+                    .clone()
+                    .and_then(|icc| ColorProfile { icc }.description())
+                    .as_deref(),
+            ),
+        ),
+        (
+            "video",
+            Json::object(vec![
+                ("enabled", Json::Bool(file.video.enabled)),
+                ("quality", Json::string(file.video.quality.as_deref())),
+                ("resolution", Json::string(file.video.resolution.as_deref())),
+                ("purged", Json::Bool(file.video.purged)),
+            ]),
+        ),
+        (
+            "content_hash",
+            Json::String(format!("{:016x}", file.content_hash)),
+        ),
+        ("layers", group_json(file, &file.layers)),
+    ]);
+    json.to_pretty()
+}
+
+fn group_json(file: &ProcreateFile, group: &SilicaGroup) -> Json {
+    Json::Array(
+        group
+            .children
+            .iter()
+            .map(|node| match node {
+                SilicaHierarchy::Layer(layer) => layer_json(file, layer),
+                SilicaHierarchy::Group(group) => Json::object(vec![
+                    ("type", Json::String(String::from("group"))),
+                    ("name", Json::string(group.name.as_deref())),
+                    ("blend", Json::String(group.blend.as_str().to_string())),
+                    ("opacity", Json::number(group.opacity)),
+                    ("hidden", Json::Bool(group.hidden)),
+                    ("children", group_json(file, group)),
+                ]),
+            })
+            .collect(),
+    )
+}
+
+fn layer_json(file: &ProcreateFile, layer: &SilicaLayer) -> Json {
+    // Bounds of the stored tiles, clipped to the canvas.
+    let bounds = layer
+        .chunks
+        .iter()
+        .fold(None, |bounds: Option<(u32, u32, u32, u32)>, &(col, row)| {
+            Some(
+                bounds.map_or((col, row, col, row), |(left, top, right, bottom)| {
+                    (left.min(col), top.min(row), right.max(col), bottom.max(row))
+                }),
+            )
+        })
+        .map_or(Json::Null, |(left, top, right, bottom)| {
+            let x = left * file.tile_size;
+            let y = top * file.tile_size;
+            Json::object(vec![
+                ("x", Json::number(x)),
+                ("y", Json::number(y)),
+                (
+                    "width",
+                    Json::number(((right + 1) * file.tile_size).min(layer.size.width) - x),
+                ),
+                (
+                    "height",
+                    Json::number(((bottom + 1) * file.tile_size).min(layer.size.height) - y),
+                ),
+            ])
+        });
+    Json::object(vec![
+        ("type", Json::String(String::from("layer"))),
+        ("name", Json::string(layer.name.as_deref())),
+        ("uuid", Json::String(layer.uuid.clone())),
+        ("blend", Json::String(layer.blend.as_str().to_string())),
+        ("opacity", Json::number(layer.opacity)),
+        ("hidden", Json::Bool(layer.hidden)),
+        ("clipped", Json::Bool(layer.clipped)),
+        ("size", Json::size(layer.size)),
+        ("bounds", bounds),
+        ("tiles", Json::number(layer.chunks.len())),
+        (
+            "damaged_tiles",
+            Json::number(layer.damage.as_ref().map_or(0, |damage| damage.tiles)),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_escaped() {
+        let mut out = String::new();
+        write_string(&mut out, "Ink \"final\"\\\n\u{1}");
+        assert_eq!(out, r#""Ink \"final\"\\\n\u0001""#);
+    }
+
+    #[test]
+    fn values_are_indented() {
+        let mut out = String::new();
+        Json::object(vec![
+            ("empty", Json::Array(Vec::new())),
+            ("items", Json::Array(vec![Json::number(1), Json::Null])),
+            ("ratio", Json::number(f32::NAN)),
+        ])
+        .write(&mut out, 0);
+        assert_eq!(
+            out,
+            "{\n  \"empty\": [],\n  \"items\": [\n    1,\n    null\n  ],\n  \"ratio\": null\n}"
+        );
+    }
+
+    #[test]
+    fn written_values_parse_back() {
+        let value = Json::object(vec![
+            ("name", Json::String(String::from("Ink \"final\"\\\n\u{1}"))),
+            ("items", Json::Array(vec![Json::number(-1.5e3), Json::Null])),
+            ("flags", Json::object(vec![("on", Json::Bool(true))])),
+        ]);
+        assert_eq!(Json::parse(&value.to_pretty()), Some(value));
+        assert_eq!(
+            Json::parse(r#" {"a":"\u00e9\/"} "#)
+                .unwrap()
+                .get("a")
+                .unwrap()
+                .as_str(),
+            Some("é/")
+        );
+    }
+
+    #[test]
+    fn malformed_documents_are_refused() {
+        for text in ["", "{", "[1,]", "{\"a\" 1}", "tru", "1 2", "\"\n\"", "NaN"] {
+            assert_eq!(Json::parse(text), None, "{text}");
+        }
+        assert_eq!(Json::parse(&"[".repeat(100)), None);
+    }
+}
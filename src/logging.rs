@@ -0,0 +1,114 @@
+use std::backtrace::Backtrace;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Number of daily log files kept around.
+const MAX_LOG_FILES: usize = 7;
+
+/// Keeps the log writer alive; logs written after this is dropped are lost.
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+/// Log to a rotating file in the data directory, and errors to stderr, along
+/// with everything else when verbose. Installs a panic hook that writes a
+/// crash report next to the logs.
+pub fn init(verbose: bool) -> LogGuard {
+    let level = if verbose {
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    };
+    // The graphics stack is chatty, only keep what might explain a failure.
+    let filter = Targets::new()
+        .with_default(level)
+        .with_target("wgpu_core", LevelFilter::WARN)
+        .with_target("wgpu_hal", LevelFilter::WARN)
+        .with_target("naga", LevelFilter::WARN);
+
+    let log_dir = crate::paths::data_dir().map(|dir| dir.join("logs"));
+    let appender = log_dir.as_deref().and_then(|dir| {
+        RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("silicate")
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(dir)
+            .map_err(|err| eprintln!("Failed to open log file in {}: {err}", dir.display()))
+            .ok()
+    });
+    let (file_layer, guard) = match appender {
+        Some(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    // Errors such as invalid arguments are always shown on the terminal.
+    let stderr_filter = if verbose {
+        filter.clone()
+    } else {
+        Targets::new().with_default(LevelFilter::ERROR)
+    };
+    let stderr_layer = tracing_subscriber::fmt::layer()
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_writer(std::io::stderr);
+
+    tracing_subscriber::registry()
+        .with(file_layer.with_filter(filter))
+        .with(stderr_layer.with_filter(stderr_filter))
+        .init();
+
+    install_panic_hook(log_dir);
+    tracing::info!("Silicate {} starting", env!("CARGO_PKG_VERSION"));
+
+    LogGuard { _file: guard }
+}
+
+/// Write panics to their own file synchronously, since the process aborts
+/// right after the hook in release builds and the log writer may not flush.
+fn install_panic_hook(log_dir: Option<PathBuf>) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let report = format!(
+            "Silicate {} panicked on thread {} ({} {}):\n{info}\n\nBacktrace:\n{}",
+            env!("CARGO_PKG_VERSION"),
+            thread.name().unwrap_or("<unnamed>"),
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            Backtrace::force_capture()
+        );
+        tracing::error!("{report}");
+
+        if let Some(dir) = &log_dir {
+            match write_crash_report(dir, &report) {
+                Ok(path) => eprintln!("Crash report written to {}", path.display()),
+                Err(err) => eprintln!("Failed to write crash report: {err}"),
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+fn write_crash_report(dir: &Path, report: &str) -> std::io::Result<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("crash-{time}.log"));
+    std::fs::File::create(&path)?.write_all(report.as_bytes())?;
+    Ok(path)
+}
@@ -1,22 +1,104 @@
-mod compositor;
+mod cli;
 mod error;
+#[cfg(test)]
+mod golden;
 mod gui;
-mod ns_archive;
-mod silica;
+mod json;
+mod logging;
+mod paths;
+mod template;
 
 use compositor::dev::GpuHandle;
 use egui_winit::winit::{dpi::PhysicalSize, event_loop::EventLoopBuilder, window::WindowBuilder};
-use gui::app::App;
-use std::{error::Error, sync::Arc};
+use gui::app::{self, App};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::{error::Error, path::PathBuf, sync::Arc};
 
 pub use egui_winit::winit;
 
+/// Name and version of the app, as written into exported images and
+/// reports.
+const SOFTWARE: &str = concat!("Silicate ", env!("CARGO_PKG_VERSION"));
+
 const INITIAL_SIZE: PhysicalSize<u32> = PhysicalSize {
     width: 1200,
     height: 700,
 };
 
+/// Save a file piped to standard input in the temporary folder, so that it
+/// can be unloaded from the GPU and reloaded like any other file. It is
+/// deleted once its tab is closed.
+fn save_stdin() -> Result<PathBuf, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    cli::stdin()?.read_to_end(&mut bytes)?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    let folder = paths::stdin_dir();
+    std::fs::create_dir_all(&folder)?;
+    let path = folder.join(format!("{:016x}.procreate", hasher.finish()));
+    std::fs::write(&path, bytes)?;
+    Ok(path)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let verbose = std::env::args()
+        .skip(1)
+        .any(|arg| arg == "--verbose" || arg == "-v");
+    let _log_guard = logging::init(verbose);
+
+    let rt = Arc::new(
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("tokio runtime creation successful"),
+    );
+
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    // The GPU chosen in the settings, unless overridden on the command line.
+    let saved_gpu = gui::settings::SettingsStore::load()
+        .saved()
+        .map(|settings| settings.gpu)
+        .unwrap_or_default();
+    let gpu = match cli::take_gpu(&mut args, saved_gpu) {
+        Ok(gpu) => gpu,
+        Err(err) => {
+            tracing::error!("{err}");
+            drop(_log_guard);
+            std::process::exit(1);
+        }
+    };
+    match cli::take_threads(&mut args) {
+        // Files decode on the global thread pool unless the viewer is set
+        // to use fewer threads.
+        Ok(Some(threads)) => {
+            if let Err(err) = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads.get())
+                .build_global()
+            {
+                tracing::warn!("Failed to limit the number of threads: {err}");
+            }
+        }
+        Ok(None) => {}
+        Err(err) => {
+            tracing::error!("{err}");
+            drop(_log_guard);
+            std::process::exit(1);
+        }
+    }
+
+    // Subcommands export headlessly instead of opening the viewer.
+    if let Some(command) = cli::Command::parse(args.clone()).transpose() {
+        if let Err(err) = command.and_then(|command| command.run(&rt, gpu)) {
+            tracing::error!("{err}");
+            // Flush the log before exiting.
+            drop(_log_guard);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let taskbar_icon = egui_winit::winit::window::Icon::from_rgba(
         include_bytes!("../assets/icon.rgba").to_vec(),
         240,
@@ -34,14 +116,50 @@ fn main() -> Result<(), Box<dyn Error>> {
         .with_window_icon(taskbar_icon)
         .build(&event_loop)?;
 
-    let rt = Arc::new(
-        tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .expect("tokio runtime creation successful")
-    );
-
-    let (dev, surface) = rt.block_on(GpuHandle::with_window(&window)).unwrap();
+    let (dev, surface) = rt
+        .block_on(GpuHandle::with_window(&window, gpu, &app::lost_backends()))
+        .ok_or(cli::CliError::NoAdapter)?;
+    // Tell what happened if another GPU had to be used than the preferred
+    // one, after it failed now or was lost in an earlier run.
+    let fallback = match std::env::var(app::LOST_BACKENDS_VAR) {
+        Ok(lost) => Some(format!(
+            "The GPU device was lost with {}, restarted with {}.",
+            lost.rsplit(',').next().unwrap_or_default(),
+            dev.preference
+        )),
+        Err(_) => (dev.preference != gpu).then(|| {
+            format!(
+                "The GPU could not be used with {gpu}, using {} instead.",
+                dev.preference
+            )
+        }),
+    };
+    // `--shader` composites with a shader file that is reloaded as it is
+    // edited, anything else on the command line is a file or a link to open.
+    let mut shader = std::env::var_os("SILICATE_SHADER").map(PathBuf::from);
+    let mut files = Vec::new();
+    let mut links = Vec::new();
+    let mut args = args
+        .into_iter()
+        .filter(|arg| arg != "--verbose" && arg != "-v");
+    while let Some(arg) = args.next() {
+        if arg == "--shader" {
+            shader = args.next().map(PathBuf::from);
+        } else if arg == cli::STDIN {
+            files.push(save_stdin()?);
+        } else if App::is_link(&arg) {
+            links.push(arg);
+        } else {
+            files.push(PathBuf::from(arg));
+        }
+    }
     let app = Arc::new(App::new(dev, rt, event_loop.create_proxy()));
-    Ok(app.run(&window, surface, event_loop)?)
+    if let Some(fallback) = fallback {
+        tracing::warn!("{fallback}");
+        app.toasts.lock().warning(fallback);
+    }
+    if let Some(shader) = shader {
+        app.rt.spawn(app.clone().watch_shader(shader));
+    }
+    Ok(app.run(&window, surface, event_loop, files, links)?)
 }
@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+
+/// Per-user directory for files that Silicate keeps between runs, such as
+/// logs and the session journal.
+pub fn data_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            })
+    };
+    base.map(|base| base.join("silicate"))
+}
+
+/// Folder in the temporary folder that files piped to standard input are
+/// saved in while they are open.
+pub fn stdin_dir() -> PathBuf {
+    std::env::temp_dir().join("silicate-stdin")
+}
+
+/// Whether the file was piped to standard input, and is deleted once it is
+/// closed.
+pub fn is_piped(path: &Path) -> bool {
+    path.parent() == Some(stdin_dir().as_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_files_in_the_stdin_folder_are_piped() {
+        assert!(is_piped(&stdin_dir().join("0123456789abcdef.procreate")));
+        assert!(!is_piped(&stdin_dir()));
+        assert!(!is_piped(&stdin_dir().join("nested/a.procreate")));
+        assert!(!is_piped(Path::new("a.procreate")));
+    }
+}
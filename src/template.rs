@@ -0,0 +1,122 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("{{{0}}} is not a known token")]
+    UnknownToken(String),
+    #[error("a {{ is never closed")]
+    Unclosed,
+}
+
+/// Replace the `{token}` placeholders of a name template with their values,
+/// given by `value`. `{{` and `}}` stand for literal braces.
+pub fn expand(
+    template: &str,
+    value: impl Fn(&str) -> Option<String>,
+) -> Result<String, TemplateError> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        out.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+        if let Some(escaped) = rest.strip_prefix(brace) {
+            out.push_str(brace);
+            rest = escaped;
+        } else if brace == "{" {
+            let (token, after) = rest.split_once('}').ok_or(TemplateError::Unclosed)?;
+            let token = token.trim();
+            out.push_str(
+                &value(token).ok_or_else(|| TemplateError::UnknownToken(token.to_string()))?,
+            );
+            rest = after;
+        } else {
+            // A lone closing brace is kept as it is.
+            out.push_str(brace);
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Replace the characters that are not allowed in file names on some
+/// platform, and leading dots that would hide the file.
+pub fn file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() {
+        String::from("Untitled Artwork")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 86_400;
+    let (year, month, day) = civil_date(days as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Year, month and day of a number of days since 1970-01-01, in the
+/// proleptic Gregorian calendar.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    // Counted in 400 year eras starting on March 1st, so that leap days
+    // fall at the end of each year.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_replaced() {
+        let value = |token: &str| (token == "name").then(|| String::from("Piece"));
+        assert_eq!(expand("{name} {{1}}", value), Ok(String::from("Piece {1}")));
+        assert_eq!(
+            expand("{ name }-{size}", value),
+            Err(TemplateError::UnknownToken(String::from("size")))
+        );
+        assert_eq!(expand("{name", value), Err(TemplateError::Unclosed));
+    }
+
+    #[test]
+    fn file_names_are_sanitized() {
+        assert_eq!(file_name("a/b: c?"), "a_b_ c_");
+        assert_eq!(file_name(" .hidden"), "hidden");
+        assert_eq!(file_name(""), "Untitled Artwork");
+    }
+
+    #[test]
+    fn dates_follow_the_calendar() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+        assert_eq!(civil_date(20_819), (2027, 1, 1));
+    }
+}